@@ -1,6 +1,12 @@
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{parse_macro_input, Data::Struct, DeriveInput};
+use syn::{
+	parse_macro_input,
+	spanned::Spanned,
+	Attribute,
+	Data::{Enum, Struct},
+	DataEnum, DeriveInput, Field, Fields, Ident, LitStr, Type,
+};
 
 /*
 --------------------------------------------------------------------------------
@@ -8,41 +14,352 @@ use syn::{parse_macro_input, Data::Struct, DeriveInput};
 --------------------------------------------------------------------------------
 */
 
-#[proc_macro_derive(ShaderStruct)]
+/// Implements `ShaderType` for a struct, generating the matching WGSL struct
+/// definition.
+///
+/// Fields can be renamed on the WGSL side with `#[shader(name = "...")]`, and
+/// left out of it with `#[shader(skip)]` for CPU-only data. A skipped field
+/// has to be at the end of the struct, or take the exact place of the padding
+/// WGSL expects.
+///
+/// Also checks at compile time that the Rust layout of the struct is the same
+/// as the WGSL one, since the bytes are uploaded as-is. The check can be turned
+/// off with `#[shader(unchecked)]`.
+///
+/// Can also be derived on a `#[repr(u32)]` fieldless enum, which is a `u32` in
+/// WGSL. Its definition is a `const ENUM_NAME_VARIANT: u32` for every variant,
+/// and it gets uploaded as its discriminant, which needs it to be `Copy`. The
+/// same constants are available on the Rust side as `Enum::SHADER_CONSTANTS`.
+#[proc_macro_derive(ShaderStruct, attributes(shader))]
 pub fn shader_struct_derive(input: TokenStream) -> TokenStream {
 	let input = parse_macro_input!(input as DeriveInput);
 
 	let name = input.ident;
 
+	let mut unchecked = false;
+	for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("shader")) {
+		let result = attr.parse_nested_meta(|meta| {
+			if meta.path.is_ident("unchecked") {
+				unchecked = true;
+				Ok(())
+			} else {
+				Err(meta.error("Unknown shader attribute, expected `unchecked`"))
+			}
+		});
+
+		if let Err(error) = result {
+			return error.to_compile_error().into();
+		}
+	}
+
 	let out = match input.data {
 		Struct(s) => {
-			let fields = s.fields.into_iter().map(|f| {
-				let field_name = f.ident.expect("All struct fields need an identifier");
-				let field_type = f.ty;
+			let all_fields = match s
+				.fields
+				.into_iter()
+				.map(ShaderField::parse)
+				.collect::<syn::Result<Vec<_>>>()
+			{
+				Ok(fields) => fields,
+				Err(error) => return error.to_compile_error().into(),
+			};
+
+			// Skipped fields only exist on the CPU side, WGSL doesn't see them at all
+			let any_skipped = all_fields.iter().any(|field| field.skip);
+			let fields = all_fields
+				.iter()
+				.filter(|field| !field.skip)
+				.map(|field| (&field.ident, &field.ty))
+				.collect::<Vec<_>>();
+
+			let wgsl_names = all_fields
+				.iter()
+				.filter(|field| !field.skip)
+				.map(|field| &field.wgsl_name);
+
+			let field_definitions = fields.iter().zip(wgsl_names).map(|((_, field_type), wgsl_name)| {
+				quote!(format!("{}: {}", #wgsl_name, <#field_type as ShaderType>::type_name()),)
+			});
+
+			let field_types = fields.iter().map(|(_, field_type)| field_type);
+
+			let field_aligns = fields
+				.iter()
+				.map(|(_, field_type)| quote!(<#field_type as ShaderType>::WGSL_ALIGN));
+
+			let field_uniform_compatible = fields
+				.iter()
+				.map(|(_, field_type)| quote!(<#field_type as ShaderType>::UNIFORM_COMPATIBLE));
+
+			// Each step places a field right after the previous one, at the next multiple
+			// of its alignment
+			let field_offsets = fields.iter().map(|(_, field_type)| {
+				quote! {
+					let offset = offset.div_ceil(<#field_type as ShaderType>::WGSL_ALIGN)
+						* <#field_type as ShaderType>::WGSL_ALIGN;
+					let offset = offset + <#field_type as ShaderType>::WGSL_SIZE;
+				}
+			});
+
+			let layout_check = (!unchecked).then(|| {
+				let field_checks = all_fields
+					.iter()
+					.enumerate()
+					.filter(|(_, field)| !field.skip)
+					.map(|(i, field)| {
+						let ShaderField {
+							ident: field_name,
+							ty: field_type,
+							..
+						} = field;
+
+						let message = match i
+							.checked_sub(1)
+							.map(|i| &all_fields[i])
+							.filter(|previous| previous.skip)
+						{
+							// A skipped field is allowed in the middle as long as it takes the place of the padding
+							Some(previous) => format!(
+							"ShaderStruct `{}`: field `{}` is not at the offset WGSL expects. The skipped field `{}` \
+							 before it has to be exactly as big as the padding WGSL puts there, move it to the end \
+							 of the struct instead, or use #[shader(unchecked)]",
+							name, field_name, previous.ident
+						),
+							None => format!(
+							"ShaderStruct `{}`: field `{}` is not at the offset WGSL expects. Insert a padding field \
+							 before `{}` so that it starts at a multiple of the WGSL alignment of `{}`, or use \
+							 #[shader(unchecked)]",
+							name,
+							field_name,
+							field_name,
+							quote!(#field_type).to_string().replace(' ', "")
+						),
+						}
+						// The message ends up as a format string
+						.replace('{', "{{")
+						.replace('}', "}}");
+
+						quote! {
+							let offset = offset.div_ceil(<#field_type as ShaderType>::WGSL_ALIGN)
+								* <#field_type as ShaderType>::WGSL_ALIGN;
+							assert!(offset == ::core::mem::offset_of!(#name, #field_name), #message);
+							let offset = offset + <#field_type as ShaderType>::WGSL_SIZE;
+						}
+					});
+
+				let size_message = format!(
+					"ShaderStruct `{}`: the Rust struct is not as big as the WGSL one. Add a padding field at the end \
+					 so that its size is a multiple of the largest WGSL field alignment, or use #[shader(unchecked)]",
+					name
+				);
 
-				quote!(format!("{}: {}", stringify!(#field_name), <#field_type as ShaderType>::type_name()),)
+				// Trailing skipped fields make the Rust struct bigger, which doesn't matter
+				// since WGSL never reads past its own size
+				let size_check = if any_skipped {
+					quote!(::core::mem::size_of::<#name>() >= <#name as ShaderType>::WGSL_SIZE)
+				} else {
+					quote!(::core::mem::size_of::<#name>() == <#name as ShaderType>::WGSL_SIZE)
+				};
+
+				quote! {
+					const _: () = {
+						let offset: usize = 0;
+						#(#field_checks)*
+						let _ = offset;
+						assert!(#size_check, #size_message);
+					};
+				}
 			});
 
 			quote! {
 				impl ShaderType for #name {
+					const WGSL_ALIGN: usize = {
+						let align: usize = 1;
+						#(let align = if #field_aligns > align { #field_aligns } else { align };)*
+						align
+					};
+
+					const WGSL_SIZE: usize = {
+						let offset: usize = 0;
+						#(#field_offsets)*
+						// The size of a struct is rounded up to its alignment
+						offset.div_ceil(Self::WGSL_ALIGN) * Self::WGSL_ALIGN
+					};
+
+					const UNIFORM_COMPATIBLE: bool = true #(&& #field_uniform_compatible)*;
+
 					fn type_name() -> String {
 						stringify!(#name).to_string()
 					}
 
 					fn struct_definition() -> Option<String> {
-						Some(format!(
+						let definition = format!(
 							r#"
 								struct {struct_name} {{
 									{fields}
 								}};
-							"#, struct_name=stringify!(#name), fields=vec![#(#fields)*].join(",")
+							"#, struct_name=stringify!(#name), fields=vec![#(#field_definitions)*].join(",")
+						);
+
+						// The fields can be shader structs themselves, which need to be declared too
+						let definitions = [#(<#field_types as ShaderType>::struct_definition(),)* Some(definition)];
+
+						Some(crate::libs::shader::merge_definitions(
+							definitions.iter().flatten().map(String::as_str),
 						))
 					}
 				}
+
+				#layout_check
 			}
 		}
-		_ => panic!("Must be a struct"),
+		Enum(e) => match enum_shader_type(&name, &input.attrs, e) {
+			Ok(out) => out,
+			Err(error) => error.to_compile_error(),
+		},
+		_ => panic!("Must be a struct or an enum"),
 	};
 
 	out.into()
 }
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+struct ShaderField {
+	ident: Ident,
+	ty: Type,
+	wgsl_name: String,
+	skip: bool,
+}
+
+impl ShaderField {
+	fn parse(field: Field) -> syn::Result<Self> {
+		let ident = field
+			.ident
+			.ok_or_else(|| syn::Error::new(field.ty.span(), "All struct fields need an identifier"))?;
+
+		let mut wgsl_name = ident.to_string();
+		let mut skip = false;
+
+		for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("shader")) {
+			attr.parse_nested_meta(|meta| {
+				if meta.path.is_ident("name") {
+					wgsl_name = meta.value()?.parse::<LitStr>()?.value();
+					Ok(())
+				} else if meta.path.is_ident("skip") {
+					skip = true;
+					Ok(())
+				} else {
+					Err(meta.error("Unknown shader field attribute, expected `name = \"...\"` or `skip`"))
+				}
+			})?;
+		}
+
+		Ok(Self {
+			ident,
+			ty: field.ty,
+			wgsl_name,
+			skip,
+		})
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+fn enum_shader_type(name: &Ident, attrs: &[Attribute], data: DataEnum) -> syn::Result<proc_macro2::TokenStream> {
+	let mut has_repr_u32 = false;
+	for attr in attrs.iter().filter(|attr| attr.path().is_ident("repr")) {
+		attr.parse_nested_meta(|meta| {
+			has_repr_u32 |= meta.path.is_ident("u32");
+			Ok(())
+		})?;
+	}
+
+	if !has_repr_u32 {
+		return Err(syn::Error::new(
+			name.span(),
+			"ShaderStruct enums need to be #[repr(u32)]",
+		));
+	}
+
+	let variants = data
+		.variants
+		.into_iter()
+		.map(|variant| match variant.fields {
+			Fields::Unit => Ok(variant.ident),
+			_ => Err(syn::Error::new(variant.span(), "ShaderStruct enums can't have fields")),
+		})
+		.collect::<syn::Result<Vec<_>>>()?;
+
+	let const_names = variants
+		.iter()
+		.map(|variant| {
+			format!(
+				"{}_{}",
+				screaming_snake_case(&name.to_string()),
+				screaming_snake_case(&variant.to_string())
+			)
+		})
+		.collect::<Vec<_>>();
+
+	Ok(quote! {
+		impl #name {
+			/// The name and value of the WGSL constant of every variant
+			pub const SHADER_CONSTANTS: &'static [(&'static str, u32)] = &[#((#const_names, #name::#variants as u32),)*];
+		}
+
+		impl ShaderType for #name {
+			const WGSL_ALIGN: usize = 4;
+			const WGSL_SIZE: usize = 4;
+
+			fn type_name() -> String {
+				"u32".to_string()
+			}
+
+			fn struct_definition() -> Option<String> {
+				Some(
+					vec![#(format!("const {}: u32 = {}u;", #const_names, #name::#variants as u32),)*].join("\n")
+				)
+			}
+		}
+
+		// Enums can't be Pod, so they don't get the blanket impl
+		impl crate::libs::buffer::BufferUploadable for #name {
+			fn get_size() -> u64 {
+				::core::mem::size_of::<u32>() as u64
+			}
+
+			fn get_bytes(&self) -> Vec<u8> {
+				(*self as u32).to_ne_bytes().to_vec()
+			}
+		}
+	})
+}
+
+/// `MaterialType` -> `MATERIAL_TYPE`, `HDRMode` -> `HDR_MODE`
+fn screaming_snake_case(ident: &str) -> String {
+	let chars = ident.chars().collect::<Vec<_>>();
+	let mut out = String::new();
+
+	for (i, &c) in chars.iter().enumerate() {
+		// A run of capitals is one word, whose last capital starts the next one
+		let starts_word = i > 0
+			&& c.is_uppercase()
+			&& (!chars[i - 1].is_uppercase() || chars.get(i + 1).is_some_and(|next| next.is_lowercase()));
+
+		if starts_word && !out.ends_with('_') {
+			out.push('_');
+		}
+		out.push(c.to_ascii_uppercase());
+	}
+
+	out
+}
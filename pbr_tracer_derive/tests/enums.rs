@@ -0,0 +1,56 @@
+mod support;
+
+use pbr_tracer_derive::ShaderStruct;
+use support::{libs, BufferUploadable, ShaderType};
+
+#[repr(u32)]
+#[derive(ShaderStruct, Copy, Clone, Debug, PartialEq)]
+enum HDRMode {
+	Off,
+	ToneMapped = 4,
+	SDRFallback,
+}
+
+#[repr(u32)]
+#[derive(ShaderStruct, Copy, Clone, Debug, PartialEq)]
+enum MaterialType {
+	Diffuse,
+	Metal,
+}
+
+#[test]
+fn enums_are_uploaded_as_their_discriminant() {
+	assert_eq!(HDRMode::type_name(), "u32");
+	assert_eq!(HDRMode::WGSL_SIZE, 4);
+	assert_eq!(HDRMode::get_size(), 4);
+
+	for (mode, value) in [
+		(HDRMode::Off, 0u32),
+		(HDRMode::ToneMapped, 4),
+		(HDRMode::SDRFallback, 5),
+	] {
+		let bytes = mode.get_bytes();
+		assert_eq!(u32::from_ne_bytes(bytes.try_into().unwrap()), value);
+	}
+}
+
+#[test]
+fn constants_match_the_discriminants() {
+	assert_eq!(
+		HDRMode::SHADER_CONSTANTS,
+		[
+			("HDR_MODE_OFF", 0),
+			("HDR_MODE_TONE_MAPPED", 4),
+			("HDR_MODE_SDR_FALLBACK", 5)
+		]
+	);
+	assert_eq!(
+		MaterialType::SHADER_CONSTANTS,
+		[("MATERIAL_TYPE_DIFFUSE", 0), ("MATERIAL_TYPE_METAL", 1)]
+	);
+
+	assert_eq!(
+		HDRMode::struct_definition().unwrap(),
+		"const HDR_MODE_OFF: u32 = 0u;\nconst HDR_MODE_TONE_MAPPED: u32 = 4u;\nconst HDR_MODE_SDR_FALLBACK: u32 = 5u;"
+	);
+}
@@ -0,0 +1,13 @@
+//! The layout checks of `ShaderStruct` fail the build, so they are tested by
+//! compiling the cases in `ui/`. Run with `TRYBUILD=overwrite` to update the
+//! expected errors after changing a message.
+
+#[test]
+fn layout_checks() {
+	let cases = trybuild::TestCases::new();
+	cases.pass("tests/ui/matching_layouts.rs");
+	cases.compile_fail("tests/ui/misaligned_field.rs");
+	cases.compile_fail("tests/ui/missing_tail_padding.rs");
+	cases.compile_fail("tests/ui/skipped_field_in_the_middle.rs");
+	cases.compile_fail("tests/ui/unknown_attribute.rs");
+}
@@ -0,0 +1,80 @@
+mod support;
+
+use naga::TypeInner;
+use pbr_tracer_derive::ShaderStruct;
+use support::{libs, ShaderType, Vec3};
+
+#[repr(C)]
+#[derive(ShaderStruct, Copy, Clone, Debug)]
+struct Inner {
+	position: Vec3,
+	radius: f32,
+}
+
+#[repr(C)]
+#[derive(ShaderStruct, Copy, Clone, Debug)]
+struct Middle {
+	count: u32,
+	#[shader(skip)]
+	_padding: [u32; 3],
+	inner: Inner,
+}
+
+// Uses `Inner` both directly and through `Middle`
+#[repr(C)]
+#[derive(ShaderStruct, Copy, Clone, Debug)]
+struct Outer {
+	middle: Middle,
+	#[shader(name = "first")]
+	inner: Inner,
+	weight: f32,
+	#[shader(skip)]
+	_padding: [u32; 3],
+}
+
+/// The size and field offsets of the struct `name`, as naga lays it out
+fn naga_layout(source: &str, name: &str) -> (u32, Vec<u32>) {
+	let module = naga::front::wgsl::parse_str(source).expect("Invalid WGSL");
+
+	let (_, ty) = module
+		.types
+		.iter()
+		.find(|(_, ty)| ty.name.as_deref() == Some(name))
+		.unwrap_or_else(|| panic!("`{}` isn't declared", name));
+	let TypeInner::Struct { members, span } = &ty.inner else {
+		panic!("`{}` isn't a struct", name);
+	};
+
+	(*span, members.iter().map(|member| member.offset).collect())
+}
+
+#[test]
+fn nested_structs_match_naga() {
+	let source = Outer::struct_definition().unwrap();
+
+	assert_eq!(naga_layout(&source, "Inner"), (Inner::WGSL_SIZE as u32, vec![0, 12]));
+	assert_eq!(naga_layout(&source, "Middle"), (Middle::WGSL_SIZE as u32, vec![0, 16]));
+	assert_eq!(
+		naga_layout(&source, "Outer"),
+		(Outer::WGSL_SIZE as u32, vec![0, 32, 48])
+	);
+
+	assert_eq!(Outer::WGSL_SIZE, std::mem::size_of::<Outer>());
+	assert_eq!(Outer::WGSL_ALIGN, 16);
+}
+
+#[test]
+fn nested_structs_are_declared_once_before_their_users() {
+	let source = Outer::struct_definition().unwrap();
+
+	assert_eq!(source.matches("struct Inner").count(), 1);
+	assert_eq!(source.matches("struct Middle").count(), 1);
+	assert_eq!(source.matches("struct Outer").count(), 1);
+
+	let inner = source.find("struct Inner").unwrap();
+	let middle = source.find("struct Middle").unwrap();
+	let outer = source.find("struct Outer").unwrap();
+	assert!(inner < middle && middle < outer, "Wrong order:\n{}", source);
+
+	assert!(source.contains("first: Inner"));
+}
@@ -0,0 +1,67 @@
+//! Stand-ins for the parts of `pbr_tracer` the derive generates code against,
+//! so that the tests don't need the whole crate. Pull them in at the crate root
+//! with `use support::libs;` (the derive refers to `crate::libs::...`).
+
+#![allow(dead_code, unused_imports)]
+
+pub mod libs {
+	pub mod buffer {
+		pub trait ShaderType {
+			const WGSL_ALIGN: usize;
+			const WGSL_SIZE: usize;
+			const UNIFORM_COMPATIBLE: bool = true;
+
+			fn type_name() -> String;
+			fn struct_definition() -> Option<String> {
+				None
+			}
+		}
+
+		pub trait BufferUploadable: ShaderType {
+			fn get_size() -> u64;
+			fn get_bytes(&self) -> Vec<u8>;
+		}
+
+		#[rustfmt::skip] impl ShaderType for u32 {const WGSL_ALIGN: usize = 4; const WGSL_SIZE: usize = 4; fn type_name() -> String {"u32".to_string()}}
+		#[rustfmt::skip] impl ShaderType for f32 {const WGSL_ALIGN: usize = 4; const WGSL_SIZE: usize = 4; fn type_name() -> String {"f32".to_string()}}
+	}
+
+	pub mod shader {
+		/// Same as the real one, minus the ordered map
+		pub fn merge_definitions<'a>(definitions: impl IntoIterator<Item = &'a str>) -> String {
+			let re = regex::Regex::new(r#"(?s)struct\s+(\w+)\s*\{.*?\}\s*;?|const\s+(\w+)[^;]*;"#).unwrap();
+
+			let mut merged = Vec::<(String, String)>::new();
+			for definition in definitions {
+				for caps in re.captures_iter(definition) {
+					let name = caps.get(1).or(caps.get(2)).unwrap().as_str();
+					if !merged.iter().any(|(merged_name, _)| merged_name == name) {
+						merged.push((name.to_owned(), caps.get(0).unwrap().as_str().to_owned()));
+					}
+				}
+			}
+
+			merged
+				.into_iter()
+				.map(|(_, definition)| definition)
+				.collect::<Vec<_>>()
+				.join("\n")
+		}
+	}
+}
+
+pub use libs::buffer::{BufferUploadable, ShaderType};
+
+/// A `vec3<f32>`, 12 bytes big but aligned to 16 in WGSL
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Vec3(pub [f32; 3]);
+
+impl ShaderType for Vec3 {
+	const WGSL_ALIGN: usize = 16;
+	const WGSL_SIZE: usize = 12;
+
+	fn type_name() -> String {
+		"vec3<f32>".to_string()
+	}
+}
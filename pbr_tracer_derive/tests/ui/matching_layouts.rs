@@ -0,0 +1,40 @@
+#[path = "../support/mod.rs"]
+mod support;
+
+use pbr_tracer_derive::ShaderStruct;
+use support::{libs, ShaderType, Vec3};
+
+#[repr(C)]
+#[derive(ShaderStruct)]
+struct Padded {
+	count: u32,
+	#[shader(skip)]
+	_padding: [u32; 3],
+	#[shader(name = "pos")]
+	position: Vec3,
+	radius: f32,
+}
+
+// Trailing skipped fields only make the Rust struct bigger
+#[repr(C)]
+#[derive(ShaderStruct)]
+struct TrailingSkip {
+	position: Vec3,
+	radius: f32,
+	#[shader(skip)]
+	cpu_only: u64,
+}
+
+#[repr(C)]
+#[derive(ShaderStruct)]
+#[shader(unchecked)]
+struct Unchecked {
+	count: u32,
+	position: Vec3,
+}
+
+fn main() {
+	assert_eq!(Padded::WGSL_SIZE, 32);
+	assert_eq!(TrailingSkip::WGSL_SIZE, 16);
+	let _ = Unchecked::WGSL_SIZE;
+}
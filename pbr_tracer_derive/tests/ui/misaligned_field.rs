@@ -0,0 +1,16 @@
+#[path = "../support/mod.rs"]
+mod support;
+
+use pbr_tracer_derive::ShaderStruct;
+use support::{libs, ShaderType, Vec3};
+
+// The vec3 wants to start at 16, not right after the u32
+#[repr(C)]
+#[derive(ShaderStruct)]
+struct Misaligned {
+	count: u32,
+	position: Vec3,
+	radius: f32,
+}
+
+fn main() {}
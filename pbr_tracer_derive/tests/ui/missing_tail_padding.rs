@@ -0,0 +1,14 @@
+#[path = "../support/mod.rs"]
+mod support;
+
+use pbr_tracer_derive::ShaderStruct;
+use support::{libs, ShaderType, Vec3};
+
+// WGSL rounds the struct up to 16 bytes
+#[repr(C)]
+#[derive(ShaderStruct)]
+struct Unpadded {
+	position: Vec3,
+}
+
+fn main() {}
@@ -0,0 +1,18 @@
+#[path = "../support/mod.rs"]
+mod support;
+
+use pbr_tracer_derive::ShaderStruct;
+use support::{libs, ShaderType, Vec3};
+
+// The skipped field only fills 4 of the 12 bytes of padding before the vec3
+#[repr(C)]
+#[derive(ShaderStruct)]
+struct SkippedInTheMiddle {
+	count: u32,
+	#[shader(skip)]
+	cpu_only: u32,
+	position: Vec3,
+	radius: f32,
+}
+
+fn main() {}
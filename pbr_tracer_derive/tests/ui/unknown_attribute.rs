@@ -0,0 +1,15 @@
+#[path = "../support/mod.rs"]
+mod support;
+
+use pbr_tracer_derive::ShaderStruct;
+use support::Vec3;
+
+#[repr(C)]
+#[derive(ShaderStruct)]
+struct UnknownAttribute {
+	#[shader(rename = "other")]
+	position: Vec3,
+	radius: f32,
+}
+
+fn main() {}
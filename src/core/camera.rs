@@ -1,27 +1,35 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result};
 use bevy_ecs::{
+	entity::Entity,
 	event::EventReader,
-	query::With,
+	query::{With, Without},
 	schedule::{IntoSystemConfigs, SystemSet},
-	system::{Local, Query, Res},
+	system::{Commands, Local, Query, Res, ResMut},
+	world::{Mut, World},
 };
 use brainrot::{
 	bevy::{self, App, Plugin},
-	calc_forward_horizontal_vector, calc_right_vector, deg, rad, spd, vec3, Angle, Direction, Frustum, Position, Speed,
-	SAFE_FRAC_PI_2,
+	calc_forward_horizontal_vector, calc_right_vector, calc_view_matrix, deg, rad, spd, vec3,
+	vek::{Vec2, Vec3},
+	Angle, Direction, Frustum, Position, Speed, SAFE_FRAC_PI_2,
 };
 use derive_more::{Deref, Display, From};
-use winit::{
-	event::ElementState,
-	keyboard::{KeyCode, PhysicalKey},
-};
+use log::info;
+use winit::event::{ElementState, MouseButton, MouseScrollDelta};
 
 use super::{
+	console::is_console_closed,
 	display::AppWindow,
+	entity_label::EntityLabel,
 	event_processing::{EventReaderProcessor, ProcessedInputEvents, ProcessedMotionEvents},
-	events::{KeyboardInputEvent, MouseMotionEvent},
+	events::{KeyboardInputEvent, MouseInputEvent, MouseMotionEvent, MouseWheelEvent},
 	gameloop::{Time, Update},
+	key_bindings::{Action, HeldKeys, KeyBindings},
+	params,
+	presentation::Presentation,
 };
-use crate::EntityLabel;
 
 /*
 --------------------------------------------------------------------------------
@@ -35,35 +43,84 @@ impl Plugin for CameraPlugin {
 	fn build(&self, app: &mut App) {
 		app.add_systems(
 			Update,
-			(process_keyboard, process_mouse, process_sprint, update_camera)
+			(
+				switch_active_camera,
+				process_keyboard,
+				process_scroll,
+				toggle_projection,
+				process_sprint,
+				// The mouse motion is drained once per update, right after it's collected
+				(process_mouse, update_camera).chain(),
+				toggle_orbit,
+				(process_orbit_input, update_orbit).chain(),
+			)
 				.in_set(CameraControl)
-				.run_if(is_cursor_attached),
+				.run_if(is_cursor_attached)
+				.run_if(is_console_closed),
 		);
 
-		app.world.spawn((
-			CameraBundle {
-				label: Camera,
-				frustum: Frustum {
-					y_fov: 45_f32.to_radians(),
-					z_near: 0.3,
-					z_far: 20.0,
-				},
-				position: vec3!(0.0, 0.0, -5.0).into(),
-				direction: Default::default(),
+		app.add_systems(Update, adjust_input_settings.run_if(is_console_closed));
+
+		app.world.insert_resource(ScrollBinding::default());
+		app.world.insert_resource(InputSettings::default());
+
+		params::registry(app).register_float(
+			"fov",
+			"The vertical field of view of the active camera, in degrees",
+			ScrollBinding::MIN_FOV..=ScrollBinding::MAX_FOV,
+			|world| Ok(active_frustum(world)?.y_fov.to_degrees()),
+			|world, fov| {
+				active_frustum(world)?.y_fov = fov.to_radians();
+				Ok(())
 			},
+		);
+
+		let camera_entity = spawn_camera(
+			&mut app.world,
+			vec3!(0.0, 0.0, -5.0).into(),
+			Default::default(),
+			Frustum {
+				y_fov: 45_f32.to_radians(),
+				z_near: 0.3,
+				z_far: 20.0,
+			},
+		);
+
+		app.world.entity_mut(camera_entity).insert((
+			ActiveCamera,
 			CameraControlBundle {
 				speed: spd!(5.0),
-				sensitivity: spd!(deg!(0.1)),
+				sensitivity: deg!(0.1).into(),
 				controller: Default::default(),
 			},
-			Sprint {
-				starting_speed: spd!(1.0),
-				acceleration: spd!(spd!(20.)),
-			},
+			Sprint::new(spd!(1.0), spd!(spd!(20.))),
 		));
 	}
 }
 
+/// Spawn a camera that stays where it is put, e.g. for a fixed shot. It can be
+/// rendered from by moving the [`ActiveCamera`] to it.
+pub fn spawn_camera(world: &mut World, position: Position, direction: Direction, frustum: Frustum) -> Entity {
+	world
+		.spawn((
+			CameraBundle {
+				label: Camera,
+				position,
+				direction,
+				frustum,
+			},
+			ProjectionMode::Perspective,
+		))
+		.id()
+}
+
+fn active_frustum(world: &mut World) -> Result<Mut<'_, Frustum>> {
+	world
+		.query_filtered::<&mut Frustum, With<ActiveCamera>>()
+		.get_single_mut(world)
+		.context("There is no active camera")
+}
+
 #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct CameraControl;
 
@@ -77,6 +134,12 @@ pub struct CameraControl;
 pub struct Camera;
 impl EntityLabel for Camera {}
 
+/// The camera that is rendered from and controlled. There should only be one,
+/// [`Action::SelectCamera`] moves it to another camera.
+#[derive(bevy::Component)]
+pub struct ActiveCamera;
+impl EntityLabel for ActiveCamera {}
+
 #[derive(bevy::Bundle)]
 struct CameraBundle {
 	label: Camera,
@@ -101,13 +164,233 @@ struct CameraControlBundle {
 #[derive(bevy::Component, Deref, From, Display, Copy, Clone, Debug, Default, PartialEq)]
 pub struct MovementSpeed(pub Speed);
 
+/// How much the camera turns per count of mouse motion. The counts are applied
+/// as they come, so it doesn't depend on the update rate.
 #[derive(bevy::Component, Deref, From, Display, Copy, Clone, Debug, Default, PartialEq)]
-pub struct Sensitivity(pub Speed<Angle>);
+pub struct Sensitivity(pub Angle);
+
+impl Sensitivity {
+	/// The angle that many counts turn the camera by
+	pub fn turn(&self, counts: f32, input_settings: &InputSettings) -> Angle {
+		self.0 * (counts * input_settings.sensitivity_scale)
+	}
+}
 
 #[derive(bevy::Component, Copy, Clone, Debug, Default, PartialEq)]
 pub struct Sprint {
 	pub starting_speed: Speed,
 	pub acceleration: Speed<Speed>,
+
+	/// The speed to go back to once the sprint key is released, `None` when not
+	/// sprinting
+	normal_speed_backup: Option<Speed>,
+}
+
+impl Sprint {
+	pub fn new(starting_speed: Speed, acceleration: Speed<Speed>) -> Self {
+		Self {
+			starting_speed,
+			acceleration,
+			normal_speed_backup: None,
+		}
+	}
+
+	/// Stop sprinting and go back to the normal speed. Sprinting starts again from
+	/// the starting speed if the key is still held.
+	pub fn reset(&mut self, speed: &mut MovementSpeed) {
+		if let Some(normal_speed) = self.normal_speed_backup.take() {
+			speed.0 = normal_speed;
+		}
+	}
+}
+
+/// Makes the camera speed up and slow down smoothly instead of moving at full
+/// speed instantly, and the rotation lag slightly behind the mouse. The camera
+/// moves raw without it.
+#[derive(bevy::Component, Copy, Clone, Debug, PartialEq)]
+pub struct MovementSmoothing {
+	/// How quickly (per second) the velocity and the rotation catch up with the
+	/// input
+	pub acceleration: f32,
+	/// How quickly (per second) the velocity fades out once no key is held
+	pub damping: f32,
+
+	velocity: Vec3<f32>,
+	pending_yaw: f32,
+	pending_pitch: f32,
+}
+
+impl Default for MovementSmoothing {
+	fn default() -> Self {
+		Self::new(10.0, 5.0)
+	}
+}
+
+impl MovementSmoothing {
+	pub fn new(acceleration: f32, damping: f32) -> Self {
+		Self {
+			acceleration,
+			damping,
+			velocity: Vec3::zero(),
+			pending_yaw: 0.0,
+			pending_pitch: 0.0,
+		}
+	}
+
+	/// Stop moving and forget the mouse movement that wasn't applied yet
+	pub fn reset(&mut self) {
+		*self = Self::new(self.acceleration, self.damping);
+	}
+
+	/// Advance by `dt` towards the target velocity (in units per second), and
+	/// eat into the mouse movement. Returns the distance to move and the mouse
+	/// movement to apply this time.
+	fn step(&mut self, target_velocity: Vec3<f32>, yaw: f32, pitch: f32, dt: Duration) -> (Vec3<f32>, f32, f32) {
+		// Exponential decay towards the target, which doesn't depend on how the time
+		// is sliced
+		let dt = dt.as_secs_f32();
+		let catch_up = 1.0 - (-self.acceleration * dt).exp();
+
+		if target_velocity == Vec3::zero() {
+			self.velocity *= (-self.damping * dt).exp();
+		} else {
+			self.velocity += (target_velocity - self.velocity) * catch_up;
+		}
+
+		self.pending_yaw += yaw;
+		self.pending_pitch += pitch;
+
+		let yaw = self.pending_yaw * catch_up;
+		let pitch = self.pending_pitch * catch_up;
+
+		self.pending_yaw -= yaw;
+		self.pending_pitch -= pitch;
+
+		(self.velocity * dt, yaw, pitch)
+	}
+}
+
+/// Turns the camera around a focus point instead of flying it: hold the right
+/// mouse button to rotate, the middle one to move the focus, and scroll to get
+/// closer or further. Toggled with [`Action::ToggleOrbit`].
+///
+/// While it is on the camera, the camera's [`Position`] and [`Direction`] are
+/// derived from it and the fly controls are ignored.
+#[derive(bevy::Component, Copy, Clone, Debug)]
+pub struct OrbitController {
+	pub focus: Position,
+	pub distance: f32,
+	pub yaw: Angle,
+	pub pitch: Angle,
+
+	rotating: bool,
+	panning: bool,
+	yaw_accu: f32,
+	pitch_accu: f32,
+	pan_accu: Vec2<f32>,
+}
+
+impl OrbitController {
+	const DEFAULT_DISTANCE: f32 = 5.0;
+	const MIN_DISTANCE: f32 = 0.1;
+
+	/// How far the focus moves per pixel dragged, relative to the distance, so
+	/// that the focus seems to follow the cursor
+	const PAN_PER_PIXEL: f32 = 0.002;
+
+	/// Orbit around the point `distance` in front of the camera, so that the
+	/// view doesn't jump
+	pub fn in_front_of(position: Position, direction: Direction, distance: f32) -> Self {
+		Self {
+			focus: (position.0 + view_axis(position, direction, Vec3::unit_z()) * distance).into(),
+			distance,
+			yaw: direction.yaw,
+			pitch: direction.pitch,
+			rotating: false,
+			panning: false,
+			yaw_accu: 0.0,
+			pitch_accu: 0.0,
+			pan_accu: Vec2::zero(),
+		}
+	}
+}
+
+/// How the rays leave the camera
+#[derive(bevy::Component, Copy, Clone, Debug, Default, PartialEq)]
+pub enum ProjectionMode {
+	/// Rays diverge from the camera position, the field of view is the one of
+	/// the [`Frustum`]
+	#[default]
+	Perspective,
+	/// Parallel rays, covering `height` world units vertically
+	Orthographic { height: f32 },
+	/// Rays in every direction around the camera, the image being an
+	/// equirectangular panorama: the longitude goes along x with the camera's
+	/// forward in the middle, the latitude along y. The field of view and the
+	/// depth of field don't apply, see
+	/// [`CaptureProjection`](super::rendering::capture::CaptureProjection).
+	Equirectangular,
+}
+
+impl ProjectionMode {
+	pub const DEFAULT_ORTHO_HEIGHT: f32 = 10.0;
+}
+
+/// What scrolling the mouse wheel does to the camera
+#[derive(bevy::Resource, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ScrollBinding {
+	/// Change the field of view
+	#[default]
+	Zoom,
+	/// Change the movement speed
+	Speed,
+}
+
+impl ScrollBinding {
+	/// How much one notch of the wheel scales the fov or speed
+	const FACTOR_PER_LINE: f32 = 1.1;
+
+	/// Touchpads scroll in pixels, this is roughly how many make up a line
+	const PIXELS_PER_LINE: f32 = 20.0;
+
+	const MIN_FOV: f32 = 10.0;
+	const MAX_FOV: f32 = 120.0;
+}
+
+/// Mouse look preferences, applied on top of the camera's [`Sensitivity`] for
+/// every camera. Only live for the session.
+#[derive(bevy::Resource, Copy, Clone, Debug, PartialEq)]
+pub struct InputSettings {
+	/// Moving the mouse up looks down
+	pub invert_y: bool,
+	pub sensitivity_scale: f32,
+}
+
+impl Default for InputSettings {
+	fn default() -> Self {
+		Self {
+			invert_y: false,
+			sensitivity_scale: 1.0,
+		}
+	}
+}
+
+impl InputSettings {
+	/// How much one press of [`Action::SensitivityUp`] or
+	/// [`Action::SensitivityDown`] scales the sensitivity
+	const SENSITIVITY_STEP: f32 = 1.25;
+
+	const MIN_SENSITIVITY_SCALE: f32 = 0.05;
+	const MAX_SENSITIVITY_SCALE: f32 = 20.0;
+
+	/// The pitch part of a mouse motion, with the inversion applied
+	fn pitch_delta(&self, motion_y: f32) -> f32 {
+		if self.invert_y {
+			-motion_y
+		} else {
+			motion_y
+		}
+	}
 }
 
 #[derive(bevy::Component, Copy, Clone, Debug, Default, PartialEq)]
@@ -123,6 +406,29 @@ pub struct CameraController {
 	direction_pitch_accu: f32,
 }
 
+impl CameraController {
+	/// Forget the held keys and the mouse movement that wasn't applied yet. The
+	/// keys that are still held are picked up again on the next update.
+	pub fn reset(&mut self) {
+		*self = Self::default();
+	}
+
+	/// Collect mouse motion, in counts, until it's taken by
+	/// [`take_mouse_motion`](Self::take_mouse_motion)
+	pub fn add_mouse_motion(&mut self, motion: Vec2<f32>, input_settings: &InputSettings) {
+		self.direction_yaw_accu += motion.x;
+		self.direction_pitch_accu += input_settings.pitch_delta(motion.y);
+	}
+
+	/// The yaw and pitch counts collected since the last call
+	pub fn take_mouse_motion(&mut self) -> (f32, f32) {
+		let motion = (self.direction_yaw_accu, self.direction_pitch_accu);
+		self.direction_yaw_accu = 0.0;
+		self.direction_pitch_accu = 0.0;
+		motion
+	}
+}
+
 /*
 --------------------------------------------------------------------------------
 ||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
@@ -133,80 +439,194 @@ fn is_cursor_attached(app_window: Res<AppWindow>) -> bool {
 	app_window.cursor_attached
 }
 
+/// The cameras are numbered in the order they were spawned
+fn switch_active_camera(
+	mut commands: Commands,
+	cameras: Query<Entity, With<Camera>>,
+	active_cameras: Query<Entity, With<ActiveCamera>>,
+	mut keyboard_events: EventReader<KeyboardInputEvent>,
+	key_bindings: Res<KeyBindings>,
+) {
+	let events = keyboard_events.read().collect::<Vec<_>>();
+
+	let pressed = |index: &u8| key_bindings.has_pressed(Action::SelectCamera(*index), events.iter().copied());
+
+	let Some(index) = (0..Action::CAMERA_SLOTS).find(pressed) else {
+		return;
+	};
+
+	let mut cameras = cameras.iter().collect::<Vec<_>>();
+	cameras.sort();
+
+	let Some(&target) = cameras.get(index as usize) else {
+		return;
+	};
+
+	for entity in active_cameras.iter() {
+		commands.entity(entity).remove::<ActiveCamera>();
+	}
+	commands.entity(target).insert(ActiveCamera);
+}
+
 fn process_keyboard(
-	mut q: Query<&mut CameraController, With<Camera>>,
+	mut q: Query<&mut CameraController, (With<ActiveCamera>, Without<OrbitController>, Without<Presentation>)>,
+	mut keyboard_events: EventReader<KeyboardInputEvent>,
+	key_bindings: Res<KeyBindings>,
+	mut held_keys: Local<HeldKeys>,
+) {
+	// Keep track of the keys even while orbiting, so that none is stuck when
+	// flying again
+	held_keys.update(keyboard_events.read());
+
+	let Ok(mut controller) = q.get_single_mut() else {
+		return;
+	};
+	controller.moving_forward = key_bindings.is_held(Action::MoveForward, &held_keys);
+	controller.moving_backward = key_bindings.is_held(Action::MoveBackward, &held_keys);
+	controller.moving_left = key_bindings.is_held(Action::MoveLeft, &held_keys);
+	controller.moving_right = key_bindings.is_held(Action::MoveRight, &held_keys);
+	controller.moving_up = key_bindings.is_held(Action::MoveUp, &held_keys);
+	controller.moving_down = key_bindings.is_held(Action::MoveDown, &held_keys);
+}
+
+fn process_mouse(
+	mut q: Query<&mut CameraController, (With<ActiveCamera>, Without<OrbitController>, Without<Presentation>)>,
+	mouse_events: EventReader<MouseMotionEvent>,
+	input_settings: Res<InputSettings>,
+) {
+	let Ok(mut controller) = q.get_single_mut() else {
+		return;
+	};
+	let motion_delta = mouse_events.process().delta_sum();
+
+	controller.add_mouse_motion(Vec2::new(motion_delta.x as f32, motion_delta.y as f32), &input_settings);
+}
+
+fn adjust_input_settings(
+	mut input_settings: ResMut<InputSettings>,
 	mut keyboard_events: EventReader<KeyboardInputEvent>,
+	key_bindings: Res<KeyBindings>,
 ) {
-	let mut controller = q.single_mut();
-
-	for KeyboardInputEvent {
-		state, physical_key, ..
-	} in keyboard_events.read()
-	{
-		let pressed = *state == ElementState::Pressed;
-
-		if let PhysicalKey::Code(key) = physical_key {
-			match key {
-				KeyCode::KeyW | KeyCode::ArrowUp => {
-					controller.moving_forward = pressed;
-				}
-				KeyCode::KeyS | KeyCode::ArrowDown => {
-					controller.moving_backward = pressed;
-				}
-				KeyCode::KeyA | KeyCode::ArrowLeft => {
-					controller.moving_left = pressed;
-				}
-				KeyCode::KeyD | KeyCode::ArrowRight => {
-					controller.moving_right = pressed;
-				}
-				KeyCode::Space => {
-					controller.moving_up = pressed;
-				}
-				KeyCode::ControlLeft => {
-					controller.moving_down = pressed;
-				}
-				_ => {}
-			};
+	let events = keyboard_events.read().collect::<Vec<_>>();
+	let pressed = |action| key_bindings.has_pressed(action, events.iter().copied());
+
+	let mut scale = input_settings.sensitivity_scale;
+	if pressed(Action::SensitivityUp) {
+		scale *= InputSettings::SENSITIVITY_STEP;
+	}
+	if pressed(Action::SensitivityDown) {
+		scale /= InputSettings::SENSITIVITY_STEP;
+	}
+	let scale = scale.clamp(
+		InputSettings::MIN_SENSITIVITY_SCALE,
+		InputSettings::MAX_SENSITIVITY_SCALE,
+	);
+
+	if scale != input_settings.sensitivity_scale {
+		input_settings.sensitivity_scale = scale;
+		info!("Mouse sensitivity: x{:.2}", scale);
+	}
+
+	if pressed(Action::ToggleInvertY) {
+		input_settings.invert_y = !input_settings.invert_y;
+		info!("Invert Y: {}", if input_settings.invert_y { "on" } else { "off" });
+	}
+}
+
+fn process_scroll(
+	mut q: Query<
+		(&mut Frustum, &mut ProjectionMode, &mut MovementSpeed),
+		(With<ActiveCamera>, Without<OrbitController>, Without<Presentation>),
+	>,
+	mut wheel_events: EventReader<MouseWheelEvent>,
+	scroll_binding: Res<ScrollBinding>,
+) {
+	let lines = wheel_events
+		.read()
+		.map(|MouseWheelEvent { wheel_delta }| match wheel_delta {
+			MouseScrollDelta::LineDelta(_, y) => *y,
+			MouseScrollDelta::PixelDelta(delta) => delta.y as f32 / ScrollBinding::PIXELS_PER_LINE,
+		})
+		.sum::<f32>();
+
+	if lines == 0.0 {
+		return;
+	}
+
+	let Ok((mut frustum, mut projection_mode, mut speed)) = q.get_single_mut() else {
+		return;
+	};
+
+	// Scaling exponentially makes every notch feel the same, no matter the
+	// current value
+	let factor = ScrollBinding::FACTOR_PER_LINE.powf(lines);
+
+	match *scroll_binding {
+		// Scrolling up zooms in, so narrows the view
+		ScrollBinding::Zoom => match &mut *projection_mode {
+			ProjectionMode::Perspective => {
+				frustum.y_fov = (frustum.y_fov / factor)
+					.clamp(ScrollBinding::MIN_FOV.to_radians(), ScrollBinding::MAX_FOV.to_radians());
+			}
+			ProjectionMode::Orthographic { height } => *height /= factor,
+			// Already sees everything
+			ProjectionMode::Equirectangular => {}
+		},
+		ScrollBinding::Speed => {
+			// The distance moved in a second is the speed as a plain number
+			let speed_per_second = speed.0 * Duration::from_secs(1);
+			speed.0 = spd!(speed_per_second * factor);
 		}
 	}
 }
 
-fn process_mouse(mut q: Query<&mut CameraController, With<Camera>>, mouse_events: EventReader<MouseMotionEvent>) {
-	let mut controller = q.single_mut();
-	let motion_delta = mouse_events.process().delta_sum();
+fn toggle_projection(
+	mut q: Query<&mut ProjectionMode, With<ActiveCamera>>,
+	mut keyboard_events: EventReader<KeyboardInputEvent>,
+	key_bindings: Res<KeyBindings>,
+) {
+	if !key_bindings.has_pressed(Action::ToggleProjection, keyboard_events.read()) {
+		return;
+	}
 
-	controller.direction_yaw_accu += motion_delta.x as f32;
-	controller.direction_pitch_accu += motion_delta.y as f32;
+	let Ok(mut projection_mode) = q.get_single_mut() else {
+		return;
+	};
+	*projection_mode = match *projection_mode {
+		ProjectionMode::Perspective => ProjectionMode::Orthographic {
+			height: ProjectionMode::DEFAULT_ORTHO_HEIGHT,
+		},
+		ProjectionMode::Orthographic { .. } | ProjectionMode::Equirectangular => ProjectionMode::Perspective,
+	};
 }
 
 fn process_sprint(
-	mut q: Query<(&mut MovementSpeed, &mut Sprint), With<Camera>>,
-	keyboard_events: EventReader<KeyboardInputEvent>,
-	mut normal_speed_backup: Local<Option<Speed>>,
+	mut q: Query<
+		(&mut MovementSpeed, &mut Sprint),
+		(With<ActiveCamera>, Without<OrbitController>, Without<Presentation>),
+	>,
+	mut keyboard_events: EventReader<KeyboardInputEvent>,
+	key_bindings: Res<KeyBindings>,
+	mut held_keys: Local<HeldKeys>,
 	time: Res<Time>,
 ) {
-	// Turns out winit events are not very reliable, and multiple Pressed events might be fired successively (and vice-versa for Released)
+	held_keys.update(keyboard_events.read());
 
-	let (mut speed, sprint) = q.single_mut();
+	let Ok((mut speed, mut sprint)) = q.get_single_mut() else {
+		return;
+	};
 
-	for state in keyboard_events.process().states(KeyCode::ShiftLeft) {
-		match state {
-			ElementState::Pressed => {
-				if normal_speed_backup.is_none() {
-					*normal_speed_backup = Some(speed.0);
-					speed.0 = sprint.starting_speed;
-				}
-			}
-			ElementState::Released => {
-				if let Some(old_speed) = (*normal_speed_backup).take() {
-					speed.0 = old_speed;
-				}
-			}
+	if key_bindings.is_held(Action::Sprint, &held_keys) {
+		if sprint.normal_speed_backup.is_none() {
+			sprint.normal_speed_backup = Some(speed.0);
+			speed.0 = sprint.starting_speed;
 		}
+	} else {
+		sprint.reset(&mut speed);
 	}
 
 	// Speed is being controlled by sprint, so accelerate it
-	if normal_speed_backup.is_some() {
+	if sprint.normal_speed_backup.is_some() {
 		speed.0 += sprint.acceleration * time.dt_u;
 	}
 }
@@ -219,47 +639,184 @@ fn update_camera(
 			&mut Direction,
 			&MovementSpeed,
 			&Sensitivity,
+			Option<&mut MovementSmoothing>,
 		),
-		With<Camera>,
+		(With<ActiveCamera>, Without<OrbitController>, Without<Presentation>),
 	>,
+	input_settings: Res<InputSettings>,
 	time: Res<Time>,
 ) {
-	let (mut controller, mut position, mut direction, movement_speed, sensitivity) = q.single_mut();
+	let Ok((mut controller, mut position, mut direction, movement_speed, sensitivity, smoothing)) = q.get_single_mut()
+	else {
+		return;
+	};
 
 	// Move forward/backward and left/right
 	let forward = calc_forward_horizontal_vector(*direction);
 	let right = calc_right_vector(*direction);
 
-	let movement = movement_speed.0 * time.dt_u;
+	// Not normalized, so diagonals are faster
+	let mut wish_direction = Vec3::zero();
 
 	if controller.moving_forward {
-		position.0 += forward * movement;
+		wish_direction += forward;
 	}
 	if controller.moving_backward {
-		position.0 -= forward * movement;
+		wish_direction -= forward;
 	}
 	if controller.moving_right {
-		position.0 += right * movement;
+		wish_direction += right;
 	}
 	if controller.moving_left {
-		position.0 -= right * movement;
+		wish_direction -= right;
 	}
 	if controller.moving_up {
-		position.0.y += movement;
+		wish_direction.y += 1.0;
 	}
 	if controller.moving_down {
-		position.0.y -= movement;
+		wish_direction.y -= 1.0;
 	}
 
-	// Rotate
-	// Need to divide by dt_u since the accumulators can be updated multiple times per tick
-	// Looks stupid but I swear semantically it makes sense (I hope, I've tried everything to fix this shit)
-	direction.yaw += sensitivity.0 * time.dt_u * controller.direction_yaw_accu / time.dt_u.as_secs_f32();
-	direction.pitch -= sensitivity.0 * time.dt_u * controller.direction_pitch_accu / time.dt_u.as_secs_f32();
+	let (yaw_counts, pitch_counts) = controller.take_mouse_motion();
+
+	let (yaw_counts, pitch_counts) = match smoothing {
+		None => {
+			position.0 += wish_direction * (movement_speed.0 * time.dt_u);
+			(yaw_counts, pitch_counts)
+		}
+		Some(mut smoothing) => {
+			// The distance moved in a second is the speed as a plain number
+			let target_velocity = wish_direction * (movement_speed.0 * Duration::from_secs(1));
 
-	controller.direction_yaw_accu = 0.0;
-	controller.direction_pitch_accu = 0.0;
+			let (movement, yaw_counts, pitch_counts) =
+				smoothing.step(target_velocity, yaw_counts, pitch_counts, time.dt_u);
+
+			position.0 += movement;
+			(yaw_counts, pitch_counts)
+		}
+	};
+
+	// Rotate, the mouse counts are angles already so the update rate doesn't matter
+	direction.yaw += sensitivity.turn(yaw_counts, &input_settings);
+	direction.pitch -= sensitivity.turn(pitch_counts, &input_settings);
 
 	// Keep the camera's angle from going too high/low.
 	direction.pitch.clamp(rad!(-SAFE_FRAC_PI_2), rad!(SAFE_FRAC_PI_2));
 }
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// One of the camera's axes in world space, `Vec3::unit_z()` being where it
+/// looks
+fn view_axis(position: Position, direction: Direction, axis: Vec3<f32>) -> Vec3<f32> {
+	calc_view_matrix(position, direction).inverted().mul_direction(axis)
+}
+
+fn toggle_orbit(
+	mut commands: Commands,
+	q: Query<(Entity, &Position, &Direction, Option<&OrbitController>), (With<ActiveCamera>, Without<Presentation>)>,
+	mut keyboard_events: EventReader<KeyboardInputEvent>,
+	key_bindings: Res<KeyBindings>,
+) {
+	if !key_bindings.has_pressed(Action::ToggleOrbit, keyboard_events.read()) {
+		return;
+	}
+
+	let Ok((entity, position, direction, orbit)) = q.get_single() else {
+		return;
+	};
+
+	// The position and direction are kept when leaving the orbit, so flying
+	// starts from where the orbit was
+	if orbit.is_some() {
+		commands.entity(entity).remove::<OrbitController>();
+	} else {
+		commands.entity(entity).insert(OrbitController::in_front_of(
+			*position,
+			*direction,
+			OrbitController::DEFAULT_DISTANCE,
+		));
+	}
+}
+
+fn process_orbit_input(
+	mut q: Query<&mut OrbitController, (With<ActiveCamera>, Without<Presentation>)>,
+	mouse_input_events: EventReader<MouseInputEvent>,
+	mouse_events: EventReader<MouseMotionEvent>,
+	mut wheel_events: EventReader<MouseWheelEvent>,
+	input_settings: Res<InputSettings>,
+) {
+	let Ok(mut orbit) = q.get_single_mut() else {
+		return;
+	};
+
+	let mouse_input = mouse_input_events.process();
+	if let Some(state) = mouse_input.latest_state(MouseButton::Right) {
+		orbit.rotating = state == ElementState::Pressed;
+	}
+	if let Some(state) = mouse_input.latest_state(MouseButton::Middle) {
+		orbit.panning = state == ElementState::Pressed;
+	}
+
+	let motion_delta = mouse_events.process().delta_sum();
+	let motion_delta = Vec2::new(motion_delta.x as f32, motion_delta.y as f32);
+
+	if orbit.rotating {
+		orbit.yaw_accu += motion_delta.x;
+		orbit.pitch_accu += input_settings.pitch_delta(motion_delta.y);
+	} else if orbit.panning {
+		orbit.pan_accu += motion_delta;
+	}
+
+	let lines = wheel_events
+		.read()
+		.map(|MouseWheelEvent { wheel_delta }| match wheel_delta {
+			MouseScrollDelta::LineDelta(_, y) => *y,
+			MouseScrollDelta::PixelDelta(delta) => delta.y as f32 / ScrollBinding::PIXELS_PER_LINE,
+		})
+		.sum::<f32>();
+
+	// Scrolling up gets closer
+	if lines != 0.0 {
+		orbit.distance =
+			(orbit.distance / ScrollBinding::FACTOR_PER_LINE.powf(lines)).max(OrbitController::MIN_DISTANCE);
+	}
+}
+
+fn update_orbit(
+	mut q: Query<
+		(&mut OrbitController, &mut Position, &mut Direction, &Sensitivity),
+		(With<ActiveCamera>, Without<Presentation>),
+	>,
+	input_settings: Res<InputSettings>,
+) {
+	let Ok((mut orbit, mut position, mut direction, sensitivity)) = q.get_single_mut() else {
+		return;
+	};
+	let orbit = &mut *orbit;
+
+	// Same as the fly controls, see update_camera
+	orbit.yaw += sensitivity.turn(orbit.yaw_accu, &input_settings);
+	orbit.pitch -= sensitivity.turn(orbit.pitch_accu, &input_settings);
+	orbit.pitch.clamp(rad!(-SAFE_FRAC_PI_2), rad!(SAFE_FRAC_PI_2));
+
+	orbit.yaw_accu = 0.0;
+	orbit.pitch_accu = 0.0;
+
+	direction.yaw = orbit.yaw;
+	direction.pitch = orbit.pitch;
+
+	// Dragging moves the focus along the view plane, so it follows the cursor
+	let right = view_axis(*position, *direction, Vec3::unit_x());
+	let up = view_axis(*position, *direction, Vec3::unit_y());
+	let pan = orbit.pan_accu * orbit.distance * OrbitController::PAN_PER_PIXEL;
+	orbit.focus.0 += up * pan.y - right * pan.x;
+	orbit.pan_accu = Vec2::zero();
+
+	let forward = view_axis(*position, *direction, Vec3::unit_z());
+	position.0 = orbit.focus.0 - forward * orbit.distance;
+}
@@ -0,0 +1,262 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use bevy_ecs::{
+	event::EventReader,
+	query::With,
+	schedule::IntoSystemConfigs,
+	system::{Local, Query, Res, ResMut},
+};
+use brainrot::{
+	bevy::{self, App, Plugin},
+	rad,
+	vek::Vec3,
+	Direction, Frustum, Position,
+};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use super::{
+	camera::{
+		ActiveCamera, CameraControl, CameraController, MovementSmoothing, MovementSpeed, OrbitController, Sprint,
+	},
+	console::{self, Console},
+	events::KeyboardInputEvent,
+	gameloop::Update,
+	key_bindings::{Action, HeldKeys, KeyBindings},
+	params::ParamEditor,
+	persistence,
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Lets the active camera's pose be saved to a slot and recalled later (see
+/// [`Action::PoseSlot`]). The slots are kept in a file, next to the executable
+/// by default, so that they survive restarts.
+///
+/// Needs to be added after the camera and key bindings plugins.
+pub struct CameraPosesPlugin {
+	pub path: PathBuf,
+}
+
+impl Default for CameraPosesPlugin {
+	fn default() -> Self {
+		let path = std::env::current_exe()
+			.map(|exe| exe.with_file_name(CameraPoses::FILE_NAME))
+			.unwrap_or_else(|_| PathBuf::from(CameraPoses::FILE_NAME));
+
+		Self { path }
+	}
+}
+
+impl Plugin for CameraPosesPlugin {
+	fn build(&self, app: &mut App) {
+		let poses = CameraPoses::load(&self.path).unwrap_or_else(|error| {
+			warn!("Couldn't load the camera poses, starting without any: {:#}", error);
+			CameraPoses::empty(self.path.clone())
+		});
+
+		app.world.insert_resource(poses);
+
+		app.add_systems(
+			Update,
+			// Not gated on the console, the held keys need its events too
+			process_pose_keys.in_set(CameraControl),
+		);
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Everything needed to put a camera back exactly where it was. Plain numbers
+/// so that the file stays readable, the angles are in radians.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub struct CameraPose {
+	pub position: [f32; 3],
+	pub yaw: f32,
+	pub pitch: f32,
+	pub y_fov: f32,
+	pub z_near: f32,
+	pub z_far: f32,
+}
+
+impl CameraPose {
+	pub fn new(position: Position, direction: Direction, frustum: Frustum) -> Self {
+		Self {
+			position: position.0.into_array(),
+			yaw: direction.yaw.to_radians(),
+			pitch: direction.pitch.to_radians(),
+			y_fov: frustum.y_fov,
+			z_near: frustum.z_near,
+			z_far: frustum.z_far,
+		}
+	}
+
+	pub fn position(&self) -> Position {
+		Vec3::from(self.position).into()
+	}
+
+	pub fn direction(&self) -> Direction {
+		let mut direction = Direction::default();
+		direction.yaw = rad!(self.yaw);
+		direction.pitch = rad!(self.pitch);
+		direction
+	}
+
+	pub fn frustum(&self) -> Frustum {
+		Frustum {
+			y_fov: self.y_fov,
+			z_near: self.z_near,
+			z_far: self.z_far,
+		}
+	}
+}
+
+/// The saved camera poses, written back to the file every time one is saved
+#[derive(bevy::Resource, Clone, Debug, PartialEq)]
+pub struct CameraPoses {
+	path: PathBuf,
+	slots: Vec<Option<CameraPose>>,
+}
+
+impl CameraPoses {
+	pub const FILE_NAME: &'static str = "camera_poses.ron";
+
+	pub fn empty(path: PathBuf) -> Self {
+		Self {
+			path,
+			slots: vec![None; Action::POSE_SLOTS as usize],
+		}
+	}
+
+	/// A missing file is the same as no poses, a broken one falls back to its
+	/// backup (see [`persistence::load_with_backup`])
+	pub fn load(path: &Path) -> Result<Self> {
+		let mut poses = Self::empty(path.to_owned());
+
+		let Some(slots) =
+			persistence::load_with_backup(path, |text| Ok(ron::from_str::<Vec<Option<CameraPose>>>(text)?))?
+		else {
+			return Ok(poses);
+		};
+
+		// Keep the slot count fixed even if the file was edited by hand
+		for (slot, pose) in poses.slots.iter_mut().zip(slots) {
+			*slot = pose;
+		}
+
+		Ok(poses)
+	}
+
+	/// See [`persistence::safe_write`], a crash in the middle of writing can't
+	/// leave a broken file behind
+	pub fn save(&self) -> Result<()> {
+		let text = ron::ser::to_string_pretty(&self.slots, ron::ser::PrettyConfig::default())?;
+		persistence::safe_write(&self.path, text.as_bytes())
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	pub fn get(&self, slot: u8) -> Option<CameraPose> {
+		self.slots.get(slot as usize).copied().flatten()
+	}
+
+	pub fn set(&mut self, slot: u8, pose: CameraPose) {
+		if let Some(existing) = self.slots.get_mut(slot as usize) {
+			*existing = Some(pose);
+		}
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+fn process_pose_keys(
+	mut q: Query<
+		(
+			&mut Position,
+			&mut Direction,
+			&mut Frustum,
+			Option<&mut CameraController>,
+			Option<(&mut Sprint, &mut MovementSpeed)>,
+			Option<&mut MovementSmoothing>,
+			Option<&mut OrbitController>,
+		),
+		With<ActiveCamera>,
+	>,
+	mut poses: ResMut<CameraPoses>,
+	mut keyboard_events: EventReader<KeyboardInputEvent>,
+	key_bindings: Res<KeyBindings>,
+	mut held_keys: Local<HeldKeys>,
+	console: Option<Res<Console>>,
+	editor: Option<Res<ParamEditor>>,
+) {
+	let events = keyboard_events.read().collect::<Vec<_>>();
+	// Even while the console is open, so that a modifier released in the
+	// meantime doesn't stay held
+	held_keys.update(events.iter().copied());
+
+	if !console::is_console_closed(console, editor) {
+		return;
+	}
+
+	let pressed = |slot: &u8| key_bindings.has_pressed(Action::PoseSlot(*slot), events.iter().copied());
+
+	let Some(slot) = (0..Action::POSE_SLOTS).find(pressed) else {
+		return;
+	};
+
+	let Ok((mut position, mut direction, mut frustum, controller, sprint, smoothing, orbit)) = q.get_single_mut()
+	else {
+		return;
+	};
+
+	if key_bindings.is_held(Action::SavePoseModifier, &held_keys) {
+		poses.set(slot, CameraPose::new(*position, *direction, *frustum));
+
+		match poses.save() {
+			Ok(()) => info!("Saved the camera pose to slot {}", slot + 1),
+			Err(error) => warn!("Couldn't save the camera poses: {:#}", error),
+		}
+		return;
+	}
+
+	let Some(pose) = poses.get(slot) else {
+		info!("No camera pose in slot {}", slot + 1);
+		return;
+	};
+
+	*position = pose.position();
+	*direction = pose.direction();
+	*frustum = pose.frustum();
+
+	// Anything still in motion would carry on from the recalled pose, so the
+	// camera would drift away from it
+	if let Some(mut controller) = controller {
+		controller.reset();
+	}
+	if let Some((mut sprint, mut speed)) = sprint {
+		sprint.reset(&mut speed);
+	}
+	if let Some(mut smoothing) = smoothing {
+		smoothing.reset();
+	}
+	if let Some(mut orbit) = orbit {
+		*orbit = OrbitController::in_front_of(*position, *direction, orbit.distance);
+	}
+
+	info!("Recalled the camera pose in slot {}", slot + 1);
+}
@@ -0,0 +1,230 @@
+use anyhow::{bail, Context, Result};
+use bevy_ecs::{
+	entity::Entity,
+	query::With,
+	schedule::{IntoSystemConfigs, SystemSet},
+	system::{Query, Res},
+	world::World,
+};
+use brainrot::{
+	bevy::{self, App, Plugin},
+	vek::Vec3,
+	Frustum, Position,
+};
+
+use super::{
+	camera::{ActiveCamera, Camera, CameraControl},
+	console,
+	gameloop::Update,
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Lets the camera's near and far planes follow the [`SceneBounds`], and adds
+/// the `clip_planes` console command to inspect or change them.
+///
+/// Needs to be added after the camera plugin. Only the cameras that exist by
+/// then get [`ClipPlanes`], the others keep their frustum as it is.
+pub struct ClipPlanesPlugin;
+
+impl Plugin for ClipPlanesPlugin {
+	fn build(&self, app: &mut App) {
+		app.world.insert_resource(SceneBounds::default());
+
+		let camera_entities = app
+			.world
+			.query_filtered::<Entity, With<Camera>>()
+			.iter(&app.world)
+			.collect::<Vec<_>>();
+
+		for camera_entity in camera_entities {
+			app.world.entity_mut(camera_entity).insert(ClipPlanes::Manual);
+		}
+
+		console::register_command(
+			app,
+			"clip_planes",
+			"clip_planes [auto | <near> <far>]: Show the clip planes and their depth precision, or change them",
+			clip_planes,
+		);
+
+		app.add_systems(
+			Update,
+			adjust_clip_planes.after(CameraControl).in_set(ClipPlanesAdjustment),
+		);
+	}
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ClipPlanesAdjustment;
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// The box containing everything that rays can hit.
+///
+/// The scene only exists in the shaders, so this has to be kept in sync by
+/// hand.
+#[derive(bevy::Resource, Copy, Clone, Debug, PartialEq)]
+pub struct SceneBounds {
+	pub min: Vec3<f32>,
+	pub max: Vec3<f32>,
+}
+
+impl Default for SceneBounds {
+	fn default() -> Self {
+		// The two spheres of the default `SdfScene`
+		Self {
+			min: Vec3::new(-1.0, -1.0, -1.0),
+			max: Vec3::new(4.0, 5.0, 3.0),
+		}
+	}
+}
+
+impl SceneBounds {
+	pub fn center(&self) -> Vec3<f32> {
+		(self.min + self.max) / 2.0
+	}
+
+	/// Distance from the point to the closest point of the bounds, zero if
+	/// the point is inside
+	pub fn closest_distance(&self, point: Vec3<f32>) -> f32 {
+		let closest = Vec3::new(
+			point.x.clamp(self.min.x, self.max.x),
+			point.y.clamp(self.min.y, self.max.y),
+			point.z.clamp(self.min.z, self.max.z),
+		);
+
+		(point - closest).magnitude()
+	}
+
+	/// Distance from the point to the farthest corner of the bounds
+	pub fn farthest_distance(&self, point: Vec3<f32>) -> f32 {
+		Vec3::new(
+			(point.x - self.min.x).abs().max((point.x - self.max.x).abs()),
+			(point.y - self.min.y).abs().max((point.y - self.max.y).abs()),
+			(point.z - self.min.z).abs().max((point.z - self.max.z).abs()),
+		)
+		.magnitude()
+	}
+}
+
+/// How the near and far planes of the camera's [`Frustum`] are chosen
+#[derive(bevy::Component, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ClipPlanes {
+	/// The planes are left as they are set
+	Manual,
+	/// The planes are derived from the [`SceneBounds`] and the camera position
+	/// every update
+	Auto,
+}
+
+impl ClipPlanes {
+	/// How much bigger than the scene the far plane is, so that the farthest
+	/// surfaces don't get clipped
+	const FAR_MARGIN: f32 = 0.05;
+
+	/// The biggest far/near ratio, past which the depth precision gets too bad
+	const MAX_DEPTH_RATIO: f32 = 10_000.0;
+
+	const MIN_Z_NEAR: f32 = 0.01;
+
+	/// How much (relatively) the ideal plane has to move away from the current
+	/// one before the plane follows it, so that the planes don't jitter when the
+	/// camera barely moves
+	const HYSTERESIS: f32 = 0.1;
+
+	fn auto_planes(bounds: &SceneBounds, position: Vec3<f32>) -> (f32, f32) {
+		let z_far = bounds.farthest_distance(position) * (1.0 + Self::FAR_MARGIN);
+
+		// Half the distance to the scene leaves some room to move towards it
+		let z_near = (bounds.closest_distance(position) / 2.0)
+			.max(z_far / Self::MAX_DEPTH_RATIO)
+			.max(Self::MIN_Z_NEAR);
+
+		(z_near, z_far)
+	}
+
+	fn settle(current: f32, target: f32) -> f32 {
+		if (target / current - 1.0).abs() > Self::HYSTERESIS {
+			target
+		} else {
+			current
+		}
+	}
+}
+
+/// The smallest depth difference that can still be told apart at `distance`
+/// with a 24-bit depth buffer and the camera's projection matrix
+pub fn depth_precision(z_near: f32, z_far: f32, distance: f32) -> f32 {
+	distance * distance * (z_far - z_near) / (z_far * z_near * (1 << 24) as f32)
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+fn adjust_clip_planes(bounds: Res<SceneBounds>, mut q: Query<(&Position, &ClipPlanes, &mut Frustum)>) {
+	for (position, clip_planes, mut frustum) in q.iter_mut() {
+		if *clip_planes == ClipPlanes::Manual {
+			continue;
+		}
+
+		let (z_near, z_far) = ClipPlanes::auto_planes(&bounds, position.0);
+		let z_near = ClipPlanes::settle(frustum.z_near, z_near);
+		let z_far = ClipPlanes::settle(frustum.z_far, z_far);
+
+		// Avoid triggering change detection when nothing moved
+		if z_near != frustum.z_near || z_far != frustum.z_far {
+			frustum.z_near = z_near;
+			frustum.z_far = z_far;
+		}
+	}
+}
+
+fn clip_planes(world: &mut World, args: &[String]) -> Result<String> {
+	let bounds = *world.resource::<SceneBounds>();
+	let mut q = world.query_filtered::<(&Position, &mut ClipPlanes, &mut Frustum), With<ActiveCamera>>();
+	let (position, mut clip_planes, mut frustum) = q
+		.get_single_mut(world)
+		.context("The active camera has no clip planes")?;
+
+	match args {
+		[] => {}
+		[mode] if mode == "auto" => *clip_planes = ClipPlanes::Auto,
+		[z_near, z_far] => {
+			let z_near = z_near.parse::<f32>().context("Expected a number for the near plane")?;
+			let z_far = z_far.parse::<f32>().context("Expected a number for the far plane")?;
+
+			if z_near <= 0.0 || z_far <= z_near {
+				bail!("Expected 0 < near < far");
+			}
+
+			*clip_planes = ClipPlanes::Manual;
+			frustum.z_near = z_near;
+			frustum.z_far = z_far;
+		}
+		_ => bail!("Usage: clip_planes [auto | <near> <far>]"),
+	}
+
+	let distance = (bounds.center() - position.0).magnitude();
+
+	Ok(format!(
+		"{:?}: near = {}, far = {}\ndepth precision: {:.6} at the scene center ({:.2} away), {:.6} at the far plane",
+		*clip_planes,
+		frustum.z_near,
+		frustum.z_far,
+		depth_precision(frustum.z_near, frustum.z_far, distance),
+		distance,
+		depth_precision(frustum.z_near, frustum.z_far, frustum.z_far),
+	))
+}
@@ -0,0 +1,612 @@
+use std::{
+	collections::VecDeque,
+	mem,
+	sync::Arc,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use bevy_ecs::{
+	entity::Entity,
+	event::EventReader,
+	query::With,
+	system::{Res, ResMut},
+	world::World,
+};
+use brainrot::{
+	bevy::{self, App, Plugin},
+	spd,
+};
+use hashlink::LinkedHashMap;
+use image::{DynamicImage, ImageBuffer, Rgba};
+use log::{info, LevelFilter};
+use wgpu::{Buffer, BufferUsages, TextureFormat};
+use winit::keyboard::{Key, KeyCode, NamedKey, PhysicalKey};
+
+use crate::{
+	core::{
+		camera::{ActiveCamera, InputSettings, MovementSmoothing, MovementSpeed, ScrollBinding},
+		display::{AppWindow, WindowSettings},
+		entity_label,
+		events::KeyboardInputEvent,
+		gameloop::{IterStep, RequestExit, Time, Update},
+		gpu::Gpu,
+		logging::LogControl,
+		params::{self, ParamEditor, ParamRegistry},
+		rendering::{compute::ComputeRenderer, dynamic_quality::DynamicQuality, globals::RenderSettings},
+	},
+	libs::{buffer::atomic_counter::AtomicCounter, smart_arc::Sarc, texture::Tex},
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+	fn build(&self, app: &mut App) {
+		app.init_resource::<Console>();
+
+		register_command(app, "help", "List all the commands", help);
+		register_command(app, "quit", "Exit the app", quit);
+		register_command(
+			app,
+			"set",
+			"set <setting> <value>: Change a setting (target_fps, target_ups, speed, scroll, smoothing, invert_y, sensitivity, dynamic_quality, grid, stable_seeds, early_submit, or any parameter listed by `get`)",
+			set,
+		);
+		register_command(
+			app,
+			"log",
+			"log <target> <level>: Change the log level of a module (e.g. pbr_tracer::libs::shader)",
+			log_level,
+		);
+		register_command(
+			app,
+			"dump_buffer",
+			"dump_buffer [entity]: List the GPU buffers, or print the content of one",
+			dump_buffer,
+		);
+		register_command(
+			app,
+			"screenshot",
+			"screenshot [path]: Save the render output as a PNG, or as an EXR if the path ends in .exr",
+			screenshot,
+		);
+
+		app.add_systems(Update, process_input);
+		app.add_systems(IterStep, execute_commands);
+	}
+}
+
+/// Run condition for systems that should ignore the keyboard while the
+/// console, or the [`ParamEditor`], is capturing it.
+pub fn is_console_closed(console: Option<Res<Console>>, editor: Option<Res<ParamEditor>>) -> bool {
+	console.map(|console| !console.open).unwrap_or(true) && editor.map(|editor| !editor.active).unwrap_or(true)
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+pub type CommandHandler = Arc<dyn Fn(&mut World, &[String]) -> Result<String> + Send + Sync>;
+
+pub struct ConsoleCommand {
+	pub help: String,
+	pub handler: CommandHandler,
+}
+
+#[derive(bevy::Resource, Default)]
+pub struct ConsoleCommands {
+	commands: LinkedHashMap<String, ConsoleCommand>,
+}
+
+impl ConsoleCommands {
+	/// Register a command under the given name, replacing any previous command
+	/// with the same name.
+	///
+	/// The handler receives the world and the arguments that were typed after
+	/// the command name, and returns the text to print to the console.
+	pub fn register<F>(&mut self, name: impl Into<String>, help: impl Into<String>, handler: F) -> &mut Self
+	where
+		F: Fn(&mut World, &[String]) -> Result<String> + Send + Sync + 'static,
+	{
+		self.commands.insert(
+			name.into(),
+			ConsoleCommand {
+				help: help.into(),
+				handler: Arc::new(handler),
+			},
+		);
+		self
+	}
+
+	pub fn get(&self, name: &str) -> Option<&ConsoleCommand> {
+		self.commands.get(name)
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = (&String, &ConsoleCommand)> {
+		self.commands.iter()
+	}
+
+	/// Complete the given prefix as far as possible, also returning all the
+	/// command names that match it
+	fn complete(&self, prefix: &str) -> (String, Vec<&str>) {
+		complete(self.commands.keys().map(String::as_str), prefix)
+	}
+}
+
+/// Complete the given prefix as far as possible with the given names, also
+/// returning all the names that match it
+pub fn complete<'a>(names: impl IntoIterator<Item = &'a str>, prefix: &str) -> (String, Vec<&'a str>) {
+	let candidates = names
+		.into_iter()
+		.filter(|name| name.starts_with(prefix))
+		.collect::<Vec<_>>();
+
+	// The longest prefix that all the candidates share
+	let completed = candidates
+		.iter()
+		.skip(1)
+		.fold(candidates.first().copied().unwrap_or(prefix), |common, name| {
+			let len = common
+				.char_indices()
+				.zip(name.chars())
+				.take_while(|((_, a), b)| a == b)
+				.last()
+				.map(|((i, a), _)| i + a.len_utf8())
+				.unwrap_or(0);
+			&common[..len]
+		});
+
+	(completed.to_owned(), candidates)
+}
+
+/// Register a console command, see [`ConsoleCommands::register`].
+/// Can be called by any plugin, whether or not the [`ConsolePlugin`] was
+/// already added.
+pub fn register_command<F>(app: &mut App, name: &str, help: &str, handler: F)
+where
+	F: Fn(&mut World, &[String]) -> Result<String> + Send + Sync + 'static,
+{
+	app.world
+		.get_resource_or_insert_with(ConsoleCommands::default)
+		.register(name, help, handler);
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+#[derive(bevy::Resource, Default)]
+pub struct Console {
+	pub open: bool,
+	input: String,
+
+	/// All the submitted lines of the session, oldest first
+	history: Vec<String>,
+	/// Position in the history while browsing it with the arrow keys
+	history_cursor: Option<usize>,
+
+	/// Lines waiting to be executed at the next `IterStep`
+	pending: Vec<String>,
+	/// The most recent output lines
+	output: VecDeque<String>,
+}
+
+impl Console {
+	const MAX_OUTPUT_LINES: usize = 64;
+
+	pub fn print(&mut self, text: impl AsRef<str>) {
+		for line in text.as_ref().lines() {
+			info!("{}", line);
+
+			if self.output.len() >= Self::MAX_OUTPUT_LINES {
+				self.output.pop_front();
+			}
+			self.output.push_back(line.to_owned());
+		}
+	}
+
+	pub fn output(&self) -> impl Iterator<Item = &String> {
+		self.output.iter()
+	}
+
+	pub fn input(&self) -> &str {
+		&self.input
+	}
+
+	/// Queue a line to be executed as if it was typed in the console
+	pub fn submit(&mut self, line: impl Into<String>) {
+		let line = line.into();
+
+		if self.history.last() != Some(&line) {
+			self.history.push(line.clone());
+		}
+		self.history_cursor = None;
+
+		self.print(format!("> {}", line));
+		self.pending.push(line);
+	}
+
+	fn browse_history(&mut self, older: bool) {
+		if self.history.is_empty() {
+			return;
+		}
+
+		self.history_cursor = match (self.history_cursor, older) {
+			(None, true) => Some(self.history.len() - 1),
+			(None, false) => None,
+			(Some(i), true) => Some(i.saturating_sub(1)),
+			(Some(i), false) if i + 1 < self.history.len() => Some(i + 1),
+			(Some(_), false) => None,
+		};
+
+		self.input = self
+			.history_cursor
+			.map(|i| self.history[i].clone())
+			.unwrap_or_default();
+	}
+}
+
+/// Split a command line into arguments on whitespace, keeping quoted
+/// arguments together
+fn parse_args(line: &str) -> Vec<String> {
+	let mut args = Vec::new();
+	let mut current = String::new();
+	let mut quoted = false;
+
+	for c in line.chars() {
+		match c {
+			'"' => quoted = !quoted,
+			c if c.is_whitespace() && !quoted => {
+				if !current.is_empty() {
+					args.push(mem::take(&mut current));
+				}
+			}
+			c => current.push(c),
+		}
+	}
+
+	if !current.is_empty() {
+		args.push(current);
+	}
+
+	args
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+fn process_input(
+	mut console: ResMut<Console>,
+	commands: Res<ConsoleCommands>,
+	params: Option<Res<ParamRegistry>>,
+	app_window: Res<AppWindow>,
+	window_settings: Res<WindowSettings>,
+	mut keyboard_events: EventReader<KeyboardInputEvent>,
+) {
+	let mut needs_redraw = false;
+
+	for KeyboardInputEvent {
+		state,
+		logical_key,
+		physical_key,
+	} in keyboard_events.read()
+	{
+		if !state.is_pressed() {
+			continue;
+		}
+
+		if *physical_key == PhysicalKey::Code(KeyCode::Backquote) {
+			console.open = !console.open;
+			needs_redraw = true;
+			continue;
+		}
+
+		if !console.open {
+			continue;
+		}
+
+		needs_redraw = true;
+
+		match logical_key {
+			Key::Named(NamedKey::Enter) => {
+				let line = mem::take(&mut console.input);
+				if !line.trim().is_empty() {
+					console.submit(line);
+				}
+			}
+			Key::Named(NamedKey::Backspace) => {
+				console.input.pop();
+			}
+			Key::Named(NamedKey::Space) => console.input.push(' '),
+			Key::Named(NamedKey::ArrowUp) => console.browse_history(true),
+			Key::Named(NamedKey::ArrowDown) => console.browse_history(false),
+			Key::Named(NamedKey::Tab) => {
+				// Only the command name is completed, and the parameter name of
+				// `set` and `get`
+				let param = ["set ", "get "]
+					.into_iter()
+					.find_map(|command| console.input.strip_prefix(command).map(|rest| (command, rest)));
+
+				if !console.input.contains(' ') {
+					let (completed, candidates) = commands.complete(&console.input);
+					if candidates.len() > 1 {
+						console.print(candidates.join("  "));
+					}
+					console.input = completed;
+				} else if let (Some((command, prefix)), Some(params)) = (param, &params) {
+					if !prefix.contains(' ') {
+						let (completed, candidates) = complete(params.names(), prefix);
+						if candidates.len() > 1 {
+							console.print(candidates.join("  "));
+						}
+						console.input = format!("{}{}", command, completed);
+					}
+				}
+			}
+			Key::Character(text) => console.input.push_str(text),
+			_ => needs_redraw = false,
+		}
+	}
+
+	// There is no text rendering yet, so the input line is shown in the window
+	// title and the output goes to the log
+	if needs_redraw {
+		let title = if console.open {
+			format!("{} > {}_", window_settings.title, console.input)
+		} else {
+			window_settings.title.to_owned()
+		};
+
+		app_window.winit_window.set_title(&title);
+	}
+}
+
+/// Execute the queued command lines. Runs as an exclusive system in
+/// `IterStep`, so that the commands have full access to the world at a point
+/// where no other system is running.
+fn execute_commands(world: &mut World) {
+	let pending = mem::take(&mut world.resource_mut::<Console>().pending);
+
+	for line in pending {
+		let args = parse_args(&line);
+		let Some((name, args)) = args.split_first() else {
+			continue;
+		};
+
+		// Clone the handler out so that the world can be borrowed mutably by it
+		let handler = world
+			.resource::<ConsoleCommands>()
+			.get(name)
+			.map(|command| command.handler.clone());
+
+		let output = match handler {
+			Some(handler) => handler(world, args),
+			None => Err(anyhow!("Unknown command `{}`, type `help` for a list of commands", name)),
+		};
+
+		let mut console = world.resource_mut::<Console>();
+		match output {
+			Ok(text) => console.print(text),
+			Err(error) => console.print(format!("Error: {:#}", error)),
+		}
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+fn help(world: &mut World, _args: &[String]) -> Result<String> {
+	Ok(world
+		.resource::<ConsoleCommands>()
+		.iter()
+		.map(|(name, command)| format!("{}: {}", name, command.help))
+		.collect::<Vec<_>>()
+		.join("\n"))
+}
+
+fn quit(world: &mut World, _args: &[String]) -> Result<String> {
+	world.insert_resource(RequestExit);
+	Ok("Bye".to_owned())
+}
+
+fn set(world: &mut World, args: &[String]) -> Result<String> {
+	let [setting, value] = args else {
+		bail!("Usage: set <setting> <value>");
+	};
+
+	match setting.as_str() {
+		"target_fps" => {
+			let target_fps = match value.as_str() {
+				"none" | "0" => None,
+				value => Some(value.parse::<u32>().context("Expected a number or `none`")?),
+			};
+			world.resource_mut::<Time>().target_fps = target_fps;
+		}
+		"target_ups" => {
+			let target_ups = value.parse::<u32>().context("Expected a number")?;
+			if target_ups == 0 {
+				bail!("target_ups needs to be at least 1");
+			}
+			world.resource_mut::<Time>().target_ups = target_ups;
+		}
+		"speed" => {
+			let speed = value.parse::<f32>().context("Expected a number")?;
+			for mut movement_speed in world
+				.query_filtered::<&mut MovementSpeed, With<ActiveCamera>>()
+				.iter_mut(world)
+			{
+				movement_speed.0 = spd!(speed);
+			}
+		}
+		"scroll" => {
+			let scroll_binding = match value.as_str() {
+				"zoom" => ScrollBinding::Zoom,
+				"speed" => ScrollBinding::Speed,
+				_ => bail!("Expected `zoom` or `speed`"),
+			};
+			world.insert_resource(scroll_binding);
+		}
+		"smoothing" => {
+			let smoothing = value.parse::<bool>().context("Expected `true` or `false`")?;
+			let camera_entity = entity_label::single_entity::<ActiveCamera>(world)?;
+
+			if smoothing {
+				world.entity_mut(camera_entity).insert(MovementSmoothing::default());
+			} else {
+				world.entity_mut(camera_entity).remove::<MovementSmoothing>();
+			}
+		}
+		"dynamic_quality" => {
+			let enabled = value.parse::<bool>().context("Expected `true` or `false`")?;
+			world.resource_mut::<DynamicQuality>().paused = !enabled;
+		}
+		"invert_y" => {
+			let invert_y = value.parse::<bool>().context("Expected `true` or `false`")?;
+			world.resource_mut::<InputSettings>().invert_y = invert_y;
+		}
+		"sensitivity" => {
+			let sensitivity_scale = value.parse::<f32>().context("Expected a number")?;
+			if sensitivity_scale <= 0.0 {
+				bail!("The sensitivity needs to be positive");
+			}
+			world.resource_mut::<InputSettings>().sensitivity_scale = sensitivity_scale;
+		}
+		"grid" => {
+			let grid = value.parse::<bool>().context("Expected `true` or `false`")?;
+			world.resource_mut::<RenderSettings>().show_reference_grid = grid;
+		}
+		"stable_seeds" => {
+			let stable_seeds = value.parse::<bool>().context("Expected `true` or `false`")?;
+			world.resource_mut::<RenderSettings>().stable_pixel_seeds = stable_seeds;
+		}
+		"early_submit" => {
+			let early_submit = value.parse::<bool>().context("Expected `true` or `false`")?;
+			world.resource_mut::<ComputeRenderer>().early_submit = early_submit;
+		}
+		// Anything else goes through the registered parameters
+		_ => return Ok(format!("{} = {}", setting, params::set_param(world, setting, value)?)),
+	}
+
+	Ok(format!("{} = {}", setting, value))
+}
+
+fn log_level(world: &mut World, args: &[String]) -> Result<String> {
+	let [target, level] = args else {
+		bail!("Usage: log <target> <level>");
+	};
+
+	let level = level
+		.parse::<LevelFilter>()
+		.context("Expected one of off, error, warn, info, debug, trace")?;
+	world.resource::<LogControl>().set_level(target.as_str(), level);
+
+	Ok(format!("Log level of `{}` set to {}", target, level))
+}
+
+fn dump_buffer(world: &mut World, args: &[String]) -> Result<String> {
+	let buffers = world
+		.query::<(Entity, &Sarc<Buffer>)>()
+		.iter(world)
+		.map(|(entity, buffer)| (entity, buffer.clone()))
+		.collect::<Vec<_>>();
+
+	let Some(index) = args.first() else {
+		return Ok(buffers
+			.iter()
+			.map(|(entity, buffer)| format!("{}: {} bytes, {:?}", entity.index(), buffer.size(), buffer.usage()))
+			.collect::<Vec<_>>()
+			.join("\n"));
+	};
+
+	let index = index.parse::<u32>().context("Expected an entity index")?;
+	let (_, buffer) = buffers
+		.iter()
+		.find(|(entity, _)| entity.index() == index)
+		.ok_or(anyhow!("No buffer on entity {}", index))?;
+
+	if !buffer.usage().contains(BufferUsages::COPY_SRC) {
+		bail!("The buffer wasn't created with COPY_SRC and can't be read back");
+	}
+
+	let words = AtomicCounter::readback(world.resource::<Gpu>(), buffer);
+	Ok(words
+		.chunks(8)
+		.map(|chunk| chunk.iter().map(|w| format!("{:08x}", w)).collect::<Vec<_>>().join(" "))
+		.collect::<Vec<_>>()
+		.join("\n"))
+}
+
+fn screenshot(world: &mut World, args: &[String]) -> Result<String> {
+	let path = match args.first() {
+		Some(path) => path.clone(),
+		None => {
+			let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+			format!("screenshot_{}.png", timestamp)
+		}
+	};
+
+	let tex = world
+		.resource::<ComputeRenderer>()
+		.output_textures
+		.first()
+		.ok_or(anyhow!("The compute renderer has no output texture"))?
+		.clone();
+
+	save_screenshot(world.resource::<Gpu>(), &tex, &path)?;
+
+	Ok(format!("Saved screenshot to `{}`", path))
+}
+
+/// Save a color texture as a PNG, the way the `screenshot` command does, or as
+/// a linear (not gamma encoded) EXR if the path ends in `.exr`
+pub fn save_screenshot(gpu: &Gpu, tex: &Tex, path: &str) -> Result<()> {
+	if tex.format() != TextureFormat::Rgba32Float {
+		bail!("Only Rgba32Float output textures can be saved, not {:?}", tex.format());
+	}
+
+	// EXR keeps the linear floats as they are, everything else gets 8 bits
+	let exr = path.to_lowercase().ends_with(".exr");
+
+	let bytes = tex.read_bytes(gpu);
+	let mut pixels = bytemuck::pod_collect_to_vec::<u8, f32>(&bytes);
+	if !exr {
+		// The surface does the sRGB encoding when rendering to the window, so it has
+		// to be done by hand here
+		for p in pixels.chunks_mut(4) {
+			for c in &mut p[..3] {
+				*c = c.powf(1.0 / 2.2);
+			}
+		}
+	}
+
+	let size = tex.size();
+	let image = ImageBuffer::<Rgba<f32>, _>::from_raw(size.width, size.height, pixels)
+		.ok_or(anyhow!("Unexpected texture data size"))?;
+
+	// The texture's y goes from bottom to top
+	let image = DynamicImage::ImageRgba32F(image).flipv();
+	let saved = if exr {
+		image.save(path)
+	} else {
+		image.to_rgba8().save(path)
+	};
+	saved.with_context(|| format!("Couldn't save the screenshot to `{}`", path))?;
+
+	Ok(())
+}
@@ -1,22 +1,29 @@
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 
-use bevy_ecs::{change_detection::DetectChanges, event::EventReader, system::ResMut};
+use bevy_ecs::{
+	change_detection::DetectChanges,
+	event::EventReader,
+	system::{Res, ResMut},
+};
 use brainrot::{
 	bevy::{self, App, Plugin},
-	size, Converter, ScreenSize,
+	size, Converter,
 };
+use log::warn;
 use winit::{
 	dpi::{PhysicalPosition, PhysicalSize},
 	event::WindowEvent,
-	keyboard::KeyCode,
+	event_loop::EventLoopBuilder,
 	window::{CursorGrabMode, Window, WindowBuilder},
 };
 
 use crate::{
 	core::{
-		event_processing::{EventReaderProcessor, ProcessedInputEvents},
 		events::{KeyboardInputEvent, WinitWindowEvent},
-		gameloop::Update,
+		gameloop::{Shutdown, Update},
+		key_bindings::{Action, KeyBindings},
+		size::WindowSize,
+		window_placement::{save_window_placement, track_window_placement, PendingWindowPlacement, WindowPlacement},
 	},
 	EventLoop,
 };
@@ -27,17 +34,59 @@ use crate::{
 --------------------------------------------------------------------------------
 */
 
-pub struct DisplayPlugin;
+pub struct DisplayPlugin {
+	/// Whether the window is shown. Automated runs use a hidden window.
+	pub visible: bool,
+	/// Allow creating the event loop outside of the main thread, which tests
+	/// need since they run on their own threads. Only supported on Linux and
+	/// Windows.
+	pub any_thread: bool,
+	/// Where the window's position, size and state are remembered between
+	/// runs. `None` to always open it centered.
+	pub placement_path: Option<PathBuf>,
+}
+
+impl Default for DisplayPlugin {
+	fn default() -> Self {
+		Self {
+			visible: true,
+			any_thread: false,
+			placement_path: Some(WindowPlacement::default_path()),
+		}
+	}
+}
 
 impl Plugin for DisplayPlugin {
 	fn build(&self, app: &mut App) {
 		let window_settings = WindowSettings {
 			title: "Pew Pew Ray Thingie",
-			size: size!(1920, 1080),
+			size: WindowSize(size!(1920, 1080)),
+			visible: self.visible,
 		};
 
-		let event_loop = EventLoop::new().expect("Couldn't create winit event_loop");
-		let app_window = AppWindow::new(&event_loop, &window_settings);
+		let placement = self.placement_path.as_ref().and_then(|path| {
+			WindowPlacement::load(path).unwrap_or_else(|error| {
+				warn!("Couldn't load the window placement, centering the window: {:#}", error);
+				None
+			})
+		});
+
+		let event_loop = new_event_loop(self.any_thread);
+		let app_window = AppWindow::new(&event_loop, &window_settings, placement.as_ref());
+
+		if let Some(path) = &self.placement_path {
+			// Start from the saved placement if it was restored, so that a maximized
+			// window keeps the rect it had before being maximized
+			let placement = placement
+				.filter(|_| app_window.placement_restored)
+				.unwrap_or_else(|| WindowPlacement::of(&app_window.winit_window));
+
+			app.world
+				.insert_resource(PendingWindowPlacement::new(path.clone(), placement));
+
+			app.add_systems(Update, track_window_placement);
+			app.add_systems(Shutdown, save_window_placement);
+		}
 
 		app.world.insert_resource(window_settings);
 		app.world.insert_non_send_resource(event_loop);
@@ -56,7 +105,8 @@ impl Plugin for DisplayPlugin {
 #[derive(bevy::Resource, Copy, Clone, Debug, Default)]
 pub struct WindowSettings {
 	pub title: &'static str,
-	pub size: ScreenSize,
+	pub size: WindowSize,
+	pub visible: bool,
 } // TODO either update this on resize or delete or make immutable or something
 
 #[derive(bevy::Resource)]
@@ -65,6 +115,9 @@ pub struct AppWindow {
 	pub winit_window: Arc<winit::window::Window>,
 
 	pub cursor_attached: bool,
+	/// Whether the window was put back where it was last time, instead of
+	/// being centered
+	pub placement_restored: bool,
 }
 
 /*
@@ -74,15 +127,18 @@ pub struct AppWindow {
 */
 
 impl AppWindow {
-	pub fn new(event_loop: &EventLoop, settings: &WindowSettings) -> Self {
+	pub fn new(event_loop: &EventLoop, settings: &WindowSettings, placement: Option<&WindowPlacement>) -> Self {
 		let window = WindowBuilder::new()
 			.with_title(settings.title)
-			.with_inner_size(Converter::<PhysicalSize<u32>>::convert(settings.size))
+			.with_inner_size(Converter::<PhysicalSize<u32>>::convert(settings.size.0))
+			.with_visible(settings.visible)
 			.build(event_loop)
 			.expect("Couldn't build winit window from event loop");
 
-		// Center the window
-		if let Some(monitor) = window.current_monitor() {
+		let placement_restored = placement.is_some_and(|placement| placement.restore(&window));
+
+		// Otherwise center the window
+		if let (false, Some(monitor)) = (placement_restored, window.current_monitor()) {
 			let screen_size = monitor.size();
 			let window_size = window.outer_size();
 
@@ -97,10 +153,39 @@ impl AppWindow {
 		Self {
 			winit_window: Arc::new(window),
 			cursor_attached: true,
+			placement_restored,
 		}
 	}
 }
 
+fn new_event_loop(any_thread: bool) -> EventLoop {
+	let mut builder = EventLoopBuilder::new();
+
+	if any_thread {
+		allow_any_thread(&mut builder);
+	}
+
+	builder.build().expect("Couldn't create winit event_loop")
+}
+
+#[cfg(target_os = "linux")]
+fn allow_any_thread(builder: &mut EventLoopBuilder<()>) {
+	// Sets the same flag for wayland
+	use winit::platform::x11::EventLoopBuilderExtX11;
+	builder.with_any_thread(true);
+}
+
+#[cfg(target_os = "windows")]
+fn allow_any_thread(builder: &mut EventLoopBuilder<()>) {
+	use winit::platform::windows::EventLoopBuilderExtWindows;
+	builder.with_any_thread(true);
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+fn allow_any_thread(_builder: &mut EventLoopBuilder<()>) {
+	log::warn!("The event loop can only be created on the main thread on this platform");
+}
+
 /*
 --------------------------------------------------------------------------------
 ||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
@@ -109,13 +194,14 @@ impl AppWindow {
 
 fn toggle_cursor_attached(
 	mut app_window: ResMut<AppWindow>,
-	keyboard_events: EventReader<KeyboardInputEvent>,
+	mut keyboard_events: EventReader<KeyboardInputEvent>,
+	key_bindings: Res<KeyBindings>,
 	mut winit_events: EventReader<WinitWindowEvent>,
 ) {
 	let mut needs_update = false;
 	let mut needs_reset = false;
 
-	if keyboard_events.process().has_pressed(KeyCode::Escape) {
+	if key_bindings.has_pressed(Action::ToggleCursor, keyboard_events.read()) {
 		app_window.cursor_attached = !app_window.cursor_attached;
 		needs_update = true;
 		needs_reset = true;
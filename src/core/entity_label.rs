@@ -0,0 +1,89 @@
+use std::{any::type_name, error::Error, fmt};
+
+use bevy_ecs::{
+	entity::Entity,
+	query::With,
+	system::{Query, SystemParam},
+	world::World,
+};
+use brainrot::bevy;
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// A marker component that says what an entity is, e.g. the
+/// [`WindowRenderTarget`](super::render_target::WindowRenderTarget) or the
+/// buffer that the renderers bind as `camera`.
+///
+/// Some labels are on many entities (every [`Camera`](super::camera::Camera)),
+/// the ones that are on a single entity can be looked up with
+/// [`single_entity`] or with the [`Labeled`] system param.
+pub trait EntityLabel: bevy::Component {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LabelError {
+	NotFound {
+		/// The name of the label, without its module path
+		label: &'static str,
+	},
+	Multiple {
+		label: &'static str,
+		count: usize,
+	},
+}
+
+impl fmt::Display for LabelError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			LabelError::NotFound { label } => write!(f, "Expected exactly one `{}` but found none", label),
+			LabelError::Multiple { label, count } => {
+				write!(f, "Expected exactly one `{}` but found {}", label, count)
+			}
+		}
+	}
+}
+
+impl Error for LabelError {}
+
+/// The only entity carrying the label `L`
+pub fn single_entity<L: EntityLabel>(world: &mut World) -> Result<Entity, LabelError> {
+	let mut entities = world.query_filtered::<Entity, With<L>>();
+	only_entity::<L>(entities.iter(world))
+}
+
+/// Resolves the only entity carrying the label `L`, for systems. Doesn't
+/// access any component, so it can be used next to any other query.
+#[derive(SystemParam)]
+pub struct Labeled<'w, 's, L: EntityLabel> {
+	entities: Query<'w, 's, Entity, With<L>>,
+}
+
+impl<L: EntityLabel> Labeled<'_, '_, L> {
+	pub fn entity(&self) -> Result<Entity, LabelError> {
+		only_entity::<L>(self.entities.iter())
+	}
+}
+
+fn only_entity<L: EntityLabel>(mut entities: impl Iterator<Item = Entity>) -> Result<Entity, LabelError> {
+	let label = label_name::<L>();
+
+	let entity = entities.next().ok_or(LabelError::NotFound { label })?;
+
+	match entities.count() {
+		0 => Ok(entity),
+		others => Err(LabelError::Multiple {
+			label,
+			count: others + 1,
+		}),
+	}
+}
+
+// Labels are plain unit structs, so the last segment of the path is the whole
+// name
+fn label_name<L>() -> &'static str {
+	let name = type_name::<L>();
+	name.rsplit("::").next().unwrap_or(name)
+}
@@ -8,7 +8,7 @@ use bevy_ecs::{
 };
 use brainrot::{
 	bevy::{self, App, Plugin},
-	vec2, MouseMotionDelta, ScreenSize,
+	vec2, MouseMotionDelta,
 };
 use events::{KeyboardInputEvent, MouseInputEvent, MouseMotionEvent, WindowResizedEvent};
 use winit::{
@@ -19,6 +19,7 @@ use winit::{
 use crate::core::{
 	events,
 	gameloop::{EventsCore, IterStep, Render, Update},
+	size::WindowSize,
 };
 
 /*
@@ -338,7 +339,7 @@ pub trait ProcessedChangeEvents {
 }
 
 impl ProcessedChangeEvents for ProcessedEventReader<WindowResizedEvent> {
-	type ItemType = ScreenSize;
+	type ItemType = WindowSize;
 
 	fn latest(&self) -> Option<Self::ItemType> {
 		self.events.last().map(|w| w.size)
@@ -1,10 +1,10 @@
 use bevy_ecs::event::Event;
 use brainrot::{
 	bevy::{App, Plugin},
-	MouseMotionDelta, ScreenSize,
+	MouseMotionDelta,
 };
 
-use super::event_processing::add_event;
+use super::{event_processing::add_event, size::WindowSize};
 
 /*
 --------------------------------------------------------------------------------
@@ -20,6 +20,7 @@ impl Plugin for EventsPlugin {
 		add_event::<KeyboardInputEvent>(app);
 		add_event::<MouseMotionEvent>(app);
 		add_event::<MouseWheelEvent>(app);
+		add_event::<MouseInputEvent>(app);
 		add_event::<WindowResizedEvent>(app);
 		add_event::<WinitWindowEvent>(app);
 	}
@@ -99,7 +100,7 @@ pub struct MouseInputEvent {
 
 #[derive(Event, Clone, Debug, PartialEq, Eq)]
 pub struct WindowResizedEvent {
-	pub size: ScreenSize,
+	pub size: WindowSize,
 }
 
 /// Event for *any* [`winit`] window event that might have been fired.
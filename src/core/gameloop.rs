@@ -9,7 +9,10 @@ use brainrot::{
 	Converter,
 };
 use log::trace;
-use winit::event::{DeviceEvent, Event, KeyEvent, WindowEvent};
+use winit::{
+	event::{DeviceEvent, Event, KeyEvent, WindowEvent},
+	event_loop::EventLoopWindowTarget,
+};
 
 use crate::{
 	core::{
@@ -18,7 +21,10 @@ use crate::{
 			KeyboardInputEvent, MouseInputEvent, MouseMotionEvent, MouseWheelEvent, WindowResizedEvent,
 			WinitWindowEvent,
 		},
+		size::WindowSize,
+		watchdog::{Heartbeat, Watchdog, Zone},
 	},
+	libs::metrics::RollingStats,
 	EventLoop,
 };
 
@@ -41,6 +47,9 @@ impl Plugin for GameloopPlugin {
 			last_update_time: Instant::now(),
 			last_render_time: Instant::now(),
 
+			ups_stats: Some(RollingStats::new()),
+			fps_stats: Some(RollingStats::new()),
+
 			..Default::default()
 		};
 
@@ -82,6 +91,16 @@ pub struct PreRender;
 #[derive(ScheduleLabel, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Render;
 
+/// The schedule that runs once when the event loop exits, however it exits.
+/// Meant for saving state, the window still exists at that point.
+#[derive(ScheduleLabel, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Shutdown;
+
+/// Insert this resource to make the event loop exit at the end of the current
+/// iteration
+#[derive(bevy::Resource, Debug, Copy, Clone)]
+pub struct RequestExit;
+
 /*
 --------------------------------------------------------------------------------
 ||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
@@ -111,13 +130,25 @@ pub struct Time {
 
 	pub ups: f32,
 	pub fps: f32,
+	/// The mean of the `ups_stats` if there are some, an exponential smoothing
+	/// of the `ups` otherwise
 	pub smooth_ups: f32,
+	/// Same as `smooth_ups`, with the `fps_stats`
 	pub smooth_fps: f32,
+
+	/// The last [`STATS_WINDOW`](Self::STATS_WINDOW) `ups`, for the percentiles.
+	/// On by default, `None` goes back to the exponential smoothing.
+	pub ups_stats: Option<RollingStats<{ Time::STATS_WINDOW }>>,
+	/// Same as `ups_stats`, for the `fps`
+	pub fps_stats: Option<RollingStats<{ Time::STATS_WINDOW }>>,
 }
 
 impl Time {
 	const SMOOTH_RESPONSIVENESS: f32 = 0.05;
 
+	/// About two seconds at 60 fps
+	pub const STATS_WINDOW: usize = 120;
+
 	pub fn smooth(&self, smoothed: &mut f32, raw: f32) {
 		*smoothed = self.smoothed(*smoothed, raw);
 	}
@@ -148,6 +179,8 @@ impl Default for Time {
 			fps: Default::default(),
 			smooth_ups: Default::default(),
 			smooth_fps: Default::default(),
+			ups_stats: Default::default(),
+			fps_stats: Default::default(),
 		}
 	}
 }
@@ -182,7 +215,82 @@ fn start_event_loop(world: &mut World) {
 		.remove_non_send_resource::<EventLoop>()
 		.expect("Tried starting the gameloop without a winit eventloop available");
 
-	let _ = event_loop.run(move |event, target| match event {
+	// Only here, the automated runs drive the loop at their own pace
+	if let Some(mut watchdog) = world.get_resource_mut::<Watchdog>() {
+		watchdog.start();
+	}
+
+	let _ = event_loop.run(move |event, target| handle_event(world, event, target));
+}
+
+/// How long [`run_frames`] waits for the next frame before giving up. Long
+/// enough for the frames of a capture.
+#[cfg(any(feature = "gpu-tests", feature = "ffi"))]
+pub const RUN_FRAMES_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Pump the event loop until `frames` frames were rendered, without handing
+/// the control over to winit. Meant for automated runs, the window can be
+/// hidden with [`DisplayPlugin::visible`](crate::core::display::DisplayPlugin::visible).
+///
+/// Can be called again to keep going, `frames` counts from the start of the
+/// app.
+///
+/// Returns an error if the event loop exited before that, e.g. because the
+/// window was closed or the app requested an exit, or if no frame was rendered
+/// for [`RUN_FRAMES_TIMEOUT`], e.g. because a hidden window gets no redraws.
+#[cfg(any(feature = "gpu-tests", feature = "ffi"))]
+pub fn run_frames(app: &mut App, frames: u64) -> anyhow::Result<()> {
+	use winit::platform::pump_events::{EventLoopExtPumpEvents, PumpStatus};
+
+	// The plugins only need to be finished the first time
+	if app.plugins_state() != PluginsState::Cleaned {
+		wait_for_plugins(app);
+	}
+
+	let world = &mut app.world;
+	let mut event_loop = world
+		.remove_non_send_resource::<EventLoop>()
+		.expect("Tried running frames without a winit eventloop available");
+
+	let mut last_frame = (world.resource::<Time>().counter_frame, Instant::now());
+
+	while world.resource::<Time>().counter_frame < frames {
+		let status = event_loop.pump_events(Some(Duration::ZERO), |event, target| handle_event(world, event, target));
+
+		let frame = world.resource::<Time>().counter_frame;
+		if frame != last_frame.0 {
+			last_frame = (frame, Instant::now());
+		} else if last_frame.1.elapsed() > RUN_FRAMES_TIMEOUT {
+			// Can still be called again, e.g. after showing the window
+			world.insert_non_send_resource(event_loop);
+			anyhow::bail!(
+				"No frame was rendered for {:?}, after {} of {} frames",
+				RUN_FRAMES_TIMEOUT,
+				frame,
+				frames
+			);
+		}
+
+		if let PumpStatus::Exit(code) = status {
+			anyhow::bail!(
+				"Event loop exited with code {} after {} of {} frames",
+				code,
+				world.resource::<Time>().counter_frame,
+				frames
+			);
+		}
+	}
+
+	// Put the event loop back so that the world can keep being driven
+	world.insert_non_send_resource(event_loop);
+	Ok(())
+}
+
+/// Handle a single winit event, that is a single iteration of the event loop.
+/// Whatever drives the event loop (the app runner or [`run_frames`]) should
+/// call this for every event.
+pub fn handle_event(world: &mut World, event: Event<()>, target: &EventLoopWindowTarget<()>) {
+	match event {
 		Event::DeviceEvent { event, .. } => match event {
 			DeviceEvent::MouseMotion { delta } => {
 				let event_out = MouseMotionEvent {
@@ -205,6 +313,11 @@ fn start_event_loop(world: &mut World) {
 			// trace!("Winit event: Event::AboutToWait");
 		}
 
+		Event::LoopExiting => {
+			trace!("Winit event: Event::LoopExiting");
+			let _ = world.try_run_schedule(Shutdown);
+		}
+
 		Event::WindowEvent { event, .. } => {
 			world.send_event(WinitWindowEvent(event.clone()));
 
@@ -242,16 +355,29 @@ fn start_event_loop(world: &mut World) {
 
 				WindowEvent::Resized(physical_size) if physical_size.width > 0 && physical_size.height > 0 => {
 					let event_out = WindowResizedEvent {
-						size: physical_size.convert(),
+						size: WindowSize(physical_size.convert()),
 					};
 					trace!("Winit event: Event::WindowEvent::Resized");
 					trace!("Event out: {event_out:#?}");
 					world.send_event(event_out);
 				}
 
+				// Nothing gets drawn while the window is hidden
+				WindowEvent::Occluded(occluded) => {
+					if let Some(watchdog) = world.get_resource::<Watchdog>() {
+						watchdog.heartbeat().set_window_hidden(occluded);
+					}
+				}
+
 				WindowEvent::RedrawRequested => {
 					// trace!("Winit event: Event::WindowEvent::RedrawRequested");
 					schedule_game_iteration(world);
+
+					if world.remove_resource::<RequestExit>().is_some() {
+						trace!("Exit requested from within the app");
+						target.exit();
+					}
+
 					world.resource::<AppWindow>().winit_window.request_redraw();
 				}
 
@@ -259,16 +385,22 @@ fn start_event_loop(world: &mut World) {
 			}
 		}
 		_ => {}
-	});
+	}
 }
 
 fn schedule_game_iteration(world: &mut World) {
 	// Inspired by https://gafferongames.com/post/fix_your_timestep/
 
+	let heartbeat = world.get_resource::<Watchdog>().map(Watchdog::heartbeat);
+	let heartbeat = heartbeat.as_deref();
+	if let Some(heartbeat) = heartbeat {
+		heartbeat.ping();
+	}
+
 	// Call the fast-looping schedules at the beginning, so they don't delay
 	// delta_iteration in case they take longer than they should
-	let _ = world.try_run_schedule(EventsCore);
-	let _ = world.try_run_schedule(IterStep);
+	run_schedule(world, heartbeat, Zone::EventsCore, EventsCore);
+	run_schedule(world, heartbeat, Zone::IterStep, IterStep);
 
 	// Due to mut borrows clashing with time when running schedules, I clone here
 	// and then re-insert time before running schedules
@@ -294,7 +426,7 @@ fn schedule_game_iteration(world: &mut World) {
 	let num_updates = time.update_accumulator.as_nanos() / time.dt_u.as_nanos();
 	for _ in 0..num_updates {
 		world.insert_resource(time);
-		let _ = world.try_run_schedule(Update);
+		run_schedule(world, heartbeat, Zone::Update, Update);
 
 		// Update current time by one step so that the update systems see it correctly
 		time.current_time += time.dt_u;
@@ -311,7 +443,13 @@ fn schedule_game_iteration(world: &mut World) {
 		// that's useless info, predict the number of updates for the next second
 		// (very jittery), and let smooth_ups do its thing
 		time.ups = 1. / (now - time.last_update_time).as_secs_f32() * num_updates as f32;
-		time.smooth_ups = time.smoothed(time.smooth_ups, time.ups);
+		time.smooth_ups = match &mut time.ups_stats {
+			Some(stats) => {
+				stats.push(time.ups);
+				stats.mean().unwrap_or_default()
+			}
+			None => time.smoothed(time.smooth_ups, time.ups),
+		};
 
 		time.last_update_time = now;
 	}
@@ -346,12 +484,18 @@ fn schedule_game_iteration(world: &mut World) {
 
 	if should_render {
 		world.insert_resource(time);
-		let _ = world.try_run_schedule(PreRender);
-		let _ = world.try_run_schedule(Render);
+		run_schedule(world, heartbeat, Zone::PreRender, PreRender);
+		run_schedule(world, heartbeat, Zone::Render, Render);
 
 		// Update FPS info; above comment about UPS also applies here
 		time.fps = 1. / (now - time.last_render_time).as_secs_f32();
-		time.smooth_fps = time.smoothed(time.smooth_fps, time.fps);
+		time.smooth_fps = match &mut time.fps_stats {
+			Some(stats) => {
+				stats.push(time.fps);
+				stats.mean().unwrap_or_default()
+			}
+			None => time.smoothed(time.smooth_fps, time.fps),
+		};
 
 		time.last_render_time = now;
 		time.counter_frame += 1;
@@ -361,3 +505,10 @@ fn schedule_game_iteration(world: &mut World) {
 	time.last_iteration_time = now;
 	world.insert_resource(time);
 }
+
+/// Run a schedule if it exists, telling the watchdog (if there's one) that
+/// the loop is in it
+fn run_schedule(world: &mut World, heartbeat: Option<&Heartbeat>, zone: Zone, label: impl ScheduleLabel) {
+	let _zone = heartbeat.map(|heartbeat| heartbeat.zone(zone));
+	let _ = world.try_run_schedule(label);
+}
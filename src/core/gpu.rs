@@ -4,6 +4,8 @@ use wgpu::{
 	PowerPreference, Queue, RequestAdapterOptions, Surface,
 };
 
+use crate::libs::texture::TrackedTextures;
+
 /*
 --------------------------------------------------------------------------------
 ||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
@@ -31,6 +33,8 @@ pub struct Gpu {
 	pub adapter: Adapter,
 	pub device: Device,
 	pub queue: Queue,
+	/// See [`Tex::tracked`](crate::libs::texture::Tex::tracked)
+	pub tracked_textures: TrackedTextures,
 }
 
 impl Gpu {
@@ -63,6 +67,9 @@ impl Gpu {
 			.await
 			.expect("Coudln't request compatible adapter");
 
+		// Only used for profiling, so only request it if it's there
+		let optional_features = adapter.features() & Features::TIMESTAMP_QUERY;
+
 		// Device esentially acts like a logical connection to the selected adapter in
 		// an application-isolated way. The device is selected based on a descriptor
 		// that describes the required features. Queue is the message queue / command
@@ -71,7 +78,7 @@ impl Gpu {
 		let (device, queue) = adapter
 			.request_device(
 				&(DeviceDescriptor {
-					required_features: Features::empty()
+					required_features: optional_features
 						// | Features::TEXTURE_BINDING_ARRAY
 						// | Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING
 						| Features::CONSERVATIVE_RASTERIZATION
@@ -91,6 +98,7 @@ impl Gpu {
 			adapter,
 			device,
 			queue,
+			tracked_textures: TrackedTextures::default(),
 		}
 	}
 }
@@ -0,0 +1,260 @@
+use std::collections::HashSet;
+
+use brainrot::bevy::{self, App, Plugin};
+use hashlink::LinkedHashMap;
+use winit::{
+	event::ElementState,
+	keyboard::{KeyCode, PhysicalKey},
+};
+
+use super::events::KeyboardInputEvent;
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Inserts the default [`KeyBindings`].
+///
+/// The bindings are only read while the app runs, so they can be replaced
+/// anytime after the app is built (see `run_with`).
+pub struct KeyBindingsPlugin;
+
+impl Plugin for KeyBindingsPlugin {
+	fn build(&self, app: &mut App) {
+		app.world.insert_resource(KeyBindings::default());
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+	MoveForward,
+	MoveBackward,
+	MoveLeft,
+	MoveRight,
+	MoveUp,
+	MoveDown,
+	Sprint,
+	ToggleProjection,
+	ToggleOrbit,
+	ToggleCursor,
+	/// See [`InputSettings`](super::camera::InputSettings)
+	SensitivityUp,
+	SensitivityDown,
+	ToggleInvertY,
+	/// See [`PresentationMode`](super::presentation::PresentationMode)
+	TogglePresentation,
+	/// Render from the camera with this index, see
+	/// [`ActiveCamera`](super::camera::ActiveCamera)
+	SelectCamera(u8),
+	/// Recall the camera pose in this slot, or save it while
+	/// [`SavePoseModifier`](Action::SavePoseModifier) is held, see
+	/// [`CameraPoses`](super::camera_poses::CameraPoses)
+	PoseSlot(u8),
+	SavePoseModifier,
+	/// See [`HighQualityCapture`](super::rendering::capture::HighQualityCapture)
+	HighQualityCapture,
+	CancelCapture,
+	/// See [`Upscaler`](super::rendering::composite::Upscaler)
+	CycleUpscaler,
+	/// See [`DebugView`](super::rendering::composite::DebugView)
+	CycleDebugView,
+	/// See [`PictureInPicture`](super::rendering::picture_in_picture::PictureInPicture)
+	TogglePictureInPicture,
+	/// See [`HistogramPlugin`](super::rendering::histogram::HistogramPlugin)
+	ToggleHistogram,
+	/// See [`ParamsPlugin`](super::params::ParamsPlugin)
+	EditParams,
+	/// Focus the depth of field on what's under the crosshair, see
+	/// [`DepthOfFieldPlugin`](super::rendering::depth_of_field::DepthOfFieldPlugin)
+	Focus,
+	/// See [`RenderScale`](super::rendering::render_scale::RenderScale)
+	RenderScaleUp,
+	RenderScaleDown,
+	/// See [`ToneMapping`](crate::fragments::post_processing::ToneMapping)
+	ExposureUp,
+	ExposureDown,
+	/// See [`Fxaa`](crate::fragments::post_processing::Fxaa)
+	ToggleFxaa,
+	/// Turn the post processing effect with this index on or off, see
+	/// [`PostProcessingEffects`](crate::fragments::post_processing::PostProcessingEffects)
+	TogglePostEffect(u8),
+	/// See [`SkyPlugin`](crate::core::rendering::sky::SkyPlugin)
+	SunUp,
+	SunDown,
+	SunLeft,
+	SunRight,
+}
+
+impl Action {
+	/// How many cameras get a [`SelectCamera`](Action::SelectCamera) binding by
+	/// default, one per function key up to F4 (F5 to F7 cycle the log levels,
+	/// see [`LoggingPlugin`](super::logging::LoggingPlugin))
+	pub const CAMERA_SLOTS: u8 = 4;
+
+	/// How many [`PoseSlot`](Action::PoseSlot)s there are, one per number key
+	pub const POSE_SLOTS: u8 = 9;
+
+	/// How many post processing effects get a
+	/// [`TogglePostEffect`](Action::TogglePostEffect) binding by default, one
+	/// per key of the numpad
+	pub const POST_EFFECT_SLOTS: u8 = 9;
+}
+
+/// Which keys trigger which [`Action`]. An action can have any number of keys,
+/// and all of them work.
+///
+/// The keys are physical, i.e. they are the position of the key on a US
+/// keyboard and not its label. So the default WASD is already ZQSD on an
+/// AZERTY keyboard, and the arrow keys are there as a layout-independent
+/// alternative.
+#[derive(bevy::Resource, Clone, Debug, PartialEq)]
+pub struct KeyBindings {
+	bindings: LinkedHashMap<Action, Vec<KeyCode>>,
+}
+
+impl Default for KeyBindings {
+	fn default() -> Self {
+		Self::empty()
+			.with(Action::MoveForward, [KeyCode::KeyW, KeyCode::ArrowUp])
+			.with(Action::MoveBackward, [KeyCode::KeyS, KeyCode::ArrowDown])
+			.with(Action::MoveLeft, [KeyCode::KeyA, KeyCode::ArrowLeft])
+			.with(Action::MoveRight, [KeyCode::KeyD, KeyCode::ArrowRight])
+			.with(Action::MoveUp, [KeyCode::Space])
+			.with(Action::MoveDown, [KeyCode::ControlLeft, KeyCode::ControlRight])
+			.with(Action::Sprint, [KeyCode::ShiftLeft, KeyCode::ShiftRight])
+			.with(Action::ToggleProjection, [KeyCode::KeyP])
+			.with(Action::ToggleOrbit, [KeyCode::Tab])
+			.with(Action::ToggleCursor, [KeyCode::Escape])
+			.with(Action::SensitivityUp, [KeyCode::BracketRight])
+			.with(Action::SensitivityDown, [KeyCode::BracketLeft])
+			.with(Action::ToggleInvertY, [KeyCode::KeyI])
+			.with(Action::TogglePresentation, [KeyCode::KeyT])
+			.with(Action::SelectCamera(0), [KeyCode::F1])
+			.with(Action::SelectCamera(1), [KeyCode::F2])
+			.with(Action::SelectCamera(2), [KeyCode::F3])
+			.with(Action::SelectCamera(3), [KeyCode::F4])
+			.with(Action::PoseSlot(0), [KeyCode::Digit1])
+			.with(Action::PoseSlot(1), [KeyCode::Digit2])
+			.with(Action::PoseSlot(2), [KeyCode::Digit3])
+			.with(Action::PoseSlot(3), [KeyCode::Digit4])
+			.with(Action::PoseSlot(4), [KeyCode::Digit5])
+			.with(Action::PoseSlot(5), [KeyCode::Digit6])
+			.with(Action::PoseSlot(6), [KeyCode::Digit7])
+			.with(Action::PoseSlot(7), [KeyCode::Digit8])
+			.with(Action::PoseSlot(8), [KeyCode::Digit9])
+			.with(Action::SavePoseModifier, [KeyCode::AltLeft, KeyCode::AltRight])
+			.with(Action::HighQualityCapture, [KeyCode::F12])
+			// Not Escape, which already toggles the cursor
+			.with(Action::CancelCapture, [KeyCode::Backspace])
+			.with(Action::CycleUpscaler, [KeyCode::KeyU])
+			.with(Action::CycleDebugView, [KeyCode::KeyG])
+			.with(Action::TogglePictureInPicture, [KeyCode::KeyV])
+			.with(Action::ToggleHistogram, [KeyCode::KeyH])
+			.with(Action::EditParams, [KeyCode::F10])
+			.with(Action::Focus, [KeyCode::KeyF])
+			.with(Action::RenderScaleUp, [KeyCode::Period])
+			.with(Action::RenderScaleDown, [KeyCode::Comma])
+			.with(Action::ExposureUp, [KeyCode::Equal, KeyCode::NumpadAdd])
+			.with(Action::ExposureDown, [KeyCode::Minus, KeyCode::NumpadSubtract])
+			.with(Action::ToggleFxaa, [KeyCode::KeyX])
+			.with(Action::TogglePostEffect(0), [KeyCode::Numpad1])
+			.with(Action::TogglePostEffect(1), [KeyCode::Numpad2])
+			.with(Action::TogglePostEffect(2), [KeyCode::Numpad3])
+			.with(Action::TogglePostEffect(3), [KeyCode::Numpad4])
+			.with(Action::TogglePostEffect(4), [KeyCode::Numpad5])
+			.with(Action::TogglePostEffect(5), [KeyCode::Numpad6])
+			.with(Action::TogglePostEffect(6), [KeyCode::Numpad7])
+			.with(Action::TogglePostEffect(7), [KeyCode::Numpad8])
+			.with(Action::TogglePostEffect(8), [KeyCode::Numpad9])
+			.with(Action::SunUp, [KeyCode::PageUp])
+			.with(Action::SunDown, [KeyCode::PageDown])
+			.with(Action::SunLeft, [KeyCode::Home])
+			.with(Action::SunRight, [KeyCode::End])
+	}
+}
+
+impl KeyBindings {
+	pub fn empty() -> Self {
+		Self {
+			bindings: LinkedHashMap::new(),
+		}
+	}
+
+	/// Add keys to an action, on top of the ones it already has
+	pub fn with(mut self, action: Action, keys: impl IntoIterator<Item = KeyCode>) -> Self {
+		self.bind(action, keys);
+		self
+	}
+
+	pub fn bind(&mut self, action: Action, keys: impl IntoIterator<Item = KeyCode>) {
+		let bound = self.bindings.entry(action).or_default();
+
+		for key in keys {
+			if !bound.contains(&key) {
+				bound.push(key);
+			}
+		}
+	}
+
+	/// Replace all the keys of an action
+	pub fn rebind(&mut self, action: Action, keys: impl IntoIterator<Item = KeyCode>) {
+		self.bindings.remove(&action);
+		self.bind(action, keys);
+	}
+
+	pub fn keys(&self, action: Action) -> &[KeyCode] {
+		self.bindings.get(&action).map(Vec::as_slice).unwrap_or_default()
+	}
+
+	pub fn is_bound(&self, action: Action, key: PhysicalKey) -> bool {
+		match key {
+			PhysicalKey::Code(key) => self.keys(action).contains(&key),
+			PhysicalKey::Unidentified(_) => false,
+		}
+	}
+
+	/// Whether any of the action's keys was pressed in the events
+	pub fn has_pressed<'a>(&self, action: Action, events: impl IntoIterator<Item = &'a KeyboardInputEvent>) -> bool {
+		events
+			.into_iter()
+			.any(|event| event.state == ElementState::Pressed && self.is_bound(action, event.physical_key))
+	}
+
+	/// Whether any of the action's keys is held down
+	pub fn is_held(&self, action: Action, held_keys: &HeldKeys) -> bool {
+		self.keys(action).iter().any(|key| held_keys.0.contains(key))
+	}
+}
+
+/// The keys that are currently held down, built from the keyboard events.
+///
+/// Needed for actions with several keys, since releasing one of them shouldn't
+/// stop the action while another one is still held.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HeldKeys(HashSet<KeyCode>);
+
+impl HeldKeys {
+	pub fn update<'a>(&mut self, events: impl IntoIterator<Item = &'a KeyboardInputEvent>) {
+		for KeyboardInputEvent {
+			state, physical_key, ..
+		} in events
+		{
+			// winit can fire several Pressed (or Released) events in a row, which a set
+			// doesn't care about
+			if let PhysicalKey::Code(key) = physical_key {
+				match state {
+					ElementState::Pressed => self.0.insert(*key),
+					ElementState::Released => self.0.remove(key),
+				};
+			}
+		}
+	}
+}
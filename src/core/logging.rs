@@ -0,0 +1,229 @@
+use std::sync::{Arc, OnceLock, RwLock};
+
+use bevy_ecs::{event::EventReader, system::Res};
+use brainrot::bevy::{self, App, Plugin};
+use log::{info, LevelFilter, Log, Metadata, Record};
+use winit::keyboard::KeyCode;
+
+use crate::core::{
+	event_processing::{EventReaderProcessor, ProcessedInputEvents},
+	events::KeyboardInputEvent,
+	gameloop::Update,
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// The levels that the hotkeys cycle through, in order
+const LEVEL_CYCLE: [LevelFilter; 6] = [
+	LevelFilter::Off,
+	LevelFilter::Error,
+	LevelFilter::Warn,
+	LevelFilter::Info,
+	LevelFilter::Debug,
+	LevelFilter::Trace,
+];
+
+static LOGGER: OnceLock<RuntimeLogger> = OnceLock::new();
+
+/// Install the runtime logger as the global [`log`] facade.
+///
+/// `default` is the level used for any target that doesn't match one of the
+/// per-target `levels`. Targets are matched by module path prefix, so
+/// `pbr_tracer::libs` also applies to `pbr_tracer::libs::shader` unless the
+/// latter has its own level.
+pub fn init(default: LevelFilter, target_levels: &[(&str, LevelFilter)]) {
+	let levels = Arc::new(LogLevels {
+		default,
+		targets: RwLock::new(Vec::new()),
+	});
+
+	for (target, level) in target_levels {
+		levels.set(*target, *level);
+	}
+
+	// The inner logger accepts everything, the filtering is done by the
+	// runtime logger before forwarding
+	let inner = env_logger::Builder::new().filter_level(LevelFilter::Trace).build();
+
+	let logger = LOGGER.get_or_init(|| RuntimeLogger { inner, levels });
+
+	log::set_logger(logger).expect("Couldn't install the runtime logger, a logger was already set");
+	log::set_max_level(logger.levels.max_level());
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+pub struct LoggingPlugin {
+	/// Keys that cycle the log level of the given target when pressed
+	pub hotkeys: Vec<(KeyCode, &'static str)>,
+}
+
+impl Default for LoggingPlugin {
+	fn default() -> Self {
+		Self {
+			hotkeys: vec![
+				(KeyCode::F5, "pbr_tracer::libs::shader"),
+				(KeyCode::F6, "pbr_tracer::core::gameloop"),
+				(KeyCode::F7, "pbr_tracer::core::rendering"),
+			],
+		}
+	}
+}
+
+impl Plugin for LoggingPlugin {
+	fn build(&self, app: &mut App) {
+		let log_control = LogControl {
+			levels: LOGGER.get().map(|logger| logger.levels.clone()),
+			hotkeys: self.hotkeys.clone(),
+		};
+
+		app.world.insert_resource(log_control);
+
+		app.add_systems(Update, cycle_levels);
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Handle to change the log levels of the running app.
+///
+/// If [`init`] wasn't called before the app was built, there is no runtime
+/// logger to control and all the changes are silently ignored.
+#[derive(bevy::Resource)]
+pub struct LogControl {
+	levels: Option<Arc<LogLevels>>,
+	hotkeys: Vec<(KeyCode, &'static str)>,
+}
+
+impl LogControl {
+	/// The level that is currently in effect for the given target
+	pub fn level(&self, target: &str) -> LevelFilter {
+		self.levels
+			.as_ref()
+			.map(|levels| levels.level(target))
+			.unwrap_or(LevelFilter::Off)
+	}
+
+	pub fn set_level(&self, target: impl Into<String>, level: LevelFilter) {
+		if let Some(levels) = &self.levels {
+			levels.set(target, level);
+			log::set_max_level(levels.max_level());
+		}
+	}
+
+	/// Remove the level override of the given target, making it fall back to
+	/// the level of its closest parent
+	pub fn reset_level(&self, target: &str) {
+		if let Some(levels) = &self.levels {
+			levels.remove(target);
+			log::set_max_level(levels.max_level());
+		}
+	}
+
+	/// Set the target to the next level in [`LEVEL_CYCLE`], wrapping around
+	pub fn cycle_level(&self, target: &str) -> LevelFilter {
+		let current = self.level(target);
+		let index = LEVEL_CYCLE.iter().position(|l| *l == current).unwrap_or(0);
+		let next = LEVEL_CYCLE[(index + 1) % LEVEL_CYCLE.len()];
+
+		self.set_level(target, next);
+		next
+	}
+}
+
+fn cycle_levels(log_control: Res<LogControl>, keyboard_events: EventReader<KeyboardInputEvent>) {
+	let keyboard_events = keyboard_events.process();
+
+	for (key, target) in &log_control.hotkeys {
+		if keyboard_events.has_pressed(*key) {
+			let level = log_control.cycle_level(target);
+			info!("Log level of `{}` set to {}", target, level);
+		}
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+struct LogLevels {
+	default: LevelFilter,
+	// Sorted from longest to shortest target, so that the first match is always
+	// the most specific one
+	targets: RwLock<Vec<(String, LevelFilter)>>,
+}
+
+impl LogLevels {
+	fn level(&self, target: &str) -> LevelFilter {
+		self.targets
+			.read()
+			.unwrap()
+			.iter()
+			.find(|(prefix, _)| {
+				target == prefix || (target.starts_with(prefix.as_str()) && target[prefix.len()..].starts_with("::"))
+			})
+			.map(|(_, level)| *level)
+			.unwrap_or(self.default)
+	}
+
+	fn set(&self, target: impl Into<String>, level: LevelFilter) {
+		let target = target.into();
+		let mut targets = self.targets.write().unwrap();
+
+		match targets.iter_mut().find(|(prefix, _)| *prefix == target) {
+			Some((_, old_level)) => *old_level = level,
+			None => {
+				targets.push((target, level));
+				targets.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()));
+			}
+		}
+	}
+
+	fn remove(&self, target: &str) {
+		self.targets.write().unwrap().retain(|(prefix, _)| prefix != target);
+	}
+
+	fn max_level(&self) -> LevelFilter {
+		self.targets
+			.read()
+			.unwrap()
+			.iter()
+			.map(|(_, level)| *level)
+			.fold(self.default, LevelFilter::max)
+	}
+}
+
+struct RuntimeLogger {
+	inner: env_logger::Logger,
+	levels: Arc<LogLevels>,
+}
+
+impl Log for RuntimeLogger {
+	fn enabled(&self, metadata: &Metadata) -> bool {
+		metadata.level() <= self.levels.level(metadata.target())
+	}
+
+	fn log(&self, record: &Record) {
+		if self.enabled(record.metadata()) {
+			self.inner.log(record);
+		}
+	}
+
+	fn flush(&self) {
+		self.inner.flush();
+	}
+}
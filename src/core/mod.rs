@@ -1,8 +1,22 @@
 pub mod camera;
+pub mod camera_poses;
+pub mod clip_planes;
+pub mod console;
 pub mod display;
+pub mod entity_label;
 pub mod event_processing;
 pub mod events;
 pub mod gameloop;
 pub mod gpu;
+pub mod key_bindings;
+pub mod logging;
+pub mod params;
+pub mod persistence;
+pub mod picking;
+pub mod presentation;
 pub mod render_target;
 pub mod rendering;
+pub mod shader_check;
+pub mod size;
+pub mod watchdog;
+pub mod window_placement;
@@ -0,0 +1,431 @@
+use std::{fmt, ops::RangeInclusive, sync::Arc};
+
+use anyhow::{anyhow, bail, Context, Result};
+use bevy_ecs::{
+	event::EventReader,
+	system::{Res, ResMut},
+	world::{Mut, World},
+};
+use brainrot::bevy::{self, App, Plugin};
+use hashlink::LinkedHashMap;
+use log::warn;
+use winit::keyboard::{Key, NamedKey};
+
+use super::{
+	console::{self, Console},
+	display::{AppWindow, WindowSettings},
+	events::KeyboardInputEvent,
+	gameloop::{IterStep, Update},
+	key_bindings::{Action, KeyBindings},
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Adds the `get` console command and the edit mode of the [`ParamRegistry`].
+/// The parameters themselves are registered by the plugins they belong to, with
+/// [`registry`], whether this plugin was already added or not.
+///
+/// The `set` console command falls back to the parameters for anything that
+/// isn't one of its own settings.
+///
+/// [`Action::EditParams`] toggles the edit mode: the arrow keys go through the
+/// parameters, typing and Enter sets the selected one. There is no text
+/// rendering yet, so it's shown in the window title like the console.
+pub struct ParamsPlugin;
+
+impl Plugin for ParamsPlugin {
+	fn build(&self, app: &mut App) {
+		registry(app);
+		app.init_resource::<ParamEditor>();
+
+		console::register_command(
+			app,
+			"get",
+			"get [param]: Show a parameter and its range, or list all of them",
+			get,
+		);
+
+		app.add_systems(Update, process_editor_input);
+		app.add_systems(IterStep, apply_editor);
+	}
+}
+
+/// The registry to register parameters in, see [`ParamRegistry::register_float`]
+pub fn registry(app: &mut App) -> Mut<'_, ParamRegistry> {
+	app.world.get_resource_or_insert_with(ParamRegistry::default)
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ParamValue {
+	Float(f32),
+	Int(i64),
+	Bool(bool),
+}
+
+impl fmt::Display for ParamValue {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ParamValue::Float(value) => write!(f, "{}", value),
+			ParamValue::Int(value) => write!(f, "{}", value),
+			ParamValue::Bool(value) => write!(f, "{}", value),
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParamKind {
+	Float(RangeInclusive<f32>),
+	Int(RangeInclusive<i64>),
+	Bool,
+}
+
+impl ParamKind {
+	/// A value out of the range is an error rather than clamped, so that a typo
+	/// doesn't go through as some other value
+	pub fn parse(&self, text: &str) -> Result<ParamValue> {
+		match self {
+			ParamKind::Float(range) => {
+				let value = text.parse::<f32>().context("Expected a number")?;
+				if !range.contains(&value) {
+					bail!("{} is out of the range {}", value, self);
+				}
+				Ok(ParamValue::Float(value))
+			}
+			ParamKind::Int(range) => {
+				let value = text.parse::<i64>().context("Expected a whole number")?;
+				if !range.contains(&value) {
+					bail!("{} is out of the range {}", value, self);
+				}
+				Ok(ParamValue::Int(value))
+			}
+			ParamKind::Bool => Ok(ParamValue::Bool(
+				text.parse::<bool>().context("Expected `true` or `false`")?,
+			)),
+		}
+	}
+}
+
+impl fmt::Display for ParamKind {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ParamKind::Float(range) => write!(f, "[{}; {}]", range.start(), range.end()),
+			ParamKind::Int(range) => write!(f, "[{}; {}]", range.start(), range.end()),
+			ParamKind::Bool => write!(f, "true | false"),
+		}
+	}
+}
+
+pub type ParamGetter = Arc<dyn Fn(&mut World) -> Result<ParamValue> + Send + Sync>;
+pub type ParamSetter = Arc<dyn Fn(&mut World, ParamValue) -> Result<()> + Send + Sync>;
+
+pub struct Param {
+	pub help: String,
+	pub kind: ParamKind,
+	get: ParamGetter,
+	set: ParamSetter,
+}
+
+/// Named values of the app that can be read and written from the console,
+/// e.g. `set fov 60`. Each one reads and writes whatever resource or component
+/// it stands for through its closures, so nothing is copied around.
+#[derive(bevy::Resource, Default)]
+pub struct ParamRegistry {
+	params: LinkedHashMap<String, Param>,
+}
+
+impl ParamRegistry {
+	/// Register a parameter, replacing any previous one with the same name
+	pub fn register_float<G, S>(
+		&mut self,
+		name: impl Into<String>,
+		help: impl Into<String>,
+		range: RangeInclusive<f32>,
+		get: G,
+		set: S,
+	) -> &mut Self
+	where
+		G: Fn(&mut World) -> Result<f32> + Send + Sync + 'static,
+		S: Fn(&mut World, f32) -> Result<()> + Send + Sync + 'static,
+	{
+		self.register(
+			name,
+			help,
+			ParamKind::Float(range),
+			move |world| get(world).map(ParamValue::Float),
+			move |world, value| match value {
+				ParamValue::Float(value) => set(world, value),
+				_ => bail!("Expected a number"),
+			},
+		)
+	}
+
+	/// See [`register_float`](Self::register_float)
+	pub fn register_int<G, S>(
+		&mut self,
+		name: impl Into<String>,
+		help: impl Into<String>,
+		range: RangeInclusive<i64>,
+		get: G,
+		set: S,
+	) -> &mut Self
+	where
+		G: Fn(&mut World) -> Result<i64> + Send + Sync + 'static,
+		S: Fn(&mut World, i64) -> Result<()> + Send + Sync + 'static,
+	{
+		self.register(
+			name,
+			help,
+			ParamKind::Int(range),
+			move |world| get(world).map(ParamValue::Int),
+			move |world, value| match value {
+				ParamValue::Int(value) => set(world, value),
+				_ => bail!("Expected a whole number"),
+			},
+		)
+	}
+
+	/// See [`register_float`](Self::register_float)
+	pub fn register_bool<G, S>(&mut self, name: impl Into<String>, help: impl Into<String>, get: G, set: S) -> &mut Self
+	where
+		G: Fn(&mut World) -> Result<bool> + Send + Sync + 'static,
+		S: Fn(&mut World, bool) -> Result<()> + Send + Sync + 'static,
+	{
+		self.register(
+			name,
+			help,
+			ParamKind::Bool,
+			move |world| get(world).map(ParamValue::Bool),
+			move |world, value| match value {
+				ParamValue::Bool(value) => set(world, value),
+				_ => bail!("Expected `true` or `false`"),
+			},
+		)
+	}
+
+	fn register<G, S>(
+		&mut self,
+		name: impl Into<String>,
+		help: impl Into<String>,
+		kind: ParamKind,
+		get: G,
+		set: S,
+	) -> &mut Self
+	where
+		G: Fn(&mut World) -> Result<ParamValue> + Send + Sync + 'static,
+		S: Fn(&mut World, ParamValue) -> Result<()> + Send + Sync + 'static,
+	{
+		self.params.insert(
+			name.into(),
+			Param {
+				help: help.into(),
+				kind,
+				get: Arc::new(get),
+				set: Arc::new(set),
+			},
+		);
+		self
+	}
+
+	pub fn get(&self, name: &str) -> Option<&Param> {
+		self.params.get(name)
+	}
+
+	pub fn names(&self) -> impl Iterator<Item = &str> {
+		self.params.keys().map(String::as_str)
+	}
+
+	pub fn len(&self) -> usize {
+		self.params.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.params.is_empty()
+	}
+}
+
+/// The current value of a registered parameter
+pub fn get_param(world: &mut World, name: &str) -> Result<ParamValue> {
+	let get = world
+		.get_resource::<ParamRegistry>()
+		.and_then(|registry| registry.get(name))
+		.map(|param| param.get.clone())
+		.ok_or(anyhow!("Unknown parameter `{}`, type `get` for a list", name))?;
+
+	get(world)
+}
+
+/// Parse the value and set the parameter to it. Nothing changes if the value
+/// doesn't parse or is out of range.
+pub fn set_param(world: &mut World, name: &str, text: &str) -> Result<ParamValue> {
+	let (kind, set) = world
+		.get_resource::<ParamRegistry>()
+		.and_then(|registry| registry.get(name))
+		.map(|param| (param.kind.clone(), param.set.clone()))
+		.ok_or(anyhow!("Unknown parameter `{}`, type `get` for a list", name))?;
+
+	let value = kind.parse(text)?;
+	set(world, value)?;
+	Ok(value)
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// The state of the edit mode, see [`ParamsPlugin`]
+#[derive(bevy::Resource, Default, Debug)]
+pub struct ParamEditor {
+	pub active: bool,
+	/// The index of the selected parameter in the registry
+	selected: usize,
+	input: String,
+	/// Set by Enter, applied at the next `IterStep`
+	submitted: bool,
+	/// The last error, shown until the next key
+	error: Option<String>,
+	needs_redraw: bool,
+}
+
+fn process_editor_input(
+	mut editor: ResMut<ParamEditor>,
+	registry: Res<ParamRegistry>,
+	console: Option<Res<Console>>,
+	key_bindings: Res<KeyBindings>,
+	mut keyboard_events: EventReader<KeyboardInputEvent>,
+) {
+	// The console has the keyboard while it's open
+	if console.is_some_and(|console| console.open) {
+		keyboard_events.clear();
+		return;
+	}
+
+	for event in keyboard_events.read() {
+		if !event.state.is_pressed() {
+			continue;
+		}
+
+		if key_bindings.has_pressed(Action::EditParams, [event]) {
+			editor.active = !editor.active;
+			editor.input.clear();
+			editor.error = None;
+			editor.needs_redraw = true;
+			continue;
+		}
+
+		if !editor.active || registry.is_empty() {
+			continue;
+		}
+
+		editor.error = None;
+		editor.needs_redraw = true;
+
+		match &event.logical_key {
+			Key::Named(NamedKey::ArrowUp) => {
+				editor.selected = (editor.selected + registry.len() - 1) % registry.len();
+				editor.input.clear();
+			}
+			Key::Named(NamedKey::ArrowDown) => {
+				editor.selected = (editor.selected + 1) % registry.len();
+				editor.input.clear();
+			}
+			Key::Named(NamedKey::Enter) => editor.submitted = !editor.input.is_empty(),
+			Key::Named(NamedKey::Backspace) => {
+				editor.input.pop();
+			}
+			Key::Named(NamedKey::Escape) => {
+				editor.active = false;
+				editor.input.clear();
+			}
+			Key::Character(text) => editor.input.push_str(text),
+			_ => {}
+		}
+	}
+}
+
+/// Applies the submitted value and shows the editor in the window title. Runs
+/// as an exclusive system since the parameters can touch anything in the world.
+fn apply_editor(world: &mut World) {
+	world.resource_scope(|world, mut editor: Mut<ParamEditor>| {
+		let Some(name) = world
+			.get_resource::<ParamRegistry>()
+			.and_then(|registry| registry.names().nth(editor.selected))
+			.map(str::to_owned)
+		else {
+			return;
+		};
+
+		if editor.submitted {
+			editor.submitted = false;
+
+			let input = std::mem::take(&mut editor.input);
+			if let Err(error) = set_param(world, &name, &input) {
+				warn!("Couldn't set `{}`: {:#}", name, error);
+				editor.error = Some(format!("{:#}", error));
+				editor.input = input;
+			}
+			editor.needs_redraw = true;
+		}
+
+		if !editor.needs_redraw {
+			return;
+		}
+		editor.needs_redraw = false;
+
+		let title = world.resource::<WindowSettings>().title.to_owned();
+		let title = if editor.active {
+			let value =
+				get_param(world, &name).map_or_else(|error| format!("<{:#}>", error), |value| value.to_string());
+			let kind = world.resource::<ParamRegistry>().get(&name).unwrap().kind.to_string();
+
+			match &editor.error {
+				Some(error) => format!(
+					"{} | {} = {} {} > {}_ | {}",
+					title, name, value, kind, editor.input, error
+				),
+				None => format!("{} | {} = {} {} > {}_", title, name, value, kind, editor.input),
+			}
+		} else {
+			title
+		};
+
+		if let Some(app_window) = world.get_resource::<AppWindow>() {
+			app_window.winit_window.set_title(&title);
+		}
+	});
+}
+
+fn get(world: &mut World, args: &[String]) -> Result<String> {
+	let names = world
+		.get_resource::<ParamRegistry>()
+		.map(|registry| registry.names().map(str::to_owned).collect::<Vec<_>>())
+		.unwrap_or_default();
+
+	let describe = |world: &mut World, name: &str| -> Result<String> {
+		let value = get_param(world, name)?;
+		let registry = world.resource::<ParamRegistry>();
+		let param = registry.get(name).unwrap();
+		Ok(format!("{} = {} {}: {}", name, value, param.kind, param.help))
+	};
+
+	match args {
+		[] => Ok(names
+			.iter()
+			.map(|name| describe(world, name).unwrap_or_else(|error| format!("{}: {:#}", name, error)))
+			.collect::<Vec<_>>()
+			.join("\n")),
+		[name] => describe(world, name),
+		_ => bail!("Usage: get [param]"),
+	}
+}
@@ -0,0 +1,132 @@
+use std::{
+	ffi::OsString,
+	fs,
+	io::{self, Write},
+	path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use log::warn;
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+// The files the app writes for itself (camera poses, window placement) are
+// replaced in one go, so that a crash can't leave half a file behind, and the
+// last file that parsed is kept as a backup in case one breaks anyway.
+
+/// Write `bytes` to `path` without ever leaving a partial file there: they go
+/// to `path.tmp` first, which then replaces the file
+pub fn safe_write(path: &Path, bytes: &[u8]) -> Result<()> {
+	safe_write_with(path, |file| file.write_all(bytes))
+}
+
+/// Same as [`safe_write`], with `write` filling the temporary file. If it
+/// fails, the temporary file is removed and `path` is left as it was.
+pub fn safe_write_with(path: &Path, write: impl FnOnce(&mut fs::File) -> io::Result<()>) -> Result<()> {
+	let tmp_path = tmp_path(path);
+
+	let result = (|| {
+		let mut file =
+			fs::File::create(&tmp_path).with_context(|| format!("Couldn't create `{}`", tmp_path.display()))?;
+		write(&mut file).with_context(|| format!("Couldn't write `{}`", tmp_path.display()))?;
+		file.sync_all()
+			.with_context(|| format!("Couldn't flush `{}`", tmp_path.display()))?;
+		drop(file);
+
+		replace(&tmp_path, path).with_context(|| format!("Couldn't replace `{}`", path.display()))
+	})();
+
+	if result.is_err() {
+		let _ = fs::remove_file(&tmp_path);
+	}
+
+	result
+}
+
+/// Read and parse `path`, a missing file being `None`.
+///
+/// Every time the file parses it is copied to `path.bak`. If it doesn't, the
+/// backup is parsed instead with a warning, and the error is only returned if
+/// that fails too.
+pub fn load_with_backup<T>(path: &Path, parse: impl Fn(&str) -> Result<T>) -> Result<Option<T>> {
+	if !path.exists() {
+		return Ok(None);
+	}
+
+	let read = |path: &Path| -> Result<T> {
+		let text = fs::read_to_string(path).with_context(|| format!("Couldn't read `{}`", path.display()))?;
+		parse(&text).with_context(|| format!("Couldn't parse `{}`", path.display()))
+	};
+
+	let backup_path = backup_path(path);
+
+	match read(path) {
+		Ok(value) => {
+			if let Err(error) = fs::copy(path, &backup_path) {
+				warn!("Couldn't back up `{}`: {}", path.display(), error);
+			}
+			Ok(Some(value))
+		}
+		Err(error) if backup_path.exists() => match read(&backup_path) {
+			Ok(value) => {
+				warn!("{:#}, using the backup `{}` instead", error, backup_path.display());
+				Ok(Some(value))
+			}
+			Err(_) => Err(error),
+		},
+		Err(error) => Err(error),
+	}
+}
+
+/// `path.tmp`, where [`safe_write`] writes first
+pub fn tmp_path(path: &Path) -> PathBuf {
+	with_suffix(path, ".tmp")
+}
+
+/// `path.bak`, the last version of the file that parsed
+pub fn backup_path(path: &Path) -> PathBuf {
+	with_suffix(path, ".bak")
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+	let mut name = OsString::from(path.as_os_str());
+	name.push(suffix);
+	PathBuf::from(name)
+}
+
+#[cfg(not(windows))]
+fn replace(from: &Path, to: &Path) -> io::Result<()> {
+	fs::rename(from, to)?;
+
+	// The rename itself only lasts once the directory is flushed too
+	if let Some(dir) = to.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+		if let Ok(dir) = fs::File::open(dir) {
+			let _ = dir.sync_all();
+		}
+	}
+
+	Ok(())
+}
+
+/// The rename replaces the file on Windows too (`MOVEFILE_REPLACE_EXISTING`),
+/// but fails while something else has the file open, which antivirus and
+/// indexing do for a moment after every write. So it gets a few tries.
+#[cfg(windows)]
+fn replace(from: &Path, to: &Path) -> io::Result<()> {
+	const TRIES: u32 = 5;
+
+	let mut tries = 1;
+	loop {
+		match fs::rename(from, to) {
+			Err(error) if error.kind() == io::ErrorKind::PermissionDenied && tries < TRIES => {
+				std::thread::sleep(std::time::Duration::from_millis(20 * tries as u64));
+				tries += 1;
+			}
+			result => return result,
+		}
+	}
+}
@@ -0,0 +1,211 @@
+use bevy_ecs::{
+	event::{Event, EventReader, EventWriter},
+	query::With,
+	schedule::IntoSystemConfigs,
+	system::{Query, Res, ResMut, SystemParam},
+};
+use brainrot::{
+	bevy::{self, App, Plugin},
+	vek::{Vec2, Vec3},
+};
+use winit::event::{ElementState, MouseButton, WindowEvent};
+
+use super::{
+	camera::ActiveCamera,
+	display::AppWindow,
+	event_processing::add_event,
+	events::{MouseInputEvent, WinitWindowEvent},
+	gameloop::Update,
+	gpu::Gpu,
+	rendering::{
+		camera_view::CameraView,
+		composite::{window_to_texture, ViewportInfo},
+		compute::ComputeRenderer,
+		picture_in_picture::PictureInPicture,
+	},
+	size::Resolution,
+};
+use crate::libs::texture::TexelReadback;
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Keeps track of the cursor, and sends a [`PixelPickedEvent`] when the
+/// rendered image is left-clicked while the cursor is free. If the renderer
+/// has an `output_id`, what was under the cursor is read back from it and sent
+/// as an [`ObjectPickedEvent`] once the GPU is done with the copy.
+///
+/// Needs to be added after the composite renderer plugin.
+pub struct PickingPlugin;
+
+impl Plugin for PickingPlugin {
+	fn build(&self, app: &mut App) {
+		app.world.insert_resource(CursorPosition::default());
+		app.world.init_resource::<PendingPick>();
+		add_event::<PixelPickedEvent>(app);
+		add_event::<ObjectPickedEvent>(app);
+
+		app.add_systems(Update, (track_cursor, pick_pixel, read_picked_object).chain());
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// The latest position of the cursor in the window, in physical pixels from
+/// the top left corner. `None` while the cursor is outside of the window.
+#[derive(bevy::Resource, Copy, Clone, Debug, Default, PartialEq)]
+pub struct CursorPosition(pub Option<Vec2<f32>>);
+
+/// A pixel of the rendered image was clicked. The ray is the one the compute
+/// shader traced for it (see [`CameraView::pixel_ray`]).
+#[derive(Event, Copy, Clone, Debug, PartialEq)]
+pub struct PixelPickedEvent {
+	/// In the render resolution
+	pub pixel: Vec2<u32>,
+	pub origin: Vec3<f32>,
+	pub dir: Vec3<f32>,
+}
+
+/// What the renderer's `output_id` held under a [`PixelPickedEvent`], i.e.
+/// what the intersection hit in that pixel. What the id means depends on the
+/// intersector (e.g. the primitive of the
+/// [`AnalyticIntersector`](crate::fragments::intersector::AnalyticIntersector)),
+/// 0 is nothing.
+#[derive(Event, Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ObjectPickedEvent {
+	/// In the render resolution
+	pub pixel: Vec2<u32>,
+	pub id: u32,
+}
+
+/// The id of the last click, while it's being read back. A new click replaces
+/// it.
+#[derive(bevy::Resource, Default)]
+struct PendingPick(Option<(Vec2<u32>, TexelReadback)>);
+
+/// Left clicks on the window while the cursor is free, for systems. While the
+/// cursor is attached, clicks are for the camera.
+#[derive(SystemParam)]
+pub struct FreeClicks<'w, 's> {
+	cursor: Res<'w, CursorPosition>,
+	app_window: Res<'w, AppWindow>,
+	mouse_input_events: EventReader<'w, 's, MouseInputEvent>,
+}
+
+impl FreeClicks<'_, '_> {
+	/// Where the cursor was when it was clicked since the last call, if it was
+	/// free and in the window
+	pub fn clicked_at(&mut self) -> Option<Vec2<f32>> {
+		let clicked = self
+			.mouse_input_events
+			.read()
+			.filter(|event| event.button == MouseButton::Left && event.state == ElementState::Pressed)
+			.last()
+			.is_some();
+
+		if !clicked || self.app_window.cursor_attached {
+			return None;
+		}
+
+		self.cursor.0
+	}
+}
+
+/// Reads back the `output_id` of the compute renderer, if it has one
+#[derive(SystemParam)]
+struct IdReadback<'w> {
+	gpu: Res<'w, Gpu>,
+	compute_renderer: Option<Res<'w, ComputeRenderer>>,
+	pending: ResMut<'w, PendingPick>,
+}
+
+impl IdReadback<'_> {
+	fn request(&mut self, pixel: Vec2<u32>) {
+		let ids = self
+			.compute_renderer
+			.as_ref()
+			.and_then(|compute_renderer| compute_renderer.output_texture("output_id"))
+			.filter(|ids| pixel.x < ids.size().width && pixel.y < ids.size().height);
+		if let Some(ids) = ids {
+			self.pending.0 = Some((pixel, ids.request_texel(&self.gpu, pixel)));
+		}
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+fn track_cursor(mut cursor: ResMut<CursorPosition>, mut winit_events: EventReader<WinitWindowEvent>) {
+	for WinitWindowEvent(event) in winit_events.read() {
+		match event {
+			WindowEvent::CursorMoved { position, .. } => {
+				cursor.0 = Some(Vec2::new(position.x as f32, position.y as f32));
+			}
+			WindowEvent::CursorLeft { .. } => cursor.0 = None,
+			_ => {}
+		}
+	}
+}
+
+fn pick_pixel(
+	mut clicks: FreeClicks,
+	resolution: Res<Resolution>,
+	viewports: Query<&ViewportInfo>,
+	cameras: Query<&CameraView, With<ActiveCamera>>,
+	pip: Option<Res<PictureInPicture>>,
+	mut id_readback: IdReadback,
+	mut picked_events: EventWriter<PixelPickedEvent>,
+) {
+	let (Some(cursor), Ok(viewport), Ok(view)) = (clicks.clicked_at(), viewports.get_single(), cameras.get_single())
+	else {
+		return;
+	};
+
+	// That click swaps the cameras
+	if pip.is_some_and(|pip| pip.contains(cursor, viewport.size.0)) {
+		return;
+	}
+
+	let texel = window_to_texture(cursor, viewport.size.0, resolution.0);
+	if texel.x < 0.0 || texel.y < 0.0 || texel.x >= resolution.w as f32 || texel.y >= resolution.h as f32 {
+		return;
+	}
+
+	// The shader traces from the corner of the texel, not its center
+	let pixel = texel.map(|x| x as u32);
+	let (origin, dir) = view.pixel_ray(pixel.map(|x| x as f32), resolution.0);
+
+	picked_events.send(PixelPickedEvent { pixel, origin, dir });
+
+	// The copy sees the frame that was clicked on, which is the last rendered one
+	id_readback.request(pixel);
+}
+
+fn read_picked_object(
+	gpu: Res<Gpu>,
+	mut pending: ResMut<PendingPick>,
+	mut picked_events: EventWriter<ObjectPickedEvent>,
+) {
+	let Some((pixel, readback)) = &pending.0 else {
+		return;
+	};
+
+	let Some(bytes) = readback.try_take(&gpu) else {
+		return;
+	};
+
+	// R32Uint
+	let id = u32::from_le_bytes(bytes[..4].try_into().unwrap());
+	picked_events.send(ObjectPickedEvent { pixel: *pixel, id });
+	pending.0 = None;
+}
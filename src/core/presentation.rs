@@ -0,0 +1,411 @@
+use std::{f32::consts::PI, time::Duration};
+
+use anyhow::{bail, Result};
+use bevy_ecs::{
+	entity::Entity,
+	event::EventReader,
+	query::With,
+	schedule::IntoSystemConfigs,
+	system::{Commands, Local, Query, Res, ResMut},
+	world::World,
+};
+use brainrot::{
+	bevy::{self, App, Plugin},
+	calc_forward_horizontal_vector, calc_view_matrix, rad,
+	vek::Vec3,
+	Direction, Position,
+};
+use log::info;
+
+use super::{
+	camera::{ActiveCamera, CameraControl, CameraController, MovementSmoothing, MovementSpeed, Sprint},
+	clip_planes::{ClipPlanesAdjustment, SceneBounds},
+	console::{self, is_console_closed},
+	events::KeyboardInputEvent,
+	gameloop::{Time, Update},
+	key_bindings::{Action, HeldKeys, KeyBindings},
+	params,
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Restricts the active camera to a turntable around the [`SceneBounds`] while
+/// the [`PresentationMode`] is on, so that showing the scene to someone can't
+/// end up inside of it. Toggled with [`Action::TogglePresentation`], the
+/// `presentation` console command, or `--presentation` on the command line.
+///
+/// Needs to be added after the camera and clip planes plugins.
+#[derive(Default)]
+pub struct PresentationPlugin {
+	pub settings: PresentationSettings,
+}
+
+impl Plugin for PresentationPlugin {
+	fn build(&self, app: &mut App) {
+		app.world.insert_resource(PresentationMode {
+			enabled: false,
+			settings: self.settings,
+		});
+
+		params::registry(app)
+			.register_float(
+				"presentation.auto_rotation",
+				"How fast the camera turns around the scene by itself while presenting, in degrees per second",
+				-180.0..=180.0,
+				|world| Ok(world.resource::<PresentationMode>().settings.auto_rotation),
+				|world, speed| {
+					world.resource_mut::<PresentationMode>().settings.auto_rotation = speed;
+					Ok(())
+				},
+			)
+			.register_float(
+				"presentation.turn_speed",
+				"How fast the left and right keys turn the camera around the scene, in degrees per second",
+				0.0..=360.0,
+				|world| Ok(world.resource::<PresentationMode>().settings.turn_speed),
+				|world, speed| {
+					world.resource_mut::<PresentationMode>().settings.turn_speed = speed;
+					Ok(())
+				},
+			)
+			.register_float(
+				"presentation.radius_scale",
+				"How far the camera is from the center of the scene, relative to the size of the scene",
+				0.5..=10.0,
+				|world| Ok(world.resource::<PresentationMode>().settings.radius_scale),
+				|world, scale| {
+					world.resource_mut::<PresentationMode>().settings.radius_scale = scale;
+					Ok(())
+				},
+			);
+
+		console::register_command(
+			app,
+			"presentation",
+			"presentation [on | off]: Show, start or stop the presentation mode",
+			presentation,
+		);
+
+		app.add_systems(
+			Update,
+			(
+				toggle_presentation.run_if(is_console_closed),
+				start_or_stop_presentation,
+				process_presentation_keys.run_if(is_console_closed),
+				update_presentation,
+			)
+				.chain()
+				.after(CameraControl)
+				.before(ClipPlanesAdjustment),
+		);
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PresentationSettings {
+	/// Degrees per second, 0 to only turn with the keys
+	pub auto_rotation: f32,
+	/// Degrees per second that [`Action::MoveLeft`] and [`Action::MoveRight`]
+	/// turn
+	pub turn_speed: f32,
+	/// The distance to the center, relative to the half diagonal of the
+	/// [`SceneBounds`], so above 1 to stay out of the scene
+	pub radius_scale: f32,
+	/// The band of degrees above the horizon the camera stays in, it keeps the
+	/// elevation it had when the presentation started if that's in there
+	pub min_elevation: f32,
+	pub max_elevation: f32,
+	/// How long the camera takes to get onto the turntable and back
+	pub transition: Duration,
+}
+
+impl Default for PresentationSettings {
+	fn default() -> Self {
+		Self {
+			auto_rotation: 0.0,
+			turn_speed: 45.0,
+			radius_scale: 1.5,
+			min_elevation: 5.0,
+			max_elevation: 35.0,
+			transition: Duration::from_millis(800),
+		}
+	}
+}
+
+/// Whether the active camera should be on the turntable. The camera only gets
+/// there (or back) after the [`PresentationSettings::transition`].
+///
+/// While presenting, the camera can't be flown, looked around with or
+/// sprinted, the left and right keys turn it around the scene instead. There's
+/// no crosshair or other indicator drawn over the render yet, so there's
+/// nothing to hide.
+#[derive(bevy::Resource, Copy, Clone, Debug, PartialEq)]
+pub struct PresentationMode {
+	pub enabled: bool,
+	pub settings: PresentationSettings,
+}
+
+/// Where the camera is on the turntable, looking at the center
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Turntable {
+	pub center: Vec3<f32>,
+	pub radius: f32,
+	/// The yaw of the camera, in radians
+	pub azimuth: f32,
+	/// Above the horizon, in radians
+	pub elevation: f32,
+}
+
+impl Turntable {
+	/// The turntable around the bounds, with the camera put where it's closest
+	/// to where it is, so that it doesn't have to go around the scene
+	pub fn around(
+		bounds: &SceneBounds,
+		position: Position,
+		direction: Direction,
+		settings: &PresentationSettings,
+	) -> Self {
+		let center = bounds.center();
+		let radius = (bounds.max - bounds.min).magnitude() / 2.0 * settings.radius_scale;
+
+		let to_center = center - position.0;
+		let horizontal = Vec3::new(to_center.x, 0.0, to_center.z);
+
+		// Whatever the yaw convention is, the yaw that looks along `horizontal`
+		let azimuth = if horizontal.magnitude() > 1e-4 {
+			let forward = yaw_forward(0.0);
+			let left_or_right = yaw_forward(PI / 2.0);
+			horizontal.dot(left_or_right).atan2(horizontal.dot(forward))
+		} else {
+			direction.yaw.to_radians()
+		};
+
+		let elevation = (-to_center.y / to_center.magnitude().max(1e-4)).clamp(-1.0, 1.0).asin();
+
+		Self {
+			center,
+			radius,
+			azimuth,
+			elevation: elevation.clamp(settings.min_elevation.to_radians(), settings.max_elevation.to_radians()),
+		}
+	}
+
+	pub fn pose(&self) -> (Position, Direction) {
+		let mut direction = Direction::default();
+		direction.yaw = rad!(self.azimuth);
+		// Looking down is a negative pitch
+		direction.pitch = rad!(-self.elevation);
+
+		let forward = calc_view_matrix(Vec3::zero().into(), direction)
+			.inverted()
+			.mul_direction(Vec3::unit_z());
+
+		((self.center - forward * self.radius).into(), direction)
+	}
+}
+
+/// Smoothly (ease in and out) between two poses, `t` going from 0 to 1. The
+/// yaw goes the short way around.
+pub fn interpolate_pose(from: (Position, Direction), to: (Position, Direction), t: f32) -> (Position, Direction) {
+	let t = t.clamp(0.0, 1.0);
+	let t = t * t * (3.0 - 2.0 * t);
+
+	let from_yaw = from.1.yaw.to_radians();
+	let yaw_difference = (to.1.yaw.to_radians() - from_yaw + PI).rem_euclid(2.0 * PI) - PI;
+
+	let from_pitch = from.1.pitch.to_radians();
+	let pitch = from_pitch + (to.1.pitch.to_radians() - from_pitch) * t;
+
+	let mut direction = to.1;
+	direction.yaw = rad!(from_yaw + yaw_difference * t);
+	direction.pitch = rad!(pitch);
+
+	((from.0 .0 + (to.0 .0 - from.0 .0) * t).into(), direction)
+}
+
+/// The horizontal forward vector of a camera with that yaw
+fn yaw_forward(yaw: f32) -> Vec3<f32> {
+	let mut direction = Direction::default();
+	direction.yaw = rad!(yaw);
+	calc_forward_horizontal_vector(direction)
+}
+
+/// On the active camera while it's on the turntable, or getting on or off of it
+#[derive(bevy::Component, Copy, Clone, Debug)]
+pub struct Presentation {
+	pub turntable: Turntable,
+	/// The pose to go back to when the presentation stops
+	pub entered_from: (Position, Direction),
+
+	/// Where the current transition started, and how long ago
+	transition_from: (Position, Direction),
+	transition_elapsed: Duration,
+	leaving: bool,
+	/// -1 to turn left, 1 to turn right
+	turning: f32,
+}
+
+impl Presentation {
+	pub fn is_leaving(&self) -> bool {
+		self.leaving
+	}
+
+	/// Go back the other way from where the camera is now
+	fn reverse(&mut self, pose: (Position, Direction)) {
+		self.transition_from = pose;
+		self.transition_elapsed = Duration::ZERO;
+		self.leaving = !self.leaving;
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+fn toggle_presentation(
+	mut mode: ResMut<PresentationMode>,
+	mut keyboard_events: EventReader<KeyboardInputEvent>,
+	key_bindings: Res<KeyBindings>,
+) {
+	if key_bindings.has_pressed(Action::TogglePresentation, keyboard_events.read()) {
+		mode.enabled = !mode.enabled;
+		info!("Presentation mode: {}", if mode.enabled { "on" } else { "off" });
+	}
+}
+
+fn start_or_stop_presentation(
+	mut commands: Commands,
+	mut q: Query<
+		(
+			Entity,
+			&Position,
+			&Direction,
+			Option<&mut Presentation>,
+			Option<&mut CameraController>,
+			Option<(&mut Sprint, &mut MovementSpeed)>,
+			Option<&mut MovementSmoothing>,
+		),
+		With<ActiveCamera>,
+	>,
+	mode: Res<PresentationMode>,
+	bounds: Option<Res<SceneBounds>>,
+) {
+	let Ok((entity, position, direction, presentation, controller, sprint, smoothing)) = q.get_single_mut() else {
+		return;
+	};
+	let pose = (*position, *direction);
+
+	match presentation {
+		Some(mut presentation) if presentation.leaving == mode.enabled => presentation.reverse(pose),
+		Some(_) => {}
+		None if mode.enabled => {
+			let bounds = bounds.map(|bounds| *bounds).unwrap_or_default();
+
+			commands.entity(entity).insert(Presentation {
+				turntable: Turntable::around(&bounds, *position, *direction, &mode.settings),
+				entered_from: pose,
+				transition_from: pose,
+				transition_elapsed: Duration::ZERO,
+				leaving: false,
+				turning: 0.0,
+			});
+
+			// Anything still in motion would carry on once the presentation stops
+			if let Some(mut controller) = controller {
+				controller.reset();
+			}
+			if let Some((mut sprint, mut speed)) = sprint {
+				sprint.reset(&mut speed);
+			}
+			if let Some(mut smoothing) = smoothing {
+				smoothing.reset();
+			}
+		}
+		None => {}
+	}
+}
+
+fn process_presentation_keys(
+	mut q: Query<&mut Presentation, With<ActiveCamera>>,
+	mut keyboard_events: EventReader<KeyboardInputEvent>,
+	key_bindings: Res<KeyBindings>,
+	mut held_keys: Local<HeldKeys>,
+) {
+	held_keys.update(keyboard_events.read());
+
+	let Ok(mut presentation) = q.get_single_mut() else {
+		return;
+	};
+
+	let left = key_bindings.is_held(Action::MoveLeft, &held_keys);
+	let right = key_bindings.is_held(Action::MoveRight, &held_keys);
+	presentation.turning = right as i32 as f32 - left as i32 as f32;
+}
+
+fn update_presentation(
+	mut commands: Commands,
+	mut q: Query<(Entity, &mut Presentation, &mut Position, &mut Direction), With<ActiveCamera>>,
+	mode: Res<PresentationMode>,
+	time: Res<Time>,
+) {
+	let Ok((entity, mut presentation, mut position, mut direction)) = q.get_single_mut() else {
+		return;
+	};
+	let settings = &mode.settings;
+	let dt = time.dt_u.as_secs_f32();
+
+	let turning = presentation.turning;
+	let turntable = &mut presentation.turntable;
+	turntable.azimuth += (settings.auto_rotation + turning * settings.turn_speed).to_radians() * dt;
+	turntable.azimuth = turntable.azimuth.rem_euclid(2.0 * PI);
+	turntable.elevation = turntable
+		.elevation
+		.clamp(settings.min_elevation.to_radians(), settings.max_elevation.to_radians());
+
+	presentation.transition_elapsed += time.dt_u;
+	let t = presentation.transition_elapsed.as_secs_f32() / settings.transition.as_secs_f32().max(1e-3);
+
+	let target = if presentation.leaving {
+		presentation.entered_from
+	} else {
+		presentation.turntable.pose()
+	};
+	(*position, *direction) = interpolate_pose(presentation.transition_from, target, t);
+
+	if presentation.leaving && t >= 1.0 {
+		commands.entity(entity).remove::<Presentation>();
+	}
+}
+
+fn presentation(world: &mut World, args: &[String]) -> Result<String> {
+	let mut mode = world.resource_mut::<PresentationMode>();
+
+	match args {
+		[] => {}
+		[on] if on == "on" => mode.enabled = true,
+		[off] if off == "off" => mode.enabled = false,
+		_ => bail!("Usage: presentation [on | off]"),
+	}
+
+	let settings = mode.settings;
+	Ok(format!(
+		"{} (auto rotation {}°/s, turn speed {}°/s, elevation {}° to {}°)",
+		if mode.enabled { "on" } else { "off" },
+		settings.auto_rotation,
+		settings.turn_speed,
+		settings.min_elevation,
+		settings.max_elevation
+	))
+}
@@ -2,12 +2,11 @@ use std::sync::Arc;
 
 use bevy_ecs::{
 	event::EventReader,
-	query::With,
 	system::{Query, Res, ResMut},
 };
 use brainrot::{
 	bevy::{self, App, Plugin},
-	Converter, ScreenSize,
+	Converter,
 };
 use wgpu::{
 	CommandBuffer, PresentMode, Surface, SurfaceCapabilities, SurfaceConfiguration, SurfaceTexture, TextureUsages,
@@ -16,13 +15,12 @@ use wgpu::{
 use winit::window::Window;
 
 use super::{
+	entity_label::{EntityLabel, Labeled},
 	event_processing::{EventReaderProcessor, ProcessedChangeEvents},
 	gpu::Gpu,
+	size::WindowSize,
 };
-use crate::{
-	core::{display::AppWindow, events::WindowResizedEvent, gameloop::Update},
-	EntityLabel,
-};
+use crate::core::{display::AppWindow, events::WindowResizedEvent, gameloop::Update};
 
 /*
 --------------------------------------------------------------------------------
@@ -34,7 +32,7 @@ use crate::{
 #[derive(bevy::Component)]
 pub struct RenderTarget {
 	pub surface: Surface<'static>,
-	pub size: ScreenSize,
+	pub size: WindowSize,
 	pub capabilities: SurfaceCapabilities,
 	pub config: SurfaceConfiguration,
 
@@ -48,7 +46,7 @@ impl RenderTarget {
 	fn from_window(window: Arc<Window>, gpu: &Gpu) -> Self {
 		// Window is passed as arc so that the surface creation can be done safely
 
-		let size = window.inner_size().convert();
+		let size = WindowSize(window.inner_size().convert());
 
 		// Create the rendering surface on which wgpu will render, from a
 		// raw_window_handle
@@ -126,13 +124,21 @@ impl EntityLabel for WindowRenderTarget {}
 fn resize(
 	gpu: Res<Gpu>,
 	window_events: EventReader<WindowResizedEvent>,
-	mut render_targets: Query<&mut RenderTarget, With<WindowRenderTarget>>,
+	window_target: Labeled<WindowRenderTarget>,
+	mut render_targets: Query<&mut RenderTarget>,
 ) {
 	if let Some(size) = window_events.process().latest() {
-		for mut render_target in render_targets.iter_mut() {
-			render_target.config.width = size.w;
-			render_target.config.height = size.h;
-			render_target.surface.configure(&gpu.device, &render_target.config);
-		}
+		let Some(mut render_target) = window_target
+			.entity()
+			.ok()
+			.and_then(|entity| render_targets.get_mut(entity).ok())
+		else {
+			return;
+		};
+
+		render_target.size = size;
+		render_target.config.width = size.w;
+		render_target.config.height = size.h;
+		render_target.surface.configure(&gpu.device, &render_target.config);
 	}
 }
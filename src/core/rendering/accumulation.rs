@@ -0,0 +1,298 @@
+use anyhow::{bail, Result};
+use bevy_ecs::{
+	query::{Changed, With},
+	schedule::IntoSystemConfigs,
+	system::{Query, Res, ResMut},
+	world::World,
+};
+use brainrot::bevy::{self, App, Plugin};
+use pbr_tracer_derive::ShaderStruct;
+use wgpu::Buffer;
+
+use super::{
+	camera_view::CameraView, compute::RendererSwapHooks, depth_of_field::DofSettings, globals::RenderSettings,
+	lights::Lights,
+};
+use crate::{
+	core::{camera::ActiveCamera, console, gameloop::PreRender, gpu::Gpu, params},
+	fragments::path_tracer::PathTracerSettings,
+	libs::{
+		buffer::{atomic_counter::AtomicCounter, uniform_buffer::UniformBuffer, BufferUploadable},
+		smart_arc::Sarc,
+	},
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Counts the frames that a progressive renderer (e.g. the
+/// [`PathTracer`](crate::fragments::path_tracer::PathTracer)) has summed into
+/// its accumulation, see [`Accumulation`]. The count goes to the shaders as
+/// `globals.accumulated_samples`.
+///
+/// Starts over whenever the picture would change: the active camera's view,
+/// the lights, the render settings, the [`PathTracerSettings`] when they are
+/// tweakable, the [`DofSettings`], or the compute renderer (e.g. after a
+/// resize).
+///
+/// With [`ReprojectionSettings::enabled`], a camera that moves doesn't start
+/// over: the renderers that follow the history (see [`ReprojectionBuffers`])
+/// reproject it into the new view instead.
+pub struct AccumulationPlugin;
+
+impl Plugin for AccumulationPlugin {
+	fn build(&self, app: &mut App) {
+		app.world.init_resource::<Accumulation>();
+
+		// The new renderer comes with new output textures, whatever is in them isn't
+		// a sum of anything
+		app.world
+			.get_resource_or_insert_with(RendererSwapHooks::default)
+			.after(|world: &mut World| world.resource_mut::<Accumulation>().reset());
+
+		params::registry(app)
+			.register_bool(
+				"accumulation.reproject",
+				"Reproject the accumulation into the new view when the camera moves, instead of starting over",
+				|world| Ok(world.resource::<Accumulation>().reprojection.enabled),
+				|world, enabled| {
+					world.resource_mut::<Accumulation>().reprojection.enabled = enabled;
+					Ok(())
+				},
+			)
+			.register_float(
+				"accumulation.history_weight",
+				"How much of its history a reprojected pixel keeps",
+				0.0..=1.0,
+				|world| Ok(world.resource::<Accumulation>().reprojection.history_weight),
+				|world, weight| {
+					world.resource_mut::<Accumulation>().reprojection.history_weight = weight;
+					Ok(())
+				},
+			);
+
+		console::register_command(
+			app,
+			"accumulation",
+			"accumulation: Show how many frames are summed, and how much of the history the last reprojection kept",
+			accumulation,
+		);
+
+		app.add_systems(PreRender, update_accumulation);
+	}
+}
+
+#[derive(bevy::Resource, Default, Debug)]
+pub struct Accumulation {
+	pub reprojection: ReprojectionSettings,
+	/// Counting the frame being rendered
+	samples: u32,
+	reset: bool,
+	/// To tell when the camera moved
+	last_view: Option<CameraView>,
+	/// The view the history was summed from, on the frames that reproject it
+	reprojected_from: Option<CameraView>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ReprojectionSettings {
+	/// Starts over when the camera moves otherwise
+	pub enabled: bool,
+	/// How much of its history a reprojected pixel keeps, the sum and its count
+	/// alike so that the average stays the same. Lower follows the new view
+	/// faster, e.g. for the view-dependent reflections.
+	pub history_weight: f32,
+	/// How far the depth found in the history can be from the expected one,
+	/// relative to it, before the pixel counts as disoccluded and starts over
+	pub depth_tolerance: f32,
+}
+
+impl Default for ReprojectionSettings {
+	fn default() -> Self {
+		Self {
+			enabled: false,
+			history_weight: 0.8,
+			depth_tolerance: 0.02,
+		}
+	}
+}
+
+impl Accumulation {
+	/// How many frames are in the accumulation, counting the one being rendered
+	pub fn samples(&self) -> u32 {
+		self.samples
+	}
+
+	/// Start over on the next frame
+	pub fn reset(&mut self) {
+		self.reset = true;
+	}
+
+	/// The view of the previous frame, if the camera moved and the history
+	/// should be reprojected from it instead of starting over
+	pub fn reprojected_from(&self) -> Option<&CameraView> {
+		self.reprojected_from.as_ref()
+	}
+
+	/// Only the rays of the same kind can be followed from one view to the other,
+	/// and the panoramas don't have a plane to project on
+	fn can_reproject(from: &CameraView, to: &CameraView) -> bool {
+		from.orthographic == to.orthographic && from.equirectangular == 0 && to.equirectangular == 0
+	}
+}
+
+pub(crate) fn update_accumulation(
+	mut accumulation: ResMut<Accumulation>,
+	cameras: Query<&CameraView, With<ActiveCamera>>,
+	lights: Option<Res<Lights>>,
+	render_settings: Option<Res<RenderSettings>>,
+	path_tracer_settings: Query<(), Changed<PathTracerSettings>>,
+	dof_settings: Query<(), Changed<DofSettings>>,
+) {
+	let view = cameras.get_single().ok().copied();
+	let view_changed = view != accumulation.last_view;
+	let lights_changed = lights.is_some_and(|lights| lights.is_changed());
+	let settings_changed = render_settings.is_some_and(|settings| settings.is_changed())
+		|| !path_tracer_settings.is_empty()
+		|| !dof_settings.is_empty();
+
+	// Only the view can be reprojected, everything else changes the light
+	let reprojected_from = match (accumulation.last_view, view) {
+		(Some(from), Some(to))
+			if view_changed
+				&& accumulation.reprojection.enabled
+				&& accumulation.samples > 0
+				&& !accumulation.reset
+				&& !lights_changed
+				&& !settings_changed
+				&& Accumulation::can_reproject(&from, &to) =>
+		{
+			Some(from)
+		}
+		_ => None,
+	};
+
+	accumulation.reprojected_from = reprojected_from;
+	if reprojected_from.is_some() {
+		accumulation.last_view = view;
+	} else if accumulation.reset || view_changed || lights_changed || settings_changed {
+		accumulation.samples = 0;
+		accumulation.reset = false;
+		accumulation.last_view = view;
+	}
+
+	accumulation.samples = accumulation.samples.saturating_add(1);
+}
+
+fn accumulation(world: &mut World, args: &[String]) -> Result<String> {
+	if !args.is_empty() {
+		bail!("Usage: accumulation");
+	}
+
+	let accumulation = world.resource::<Accumulation>();
+	let mut lines = vec![format!("{} samples", accumulation.samples)];
+
+	if !accumulation.reprojection.enabled {
+		lines.push("Reprojection off".to_owned());
+	} else {
+		match world.get_resource::<ReprojectionBuffers>() {
+			Some(buffers) => match buffers.retained_history(world.resource::<Gpu>()) {
+				Some(retained) => lines.push(format!(
+					"The last reprojection kept {:.1}% of the history",
+					retained * 100.0
+				)),
+				None => lines.push("Nothing reprojected yet".to_owned()),
+			},
+			None => lines.push("Reprojection on, but the renderer doesn't follow the history".to_owned()),
+		}
+	}
+
+	Ok(lines.join("\n"))
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// The `reprojection` uniform, uploaded from the [`Accumulation`] before every
+/// frame
+#[repr(C)]
+#[derive(ShaderStruct, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, Default, PartialEq)]
+pub struct Reprojection {
+	/// 1 on the frames whose history was summed from the `previous_camera`
+	pub active: u32,
+	/// See [`ReprojectionSettings`]
+	pub history_weight: f32,
+	pub depth_tolerance: f32,
+	#[shader(skip)]
+	_padding: u32,
+}
+
+/// The buffers that a renderer binds to reproject its history when the camera
+/// moves: the `reprojection` uniform, the view the history was summed from as
+/// `previous_camera`, and the `reprojection_stats` counters (how many pixels
+/// were reprojected, and how much of their history they kept in 1/256ths).
+/// Shared by all the renderers, created by the first one that asks.
+#[derive(bevy::Resource, Clone)]
+pub struct ReprojectionBuffers {
+	pub reprojection: Sarc<Buffer>,
+	pub previous_camera: Sarc<Buffer>,
+	pub stats: Sarc<Buffer>,
+}
+
+impl ReprojectionBuffers {
+	/// How much of the history the pixels kept on average, on the last frame that
+	/// reprojected it. Blocks until the counters are read back.
+	pub fn retained_history(&self, gpu: &Gpu) -> Option<f32> {
+		let stats = AtomicCounter::readback(gpu, &self.stats);
+		(stats[0] > 0).then(|| stats[1] as f32 / 256.0 / stats[0] as f32)
+	}
+}
+
+/// The [`ReprojectionBuffers`], spawning them the first time. Needs the GPU
+/// plugin and the [`AccumulationPlugin`].
+pub fn reprojection_buffers(app: &mut App) -> ReprojectionBuffers {
+	if let Some(buffers) = app.world.get_resource::<ReprojectionBuffers>() {
+		return buffers.clone();
+	}
+
+	let gpu = app.world.resource::<Gpu>();
+	let buffers = ReprojectionBuffers {
+		reprojection: Sarc::new(UniformBuffer::raw_buffer_from_type::<Reprojection>(
+			gpu,
+			Some("Reprojection"),
+		)),
+		previous_camera: Sarc::new(UniformBuffer::raw_buffer_from_type::<CameraView>(
+			gpu,
+			Some("Reprojection previous camera"),
+		)),
+		stats: Sarc::new(AtomicCounter::raw_buffer_from_count(gpu, 2, Some("Reprojection stats"))),
+	};
+
+	app.world.insert_resource(buffers.clone());
+	app.add_systems(PreRender, upload_reprojection.after(update_accumulation));
+
+	buffers
+}
+
+fn upload_reprojection(gpu: Res<Gpu>, accumulation: Res<Accumulation>, buffers: Res<ReprojectionBuffers>) {
+	let settings = accumulation.reprojection;
+	let reprojection = Reprojection {
+		active: accumulation.reprojected_from.is_some() as u32,
+		history_weight: settings.history_weight,
+		depth_tolerance: settings.depth_tolerance,
+		_padding: Default::default(),
+	};
+	buffers.reprojection.upload_bytes(&gpu, &reprojection.get_bytes(), 0);
+
+	// The counters keep the last reprojection's, for the `accumulation` command
+	if let Some(previous) = &accumulation.reprojected_from {
+		buffers.previous_camera.upload_bytes(&gpu, &previous.get_bytes(), 0);
+		buffers.stats.upload_bytes(&gpu, &[0; 8], 0);
+	}
+}
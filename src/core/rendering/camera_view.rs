@@ -1,26 +1,35 @@
+use std::{
+	f32::consts::{PI, TAU},
+	time::{Duration, Instant},
+};
+
 use bevy_ecs::{
 	entity::Entity,
-	query::With,
+	query::{With, Without},
 	schedule::IntoSystemConfigs,
-	system::{Query, Res},
+	system::{Commands, Local, Query, Res},
 };
 use brainrot::{
 	bevy::{self, App, Plugin},
 	calc_projection_matrix, calc_view_matrix,
-	vek::Mat4,
+	vek::{Extent2, FrustumPlanes, Mat4, Vec2, Vec3},
 	Direction, Frustum, Position,
 };
+use log::warn;
 use pbr_tracer_derive::ShaderStruct;
+use wgpu::Buffer;
 
 use crate::{
 	core::{
-		camera::{Camera, CameraControl},
-		gameloop::Update,
+		camera::{ActiveCamera, Camera, CameraControl, ProjectionMode},
+		clip_planes::ClipPlanesAdjustment,
+		entity_label::{EntityLabel, Labeled},
+		gameloop::{PreRender, Update},
 		gpu::Gpu,
-		render_target::RenderTarget,
+		size::Resolution,
 	},
 	libs::{
-		buffer::{self, uniform_buffer::UniformBuffer, ShaderType},
+		buffer::{uniform_buffer::UniformBuffer, BufferUploadable, ShaderType},
 		smart_arc::Sarc,
 	},
 };
@@ -31,6 +40,8 @@ use crate::{
 --------------------------------------------------------------------------------
 */
 
+/// Gives every [`Camera`] a [`CameraView`], and spawns the buffer that the
+/// view of the [`ActiveCamera`] is uploaded to (see [`ActiveCameraView`]).
 pub struct CameraViewPlugin;
 
 impl Plugin for CameraViewPlugin {
@@ -38,23 +49,26 @@ impl Plugin for CameraViewPlugin {
 		let gpu = app.world.resource::<Gpu>();
 
 		let camera_view_buffer = Sarc::new(UniformBuffer::raw_buffer_from_type::<CameraView>(gpu, None));
-
-		let camera_entity = app
-			.world
-			.query_filtered::<Entity, With<Camera>>()
-			.single_mut(&mut app.world);
-
-		app.world
-			.entity_mut(camera_entity)
-			.insert(CameraView::default())
-			.insert(camera_view_buffer);
-
-		buffer::register_auto_update::<CameraView>(app);
-
-		app.add_systems(Update, (update_view).after(CameraControl));
+		app.world.spawn((ActiveCameraView, camera_view_buffer));
+
+		app.add_systems(
+			Update,
+			(insert_camera_views, update_view)
+				.chain()
+				.after(CameraControl)
+				.after(ClipPlanesAdjustment),
+		);
+		app.add_systems(PreRender, upload_active_view);
 	}
 }
 
+/// Marks the buffer that the renderers bind as `camera`. It doesn't belong to
+/// any camera, the view of the active one is copied into it before every
+/// render, so switching cameras doesn't need to rebuild anything.
+#[derive(bevy::Component)]
+pub struct ActiveCameraView;
+impl EntityLabel for ActiveCameraView {}
+
 /*
 --------------------------------------------------------------------------------
 ||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
@@ -70,19 +84,31 @@ pub struct CameraView {
 	pub y_fov: f32,
 	pub focal_length: f32,
 
+	/// 1 if the rays are parallel, see [`ProjectionMode`]
+	pub orthographic: u32,
+	/// The height of the view in world units, when orthographic
+	pub ortho_height: f32,
+	/// 1 if the image is a panorama all around the camera, see [`ProjectionMode`]
+	pub equirectangular: u32,
+	#[shader(skip)]
+	_padding: u32,
+
 	pub view_mat: Mat4<f32>,
 	pub inverse_view_mat: Mat4<f32>,
 	pub proj_mat: Mat4<f32>,
 }
 
-fn update_view(
-	render_target: Res<RenderTarget<'static>>,
-	mut q: Query<(&Position, &Direction, &Frustum, &mut CameraView)>,
-) {
-	for (position, direction, frustum, mut view) in q.iter_mut() {
-		let position = *position;
-		let direction = *direction;
-		let size = render_target.size;
+impl CameraView {
+	pub fn new(
+		position: Position,
+		direction: Direction,
+		frustum: Frustum,
+		projection_mode: ProjectionMode,
+		resolution: Resolution,
+	) -> Self {
+		// The camera looks at the scene through the rendered image, not through the
+		// window, so everything is relative to the render resolution
+		let size = resolution.0;
 		let z_near = frustum.z_near;
 		let z_far = frustum.z_far;
 		let y_fov = frustum.y_fov;
@@ -90,16 +116,123 @@ fn update_view(
 		let focal_length = (size.h as f32) / 2.0 / (y_fov / 2.0).tan();
 		let view_mat = calc_view_matrix(position, direction);
 		let inverse_view_mat = calc_view_matrix(position, direction).inverted();
-		let proj_mat = calc_projection_matrix(*frustum, size);
 
-		*view = CameraView {
+		let (orthographic, ortho_height, proj_mat) = match projection_mode {
+			// The panorama has no projection matrix, the rays bypass it anyway
+			ProjectionMode::Perspective | ProjectionMode::Equirectangular => {
+				(0, 0.0, calc_projection_matrix(frustum, size))
+			}
+			ProjectionMode::Orthographic { height } => {
+				let width = height * size.w as f32 / size.h as f32;
+				let proj_mat = Mat4::orthographic_lh_zo(FrustumPlanes {
+					left: -width / 2.0,
+					right: width / 2.0,
+					bottom: -height / 2.0,
+					top: height / 2.0,
+					near: z_near,
+					far: z_far,
+				});
+
+				(1, height, proj_mat)
+			}
+		};
+
+		Self {
 			z_near,
 			z_far,
 			y_fov,
 			focal_length,
+			orthographic,
+			ortho_height,
+			equirectangular: (projection_mode == ProjectionMode::Equirectangular) as u32,
+			_padding: Default::default(),
 			view_mat,
 			inverse_view_mat,
 			proj_mat,
 		}
 	}
+
+	/// The world space ray (origin, direction) that the compute shader traces
+	/// for a pixel of the rendered image, the same math as `camera_ray` in
+	/// `mpr_common.wgsl` through the center of the lens. The pixel is in the render resolution, with (0, 0)
+	/// being the first texel of the output texture.
+	pub fn pixel_ray(&self, pixel: Vec2<f32>, resolution: Extent2<u32>) -> (Vec3<f32>, Vec3<f32>) {
+		let height = resolution.h as f32;
+		let coord = (pixel - Vec2::new(resolution.w as f32, height) / 2.0) / height;
+
+		let (origin, dir) = if self.equirectangular != 0 {
+			(Vec3::zero(), equirectangular_direction(pixel, resolution))
+		} else if self.orthographic != 0 {
+			// Parallel rays, offset by the pixel position on the view plane
+			let offset = coord * self.ortho_height;
+			(Vec3::new(offset.x, offset.y, 0.0), Vec3::unit_z())
+		} else {
+			let focal_length = self.focal_length / height;
+			(Vec3::zero(), Vec3::new(coord.x, coord.y, focal_length).normalized())
+		};
+
+		(
+			self.inverse_view_mat.mul_point(origin),
+			self.inverse_view_mat.mul_direction(dir),
+		)
+	}
+}
+
+/// The view space direction of a pixel of an equirectangular panorama, the
+/// same math as `camera_equirectangular_direction` in `mpr_common.wgsl`
+fn equirectangular_direction(pixel: Vec2<f32>, resolution: Extent2<u32>) -> Vec3<f32> {
+	let longitude = (pixel.x / resolution.w as f32 - 0.5) * TAU;
+	let latitude = (pixel.y / resolution.h as f32 - 0.5) * PI;
+
+	Vec3::new(
+		latitude.cos() * longitude.sin(),
+		latitude.sin(),
+		latitude.cos() * longitude.cos(),
+	)
+}
+
+// Also covers the cameras spawned while the app runs
+fn insert_camera_views(mut commands: Commands, q: Query<Entity, (With<Camera>, Without<CameraView>)>) {
+	for entity in q.iter() {
+		commands.entity(entity).insert(CameraView::default());
+	}
+}
+
+fn update_view(
+	resolution: Res<Resolution>,
+	mut q: Query<(&Position, &Direction, &Frustum, &ProjectionMode, &mut CameraView)>,
+) {
+	for (position, direction, frustum, projection_mode, mut view) in q.iter_mut() {
+		*view = CameraView::new(*position, *direction, *frustum, *projection_mode, *resolution);
+	}
+}
+
+fn upload_active_view(
+	gpu: Res<Gpu>,
+	active_camera: Labeled<ActiveCamera>,
+	views: Query<&CameraView>,
+	buffers: Query<&Sarc<Buffer>, With<ActiveCameraView>>,
+	mut last_warning: Local<Option<Instant>>,
+) {
+	// Keep the last view if there isn't exactly one active camera
+	let camera = match active_camera.entity() {
+		Ok(camera) => camera,
+		Err(e) => {
+			// Every frame would flood the log
+			if last_warning.map_or(true, |last_warning| last_warning.elapsed() >= Duration::from_secs(5)) {
+				*last_warning = Some(Instant::now());
+				warn!("{}, keeping the last view (is the CameraPlugin added?)", e);
+			}
+			return;
+		}
+	};
+
+	// Its view is inserted by the end of the first update it was spawned in
+	let Ok(view) = views.get(camera) else {
+		return;
+	};
+
+	for buffer in buffers.iter() {
+		buffer.upload_bytes(&gpu, &view.get_bytes(), 0);
+	}
 }
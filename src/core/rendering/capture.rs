@@ -0,0 +1,582 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use bevy_ecs::{
+	event::EventReader,
+	query::With,
+	schedule::IntoSystemConfigs,
+	system::{Commands, Query, Res, ResMut, SystemParam},
+	world::World,
+};
+use brainrot::{
+	bevy::{self, App, Plugin},
+	size, vec2,
+	vek::Vec2,
+};
+use log::{error, info, warn};
+use wgpu::{
+	CommandEncoderDescriptor, ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, ShaderStages,
+	StorageTextureAccess, TextureAspect, TextureFormat, TextureFormatFeatureFlags, TextureUsages,
+};
+
+use super::{
+	compute::{self, ComputeRenderPass, ComputeRenderer},
+	dynamic_quality::DynamicQuality,
+	globals::RenderSettings,
+	render::InnerRenderPass,
+};
+use crate::{
+	core::{
+		camera::{ActiveCamera, CameraControl, ProjectionMode},
+		console::{self, is_console_closed, save_screenshot},
+		events::KeyboardInputEvent,
+		gameloop::{Render, RequestExit, Update},
+		gpu::Gpu,
+		key_bindings::{Action, KeyBindings},
+		render_target::RenderTarget,
+		size::Resolution,
+	},
+	libs::{
+		buffer::{ping_pong_texture::PingPongParity, storage_texture_buffer::StorageTexture, BufferMappingApplicable},
+		shader::{CompiledShader, ShaderBuilder},
+		smart_arc::Sarc,
+		texture::{InitPolicy, Tex, TexDescriptor, TextureAssetDimensions},
+	},
+	ShaderAssets,
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Renders a screenshot at a multiple of the render resolution, averaged over
+/// several frames and box filtered back down to the render resolution.
+///
+/// Started with [`Action::HighQualityCapture`], the `capture` console command,
+/// or [`HighQualityCapture::request`]. The interactive renderer and settings
+/// are put back once it's done, or when it's cancelled with
+/// [`Action::CancelCapture`]. The camera should stay still during the capture,
+/// every rendered frame ends up in the average.
+///
+/// With [`CaptureProjection::Equirectangular`], the capture is a 360°
+/// panorama around the camera instead, at a resolution of its own.
+///
+/// Needs to be added after the compute renderer and the console.
+pub struct HighQualityCapturePlugin;
+
+impl Plugin for HighQualityCapturePlugin {
+	fn build(&self, app: &mut App) {
+		app.world.insert_resource(HighQualityCapture::default());
+
+		console::register_command(
+			app,
+			"capture",
+			"capture [<factor> <frames> [path] | panorama [<width> [<frames> [path]]] | cancel]: Save a supersampled screenshot or 360° panorama",
+			capture,
+		);
+
+		app.add_systems(
+			Update,
+			(process_capture_keys.run_if(is_console_closed), advance_capture)
+				.chain()
+				.before(CameraControl),
+		);
+		app.add_systems(
+			Render,
+			accumulate_capture.after(ComputeRenderPass).in_set(InnerRenderPass),
+		);
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum CaptureProjection {
+	/// Through the active camera, at the render resolution
+	#[default]
+	Camera,
+	/// All around the active camera, from its position and orientation, as an
+	/// equirectangular image `width` pixels wide and half as high. The lens and
+	/// its depth of field are left out.
+	Equirectangular { width: u32 },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CaptureSettings {
+	pub projection: CaptureProjection,
+	/// How many times the output resolution is multiplied on each axis
+	pub factor: u32,
+	/// How many frames are averaged
+	pub frames: u32,
+	pub path: String,
+	/// Exit the app once the capture is saved, for command line captures
+	pub exit_when_done: bool,
+}
+
+impl Default for CaptureSettings {
+	fn default() -> Self {
+		let timestamp = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.map(|time| time.as_secs())
+			.unwrap_or_default();
+
+		Self {
+			projection: CaptureProjection::Camera,
+			factor: 2,
+			frames: 16,
+			path: format!("capture_{}.png", timestamp),
+			exit_when_done: false,
+		}
+	}
+}
+
+impl CaptureSettings {
+	/// A panorama `width` pixels wide, saved to a path that says what it is so
+	/// that it's easy to find for the viewers
+	pub fn panorama(width: u32) -> Self {
+		let timestamp = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.map(|time| time.as_secs())
+			.unwrap_or_default();
+
+		Self {
+			projection: CaptureProjection::Equirectangular { width },
+			factor: 1,
+			path: format!("panorama_{}_{}x{}_360_equirect.png", timestamp, width, width / 2),
+			..Default::default()
+		}
+	}
+}
+
+#[derive(bevy::Resource, Default)]
+pub struct HighQualityCapture {
+	state: CaptureState,
+	cancel_requested: bool,
+}
+
+#[derive(Default)]
+enum CaptureState {
+	#[default]
+	Idle,
+	/// Starts on the next update
+	Requested(CaptureSettings),
+	Running(Box<RunningCapture>),
+}
+
+struct RunningCapture {
+	settings: CaptureSettings,
+	/// Counted by the render pass, since updates and renders don't go one to one
+	frames_done: u32,
+	frames_reported: u32,
+
+	accumulation: Sarc<Tex>,
+	shader: CompiledShader,
+	pipeline: ComputePipeline,
+	workgroups: Vec2<u32>,
+
+	// Everything the capture changes, to be put back afterwards. The renderer
+	// is swapped through a command, so it's only there once that has run.
+	interactive_renderer: Option<ComputeRenderer>,
+	interactive_resolution: Resolution,
+	interactive_render_settings: RenderSettings,
+	dynamic_quality_paused: Option<bool>,
+	interactive_projection: Option<ProjectionMode>,
+}
+
+impl HighQualityCapture {
+	pub const MAX_FACTOR: u32 = 4;
+
+	/// The width of the panoramas of the `capture panorama` command
+	pub const DEFAULT_PANORAMA_WIDTH: u32 = 4096;
+
+	const WORKGROUP_SIZE: u32 = 8;
+
+	pub fn request(&mut self, settings: CaptureSettings) -> Result<()> {
+		if !matches!(self.state, CaptureState::Idle) {
+			bail!("A capture is already running");
+		}
+		if !(1..=Self::MAX_FACTOR).contains(&settings.factor) {
+			bail!("The factor has to be between 1 and {}", Self::MAX_FACTOR);
+		}
+		if settings.frames == 0 {
+			bail!("A capture needs at least 1 frame");
+		}
+		if let CaptureProjection::Equirectangular { width } = settings.projection {
+			if width < 2 || width % 2 != 0 {
+				bail!("The width of a panorama has to be even, so that it's twice its height");
+			}
+		}
+
+		self.state = CaptureState::Requested(settings);
+		Ok(())
+	}
+
+	/// Stop the running capture on the next update, without saving anything
+	pub fn cancel(&mut self) {
+		if self.is_running() {
+			self.cancel_requested = true;
+		}
+	}
+
+	pub fn is_running(&self) -> bool {
+		!matches!(self.state, CaptureState::Idle)
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+fn process_capture_keys(
+	mut capture: ResMut<HighQualityCapture>,
+	mut keyboard_events: EventReader<KeyboardInputEvent>,
+	key_bindings: Res<KeyBindings>,
+) {
+	let events = keyboard_events.read().collect::<Vec<_>>();
+
+	if key_bindings.has_pressed(Action::CancelCapture, events.iter().copied()) {
+		capture.cancel();
+	}
+
+	if key_bindings.has_pressed(Action::HighQualityCapture, events.iter().copied()) {
+		if let Err(error) = capture.request(CaptureSettings::default()) {
+			warn!("Couldn't start the capture: {:#}", error);
+		}
+	}
+}
+
+/// What the capture changes while it runs, and puts back once it's done
+#[derive(SystemParam)]
+struct Interactive<'w> {
+	compute_renderer: Res<'w, ComputeRenderer>,
+	resolution: ResMut<'w, Resolution>,
+	render_settings: ResMut<'w, RenderSettings>,
+	dynamic_quality: Option<ResMut<'w, DynamicQuality>>,
+}
+
+fn advance_capture(
+	mut commands: Commands,
+	mut capture: ResMut<HighQualityCapture>,
+	mut interactive: Interactive,
+	mut projection_modes: Query<&mut ProjectionMode, With<ActiveCamera>>,
+	gpu: Res<Gpu>,
+) {
+	let capture = &mut *capture;
+
+	match std::mem::take(&mut capture.state) {
+		CaptureState::Idle => {}
+
+		CaptureState::Requested(settings) => {
+			if std::mem::take(&mut capture.cancel_requested) {
+				info!("Capture cancelled");
+				return;
+			}
+
+			match start(
+				&gpu,
+				settings,
+				&interactive.compute_renderer,
+				&mut interactive.resolution,
+				&mut interactive.render_settings,
+				interactive.dynamic_quality.as_deref_mut(),
+				projection_modes.get_single_mut().ok().as_deref_mut(),
+			) {
+				Ok((running, capture_renderer)) => {
+					capture.state = CaptureState::Running(Box::new(running));
+
+					// Through the swap hooks, so that the composite, the denoiser and
+					// the accumulation follow
+					commands.add(move |world: &mut World| {
+						let interactive_renderer = compute::replace_compute_renderer(world, capture_renderer);
+						if let CaptureState::Running(running) = &mut world.resource_mut::<HighQualityCapture>().state {
+							running.interactive_renderer = interactive_renderer;
+						}
+					});
+				}
+				Err(error) => error!("Couldn't start the capture: {:#}", error),
+			}
+		}
+
+		CaptureState::Running(mut running) => {
+			if capture.cancel_requested {
+				capture.cancel_requested = false;
+				info!("Capture cancelled");
+			} else if running.frames_done < running.settings.frames {
+				if running.frames_done != running.frames_reported {
+					running.frames_reported = running.frames_done;
+					info!("Capture: {}/{} frames", running.frames_done, running.settings.frames);
+				}
+
+				capture.state = CaptureState::Running(running);
+				return;
+			} else {
+				match save_screenshot(&gpu, &running.accumulation, &running.settings.path) {
+					Ok(()) => info!("Saved the capture to `{}`", running.settings.path),
+					Err(error) => error!("Couldn't save the capture: {:#}", error),
+				}
+
+				if running.settings.exit_when_done {
+					commands.insert_resource(RequestExit);
+				}
+			}
+
+			let running = *running;
+			if let Some(renderer) = running.interactive_renderer {
+				commands.add(move |world: &mut World| compute::swap_compute_renderer(world, renderer));
+			}
+			*interactive.resolution = running.interactive_resolution;
+			*interactive.render_settings = running.interactive_render_settings;
+			if let (Some(dynamic_quality), Some(paused)) = (
+				interactive.dynamic_quality.as_deref_mut(),
+				running.dynamic_quality_paused,
+			) {
+				dynamic_quality.paused = paused;
+			}
+			if let (Ok(mut projection_mode), Some(projection)) =
+				(projection_modes.get_single_mut(), running.interactive_projection)
+			{
+				*projection_mode = projection;
+			}
+		}
+	}
+}
+
+/// Set up the accumulation, and the supersampled renderer to swap in
+fn start(
+	gpu: &Gpu,
+	settings: CaptureSettings,
+	compute_renderer: &ComputeRenderer,
+	resolution: &mut Resolution,
+	render_settings: &mut RenderSettings,
+	dynamic_quality: Option<&mut DynamicQuality>,
+	projection_mode: Option<&mut ProjectionMode>,
+) -> Result<(RunningCapture, ComputeRenderer)> {
+	let output_size = match settings.projection {
+		CaptureProjection::Camera => compute_renderer.resolution().0,
+		CaptureProjection::Equirectangular { width } => size!(width, width / 2),
+	};
+	let capture_size = size!(output_size.w * settings.factor, output_size.h * settings.factor);
+
+	let max_size = gpu.device.limits().max_texture_dimension_2d;
+	if capture_size.w > max_size || capture_size.h > max_size {
+		bail!(
+			"{}x{} is bigger than the GPU allows ({}), use a smaller factor or panorama",
+			capture_size.w,
+			capture_size.h,
+			max_size
+		);
+	}
+
+	let format = TextureFormat::Rgba32Float;
+	let features = gpu.adapter.get_texture_format_features(format);
+	if !features.flags.contains(TextureFormatFeatureFlags::STORAGE_READ_WRITE) {
+		bail!("The GPU can't accumulate into a read-write {:?} texture", format);
+	}
+
+	let capture_renderer = compute_renderer.resized(gpu, Resolution(capture_size))?;
+	let source = capture_renderer
+		.output_textures
+		.first()
+		.context("The compute renderer has no output texture")?
+		.clone();
+
+	let accumulation = Tex::create(
+		gpu,
+		TexDescriptor {
+			label: "Capture accumulation",
+			dimensions: TextureAssetDimensions::D2(output_size.into()),
+			format,
+			usage: Some(TextureUsages::STORAGE_BINDING | TextureUsages::COPY_SRC),
+			aspect: TextureAspect::All,
+		},
+		None,
+	);
+	accumulation.initialize(gpu, InitPolicy::Zero);
+	let accumulation = accumulation.tracked(gpu);
+
+	let shader = ShaderBuilder::new()
+		.include_path("capture/downsample.wgsl")
+		.define("WORKGROUP_SIZE", format!("{}", HighQualityCapture::WORKGROUP_SIZE))
+		.include_value("capture_factor", settings.factor)
+		.include_value("capture_weight", 1.0 / settings.frames as f32)
+		.include_buffer(StorageTexture::FromTex {
+			var_name: "capture_source",
+			access: StorageTextureAccess::ReadOnly,
+			tex: source,
+		})
+		.include_buffer(StorageTexture::FromTex {
+			var_name: "capture_accumulation",
+			access: StorageTextureAccess::ReadWrite,
+			tex: accumulation.clone(),
+		})
+		.build(
+			gpu,
+			"Capture downsample shader",
+			&ShaderAssets,
+			ShaderStages::COMPUTE,
+			0,
+		)?;
+
+	let pipeline_layout = gpu.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+		label: Some("Capture downsample pipeline layout"),
+		bind_group_layouts: &shader.layouts(),
+		push_constant_ranges: &[],
+	});
+
+	let pipeline = shader.create_pipeline(gpu, || {
+		gpu.device.create_compute_pipeline(&ComputePipelineDescriptor {
+			label: Some("Capture downsample pipeline"),
+			layout: Some(&pipeline_layout),
+			module: &shader.shader_module,
+			entry_point: "main",
+		})
+	});
+
+	info!(
+		"Capturing {} frames at {}x{}",
+		settings.frames, capture_size.w, capture_size.h
+	);
+
+	let interactive_projection = match (settings.projection, projection_mode) {
+		(CaptureProjection::Equirectangular { .. }, Some(projection_mode)) => {
+			Some(std::mem::replace(projection_mode, ProjectionMode::Equirectangular))
+		}
+		(CaptureProjection::Equirectangular { .. }, None) => bail!("There's no active camera to take a panorama from"),
+		(CaptureProjection::Camera, _) => None,
+	};
+
+	// Full quality, and nothing that changes it halfway through
+	let interactive_render_settings = *render_settings;
+	render_settings.march_steps_scale = 1.0;
+
+	let dynamic_quality_paused = dynamic_quality.map(|dynamic_quality| {
+		let paused = dynamic_quality.paused;
+		dynamic_quality.paused = true;
+		paused
+	});
+
+	let interactive_resolution = std::mem::replace(resolution, Resolution(capture_size));
+
+	let running = RunningCapture {
+		settings,
+		frames_done: 0,
+		frames_reported: 0,
+		accumulation,
+		shader,
+		pipeline,
+		workgroups: <Vec2<u32>>::from(output_size) / HighQualityCapture::WORKGROUP_SIZE + vec2!(1),
+		interactive_renderer: None,
+		interactive_resolution,
+		interactive_render_settings,
+		dynamic_quality_paused,
+		interactive_projection,
+	};
+
+	Ok((running, capture_renderer))
+}
+
+/// Runs right after the compute renderer, on its supersampled output
+fn accumulate_capture(
+	mut capture: ResMut<HighQualityCapture>,
+	mut render_target: ResMut<RenderTarget<'static>>,
+	gpu: Res<Gpu>,
+	parity: Res<PingPongParity>,
+) {
+	let CaptureState::Running(running) = &mut capture.state else {
+		return;
+	};
+
+	if running.frames_done >= running.settings.frames {
+		return;
+	}
+
+	let mut encoder = gpu.device.create_command_encoder(&CommandEncoderDescriptor {
+		label: Some("Capture Command Encoder"),
+	});
+
+	{
+		let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+			label: Some("Capture Downsample Pass"),
+			timestamp_writes: None,
+		});
+
+		compute_pass.set_pipeline(&running.pipeline);
+		compute_pass.apply_buffer_mapping(&running.shader.binding, *parity);
+		compute_pass.dispatch_workgroups(running.workgroups.x, running.workgroups.y, 1);
+	}
+
+	render_target.command_queue.push(encoder.finish());
+	running.frames_done += 1;
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+fn capture(world: &mut World, args: &[String]) -> Result<String> {
+	let mut capture = world.resource_mut::<HighQualityCapture>();
+
+	let settings = match args {
+		[cancel] if cancel == "cancel" => {
+			if !capture.is_running() {
+				bail!("No capture is running");
+			}
+			capture.cancel();
+			return Ok("Cancelling the capture".to_owned());
+		}
+		[] => CaptureSettings::default(),
+		[panorama, rest @ ..] if panorama == "panorama" && rest.len() <= 3 => {
+			let width = match rest.first() {
+				Some(width) => width.parse().context("Expected a number for the width")?,
+				None => HighQualityCapture::DEFAULT_PANORAMA_WIDTH,
+			};
+			let mut settings = CaptureSettings::panorama(width);
+			if let Some(frames) = rest.get(1) {
+				settings.frames = frames.parse().context("Expected a number of frames")?;
+			}
+			if let Some(path) = rest.get(2) {
+				settings.path = path.clone();
+			}
+			settings
+		}
+		[factor, frames, rest @ ..] if rest.len() <= 1 => {
+			let mut settings = CaptureSettings {
+				factor: factor.parse().context("Expected a number for the factor")?,
+				frames: frames.parse().context("Expected a number of frames")?,
+				..Default::default()
+			};
+			if let Some(path) = rest.first() {
+				settings.path = path.clone();
+			}
+			settings
+		}
+		_ => bail!("Usage: capture [<factor> <frames> [path] | panorama [<width> [<frames> [path]]] | cancel]"),
+	};
+
+	let message = match settings.projection {
+		CaptureProjection::Camera => format!(
+			"Capturing {} frames at {}x to `{}`",
+			settings.frames, settings.factor, settings.path
+		),
+		CaptureProjection::Equirectangular { width } => format!(
+			"Capturing a {}x{} panorama over {} frames to `{}`",
+			width,
+			width / 2,
+			settings.frames,
+			settings.path
+		),
+	};
+	capture.request(settings)?;
+
+	Ok(message)
+}
@@ -0,0 +1,338 @@
+use std::{
+	collections::VecDeque,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc,
+	},
+};
+
+use bevy_ecs::{
+	schedule::IntoSystemConfigs,
+	system::{Res, ResMut},
+	world::World,
+};
+use brainrot::bevy::{self, App, Plugin};
+use log::info;
+use wgpu::{Buffer, COPY_BUFFER_ALIGNMENT};
+
+use super::compute::{self, ComputeRenderer};
+use crate::{
+	core::{gameloop::PreRender, gpu::Gpu},
+	libs::{
+		smart_arc::{Sarc, WeakSarc},
+		texture::Tex,
+	},
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Uploads big buffers and textures a few chunks per frame, see
+/// [`ChunkedUploader`]. Needs to be added before the plugins that stream their
+/// data through it, like the
+/// [`EnvironmentPlugin`](super::environment::EnvironmentPlugin).
+pub struct ChunkedUploadPlugin {
+	/// How many bytes are uploaded per frame at most
+	pub budget: u64,
+	/// How many bytes a single write is at most
+	pub chunk_size: u64,
+}
+
+impl Default for ChunkedUploadPlugin {
+	fn default() -> Self {
+		Self {
+			budget: 16 * 1024 * 1024,
+			chunk_size: 4 * 1024 * 1024,
+		}
+	}
+}
+
+impl Plugin for ChunkedUploadPlugin {
+	fn build(&self, app: &mut App) {
+		app.world
+			.insert_resource(ChunkedUploader::new(self.budget, self.chunk_size));
+
+		app.add_systems(PreRender, (advance_uploads, finish_pending_swaps).chain());
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Writing a 100 MB texture or buffer in one go stalls the frame it happens in,
+/// so the uploads queued here are split into chunks and written over the next
+/// frames instead, at most [`budget`](Self::budget) bytes per frame. They are
+/// written in the order they were queued.
+///
+/// Whatever reads the target shouldn't use it before its [`UploadHandle`] is
+/// done, since it's only partly written until then. The usual way is to keep
+/// using the old resource meanwhile and switch once it's done, e.g. with
+/// [`swap_compute_renderer_when_uploaded`].
+///
+/// The uploads only hold a weak reference to their target, dropping it cancels
+/// the upload.
+#[derive(bevy::Resource)]
+pub struct ChunkedUploader {
+	pub budget: u64,
+	pub chunk_size: u64,
+	uploads: VecDeque<Upload>,
+	/// Waiting for their uploads, the current renderer is kept until then
+	pending_swaps: Vec<(ComputeRenderer, Vec<UploadHandle>)>,
+}
+
+struct Upload {
+	target: UploadTarget,
+	bytes: Vec<u8>,
+	handle: UploadHandle,
+}
+
+enum UploadTarget {
+	Buffer { buffer: WeakSarc<Buffer>, offset: u64 },
+	Texture { tex: WeakSarc<Tex>, mip_level: u32 },
+}
+
+/// How far a queued upload is, can be kept around and checked without the
+/// [`ChunkedUploader`]
+#[derive(Clone, Debug)]
+pub struct UploadHandle {
+	written: Arc<AtomicU64>,
+	total: u64,
+}
+
+impl UploadHandle {
+	pub fn written(&self) -> u64 {
+		self.written.load(Ordering::Acquire)
+	}
+
+	pub fn total(&self) -> u64 {
+		self.total
+	}
+
+	/// From 0 to 1
+	pub fn progress(&self) -> f32 {
+		if self.total == 0 {
+			return 1.0;
+		}
+		self.written() as f32 / self.total as f32
+	}
+
+	/// Once the last chunk is written, what's submitted from then on sees all of
+	/// the data
+	pub fn is_done(&self) -> bool {
+		self.written() >= self.total
+	}
+
+	/// The progress of all of them together, weighted by their size. 1 if there
+	/// are none.
+	pub fn progress_of(handles: &[UploadHandle]) -> f32 {
+		let total = handles.iter().map(|handle| handle.total).sum::<u64>();
+		if total == 0 {
+			return 1.0;
+		}
+		handles.iter().map(|handle| handle.written()).sum::<u64>() as f32 / total as f32
+	}
+
+	pub fn all_done(handles: &[UploadHandle]) -> bool {
+		handles.iter().all(UploadHandle::is_done)
+	}
+
+	fn advance(&self, bytes: u64) {
+		self.written.fetch_add(bytes, Ordering::Release);
+	}
+
+	/// Done without writing the rest, the target is gone anyway
+	fn finish(&self) {
+		self.written.store(self.total, Ordering::Release);
+	}
+}
+
+impl ChunkedUploader {
+	pub fn new(budget: u64, chunk_size: u64) -> Self {
+		Self {
+			budget: budget.max(COPY_BUFFER_ALIGNMENT),
+			chunk_size: chunk_size.max(COPY_BUFFER_ALIGNMENT),
+			uploads: VecDeque::new(),
+			pending_swaps: Vec::new(),
+		}
+	}
+
+	/// Queue `bytes` to be written to `buffer` from `offset` on. Both need to be
+	/// multiples of 4 (`COPY_BUFFER_ALIGNMENT`), and the buffer needs
+	/// `COPY_DST`.
+	pub fn upload_buffer(&mut self, buffer: &Sarc<Buffer>, offset: u64, bytes: Vec<u8>) -> UploadHandle {
+		// Panic to avoid dumb errors in the long run
+		assert!(offset % COPY_BUFFER_ALIGNMENT == 0);
+		assert!(bytes.len() as u64 % COPY_BUFFER_ALIGNMENT == 0);
+		assert!(offset + bytes.len() as u64 <= buffer.size());
+
+		self.queue(
+			UploadTarget::Buffer {
+				buffer: buffer.downgrade(),
+				offset,
+			},
+			bytes,
+		)
+	}
+
+	/// Queue tightly packed texels for the whole of one mip level, same layout
+	/// as [`Tex::upload_texels_mip`]. The texture is written a few rows at a
+	/// time, always at least one row per frame.
+	pub fn upload_texture(&mut self, tex: &Sarc<Tex>, mip_level: u32, bytes: Vec<u8>) -> UploadHandle {
+		let size = tex.mip_size(mip_level);
+		let bytes_per_texel = tex.bytes_per_texel();
+
+		// Panic to avoid dumb errors in the long run
+		assert!(bytes.len() == (size.width * size.height * size.depth_or_array_layers * bytes_per_texel) as usize);
+
+		self.queue(
+			UploadTarget::Texture {
+				tex: tex.downgrade(),
+				mip_level,
+			},
+			bytes,
+		)
+	}
+
+	fn queue(&mut self, target: UploadTarget, bytes: Vec<u8>) -> UploadHandle {
+		let handle = UploadHandle {
+			written: Arc::new(AtomicU64::new(0)),
+			total: bytes.len() as u64,
+		};
+
+		self.uploads.push_back(Upload {
+			target,
+			bytes,
+			handle: handle.clone(),
+		});
+
+		handle
+	}
+
+	pub fn is_idle(&self) -> bool {
+		self.uploads.is_empty()
+	}
+
+	/// Of everything that is queued, from 0 to 1, `None` if nothing is
+	pub fn progress(&self) -> Option<f32> {
+		if self.is_idle() {
+			return None;
+		}
+
+		let (written, total) = self.uploads.iter().fold((0, 0), |(written, total), upload| {
+			(written + upload.handle.written(), total + upload.handle.total)
+		});
+		Some(written as f32 / total.max(1) as f32)
+	}
+
+	/// Write the next chunks, up to the budget. Returns how many bytes were
+	/// written.
+	pub fn advance(&mut self, gpu: &Gpu) -> u64 {
+		let mut spent = 0;
+
+		while spent < self.budget {
+			let Some(upload) = self.uploads.front() else {
+				break;
+			};
+
+			let allowed = self.chunk_size.min(self.budget - spent);
+			spent += match upload.write_chunk(gpu, allowed) {
+				Some(written) => written,
+				// The target was dropped
+				None => {
+					upload.handle.finish();
+					0
+				}
+			};
+
+			if upload.handle.is_done() {
+				self.uploads.pop_front();
+			}
+		}
+
+		spent
+	}
+}
+
+impl Upload {
+	/// Returns how many bytes were written, `None` if the target is gone
+	fn write_chunk(&self, gpu: &Gpu, allowed: u64) -> Option<u64> {
+		let start = self.handle.written();
+		let remaining = self.handle.total - start;
+
+		let written = match &self.target {
+			UploadTarget::Buffer { buffer, offset } => {
+				let buffer = buffer.upgrade()?;
+
+				let length = (allowed / COPY_BUFFER_ALIGNMENT * COPY_BUFFER_ALIGNMENT)
+					.max(COPY_BUFFER_ALIGNMENT)
+					.min(remaining);
+				let chunk = &self.bytes[start as usize..(start + length) as usize];
+				gpu.queue.write_buffer(&buffer, offset + start, chunk);
+
+				length
+			}
+			UploadTarget::Texture { tex, mip_level } => {
+				let tex = tex.upgrade()?;
+
+				let size = tex.mip_size(*mip_level);
+				let bytes_per_row = (size.width * tex.bytes_per_texel()) as u64;
+
+				// Whole rows, and within one layer (or slice)
+				let first_row = (start / bytes_per_row) as u32;
+				let (layer, row) = (first_row / size.height, first_row % size.height);
+				let rows = ((allowed / bytes_per_row) as u32).clamp(1, size.height - row);
+
+				let length = rows as u64 * bytes_per_row;
+				let chunk = &self.bytes[start as usize..(start + length) as usize];
+				tex.upload_rows(gpu, *mip_level, layer, row, chunk);
+
+				length
+			}
+		};
+
+		self.handle.advance(written);
+		Some(written)
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Swap the [`ComputeRenderer`] for `renderer` once all of the `uploads` are
+/// done, e.g. for a renderer whose mesh is being streamed. The current renderer
+/// keeps rendering until then.
+pub fn swap_compute_renderer_when_uploaded(world: &mut World, renderer: ComputeRenderer, uploads: Vec<UploadHandle>) {
+	world
+		.resource_mut::<ChunkedUploader>()
+		.pending_swaps
+		.push((renderer, uploads));
+}
+
+fn advance_uploads(mut uploader: ResMut<ChunkedUploader>, gpu: Res<Gpu>) {
+	uploader.advance(&gpu);
+}
+
+fn finish_pending_swaps(world: &mut World) {
+	let mut uploader = world.resource_mut::<ChunkedUploader>();
+	let Some(index) = uploader
+		.pending_swaps
+		.iter()
+		.position(|(_, uploads)| UploadHandle::all_done(uploads))
+	else {
+		return;
+	};
+
+	// One per frame is plenty, the next ones get their turn on the next frames
+	let (renderer, _) = uploader.pending_swaps.remove(index);
+	info!("The uploads are done, swapping the compute renderer");
+	compute::swap_compute_renderer(world, renderer);
+}
@@ -0,0 +1,406 @@
+use std::path::Path;
+
+use anyhow::{bail, ensure, Context, Result};
+use bevy_ecs::{
+	event::EventReader,
+	system::{Query, Res, ResMut},
+	world::{Mut, World},
+};
+use brainrot::{
+	bevy::{self, App, Plugin},
+	vek::{Extent3, Rgb, Vec3},
+};
+use image::DynamicImage;
+use log::{error, info};
+use pbr_tracer_derive::ShaderStruct;
+use wgpu::{Buffer, FilterMode, TextureAspect, TextureFormat};
+use winit::event::WindowEvent;
+
+use crate::{
+	core::{console, events::WinitWindowEvent, gameloop::Update, gpu::Gpu, params},
+	libs::{
+		buffer::{self, uniform_buffer::UniformBuffer, ShaderType},
+		embed::Assets,
+		lut::{self, CubeLut, LutDimensions},
+		smart_arc::Sarc,
+		texture::{self, SamplerEdges, Tex, TexDescriptor, TexSamplerDescriptor, TextureAssetDimensions},
+	},
+	TextureAssets,
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Color grading with `.cube` LUTs or 3D LUT strips in PNGs, applied by the
+/// [`ColorGrade`](crate::fragments::post_processing::ColorGrade) effect, see
+/// [`ColorGradeLut`].
+///
+/// A LUT is loaded with the `lut` console command, or by dropping a `.cube`
+/// file on the window (the dropped images are environments). The built-in
+/// strips of `assets/luts` are loaded by name, e.g. `lut teal_orange`. How
+/// much of the LUT is applied is the `color_grade.intensity` param. Has to be
+/// added before the renderer is made, so that its post processing can sample
+/// the LUT.
+pub struct ColorGradePlugin {
+	/// How much of the graded colors replace the original ones, from 0 to 1
+	pub intensity: f32,
+}
+
+impl Default for ColorGradePlugin {
+	fn default() -> Self {
+		Self { intensity: 1.0 }
+	}
+}
+
+impl Plugin for ColorGradePlugin {
+	fn build(&self, app: &mut App) {
+		let settings = ColorGradeSettings {
+			intensity: self.intensity,
+			..Default::default()
+		};
+
+		let gpu = app.world.resource::<Gpu>();
+		let color_grade_lut = ColorGradeLut::new(gpu, &settings);
+
+		buffer::spawn_buffer(app, settings, color_grade_lut.settings_buffer.clone());
+		app.world.insert_resource(color_grade_lut);
+
+		console::register_command(
+			app,
+			"lut",
+			"lut [path | built-in name | off]: Grade the colors with a .cube or .png LUT, stop grading them, or show which LUT is loaded",
+			lut,
+		);
+
+		params::registry(app).register_float(
+			"color_grade.intensity",
+			"How much of the colors graded by the LUT replace the original ones",
+			0.0..=1.0,
+			|world| Ok(color_grade_settings(world)?.intensity),
+			|world, intensity| {
+				color_grade_settings(world)?.intensity = intensity;
+				Ok(())
+			},
+		);
+
+		app.add_systems(Update, load_dropped_luts);
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// The LUT that the colors are graded with. The texture always has the
+/// largest size and only its first `size` texels per axis are used, so that
+/// swapping LUTs doesn't need the shaders to be rebuilt.
+///
+/// A 1D LUT is stored along the x axis, with its three curves in the color
+/// channels.
+#[derive(bevy::Resource, Clone)]
+pub struct ColorGradeLut {
+	pub texture: Sarc<Tex>,
+	/// Holds the [`ColorGradeSettings`]
+	pub settings_buffer: Sarc<Buffer>,
+	/// The name of the LUT in use, if any
+	pub name: Option<String>,
+}
+
+impl ColorGradeLut {
+	/// The most entries per axis, 3D LUTs can't be bigger and 1D ones are
+	/// resampled down to it
+	pub const MAX_SIZE: u32 = 65;
+	/// Filterable, unlike Rgba32Float, and keeps the values outside of [0; 1]
+	pub const FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+	pub fn new(gpu: &Gpu, settings: &ColorGradeSettings) -> Self {
+		let texture = Tex::create(
+			gpu,
+			TexDescriptor {
+				label: "Color grading LUT",
+				dimensions: TextureAssetDimensions::D3(Extent3::broadcast(Self::MAX_SIZE)),
+				format: Self::FORMAT,
+				usage: None,
+				aspect: TextureAspect::All,
+			},
+			Some(TexSamplerDescriptor {
+				filter: FilterMode::Linear,
+				edges: SamplerEdges::ClampToEdge,
+				compare: None,
+			}),
+		);
+
+		Self {
+			texture: Sarc::new(texture),
+			settings_buffer: Sarc::new(UniformBuffer::raw_buffer_from_data(gpu, settings, None)),
+			name: None,
+		}
+	}
+
+	/// Upload a LUT and point the settings at it. The texture is written right
+	/// away, the settings are uploaded before the next render.
+	pub fn set(&mut self, gpu: &Gpu, name: String, lut: &CubeLut, settings: &mut ColorGradeSettings) -> Result<()> {
+		let (table, size) = match lut.dimensions {
+			LutDimensions::D3 => {
+				ensure!(
+					lut.size <= Self::MAX_SIZE,
+					"3D LUTs can't be bigger than {}³, this one is {}³",
+					Self::MAX_SIZE,
+					lut.size
+				);
+				(lut.table.clone(), lut.size)
+			}
+			LutDimensions::D1 if lut.size > Self::MAX_SIZE => (resample_1d(&lut.table, Self::MAX_SIZE), Self::MAX_SIZE),
+			LutDimensions::D1 => (lut.table.clone(), lut.size),
+		};
+
+		self.texture
+			.upload_texels(gpu, &lut_texels(&table, lut.dimensions, size));
+
+		settings.domain_min = lut.domain_min;
+		settings.domain_max = lut.domain_max;
+		settings.size = size;
+		settings.is_1d = (lut.dimensions == LutDimensions::D1) as u32;
+
+		info!("Grading the colors with the LUT `{}`", name);
+		self.name = Some(name);
+
+		Ok(())
+	}
+
+	/// Upload a LUT strip (see [`lut::strip_layers`]) one depth layer at a time
+	/// and point the settings at it, same as [`set`](Self::set)
+	pub fn set_strip(
+		&mut self,
+		gpu: &Gpu,
+		name: String,
+		image: &DynamicImage,
+		settings: &mut ColorGradeSettings,
+	) -> Result<()> {
+		let layers = lut::strip_layers(image)?;
+		let size = layers.len() as u32;
+		ensure!(
+			size <= Self::MAX_SIZE,
+			"3D LUTs can't be bigger than {}³, this one is {}³",
+			Self::MAX_SIZE,
+			size
+		);
+
+		// Only the corner of every layer is written, and only the first layers
+		for (b, layer) in layers.iter().enumerate() {
+			self.texture.upload_image_layer(gpu, layer, b as u32);
+		}
+
+		settings.domain_min = Vec3::zero();
+		settings.domain_max = Vec3::one();
+		settings.size = size;
+		settings.is_1d = 0;
+
+		info!("Grading the colors with the LUT `{}`", name);
+		self.name = Some(name);
+
+		Ok(())
+	}
+
+	/// Stop grading the colors, the texture is left as it is
+	pub fn clear(&mut self, settings: &mut ColorGradeSettings) {
+		settings.size = 0;
+		self.name = None;
+	}
+
+	/// Load a `.cube` file and [`set`](Self::set) it, or a `.png` strip and
+	/// [`set_strip`](Self::set_strip) it
+	pub fn load_file(&mut self, gpu: &Gpu, path: &Path, settings: &mut ColorGradeSettings) -> Result<()> {
+		let file_name = path
+			.file_name()
+			.map(|name| name.to_string_lossy().into_owned())
+			.unwrap_or_else(|| path.display().to_string());
+
+		if has_extension(path, "png") {
+			let image = image::open(path).with_context(|| format!("Couldn't read {}", path.display()))?;
+			return self
+				.set_strip(gpu, file_name, &image, settings)
+				.with_context(|| format!("Couldn't use {}", path.display()));
+		}
+
+		let lut = CubeLut::load(path)?;
+		let name = lut.title.clone().unwrap_or(file_name);
+
+		self.set(gpu, name, &lut, settings)
+	}
+
+	/// The names of the LUT strips in `assets/luts`, for
+	/// [`load_builtin`](Self::load_builtin)
+	pub fn builtin_names() -> Vec<String> {
+		TextureAssets
+			.iter_paths("png")
+			.into_iter()
+			.filter_map(|path| Some(path.strip_prefix("luts/")?.strip_suffix(".png")?.to_owned()))
+			.collect()
+	}
+
+	/// [`set_strip`](Self::set_strip) one of the LUTs in `assets/luts`, by the
+	/// name of its file without the extension
+	pub fn load_builtin(&mut self, gpu: &Gpu, name: &str, settings: &mut ColorGradeSettings) -> Result<()> {
+		let file = TextureAssets
+			.get(&format!("luts/{}.png", name))
+			.with_context(|| format!("There's no built-in LUT `{}`", name))?;
+		let image = image::load_from_memory(&file.data).context("Couldn't decode the LUT")?;
+
+		self.set_strip(gpu, name.to_owned(), &image, settings)
+	}
+}
+
+/// The whole texture, with the table in its corner and the rest black
+fn lut_texels(table: &[Rgb<f32>], dimensions: LutDimensions, size: u32) -> Vec<u8> {
+	let max = ColorGradeLut::MAX_SIZE;
+	let mut texels = vec![Rgb::<f32>::zero(); max.pow(3) as usize];
+
+	match dimensions {
+		LutDimensions::D1 => texels[..table.len()].copy_from_slice(table),
+		LutDimensions::D3 => {
+			for b in 0..size {
+				for g in 0..size {
+					let from = ((g + size * b) * size) as usize;
+					let to = ((g + max * b) * max) as usize;
+					texels[to..to + size as usize].copy_from_slice(&table[from..from + size as usize]);
+				}
+			}
+		}
+	}
+
+	texels
+		.into_iter()
+		.flat_map(|rgb| [rgb.r, rgb.g, rgb.b, 1.0].map(texture::f32_to_f16_bits))
+		.flat_map(u16::to_ne_bytes)
+		.collect()
+}
+
+/// Linearly resample the curves of a 1D LUT to `size` entries
+fn resample_1d(table: &[Rgb<f32>], size: u32) -> Vec<Rgb<f32>> {
+	(0..size)
+		.map(|i| {
+			let position = i as f32 / (size - 1) as f32 * (table.len() - 1) as f32;
+			let index = (position as usize).min(table.len() - 2);
+			let t = position - index as f32;
+			table[index] * (1.0 - t) + table[index + 1] * t
+		})
+		.collect()
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// The `color_grade` uniform. Changing this component changes the uniform,
+/// the [`ColorGradeLut`] keeps the LUT-related fields up to date.
+#[repr(C)]
+#[derive(ShaderStruct, bevy::Component, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+pub struct ColorGradeSettings {
+	/// The input range of the LUT, mapped to [0; 1] before the lookup
+	pub domain_min: Vec3<f32>,
+	/// How much of the graded colors replace the original ones, from 0 to 1
+	pub intensity: f32,
+	pub domain_max: Vec3<f32>,
+	/// How many entries per axis the LUT has, 0 when there's none
+	pub size: u32,
+	/// Whether the LUT is three curves instead of a cube. 0 or 1.
+	pub is_1d: u32,
+	#[shader(skip)]
+	_padding: [u32; 3],
+}
+
+impl Default for ColorGradeSettings {
+	fn default() -> Self {
+		Self {
+			domain_min: Vec3::zero(),
+			intensity: 1.0,
+			domain_max: Vec3::one(),
+			size: 0,
+			is_1d: 0,
+			_padding: [0; 3],
+		}
+	}
+}
+
+fn color_grade_settings(world: &mut World) -> Result<Mut<'_, ColorGradeSettings>> {
+	world
+		.query::<&mut ColorGradeSettings>()
+		.get_single_mut(world)
+		.context("The color grading isn't tweakable")
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+	path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+}
+
+fn load_dropped_luts(
+	mut lut: ResMut<ColorGradeLut>,
+	mut settings: Query<&mut ColorGradeSettings>,
+	mut winit_events: EventReader<WinitWindowEvent>,
+	gpu: Res<Gpu>,
+) {
+	for WinitWindowEvent(event) in winit_events.read() {
+		if let WindowEvent::DroppedFile(path) = event {
+			if !has_extension(path, "cube") {
+				continue;
+			}
+
+			let Ok(mut settings) = settings.get_single_mut() else {
+				continue;
+			};
+
+			if let Err(e) = lut.load_file(&gpu, path, &mut settings) {
+				error!("Couldn't load the LUT: {:#}", e);
+			}
+		}
+	}
+}
+
+fn lut(world: &mut World, args: &[String]) -> Result<String> {
+	match args {
+		[] => Ok(match &world.resource::<ColorGradeLut>().name {
+			Some(name) => format!("Grading with `{}`", name),
+			None => "No LUT is loaded".to_owned(),
+		}),
+		[off] if off == "off" => {
+			let mut settings = *color_grade_settings(world)?;
+			world.resource_mut::<ColorGradeLut>().clear(&mut settings);
+			*color_grade_settings(world)? = settings;
+			Ok("Stopped grading the colors".to_owned())
+		}
+		[path] => {
+			// A file takes precedence over a built-in LUT of the same name
+			let builtin = !Path::new(path).exists() && ColorGradeLut::builtin_names().contains(path);
+
+			let mut settings = *color_grade_settings(world)?;
+			world.resource_scope(|world, mut lut: Mut<ColorGradeLut>| {
+				let gpu = world.resource::<Gpu>();
+				if builtin {
+					lut.load_builtin(gpu, path, &mut settings)
+				} else {
+					lut.load_file(gpu, Path::new(path), &mut settings)
+				}
+			})?;
+			*color_grade_settings(world)? = settings;
+			Ok(format!("Loaded `{}`", path))
+		}
+		_ => bail!(
+			"Usage: lut [path | built-in name | off], the built-in LUTs are {}",
+			ColorGradeLut::builtin_names().join(", ")
+		),
+	}
+}
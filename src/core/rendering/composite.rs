@@ -1,40 +1,59 @@
+use std::fmt;
+
+use anyhow::{bail, Context, Result};
 use bevy_ecs::{
 	event::EventReader,
+	query::With,
 	schedule::IntoSystemConfigs,
 	system::{Query, Res, ResMut},
+	world::World,
 };
 use brainrot::{
 	bevy::{self, App, Plugin},
-	vek::Vec2,
-	ScreenSize,
+	vec2,
+	vek::{Extent2, Vec2},
 };
+use log::info;
 use pbr_tracer_derive::ShaderStruct;
 use velcro::vec;
 use wgpu::{
-	BlendState, Buffer, Color, ColorTargetState, ColorWrites, CommandEncoderDescriptor, FragmentState, FrontFace,
-	LoadOp, MultisampleState, Operations, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology,
-	RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, ShaderStages, StoreOp,
-	VertexState,
+	BlendState, Buffer, Color, ColorTargetState, ColorWrites, CommandEncoder, CommandEncoderDescriptor,
+	ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, FilterMode, FragmentState, FrontFace, LoadOp,
+	MultisampleState, Operations, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology,
+	RenderPassColorAttachment, RenderPassDescriptor, RenderPassTimestampWrites, RenderPipeline,
+	RenderPipelineDescriptor, ShaderStages, StorageTextureAccess, StoreOp, TextureAspect, TextureFormat, TextureUsages,
+	TextureView, VertexState,
 };
 
-use super::compute::ComputeRenderer;
+use super::{
+	camera_view::CameraView,
+	compute::{ComputeRenderer, RendererSwapHooks},
+	gpu_timers::GpuTimers,
+};
 use crate::{
 	core::{
+		camera::ActiveCamera,
+		console::{self, is_console_closed},
 		event_processing::{EventReaderProcessor, ProcessedChangeEvents},
-		events::WindowResizedEvent,
+		events::{KeyboardInputEvent, WindowResizedEvent},
 		gameloop::{Render, Update},
 		gpu::Gpu,
+		key_bindings::{Action, KeyBindings},
 		render_target::RenderTarget,
+		size::WindowSize,
 	},
 	libs::{
 		buffer::{
 			self,
+			ping_pong_texture::PingPongParity,
 			sampled_texture_buffer::SampledTexture,
+			storage_texture_buffer::StorageTexture,
 			uniform_buffer::{UniformBuffer, UniformBufferDescriptor},
 			BufferMappingApplicable, ShaderType,
 		},
-		shader::{CompiledShader, ShaderBuilder},
+		shader::{CompiledShader, Shader, ShaderBuilder},
 		smart_arc::Sarc,
+		texture::{SamplerEdges, Tex, TexDescriptor, TexSamplerDescriptor, TextureAssetDimensions},
 	},
 	ShaderAssets,
 };
@@ -45,9 +64,26 @@ use crate::{
 --------------------------------------------------------------------------------
 */
 
-pub struct CompositeRendererPlugin;
+pub struct CompositeRendererPlugin<C = DefaultComposite>
+where
+	C: CompositeFragment,
+{
+	/// What the render looks like on the window, see [`CompositeFragment`]
+	pub composite: C,
+}
+
+impl Default for CompositeRendererPlugin {
+	fn default() -> Self {
+		Self {
+			composite: DefaultComposite,
+		}
+	}
+}
 
-impl Plugin for CompositeRendererPlugin {
+impl<C> Plugin for CompositeRendererPlugin<C>
+where
+	C: CompositeFragment + 'static,
+{
 	fn build(&self, app: &mut App) {
 		let gpu = app.world.resource::<Gpu>();
 		let render_target = app.world.resource::<RenderTarget>();
@@ -58,12 +94,63 @@ impl Plugin for CompositeRendererPlugin {
 		};
 		let viewport_buffer = Sarc::new(UniformBuffer::raw_buffer_from_data(gpu, &viewport_info, None));
 
-		let composite_renderer = CompositeRenderer::new(gpu, render_target, computer_renderer, viewport_buffer.clone());
+		let upscaler_params = UpscalerParams::default();
+		let upscaler_buffer = Sarc::new(UniformBuffer::raw_buffer_from_data(gpu, &upscaler_params, None));
+
+		let debug_view_params = DebugViewParams::default();
+		let debug_view_buffer = Sarc::new(UniformBuffer::raw_buffer_from_data(gpu, &debug_view_params, None));
+
+		let composite_renderer = CompositeRenderer::new(
+			gpu,
+			render_target,
+			computer_renderer,
+			CompositeBuffers {
+				viewport: viewport_buffer.clone(),
+				upscaler: upscaler_buffer.clone(),
+				debug_view: debug_view_buffer.clone(),
+			},
+			&self.composite,
+		);
 
 		buffer::spawn_buffer(app, viewport_info, viewport_buffer);
+		buffer::spawn_buffer(app, upscaler_params, upscaler_buffer);
+		buffer::spawn_buffer(app, debug_view_params, debug_view_buffer);
 		app.world.insert_resource(composite_renderer);
+		app.world.insert_resource(Upscaler::default());
+		app.world.insert_resource(DebugView::default());
+
+		// A new compute renderer comes with new output textures
+		app.world
+			.resource_mut::<RendererSwapHooks>()
+			.after(rebind_output_texture);
+
+		console::register_command(
+			app,
+			"upscaler",
+			"upscaler [bilinear | lanczos3 | fsr] [sharpness]: Show or change how the render is scaled to the window, \
+			 the sharpness is in stops (0 is the sharpest)",
+			upscaler,
+		);
+		console::register_command(
+			app,
+			"debug_view",
+			"debug_view [color | normal | depth | heatmap | steps]: Show or change which of the renderer's outputs is \
+			 drawn",
+			debug_view,
+		);
 
-		app.add_systems(Update, resize);
+		app.add_systems(
+			Update,
+			(
+				resize,
+				resize_upscaler,
+				cycle_upscaler.run_if(is_console_closed),
+				sync_upscaler_params,
+				cycle_debug_view.run_if(is_console_closed),
+				sync_debug_view_params,
+			)
+				.chain(),
+		);
 		app.add_systems(Render, (render).in_set(CompositeRenderPass).chain());
 	}
 }
@@ -71,6 +158,42 @@ impl Plugin for CompositeRendererPlugin {
 #[derive(bevy::SystemSet, Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
 pub struct CompositeRenderPass;
 
+/// Shader API:\
+/// `fn composite(uv: vec2f) -> vec4f`
+///
+/// The last step of the frame, drawing the compute renderer's output to the
+/// window, e.g. to apply a LUT or frame the picture. Called for every pixel of
+/// the window, `uv` is where it lands in the output (from the top left, the
+/// output covers the window and the overflow is cropped). Returns the linear
+/// color, the window's sRGB encodes it.
+///
+/// Not called while a [`DebugView`] other than the color is shown.
+///
+/// The renderer adds the vertex stage and these bindings, which the fragment
+/// can use as they are:
+/// - `out_texture` and `out_sampler`, the output of the compute renderer
+/// - `viewport_size`, the window size in pixels
+/// - `upscaler`, the [`UpscalerParams`]
+/// - `composite_pixel`, the window pixel being drawn
+/// - `composite_upscaled(uv)`, the output scaled by the current [`Upscaler`].
+///   It samples with implicit derivatives, so it needs uniform control flow,
+///   i.e. it can't be called from within a branch.
+///
+/// Its own bindings (e.g. the LUT) are included with the shader as usual.
+pub trait CompositeFragment {
+	fn shader(&self) -> Shader;
+}
+
+/// The output as it is, through the [`Upscaler`]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct DefaultComposite;
+
+impl CompositeFragment for DefaultComposite {
+	fn shader(&self) -> Shader {
+		ShaderBuilder::new().include_path("composite/default.wgsl").into()
+	}
+}
+
 /*
 --------------------------------------------------------------------------------
 ||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
@@ -80,38 +203,466 @@ pub struct CompositeRenderPass;
 #[repr(C)]
 #[derive(ShaderStruct, bevy::Component, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug)]
 pub struct ViewportInfo {
-	pub size: ScreenSize,
+	pub size: WindowSize,
+}
+
+/// How the compute renderer's output is scaled to the window when their sizes
+/// differ. Switched with [`Action::CycleUpscaler`] or the `upscaler` console
+/// command.
+#[derive(bevy::Resource, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Upscaler {
+	/// Whatever the output's sampler does
+	#[default]
+	Bilinear,
+	/// A 6x6 lanczos kernel in the composite shader, sharper but can ring
+	Lanczos3,
+	/// An edge adaptive upscale and a sharpening pass, both compute passes into
+	/// window sized textures, in the spirit of FSR1's EASU and RCAS
+	Fsr,
+}
+
+impl Upscaler {
+	pub const ALL: [Self; 3] = [Self::Bilinear, Self::Lanczos3, Self::Fsr];
+
+	pub fn next(self) -> Self {
+		let index = Self::ALL.iter().position(|upscaler| *upscaler == self).unwrap();
+		Self::ALL[(index + 1) % Self::ALL.len()]
+	}
+
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Bilinear => "bilinear",
+			Self::Lanczos3 => "lanczos3",
+			Self::Fsr => "fsr",
+		}
+	}
+}
+
+impl fmt::Display for Upscaler {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(self.name())
+	}
+}
+
+/// The upscaler uniform, bound as `upscaler` in the composite and FSR shaders
+#[repr(C)]
+#[derive(ShaderStruct, bevy::Component, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+pub struct UpscalerParams {
+	/// The index of the [`Upscaler`], kept in sync with the resource
+	pub mode: u32,
+	/// How much the FSR sharpening holds back, in stops. 0 is the sharpest.
+	pub sharpness: f32,
+}
+
+impl Default for UpscalerParams {
+	fn default() -> Self {
+		Self {
+			mode: Upscaler::default() as u32,
+			sharpness: 0.2,
+		}
+	}
+}
+
+/// Which of the compute renderer's outputs the composite draws, to debug what
+/// goes into the passes that use them (e.g. the denoiser). Switched with
+/// [`Action::CycleDebugView`] or the `debug_view` console command.
+///
+/// Only the color is drawn through the [`CompositeFragment`] and the
+/// [`Upscaler`]. The other views need the renderer to have the matching
+/// output, see [`CompositeRenderer::supports`].
+#[derive(bevy::Resource, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DebugView {
+	/// The render as usual
+	#[default]
+	Color,
+	/// The `output_normal`, as `0.5 * n + 0.5`
+	Normal,
+	/// The `output_depth`, logarithmically from the near to the far plane of
+	/// the camera, black to white
+	Depth,
+	/// The luminance of the drawn color in false colors, from blue at 1/64 to
+	/// red at 64
+	Heatmap,
+	/// The `output_debug` in false colors, from blue at 0 to red at 1, e.g. the
+	/// steps of a [`Raymarcher`](crate::fragments::intersector::Raymarcher) with
+	/// the step heatmap
+	Steps,
+}
+
+impl DebugView {
+	pub const ALL: [Self; 5] = [Self::Color, Self::Normal, Self::Depth, Self::Heatmap, Self::Steps];
+
+	pub fn next(self) -> Self {
+		let index = Self::ALL.iter().position(|view| *view == self).unwrap();
+		Self::ALL[(index + 1) % Self::ALL.len()]
+	}
+
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Color => "color",
+			Self::Normal => "normal",
+			Self::Depth => "depth",
+			Self::Heatmap => "heatmap",
+			Self::Steps => "steps",
+		}
+	}
+}
+
+impl fmt::Display for DebugView {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(self.name())
+	}
+}
+
+/// The debug view uniform, bound as `debug_view` in the composite shader
+#[repr(C)]
+#[derive(ShaderStruct, bevy::Component, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+pub struct DebugViewParams {
+	/// The index of the [`DebugView`], kept in sync with the resource
+	pub mode: u32,
+	/// The planes of the active camera, to remap the depth
+	pub z_near: f32,
+	pub z_far: f32,
+	#[shader(skip)]
+	_padding: u32,
+}
+
+impl Default for DebugViewParams {
+	fn default() -> Self {
+		Self {
+			mode: DebugView::default() as u32,
+			z_near: 0.1,
+			z_far: 1000.0,
+			_padding: 0,
+		}
+	}
 }
 
 #[derive(bevy::Resource)]
 pub struct CompositeRenderer {
 	pipeline: RenderPipeline,
 	shader: CompiledShader,
+	fsr: FsrPasses,
+	/// Everything needed to build the renderer again at another window size
+	source: CompositeRendererSource,
+}
+
+#[derive(Clone)]
+struct CompositeRendererSource {
+	output_texture: Sarc<Tex>,
+	debug_textures: DebugTextures,
+	buffers: CompositeBuffers,
+	format: TextureFormat,
+	composite: Shader,
+}
+
+/// The other outputs of the compute renderer that the [`DebugView`]s show, if
+/// it has them
+#[derive(Clone)]
+struct DebugTextures {
+	normal: Option<Sarc<Tex>>,
+	depth: Option<Sarc<Tex>>,
+	debug: Option<Sarc<Tex>>,
+}
+
+/// The uniforms of the [`CompositeRenderer`], spawned by the plugin
+#[derive(Clone)]
+pub struct CompositeBuffers {
+	/// Holds the [`ViewportInfo`]
+	pub viewport: Sarc<Buffer>,
+	/// Holds the [`UpscalerParams`]
+	pub upscaler: Sarc<Buffer>,
+	/// Holds the [`DebugViewParams`]
+	pub debug_view: Sarc<Buffer>,
+}
+
+/// The two compute passes of [`Upscaler::Fsr`]. They go through window sized
+/// textures, which is why the renderer is rebuilt when the window is resized.
+struct FsrPasses {
+	easu: (CompiledShader, ComputePipeline),
+	rcas: (CompiledShader, ComputePipeline),
+	workgroups: Vec2<u32>,
+	output: Sarc<Tex>,
+}
+
+impl FsrPasses {
+	const WORKGROUP_SIZE: u32 = 8;
+
+	fn new(gpu: &Gpu, window_size: WindowSize, source: &CompositeRendererSource) -> Self {
+		// Minimized windows are 0x0
+		let size = window_size.0.map(|x| x.max(1));
+
+		let texture = |label| {
+			Tex::create(
+				gpu,
+				TexDescriptor {
+					label,
+					dimensions: TextureAssetDimensions::D2(size),
+					format: TextureFormat::Rgba16Float,
+					usage: Some(TextureUsages::STORAGE_BINDING),
+					aspect: TextureAspect::All,
+				},
+				Some(TexSamplerDescriptor {
+					filter: FilterMode::Nearest,
+					edges: SamplerEdges::ClampToEdge,
+					compare: None,
+				}),
+			)
+			.tracked(gpu)
+		};
+
+		let intermediate = texture("Upscale intermediate");
+		let output = texture("Upscale output");
+
+		let easu = Self::build_pass(
+			gpu,
+			"EASU",
+			ShaderBuilder::new()
+				.include_path("composite/easu.wgsl")
+				.include_buffer(SampledTexture::FromTex {
+					texture_var_name: "out_texture",
+					sampler_var_name: "out_sampler",
+					tex: source.output_texture.clone(),
+				})
+				.include_buffer(StorageTexture::FromTex {
+					var_name: "upscale_intermediate",
+					access: StorageTextureAccess::WriteOnly,
+					tex: intermediate.clone(),
+				}),
+		);
+
+		let rcas = Self::build_pass(
+			gpu,
+			"RCAS",
+			ShaderBuilder::new()
+				.include_path("composite/rcas.wgsl")
+				.include_buffer(SampledTexture::FromTex {
+					texture_var_name: "upscale_intermediate",
+					sampler_var_name: "upscale_sampler",
+					tex: intermediate,
+				})
+				.include_buffer(StorageTexture::FromTex {
+					var_name: "upscale_output",
+					access: StorageTextureAccess::WriteOnly,
+					tex: output.clone(),
+				})
+				.include_buffer(UniformBufferDescriptor::FromBuffer::<UpscalerParams, _> {
+					var_name: "upscaler",
+					buffer: source.buffers.upscaler.clone(),
+				}),
+		);
+
+		Self {
+			easu,
+			rcas,
+			workgroups: <Vec2<u32>>::from(size) / Self::WORKGROUP_SIZE + vec2!(1),
+			output,
+		}
+	}
+
+	fn build_pass(gpu: &Gpu, name: &str, shader: &mut ShaderBuilder) -> (CompiledShader, ComputePipeline) {
+		let shader = shader
+			.define("WORKGROUP_SIZE", format!("{}", Self::WORKGROUP_SIZE))
+			.build(gpu, format!("{} Shader", name), &ShaderAssets, ShaderStages::COMPUTE, 0)
+			.expect("Couldn't build shader");
+
+		let pipeline_layout = gpu.device.create_pipeline_layout(&PipelineLayoutDescriptor {
+			label: Some(&format!("{} Pipeline Layout", name)),
+			bind_group_layouts: &shader.layouts(),
+			push_constant_ranges: &[],
+		});
+
+		let pipeline = shader.create_pipeline(gpu, || {
+			gpu.device.create_compute_pipeline(&ComputePipelineDescriptor {
+				label: Some(&format!("{} Pipeline", name)),
+				layout: Some(&pipeline_layout),
+				module: &shader.shader_module,
+				entry_point: "main",
+			})
+		});
+
+		(shader, pipeline)
+	}
 }
 
 impl CompositeRenderer {
+	/// Draws the first of the compute renderer's output textures, and binds its
+	/// `output_normal`, `output_depth` and `output_debug` for the [`DebugView`]s
 	pub fn new(
 		gpu: &Gpu,
 		render_target: &RenderTarget,
 		compute_renderer: &ComputeRenderer,
-		viewport_buffer: Sarc<Buffer>,
+		buffers: CompositeBuffers,
+		composite: &dyn CompositeFragment,
 	) -> Self {
-		let output_texture = compute_renderer
-			.output_textures
-			.first()
-			.expect("Compute renderer needs at least 1 output texture")
-			.clone();
+		let (output_texture, debug_textures) = renderer_outputs(compute_renderer);
+
+		let source = CompositeRendererSource {
+			output_texture,
+			debug_textures,
+			buffers,
+			format: render_target.config.format,
+			composite: composite.shader(),
+		};
+
+		Self::build(gpu, render_target.size, source)
+	}
+
+	/// The same renderer, with the FSR textures at the new window size
+	pub fn resized(&self, gpu: &Gpu, window_size: WindowSize) -> Self {
+		Self::build(gpu, window_size, self.source.clone())
+	}
+
+	/// The same renderer, with the outputs of another compute renderer
+	pub fn with_renderer(&self, gpu: &Gpu, window_size: WindowSize, compute_renderer: &ComputeRenderer) -> Self {
+		let mut source = self.source.clone();
+		(source.output_texture, source.debug_textures) = renderer_outputs(compute_renderer);
+		Self::build(gpu, window_size, source)
+	}
+
+	/// The same renderer, compositing another output texture. The
+	/// [`DebugView`]s other than the color still show the renderer's outputs.
+	pub fn with_output_texture(&self, gpu: &Gpu, window_size: WindowSize, output_texture: Sarc<Tex>) -> Self {
+		let mut source = self.source.clone();
+		source.output_texture = output_texture;
+		Self::build(gpu, window_size, source)
+	}
+
+	/// The same renderer, with another [`CompositeFragment`]
+	pub fn with_composite(&self, gpu: &Gpu, window_size: WindowSize, composite: &dyn CompositeFragment) -> Self {
+		let mut source = self.source.clone();
+		source.composite = composite.shader();
+		Self::build(gpu, window_size, source)
+	}
+
+	/// The texture it draws, see [`with_output_texture`](Self::with_output_texture)
+	pub fn output_texture(&self) -> &Sarc<Tex> {
+		&self.source.output_texture
+	}
+
+	/// The format of the texture it draws to, the window's
+	pub fn format(&self) -> TextureFormat {
+		self.source.format
+	}
+
+	/// Whether the compute renderer has the output the view shows
+	pub fn supports(&self, view: DebugView) -> bool {
+		match view {
+			DebugView::Color | DebugView::Heatmap => true,
+			DebugView::Normal => self.source.debug_textures.normal.is_some(),
+			DebugView::Depth => self.source.debug_textures.depth.is_some(),
+			DebugView::Steps => self.source.debug_textures.debug.is_some(),
+		}
+	}
+
+	/// Record the upscaling passes if there are any, then draw to `view`, which
+	/// needs to have the renderer's [`format`](Self::format). The
+	/// [`CompositeRenderPass`] draws to the window with it.
+	pub fn encode(
+		&self,
+		encoder: &mut CommandEncoder,
+		view: &TextureView,
+		upscaler: Upscaler,
+		parity: PingPongParity,
+		timestamp_writes: Option<RenderPassTimestampWrites>,
+	) {
+		if upscaler == Upscaler::Fsr {
+			let fsr = &self.fsr;
+
+			// Not timed, it doesn't run every frame
+			let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+				label: Some("Upscale Pass"),
+				timestamp_writes: None,
+			});
+
+			for (shader, pipeline) in [&fsr.easu, &fsr.rcas] {
+				compute_pass.set_pipeline(pipeline);
+				compute_pass.apply_buffer_mapping(&shader.binding, parity);
+				compute_pass.dispatch_workgroups(fsr.workgroups.x, fsr.workgroups.y, 1);
+			}
+		}
+
+		// A render pass records a single pass of a pipeline
+		let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+			label: Some("CompositeRenderer Render Pass"),
+			color_attachments: &[Some(RenderPassColorAttachment {
+				view,
+				resolve_target: None,
+				ops: Operations {
+					load: LoadOp::Clear(Color {
+						r: 0.0,
+						g: 0.0,
+						b: 0.0,
+						a: 1.0,
+					}),
+					store: StoreOp::Store,
+				},
+			})],
+			depth_stencil_attachment: None,
+			occlusion_query_set: None,
+			timestamp_writes,
+		});
+
+		render_pass.set_pipeline(&self.pipeline);
+
+		render_pass.apply_buffer_mapping(&self.shader.binding, parity);
+
+		// Draw 2 fullscreen triangles
+		// 2 - 3
+		// | \ |
+		// 0 - 1
+		render_pass.draw(0..4, 0..1);
+	}
+
+	fn build(gpu: &Gpu, window_size: WindowSize, source: CompositeRendererSource) -> Self {
+		let fsr = FsrPasses::new(gpu, window_size, &source);
+
+		// Something has to be bound, the debug view can't be switched to them
+		// anyway
+		let debug_textures = &source.debug_textures;
+		let normal_texture = debug_textures.normal.as_ref().unwrap_or(&source.output_texture);
+		let depth_texture = debug_textures.depth.as_ref().unwrap_or(&source.output_texture);
+		let debug_texture = debug_textures.debug.as_ref().unwrap_or(&source.output_texture);
 
 		let shader = ShaderBuilder::new()
 			.include_path("composite.wgsl")
+			.include(source.composite.clone())
 			.include_buffer(SampledTexture::FromTex {
 				texture_var_name: "out_texture",
 				sampler_var_name: "out_sampler",
-				tex: output_texture,
+				tex: source.output_texture.clone(),
+			})
+			.include_buffer(SampledTexture::FromTex {
+				texture_var_name: "upscaled_texture",
+				sampler_var_name: "upscaled_sampler",
+				tex: fsr.output.clone(),
 			})
-			.include_buffer(UniformBufferDescriptor::FromBuffer::<Vec2<u32>, _> {
+			.include_buffer(SampledTexture::FromTex {
+				texture_var_name: "debug_normal_texture",
+				sampler_var_name: "debug_normal_sampler",
+				tex: normal_texture.clone(),
+			})
+			.include_buffer(SampledTexture::FromTex {
+				texture_var_name: "debug_depth_texture",
+				sampler_var_name: "debug_depth_sampler",
+				tex: depth_texture.clone(),
+			})
+			.include_buffer(SampledTexture::FromTex {
+				texture_var_name: "debug_output_texture",
+				sampler_var_name: "debug_output_sampler",
+				tex: debug_texture.clone(),
+			})
+			.include_buffer(UniformBufferDescriptor::FromBuffer::<WindowSize, _> {
 				var_name: "viewport_size",
-				buffer: viewport_buffer,
+				buffer: source.buffers.viewport.clone(),
+			})
+			.include_buffer(UniformBufferDescriptor::FromBuffer::<UpscalerParams, _> {
+				var_name: "upscaler",
+				buffer: source.buffers.upscaler.clone(),
+			})
+			.include_buffer(UniformBufferDescriptor::FromBuffer::<DebugViewParams, _> {
+				var_name: "debug_view",
+				buffer: source.buffers.debug_view.clone(),
 			})
 			.build(gpu, "Composite Shader", &ShaderAssets, ShaderStages::FRAGMENT, 0)
 			.expect("Couldn't build shader");
@@ -125,58 +676,113 @@ impl CompositeRenderer {
 
 		// Create the render pipeline. Specify shader stages, primitive type,
 		// stencil/depth information, and some more stuff.
-		let pipeline = gpu.device.create_render_pipeline(&RenderPipelineDescriptor {
-			label: Some("Basic Render Pipeline"),
-			layout: Some(&render_pipeline_layout),
-			// No vertex buffers, we'll render 2 fullscreen triangles
-			// and set their positions in the shader
-			vertex: VertexState {
-				module: &shader.shader_module,
-				entry_point: "vs_main",
-				buffers: &[],
-			},
-			fragment: Some(FragmentState {
-				module: &shader.shader_module,
-				entry_point: "fs_main",
-				targets: &[Some(ColorTargetState {
-					format: render_target.config.format,
-					blend: Some(BlendState::REPLACE),
-					write_mask: ColorWrites::ALL,
-				})],
-			}),
-			// The point is to draw 2 triangles using 4 vertices.
-			// 1 -- 2
-			// | /  |
-			// 3 -- 4
-			primitive: PrimitiveState {
-				topology: PrimitiveTopology::TriangleStrip,
-				strip_index_format: None,
-				front_face: FrontFace::Ccw,
-				cull_mode: None,
-				polygon_mode: PolygonMode::Fill,
-				unclipped_depth: false,
-				conservative: true,
-			},
-			// Don't worry about the depth buffer for now
-			depth_stencil: None,
-			multisample: MultisampleState {
-				count: 1,
-				mask: !0,
-				alpha_to_coverage_enabled: false,
-			},
-			multiview: None,
+		let pipeline = shader.create_pipeline(gpu, || {
+			gpu.device.create_render_pipeline(&RenderPipelineDescriptor {
+				label: Some("Basic Render Pipeline"),
+				layout: Some(&render_pipeline_layout),
+				// No vertex buffers, we'll render 2 fullscreen triangles
+				// and set their positions in the shader
+				vertex: VertexState {
+					module: &shader.shader_module,
+					entry_point: "vs_main",
+					buffers: &[],
+				},
+				fragment: Some(FragmentState {
+					module: &shader.shader_module,
+					entry_point: "fs_main",
+					targets: &[Some(ColorTargetState {
+						format: source.format,
+						blend: Some(BlendState::REPLACE),
+						write_mask: ColorWrites::ALL,
+					})],
+				}),
+				// The point is to draw 2 triangles using 4 vertices.
+				// 1 -- 2
+				// | /  |
+				// 3 -- 4
+				primitive: PrimitiveState {
+					topology: PrimitiveTopology::TriangleStrip,
+					strip_index_format: None,
+					front_face: FrontFace::Ccw,
+					cull_mode: None,
+					polygon_mode: PolygonMode::Fill,
+					unclipped_depth: false,
+					conservative: true,
+				},
+				// Don't worry about the depth buffer for now
+				depth_stencil: None,
+				multisample: MultisampleState {
+					count: 1,
+					mask: !0,
+					alpha_to_coverage_enabled: false,
+				},
+				multiview: None,
+			})
 		});
 
-		Self { pipeline, shader }
+		Self {
+			pipeline,
+			shader,
+			fsr,
+			source,
+		}
 	}
 }
 
+/// Where a point of the window ends up in the compute renderer's output, in
+/// texels (not rounded). Same fitting as `get_texture_coordinates` in
+/// `composite/viewport.wgsl`, the texture covers the window and the overflow is
+/// cropped.
+pub fn window_to_texture(point: Vec2<f32>, window_size: Extent2<u32>, texture_size: Extent2<u32>) -> Vec2<f32> {
+	let screen = Vec2::new(window_size.w as f32, window_size.h as f32);
+	let texture = Vec2::new(texture_size.w as f32, texture_size.h as f32);
+
+	let tex_coord = if texture.x / texture.y < screen.x / screen.y {
+		// texture is TALLER than the screen
+		let size_y = screen.y / screen.x / texture.y * texture.x;
+		Vec2::new(point.x / screen.x, point.y / screen.y * size_y + (1.0 - size_y) * 0.5)
+	} else {
+		// texture is WIDER than the screen
+		let size_x = screen.x / screen.y / texture.x * texture.y;
+		Vec2::new(point.x / screen.x * size_x + (1.0 - size_x) * 0.5, point.y / screen.y)
+	};
+
+	// The texture is flipped vertically when drawn
+	Vec2::new(tex_coord.x, 1.0 - tex_coord.y) * texture
+}
+
 /*
 --------------------------------------------------------------------------------
 ||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
 --------------------------------------------------------------------------------
 */
 
+/// The color output of the renderer and the ones of the debug views
+fn renderer_outputs(compute_renderer: &ComputeRenderer) -> (Sarc<Tex>, DebugTextures) {
+	let output_texture = compute_renderer
+		.output_textures
+		.first()
+		.expect("Compute renderer needs at least 1 output texture")
+		.clone();
+
+	let debug_textures = DebugTextures {
+		normal: compute_renderer.output_texture("output_normal").cloned(),
+		depth: compute_renderer.output_texture("output_depth").cloned(),
+		debug: compute_renderer.output_texture("output_debug").cloned(),
+	};
+
+	(output_texture, debug_textures)
+}
+
+fn rebind_output_texture(world: &mut World) {
+	let composite_renderer = world.resource::<CompositeRenderer>().with_renderer(
+		world.resource::<Gpu>(),
+		world.resource::<RenderTarget>().size,
+		world.resource::<ComputeRenderer>(),
+	);
+	world.insert_resource(composite_renderer);
+}
+
 fn resize(window_events: EventReader<WindowResizedEvent>, mut q: Query<&mut ViewportInfo>) {
 	if let Some(size) = window_events.process().latest() {
 		for mut viewport_info in q.iter_mut() {
@@ -185,7 +791,147 @@ fn resize(window_events: EventReader<WindowResizedEvent>, mut q: Query<&mut View
 	}
 }
 
-fn render(composite_renderer: Res<CompositeRenderer>, mut render_target: ResMut<RenderTarget<'static>>, gpu: Res<Gpu>) {
+fn resize_upscaler(
+	gpu: Res<Gpu>,
+	mut composite_renderer: ResMut<CompositeRenderer>,
+	window_events: EventReader<WindowResizedEvent>,
+) {
+	if let Some(size) = window_events.process().latest() {
+		*composite_renderer = composite_renderer.resized(&gpu, size);
+	}
+}
+
+fn cycle_upscaler(
+	mut upscaler: ResMut<Upscaler>,
+	mut keyboard_events: EventReader<KeyboardInputEvent>,
+	key_bindings: Res<KeyBindings>,
+) {
+	if key_bindings.has_pressed(Action::CycleUpscaler, keyboard_events.read()) {
+		*upscaler = upscaler.next();
+		info!("Upscaler: {}", *upscaler);
+	}
+}
+
+fn sync_upscaler_params(upscaler: Res<Upscaler>, mut q: Query<&mut UpscalerParams>) {
+	for mut params in q.iter_mut() {
+		params.mode = *upscaler as u32;
+	}
+}
+
+fn cycle_debug_view(
+	mut debug_view: ResMut<DebugView>,
+	composite_renderer: Res<CompositeRenderer>,
+	mut keyboard_events: EventReader<KeyboardInputEvent>,
+	key_bindings: Res<KeyBindings>,
+) {
+	if key_bindings.has_pressed(Action::CycleDebugView, keyboard_events.read()) {
+		// Skip the views of the outputs the renderer doesn't have, the color
+		// always works
+		let mut next = debug_view.next();
+		while !composite_renderer.supports(next) {
+			next = next.next();
+		}
+
+		*debug_view = next;
+		info!("Debug view: {}", *debug_view);
+	}
+}
+
+fn sync_debug_view_params(
+	mut debug_view: ResMut<DebugView>,
+	composite_renderer: Res<CompositeRenderer>,
+	cameras: Query<&CameraView, With<ActiveCamera>>,
+	mut q: Query<&mut DebugViewParams>,
+) {
+	// E.g. after a swap to a renderer without normals
+	if !composite_renderer.supports(*debug_view) {
+		info!(
+			"The renderer has no output for the {} debug view, back to the color",
+			*debug_view
+		);
+		*debug_view = DebugView::Color;
+	}
+
+	let camera = cameras.get_single().ok();
+
+	for mut params in q.iter_mut() {
+		let mut new_params = *params;
+		new_params.mode = *debug_view as u32;
+		if let Some(camera) = camera {
+			new_params.z_near = camera.z_near;
+			new_params.z_far = camera.z_far;
+		}
+
+		// Not to re-upload it every frame for nothing
+		if *params != new_params {
+			*params = new_params;
+		}
+	}
+}
+
+fn debug_view(world: &mut World, args: &[String]) -> Result<String> {
+	match args {
+		[] => {}
+		[name] => {
+			let view = DebugView::ALL
+				.into_iter()
+				.find(|view| view.name() == name.as_str())
+				.context("Expected `color`, `normal`, `depth`, `heatmap` or `steps`")?;
+			if !world.resource::<CompositeRenderer>().supports(view) {
+				bail!("The renderer has no output for the {} debug view", view);
+			}
+			world.insert_resource(view);
+		}
+		_ => bail!("Usage: debug_view [color | normal | depth | heatmap | steps]"),
+	}
+
+	Ok(world.resource::<DebugView>().to_string())
+}
+
+fn upscaler(world: &mut World, args: &[String]) -> Result<String> {
+	let (name, sharpness) = match args {
+		[] => (None, None),
+		[name] => (Some(name), None),
+		[name, sharpness] => (Some(name), Some(sharpness)),
+		_ => bail!("Usage: upscaler [bilinear | lanczos3 | fsr] [sharpness]"),
+	};
+
+	if let Some(name) = name {
+		let upscaler = Upscaler::ALL
+			.into_iter()
+			.find(|upscaler| upscaler.name() == name.as_str())
+			.context("Expected `bilinear`, `lanczos3` or `fsr`")?;
+		world.insert_resource(upscaler);
+	}
+
+	let mut params = world.query::<&mut UpscalerParams>();
+
+	if let Some(sharpness) = sharpness {
+		let sharpness = sharpness
+			.parse::<f32>()
+			.context("Expected a number for the sharpness")?;
+		if sharpness < 0.0 {
+			bail!("The sharpness can't be negative");
+		}
+
+		for mut params in params.iter_mut(world) {
+			params.sharpness = sharpness;
+		}
+	}
+
+	let sharpness = params.iter(world).next().map_or(0.0, |params| params.sharpness);
+
+	Ok(format!("{} (sharpness {})", world.resource::<Upscaler>(), sharpness))
+}
+
+fn render(
+	composite_renderer: Res<CompositeRenderer>,
+	upscaler: Res<Upscaler>,
+	mut render_target: ResMut<RenderTarget<'static>>,
+	gpu: Res<Gpu>,
+	parity: Res<PingPongParity>,
+	gpu_timers: Option<Res<GpuTimers>>,
+) {
 	// trace!("Rendering terrain");
 
 	// A command encoder takes multiple draw/compute commands that can then be
@@ -194,45 +940,20 @@ fn render(composite_renderer: Res<CompositeRenderer>, mut render_target: ResMut<
 		label: Some("CompositeRenderer Command Encoder"),
 	});
 
-	{
-		let render_view = &render_target
-			.current_view
-			.as_ref()
-			.expect("Attempt to encode renderpass while RenderTarget view is unavailable");
-
-		// A render pass records a single pass of a pipeline
-		let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
-			label: Some("CompositeRenderer Render Pass"),
-			color_attachments: &[Some(RenderPassColorAttachment {
-				view: render_view,
-				resolve_target: None,
-				ops: Operations {
-					load: LoadOp::Clear(Color {
-						r: 0.0,
-						g: 0.0,
-						b: 0.0,
-						a: 1.0,
-					}),
-					store: StoreOp::Store,
-				},
-			})],
-			depth_stencil_attachment: None,
-			occlusion_query_set: None,
-			timestamp_writes: None,
-		});
-
-		render_pass.set_pipeline(&composite_renderer.pipeline);
-
-		render_pass.apply_buffer_mapping(&composite_renderer.shader.binding);
+	let render_view = render_target
+		.current_view
+		.as_ref()
+		.expect("Attempt to encode renderpass while RenderTarget view is unavailable");
 
-		// Draw 2 fullscreen triangles
-		// 2 - 3
-		// | \ |
-		// 0 - 1
-		render_pass.draw(0..4, 0..1);
-	}
-	// Extra scope here to make sure render_pass is dropped, otherwise
-	// encoder.finish() can't be called
+	composite_renderer.encode(
+		&mut encoder,
+		render_view,
+		*upscaler,
+		*parity,
+		gpu_timers
+			.as_ref()
+			.and_then(|gpu_timers| gpu_timers.render_pass_writes("composite")),
+	);
 
 	render_target.command_queue.push(encoder.finish());
 }
@@ -1,30 +1,54 @@
+use anyhow::{anyhow, bail, Result};
 use bevy_ecs::{
-	query::With,
 	schedule::IntoSystemConfigs,
 	system::{Res, ResMut},
+	world::{Mut, World},
 };
 use brainrot::{
 	bevy::{self, App, Plugin},
 	vec2,
 	vek::Vec2,
-	ScreenSize,
 };
+use log::warn;
+use regex::Regex;
 use wgpu::{
-	Buffer, CommandEncoderDescriptor, ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, FilterMode,
-	SamplerBorderColor, ShaderStages, StorageTextureAccess,
+	Buffer, CommandEncoder, CommandEncoderDescriptor, ComputePassDescriptor, ComputePassTimestampWrites,
+	ComputePipeline, ComputePipelineDescriptor, FilterMode, SamplerBorderColor, ShaderStages, StorageTextureAccess,
+	TextureAspect, TextureFormat, TextureFormatFeatureFlags, TextureUsages,
 };
 
-use super::camera_view::CameraView;
+use super::{
+	camera_view::{ActiveCameraView, CameraView},
+	depth_of_field::DofSettings,
+	frame_info::{self, FrameInfo},
+	globals::Globals,
+	gpu_asserts,
+	gpu_timers::GpuTimers,
+	lights::{Light, Lights, LightsBuffer},
+};
 use crate::{
-	core::{camera::Camera, gameloop::Render, gpu::Gpu, render_target::RenderTarget},
+	core::{
+		console,
+		entity_label::{self, EntityLabel, LabelError},
+		gameloop::{PreRender, Render},
+		gpu::Gpu,
+		render_target::RenderTarget,
+		size::Resolution,
+	},
+	fragments::instrumentation::GpuAsserts,
 	libs::{
 		buffer::{
-			storage_texture_buffer::StorageTexture, uniform_buffer::UniformBufferDescriptor, BufferMappingApplicable,
+			indirect_dispatch::{DispatchIndirectArgs, IndirectDispatchBuffer},
+			ping_pong_texture::PingPongParity,
+			storage_buffer::{StorageArray, StorageBuffer, StorageBufferDescriptor},
+			storage_texture_buffer::StorageTexture,
+			uniform_buffer::{UniformBuffer, UniformBufferDescriptor},
+			BufferMappingApplicable, BufferUploadable,
 		},
-		shader::{CompiledShader, ShaderBuilder},
-		shader_fragment::Renderer,
+		shader::{CompiledShader, Shader, ShaderBuilder},
+		shader_fragment::{PrePassDesc, PrePassDispatch, Renderer, RendererResizer, ShaderFragment},
 		smart_arc::Sarc,
-		texture::{SamplerEdges, Tex, TexSamplerDescriptor},
+		texture::{SamplerEdges, Tex, TexDescriptor, TexSamplerDescriptor, TextureAssetDimensions},
 	},
 	ShaderAssets,
 };
@@ -37,8 +61,12 @@ use crate::{
 
 pub struct ComputeRendererPlugin<R: Renderer> {
 	pub workgroup_size: Vec2<u32>,
-	pub resolution: ScreenSize,
+	pub resolution: Resolution,
 	pub filter_mode: FilterMode,
+	pub dispatch_mode: DispatchMode,
+	/// Submit the compute pass as soon as it is encoded instead of at the end of
+	/// the frame, so that the GPU can start on it while the CPU encodes the rest
+	pub early_submit: bool,
 	pub renderer: R,
 }
 
@@ -47,26 +75,53 @@ where
 	R: Renderer + 'static,
 {
 	fn build(&self, app: &mut App) {
-		let camera_buffer = app
-			.world
-			.query_filtered::<&Sarc<Buffer>, With<Camera>>()
-			.single(&app.world)
-			.clone();
+		let bindings = ComputeBindings {
+			camera_buffer: labeled_buffer::<ActiveCameraView>(&mut app.world, "CameraViewPlugin"),
+			// Disabled by default
+			dof_buffer: labeled_buffer_or::<DofSettings>(&mut app.world, |gpu| {
+				UniformBuffer::raw_buffer_from_data(gpu, &DofSettings::default(), None)
+			}),
+			globals_buffer: labeled_buffer::<Globals>(&mut app.world, "GlobalsPlugin"),
+			// No lights, which the buffer holds as a single black one
+			lights_buffer: labeled_buffer_or::<LightsBuffer>(&mut app.world, |gpu| {
+				StorageBuffer::raw_buffer_from_data(gpu, &Lights(Vec::new()).gpu_data(), Some("Lights"))
+			}),
+			frame_info_buffer: frame_info::spawn_frame_info(app),
+			gpu_asserts: gpu_asserts::gpu_asserts_fragment(app),
+		};
 
 		let gpu = app.world.resource::<Gpu>();
 
 		// TODO: Somehow clean up all the plugin vs resource instance stuff?
-		let compute_renderer = ComputeRenderer::new(
+		let mut compute_renderer = ComputeRenderer::new(
 			gpu,
 			self.workgroup_size,
 			self.resolution,
 			self.filter_mode,
+			self.dispatch_mode.clone(),
 			&self.renderer,
-			camera_buffer,
+			bindings,
 		);
+		compute_renderer.early_submit = self.early_submit;
 
 		app.world.insert_resource(compute_renderer);
+		app.world.insert_resource(self.resolution);
+		app.world.init_resource::<RendererSwapHooks>();
+
+		console::register_command(
+			app,
+			"gpu.textures",
+			"List the live textures with their label, format, size and strong count",
+			gpu_textures,
+		);
+		console::register_command(
+			app,
+			"gpu.leakcheck",
+			"Swap the compute renderer for a copy of itself and list the textures still held by the old one",
+			gpu_leakcheck,
+		);
 
+		app.add_systems(PreRender, frame_info::update_frame_info);
 		app.add_systems(Render, (render).in_set(ComputeRenderPass).chain());
 	}
 }
@@ -74,6 +129,110 @@ where
 #[derive(bevy::SystemSet, Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
 pub struct ComputeRenderPass;
 
+/// The buffer of the entity labeled `L`, spawned by `plugin`
+fn labeled_buffer<L: EntityLabel>(world: &mut World, plugin: &str) -> Sarc<Buffer> {
+	let entity = entity_label::single_entity::<L>(world)
+		.unwrap_or_else(|e| panic!("{}, add the {} before the compute renderer", e, plugin));
+
+	world
+		.get::<Sarc<Buffer>>(entity)
+		.unwrap_or_else(|| panic!("The `{}` doesn't have a buffer", std::any::type_name::<L>()))
+		.clone()
+}
+
+/// Same as [`labeled_buffer`], but for the optional plugins: without the
+/// entity, the renderer binds the buffer made by `fallback` instead
+fn labeled_buffer_or<L: EntityLabel>(world: &mut World, fallback: impl FnOnce(&Gpu) -> Buffer) -> Sarc<Buffer> {
+	match entity_label::single_entity::<L>(world) {
+		Ok(entity) => world
+			.get::<Sarc<Buffer>>(entity)
+			.unwrap_or_else(|| panic!("The `{}` doesn't have a buffer", std::any::type_name::<L>()))
+			.clone(),
+		Err(LabelError::NotFound { .. }) => Sarc::new(fallback(world.resource::<Gpu>())),
+		Err(e) => panic!("{}", e),
+	}
+}
+
+type SwapHook = Box<dyn Fn(&mut World) + Send + Sync>;
+
+/// Called by [`swap_compute_renderer`] right before and right after the old
+/// renderer is replaced (and dropped), in the order they were added. Also
+/// around [`resize_compute_renderer`], whose renderer gets new output textures.
+#[derive(bevy::Resource, Default)]
+pub struct RendererSwapHooks {
+	before: Vec<SwapHook>,
+	after: Vec<SwapHook>,
+}
+
+impl RendererSwapHooks {
+	pub fn before(&mut self, hook: impl Fn(&mut World) + Send + Sync + 'static) {
+		self.before.push(Box::new(hook));
+	}
+
+	pub fn after(&mut self, hook: impl Fn(&mut World) + Send + Sync + 'static) {
+		self.after.push(Box::new(hook));
+	}
+}
+
+/// Replace the [`ComputeRenderer`], running the [`RendererSwapHooks`] around it
+pub fn swap_compute_renderer(world: &mut World, renderer: ComputeRenderer) {
+	with_swap_hooks(world, |world| world.insert_resource(renderer));
+}
+
+/// Same as [`swap_compute_renderer`], giving the old renderer back instead of
+/// dropping it, e.g. to swap it back in later
+pub fn replace_compute_renderer(world: &mut World, renderer: ComputeRenderer) -> Option<ComputeRenderer> {
+	let mut old = None;
+	with_swap_hooks(world, |world| {
+		old = world.remove_resource::<ComputeRenderer>();
+		world.insert_resource(renderer);
+	});
+	old
+}
+
+/// Resize the [`ComputeRenderer`] in place (see [`ComputeRenderer::resize`])
+/// and update the [`Resolution`], running the [`RendererSwapHooks`] around it
+pub fn resize_compute_renderer(world: &mut World, resolution: Resolution) -> Result<()> {
+	let mut result = Ok(());
+	with_swap_hooks(world, |world| {
+		world.resource_scope(|world, mut renderer: Mut<ComputeRenderer>| {
+			result = renderer.resize(world.resource::<Gpu>(), resolution);
+		});
+		if result.is_ok() {
+			world.insert_resource(resolution);
+		}
+	});
+	result
+}
+
+fn with_swap_hooks(world: &mut World, swap: impl FnOnce(&mut World)) {
+	world.resource_scope(|world, hooks: Mut<RendererSwapHooks>| {
+		for hook in &hooks.before {
+			hook(world);
+		}
+	});
+
+	swap(world);
+
+	world.resource_scope(|world, hooks: Mut<RendererSwapHooks>| {
+		for hook in &hooks.after {
+			hook(world);
+		}
+	});
+}
+
+/// How the number of workgroups of a compute pass is decided
+#[derive(Clone, Debug, Default)]
+pub enum DispatchMode {
+	/// Enough workgroups to cover the whole resolution
+	#[default]
+	Fixed,
+	/// Read the workgroup counts from a buffer of
+	/// [`DispatchIndirectArgs`](crate::libs::buffer::indirect_dispatch::DispatchIndirectArgs),
+	/// usually written by an earlier compute pass
+	Indirect(Sarc<Buffer>),
+}
+
 /*
 --------------------------------------------------------------------------------
 ||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
@@ -83,45 +242,409 @@ pub struct ComputeRenderPass;
 #[derive(bevy::Resource)]
 pub struct ComputeRenderer {
 	workgroup_size: Vec2<u32>,
-	resolution: ScreenSize,
+	resolution: Resolution,
+	dispatch_mode: DispatchMode,
+	pub early_submit: bool,
 	pipeline: ComputePipeline,
+	/// Encoded before the main dispatch, in order. The buffer is where the args
+	/// of an `IndirectCopy` pass are copied to.
+	pre_passes: Vec<(PrePassDesc, ComputePipeline, Option<Buffer>)>,
 	shader: CompiledShader,
 	pub output_textures: Vec<Sarc<Tex>>,
+	/// Everything needed to build the renderer again at another resolution
+	source: ComputeRendererSource,
+}
+
+#[derive(Clone)]
+struct ComputeRendererSource {
+	renderer_shader: Shader,
+	pre_passes: Vec<PrePassDesc>,
+	/// For the renderers with resolution-dependent resources of their own
+	resizer: Option<RendererResizer>,
+	outputs: Vec<OutputTexture>,
+	filter_mode: FilterMode,
+	bindings: ComputeBindings,
+}
+
+/// What the compute renderer binds besides the renderer's own resources, the
+/// same for every renderer
+#[derive(Clone)]
+pub struct ComputeBindings {
+	/// A [`CameraView`]
+	pub camera_buffer: Sarc<Buffer>,
+	/// The [`DofSettings`]
+	pub dof_buffer: Sarc<Buffer>,
+	/// The [`Globals`]
+	pub globals_buffer: Sarc<Buffer>,
+	/// The [`FrameInfo`]
+	pub frame_info_buffer: Sarc<Buffer>,
+	/// An array of [`Light`]s
+	pub lights_buffer: Sarc<Buffer>,
+	pub gpu_asserts: GpuAsserts,
+}
+
+/// An owned [`TexDescriptor`], since the renderer isn't kept around
+#[derive(Clone)]
+struct OutputTexture {
+	var_name: String,
+	label: String,
+	dimensions: TextureAssetDimensions,
+	format: TextureFormat,
+	usage: Option<TextureUsages>,
+	aspect: TextureAspect,
+}
+
+impl OutputTexture {
+	/// The list of output textures given by the renderer
+	fn list(renderer: &dyn Renderer, resolution: Resolution) -> Vec<Self> {
+		renderer
+			.output_textures(resolution)
+			.into_iter()
+			.map(|(var_name, desc)| Self {
+				var_name,
+				label: desc.label.to_owned(),
+				dimensions: desc.dimensions,
+				format: desc.format,
+				usage: desc.usage,
+				aspect: desc.aspect,
+			})
+			.collect()
+	}
+}
+
+impl ComputeRendererSource {
+	/// The same source at another resolution. The output textures that
+	/// followed the resolution follow the new one, and the renderer's shader is
+	/// built again if it has resources of its own sized for the old one.
+	fn resized(&self, gpu: &Gpu, old_resolution: Resolution, resolution: Resolution) -> Self {
+		let mut source = self.clone();
+
+		for output in &mut source.outputs {
+			if output.dimensions == TextureAssetDimensions::D2(old_resolution.into()) {
+				output.dimensions = TextureAssetDimensions::D2(resolution.into());
+			}
+		}
+
+		if let Some(resizer) = &self.resizer {
+			(source.renderer_shader, source.pre_passes) = resizer(gpu, resolution);
+		}
+
+		source
+	}
 }
 
 impl ComputeRenderer {
+	const FALLBACK_OUTPUT_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+	/// Submitted at the end of the frame, see
+	/// [`early_submit`](Self::early_submit) to submit it right away
 	pub fn new(
 		gpu: &Gpu,
 		workgroup_size: Vec2<u32>,
-		resolution: ScreenSize,
+		resolution: Resolution,
 		filter_mode: FilterMode,
+		dispatch_mode: DispatchMode,
 		renderer: &dyn Renderer,
-		camera_buffer: Sarc<Buffer>,
+		bindings: ComputeBindings,
 	) -> Self {
+		if let DispatchMode::Indirect(buffer) = &dispatch_mode {
+			IndirectDispatchBuffer::validate(buffer).expect("Invalid indirect dispatch buffer");
+		}
+
+		let source = ComputeRendererSource {
+			renderer_shader: renderer.shader(),
+			pre_passes: renderer.pre_passes(),
+			resizer: renderer.resizer(),
+			outputs: OutputTexture::list(renderer, resolution),
+			filter_mode,
+			bindings,
+		};
+
+		Self::build(gpu, workgroup_size, resolution, dispatch_mode, false, source)
+	}
+
+	/// The same renderer at another resolution, with its own output textures.
+	/// The output textures that followed the resolution follow the new one, and
+	/// so do the resources of renderers that size some of their own (see
+	/// [`Renderer::resizer`]).
+	pub fn resized(&self, gpu: &Gpu, resolution: Resolution) -> Result<Self> {
+		self.with_camera(gpu, resolution, self.source.bindings.camera_buffer.clone())
+	}
+
+	/// Same as [`resized`](Self::resized), seeing the scene through another
+	/// `camera` buffer (holding a [`CameraView`]), e.g. for a second view of
+	/// the scene
+	pub fn with_camera(&self, gpu: &Gpu, resolution: Resolution, camera_buffer: Sarc<Buffer>) -> Result<Self> {
+		if let DispatchMode::Indirect(_) = self.dispatch_mode {
+			bail!("Can't resize a renderer with an indirect dispatch, its workgroup counts are for the old resolution");
+		}
+
+		let mut source = self.source.resized(gpu, self.resolution, resolution);
+		source.bindings.camera_buffer = camera_buffer;
+
+		Ok(Self::build(
+			gpu,
+			self.workgroup_size,
+			resolution,
+			self.dispatch_mode.clone(),
+			self.early_submit,
+			source,
+		))
+	}
+
+	/// The same renderer, binding another buffer of
+	/// [`Lights`](super::lights::Lights) as `lights`, e.g. after the light count
+	/// changed
+	pub fn with_lights(&self, gpu: &Gpu, lights_buffer: Sarc<Buffer>) -> Self {
+		let mut source = self.source.clone();
+		source.bindings.lights_buffer = lights_buffer;
+
+		Self::build(
+			gpu,
+			self.workgroup_size,
+			self.resolution,
+			self.dispatch_mode.clone(),
+			self.early_submit,
+			source,
+		)
+	}
+
+	/// Another renderer in place of this one, with the same buffers, resolution
+	/// and dispatch, e.g. to try a renderer on the running app
+	pub fn with_renderer(&self, gpu: &Gpu, renderer: &dyn Renderer) -> Self {
+		let mut source = self.source.clone();
+		source.renderer_shader = renderer.shader();
+		source.pre_passes = renderer.pre_passes();
+		source.resizer = renderer.resizer();
+		source.outputs = OutputTexture::list(renderer, self.resolution);
+
+		Self::build(
+			gpu,
+			self.workgroup_size,
+			self.resolution,
+			self.dispatch_mode.clone(),
+			self.early_submit,
+			source,
+		)
+	}
+
+	pub fn resolution(&self) -> Resolution {
+		self.resolution
+	}
+
+	/// How the output textures are sampled
+	pub fn filter_mode(&self) -> FilterMode {
+		self.source.filter_mode
+	}
+
+	/// The output texture that the renderer binds as `var_name`, e.g.
+	/// `output_depth`. Not every renderer has the same outputs.
+	pub fn output_texture(&self, var_name: &str) -> Option<&Sarc<Tex>> {
+		self.source
+			.outputs
+			.iter()
+			.position(|output| output.var_name == var_name)
+			.map(|i| &self.output_textures[i])
+	}
+
+	/// Encode the pre-passes and the main dispatch. The copies of the indirect
+	/// args split the compute pass, the timestamps go around all the parts.
+	pub fn encode(
+		&self,
+		encoder: &mut CommandEncoder,
+		parity: PingPongParity,
+		timestamp_writes: Option<ComputePassTimestampWrites>,
+	) {
+		let last_part = self
+			.pre_passes
+			.iter()
+			.filter(|(_, _, copy_target)| copy_target.is_some())
+			.count();
+		let pass_descriptor = |part: usize| ComputePassDescriptor {
+			label: Some("ComputeRenderer Compute Pass"),
+			timestamp_writes: timestamp_writes.as_ref().map(|writes| ComputePassTimestampWrites {
+				query_set: writes.query_set,
+				beginning_of_pass_write_index: writes.beginning_of_pass_write_index.filter(|_| part == 0),
+				end_of_pass_write_index: writes.end_of_pass_write_index.filter(|_| part == last_part),
+			}),
+		};
+
+		let mut part = 0;
+		let mut compute_pass = encoder.begin_compute_pass(&pass_descriptor(part));
+
+		// Every dispatch sees the writes of the previous ones
+		for (desc, pipeline, copy_target) in &self.pre_passes {
+			if let (PrePassDispatch::IndirectCopy { buffer, offset }, Some(copy_target)) = (&desc.dispatch, copy_target)
+			{
+				drop(compute_pass);
+				encoder.copy_buffer_to_buffer(buffer, *offset, copy_target, 0, DispatchIndirectArgs::get_size());
+
+				part += 1;
+				compute_pass = encoder.begin_compute_pass(&pass_descriptor(part));
+			}
+
+			compute_pass.set_pipeline(pipeline);
+			compute_pass.apply_buffer_mapping(&self.shader.binding, parity);
+
+			match &desc.dispatch {
+				PrePassDispatch::Fixed(workgroups) => {
+					compute_pass.dispatch_workgroups(workgroups.x, workgroups.y, workgroups.z)
+				}
+				PrePassDispatch::Resolution(workgroup_size) => {
+					let workgroups = <Vec2<u32>>::from(self.resolution.0) / *workgroup_size + vec2!(1);
+					compute_pass.dispatch_workgroups(workgroups.x, workgroups.y, 1);
+				}
+				PrePassDispatch::Indirect(buffer) => compute_pass.dispatch_workgroups_indirect(buffer, 0),
+				PrePassDispatch::IndirectCopy { .. } => {
+					let copy_target = copy_target
+						.as_ref()
+						.expect("Every indirect copy pass has a copy target");
+					compute_pass.dispatch_workgroups_indirect(copy_target, 0);
+				}
+			}
+		}
+
+		compute_pass.set_pipeline(&self.pipeline);
+
+		compute_pass.apply_buffer_mapping(&self.shader.binding, parity);
+
+		match &self.dispatch_mode {
+			DispatchMode::Fixed => {
+				let workgroups = <Vec2<u32>>::from(self.resolution.0) / self.workgroup_size + vec2!(1);
+				compute_pass.dispatch_workgroups(workgroups.x, workgroups.y, 1);
+			}
+			DispatchMode::Indirect(buffer) => compute_pass.dispatch_workgroups_indirect(buffer, 0),
+		}
+	}
+
+	/// Resize the output textures that follow the resolution in place, e.g. to
+	/// follow the window. Only the output textures and the bind group are
+	/// created again, the compiled shader and the pipelines stay the same, so
+	/// it's a lot cheaper than [`resized`](Self::resized).
+	///
+	/// Falls back to building everything again if the bindings changed with the
+	/// resolution, and for renderers that size resources of their own (see
+	/// [`Renderer::resizer`]), whose shader changes with it.
+	pub fn resize(&mut self, gpu: &Gpu, resolution: Resolution) -> Result<()> {
+		if let DispatchMode::Indirect(_) = self.dispatch_mode {
+			bail!("Can't resize a renderer with an indirect dispatch, its workgroup counts are for the old resolution");
+		}
+
+		self.source = self.source.resized(gpu, self.resolution, resolution);
+		self.resolution = resolution;
+
+		if self.source.resizer.is_some() {
+			*self = Self::build(
+				gpu,
+				self.workgroup_size,
+				resolution,
+				self.dispatch_mode.clone(),
+				self.early_submit,
+				self.source.clone(),
+			);
+			return Ok(());
+		}
+
+		let (mut shader, output_textures) = Self::shader_builder(gpu, self.workgroup_size, &self.source);
+		let rebound = shader
+			.build_source(gpu, &ShaderAssets)
+			.and_then(|source| source.rebind(gpu, &mut self.shader));
+
+		match rebound {
+			Ok(()) => self.output_textures = output_textures,
+			Err(e) => {
+				warn!("Couldn't resize the renderer in place, building it again: {:#}", e);
+				*self = Self::build(
+					gpu,
+					self.workgroup_size,
+					resolution,
+					self.dispatch_mode.clone(),
+					self.early_submit,
+					self.source.clone(),
+				);
+			}
+		}
+
+		Ok(())
+	}
+
+	/// The shader of the renderer with everything included, and the output
+	/// textures it binds
+	fn shader_builder(
+		gpu: &Gpu,
+		workgroup_size: Vec2<u32>,
+		source: &ComputeRendererSource,
+	) -> (ShaderBuilder, Vec<Sarc<Tex>>) {
 		// Dynamically create shader from the renderer
 		let mut shader = ShaderBuilder::new();
 		shader
 			.include_path("compute.wgsl")
-			.include(renderer.shader())
+			.include(source.renderer_shader.clone())
+			.include(source.bindings.gpu_asserts.shader())
 			.define("WORKGROUP_X", format!("{}", workgroup_size.x))
 			.define("WORKGROUP_Y", format!("{}", workgroup_size.y))
 			.include_buffer(UniformBufferDescriptor::FromBuffer::<CameraView, _> {
 				var_name: "camera",
-				buffer: camera_buffer,
+				buffer: source.bindings.camera_buffer.clone(),
+			})
+			.include_buffer(UniformBufferDescriptor::FromBuffer::<DofSettings, _> {
+				var_name: "dof",
+				buffer: source.bindings.dof_buffer.clone(),
+			})
+			.include_buffer(UniformBufferDescriptor::FromBuffer::<Globals, _> {
+				var_name: "globals",
+				buffer: source.bindings.globals_buffer.clone(),
+			})
+			.include_buffer(UniformBufferDescriptor::FromBuffer::<FrameInfo, _> {
+				var_name: "frame_info",
+				buffer: source.bindings.frame_info_buffer.clone(),
+			})
+			.include_buffer(StorageBufferDescriptor::FromBuffer::<StorageArray<Light>, _> {
+				var_name: "lights",
+				read_only: true,
+				buffer: source.bindings.lights_buffer.clone(),
 			});
 
 		// The sampler that will be added to all output textures
 		let output_sampler = Some(TexSamplerDescriptor {
 			edges: SamplerEdges::ClampToColor(SamplerBorderColor::TransparentBlack),
-			filter: filter_mode,
+			filter: source.filter_mode,
 			compare: None,
 		});
 
-		// The list of output textures given by the renderer
-		let output_textures = renderer
-			.output_textures(resolution)
-			.into_iter()
-			.map(|(name, desc)| (name, Sarc::new(Tex::create(gpu, desc, output_sampler))))
+		let output_textures = source
+			.outputs
+			.iter()
+			.map(|output| {
+				let name = output.var_name.clone();
+				let desc = TexDescriptor {
+					label: &output.label,
+					dimensions: output.dimensions,
+					format: output.format,
+					usage: output.usage,
+					aspect: output.aspect,
+				};
+
+				let format = supported_output_format(gpu, desc.format).unwrap_or_else(|| {
+					panic!(
+						"Output texture '{}' can't be a read-write storage texture on this adapter, not even as {:?}",
+						name,
+						Self::FALLBACK_OUTPUT_FORMAT
+					)
+				});
+
+				if format != desc.format {
+					warn!(
+						"Output texture '{}' can't be a read-write storage texture as {:?} on this adapter, falling back \
+						 to {:?}",
+						name, desc.format, format
+					);
+				}
+
+				// No InitPolicy, the renderer writes every texel before anything reads them
+				let tex = Tex::create(gpu, TexDescriptor { format, ..desc }, output_sampler);
+				(name, tex.tracked(gpu))
+			})
 			.collect::<Vec<_>>();
 
 		// Add the output textures to the shader
@@ -135,6 +658,19 @@ impl ComputeRenderer {
 
 		let output_textures = output_textures.into_iter().map(|(_, tex)| tex).collect::<Vec<_>>();
 
+		(shader, output_textures)
+	}
+
+	fn build(
+		gpu: &Gpu,
+		workgroup_size: Vec2<u32>,
+		resolution: Resolution,
+		dispatch_mode: DispatchMode,
+		early_submit: bool,
+		source: ComputeRendererSource,
+	) -> Self {
+		let (mut shader, output_textures) = Self::shader_builder(gpu, workgroup_size, &source);
+
 		// Compile the shader
 		let shader = shader
 			.build(gpu, "Compute shader", &ShaderAssets, ShaderStages::COMPUTE, 0)
@@ -146,47 +682,248 @@ impl ComputeRenderer {
 			push_constant_ranges: &[],
 		});
 
-		let pipeline = gpu.device.create_compute_pipeline(&ComputePipelineDescriptor {
-			label: Some("Compute pipeline"),
-			layout: Some(&pipeline_layout),
-			module: &shader.shader_module,
-			entry_point: "main",
+		let pipeline = shader.create_pipeline(gpu, || {
+			gpu.device.create_compute_pipeline(&ComputePipelineDescriptor {
+				label: Some("Compute pipeline"),
+				layout: Some(&pipeline_layout),
+				module: &shader.shader_module,
+				entry_point: "main",
+			})
 		});
 
+		for warning in validate_pre_passes(&source.pre_passes, &shader.binding_declarations)
+			.unwrap_or_else(|error| panic!("Invalid pre-passes: {:#}", error))
+		{
+			warn!("{}", warning);
+		}
+
+		// Same module and layout, so the pre-passes can use the main bind group
+		let pre_passes = source
+			.pre_passes
+			.iter()
+			.map(|desc| {
+				let pipeline = shader.create_pipeline(gpu, || {
+					gpu.device.create_compute_pipeline(&ComputePipelineDescriptor {
+						label: Some(&format!("Compute pre-pass '{}' pipeline", desc.entry_point)),
+						layout: Some(&pipeline_layout),
+						module: &shader.shader_module,
+						entry_point: &desc.entry_point,
+					})
+				});
+
+				let copy_target = match desc.dispatch {
+					PrePassDispatch::IndirectCopy { .. } => Some(IndirectDispatchBuffer::raw_copy_target(
+						gpu,
+						Some(&format!("Compute pre-pass '{}' indirect args", desc.entry_point)),
+					)),
+					_ => None,
+				};
+
+				(desc.clone(), pipeline, copy_target)
+			})
+			.collect();
+
 		Self {
 			workgroup_size,
 			resolution,
+			dispatch_mode,
+			early_submit,
 			pipeline,
+			pre_passes,
 			shader,
 			output_textures,
+			source,
 		}
 	}
 }
 
+/// The format the output texture will actually use: the requested one if the
+/// adapter supports it as a read-write storage texture, otherwise
+/// [`ComputeRenderer::FALLBACK_OUTPUT_FORMAT`] if that one is supported.
+///
+/// The composite pass and the shader bindings take their format from the
+/// texture itself, so they adapt automatically.
+fn supported_output_format(gpu: &Gpu, format: TextureFormat) -> Option<TextureFormat> {
+	let is_supported = |format: TextureFormat| {
+		let features = gpu.adapter.get_texture_format_features(format);
+
+		// `Tex::create` always adds both usages
+		features
+			.allowed_usages
+			.contains(TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING)
+			&& features.flags.contains(TextureFormatFeatureFlags::STORAGE_READ_WRITE)
+	};
+
+	[format, ComputeRenderer::FALLBACK_OUTPUT_FORMAT]
+		.into_iter()
+		.find(|format| is_supported(*format))
+}
+
+/// Check the declared reads and writes of the pre-passes against the bindings
+/// of the shader: every variable has to be bound, and the written ones have to
+/// be writable. Returns what looks suspicious but still works, like a pass that
+/// reads something written by a later pass (so it gets last frame's value).
+fn validate_pre_passes(pre_passes: &[PrePassDesc], binding_declarations: &[String]) -> Result<Vec<String>> {
+	let re = Regex::new(r"var(?:<[^>]*>)?\s+(\w+)\s*:").unwrap();
+
+	let binding = |var_name: &str| {
+		binding_declarations.iter().find(|declaration| {
+			re.captures(declaration)
+				.is_some_and(|caps| caps.get(1).unwrap().as_str() == var_name)
+		})
+	};
+
+	let mut warnings = Vec::new();
+
+	for (i, pass) in pre_passes.iter().enumerate() {
+		if pass.entry_point == "main"
+			|| pre_passes[..i]
+				.iter()
+				.any(|other| other.entry_point == pass.entry_point)
+		{
+			bail!("The entry point `{}` is used more than once", pass.entry_point);
+		}
+
+		match &pass.dispatch {
+			PrePassDispatch::Indirect(buffer) => IndirectDispatchBuffer::validate(buffer)?,
+			PrePassDispatch::IndirectCopy { buffer, offset } => {
+				IndirectDispatchBuffer::validate_copy_source(buffer, *offset)?
+			}
+			_ => (),
+		}
+
+		for var_name in &pass.reads {
+			binding(var_name)
+				.ok_or_else(|| anyhow!("`{}` reads `{}`, which isn't bound", pass.entry_point, var_name))?;
+
+			if let Some(later) = pre_passes[i + 1..].iter().find(|later| later.writes.contains(var_name)) {
+				warnings.push(format!(
+					"Pre-pass `{}` reads `{}` before `{}` writes it, so it gets the previous frame's value",
+					pass.entry_point, var_name, later.entry_point
+				));
+			}
+		}
+
+		for var_name in &pass.writes {
+			let declaration = binding(var_name)
+				.ok_or_else(|| anyhow!("`{}` writes `{}`, which isn't bound", pass.entry_point, var_name))?;
+
+			if !declaration.contains("read_write") && !declaration.contains(", write>") {
+				bail!(
+					"`{}` writes `{}`, which is read-only: {}",
+					pass.entry_point,
+					var_name,
+					declaration
+				);
+			}
+		}
+	}
+
+	Ok(warnings)
+}
+
 /*
 --------------------------------------------------------------------------------
 ||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
 --------------------------------------------------------------------------------
 */
 
-fn render(compute_renderer: Res<ComputeRenderer>, mut render_target: ResMut<RenderTarget<'static>>, gpu: Res<Gpu>) {
+fn render(
+	compute_renderer: Res<ComputeRenderer>,
+	mut render_target: ResMut<RenderTarget<'static>>,
+	gpu: Res<Gpu>,
+	parity: Res<PingPongParity>,
+	gpu_timers: Option<Res<GpuTimers>>,
+) {
 	let mut encoder = gpu.device.create_command_encoder(&CommandEncoderDescriptor {
 		label: Some("ComputeRenderer Command Encoder"),
 	});
 
-	{
-		let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
-			label: Some("ComputeRenderer Compute Pass"),
-			timestamp_writes: None,
-		});
+	compute_renderer.encode(
+		&mut encoder,
+		*parity,
+		gpu_timers
+			.as_ref()
+			.and_then(|gpu_timers| gpu_timers.compute_pass_writes("compute")),
+	);
 
-		compute_pass.set_pipeline(&compute_renderer.pipeline);
+	if compute_renderer.early_submit {
+		// Submissions execute in order, so whatever was queued before this pass
+		// needs to go first
+		let queued = render_target.command_queue.drain(..);
+		gpu.queue.submit(queued.chain([encoder.finish()]));
+	} else {
+		render_target.command_queue.push(encoder.finish());
+	}
+}
 
-		compute_pass.apply_buffer_mapping(&compute_renderer.shader.binding);
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
 
-		let workgroups = <Vec2<u32>>::from(compute_renderer.resolution) / compute_renderer.workgroup_size + vec2!(1);
-		compute_pass.dispatch_workgroups(workgroups.x, workgroups.y, 1);
-	}
+fn describe_texture(tex: &Tex, strong_count: usize) -> String {
+	let size = tex.size();
+	format!(
+		"'{}': {:?}, {}x{}x{}, {} strong",
+		tex.label(),
+		tex.format(),
+		size.width,
+		size.height,
+		size.depth_or_array_layers,
+		strong_count
+	)
+}
+
+fn gpu_textures(world: &mut World, _args: &[String]) -> Result<String> {
+	let lines = world
+		.resource::<Gpu>()
+		.tracked_textures
+		.alive()
+		.iter()
+		.filter_map(|weak| {
+			// Read the count before upgrading, which adds one
+			let strong_count = weak.strong_count();
+			weak.upgrade().map(|tex| describe_texture(&tex, strong_count))
+		})
+		.collect::<Vec<_>>();
+
+	Ok(format!("{} live textures\n{}", lines.len(), lines.join("\n")))
+}
 
-	render_target.command_queue.push(encoder.finish());
+/// Every texture has to be back to the strong count it had before the swap,
+/// or dropped. Anything else is still held by something built for the old
+/// renderer, e.g. a forgotten bind group.
+fn gpu_leakcheck(world: &mut World, _args: &[String]) -> Result<String> {
+	let before = world
+		.resource::<Gpu>()
+		.tracked_textures
+		.alive()
+		.into_iter()
+		.map(|weak| (weak.strong_count(), weak))
+		.collect::<Vec<_>>();
+
+	let renderer = world.resource::<ComputeRenderer>();
+	let renderer = renderer.resized(world.resource::<Gpu>(), renderer.resolution())?;
+	swap_compute_renderer(world, renderer);
+
+	let leaks = before
+		.iter()
+		.filter_map(|(count_before, weak)| {
+			let count_after = weak.strong_count();
+			if count_after == 0 || count_after == *count_before {
+				return None;
+			}
+
+			weak.upgrade()
+				.map(|tex| format!("{} (was {})", describe_texture(&tex, count_after), count_before))
+		})
+		.collect::<Vec<_>>();
+
+	if leaks.is_empty() {
+		Ok(format!("No leaks in {} textures", before.len()))
+	} else {
+		Ok(format!("{} possible leaks\n{}", leaks.len(), leaks.join("\n")))
+	}
 }
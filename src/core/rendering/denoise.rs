@@ -0,0 +1,448 @@
+use anyhow::{Context, Result};
+use bevy_ecs::{
+	system::{Query, Res, ResMut},
+	world::{Mut, World},
+};
+use brainrot::{
+	bevy::{self, App, Plugin},
+	vec2,
+	vek::Vec2,
+};
+use log::info;
+use pbr_tracer_derive::ShaderStruct;
+use wgpu::{
+	Buffer, CommandEncoderDescriptor, ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor,
+	PipelineLayoutDescriptor, SamplerBorderColor, ShaderStages, StorageTextureAccess, TextureAspect, TextureUsages,
+};
+
+use super::{
+	accumulation::Accumulation,
+	composite::CompositeRenderer,
+	compute::{ComputeRenderer, RendererSwapHooks},
+};
+use crate::{
+	core::{
+		entity_label::EntityLabel,
+		gameloop::{PreRender, Render},
+		gpu::Gpu,
+		params,
+		render_target::RenderTarget,
+	},
+	libs::{
+		buffer::{
+			self,
+			ping_pong_texture::PingPongParity,
+			storage_texture_buffer::StorageTexture,
+			uniform_buffer::{UniformBuffer, UniformBufferDescriptor},
+			BufferMappingApplicable, ShaderType,
+		},
+		shader::{CompiledShader, ShaderBuilder},
+		smart_arc::Sarc,
+		texture::{SamplerEdges, Tex, TexDescriptor, TexSamplerDescriptor, TextureAssetDimensions},
+	},
+	ShaderAssets,
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Smooths the noise out of the compute renderer's output before the
+/// composite draws it, with an edge-avoiding À-Trous wavelet filter (see
+/// `denoise/atrous.wgsl`) guided by the renderer's `output_normal` and
+/// `output_depth`. Renderers without them aren't denoised.
+///
+/// The filter backs off as the frames are accumulated, since the noise goes
+/// down on its own. The [`DenoiseSettings`] are tweakable with the params,
+/// `denoise.enabled` being there to compare with and without. It's off by
+/// default, a renderer that isn't noisy would only get blurrier.
+///
+/// The color it filters is the renderer's final one, after its post
+/// processing. Needs to be added after the compute and composite renderers.
+#[derive(Default)]
+pub struct DenoisePlugin {
+	pub settings: DenoiseSettings,
+}
+
+impl Plugin for DenoisePlugin {
+	fn build(&self, app: &mut App) {
+		let gpu = app.world.resource::<Gpu>();
+
+		let settings = DenoiseSettings {
+			iterations: self.settings.iterations.clamp(1, Denoiser::MAX_ITERATIONS),
+			..self.settings
+		};
+		let settings_buffer = Sarc::new(UniformBuffer::raw_buffer_from_data(gpu, &settings, None));
+		buffer::spawn_buffer(app, settings, settings_buffer.clone());
+
+		let denoiser = Denoiser::new(gpu, app.world.resource::<ComputeRenderer>(), settings_buffer);
+		app.world.insert_resource(denoiser);
+		rebind_composite(&mut app.world);
+
+		// The new renderer comes with new output textures, after the composite
+		// bound the unfiltered one
+		app.world
+			.resource_mut::<RendererSwapHooks>()
+			.after(|world: &mut World| {
+				let denoiser = world
+					.resource::<Denoiser>()
+					.with_renderer(world.resource::<Gpu>(), world.resource::<ComputeRenderer>());
+				world.insert_resource(denoiser);
+				rebind_composite(world);
+			});
+
+		params::registry(app)
+			.register_bool(
+				"denoise.enabled",
+				"Whether the render is denoised before it's drawn",
+				|world| Ok(denoise_settings(world)?.enabled != 0),
+				|world, enabled| {
+					denoise_settings(world)?.enabled = enabled as u32;
+					rebind_composite(world);
+					Ok(())
+				},
+			)
+			.register_int(
+				"denoise.iterations",
+				"How many times the filter runs, each one twice as wide as the last",
+				1..=Denoiser::MAX_ITERATIONS as i64,
+				|world| Ok(denoise_settings(world)?.iterations as i64),
+				|world, iterations| {
+					denoise_settings(world)?.iterations = iterations as u32;
+					rebind_composite(world);
+					Ok(())
+				},
+			)
+			.register_float(
+				"denoise.sigma_color",
+				"How different two colors can be and still be averaged, for a single frame",
+				0.0..=10.0,
+				|world| Ok(denoise_settings(world)?.sigma_color),
+				|world, sigma| {
+					denoise_settings(world)?.sigma_color = sigma;
+					Ok(())
+				},
+			)
+			.register_float(
+				"denoise.sigma_normal",
+				"How sharply the filter stops at creases, the bigger the sharper",
+				0.0..=512.0,
+				|world| Ok(denoise_settings(world)?.sigma_normal),
+				|world, sigma| {
+					denoise_settings(world)?.sigma_normal = sigma;
+					Ok(())
+				},
+			)
+			.register_float(
+				"denoise.sigma_depth",
+				"How far apart (relative to the distance) two pixels can be and still be averaged",
+				0.0..=10.0,
+				|world| Ok(denoise_settings(world)?.sigma_depth),
+				|world, sigma| {
+					denoise_settings(world)?.sigma_depth = sigma;
+					Ok(())
+				},
+			);
+
+		app.add_systems(PreRender, sync_accumulated_samples);
+		app.add_systems(Render, render.in_set(DenoisePass));
+	}
+}
+
+#[derive(bevy::SystemSet, Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct DenoisePass;
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// The uniform of the denoiser, bound as `denoise`
+#[repr(C)]
+#[derive(ShaderStruct, bevy::Component, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+pub struct DenoiseSettings {
+	/// 0 or 1, the composite draws the unfiltered output when 0
+	pub enabled: u32,
+	/// How many passes of the filter run, up to [`Denoiser::MAX_ITERATIONS`]
+	pub iterations: u32,
+	/// How far apart two colors can be and still be averaged, on the first
+	/// frame. Shrinks with every iteration and every accumulated frame.
+	pub sigma_color: f32,
+	/// The exponent of the dot product of the normals
+	pub sigma_normal: f32,
+	/// How far apart two depths can be and still be averaged, relative to the
+	/// depth and the distance between the pixels
+	pub sigma_depth: f32,
+	/// Kept in sync with the [`Accumulation`]
+	pub accumulated_samples: u32,
+	#[shader(skip)]
+	_padding: [u32; 2],
+}
+impl EntityLabel for DenoiseSettings {}
+
+impl Default for DenoiseSettings {
+	fn default() -> Self {
+		Self {
+			enabled: 0,
+			iterations: 4,
+			sigma_color: 0.5,
+			sigma_normal: 64.0,
+			sigma_depth: 0.1,
+			accumulated_samples: 0,
+			_padding: Default::default(),
+		}
+	}
+}
+
+#[derive(bevy::Resource)]
+pub struct Denoiser {
+	/// One per iteration, each with its own step size and textures
+	passes: Vec<(CompiledShader, ComputePipeline)>,
+	workgroups: Vec2<u32>,
+	/// The unfiltered color, then where the iterations go back and forth
+	color: Option<Sarc<Tex>>,
+	ping_pong: Vec<Sarc<Tex>>,
+	settings_buffer: Sarc<Buffer>,
+}
+
+impl Denoiser {
+	pub const MAX_ITERATIONS: u32 = 5;
+
+	const WORKGROUP_SIZE: u32 = 8;
+
+	pub fn new(gpu: &Gpu, compute_renderer: &ComputeRenderer, settings_buffer: Sarc<Buffer>) -> Self {
+		let mut denoiser = Self {
+			passes: Vec::new(),
+			workgroups: vec2!(0),
+			color: None,
+			ping_pong: Vec::new(),
+			settings_buffer,
+		};
+
+		if let Err(error) = denoiser.build(gpu, compute_renderer) {
+			info!("No denoising: {:#}", error);
+		}
+
+		denoiser
+	}
+
+	/// The same denoiser, for the output textures of another compute renderer
+	pub fn with_renderer(&self, gpu: &Gpu, compute_renderer: &ComputeRenderer) -> Self {
+		Self::new(gpu, compute_renderer, self.settings_buffer.clone())
+	}
+
+	/// Whether the compute renderer has everything the filter needs
+	pub fn is_available(&self) -> bool {
+		!self.passes.is_empty()
+	}
+
+	/// What the composite should draw: the last iteration's texture, or the
+	/// unfiltered output when disabled
+	pub fn output(&self, settings: &DenoiseSettings) -> Option<&Sarc<Tex>> {
+		if settings.enabled == 0 || !self.is_available() {
+			return self.color.as_ref();
+		}
+
+		let iterations = settings.iterations.clamp(1, Self::MAX_ITERATIONS);
+		self.ping_pong.get((iterations as usize - 1) % 2)
+	}
+
+	fn build(&mut self, gpu: &Gpu, compute_renderer: &ComputeRenderer) -> Result<()> {
+		let color = compute_renderer
+			.output_textures
+			.first()
+			.context("The compute renderer has no output texture")?
+			.clone();
+		self.color = Some(color.clone());
+
+		let normal = compute_renderer
+			.output_texture("output_normal")
+			.context("The renderer doesn't output its normals")?
+			.clone();
+		let depth = compute_renderer
+			.output_texture("output_depth")
+			.context("The renderer doesn't output its depth")?
+			.clone();
+
+		let size = compute_renderer.resolution().0;
+
+		// The same format and sampler as the output, which the composite can already
+		// sample
+		let texture = |label| {
+			Tex::create(
+				gpu,
+				TexDescriptor {
+					label,
+					dimensions: TextureAssetDimensions::D2(size),
+					format: color.format(),
+					usage: Some(TextureUsages::STORAGE_BINDING | TextureUsages::COPY_SRC),
+					aspect: TextureAspect::All,
+				},
+				Some(TexSamplerDescriptor {
+					filter: compute_renderer.filter_mode(),
+					edges: SamplerEdges::ClampToColor(SamplerBorderColor::TransparentBlack),
+					compare: None,
+				}),
+			)
+			.tracked(gpu)
+		};
+		let ping_pong = vec![texture("Denoise ping"), texture("Denoise pong")];
+
+		// 0 reads the output, then they take turns
+		let passes = (0..Self::MAX_ITERATIONS)
+			.map(|iteration| {
+				let source = match iteration {
+					0 => color.clone(),
+					_ => ping_pong[(iteration as usize - 1) % 2].clone(),
+				};
+				let target = ping_pong[iteration as usize % 2].clone();
+
+				self.build_pass(gpu, iteration, source, target, normal.clone(), depth.clone())
+			})
+			.collect::<Result<Vec<_>>>()?;
+
+		self.passes = passes;
+		self.ping_pong = ping_pong;
+		self.workgroups = <Vec2<u32>>::from(size) / Self::WORKGROUP_SIZE + vec2!(1);
+
+		Ok(())
+	}
+
+	fn build_pass(
+		&self,
+		gpu: &Gpu,
+		iteration: u32,
+		source: Sarc<Tex>,
+		target: Sarc<Tex>,
+		normal: Sarc<Tex>,
+		depth: Sarc<Tex>,
+	) -> Result<(CompiledShader, ComputePipeline)> {
+		let read_only = |var_name, tex| StorageTexture::FromTex {
+			var_name,
+			access: StorageTextureAccess::ReadOnly,
+			tex,
+		};
+
+		let shader = ShaderBuilder::new()
+			.include_path("denoise/atrous.wgsl")
+			.define("WORKGROUP_SIZE", format!("{}", Self::WORKGROUP_SIZE))
+			.include_value("denoise_iteration", iteration)
+			.include_buffer(UniformBufferDescriptor::FromBuffer::<DenoiseSettings, _> {
+				var_name: "denoise",
+				buffer: self.settings_buffer.clone(),
+			})
+			.include_buffer(read_only("denoise_source", source))
+			.include_buffer(read_only("denoise_normal", normal))
+			.include_buffer(read_only("denoise_depth", depth))
+			.include_buffer(StorageTexture::FromTex {
+				var_name: "denoise_target",
+				access: StorageTextureAccess::WriteOnly,
+				tex: target,
+			})
+			.build(
+				gpu,
+				format!("Denoise shader {}", iteration),
+				&ShaderAssets,
+				ShaderStages::COMPUTE,
+				0,
+			)?;
+
+		let pipeline_layout = gpu.device.create_pipeline_layout(&PipelineLayoutDescriptor {
+			label: Some("Denoise pipeline layout"),
+			bind_group_layouts: &shader.layouts(),
+			push_constant_ranges: &[],
+		});
+
+		let pipeline = shader.create_pipeline(gpu, || {
+			gpu.device.create_compute_pipeline(&ComputePipelineDescriptor {
+				label: Some("Denoise pipeline"),
+				layout: Some(&pipeline_layout),
+				module: &shader.shader_module,
+				entry_point: "main",
+			})
+		});
+
+		Ok((shader, pipeline))
+	}
+}
+
+fn denoise_settings(world: &mut World) -> Result<Mut<'_, DenoiseSettings>> {
+	world
+		.query::<&mut DenoiseSettings>()
+		.get_single_mut(world)
+		.context("The denoiser isn't tweakable")
+}
+
+/// Point the composite at the [`Denoiser::output`], if it isn't already
+fn rebind_composite(world: &mut World) {
+	let Ok(settings) = world.query::<&DenoiseSettings>().get_single(world).copied() else {
+		return;
+	};
+	let Some(output) = world.resource::<Denoiser>().output(&settings).cloned() else {
+		return;
+	};
+
+	let composite_renderer = world.resource::<CompositeRenderer>();
+	if composite_renderer.output_texture() == &output {
+		return;
+	}
+
+	let composite_renderer =
+		composite_renderer.with_output_texture(world.resource::<Gpu>(), world.resource::<RenderTarget>().size, output);
+	world.insert_resource(composite_renderer);
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+fn sync_accumulated_samples(accumulation: Option<Res<Accumulation>>, mut settings: Query<&mut DenoiseSettings>) {
+	let samples = accumulation.map_or(0, |accumulation| accumulation.samples());
+
+	for mut settings in settings.iter_mut() {
+		settings.accumulated_samples = samples;
+	}
+}
+
+/// Runs right after the compute renderer, on its output
+fn render(
+	denoiser: Res<Denoiser>,
+	settings: Query<&DenoiseSettings>,
+	mut render_target: ResMut<RenderTarget<'static>>,
+	gpu: Res<Gpu>,
+	parity: Res<PingPongParity>,
+) {
+	let Ok(settings) = settings.get_single() else {
+		return;
+	};
+	if settings.enabled == 0 || !denoiser.is_available() {
+		return;
+	}
+
+	let mut encoder = gpu.device.create_command_encoder(&CommandEncoderDescriptor {
+		label: Some("Denoise Command Encoder"),
+	});
+
+	{
+		// Not timed, it doesn't run when disabled
+		let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+			label: Some("Denoise Pass"),
+			timestamp_writes: None,
+		});
+
+		// Every dispatch sees the writes of the previous ones
+		let iterations = settings.iterations.clamp(1, Denoiser::MAX_ITERATIONS) as usize;
+		for (shader, pipeline) in &denoiser.passes[..iterations] {
+			compute_pass.set_pipeline(pipeline);
+			compute_pass.apply_buffer_mapping(&shader.binding, *parity);
+			compute_pass.dispatch_workgroups(denoiser.workgroups.x, denoiser.workgroups.y, 1);
+		}
+	}
+
+	render_target.command_queue.push(encoder.finish());
+}
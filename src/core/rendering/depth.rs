@@ -0,0 +1,139 @@
+use bevy_ecs::{
+	entity::Entity,
+	schedule::IntoSystemConfigs,
+	system::{Query, Res},
+};
+use brainrot::{
+	bevy::{self, App},
+	vek::Extent2,
+};
+use wgpu::{LoadOp, Operations, RenderPassDepthStencilAttachment, StoreOp, TextureView};
+
+use super::render::PreRenderPass;
+use crate::{
+	core::{
+		entity_label,
+		gameloop::Render,
+		gpu::Gpu,
+		render_target::{RenderTarget, WindowRenderTarget},
+	},
+	libs::texture::Tex,
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Add a depth attachment to the window render target, for the raster passes
+/// that need one. Can be called by several plugins, only one attachment is
+/// created.
+pub fn request_window_depth(app: &mut App) {
+	register_depth_updates(app);
+
+	let target_entity = entity_label::single_entity::<WindowRenderTarget>(&mut app.world)
+		.unwrap_or_else(|e| panic!("{}, add the WindowRenderTargetPlugin before requesting a depth", e));
+
+	if app.world.get::<DepthAttachment>(target_entity).is_none() {
+		app.world.entity_mut(target_entity).insert(DepthAttachment {
+			label: "Window Depth",
+			size: DepthSize::Target,
+			tex: None,
+		});
+	}
+}
+
+/// Spawn a depth texture of a fixed size that isn't attached to a render
+/// target, e.g. for a shadow map
+pub fn spawn_fixed_depth(app: &mut App, label: &'static str, size: Extent2<u32>) -> Entity {
+	register_depth_updates(app);
+
+	app.world
+		.spawn(DepthAttachment {
+			label,
+			size: DepthSize::Fixed(size),
+			tex: None,
+		})
+		.id()
+}
+
+#[derive(bevy::Resource)]
+struct DepthUpdatesRegistered;
+
+fn register_depth_updates(app: &mut App) {
+	// The system goes through all the attachments at once, so only add it once
+	if !app.world.contains_resource::<DepthUpdatesRegistered>() {
+		app.world.insert_resource(DepthUpdatesRegistered);
+		app.add_systems(Render, update_depth_attachments.in_set(PreRenderPass));
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DepthSize {
+	/// Follows the size of the render target on the same entity
+	Target,
+	Fixed(Extent2<u32>),
+}
+
+/// A depth texture that is (re)created at the start of every render pass when
+/// it doesn't match its size anymore. It isn't initialized, the first pass
+/// using it should clear it.
+///
+/// The texture is only valid for the current frame, so passes should get the
+/// view through this component every time instead of keeping it around.
+#[derive(bevy::Component)]
+pub struct DepthAttachment {
+	pub label: &'static str,
+	pub size: DepthSize,
+	tex: Option<Tex>,
+}
+
+impl DepthAttachment {
+	/// `None` until the first render pass after the attachment was requested
+	pub fn tex(&self) -> Option<&Tex> {
+		self.tex.as_ref()
+	}
+
+	pub fn view(&self) -> Option<&TextureView> {
+		self.tex.as_ref().map(|tex| &tex.view)
+	}
+
+	/// The attachment to put in a `RenderPassDescriptor`, cleared to the far
+	/// plane if `clear` is set
+	pub fn attachment(&self, clear: bool) -> Option<RenderPassDepthStencilAttachment<'_>> {
+		self.view().map(|view| RenderPassDepthStencilAttachment {
+			view,
+			depth_ops: Some(Operations {
+				load: if clear { LoadOp::Clear(1.0) } else { LoadOp::Load },
+				store: StoreOp::Store,
+			}),
+			stencil_ops: None,
+		})
+	}
+}
+
+fn update_depth_attachments(gpu: Res<Gpu>, mut q: Query<(&mut DepthAttachment, Option<&RenderTarget>)>) {
+	for (mut depth, render_target) in q.iter_mut() {
+		let size = match (depth.size, render_target) {
+			(DepthSize::Fixed(size), _) => size,
+			(DepthSize::Target, Some(render_target)) => render_target.size.0,
+			(DepthSize::Target, None) => continue,
+		};
+
+		let up_to_date = depth
+			.tex
+			.as_ref()
+			.is_some_and(|tex| tex.size().width == size.w && tex.size().height == size.h);
+
+		if !up_to_date {
+			depth.tex = Some(Tex::create_depth_texture(&gpu, size, depth.label));
+		}
+	}
+}
@@ -0,0 +1,196 @@
+use anyhow::{ensure, Context, Result};
+use bevy_ecs::{
+	event::EventReader,
+	query::With,
+	schedule::IntoSystemConfigs,
+	system::{Query, Res},
+	world::{Mut, World},
+};
+use brainrot::{
+	bevy::{self, App, Plugin},
+	vek::Vec2,
+};
+use log::{info, warn};
+use pbr_tracer_derive::ShaderStruct;
+use wgpu::TextureFormat;
+
+use super::{camera_view::CameraView, compute::ComputeRenderer};
+use crate::{
+	core::{
+		camera::ActiveCamera,
+		console::is_console_closed,
+		entity_label::EntityLabel,
+		events::KeyboardInputEvent,
+		gameloop::Update,
+		gpu::Gpu,
+		key_bindings::{Action, KeyBindings},
+		params,
+	},
+	libs::{
+		buffer::{self, uniform_buffer::UniformBuffer, ShaderType},
+		smart_arc::Sarc,
+	},
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Spawns the [`DofSettings`] uniform, which the compute renderer binds as
+/// `dof`, and makes them tweakable with the params. Needs to be added before
+/// the compute renderer, which binds disabled settings without it.
+///
+/// [`Action::Focus`] sets the focus distance to whatever is under the
+/// crosshair, i.e. in the middle of the rendered image.
+#[derive(Default)]
+pub struct DepthOfFieldPlugin {
+	pub settings: DofSettings,
+}
+
+impl Plugin for DepthOfFieldPlugin {
+	fn build(&self, app: &mut App) {
+		let gpu = app.world.resource::<Gpu>();
+
+		let dof_buffer = Sarc::new(UniformBuffer::raw_buffer_from_data(gpu, &self.settings, None));
+		buffer::spawn_buffer(app, self.settings, dof_buffer);
+
+		params::registry(app)
+			.register_bool(
+				"dof.enabled",
+				"Blur what's out of focus, once the frames are accumulated",
+				|world| Ok(dof_settings(world)?.enabled != 0),
+				|world, enabled| {
+					dof_settings(world)?.enabled = enabled as u32;
+					Ok(())
+				},
+			)
+			.register_float(
+				"dof.aperture",
+				"The radius of the lens in scene units, the bigger the blurrier",
+				0.0..=10.0,
+				|world| Ok(dof_settings(world)?.aperture),
+				|world, aperture| {
+					dof_settings(world)?.aperture = aperture;
+					Ok(())
+				},
+			)
+			.register_float(
+				"dof.focus_distance",
+				"How far in front of the camera things are sharp",
+				0.01..=10000.0,
+				|world| Ok(dof_settings(world)?.focus_distance),
+				|world, distance| {
+					dof_settings(world)?.focus_distance = distance;
+					Ok(())
+				},
+			);
+
+		app.add_systems(Update, focus_under_crosshair.run_if(is_console_closed));
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Thin lens depth of field, see `camera_ray` in `mpr_common.wgsl`. Every ray
+/// starts on a disc around the camera and goes through the point of the focal
+/// plane that the pinhole ray would go through.
+///
+/// A single frame only goes through the center of the lens, which is sharp.
+/// The blur comes from the frames that are accumulated, by a progressive
+/// renderer or a [`HighQualityCapture`](super::capture::HighQualityCapture).
+///
+/// Changing this component changes the uniform and starts the accumulation
+/// over.
+#[repr(C)]
+#[derive(ShaderStruct, bevy::Component, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+pub struct DofSettings {
+	/// The radius of the lens in scene units, 0 for a pinhole
+	pub aperture: f32,
+	/// How far from the camera the focal plane is, along its view direction
+	pub focus_distance: f32,
+	/// 0 or 1
+	pub enabled: u32,
+	#[shader(skip)]
+	_padding: u32,
+}
+impl EntityLabel for DofSettings {}
+
+impl Default for DofSettings {
+	fn default() -> Self {
+		Self {
+			aperture: 0.05,
+			focus_distance: 5.0,
+			enabled: 0,
+			_padding: 0,
+		}
+	}
+}
+
+fn dof_settings(world: &mut World) -> Result<Mut<'_, DofSettings>> {
+	world
+		.query::<&mut DofSettings>()
+		.get_single_mut(world)
+		.context("The depth of field isn't tweakable")
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// The distance to what's in the middle of the last rendered frame, read back
+/// from the renderer's `output_depth`
+pub fn depth_under_crosshair(gpu: &Gpu, compute_renderer: &ComputeRenderer, view: &CameraView) -> Result<f32> {
+	let output_depth = compute_renderer
+		.output_texture("output_depth")
+		.context("The renderer doesn't output its depth")?;
+	ensure!(
+		output_depth.format() == TextureFormat::Rgba32Float,
+		"Expected an Rgba32Float depth output, not {:?}",
+		output_depth.format()
+	);
+
+	// The pixel whose ray goes straight through the middle, see camera_ray()
+	let resolution = compute_renderer.resolution();
+	let center = Vec2::new(resolution.w / 2, resolution.h / 2);
+
+	let bytes = output_depth.read_texel(gpu, center);
+	// The depth is stored divided by z_far
+	let depth = bytemuck::pod_read_unaligned::<f32>(&bytes[..4]);
+
+	ensure!(depth > 0.0 && depth < 1.0, "There's nothing under the crosshair");
+
+	Ok(depth * view.z_far)
+}
+
+fn focus_under_crosshair(
+	gpu: Res<Gpu>,
+	compute_renderer: Res<ComputeRenderer>,
+	cameras: Query<&CameraView, With<ActiveCamera>>,
+	mut settings: Query<&mut DofSettings>,
+	mut keyboard_events: EventReader<KeyboardInputEvent>,
+	key_bindings: Res<KeyBindings>,
+) {
+	if !key_bindings.has_pressed(Action::Focus, keyboard_events.read()) {
+		return;
+	}
+
+	let (Ok(view), Ok(mut settings)) = (cameras.get_single(), settings.get_single_mut()) else {
+		return;
+	};
+
+	match depth_under_crosshair(&gpu, &compute_renderer, view) {
+		Ok(distance) => {
+			settings.focus_distance = distance;
+			info!("Focused at {:.2}", distance);
+		}
+		Err(e) => warn!("Couldn't focus: {}", e),
+	}
+}
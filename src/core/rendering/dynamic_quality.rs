@@ -0,0 +1,306 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use bevy_ecs::system::{Res, ResMut};
+use brainrot::bevy::{self, App, Plugin};
+use log::info;
+
+use super::{globals::RenderSettings, gpu_timers::FrameTimings, render_scale::RenderScale};
+use crate::{
+	core::{
+		display::WindowSettings,
+		gameloop::{Time, Update},
+		params,
+	},
+	libs::metrics::{format_duration_ms, format_ms, RollingStats},
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Lowers the render quality when the GPU can't hold the target frame rate,
+/// and raises it back once there is room again.
+///
+/// Goes by the GPU frame times, so it does nothing when the GPU doesn't
+/// support timestamp queries. Needs to be added after the display, globals and
+/// GPU timers plugins, and after the render scale plugin for the first rungs.
+/// Starts paused when the window is hidden, since that means an automated run
+/// that needs stable settings.
+///
+/// The target can be changed with the `quality_target_fps` param.
+pub struct DynamicQualityPlugin {
+	/// At least 1
+	pub target_fps: u32,
+}
+
+impl Default for DynamicQualityPlugin {
+	fn default() -> Self {
+		Self { target_fps: 60 }
+	}
+}
+
+impl Plugin for DynamicQualityPlugin {
+	fn build(&self, app: &mut App) {
+		assert!(
+			self.target_fps > 0,
+			"The target_fps of DynamicQualityPlugin needs to be at least 1"
+		);
+
+		let visible = app.world.resource::<WindowSettings>().visible;
+
+		app.world.insert_resource(DynamicQuality {
+			paused: !visible,
+			budget: DynamicQuality::budget_for(self.target_fps),
+			rung: QualityRung::Full,
+			base_render_scale: None,
+			smoothed_frame_time: None,
+			frame_times: RollingStats::new(),
+			last_frame: 0,
+			frames_over_budget: 0,
+			under_budget_since: None,
+			last_change: Instant::now(),
+		});
+
+		params::registry(app).register_int(
+			"quality_target_fps",
+			"The frame rate the dynamic quality tries to hold",
+			1..=1000,
+			|world| Ok(world.resource::<DynamicQuality>().target_fps() as i64),
+			|world, fps| world.resource_mut::<DynamicQuality>().set_target_fps(fps as u32),
+		);
+
+		app.add_systems(Update, adjust_quality);
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// The steps the quality goes down, from best to worst
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum QualityRung {
+	#[default]
+	Full,
+	/// Renders at a lower [`RenderScale`]
+	LowerResolution,
+	MinimumResolution,
+	/// Also at the minimum render scale
+	FewerMarchSteps,
+	MinimumMarchSteps,
+}
+
+impl QualityRung {
+	const LADDER: [QualityRung; 5] = [
+		QualityRung::Full,
+		QualityRung::LowerResolution,
+		QualityRung::MinimumResolution,
+		QualityRung::FewerMarchSteps,
+		QualityRung::MinimumMarchSteps,
+	];
+
+	fn index(self) -> usize {
+		Self::LADDER.iter().position(|rung| *rung == self).unwrap()
+	}
+
+	fn lower(self) -> Option<Self> {
+		Self::LADDER.get(self.index() + 1).copied()
+	}
+
+	fn higher(self) -> Option<Self> {
+		self.index().checked_sub(1).map(|index| Self::LADDER[index])
+	}
+
+	fn march_steps_scale(self) -> f32 {
+		match self {
+			QualityRung::FewerMarchSteps => 0.5,
+			QualityRung::MinimumMarchSteps => 0.25,
+			_ => 1.0,
+		}
+	}
+
+	/// Relative to the render scale the rungs started lowering it from
+	fn render_scale_factor(self) -> f32 {
+		match self {
+			QualityRung::Full => 1.0,
+			QualityRung::LowerResolution => 0.75,
+			QualityRung::MinimumResolution | QualityRung::FewerMarchSteps | QualityRung::MinimumMarchSteps => 0.5,
+		}
+	}
+}
+
+#[derive(bevy::Resource, Clone, Debug)]
+pub struct DynamicQuality {
+	/// Keeps the current rung as it is
+	pub paused: bool,
+	/// The GPU time a frame can take
+	pub budget: Duration,
+	rung: QualityRung,
+	/// The render scale from before the rungs lowered it, to go back to
+	base_render_scale: Option<RenderScale>,
+
+	smoothed_frame_time: Option<Duration>,
+	frame_times: RollingStats<{ DynamicQuality::STATS_WINDOW }>,
+	last_frame: u64,
+	frames_over_budget: u32,
+	under_budget_since: Option<Instant>,
+	last_change: Instant,
+}
+
+impl DynamicQuality {
+	/// How much a new frame time weighs in the smoothed one
+	const SMOOTHING: f32 = 0.1;
+
+	/// How many frames in a row have to go over the budget before lowering the
+	/// quality
+	const FRAMES_BEFORE_LOWERING: u32 = 30;
+
+	/// The fraction of the budget the frames have to stay under before raising
+	/// the quality, so that raising it doesn't immediately go over the budget
+	/// again
+	const RAISE_HEADROOM: f32 = 0.7;
+
+	/// How long the frames have to stay under the headroom before raising the
+	/// quality
+	const STABILITY_PERIOD: Duration = Duration::from_secs(5);
+
+	/// How long a rung is kept at least, whichever direction comes next
+	const MIN_DWELL: Duration = Duration::from_secs(2);
+
+	pub const STATS_WINDOW: usize = 120;
+
+	fn budget_for(target_fps: u32) -> Duration {
+		Duration::from_secs(1) / target_fps
+	}
+
+	pub fn rung(&self) -> QualityRung {
+		self.rung
+	}
+
+	pub fn target_fps(&self) -> u32 {
+		(1.0 / self.budget.as_secs_f32()).round() as u32
+	}
+
+	pub fn set_target_fps(&mut self, target_fps: u32) -> Result<()> {
+		if target_fps == 0 {
+			bail!("The target fps needs to be at least 1");
+		}
+
+		self.budget = Self::budget_for(target_fps);
+		Ok(())
+	}
+
+	pub fn smoothed_frame_time(&self) -> Option<Duration> {
+		self.smoothed_frame_time
+	}
+
+	/// The last GPU frame times, in milliseconds. Only for reporting, the rung
+	/// goes by the smoothed frame time.
+	pub fn frame_times(&self) -> &RollingStats<{ DynamicQuality::STATS_WINDOW }> {
+		&self.frame_times
+	}
+
+	/// Take a new GPU frame time into account, returning the new rung if it
+	/// should change
+	fn observe(&mut self, frame_time: Duration) -> Option<QualityRung> {
+		let smoothed = match self.smoothed_frame_time {
+			Some(smoothed) => smoothed.mul_f32(1.0 - Self::SMOOTHING) + frame_time.mul_f32(Self::SMOOTHING),
+			None => frame_time,
+		};
+		self.smoothed_frame_time = Some(smoothed);
+		self.frame_times.push(frame_time.as_secs_f32() * 1000.0);
+
+		if smoothed > self.budget {
+			self.frames_over_budget += 1;
+			self.under_budget_since = None;
+		} else if smoothed < self.budget.mul_f32(Self::RAISE_HEADROOM) {
+			self.frames_over_budget = 0;
+			self.under_budget_since.get_or_insert_with(Instant::now);
+		} else {
+			// In between, neither direction is justified
+			self.frames_over_budget = 0;
+			self.under_budget_since = None;
+		}
+
+		if self.last_change.elapsed() < Self::MIN_DWELL {
+			return None;
+		}
+
+		if self.frames_over_budget >= Self::FRAMES_BEFORE_LOWERING {
+			self.rung.lower()
+		} else if self
+			.under_budget_since
+			.is_some_and(|since| since.elapsed() >= Self::STABILITY_PERIOD)
+		{
+			self.rung.higher()
+		} else {
+			None
+		}
+	}
+
+	fn change_to(&mut self, rung: QualityRung) {
+		self.rung = rung;
+		self.last_change = Instant::now();
+		self.frames_over_budget = 0;
+		self.under_budget_since = None;
+	}
+
+	fn apply(&mut self, render_settings: &mut RenderSettings, render_scale: Option<&mut RenderScale>) {
+		render_settings.march_steps_scale = self.rung.march_steps_scale();
+
+		let Some(render_scale) = render_scale else {
+			return;
+		};
+
+		let factor = self.rung.render_scale_factor();
+		if factor < 1.0 {
+			let base = *self.base_render_scale.get_or_insert(*render_scale);
+			*render_scale = RenderScale((base.0 * factor).max(RenderScale::MIN));
+		} else if let Some(base) = self.base_render_scale.take() {
+			*render_scale = base;
+		}
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+fn adjust_quality(
+	mut dynamic_quality: ResMut<DynamicQuality>,
+	mut render_settings: ResMut<RenderSettings>,
+	render_scale: Option<ResMut<RenderScale>>,
+	frame_timings: Res<FrameTimings>,
+	time: Res<Time>,
+) {
+	// Updates can run several times per frame, only look at every frame once
+	if dynamic_quality.paused || frame_timings.passes.is_empty() || dynamic_quality.last_frame == time.counter_frame {
+		return;
+	}
+
+	dynamic_quality.last_frame = time.counter_frame;
+
+	let frame_time = frame_timings.passes.values().sum::<Duration>();
+
+	if let Some(rung) = dynamic_quality.observe(frame_time) {
+		info!(
+			"Dynamic quality: {:?} -> {:?} (GPU frame time {}, p99 {}, budget {})",
+			dynamic_quality.rung,
+			rung,
+			format_duration_ms(dynamic_quality.smoothed_frame_time.unwrap_or_default()),
+			format_ms(dynamic_quality.frame_times.p99().unwrap_or_default()),
+			format_duration_ms(dynamic_quality.budget)
+		);
+
+		dynamic_quality.change_to(rung);
+		// The resolution follows before the frame is rendered
+		dynamic_quality.apply(&mut render_settings, render_scale.map(|scale| scale.into_inner()));
+	}
+}
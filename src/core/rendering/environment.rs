@@ -0,0 +1,597 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use bevy_ecs::{
+	event::EventReader,
+	schedule::IntoSystemConfigs,
+	system::{Res, ResMut},
+	world::World,
+};
+use bevy_tasks::{futures_lite::future, AsyncComputeTaskPool, Task, TaskPool};
+use brainrot::{
+	bevy::{self, App, Plugin},
+	vek::Extent2,
+};
+use image::{
+	imageops::{self, FilterType},
+	Rgba32FImage,
+};
+use log::{error, info};
+use pbr_tracer_derive::ShaderStruct;
+use wgpu::{
+	Buffer, CommandBuffer, CommandEncoder, CommandEncoderDescriptor, ComputePassDescriptor, ComputePipeline,
+	ComputePipelineDescriptor, FilterMode, ImageCopyTexture, Origin3d, ShaderStages, StorageTextureAccess,
+	TextureAspect, TextureFormat, TextureUsages,
+};
+use winit::event::WindowEvent;
+
+use super::{
+	chunked_upload::{ChunkedUploader, UploadHandle},
+	compute::ComputeRenderPass,
+	render::InnerRenderPass,
+};
+use crate::{
+	core::{
+		console,
+		events::WinitWindowEvent,
+		gameloop::{Render, Update},
+		gpu::Gpu,
+		render_target::RenderTarget,
+	},
+	libs::{
+		buffer::{
+			ping_pong_texture::PingPongParity,
+			sampled_texture_buffer::SampledTexture,
+			storage_texture_buffer::StorageTexture,
+			uniform_buffer::{UniformBuffer, UniformBufferDescriptor},
+			BufferMappingApplicable, ShaderType,
+		},
+		shader::{CompiledShader, ShaderBuilder},
+		smart_arc::Sarc,
+		texture::{self, SamplerEdges, Tex, TexDescriptor, TexSamplerDescriptor, TextureAssetDimensions},
+	},
+	ShaderAssets,
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Image-based lighting for the [`PbrShading`](crate::fragments::shading::PbrShading),
+/// see [`EnvironmentMaps`].
+///
+/// An environment is an equirectangular image, e.g. an `.hdr`. It's loaded by
+/// dropping the file on the window, with the `environment` console command or
+/// with [`EnvironmentPrefilter::load`]. The file is decoded in the background,
+/// streamed to the GPU by the [`ChunkedUploader`] and then prefiltered a few
+/// rows per frame, so that swapping environments doesn't hitch. The maps only
+/// change once all of it is done, and loading another environment in the
+/// meantime cancels the one in progress.
+///
+/// Needs the [`ChunkedUploadPlugin`](super::chunked_upload::ChunkedUploadPlugin)
+/// to be added before it.
+pub struct EnvironmentPlugin {
+	/// How many samples every texel of the maps averages
+	pub samples: u32,
+	/// How many rows of the maps are prefiltered per frame
+	pub rows_per_frame: u32,
+}
+
+impl Default for EnvironmentPlugin {
+	fn default() -> Self {
+		Self {
+			samples: 256,
+			rows_per_frame: 8,
+		}
+	}
+}
+
+impl Plugin for EnvironmentPlugin {
+	fn build(&self, app: &mut App) {
+		assert!(
+			app.world.contains_resource::<ChunkedUploader>(),
+			"Expected a `ChunkedUploader`, add the ChunkedUploadPlugin before the EnvironmentPlugin"
+		);
+
+		let maps = EnvironmentMaps::new(app.world.resource::<Gpu>(), "Environment");
+		app.world.insert_resource(maps);
+		app.world
+			.insert_resource(EnvironmentPrefilter::new(self.samples, self.rows_per_frame));
+
+		console::register_command(
+			app,
+			"environment",
+			"environment [path | cancel]: Load an equirectangular environment, or show how far its prefiltering is",
+			environment,
+		);
+
+		app.add_systems(Update, load_dropped_environments);
+		app.add_systems(
+			Render,
+			advance_prefilter.before(ComputeRenderPass).in_set(InnerRenderPass),
+		);
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// The prefiltered environment that the shading samples, both equirectangular
+/// like the environment itself. They are black until an environment is done
+/// prefiltering, and the same textures are reused for every environment.
+#[derive(bevy::Resource, Clone)]
+pub struct EnvironmentMaps {
+	/// The environment convolved with the GGX lobe of every roughness, from 0
+	/// at mip 0 to 1 at the last mip
+	pub prefiltered: Sarc<Tex>,
+	/// The cosine-weighted average of the environment around every direction,
+	/// for the diffuse light
+	pub irradiance: Sarc<Tex>,
+}
+
+impl EnvironmentMaps {
+	pub const PREFILTERED_SIZE: Extent2<u32> = Extent2 { w: 128, h: 64 };
+	/// Down to 8x4, which is plenty for the roughest lobes
+	pub const PREFILTERED_MIP_LEVELS: u32 = 5;
+	pub const IRRADIANCE_SIZE: Extent2<u32> = Extent2 { w: 32, h: 16 };
+	/// Filterable, unlike Rgba32Float
+	pub const FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+	pub fn new(gpu: &Gpu, label: &str) -> Self {
+		let create = |label: &str, size: Extent2<u32>, mip_levels: u32| {
+			Tex::create_with_mips(
+				gpu,
+				TexDescriptor {
+					label,
+					dimensions: TextureAssetDimensions::D2(size),
+					format: Self::FORMAT,
+					usage: Some(TextureUsages::COPY_SRC),
+					aspect: TextureAspect::All,
+				},
+				mip_levels,
+				Some(SAMPLER),
+			)
+			.tracked(gpu)
+		};
+
+		Self {
+			prefiltered: create(
+				&format!("{} prefiltered", label),
+				Self::PREFILTERED_SIZE,
+				Self::PREFILTERED_MIP_LEVELS,
+			),
+			irradiance: create(&format!("{} irradiance", label), Self::IRRADIANCE_SIZE, 1),
+		}
+	}
+}
+
+const SAMPLER: TexSamplerDescriptor = TexSamplerDescriptor {
+	filter: FilterMode::Linear,
+	edges: SamplerEdges::ClampToEdge,
+	compare: None,
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Prefilters one environment at a time into the [`EnvironmentMaps`], see
+/// [`EnvironmentPlugin`]
+#[derive(bevy::Resource)]
+pub struct EnvironmentPrefilter {
+	samples: u32,
+	rows_per_frame: u32,
+	/// The name and mip chain of the environment being decoded
+	loading: Option<(String, Task<Result<Vec<Rgba32FImage>>>)>,
+	job: Option<PrefilterJob>,
+}
+
+struct PrefilterJob {
+	name: String,
+	/// Of the source's mips, nothing is prefiltered before they are all done
+	uploads: Vec<UploadHandle>,
+	/// Prefiltered into first and copied into the maps at the end, so that the
+	/// maps are never half done
+	staging: EnvironmentMaps,
+	chunk_buffer: Sarc<Buffer>,
+	passes: Vec<PrefilterPass>,
+	/// Where the next chunk starts
+	pass: usize,
+	row: u32,
+}
+
+struct PrefilterPass {
+	shader: CompiledShader,
+	pipeline: ComputePipeline,
+	size: Extent2<u32>,
+	roughness: f32,
+}
+
+/// The `prefilter_chunk` uniform, which rows the next dispatch prefilters
+#[repr(C)]
+#[derive(ShaderStruct, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+struct PrefilterChunk {
+	row_offset: u32,
+	rows: u32,
+	samples: u32,
+	roughness: f32,
+}
+
+impl EnvironmentPrefilter {
+	const WORKGROUP_SIZE: u32 = 8;
+
+	pub fn new(samples: u32, rows_per_frame: u32) -> Self {
+		Self {
+			samples: samples.max(1),
+			rows_per_frame: rows_per_frame.max(1),
+			loading: None,
+			job: None,
+		}
+	}
+
+	/// Decode the file in the background, then prefilter it like
+	/// [`load`](Self::load)
+	pub fn load_file(&mut self, path: impl Into<PathBuf>) {
+		let path = path.into();
+		let name = path
+			.file_name()
+			.map(|name| name.to_string_lossy().into_owned())
+			.unwrap_or_else(|| path.display().to_string());
+
+		self.cancel();
+		info!("Loading the environment `{}`", name);
+
+		let task = AsyncComputeTaskPool::get_or_init(TaskPool::new)
+			.spawn(async move { read_environment(&path).map(environment_mips) });
+		self.loading = Some((name, task));
+	}
+
+	/// Start uploading and prefiltering an equirectangular environment,
+	/// cancelling the one in progress. Its mip chain is made right away,
+	/// [`load_file`](Self::load_file) does that in the background.
+	pub fn load(
+		&mut self,
+		gpu: &Gpu,
+		uploader: &mut ChunkedUploader,
+		name: &str,
+		environment: Rgba32FImage,
+	) -> Result<()> {
+		self.cancel();
+		self.start(gpu, uploader, name, environment_mips(environment))
+	}
+
+	/// Returns whether there was anything to cancel. The maps keep the last
+	/// environment that was done, and the uploads of this one stop with it.
+	pub fn cancel(&mut self) -> bool {
+		let name = match (self.loading.take(), self.job.take()) {
+			(Some((name, _)), _) | (None, Some(PrefilterJob { name, .. })) => name,
+			(None, None) => return false,
+		};
+
+		info!("Cancelled the environment `{}`", name);
+		true
+	}
+
+	pub fn is_running(&self) -> bool {
+		self.loading.is_some() || self.job.is_some()
+	}
+
+	/// Whether the environment is still being streamed to the GPU
+	pub fn is_uploading(&self) -> bool {
+		self.job
+			.as_ref()
+			.is_some_and(|job| !UploadHandle::all_done(&job.uploads))
+	}
+
+	/// From 0 to 1, `None` if nothing is being prefiltered. The upload is the
+	/// first half, the prefiltering the second.
+	pub fn progress(&self) -> Option<f32> {
+		if self.loading.is_some() {
+			return Some(0.0);
+		}
+
+		let job = self.job.as_ref()?;
+		let total = job.passes.iter().map(|pass| pass.size.h).sum::<u32>();
+		let done = job.passes[..job.pass].iter().map(|pass| pass.size.h).sum::<u32>() + job.row;
+
+		Some((UploadHandle::progress_of(&job.uploads) + done as f32 / total as f32) / 2.0)
+	}
+
+	/// Encode the next chunk of rows, or the copy into the maps once all of
+	/// them are done. Nothing to do if it returns `None`, e.g. while the
+	/// environment is still uploading.
+	///
+	/// Only one chunk is encoded per call, since they share the uniform.
+	pub fn advance(&mut self, gpu: &Gpu, maps: &EnvironmentMaps, parity: PingPongParity) -> Option<CommandBuffer> {
+		let job = self.job.as_mut()?;
+		if !UploadHandle::all_done(&job.uploads) {
+			return None;
+		}
+
+		let mut encoder = gpu.device.create_command_encoder(&CommandEncoderDescriptor {
+			label: Some("Environment Prefilter Command Encoder"),
+		});
+
+		if let Some(pass) = job.passes.get(job.pass) {
+			let rows = self.rows_per_frame.min(pass.size.h - job.row);
+			let chunk = PrefilterChunk {
+				row_offset: job.row,
+				rows,
+				samples: self.samples,
+				roughness: pass.roughness,
+			};
+			job.chunk_buffer.upload_bytes(gpu, bytemuck::bytes_of(&chunk), 0);
+
+			{
+				let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+					label: Some("Environment Prefilter Pass"),
+					timestamp_writes: None,
+				});
+
+				compute_pass.set_pipeline(&pass.pipeline);
+				compute_pass.apply_buffer_mapping(&pass.shader.binding, parity);
+				compute_pass.dispatch_workgroups(
+					pass.size.w.div_ceil(Self::WORKGROUP_SIZE),
+					rows.div_ceil(Self::WORKGROUP_SIZE),
+					1,
+				);
+			}
+
+			job.row += rows;
+			if job.row >= pass.size.h {
+				job.pass += 1;
+				job.row = 0;
+				info!("Environment `{}`: {}/{} maps", job.name, job.pass, job.passes.len());
+			}
+
+			return Some(encoder.finish());
+		}
+
+		for mip_level in 0..maps.prefiltered.mip_level_count() {
+			copy_mip(&mut encoder, &job.staging.prefiltered, &maps.prefiltered, mip_level);
+		}
+		copy_mip(&mut encoder, &job.staging.irradiance, &maps.irradiance, 0);
+
+		info!("Environment `{}` is ready", job.name);
+		self.job = None;
+
+		Some(encoder.finish())
+	}
+
+	fn start(&mut self, gpu: &Gpu, uploader: &mut ChunkedUploader, name: &str, mips: Vec<Rgba32FImage>) -> Result<()> {
+		let Some(environment) = mips.first() else {
+			bail!("The environment is empty");
+		};
+
+		let size = Extent2::new(environment.width(), environment.height());
+		let max_size = gpu.device.limits().max_texture_dimension_2d;
+		if size.w > max_size || size.h > max_size {
+			bail!("{}x{} is bigger than the GPU allows ({})", size.w, size.h, max_size);
+		}
+
+		let source = Tex::create_with_mips(
+			gpu,
+			TexDescriptor {
+				label: &format!("Environment source '{}'", name),
+				dimensions: TextureAssetDimensions::D2(size),
+				format: EnvironmentMaps::FORMAT,
+				usage: None,
+				aspect: TextureAspect::All,
+			},
+			mips.len() as u32,
+			Some(SAMPLER),
+		)
+		.tracked(gpu);
+
+		// Only held by the passes, cancelling drops it and so stops the uploads
+		let uploads = mips
+			.iter()
+			.enumerate()
+			.map(|(mip_level, mip)| {
+				let texels = mip
+					.as_raw()
+					.iter()
+					.flat_map(|value| texture::f32_to_f16_bits(*value).to_le_bytes())
+					.collect::<Vec<_>>();
+				uploader.upload_texture(&source, mip_level as u32, texels)
+			})
+			.collect();
+
+		let staging = EnvironmentMaps::new(gpu, &format!("Environment staging '{}'", name));
+		let chunk_buffer = Sarc::new(UniformBuffer::raw_buffer_from_data(
+			gpu,
+			&<PrefilterChunk as bytemuck::Zeroable>::zeroed(),
+			Some("Environment prefilter chunk"),
+		));
+
+		let build_pass = |path: &str, output: &Sarc<Tex>, mip_level: u32, roughness: f32| -> Result<PrefilterPass> {
+			let shader = ShaderBuilder::new()
+				.include_path(path)
+				.define("WORKGROUP_SIZE", format!("{}", Self::WORKGROUP_SIZE))
+				.include_buffer(SampledTexture::FromTex {
+					texture_var_name: "environment_source",
+					sampler_var_name: "environment_source_sampler",
+					tex: source.clone(),
+				})
+				.include_buffer(StorageTexture::FromTexMip {
+					var_name: "prefilter_output",
+					access: StorageTextureAccess::WriteOnly,
+					tex: output.clone(),
+					mip_level,
+				})
+				.include_buffer(UniformBufferDescriptor::FromBuffer::<PrefilterChunk, _> {
+					var_name: "prefilter_chunk",
+					buffer: chunk_buffer.clone(),
+				})
+				.build(
+					gpu,
+					format!("Environment prefilter shader ({}, mip {})", path, mip_level),
+					&ShaderAssets,
+					ShaderStages::COMPUTE,
+					0,
+				)?;
+
+			let pipeline_layout = gpu.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+				label: Some("Environment prefilter pipeline layout"),
+				bind_group_layouts: &shader.layouts(),
+				push_constant_ranges: &[],
+			});
+
+			let pipeline = shader.create_pipeline(gpu, || {
+				gpu.device.create_compute_pipeline(&ComputePipelineDescriptor {
+					label: Some("Environment prefilter pipeline"),
+					layout: Some(&pipeline_layout),
+					module: &shader.shader_module,
+					entry_point: "main",
+				})
+			});
+
+			let size = output.mip_size(mip_level);
+			Ok(PrefilterPass {
+				shader,
+				pipeline,
+				size: Extent2::new(size.width, size.height),
+				roughness,
+			})
+		};
+
+		let last_mip = EnvironmentMaps::PREFILTERED_MIP_LEVELS - 1;
+		let mut passes = (0..=last_mip)
+			.map(|mip_level| {
+				let roughness = mip_level as f32 / last_mip as f32;
+				build_pass("environment/prefilter.wgsl", &staging.prefiltered, mip_level, roughness)
+			})
+			.collect::<Result<Vec<_>>>()?;
+		passes.push(build_pass("environment/irradiance.wgsl", &staging.irradiance, 0, 1.0)?);
+
+		info!(
+			"Prefiltering the environment `{}` ({}x{}) over the next frames",
+			name, size.w, size.h
+		);
+
+		self.job = Some(PrefilterJob {
+			name: name.to_owned(),
+			uploads,
+			staging,
+			chunk_buffer,
+			passes,
+			pass: 0,
+			row: 0,
+		});
+
+		Ok(())
+	}
+
+	/// Start prefiltering the environment that was decoded in the background,
+	/// if it's done
+	fn poll_loading(&mut self, gpu: &Gpu, uploader: &mut ChunkedUploader) {
+		let Some((_, task)) = &mut self.loading else {
+			return;
+		};
+		let Some(result) = future::block_on(future::poll_once(task)) else {
+			return;
+		};
+
+		let (name, _) = self.loading.take().unwrap();
+		if let Err(error) = result.and_then(|mips| self.start(gpu, uploader, &name, mips)) {
+			error!("Couldn't load the environment `{}`: {:#}", name, error);
+		}
+	}
+}
+
+fn read_environment(path: &Path) -> Result<Rgba32FImage> {
+	Ok(image::open(path)
+		.with_context(|| format!("Couldn't read `{}`", path.display()))?
+		.into_rgba32f())
+}
+
+/// The environment and its halves down to 1x1, averaged in equirectangular
+/// space. Good enough for picking the source samples of the prefiltering.
+fn environment_mips(environment: Rgba32FImage) -> Vec<Rgba32FImage> {
+	let mip_levels = texture::mip_levels_for(Extent2::new(environment.width(), environment.height()));
+
+	let mut mips = vec![environment];
+	for _ in 1..mip_levels {
+		let previous = mips.last().unwrap();
+		let (width, height) = ((previous.width() / 2).max(1), (previous.height() / 2).max(1));
+		mips.push(imageops::resize(previous, width, height, FilterType::Triangle));
+	}
+
+	mips
+}
+
+fn copy_mip(encoder: &mut CommandEncoder, from: &Tex, to: &Tex, mip_level: u32) {
+	let copy = |tex: &Tex| ImageCopyTexture {
+		texture: &tex.texture,
+		mip_level,
+		origin: Origin3d::ZERO,
+		aspect: TextureAspect::All,
+	};
+
+	encoder.copy_texture_to_texture(copy(from), copy(to), from.mip_size(mip_level));
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+fn load_dropped_environments(
+	mut prefilter: ResMut<EnvironmentPrefilter>,
+	mut winit_events: EventReader<WinitWindowEvent>,
+) {
+	for WinitWindowEvent(event) in winit_events.read() {
+		// Other files, like the LUTs, are for someone else
+		if let WindowEvent::DroppedFile(path) = event {
+			if image::ImageFormat::from_path(path).is_ok() {
+				prefilter.load_file(path.clone());
+			}
+		}
+	}
+}
+
+fn advance_prefilter(
+	mut prefilter: ResMut<EnvironmentPrefilter>,
+	maps: Res<EnvironmentMaps>,
+	mut uploader: ResMut<ChunkedUploader>,
+	mut render_target: ResMut<RenderTarget<'static>>,
+	gpu: Res<Gpu>,
+	parity: Res<PingPongParity>,
+) {
+	prefilter.poll_loading(&gpu, &mut uploader);
+
+	if let Some(commands) = prefilter.advance(&gpu, &maps, *parity) {
+		render_target.command_queue.push(commands);
+	}
+}
+
+fn environment(world: &mut World, args: &[String]) -> Result<String> {
+	let mut prefilter = world.resource_mut::<EnvironmentPrefilter>();
+
+	match args {
+		[] => Ok(match prefilter.progress() {
+			Some(progress) if prefilter.is_uploading() => format!("Uploading: {:.0}%", progress * 100.0),
+			Some(progress) => format!("Prefiltering: {:.0}%", progress * 100.0),
+			None => "Nothing is being prefiltered".to_owned(),
+		}),
+		[cancel] if cancel == "cancel" => {
+			if !prefilter.cancel() {
+				bail!("Nothing is being prefiltered");
+			}
+			Ok("Cancelled".to_owned())
+		}
+		[path] => {
+			prefilter.load_file(path);
+			Ok(format!("Loading `{}`", path))
+		}
+		_ => bail!("Usage: environment [path | cancel]"),
+	}
+}
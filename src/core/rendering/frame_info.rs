@@ -0,0 +1,59 @@
+use bevy_ecs::system::{Query, Res};
+use brainrot::bevy::{self, App};
+use pbr_tracer_derive::ShaderStruct;
+use wgpu::Buffer;
+
+use crate::{
+	core::{gameloop::Time, gpu::Gpu, size::Resolution},
+	libs::{
+		buffer::{uniform_buffer::UniformBuffer, BufferUploadable, ShaderType},
+		smart_arc::Sarc,
+	},
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// What the compute renderer binds as `frame_info`, updated from the [`Time`]
+/// every frame. `rng_init()` in sampling/rng.wgsl seeds from the frame counter,
+/// so every frame gets new random numbers.
+#[repr(C)]
+#[derive(ShaderStruct, bytemuck::Pod, bytemuck::Zeroable, bevy::Component, Copy, Clone, Debug, Default, PartialEq)]
+pub struct FrameInfo {
+	/// How many frames were rendered before this one
+	pub frame: u32,
+	/// In seconds since the start
+	pub time: f32,
+	pub resolution: Resolution,
+}
+
+/// Spawn the [`FrameInfo`] with its buffer, done by the
+/// [`ComputeRendererPlugin`](super::compute::ComputeRendererPlugin)
+pub(crate) fn spawn_frame_info(app: &mut App) -> Sarc<Buffer> {
+	let gpu = app.world.resource::<Gpu>();
+
+	let buffer = Sarc::new(UniformBuffer::raw_buffer_from_type::<FrameInfo>(gpu, None));
+	app.world.spawn((FrameInfo::default(), buffer.clone()));
+
+	buffer
+}
+
+pub(crate) fn update_frame_info(
+	gpu: Res<Gpu>,
+	time: Res<Time>,
+	resolution: Res<Resolution>,
+	mut q: Query<(&mut FrameInfo, &Sarc<Buffer>)>,
+) {
+	for (mut frame_info, buffer) in q.iter_mut() {
+		*frame_info = FrameInfo {
+			frame: time.counter_frame as u32,
+			time: time.current_time.as_secs_f32(),
+			resolution: *resolution,
+		};
+
+		buffer.upload_bytes(&gpu, &frame_info.get_bytes(), 0);
+	}
+}
@@ -0,0 +1,296 @@
+use std::mem;
+
+use anyhow::{bail, Result};
+use bevy_ecs::{
+	schedule::IntoSystemConfigs,
+	system::{Query, Res, SystemParam},
+	world::World,
+};
+use brainrot::bevy::{self, App, Plugin};
+use log::info;
+use pbr_tracer_derive::ShaderStruct;
+use serde::{Deserialize, Serialize};
+use wgpu::Buffer;
+
+use super::{
+	accumulation::{self, Accumulation},
+	capture::HighQualityCapture,
+	interactive::InteractiveRendering,
+};
+use crate::{
+	core::{
+		console,
+		entity_label::EntityLabel,
+		gameloop::{PreRender, Time},
+		gpu::Gpu,
+		size::Resolution,
+	},
+	libs::{
+		buffer::{uniform_buffer::UniformBuffer, BufferUploadable, ShaderType},
+		smart_arc::Sarc,
+	},
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Spawns the [`Globals`] uniform, which the compute renderer binds as
+/// `globals`. Needs to be added before the compute renderer.
+pub struct GlobalsPlugin;
+
+impl Plugin for GlobalsPlugin {
+	fn build(&self, app: &mut App) {
+		let gpu = app.world.resource::<Gpu>();
+
+		let globals_buffer = Sarc::new(UniformBuffer::raw_buffer_from_type::<Globals>(gpu, None));
+		app.world.spawn((Globals::default(), globals_buffer));
+		app.world.insert_resource(RenderSettings::default());
+		app.world.init_resource::<SceneSettings>();
+
+		console::register_command(
+			app,
+			"settings",
+			"settings [ron]: List the render settings, marking the ones the scene overrides, or print them as a scene's `settings` section",
+			settings,
+		);
+
+		app.add_systems(PreRender, update_globals.after(accumulation::update_accumulation));
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Per-frame values that any shader might need
+#[repr(C)]
+#[derive(ShaderStruct, bytemuck::Pod, bytemuck::Zeroable, bevy::Component, Copy, Clone, Debug, Default, PartialEq)]
+pub struct Globals {
+	/// The index of the frame being rendered
+	pub frame: u32,
+	/// A new random value every frame
+	pub seed: u32,
+	pub resolution: Resolution,
+	/// See [`RenderSettings`]
+	pub march_steps_scale: f32,
+	/// 1 if the reference grid is drawn, see [`RenderSettings`]
+	pub show_reference_grid: u32,
+	/// 1 if the pixel seeds don't depend on the resolution, see
+	/// [`RenderSettings`]
+	pub stable_pixel_seeds: u32,
+	/// 1 while a [`HighQualityCapture`] averages the frames, so that the shaders
+	/// can spread their samples over them
+	pub accumulating: u32,
+	/// How many earlier frames a progressive renderer has summed, 0 to start over,
+	/// see [`Accumulation`]
+	pub accumulated_samples: u32,
+	/// 1 while the progressive renderers should leave their output as it is, see
+	/// [`InteractiveRendering`]
+	pub hold_output: u32,
+}
+impl EntityLabel for Globals {}
+
+/// Defines [`RenderSettings`] and its [`RenderSettingsOverrides`] from the
+/// same list of fields, so that the two can't drift apart
+macro_rules! render_settings {
+	($(
+		$(#[doc = $doc:expr])*
+		$field:ident: $ty:ty = $default:expr,
+	)*) => {
+		/// Render settings that can change while the app runs, uploaded with the
+		/// [`Globals`]
+		#[derive(bevy::Resource, Copy, Clone, Debug, PartialEq)]
+		pub struct RenderSettings {
+			$(
+				$(#[doc = $doc])*
+				pub $field: $ty,
+			)*
+		}
+
+		impl Default for RenderSettings {
+			fn default() -> Self {
+				Self {
+					$($field: $default,)*
+				}
+			}
+		}
+
+		impl RenderSettings {
+			/// The name and value of every field
+			pub fn fields(&self) -> Vec<(&'static str, String)> {
+				vec![$((stringify!($field), format!("{:?}", self.$field)),)*]
+			}
+		}
+
+		/// Some fields of the [`RenderSettings`], e.g. the ones a scene wants. Only
+		/// the fields that are set override anything, so in RON a missing field is
+		/// left alone.
+		#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, PartialEq)]
+		#[serde(default, deny_unknown_fields)]
+		pub struct RenderSettingsOverrides {
+			$(
+				#[serde(skip_serializing_if = "Option::is_none")]
+				pub $field: Option<$ty>,
+			)*
+		}
+
+		impl RenderSettingsOverrides {
+			/// Every field set to the value in the settings
+			pub fn all(settings: &RenderSettings) -> Self {
+				Self {
+					$($field: Some(settings.$field),)*
+				}
+			}
+
+			/// Write the fields that are set into the settings. Returns what those
+			/// fields were before, which undoes it when applied in turn.
+			pub fn apply(&self, settings: &mut RenderSettings) -> Self {
+				let mut previous = Self::default();
+				$(
+					if let Some(value) = self.$field {
+						previous.$field = Some(mem::replace(&mut settings.$field, value));
+					}
+				)*
+				previous
+			}
+
+			/// The names of the fields that are set
+			pub fn fields(&self) -> Vec<&'static str> {
+				let mut fields = Vec::new();
+				$(
+					if self.$field.is_some() {
+						fields.push(stringify!($field));
+					}
+				)*
+				fields
+			}
+		}
+	};
+}
+
+render_settings! {
+	/// Scales the raymarcher's own max steps
+	march_steps_scale: f32 = 1.0,
+	/// Only matters if the renderer has a
+	/// [`ReferenceGrid`](crate::fragments::reference_grid::ReferenceGrid)
+	show_reference_grid: bool = true,
+	/// Seed the per-pixel randomness from where the pixel is in the image
+	/// instead of its index, see
+	/// [`pixel_seed`](crate::fragments::sampling::pixel_seed)
+	stable_pixel_seeds: bool = true,
+}
+
+/// The [`RenderSettingsOverrides`] of the scene that was loaded last. They are
+/// applied over the user's settings, and reverted when the next scene loads.
+#[derive(bevy::Resource, Default, Debug)]
+pub struct SceneSettings {
+	overrides: RenderSettingsOverrides,
+	/// What the overridden fields were before the scene, to put them back
+	previous: RenderSettingsOverrides,
+}
+
+impl SceneSettings {
+	/// Revert the overrides of the previous scene, then apply the new ones. The
+	/// fields that neither scene overrides keep whatever the user set.
+	pub fn load(&mut self, overrides: RenderSettingsOverrides, settings: &mut RenderSettings) {
+		self.previous.apply(settings);
+		self.previous = overrides.apply(settings);
+		self.overrides = overrides;
+
+		let fields = overrides.fields();
+		if !fields.is_empty() {
+			info!("The scene overrides the render settings {}", fields.join(", "));
+		}
+	}
+
+	pub fn overrides(&self) -> &RenderSettingsOverrides {
+		&self.overrides
+	}
+
+	pub fn is_overridden(&self, field: &str) -> bool {
+		self.overrides.fields().contains(&field)
+	}
+}
+
+/// The plugins the globals follow if they were added
+#[derive(SystemParam)]
+struct OptionalPlugins<'w> {
+	capture: Option<Res<'w, HighQualityCapture>>,
+	accumulation: Option<Res<'w, Accumulation>>,
+	interactive: Option<Res<'w, InteractiveRendering>>,
+}
+
+// Uploads directly instead of going through `register_auto_update`, so that the
+// values can't be uploaded before they are updated
+fn update_globals(
+	gpu: Res<Gpu>,
+	time: Res<Time>,
+	resolution: Res<Resolution>,
+	render_settings: Res<RenderSettings>,
+	plugins: OptionalPlugins,
+	mut q: Query<(&mut Globals, &Sarc<Buffer>)>,
+) {
+	let OptionalPlugins {
+		capture,
+		accumulation,
+		interactive,
+	} = plugins;
+
+	let render_settings = match &interactive {
+		Some(interactive) => interactive.effective_settings(&render_settings),
+		None => *render_settings,
+	};
+
+	for (mut globals, buffer) in q.iter_mut() {
+		*globals = Globals {
+			frame: time.counter_frame as u32,
+			seed: rand::random(),
+			resolution: *resolution,
+			march_steps_scale: render_settings.march_steps_scale,
+			show_reference_grid: render_settings.show_reference_grid as u32,
+			stable_pixel_seeds: render_settings.stable_pixel_seeds as u32,
+			accumulating: capture.as_ref().is_some_and(|capture| capture.is_running()) as u32,
+			accumulated_samples: accumulation
+				.as_ref()
+				.map_or(0, |accumulation| accumulation.samples().saturating_sub(1)),
+			hold_output: interactive
+				.as_ref()
+				.is_some_and(|interactive| interactive.holds_output()) as u32,
+		};
+
+		buffer.upload_bytes(&gpu, &globals.get_bytes(), 0);
+	}
+}
+
+fn settings(world: &mut World, args: &[String]) -> Result<String> {
+	let settings = world.resource::<RenderSettings>();
+
+	match args.first().map(String::as_str) {
+		None => {
+			let scene = world.resource::<SceneSettings>();
+
+			Ok(settings
+				.fields()
+				.into_iter()
+				.map(|(field, value)| {
+					if scene.is_overridden(field) {
+						format!("{} = {} (overridden by the scene)", field, value)
+					} else {
+						format!("{} = {}", field, value)
+					}
+				})
+				.collect::<Vec<_>>()
+				.join("\n"))
+		}
+		Some("ron") => Ok(format!(
+			"settings: {}",
+			ron::ser::to_string(&RenderSettingsOverrides::all(settings))?
+		)),
+		Some(_) => bail!("Usage: settings [ron]"),
+	}
+}
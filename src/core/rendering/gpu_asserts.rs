@@ -0,0 +1,184 @@
+use std::{
+	collections::BTreeMap,
+	time::{Duration, Instant},
+};
+
+use bevy_ecs::{
+	query::With,
+	system::{Local, Query, Res},
+};
+use brainrot::bevy::{self, App, Plugin};
+use log::warn;
+use wgpu::Buffer;
+
+use crate::{
+	core::{gameloop::Update, gpu::Gpu},
+	fragments::{instrumentation::GpuAsserts, intersector::RaymarchAssert, mesh::MeshAssert},
+	libs::{buffer::atomic_counter::AtomicCounter, smart_arc::Sarc},
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Whether the shaders are built with working GPU asserts
+pub const DEBUG_INSTRUMENTATION: bool = cfg!(debug_assertions);
+
+/// Spawns the log that `gpu_assert` writes its failures to, and logs them
+/// once per second.
+///
+/// Does nothing unless [`DEBUG_INSTRUMENTATION`] is on, otherwise the asserts
+/// compile to nothing. Needs to be added before the compute renderer.
+pub struct GpuAssertsPlugin {
+	/// How many failures are recorded per second at most, the others are only
+	/// counted
+	pub max_records: u32,
+}
+
+impl Default for GpuAssertsPlugin {
+	fn default() -> Self {
+		Self { max_records: 64 }
+	}
+}
+
+impl Plugin for GpuAssertsPlugin {
+	fn build(&self, app: &mut App) {
+		if !DEBUG_INSTRUMENTATION {
+			return;
+		}
+
+		let gpu = app.world.resource::<Gpu>();
+
+		// The counter of failures, then the records
+		let log = AtomicCounter::raw_buffer_from_count(
+			gpu,
+			1 + self.max_records * GpuAsserts::RECORD_WORDS,
+			Some("GPU Assert Log"),
+		);
+
+		app.world.spawn((GpuAssertLog, Sarc::new(log)));
+
+		let mut sites = AssertSites::default();
+		sites.register(RaymarchAssert::SHADER_CONSTANTS);
+		sites.register(MeshAssert::SHADER_CONSTANTS);
+		app.world.insert_resource(sites);
+
+		app.add_systems(Update, log_failed_asserts);
+	}
+}
+
+#[derive(bevy::Component, Copy, Clone, Debug, Default)]
+pub struct GpuAssertLog;
+
+/// The fragment to include in a pipeline so that its shaders can use
+/// `gpu_assert`
+pub fn gpu_asserts_fragment(app: &mut App) -> GpuAsserts {
+	app.world
+		.query_filtered::<&Sarc<Buffer>, With<GpuAssertLog>>()
+		.get_single(&app.world)
+		.map(|log| GpuAsserts::with_log(log.clone()))
+		.unwrap_or_else(|_| GpuAsserts::disabled())
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// The name of every assert code, so that the failures can be logged by name.
+/// Inserted by the [`GpuAssertsPlugin`] with the codes of the built-in
+/// fragments.
+#[derive(bevy::Resource, Default)]
+pub struct AssertSites(BTreeMap<u32, &'static str>);
+
+impl AssertSites {
+	/// Register the names of assert codes, usually the `SHADER_CONSTANTS` of a
+	/// `ShaderStruct` enum whose constants are passed to `gpu_assert`.
+	///
+	/// The codes are shared by all the fragments, so every fragment should use
+	/// its own range of values.
+	pub fn register(&mut self, sites: &[(&'static str, u32)]) {
+		for (name, code) in sites {
+			match self.0.insert(*code, name) {
+				Some(previous) if previous != *name => {
+					warn!("GPU assert code {} is used by both {} and {}", code, previous, name)
+				}
+				_ => {}
+			}
+		}
+	}
+
+	fn name(&self, code: u32) -> String {
+		self.0
+			.get(&code)
+			.map(|name| name.to_string())
+			.unwrap_or_else(|| format!("unknown assert {}", code))
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+fn log_failed_asserts(
+	gpu: Res<Gpu>,
+	sites: Res<AssertSites>,
+	q: Query<&Sarc<Buffer>, With<GpuAssertLog>>,
+	mut last_log: Local<Option<Instant>>,
+) {
+	if last_log.is_some_and(|last_log| last_log.elapsed() < Duration::from_secs(1)) {
+		return;
+	}
+
+	*last_log = Some(Instant::now());
+
+	for log in q.iter() {
+		// Blocks, but only once per second and only in debug builds
+		let words = AtomicCounter::readback(&gpu, log);
+		let failed = words[0];
+
+		if failed == 0 {
+			continue;
+		}
+
+		// Only the counter needs a reset, the records past it are just overwritten
+		log.upload_bytes(&gpu, &0_u32.to_ne_bytes(), 0);
+
+		let records = words[1..]
+			.chunks_exact(GpuAsserts::RECORD_WORDS as usize)
+			.take(failed as usize)
+			.collect::<Vec<_>>();
+
+		// Group by code, keeping the first failure of each
+		let mut by_code = BTreeMap::<u32, (u32, &[u32])>::new();
+		for record in &records {
+			by_code.entry(record[0]).or_insert((0, record)).0 += 1;
+		}
+
+		for (code, (count, first)) in by_code {
+			let payload = first[4..8].iter().map(|bits| f32::from_bits(*bits)).collect::<Vec<_>>();
+
+			warn!(
+				"GPU assert {} failed {} times, first at pixel ({}, {}) on frame {} with payload {:?}",
+				sites.name(code),
+				count,
+				first[1],
+				first[2],
+				first[3],
+				payload
+			);
+		}
+
+		if failed as usize > records.len() {
+			warn!(
+				"{} more GPU asserts failed but didn't fit in the log",
+				failed as usize - records.len()
+			);
+		}
+	}
+}
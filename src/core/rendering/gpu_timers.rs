@@ -0,0 +1,296 @@
+use std::{
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc,
+	},
+	time::{Duration, Instant},
+};
+
+use bevy_ecs::{
+	schedule::IntoSystemConfigs,
+	system::{Local, Res, ResMut},
+};
+use brainrot::bevy::{self, App, Plugin};
+use hashlink::LinkedHashMap;
+use log::debug;
+use wgpu::{
+	Buffer, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, ComputePassTimestampWrites, Features, Maintain,
+	MapMode, QuerySet, QuerySetDescriptor, QueryType, RenderPassTimestampWrites, QUERY_SIZE,
+};
+
+use super::render::{self, PostRenderPass};
+use crate::{
+	core::{
+		gameloop::{Render, Update},
+		gpu::Gpu,
+		render_target::RenderTarget,
+	},
+	libs::metrics::{format_duration_ms, format_ms, RollingStats},
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Measures how long each render pass takes on the GPU.
+///
+/// Only does anything if the GPU supports `Features::TIMESTAMP_QUERY`,
+/// otherwise all the timestamp writes are `None` and [`FrameTimings`] stays
+/// empty.
+pub struct GpuTimersPlugin {
+	/// The names of the passes that will request timestamp writes
+	pub passes: Vec<&'static str>,
+}
+
+impl Default for GpuTimersPlugin {
+	fn default() -> Self {
+		Self {
+			passes: vec!["compute", "composite"],
+		}
+	}
+}
+
+impl Plugin for GpuTimersPlugin {
+	fn build(&self, app: &mut App) {
+		let gpu = app.world.resource::<Gpu>();
+		let gpu_timers = GpuTimers::new(gpu, &self.passes);
+
+		app.world.insert_resource(gpu_timers);
+		app.world.insert_resource(FrameTimings::default());
+
+		app.add_systems(
+			Render,
+			(
+				resolve_timers.before(render::finish_render_pass),
+				read_timers.after(render::finish_render_pass),
+			)
+				.in_set(PostRenderPass),
+		);
+		app.add_systems(Update, log_timings);
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+#[derive(bevy::Resource)]
+pub struct GpuTimers {
+	inner: Option<GpuTimersInner>,
+	passes: Vec<&'static str>,
+}
+
+struct GpuTimersInner {
+	query_set: QuerySet,
+	query_count: u32,
+	resolve_buffer: Buffer,
+	readback_buffer: Buffer,
+	/// Nanoseconds per timestamp tick
+	period: f32,
+
+	// The readback buffer can't be copied into while it's mapped, so a new copy
+	// is only made once the previous one was read
+	copied: bool,
+	mapping: bool,
+	mapped: Arc<AtomicBool>,
+}
+
+impl GpuTimers {
+	pub fn new(gpu: &Gpu, passes: &[&'static str]) -> Self {
+		let inner = gpu.device.features().contains(Features::TIMESTAMP_QUERY).then(|| {
+			// A start and an end timestamp for every pass
+			let count = passes.len() as u32 * 2;
+			let size = count as u64 * QUERY_SIZE as u64;
+
+			GpuTimersInner {
+				query_set: gpu.device.create_query_set(&QuerySetDescriptor {
+					label: Some("GpuTimers Query Set"),
+					ty: QueryType::Timestamp,
+					count,
+				}),
+				query_count: count,
+				resolve_buffer: gpu.device.create_buffer(&BufferDescriptor {
+					label: Some("GpuTimers Resolve Buffer"),
+					size,
+					usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+					mapped_at_creation: false,
+				}),
+				readback_buffer: gpu.device.create_buffer(&BufferDescriptor {
+					label: Some("GpuTimers Readback Buffer"),
+					size,
+					usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+					mapped_at_creation: false,
+				}),
+				period: gpu.queue.get_timestamp_period(),
+				copied: false,
+				mapping: false,
+				mapped: Arc::new(AtomicBool::new(false)),
+			}
+		});
+
+		Self {
+			inner,
+			passes: passes.to_vec(),
+		}
+	}
+
+	pub fn is_enabled(&self) -> bool {
+		self.inner.is_some()
+	}
+
+	fn query_indices(&self, pass: &str) -> Option<(&QuerySet, u32)> {
+		let inner = self.inner.as_ref()?;
+		let index = self.passes.iter().position(|p| *p == pass)? as u32;
+
+		Some((&inner.query_set, index * 2))
+	}
+
+	/// The timestamp writes to put in the `ComputePassDescriptor` of the given
+	/// pass, `None` if timestamps aren't supported or the pass isn't known
+	pub fn compute_pass_writes(&self, pass: &str) -> Option<ComputePassTimestampWrites> {
+		self.query_indices(pass)
+			.map(|(query_set, index)| ComputePassTimestampWrites {
+				query_set,
+				beginning_of_pass_write_index: Some(index),
+				end_of_pass_write_index: Some(index + 1),
+			})
+	}
+
+	/// The timestamp writes to put in the `RenderPassDescriptor` of the given
+	/// pass, `None` if timestamps aren't supported or the pass isn't known
+	pub fn render_pass_writes(&self, pass: &str) -> Option<RenderPassTimestampWrites> {
+		self.query_indices(pass)
+			.map(|(query_set, index)| RenderPassTimestampWrites {
+				query_set,
+				beginning_of_pass_write_index: Some(index),
+				end_of_pass_write_index: Some(index + 1),
+			})
+	}
+}
+
+/// The smoothed GPU duration of every timed pass, in the order the passes were
+/// given to the [`GpuTimersPlugin`]
+#[derive(bevy::Resource, Default, Debug)]
+pub struct FrameTimings {
+	/// The mean of the `stats`
+	pub passes: LinkedHashMap<&'static str, Duration>,
+	/// The last [`STATS_WINDOW`](Self::STATS_WINDOW) durations of every pass,
+	/// in milliseconds
+	pub stats: LinkedHashMap<&'static str, RollingStats<{ FrameTimings::STATS_WINDOW }>>,
+}
+
+impl FrameTimings {
+	/// The timestamps are only read every other frame or so, so this is a few
+	/// seconds' worth
+	pub const STATS_WINDOW: usize = 120;
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+fn resolve_timers(mut gpu_timers: ResMut<GpuTimers>, mut render_target: ResMut<RenderTarget<'static>>, gpu: Res<Gpu>) {
+	let Some(inner) = &mut gpu_timers.inner else {
+		return;
+	};
+
+	if inner.copied || inner.mapping {
+		return;
+	}
+
+	let mut encoder = gpu.device.create_command_encoder(&CommandEncoderDescriptor {
+		label: Some("GpuTimers Command Encoder"),
+	});
+
+	encoder.resolve_query_set(&inner.query_set, 0..inner.query_count, &inner.resolve_buffer, 0);
+	encoder.copy_buffer_to_buffer(
+		&inner.resolve_buffer,
+		0,
+		&inner.readback_buffer,
+		0,
+		inner.readback_buffer.size(),
+	);
+
+	// Submitted together with the passes by `finish_render_pass`
+	render_target.command_queue.push(encoder.finish());
+	inner.copied = true;
+}
+
+fn read_timers(mut gpu_timers: ResMut<GpuTimers>, mut frame_timings: ResMut<FrameTimings>, gpu: Res<Gpu>) {
+	let GpuTimers { inner, passes } = &mut *gpu_timers;
+	let Some(inner) = inner else {
+		return;
+	};
+
+	// The copy was just submitted, start mapping it without waiting for it
+	if inner.copied {
+		let mapped = inner.mapped.clone();
+		inner.readback_buffer.slice(..).map_async(MapMode::Read, move |result| {
+			if result.is_ok() {
+				mapped.store(true, Ordering::Release);
+			}
+		});
+
+		inner.copied = false;
+		inner.mapping = true;
+		return;
+	}
+
+	if !inner.mapping {
+		return;
+	}
+
+	gpu.device.poll(Maintain::Poll);
+	if !inner.mapped.swap(false, Ordering::Acquire) {
+		return;
+	}
+
+	let timestamps = bytemuck::cast_slice::<u8, u64>(&inner.readback_buffer.slice(..).get_mapped_range()).to_vec();
+	inner.readback_buffer.unmap();
+	inner.mapping = false;
+
+	for (pass, range) in passes.iter().zip(timestamps.chunks_exact(2)) {
+		// A pass that didn't run this frame leaves its timestamps untouched
+		let Some(ticks) = range[1].checked_sub(range[0]).filter(|ticks| *ticks > 0) else {
+			continue;
+		};
+
+		let ms = ticks as f32 * inner.period / 1_000_000.0;
+		let stats = frame_timings.stats.entry(pass).or_default();
+		stats.push(ms);
+		let mean = stats.mean().unwrap_or(ms);
+
+		frame_timings
+			.passes
+			.replace(pass, Duration::from_secs_f32(mean / 1000.0));
+	}
+}
+
+fn log_timings(frame_timings: Res<FrameTimings>, mut last_log: Local<Option<Instant>>) {
+	if frame_timings.passes.is_empty() || last_log.is_some_and(|last_log| last_log.elapsed() < Duration::from_secs(1)) {
+		return;
+	}
+
+	*last_log = Some(Instant::now());
+
+	let timings = frame_timings
+		.passes
+		.iter()
+		.map(|(pass, duration)| {
+			let p95 = frame_timings.stats.get(pass).and_then(RollingStats::p95);
+			match p95 {
+				Some(p95) => format!("{}: {} (p95 {})", pass, format_duration_ms(*duration), format_ms(p95)),
+				None => format!("{}: {}", pass, format_duration_ms(*duration)),
+			}
+		})
+		.collect::<Vec<_>>()
+		.join(", ");
+
+	debug!("GPU timings: {}", timings);
+}
@@ -0,0 +1,638 @@
+use std::sync::{
+	atomic::{AtomicBool, Ordering},
+	Arc,
+};
+
+use anyhow::{bail, Result};
+use bevy_ecs::{
+	event::EventReader,
+	schedule::IntoSystemConfigs,
+	system::{Query, Res, ResMut},
+	world::World,
+};
+use brainrot::{
+	bevy::{self, App, Plugin},
+	vec2,
+	vek::{Extent2, Vec2},
+};
+use log::info;
+use pbr_tracer_derive::ShaderStruct;
+use wgpu::{
+	BlendState, Buffer, BufferDescriptor, BufferUsages, ColorTargetState, ColorWrites, CommandEncoderDescriptor,
+	ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, FragmentState, FrontFace, LoadOp, Maintain,
+	MapMode, MultisampleState, Operations, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology,
+	RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, ShaderStages,
+	StorageTextureAccess, StoreOp, TextureFormat, VertexState,
+};
+
+use super::{
+	composite::{CompositeRenderPass, CompositeRenderer},
+	render::{self, InnerRenderPass, PostRenderPass},
+};
+use crate::{
+	core::{
+		console::{self, is_console_closed},
+		events::KeyboardInputEvent,
+		gameloop::{Render, Update},
+		gpu::Gpu,
+		key_bindings::{Action, KeyBindings},
+		render_target::RenderTarget,
+	},
+	fragments::post_processing::ToneMappingSettings,
+	libs::{
+		buffer::{
+			atomic_counter::{AtomicCounter, AtomicCounterDescriptor},
+			ping_pong_texture::PingPongParity,
+			storage_buffer::{StorageArray, StorageBuffer, StorageBufferDescriptor},
+			storage_texture_buffer::StorageTexture,
+			uniform_buffer::{UniformBuffer, UniformBufferDescriptor},
+			BufferMappingApplicable, BufferUploadable,
+		},
+		shader::{CompiledShader, ShaderBuilder},
+		smart_arc::Sarc,
+		texture::Tex,
+	},
+	ShaderAssets,
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Shows a histogram of the luminance of the render in the bottom left corner
+/// of the window, to see how well the exposure fits the scene. It's toggled
+/// with [`Action::ToggleHistogram`].
+///
+/// A compute pass counts the luminance of what the composite draws in
+/// [`Histogram::BINS`] bins over [0; 1], and the counts are read back every
+/// few frames without waiting on the GPU. The bars are drawn relative to the
+/// tallest one that isn't clipped, the first and last ones (the clipped
+/// shadows and highlights) in red. The line is where middle gray ends up with
+/// the current [`ToneMappingSettings`], the effects after the tone mapping
+/// (e.g. the color grading) aren't accounted for.
+///
+/// There's no auto-exposure to share the pass with, so it only runs while the
+/// overlay is shown, or once on demand with the `histogram` console command,
+/// which also prints the clipped percentages. Needs to be added after the
+/// composite renderer.
+pub struct HistogramPlugin {
+	/// The histogram is counted once every this many frames
+	pub interval: u32,
+	/// In physical pixels
+	pub panel_size: Extent2<f32>,
+}
+
+impl Default for HistogramPlugin {
+	fn default() -> Self {
+		Self {
+			interval: 8,
+			panel_size: Extent2::new(256.0, 96.0),
+		}
+	}
+}
+
+impl Plugin for HistogramPlugin {
+	fn build(&self, app: &mut App) {
+		let gpu = app.world.resource::<Gpu>();
+		let source = app.world.resource::<CompositeRenderer>().output_texture().clone();
+		let format = app.world.resource::<RenderTarget>().config.format;
+
+		let histogram = Histogram::new(gpu, source, format, self.interval, self.panel_size);
+		app.world.insert_resource(histogram);
+
+		console::register_command(
+			app,
+			"histogram",
+			"histogram [on | off]: Show or hide the luminance histogram, and print how much of the render is clipped",
+			histogram,
+		);
+
+		app.add_systems(Update, toggle_histogram.run_if(is_console_closed));
+		app.add_systems(
+			Render,
+			(count, draw).chain().after(CompositeRenderPass).in_set(InnerRenderPass),
+		);
+		app.add_systems(
+			Render,
+			read_counts.after(render::finish_render_pass).in_set(PostRenderPass),
+		);
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// What the last read histogram says
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistogramStats {
+	/// How many pixels fell in every bin, from black to white
+	pub bins: Vec<u32>,
+}
+
+impl HistogramStats {
+	pub fn total(&self) -> u64 {
+		self.bins.iter().map(|count| *count as u64).sum()
+	}
+
+	/// The part of the pixels in the first bin, black or darker
+	pub fn shadows_clipped(&self) -> f32 {
+		self.part(self.bins.first())
+	}
+
+	/// The part of the pixels in the last bin, white or brighter
+	pub fn highlights_clipped(&self) -> f32 {
+		self.part(self.bins.last())
+	}
+
+	/// The height of the bars in [0; 1], relative to the tallest bin that isn't
+	/// clipped, so that a lot of black doesn't flatten everything else
+	pub fn normalized(&self) -> Vec<f32> {
+		let inner = &self.bins[1..self.bins.len().saturating_sub(1).max(1)];
+		let tallest = inner.iter().copied().max().unwrap_or(0).max(1) as f32;
+
+		self.bins
+			.iter()
+			.map(|count| (*count as f32 / tallest).min(1.0))
+			.collect()
+	}
+
+	fn part(&self, count: Option<&u32>) -> f32 {
+		let total = self.total();
+		match count {
+			Some(count) if total > 0 => *count as f32 / total as f32,
+			_ => 0.0,
+		}
+	}
+}
+
+/// The overlay's uniform, bound as `histogram_marker`
+#[repr(C)]
+#[derive(ShaderStruct, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+pub struct HistogramMarker {
+	/// Where middle gray ends up in [0; 1], negative to hide the line
+	pub middle_gray: f32,
+	#[shader(skip)]
+	_padding: [u32; 3],
+}
+
+impl HistogramMarker {
+	/// The middle gray of the photographers, 18% reflectance
+	pub const MIDDLE_GRAY: f32 = 0.18;
+
+	pub fn new(tone_mapping: Option<&ToneMappingSettings>) -> Self {
+		Self {
+			middle_gray: tone_mapping.map_or(-1.0, |settings| settings.map_gray(Self::MIDDLE_GRAY)),
+			_padding: [0; 3],
+		}
+	}
+}
+
+#[derive(bevy::Resource)]
+pub struct Histogram {
+	pub enabled: bool,
+	pub interval: u32,
+	pub panel_size: Extent2<f32>,
+	/// Counts the rendered frames since the overlay was shown
+	frame: u64,
+	/// Count once more even if the overlay is hidden, see [`request`](Self::request)
+	requested: bool,
+	stats: Option<HistogramStats>,
+
+	pass: HistogramPass,
+	bins_buffer: Sarc<Buffer>,
+	readback_buffer: Buffer,
+	// The readback buffer can't be copied into while it's mapped, so a new copy
+	// is only made once the previous one was read
+	copied: bool,
+	mapping: bool,
+	mapped: Arc<AtomicBool>,
+
+	/// The normalized bars and the [`HistogramMarker`] of the overlay
+	bars_buffer: Sarc<Buffer>,
+	marker_buffer: Sarc<Buffer>,
+	overlay: HistogramOverlay,
+}
+
+impl Histogram {
+	pub const BINS: u32 = 256;
+
+	/// Pixels between the panel and the edges of the window
+	const MARGIN: f32 = 16.0;
+
+	fn new(gpu: &Gpu, source: Sarc<Tex>, format: TextureFormat, interval: u32, panel_size: Extent2<f32>) -> Self {
+		let bins_buffer = Sarc::new(AtomicCounter::raw_buffer_from_count(
+			gpu,
+			Self::BINS,
+			Some("Histogram bins"),
+		));
+		let bars_buffer = Sarc::new(StorageBuffer::raw_buffer_from_size(
+			gpu,
+			Self::BINS as u64 * f32::get_size(),
+			Some("Histogram bars"),
+		));
+		let marker_buffer = Sarc::new(UniformBuffer::raw_buffer_from_data(
+			gpu,
+			&HistogramMarker::new(None),
+			Some("Histogram marker"),
+		));
+
+		Self {
+			enabled: false,
+			interval: interval.max(1),
+			panel_size,
+			frame: 0,
+			requested: false,
+			stats: None,
+			pass: HistogramPass::new(gpu, source, bins_buffer.clone()),
+			readback_buffer: gpu.device.create_buffer(&BufferDescriptor {
+				label: Some("Histogram Readback Buffer"),
+				size: bins_buffer.size(),
+				usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+				mapped_at_creation: false,
+			}),
+			bins_buffer,
+			copied: false,
+			mapping: false,
+			mapped: Arc::new(AtomicBool::new(false)),
+			overlay: HistogramOverlay::new(gpu, bars_buffer.clone(), marker_buffer.clone(), format),
+			bars_buffer,
+			marker_buffer,
+		}
+	}
+
+	/// The last histogram read back, `None` until one was counted
+	pub fn stats(&self) -> Option<&HistogramStats> {
+		self.stats.as_ref()
+	}
+
+	/// Count the histogram with the next frame even if the overlay is hidden.
+	/// It's read back a frame or two later.
+	pub fn request(&mut self) {
+		self.requested = true;
+	}
+
+	/// Where the panel is in the window, as its top left corner and its size in
+	/// physical pixels. `None` if the window is too small for it.
+	pub fn panel_rect(&self, window_size: Extent2<u32>) -> Option<(Vec2<f32>, Extent2<f32>)> {
+		let window = Extent2::new(window_size.w as f32, window_size.h as f32);
+		let size = Extent2::new(
+			self.panel_size.w.min(window.w - 2.0 * Self::MARGIN),
+			self.panel_size.h.min(window.h - 2.0 * Self::MARGIN),
+		);
+
+		if size.w < 1.0 || size.h < 1.0 {
+			return None;
+		}
+
+		let corner = Vec2::new(Self::MARGIN, window.h - size.h - Self::MARGIN);
+		Some((corner, size))
+	}
+
+	/// Whether the pass should count this frame
+	fn is_due(&self) -> bool {
+		self.requested || (self.enabled && self.frame % self.interval as u64 == 0)
+	}
+}
+
+/// The compute pass counting the bins, for the texture the composite draws
+struct HistogramPass {
+	source: Sarc<Tex>,
+	shader: CompiledShader,
+	pipeline: ComputePipeline,
+	workgroups: Vec2<u32>,
+}
+
+impl HistogramPass {
+	const WORKGROUP_SIZE: u32 = 16;
+
+	fn new(gpu: &Gpu, source: Sarc<Tex>, bins_buffer: Sarc<Buffer>) -> Self {
+		let size = source.size();
+
+		let shader = ShaderBuilder::new()
+			.include_path("histogram/histogram.wgsl")
+			.define("WORKGROUP_SIZE", format!("{}", Self::WORKGROUP_SIZE))
+			.define("HISTOGRAM_BINS", format!("{}u", Histogram::BINS))
+			.include_buffer(StorageTexture::FromTex {
+				var_name: "histogram_source",
+				access: StorageTextureAccess::ReadOnly,
+				tex: source.clone(),
+			})
+			.include_buffer(AtomicCounterDescriptor::FromBuffer {
+				var_name: "histogram_bins",
+				buffer: bins_buffer,
+			})
+			.build(gpu, "Histogram Shader", &ShaderAssets, ShaderStages::COMPUTE, 0)
+			.expect("Couldn't build shader");
+
+		let pipeline_layout = gpu.device.create_pipeline_layout(&PipelineLayoutDescriptor {
+			label: Some("Histogram Pipeline Layout"),
+			bind_group_layouts: &shader.layouts(),
+			push_constant_ranges: &[],
+		});
+
+		let pipeline = shader.create_pipeline(gpu, || {
+			gpu.device.create_compute_pipeline(&ComputePipelineDescriptor {
+				label: Some("Histogram Pipeline"),
+				layout: Some(&pipeline_layout),
+				module: &shader.shader_module,
+				entry_point: "main",
+			})
+		});
+
+		Self {
+			source,
+			shader,
+			pipeline,
+			workgroups: vec2!(size.width, size.height) / Self::WORKGROUP_SIZE + vec2!(1),
+		}
+	}
+}
+
+/// Draws the bars over the composited image
+struct HistogramOverlay {
+	pipeline: RenderPipeline,
+	shader: CompiledShader,
+}
+
+impl HistogramOverlay {
+	fn new(gpu: &Gpu, bars_buffer: Sarc<Buffer>, marker_buffer: Sarc<Buffer>, format: TextureFormat) -> Self {
+		let shader = ShaderBuilder::new()
+			.include_path("composite/histogram.wgsl")
+			.define("HISTOGRAM_BINS", format!("{}u", Histogram::BINS))
+			.include_buffer(StorageBufferDescriptor::FromBuffer::<StorageArray<f32>, _> {
+				var_name: "histogram_bars",
+				read_only: true,
+				buffer: bars_buffer,
+			})
+			.include_buffer(UniformBufferDescriptor::FromBuffer::<HistogramMarker, _> {
+				var_name: "histogram_marker",
+				buffer: marker_buffer,
+			})
+			.build(
+				gpu,
+				"Histogram Overlay Shader",
+				&ShaderAssets,
+				ShaderStages::FRAGMENT,
+				0,
+			)
+			.expect("Couldn't build shader");
+
+		let pipeline_layout = gpu.device.create_pipeline_layout(&PipelineLayoutDescriptor {
+			label: Some("Histogram Overlay Pipeline Layout"),
+			bind_group_layouts: &shader.layouts(),
+			push_constant_ranges: &[],
+		});
+
+		// Same quad as the picture-in-picture, squeezed into the panel by the
+		// viewport. Blended, the background is see-through.
+		let pipeline = shader.create_pipeline(gpu, || {
+			gpu.device.create_render_pipeline(&RenderPipelineDescriptor {
+				label: Some("Histogram Overlay Pipeline"),
+				layout: Some(&pipeline_layout),
+				vertex: VertexState {
+					module: &shader.shader_module,
+					entry_point: "vs_main",
+					buffers: &[],
+				},
+				fragment: Some(FragmentState {
+					module: &shader.shader_module,
+					entry_point: "fs_main",
+					targets: &[Some(ColorTargetState {
+						format,
+						blend: Some(BlendState::ALPHA_BLENDING),
+						write_mask: ColorWrites::ALL,
+					})],
+				}),
+				primitive: PrimitiveState {
+					topology: PrimitiveTopology::TriangleStrip,
+					strip_index_format: None,
+					front_face: FrontFace::Ccw,
+					cull_mode: None,
+					polygon_mode: PolygonMode::Fill,
+					unclipped_depth: false,
+					conservative: false,
+				},
+				depth_stencil: None,
+				multisample: MultisampleState {
+					count: 1,
+					mask: !0,
+					alpha_to_coverage_enabled: false,
+				},
+				multiview: None,
+			})
+		});
+
+		Self { pipeline, shader }
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+fn toggle_histogram(
+	mut histogram: ResMut<Histogram>,
+	mut keyboard_events: EventReader<KeyboardInputEvent>,
+	key_bindings: Res<KeyBindings>,
+) {
+	if key_bindings.has_pressed(Action::ToggleHistogram, keyboard_events.read()) {
+		histogram.enabled = !histogram.enabled;
+		// Count it right away, the bars are stale
+		histogram.frame = 0;
+		info!("Histogram: {}", if histogram.enabled { "on" } else { "off" });
+	}
+}
+
+/// Runs after the composite, on the same texture it drew
+fn count(
+	mut histogram: ResMut<Histogram>,
+	composite_renderer: Res<CompositeRenderer>,
+	mut render_target: ResMut<RenderTarget<'static>>,
+	gpu: Res<Gpu>,
+	parity: Res<PingPongParity>,
+) {
+	let due = histogram.is_due();
+	if histogram.enabled {
+		histogram.frame += 1;
+	}
+
+	if !due || histogram.copied || histogram.mapping {
+		return;
+	}
+	histogram.requested = false;
+
+	// E.g. after a swap of the renderer, or with the denoiser turned on
+	let source = composite_renderer.output_texture();
+	if &histogram.pass.source != source {
+		histogram.pass = HistogramPass::new(&gpu, source.clone(), histogram.bins_buffer.clone());
+	}
+
+	let mut encoder = gpu.device.create_command_encoder(&CommandEncoderDescriptor {
+		label: Some("Histogram Command Encoder"),
+	});
+
+	encoder.clear_buffer(&histogram.bins_buffer, 0, None);
+
+	{
+		// Not timed, it doesn't run every frame
+		let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+			label: Some("Histogram Pass"),
+			timestamp_writes: None,
+		});
+
+		let pass = &histogram.pass;
+		compute_pass.set_pipeline(&pass.pipeline);
+		compute_pass.apply_buffer_mapping(&pass.shader.binding, *parity);
+		compute_pass.dispatch_workgroups(pass.workgroups.x, pass.workgroups.y, 1);
+	}
+
+	encoder.copy_buffer_to_buffer(
+		&histogram.bins_buffer,
+		0,
+		&histogram.readback_buffer,
+		0,
+		histogram.readback_buffer.size(),
+	);
+
+	// Submitted together with the passes by `finish_render_pass`
+	render_target.command_queue.push(encoder.finish());
+	histogram.copied = true;
+}
+
+fn read_counts(mut histogram: ResMut<Histogram>, tone_mapping: Query<&ToneMappingSettings>, gpu: Res<Gpu>) {
+	// The copy was just submitted, start mapping it without waiting for it
+	if histogram.copied {
+		let mapped = histogram.mapped.clone();
+		histogram
+			.readback_buffer
+			.slice(..)
+			.map_async(MapMode::Read, move |result| {
+				if result.is_ok() {
+					mapped.store(true, Ordering::Release);
+				}
+			});
+
+		histogram.copied = false;
+		histogram.mapping = true;
+		return;
+	}
+
+	if !histogram.mapping {
+		return;
+	}
+
+	gpu.device.poll(Maintain::Poll);
+	if !histogram.mapped.swap(false, Ordering::Acquire) {
+		return;
+	}
+
+	let bins = bytemuck::cast_slice::<u8, u32>(&histogram.readback_buffer.slice(..).get_mapped_range()).to_vec();
+	histogram.readback_buffer.unmap();
+	histogram.mapping = false;
+
+	let stats = HistogramStats { bins };
+	let bars = stats.normalized();
+	histogram.bars_buffer.upload_bytes(&gpu, bytemuck::cast_slice(&bars), 0);
+
+	// The exposure can change in between, but then the bars are stale too
+	let marker = HistogramMarker::new(tone_mapping.get_single().ok());
+	histogram.marker_buffer.upload_bytes(&gpu, &marker.get_bytes(), 0);
+
+	histogram.stats = Some(stats);
+}
+
+fn draw(
+	histogram: Res<Histogram>,
+	mut render_target: ResMut<RenderTarget<'static>>,
+	gpu: Res<Gpu>,
+	parity: Res<PingPongParity>,
+) {
+	// Nothing to draw before the first counts are read
+	if !histogram.enabled || histogram.stats.is_none() {
+		return;
+	}
+
+	let Some((corner, size)) = histogram.panel_rect(render_target.size.0) else {
+		return;
+	};
+
+	let mut encoder = gpu.device.create_command_encoder(&CommandEncoderDescriptor {
+		label: Some("Histogram Overlay Command Encoder"),
+	});
+
+	{
+		let render_view = render_target
+			.current_view
+			.as_ref()
+			.expect("Attempt to encode renderpass while RenderTarget view is unavailable");
+
+		// Drawn on top of the composited image
+		let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+			label: Some("Histogram Overlay Render Pass"),
+			color_attachments: &[Some(RenderPassColorAttachment {
+				view: render_view,
+				resolve_target: None,
+				ops: Operations {
+					load: LoadOp::Load,
+					store: StoreOp::Store,
+				},
+			})],
+			depth_stencil_attachment: None,
+			occlusion_query_set: None,
+			timestamp_writes: None,
+		});
+
+		render_pass.set_viewport(corner.x, corner.y, size.w, size.h, 0.0, 1.0);
+		render_pass.set_pipeline(&histogram.overlay.pipeline);
+		render_pass.apply_buffer_mapping(&histogram.overlay.shader.binding, *parity);
+		render_pass.draw(0..4, 0..1);
+	}
+
+	render_target.command_queue.push(encoder.finish());
+}
+
+fn histogram(world: &mut World, args: &[String]) -> Result<String> {
+	let mut histogram = world.resource_mut::<Histogram>();
+
+	match args {
+		[] => {}
+		[state] if state == "on" => histogram.enabled = true,
+		[state] if state == "off" => histogram.enabled = false,
+		_ => bail!("Usage: histogram [on | off]"),
+	}
+
+	// Counted even with the overlay hidden, so that the numbers are up to date
+	// next time
+	histogram.request();
+
+	let shown = if histogram.enabled { "shown" } else { "hidden" };
+	let Some(stats) = histogram.stats().cloned() else {
+		return Ok(format!(
+			"Histogram {}, nothing counted yet, ask again in a moment",
+			shown
+		));
+	};
+
+	let mut line = format!(
+		"Histogram {}: {:.1}% of the pixels clipped to black, {:.1}% to white",
+		shown,
+		stats.shadows_clipped() * 100.0,
+		stats.highlights_clipped() * 100.0
+	);
+
+	if let Ok(settings) = world.query::<&ToneMappingSettings>().get_single(world) {
+		line += &format!(
+			", middle gray at {:.2}",
+			settings.map_gray(HistogramMarker::MIDDLE_GRAY)
+		);
+	}
+
+	Ok(line)
+}
@@ -0,0 +1,347 @@
+use std::{
+	collections::VecDeque,
+	time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use bevy_ecs::{
+	query::With,
+	schedule::IntoSystemConfigs,
+	system::{Query, Res, ResMut},
+	world::World,
+};
+use brainrot::bevy::{self, App, Plugin};
+use log::debug;
+
+use super::{
+	accumulation::{self, Accumulation},
+	camera_view::CameraView,
+	capture::HighQualityCapture,
+	globals::{RenderSettings, RenderSettingsOverrides},
+};
+use crate::{
+	core::{
+		camera::ActiveCamera,
+		console,
+		gameloop::{PreRender, Time},
+		params,
+	},
+	fragments::path_tracer::PathTracerSettings,
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Renders fast while the camera moves and at full quality once it stops, see
+/// [`RenderMode`].
+///
+/// While moving, the [`RenderSettingsOverrides`] of the [`InteractiveSettings`]
+/// go to the shaders instead of the [`RenderSettings`], which themselves are
+/// left alone, and a [`tweakable`](crate::fragments::path_tracer::PathTracer::tweakable)
+/// path tracer traces a single sample with fewer bounces. Once the camera
+/// stopped for a moment, the full settings come back and the accumulation
+/// starts over, but the path tracer keeps showing the last interactive frame
+/// until a few full frames are summed, so that the picture doesn't get noisier
+/// for a moment.
+///
+/// The mode changes are logged at the debug level, and the `render_mode`
+/// console command lists the latest ones. Needs to be added after the
+/// accumulation and globals plugins.
+#[derive(Default)]
+pub struct InteractiveRenderingPlugin {
+	pub settings: InteractiveSettings,
+}
+
+impl Plugin for InteractiveRenderingPlugin {
+	fn build(&self, app: &mut App) {
+		app.world.insert_resource(InteractiveRendering::new(self.settings));
+
+		params::registry(app)
+			.register_bool(
+				"interactive.enabled",
+				"Whether the quality drops while the camera moves",
+				|world| Ok(world.resource::<InteractiveRendering>().settings.enabled),
+				|world, enabled| {
+					world.resource_mut::<InteractiveRendering>().settings.enabled = enabled;
+					Ok(())
+				},
+			)
+			.register_float(
+				"interactive.march_steps_scale",
+				"Scales the raymarcher's max steps while the camera moves",
+				0.05..=1.0,
+				|world| {
+					let interactive = world.resource::<InteractiveRendering>();
+					Ok(interactive.settings.overrides.march_steps_scale.unwrap_or(1.0))
+				},
+				|world, scale| {
+					world
+						.resource_mut::<InteractiveRendering>()
+						.settings
+						.overrides
+						.march_steps_scale = Some(scale);
+					Ok(())
+				},
+			)
+			.register_int(
+				"interactive.bounces",
+				"How often the paths of the path tracer can bounce while the camera moves",
+				0..=64,
+				|world| Ok(world.resource::<InteractiveRendering>().settings.max_bounces as i64),
+				|world, bounces| {
+					world.resource_mut::<InteractiveRendering>().settings.max_bounces = bounces as u32;
+					Ok(())
+				},
+			)
+			.register_int(
+				"interactive.settle_samples",
+				"How many full frames the path tracer sums before they replace the last interactive one",
+				1..=256,
+				|world| Ok(world.resource::<InteractiveRendering>().settings.settle_samples as i64),
+				|world, samples| {
+					world.resource_mut::<InteractiveRendering>().settings.settle_samples = samples as u32;
+					Ok(())
+				},
+			);
+
+		console::register_command(
+			app,
+			"render_mode",
+			"render_mode: Show whether the render is interactive or converging, and when that last changed",
+			render_mode,
+		);
+
+		app.add_systems(PreRender, update_render_mode.before(accumulation::update_accumulation));
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct InteractiveSettings {
+	/// Stays in [`RenderMode::Converging`] otherwise
+	pub enabled: bool,
+	/// Applied over the [`RenderSettings`] while the camera moves
+	pub overrides: RenderSettingsOverrides,
+	/// The path tracer's bounces while the camera moves, 0 for the direct light
+	/// only (no reflections)
+	pub max_bounces: u32,
+	/// How long the camera has to stay still before the full settings come back,
+	/// so that short pauses don't flicker between the two
+	pub settle_delay: Duration,
+	/// How many full frames the path tracer sums before they replace the last
+	/// interactive frame, at least 1
+	pub settle_samples: u32,
+}
+
+impl Default for InteractiveSettings {
+	fn default() -> Self {
+		Self {
+			enabled: true,
+			overrides: RenderSettingsOverrides {
+				march_steps_scale: Some(0.5),
+				..Default::default()
+			},
+			max_bounces: 0,
+			settle_delay: Duration::from_millis(150),
+			settle_samples: 4,
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum RenderMode {
+	/// The camera is still, the frames are rendered with the full settings and
+	/// summed
+	#[default]
+	Converging,
+	/// The camera moves, the frames are rendered with the interactive settings
+	/// and every one of them starts over
+	Interactive,
+	/// The camera just stopped, the frames are rendered with the full settings
+	/// and summed, but the path tracer keeps showing the last interactive frame
+	Settling,
+}
+
+#[derive(bevy::Resource, Debug)]
+pub struct InteractiveRendering {
+	pub settings: InteractiveSettings,
+	mode: RenderMode,
+
+	/// To tell when the camera moved
+	last_view: Option<CameraView>,
+	still_since: Instant,
+	/// The path tracer's full settings and the interactive ones that replaced
+	/// them, while in [`RenderMode::Interactive`]
+	replaced_path_tracer_settings: Option<(PathTracerSettings, PathTracerSettings)>,
+	/// Counted rather than read from the [`Accumulation`], which something else
+	/// could keep starting over
+	settling_frames: u32,
+
+	/// When (since the start of the app) and from what to what the latest mode
+	/// changes were, oldest first
+	transitions: VecDeque<(Duration, RenderMode, RenderMode)>,
+}
+
+impl InteractiveRendering {
+	/// How many transitions the `render_mode` command shows
+	const KEPT_TRANSITIONS: usize = 8;
+
+	pub fn new(settings: InteractiveSettings) -> Self {
+		Self {
+			settings,
+			mode: RenderMode::default(),
+			last_view: None,
+			still_since: Instant::now(),
+			replaced_path_tracer_settings: None,
+			settling_frames: 0,
+			transitions: VecDeque::new(),
+		}
+	}
+
+	pub fn mode(&self) -> RenderMode {
+		self.mode
+	}
+
+	/// Whether the renderers should leave their output as it is, see
+	/// [`RenderMode::Settling`]
+	pub fn holds_output(&self) -> bool {
+		self.mode == RenderMode::Settling
+	}
+
+	/// The settings the shaders should get, which are the interactive ones while
+	/// the camera moves
+	pub fn effective_settings(&self, settings: &RenderSettings) -> RenderSettings {
+		let mut settings = *settings;
+		if self.mode == RenderMode::Interactive {
+			self.settings.overrides.apply(&mut settings);
+		}
+		settings
+	}
+
+	pub fn transitions(&self) -> impl Iterator<Item = &(Duration, RenderMode, RenderMode)> {
+		self.transitions.iter()
+	}
+
+	/// The mode that comes after the current one
+	fn next_mode(&self, moved: bool, capturing: bool) -> RenderMode {
+		// The capture needs the full settings, and sums the frames itself
+		if !self.settings.enabled || capturing {
+			return RenderMode::Converging;
+		}
+
+		match self.mode {
+			RenderMode::Converging | RenderMode::Settling if moved => RenderMode::Interactive,
+			RenderMode::Interactive if self.still_since.elapsed() >= self.settings.settle_delay => RenderMode::Settling,
+			RenderMode::Settling if self.settling_frames >= self.settings.settle_samples => RenderMode::Converging,
+			mode => mode,
+		}
+	}
+
+	fn change_to(&mut self, mode: RenderMode, now: Duration) {
+		debug!("Render mode: {:?} -> {:?}", self.mode, mode);
+
+		if self.transitions.len() == Self::KEPT_TRANSITIONS {
+			self.transitions.pop_front();
+		}
+		self.transitions.push_back((now, self.mode, mode));
+		self.mode = mode;
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+// Before the accumulation, so that it sees the changed path tracer settings on
+// the same frame
+fn update_render_mode(
+	mut interactive: ResMut<InteractiveRendering>,
+	mut accumulation: ResMut<Accumulation>,
+	mut path_tracer_settings: Query<&mut PathTracerSettings>,
+	cameras: Query<&CameraView, With<ActiveCamera>>,
+	capture: Option<Res<HighQualityCapture>>,
+	time: Res<Time>,
+) {
+	// The first view is where the camera starts, not a move
+	let view = cameras.get_single().ok().copied();
+	let moved = interactive.last_view.is_some() && view != interactive.last_view;
+	interactive.last_view = view;
+	if moved {
+		interactive.still_since = Instant::now();
+	}
+
+	if interactive.mode == RenderMode::Settling {
+		interactive.settling_frames += 1;
+	}
+
+	let capturing = capture.is_some_and(|capture| capture.is_running());
+	let mode = interactive.next_mode(moved, capturing);
+	if mode == interactive.mode {
+		return;
+	}
+
+	if interactive.mode == RenderMode::Interactive {
+		// Unless they were changed in the meantime, e.g. with the params
+		if let Some((full, replaced)) = interactive.replaced_path_tracer_settings.take() {
+			if let Ok(mut settings) = path_tracer_settings.get_single_mut() {
+				if *settings == replaced {
+					*settings = full;
+				}
+			}
+		}
+
+		// The interactive frames don't belong in the full quality sum
+		accumulation.reset();
+	}
+
+	if mode == RenderMode::Interactive {
+		if let Ok(mut settings) = path_tracer_settings.get_single_mut() {
+			let full = *settings;
+			settings.max_bounces = full.max_bounces.min(interactive.settings.max_bounces);
+			settings.samples_per_frame = 1;
+			interactive.replaced_path_tracer_settings = Some((full, *settings));
+		}
+	}
+
+	if mode == RenderMode::Settling {
+		interactive.settling_frames = 0;
+	}
+
+	interactive.change_to(mode, time.current_time);
+}
+
+fn render_mode(world: &mut World, args: &[String]) -> Result<String> {
+	if !args.is_empty() {
+		bail!("Usage: render_mode");
+	}
+
+	let interactive = world.resource::<InteractiveRendering>();
+
+	let mut lines = vec![format!(
+		"{:?}{}",
+		interactive.mode,
+		if interactive.settings.enabled {
+			""
+		} else {
+			" (disabled)"
+		}
+	)];
+	lines.extend(
+		interactive
+			.transitions()
+			.rev()
+			.map(|(at, from, to)| format!("{:.2}s: {:?} -> {:?}", at.as_secs_f32(), from, to)),
+	);
+
+	Ok(lines.join("\n"))
+}
@@ -0,0 +1,283 @@
+use anyhow::{bail, Result};
+use bevy_ecs::{
+	query::With,
+	system::Local,
+	world::{Mut, World},
+};
+use brainrot::{
+	bevy::{self, App, Plugin},
+	vec3,
+	vek::{Rgb, Vec3},
+};
+use derive_more::{Deref, DerefMut};
+use log::info;
+use pbr_tracer_derive::ShaderStruct;
+use wgpu::Buffer;
+
+use super::{
+	capture::HighQualityCapture,
+	compute::{swap_compute_renderer, ComputeRenderer},
+};
+use crate::{
+	core::{entity_label::EntityLabel, gameloop::PreRender, gpu::Gpu, params},
+	libs::{
+		buffer::{
+			storage_buffer::{StorageArray, StorageBuffer},
+			DynBufferUploadable, ShaderType,
+		},
+		smart_arc::Sarc,
+	},
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Spawns the storage buffer of the [`Lights`], which the compute renderer
+/// binds as `lights` for the shading fragments (see `shading/lights.wgsl`).
+/// Needs to be added before the compute renderer, which binds no lights
+/// without it.
+///
+/// The lights are uploaded whenever the resource changes. The shaders take the
+/// light count from the size of the buffer, so adding or removing lights makes
+/// a new buffer and rebuilds the compute renderer to bind it.
+pub struct LightsPlugin;
+
+impl Plugin for LightsPlugin {
+	fn build(&self, app: &mut App) {
+		app.world.init_resource::<Lights>();
+
+		let gpu = app.world.resource::<Gpu>();
+		let lights_buffer = Sarc::new(StorageBuffer::raw_buffer_from_data(
+			gpu,
+			&app.world.resource::<Lights>().gpu_data(),
+			Some("Lights"),
+		));
+		app.world.spawn((LightsBuffer, lights_buffer));
+
+		for (axis, name) in ["x", "y", "z"].into_iter().enumerate() {
+			params::registry(app).register_float(
+				format!("sun.{}", name),
+				format!(
+					"The {} of the direction of the first directional light, normalized afterwards",
+					name
+				),
+				-1.0..=1.0,
+				move |world| Ok(sun(world)?.direction[axis]),
+				move |world, value| {
+					let mut sun = sun(world)?;
+					let mut direction = sun.direction;
+					direction[axis] = value;
+					if direction.magnitude_squared() < 1e-8 {
+						bail!("The direction can't be zero");
+					}
+					sun.direction = direction.normalized();
+					Ok(())
+				},
+			);
+		}
+
+		app.add_systems(PreRender, update_lights);
+	}
+}
+
+/// The first directional light
+fn sun(world: &mut World) -> Result<Mut<'_, Light>> {
+	let lights = world.resource_mut::<Lights>();
+	if !lights.iter().any(|light| light.kind == Light::DIRECTIONAL) {
+		bail!("There is no directional light");
+	}
+
+	Ok(lights.map_unchanged(|lights| {
+		lights
+			.iter_mut()
+			.find(|light| light.kind == Light::DIRECTIONAL)
+			.unwrap()
+	}))
+}
+
+/// Marks the buffer that the renderers bind as `lights`
+#[derive(bevy::Component)]
+pub struct LightsBuffer;
+impl EntityLabel for LightsBuffer {}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// A point, directional or spot light, see the constructors. The kind is one
+/// of the `Light::*` constants.
+#[repr(C)]
+#[derive(ShaderStruct, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+pub struct Light {
+	pub position: Vec3<f32>,
+	pub kind: u32,
+	/// Where the light is going, for the directional and spot lights
+	pub direction: Vec3<f32>,
+	/// The point and spot lights don't reach further than this
+	pub radius: f32,
+	pub color: Rgb<f32>,
+	pub intensity: f32,
+	/// The cosines of the angles (from the direction) where a spot light starts
+	/// fading out and where it's gone
+	pub cos_inner_cone: f32,
+	pub cos_outer_cone: f32,
+	/// The radius of the sphere that gives off the light of a point or spot
+	/// light, 0 for a point, see [`with_size`](Self::with_size)
+	pub size: f32,
+	#[shader(skip)]
+	_padding: u32,
+}
+
+impl Light {
+	// Same as the `LIGHT_*` constants of `shading/lights.wgsl`
+	pub const POINT: u32 = 0;
+	pub const DIRECTIONAL: u32 = 1;
+	pub const SPOT: u32 = 2;
+
+	pub fn point(position: Vec3<f32>, color: Rgb<f32>, intensity: f32, radius: f32) -> Self {
+		Self {
+			position,
+			kind: Self::POINT,
+			direction: Vec3::zero(),
+			radius,
+			color,
+			intensity,
+			cos_inner_cone: 0.0,
+			cos_outer_cone: 0.0,
+			size: 0.0,
+			_padding: 0,
+		}
+	}
+
+	pub fn directional(direction: Vec3<f32>, color: Rgb<f32>, intensity: f32) -> Self {
+		Self {
+			position: Vec3::zero(),
+			kind: Self::DIRECTIONAL,
+			direction: direction.normalized(),
+			radius: 0.0,
+			color,
+			intensity,
+			cos_inner_cone: 0.0,
+			cos_outer_cone: 0.0,
+			size: 0.0,
+			_padding: 0,
+		}
+	}
+
+	/// The cone angles are in radians, from the direction to the edge
+	pub fn spot(
+		position: Vec3<f32>,
+		direction: Vec3<f32>,
+		color: Rgb<f32>,
+		intensity: f32,
+		radius: f32,
+		inner_angle: f32,
+		outer_angle: f32,
+	) -> Self {
+		Self {
+			position,
+			kind: Self::SPOT,
+			direction: direction.normalized(),
+			radius,
+			color,
+			intensity,
+			cos_inner_cone: inner_angle.cos(),
+			// A hard edge would divide by zero in the falloff
+			cos_outer_cone: outer_angle.cos().min(inner_angle.cos() - 1e-4),
+			size: 0.0,
+			_padding: 0,
+		}
+	}
+
+	/// A sphere of light instead of a point, as bright in total. Only the
+	/// [`PathTracer`](crate::fragments::path_tracer::PathTracer) sees the
+	/// sphere, the other renderers still light with its center. The directional
+	/// lights stay directions.
+	pub fn with_size(mut self, size: f32) -> Self {
+		self.size = size.max(0.0);
+		self
+	}
+}
+
+/// The lights of the scene, uploaded by the [`LightsPlugin`]
+#[derive(bevy::Resource, Deref, DerefMut, Clone, Debug, PartialEq)]
+pub struct Lights(pub Vec<Light>);
+
+impl Default for Lights {
+	/// The sun that used to be hard-coded in the shading fragments
+	fn default() -> Self {
+		Self(vec![Light::directional(vec3!(1.0, -1.0, 1.0), Rgb::one(), 1.0)])
+	}
+}
+
+impl Lights {
+	/// What ends up in the buffer. Storage arrays can't be empty, so no lights is
+	/// a single black one.
+	pub fn gpu_data(&self) -> StorageArray<Light> {
+		if self.is_empty() {
+			StorageArray(vec![Light::directional(-Vec3::unit_y(), Rgb::zero(), 0.0)])
+		} else {
+			StorageArray(self.0.clone())
+		}
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+fn update_lights(world: &mut World, mut rebind_pending: Local<bool>) {
+	if world.is_resource_changed::<Lights>() {
+		let data = world.resource::<Lights>().gpu_data();
+
+		world.resource_scope(|world, gpu: Mut<Gpu>| {
+			let mut buffers = world.query_filtered::<&mut Sarc<Buffer>, With<LightsBuffer>>();
+
+			for mut buffer in buffers.iter_mut(world) {
+				if buffer.size() == data.get_dyn_size() {
+					buffer.upload_bytes(&gpu, &data.get_dyn_bytes(), 0);
+				} else {
+					*buffer = Sarc::new(StorageBuffer::raw_buffer_from_data(&gpu, &data, Some("Lights")));
+					*rebind_pending = true;
+				}
+			}
+		});
+	}
+
+	// The capture swapped the renderer, wait until it puts the interactive one
+	// back
+	let capturing = world
+		.get_resource::<HighQualityCapture>()
+		.is_some_and(|capture| capture.is_running());
+
+	if !*rebind_pending || capturing {
+		return;
+	}
+	*rebind_pending = false;
+
+	let Some(buffer) = world
+		.query_filtered::<&Sarc<Buffer>, With<LightsBuffer>>()
+		.iter(world)
+		.next()
+		.cloned()
+	else {
+		return;
+	};
+
+	let renderer = world
+		.resource::<ComputeRenderer>()
+		.with_lights(world.resource::<Gpu>(), buffer);
+	swap_compute_renderer(world, renderer);
+
+	info!(
+		"Rebuilt the compute renderer for {} lights",
+		world.resource::<Lights>().len()
+	);
+}
@@ -1,4 +1,23 @@
+pub mod accumulation;
 pub mod camera_view;
+pub mod capture;
+pub mod chunked_upload;
+pub mod color_grade;
 pub mod composite;
 pub mod compute;
+pub mod denoise;
+pub mod depth;
+pub mod depth_of_field;
+pub mod dynamic_quality;
+pub mod environment;
+pub mod frame_info;
+pub mod globals;
+pub mod gpu_asserts;
+pub mod gpu_timers;
+pub mod histogram;
+pub mod interactive;
+pub mod lights;
+pub mod picture_in_picture;
 pub mod render;
+pub mod render_scale;
+pub mod sky;
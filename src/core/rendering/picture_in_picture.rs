@@ -0,0 +1,415 @@
+use bevy_ecs::{
+	entity::Entity,
+	event::EventReader,
+	query::With,
+	schedule::IntoSystemConfigs,
+	system::{Commands, Query, Res, ResMut},
+	world::{Mut, World},
+};
+use brainrot::{
+	bevy::{self, App, Plugin},
+	rad, size, vec3,
+	vek::{Extent2, Vec2},
+	Direction, Frustum, Position, SAFE_FRAC_PI_2,
+};
+use log::{info, warn};
+use wgpu::{
+	BlendState, Buffer, ColorTargetState, ColorWrites, CommandEncoderDescriptor, FragmentState, FrontFace, LoadOp,
+	MultisampleState, Operations, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology,
+	RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, ShaderStages, StoreOp,
+	TextureFormat, VertexState,
+};
+
+use super::{
+	camera_view::CameraView,
+	composite::CompositeRenderPass,
+	compute::{ComputeRenderer, RendererSwapHooks},
+	render::InnerRenderPass,
+};
+use crate::{
+	core::{
+		camera::{spawn_camera, ActiveCamera, ProjectionMode},
+		console::is_console_closed,
+		events::KeyboardInputEvent,
+		gameloop::{Render, Update},
+		gpu::Gpu,
+		key_bindings::{Action, KeyBindings},
+		picking::FreeClicks,
+		render_target::RenderTarget,
+		size::Resolution,
+	},
+	libs::{
+		buffer::{
+			ping_pong_texture::PingPongParity, sampled_texture_buffer::SampledTexture, uniform_buffer::UniformBuffer,
+			BufferMappingApplicable, BufferUploadable,
+		},
+		shader::{CompiledShader, ShaderBuilder},
+		smart_arc::Sarc,
+	},
+	ShaderAssets,
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Shows the scene from a second camera in the top right corner of the window,
+/// by default a top-down orthographic view above the origin.
+///
+/// The panel is a copy of the compute renderer looking through its own
+/// `camera` buffer, at a low resolution and only every few frames to keep its
+/// cost down. It's toggled with [`Action::TogglePictureInPicture`], and
+/// clicking it (while the cursor is free) swaps the [`ActiveCamera`] and the
+/// [`SecondaryCamera`].
+///
+/// Needs to be added after the compute and composite renderers and the picking
+/// plugin.
+pub struct PictureInPicturePlugin {
+	pub resolution: Resolution,
+	/// The panel is rendered once every this many frames
+	pub interval: u32,
+	/// How much of the window's width the panel takes
+	pub panel_width: f32,
+}
+
+impl Default for PictureInPicturePlugin {
+	fn default() -> Self {
+		Self {
+			resolution: Resolution(size!(426, 240)),
+			interval: 2,
+			panel_width: 0.25,
+		}
+	}
+}
+
+impl Plugin for PictureInPicturePlugin {
+	fn build(&self, app: &mut App) {
+		let gpu = app.world.resource::<Gpu>();
+
+		let camera_buffer = Sarc::new(UniformBuffer::raw_buffer_from_type::<CameraView>(
+			gpu,
+			Some("Picture-in-picture camera view"),
+		));
+
+		let renderer =
+			match app
+				.world
+				.resource::<ComputeRenderer>()
+				.with_camera(gpu, self.resolution, camera_buffer.clone())
+			{
+				Ok(renderer) => renderer,
+				Err(error) => {
+					warn!("No picture-in-picture: {:#}", error);
+					return;
+				}
+			};
+
+		let format = app.world.resource::<RenderTarget>().config.format;
+		let overlay = Overlay::new(gpu, &renderer, format);
+
+		// Looking straight down
+		let mut direction = Direction::default();
+		direction.pitch = rad!(-SAFE_FRAC_PI_2);
+
+		let secondary_camera = spawn_camera(
+			&mut app.world,
+			vec3!(0.0, 15.0, 0.0).into(),
+			direction,
+			Frustum {
+				y_fov: 45_f32.to_radians(),
+				z_near: 0.3,
+				z_far: 40.0,
+			},
+		);
+		app.world
+			.entity_mut(secondary_camera)
+			.insert((SecondaryCamera, ProjectionMode::Orthographic { height: 20.0 }));
+
+		app.world.insert_resource(PictureInPicture {
+			enabled: false,
+			interval: self.interval.max(1),
+			panel_width: self.panel_width,
+			frame: 0,
+			camera_buffer,
+			renderer,
+			overlay,
+		});
+
+		// The panel's renderer is a copy of the main one, so it follows it
+		app.world
+			.resource_mut::<RendererSwapHooks>()
+			.after(rebuild_picture_in_picture);
+
+		app.add_systems(
+			Update,
+			(toggle_picture_in_picture.run_if(is_console_closed), swap_cameras).chain(),
+		);
+		app.add_systems(Render, render.after(CompositeRenderPass).in_set(InnerRenderPass));
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// The camera shown in the picture-in-picture panel. There should only be
+/// one.
+#[derive(bevy::Component)]
+pub struct SecondaryCamera;
+
+#[derive(bevy::Resource)]
+pub struct PictureInPicture {
+	pub enabled: bool,
+	pub interval: u32,
+	pub panel_width: f32,
+	/// Counts the rendered frames since the panel was shown
+	frame: u64,
+	camera_buffer: Sarc<Buffer>,
+	renderer: ComputeRenderer,
+	overlay: Overlay,
+}
+
+impl PictureInPicture {
+	/// Pixels between the panel and the edges of the window
+	const MARGIN: f32 = 16.0;
+
+	pub fn resolution(&self) -> Resolution {
+		self.renderer.resolution()
+	}
+
+	/// Where the panel is in the window, as its top left corner and its size in
+	/// physical pixels. `None` if the window is too small for it.
+	pub fn panel_rect(&self, window_size: Extent2<u32>) -> Option<(Vec2<f32>, Extent2<f32>)> {
+		let resolution = self.resolution().0;
+		let window = Extent2::new(window_size.w as f32, window_size.h as f32);
+
+		let width = window.w * self.panel_width;
+		let height = (width * resolution.h as f32 / resolution.w as f32).min(window.h - 2.0 * Self::MARGIN);
+		let width = height * resolution.w as f32 / resolution.h as f32;
+
+		if width < 1.0 || height < 1.0 {
+			return None;
+		}
+
+		let corner = Vec2::new(window.w - width - Self::MARGIN, Self::MARGIN);
+		Some((corner, Extent2::new(width, height)))
+	}
+
+	/// Whether a point of the window is on the shown panel
+	pub fn contains(&self, point: Vec2<f32>, window_size: Extent2<u32>) -> bool {
+		self.enabled
+			&& self.panel_rect(window_size).is_some_and(|(corner, size)| {
+				point.x >= corner.x && point.y >= corner.y && point.x < corner.x + size.w && point.y < corner.y + size.h
+			})
+	}
+}
+
+/// Draws the panel's output texture over the composited image
+struct Overlay {
+	pipeline: RenderPipeline,
+	shader: CompiledShader,
+}
+
+impl Overlay {
+	fn new(gpu: &Gpu, renderer: &ComputeRenderer, format: TextureFormat) -> Self {
+		let output_texture = renderer
+			.output_textures
+			.first()
+			.expect("Compute renderer needs at least 1 output texture")
+			.clone();
+
+		let shader = ShaderBuilder::new()
+			.include_path("composite/picture_in_picture.wgsl")
+			.include_buffer(SampledTexture::FromTex {
+				texture_var_name: "pip_texture",
+				sampler_var_name: "pip_sampler",
+				tex: output_texture,
+			})
+			.build(
+				gpu,
+				"Picture-in-picture Shader",
+				&ShaderAssets,
+				ShaderStages::FRAGMENT,
+				0,
+			)
+			.expect("Couldn't build shader");
+
+		let pipeline_layout = gpu.device.create_pipeline_layout(&PipelineLayoutDescriptor {
+			label: Some("Picture-in-picture Pipeline Layout"),
+			bind_group_layouts: &shader.layouts(),
+			push_constant_ranges: &[],
+		});
+
+		// Same quad as the composite pass, squeezed into the panel by the viewport
+		let pipeline = shader.create_pipeline(gpu, || {
+			gpu.device.create_render_pipeline(&RenderPipelineDescriptor {
+				label: Some("Picture-in-picture Pipeline"),
+				layout: Some(&pipeline_layout),
+				vertex: VertexState {
+					module: &shader.shader_module,
+					entry_point: "vs_main",
+					buffers: &[],
+				},
+				fragment: Some(FragmentState {
+					module: &shader.shader_module,
+					entry_point: "fs_main",
+					targets: &[Some(ColorTargetState {
+						format,
+						blend: Some(BlendState::REPLACE),
+						write_mask: ColorWrites::ALL,
+					})],
+				}),
+				primitive: PrimitiveState {
+					topology: PrimitiveTopology::TriangleStrip,
+					strip_index_format: None,
+					front_face: FrontFace::Ccw,
+					cull_mode: None,
+					polygon_mode: PolygonMode::Fill,
+					unclipped_depth: false,
+					conservative: false,
+				},
+				depth_stencil: None,
+				multisample: MultisampleState {
+					count: 1,
+					mask: !0,
+					alpha_to_coverage_enabled: false,
+				},
+				multiview: None,
+			})
+		});
+
+		Self { pipeline, shader }
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+fn rebuild_picture_in_picture(world: &mut World) {
+	world.resource_scope(|world, mut pip: Mut<PictureInPicture>| {
+		let gpu = world.resource::<Gpu>();
+		let format = world.resource::<RenderTarget>().config.format;
+
+		match world
+			.resource::<ComputeRenderer>()
+			.with_camera(gpu, pip.resolution(), pip.camera_buffer.clone())
+		{
+			Ok(renderer) => {
+				pip.overlay = Overlay::new(gpu, &renderer, format);
+				pip.renderer = renderer;
+				pip.frame = 0;
+			}
+			Err(error) => {
+				warn!("Couldn't rebuild the picture-in-picture, hiding it: {:#}", error);
+				pip.enabled = false;
+			}
+		}
+	});
+}
+
+fn toggle_picture_in_picture(
+	mut pip: ResMut<PictureInPicture>,
+	mut keyboard_events: EventReader<KeyboardInputEvent>,
+	key_bindings: Res<KeyBindings>,
+) {
+	if key_bindings.has_pressed(Action::TogglePictureInPicture, keyboard_events.read()) {
+		pip.enabled = !pip.enabled;
+		// Render it right away, the texture is stale
+		pip.frame = 0;
+		info!("Picture-in-picture: {}", if pip.enabled { "on" } else { "off" });
+	}
+}
+
+fn swap_cameras(
+	mut commands: Commands,
+	pip: Res<PictureInPicture>,
+	mut clicks: FreeClicks,
+	render_target: Res<RenderTarget<'static>>,
+	active_cameras: Query<Entity, With<ActiveCamera>>,
+	secondary_cameras: Query<Entity, With<SecondaryCamera>>,
+) {
+	let Some(cursor) = clicks.clicked_at() else {
+		return;
+	};
+
+	if !pip.contains(cursor, render_target.size.0) {
+		return;
+	}
+
+	for entity in active_cameras.iter() {
+		commands.entity(entity).remove::<ActiveCamera>().insert(SecondaryCamera);
+	}
+	for entity in secondary_cameras.iter() {
+		commands.entity(entity).remove::<SecondaryCamera>().insert(ActiveCamera);
+	}
+}
+
+fn render(
+	mut pip: ResMut<PictureInPicture>,
+	mut render_target: ResMut<RenderTarget<'static>>,
+	gpu: Res<Gpu>,
+	parity: Res<PingPongParity>,
+	cameras: Query<(&Position, &Direction, &Frustum, &ProjectionMode), With<SecondaryCamera>>,
+) {
+	if !pip.enabled {
+		return;
+	}
+
+	let Some((corner, size)) = pip.panel_rect(render_target.size.0) else {
+		return;
+	};
+
+	let mut encoder = gpu.device.create_command_encoder(&CommandEncoderDescriptor {
+		label: Some("Picture-in-picture Command Encoder"),
+	});
+
+	// In between, the panel shows the last frame again
+	if pip.frame % pip.interval as u64 == 0 {
+		if let Ok((position, direction, frustum, projection_mode)) = cameras.get_single() {
+			// Not the camera's own view, that one is for the main resolution
+			let view = CameraView::new(*position, *direction, *frustum, *projection_mode, pip.resolution());
+			pip.camera_buffer.upload_bytes(&gpu, &view.get_bytes(), 0);
+		}
+
+		// Not timed, it doesn't run every frame
+		pip.renderer.encode(&mut encoder, *parity, None);
+	}
+	pip.frame += 1;
+
+	{
+		let render_view = render_target
+			.current_view
+			.as_ref()
+			.expect("Attempt to encode renderpass while RenderTarget view is unavailable");
+
+		// Drawn on top of the composited image
+		let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+			label: Some("Picture-in-picture Render Pass"),
+			color_attachments: &[Some(RenderPassColorAttachment {
+				view: render_view,
+				resolve_target: None,
+				ops: Operations {
+					load: LoadOp::Load,
+					store: StoreOp::Store,
+				},
+			})],
+			depth_stencil_attachment: None,
+			occlusion_query_set: None,
+			timestamp_writes: None,
+		});
+
+		render_pass.set_viewport(corner.x, corner.y, size.w, size.h, 0.0, 1.0);
+		render_pass.set_pipeline(&pip.overlay.pipeline);
+		render_pass.apply_buffer_mapping(&pip.overlay.shader.binding, *parity);
+		render_pass.draw(0..4, 0..1);
+	}
+
+	render_target.command_queue.push(encoder.finish());
+}
@@ -5,7 +5,15 @@ use bevy_ecs::{
 use brainrot::bevy::{self, App, Plugin};
 use wgpu::TextureViewDescriptor;
 
-use crate::core::{gameloop::Render, gpu::Gpu, render_target::RenderTarget};
+use crate::{
+	core::{
+		gameloop::Render,
+		gpu::Gpu,
+		render_target::RenderTarget,
+		watchdog::{Watchdog, Zone},
+	},
+	libs::buffer::ping_pong_texture::PingPongParity,
+};
 
 /*
 --------------------------------------------------------------------------------
@@ -17,11 +25,15 @@ pub struct RenderPlugin;
 
 impl Plugin for RenderPlugin {
 	fn build(&self, app: &mut App) {
+		app.init_resource::<PingPongParity>();
+
 		app.add_systems(
 			Render,
 			(
 				prepare_render_pass.in_set(PreRenderPass),
-				finish_render_pass.in_set(PostRenderPass),
+				(finish_render_pass, swap_ping_pong_textures)
+					.chain()
+					.in_set(PostRenderPass),
 			)
 				.chain()
 				.in_set(RenderPass),
@@ -66,15 +78,31 @@ fn prepare_render_pass(mut render_target: ResMut<RenderTarget<'static>>) {
 	render_target.current_view = view;
 }
 
-fn finish_render_pass(mut render_target: ResMut<RenderTarget<'static>>, gpu: Res<Gpu>) {
+pub(crate) fn finish_render_pass(
+	mut render_target: ResMut<RenderTarget<'static>>,
+	gpu: Res<Gpu>,
+	watchdog: Option<Res<Watchdog>>,
+) {
 	// trace!("Finishing render pass");
 
+	// Where drivers tend to stall, so the watchdog is told about it
+	let heartbeat = watchdog.map(|watchdog| watchdog.heartbeat());
+
 	// Submit the encoded command buffer to the queue
 	// And clear queue at the same time
-	gpu.queue.submit(render_target.command_queue.drain(..));
+	{
+		let _zone = heartbeat.as_ref().map(|heartbeat| heartbeat.zone(Zone::Submit));
+		gpu.queue.submit(render_target.command_queue.drain(..));
+	}
 
 	// Swap the draw buffers and show what we rendered to the screen
 	if let Some(output) = render_target.current_texture.take() {
+		let _zone = heartbeat.as_ref().map(|heartbeat| heartbeat.zone(Zone::Present));
 		output.present();
 	}
 }
+
+fn swap_ping_pong_textures(mut parity: ResMut<PingPongParity>) {
+	// What was written this frame is what will be read next frame
+	parity.swap();
+}
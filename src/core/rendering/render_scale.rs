@@ -0,0 +1,237 @@
+use anyhow::{bail, Result};
+use bevy_ecs::{
+	event::EventReader,
+	schedule::IntoSystemConfigs,
+	system::{Commands, Local, Res, ResMut},
+	world::World,
+};
+use brainrot::bevy::{self, App, Plugin};
+use log::{info, warn};
+
+use super::{accumulation, capture::HighQualityCapture, compute};
+use crate::core::{
+	console::is_console_closed,
+	entity_label,
+	event_processing::{EventReaderProcessor, ProcessedChangeEvents},
+	events::{KeyboardInputEvent, WindowResizedEvent},
+	gameloop::{PreRender, Update},
+	gpu::Gpu,
+	key_bindings::{Action, KeyBindings},
+	params,
+	render_target::{RenderTarget, WindowRenderTarget},
+	size::{Resolution, WindowSize},
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Keeps the [`Resolution`] of the compute renderer at the size of the window
+/// times the [`RenderScale`], so that a slow scene can be rendered at a lower
+/// resolution and upscaled by the composite (or a fast one supersampled).
+///
+/// The renderer is resized whenever the window is resized or the scale
+/// changes, through [`resize_compute_renderer`](compute::resize_compute_renderer)
+/// so that the composite follows and the accumulation starts over. The scale
+/// is stepped through [`RenderScale::STEPS`] with
+/// [`Action::RenderScaleUp`]/[`Action::RenderScaleDown`], or set to anything
+/// with the `render_scale` param.
+///
+/// Needs to be added after the window render target, and before the compute
+/// renderer which starts at [`target_resolution`].
+pub struct RenderScalePlugin {
+	pub scale: f32,
+}
+
+impl Default for RenderScalePlugin {
+	fn default() -> Self {
+		Self { scale: 1.0 }
+	}
+}
+
+impl Plugin for RenderScalePlugin {
+	fn build(&self, app: &mut App) {
+		let scale = RenderScale(self.scale);
+		let window = window_size(&mut app.world).expect("The window should have a render target");
+		app.world.insert_resource(scale);
+		app.world.insert_resource(Scaled { window, scale });
+
+		params::registry(app).register_float(
+			"render_scale",
+			"The resolution of the compute renderer, relative to the size of the window",
+			RenderScale::MIN..=RenderScale::MAX,
+			|world| Ok(world.resource::<RenderScale>().0),
+			set_render_scale,
+		);
+
+		app.add_systems(Update, step_render_scale.run_if(is_console_closed));
+		// Reset on the same frame, which is already rendered at the new resolution
+		app.add_systems(PreRender, follow_window.before(accumulation::update_accumulation));
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// The resolution of the compute renderer relative to the size of the window,
+/// e.g. 0.5 renders a quarter of the pixels
+#[derive(bevy::Resource, Copy, Clone, Debug, PartialEq)]
+pub struct RenderScale(pub f32);
+
+impl RenderScale {
+	pub const MIN: f32 = 0.25;
+	pub const MAX: f32 = 2.0;
+
+	/// The scales [`Action::RenderScaleUp`]/[`Action::RenderScaleDown`] go
+	/// through
+	pub const STEPS: [f32; 7] = [0.25, 0.5, 0.75, 1.0, 1.25, 1.5, 2.0];
+
+	/// The resolution for a window of `size`, never 0
+	pub fn resolution(self, size: WindowSize) -> Resolution {
+		Resolution(size.map(|x| ((x as f32 * self.0).round() as u32).max(1)))
+	}
+
+	/// The next step above, from wherever the scale is between them
+	pub fn stepped_up(self) -> Self {
+		let step = Self::STEPS.into_iter().find(|step| *step > self.0);
+		Self(step.unwrap_or(self.0))
+	}
+
+	/// The next step below, from wherever the scale is between them
+	pub fn stepped_down(self) -> Self {
+		let step = Self::STEPS.into_iter().rev().find(|step| *step < self.0);
+		Self(step.unwrap_or(self.0))
+	}
+}
+
+/// The resolution the compute renderer should have for the current size of the
+/// window and [`RenderScale`]
+pub fn target_resolution(world: &World) -> Resolution {
+	let Scaled { window, scale } = *world.resource::<Scaled>();
+	scale.resolution(window)
+}
+
+fn window_size(world: &mut World) -> Result<WindowSize> {
+	let window_target = entity_label::single_entity::<WindowRenderTarget>(world)?;
+	match world.get::<RenderTarget>(window_target) {
+		Some(render_target) => Ok(render_target.size),
+		None => bail!("The window doesn't have a render target"),
+	}
+}
+
+/// The window size and scale the resolution was last computed for. Only a
+/// change of either rescales, so that a renderer swapped in at another
+/// resolution (e.g. by a test) stays as it is.
+#[derive(bevy::Resource, Copy, Clone, Debug, PartialEq)]
+struct Scaled {
+	window: WindowSize,
+	scale: RenderScale,
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+fn set_render_scale(world: &mut World, scale: f32) -> Result<()> {
+	// The capture renders on its own resized copy, and swaps the old one back
+	// when it's done
+	if world
+		.get_resource::<HighQualityCapture>()
+		.is_some_and(HighQualityCapture::is_running)
+	{
+		bail!("Can't change the render scale during a capture");
+	}
+
+	world.insert_resource(RenderScale(scale));
+	let window = world.resource::<Scaled>().window;
+	rescale(world, window)
+}
+
+/// Resize the compute renderer for the `window` size and the current
+/// [`RenderScale`], if it isn't at that resolution already
+fn rescale(world: &mut World, window: WindowSize) -> Result<()> {
+	let scale = *world.resource::<RenderScale>();
+	// Even if it fails, it would fail again every frame until something changes
+	world.insert_resource(Scaled { window, scale });
+
+	// Minimized, keep the resolution for when it comes back
+	if window.w == 0 || window.h == 0 {
+		return Ok(());
+	}
+
+	let gpu = world.resource::<Gpu>();
+	let max_size = gpu.device.limits().max_texture_dimension_2d;
+	let resolution = Resolution(scale.resolution(window).map(|x| x.min(max_size)));
+	if resolution == *world.resource::<Resolution>() {
+		return Ok(());
+	}
+
+	// Usually only the textures change, which is cheap enough to do on every
+	// frame of a drag-resize
+	compute::resize_compute_renderer(world, resolution)?;
+
+	info!(
+		"Render resolution: {}x{} ({}% of the {}x{} window)",
+		resolution.w,
+		resolution.h,
+		(scale.0 * 100.0).round(),
+		window.w,
+		window.h
+	);
+
+	Ok(())
+}
+
+/// Rescales when the window is resized or the scale changes, after the capture
+/// is done if that happens during it
+fn follow_window(
+	render_scale: Res<RenderScale>,
+	scaled: Res<Scaled>,
+	capture: Option<Res<HighQualityCapture>>,
+	window_events: EventReader<WindowResizedEvent>,
+	mut latest_window: Local<Option<WindowSize>>,
+	mut commands: Commands,
+) {
+	if let Some(size) = window_events.process().latest() {
+		*latest_window = Some(size);
+	}
+
+	let window = latest_window.unwrap_or(scaled.window);
+	let target = Scaled {
+		window,
+		scale: *render_scale,
+	};
+	if target == *scaled || capture.is_some_and(|capture| capture.is_running()) {
+		return;
+	}
+
+	commands.add(move |world: &mut World| {
+		if let Err(e) = rescale(world, window) {
+			warn!("Couldn't change the render resolution: {}", e);
+		}
+	});
+}
+
+fn step_render_scale(
+	mut render_scale: ResMut<RenderScale>,
+	mut keyboard_events: EventReader<KeyboardInputEvent>,
+	key_bindings: Res<KeyBindings>,
+) {
+	let events = keyboard_events.read().collect::<Vec<_>>();
+	let pressed = |action| key_bindings.has_pressed(action, events.iter().copied());
+
+	// The resolution follows before the frame is rendered
+	if pressed(Action::RenderScaleUp) {
+		*render_scale = render_scale.stepped_up();
+	}
+	if pressed(Action::RenderScaleDown) {
+		*render_scale = render_scale.stepped_down();
+	}
+}
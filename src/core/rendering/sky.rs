@@ -0,0 +1,413 @@
+use std::f32::consts::{PI, TAU};
+
+use anyhow::{bail, ensure, Context, Result};
+use bevy_ecs::{
+	event::EventReader,
+	schedule::IntoSystemConfigs,
+	system::{Local, Query, Res, ResMut},
+	world::World,
+};
+use brainrot::{
+	bevy::{self, App, Plugin},
+	vek::{Rgb, Vec3, Vec4},
+};
+use pbr_tracer_derive::ShaderStruct;
+use wgpu::Buffer;
+
+use super::lights::{Light, Lights};
+use crate::{
+	core::{
+		console::{self, is_console_closed},
+		events::KeyboardInputEvent,
+		gameloop::{Time, Update},
+		gpu::Gpu,
+		key_bindings::{Action, HeldKeys, KeyBindings},
+		params,
+	},
+	libs::{
+		buffer::{self, uniform_buffer::UniformBuffer, ShaderType},
+		smart_arc::Sarc,
+	},
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Daylight from the position of the sun, see [`SkySettings`]. Keeps the
+/// [`SkyModel`] uniform of the
+/// [`PreethamSky`](crate::fragments::environment::PreethamSky) and the first
+/// directional light of the [`Lights`] in sync with it, so that the sky, the
+/// sun disc and the shading all agree on where the sun is and how bright it
+/// is. Changing the light directly works until the sun moves again.
+///
+/// The sun is moved with the `sky.*` params, with [`Action::SunUp`],
+/// [`Action::SunDown`], [`Action::SunLeft`] and [`Action::SunRight`], or by the
+/// day cycle of the `day_cycle` console command. Has to be added before the
+/// renderer is made, so that its environment can bind the uniform.
+#[derive(Default)]
+pub struct SkyPlugin {
+	pub settings: SkySettings,
+}
+
+impl Plugin for SkyPlugin {
+	fn build(&self, app: &mut App) {
+		app.world.init_resource::<Lights>();
+		app.world.insert_resource(self.settings);
+		app.world.insert_resource(DayCycle::default());
+
+		update_sun_light(&mut app.world.resource_mut::<Lights>(), &self.settings);
+
+		let model = SkyModel::new(&self.settings);
+		let gpu = app.world.resource::<Gpu>();
+		let model_buffer = Sarc::new(UniformBuffer::raw_buffer_from_data(gpu, &model, None));
+		buffer::spawn_buffer(app, model, model_buffer.clone());
+		app.world.insert_resource(Sky { model_buffer });
+
+		params::registry(app)
+			.register_float(
+				"sky.sun_elevation",
+				"How high the sun is above the horizon, in degrees",
+				-90.0..=90.0,
+				|world| Ok(world.resource::<SkySettings>().sun_elevation.to_degrees()),
+				|world, elevation| {
+					world.resource_mut::<SkySettings>().sun_elevation = elevation.to_radians();
+					Ok(())
+				},
+			)
+			.register_float(
+				"sky.sun_azimuth",
+				"Where the sun is around the vertical, in degrees, 0 being +z and 90 +x",
+				-360.0..=360.0,
+				|world| Ok(world.resource::<SkySettings>().sun_azimuth.to_degrees()),
+				|world, azimuth| {
+					world.resource_mut::<SkySettings>().sun_azimuth = azimuth.to_radians();
+					Ok(())
+				},
+			)
+			.register_float(
+				"sky.turbidity",
+				"How hazy the air is, 2 for a clear sky and 10 for a hazy one",
+				SkySettings::TURBIDITY_RANGE,
+				|world| Ok(world.resource::<SkySettings>().turbidity),
+				|world, turbidity| {
+					world.resource_mut::<SkySettings>().turbidity = turbidity;
+					Ok(())
+				},
+			);
+
+		console::register_command(
+			app,
+			"day_cycle",
+			"day_cycle [off | <seconds per day>]: Move the sun around the sky, or show how fast it moves",
+			day_cycle,
+		);
+
+		app.add_systems(
+			Update,
+			((move_sun.run_if(is_console_closed), advance_day_cycle), update_sky).chain(),
+		);
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// What the [`PreethamSky`](crate::fragments::environment::PreethamSky) binds
+#[derive(bevy::Resource, Clone)]
+pub struct Sky {
+	/// Holds the [`SkyModel`]
+	pub model_buffer: Sarc<Buffer>,
+}
+
+/// Where the sun is and what the air is like. The single source of truth for
+/// the sun, the [`SkyPlugin`] derives the [`SkyModel`] and the sun light from
+/// it whenever it changes.
+#[derive(bevy::Resource, Copy, Clone, Debug, PartialEq)]
+pub struct SkySettings {
+	/// Above the horizon, in radians. The sky fades to black and the sun light
+	/// goes out when it's below.
+	pub sun_elevation: f32,
+	/// Around +y, in radians, 0 being +z and pi/2 +x
+	pub sun_azimuth: f32,
+	/// How hazy the air is, see [`TURBIDITY_RANGE`](Self::TURBIDITY_RANGE)
+	pub turbidity: f32,
+	/// The luminance of the model is in kcd/m², this brings it to the scale of
+	/// the scene
+	pub sky_intensity: f32,
+	/// The intensity of the sun light before the atmosphere dims it
+	pub sun_intensity: f32,
+	/// The angular radius of the sun disc, in radians
+	pub sun_radius: f32,
+}
+
+impl Default for SkySettings {
+	/// The same sun as the default [`Lights`], about as bright
+	fn default() -> Self {
+		Self {
+			// Coming from (-1, 1, -1)
+			sun_elevation: 3_f32.sqrt().recip().asin(),
+			sun_azimuth: -135_f32.to_radians(),
+			turbidity: 3.0,
+			sky_intensity: 0.05,
+			sun_intensity: 1.3,
+			sun_radius: 0.01,
+		}
+	}
+}
+
+impl SkySettings {
+	/// What the fit of the model was made for
+	pub const TURBIDITY_RANGE: std::ops::RangeInclusive<f32> = 1.7..=10.0;
+
+	/// The unit vector towards the sun
+	pub fn sun_direction(&self) -> Vec3<f32> {
+		let (sin_elevation, cos_elevation) = self.sun_elevation.sin_cos();
+		let (sin_azimuth, cos_azimuth) = self.sun_azimuth.sin_cos();
+		Vec3::new(cos_elevation * sin_azimuth, sin_elevation, cos_elevation * cos_azimuth)
+	}
+
+	/// How much of the sun's light gets through the atmosphere, from the
+	/// Rayleigh and aerosol scattering of the appendix of Preetham et al.
+	pub fn sun_transmittance(&self) -> Rgb<f32> {
+		let zenith_angle = PI / 2.0 - self.sun_elevation;
+		if zenith_angle >= PI / 2.0 {
+			return Rgb::zero();
+		}
+
+		// Kasten and Young's fit, which stays finite at the horizon
+		let air_mass = 1.0 / (zenith_angle.cos() + 0.15 * (93.885 - zenith_angle.to_degrees()).powf(-1.253));
+
+		let beta = 0.04608 * self.turbidity - 0.04586;
+		// In micrometers
+		let transmittance = |wavelength: f32| {
+			let rayleigh = (-0.008735 * wavelength.powf(-4.08) * air_mass).exp();
+			let aerosol = (-beta * wavelength.powf(-1.3) * air_mass).exp();
+			rayleigh * aerosol
+		};
+
+		Rgb::new(transmittance(0.65), transmittance(0.57), transmittance(0.475))
+	}
+
+	/// The radiance of the sun disc, so that it gives off as much light as the
+	/// sun light does
+	pub fn sun_radiance(&self) -> Rgb<f32> {
+		let solid_angle = TAU * (1.0 - self.sun_radius.cos());
+		self.sun_transmittance() * self.sun_intensity / solid_angle
+	}
+}
+
+/// The `sky_model` uniform, the parts of "A Practical Analytic Model for
+/// Daylight" (Preetham et al. 1999) that only depend on the sun and the
+/// turbidity, so that every pixel only has to evaluate the Perez function.
+#[repr(C)]
+#[derive(ShaderStruct, bevy::Component, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+pub struct SkyModel {
+	/// The coefficients A to E of the Perez function, for the luminance Y and
+	/// the chromaticities x and y in xyz
+	pub perez: [Vec4<f32>; 5],
+	/// Y, x and y at the zenith, divided by the Perez function at the zenith
+	pub zenith: Vec3<f32>,
+	/// Multiplied with the luminance, 0 at night
+	pub brightness: f32,
+	/// Towards the sun
+	pub sun_direction: Vec3<f32>,
+	pub cos_sun_radius: f32,
+	pub sun_radiance: Rgb<f32>,
+	#[shader(skip)]
+	_padding: u32,
+}
+
+impl SkyModel {
+	pub fn new(settings: &SkySettings) -> Self {
+		let t = settings.turbidity;
+
+		// The model is made for a sun above the horizon, below it the sky fades out
+		let elevation = settings.sun_elevation.max(0.0);
+		let sun_zenith_angle = PI / 2.0 - elevation;
+
+		#[rustfmt::skip]
+		let perez = [
+			Vec4::new( 0.1787 * t - 1.4630, -0.0193 * t - 0.2592, -0.0167 * t - 0.2608, 0.0),
+			Vec4::new(-0.3554 * t + 0.4275, -0.0665 * t + 0.0008, -0.0950 * t + 0.0092, 0.0),
+			Vec4::new(-0.0227 * t + 5.3251, -0.0004 * t + 0.2125, -0.0079 * t + 0.2102, 0.0),
+			Vec4::new( 0.1206 * t - 2.5771, -0.0641 * t - 0.8989, -0.0441 * t - 1.6537, 0.0),
+			Vec4::new(-0.0670 * t + 0.3703, -0.0033 * t + 0.0452, -0.0109 * t + 0.0529, 0.0),
+		];
+
+		let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * sun_zenith_angle);
+		let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+
+		// The chromaticities are polynomials in the turbidity (the rows, from T²
+		// to 1) and the sun zenith angle (the columns, from θ³ to 1)
+		let thetas = Vec4::new(
+			sun_zenith_angle.powi(3),
+			sun_zenith_angle.powi(2),
+			sun_zenith_angle,
+			1.0,
+		);
+		let polynomial =
+			|rows: [Vec4<f32>; 3]| Vec3::new(t * t, t, 1.0).dot(Vec3::from(rows.map(|row| row.dot(thetas))));
+		#[rustfmt::skip]
+		let zenith_x = polynomial([
+			Vec4::new( 0.00166, -0.00375,  0.00209, 0.0),
+			Vec4::new(-0.02903,  0.06377, -0.03202, 0.00394),
+			Vec4::new( 0.11693, -0.21196,  0.06052, 0.25886),
+		]);
+		#[rustfmt::skip]
+		let zenith_y = polynomial([
+			Vec4::new( 0.00275, -0.00610,  0.00317, 0.0),
+			Vec4::new(-0.04214,  0.08970, -0.04153, 0.00516),
+			Vec4::new( 0.15346, -0.26756,  0.06670, 0.26688),
+		]);
+
+		// The Perez function at the zenith, which the shader divides by
+		let perez_at_zenith = |i: usize| {
+			let [a, b, c, d, e] = perez.map(|coefficients| coefficients[i]);
+			(1.0 + a * b.exp()) * (1.0 + c * (d * sun_zenith_angle).exp() + e * sun_zenith_angle.cos().powi(2))
+		};
+		let zenith = Vec3::new(
+			zenith_luminance / perez_at_zenith(0),
+			zenith_x / perez_at_zenith(1),
+			zenith_y / perez_at_zenith(2),
+		);
+
+		// Twilight, until the sun is well below the horizon
+		let twilight = ((settings.sun_elevation + 0.2) / 0.2).clamp(0.0, 1.0);
+		let night_fade = twilight * twilight * (3.0 - 2.0 * twilight);
+
+		Self {
+			perez,
+			zenith,
+			brightness: settings.sky_intensity * night_fade,
+			sun_direction: settings.sun_direction(),
+			cos_sun_radius: settings.sun_radius.cos(),
+			sun_radiance: settings.sun_radiance(),
+			_padding: 0,
+		}
+	}
+}
+
+/// Point the first directional light at the ground from the sun, with the
+/// color and intensity that make it through the atmosphere. Adds one if there
+/// is none.
+fn update_sun_light(lights: &mut Lights, settings: &SkySettings) {
+	let transmittance = settings.sun_transmittance();
+	let brightest = transmittance.reduce_partial_max();
+
+	let sun = Light::directional(
+		-settings.sun_direction(),
+		if brightest > 0.0 {
+			transmittance / brightest
+		} else {
+			Rgb::zero()
+		},
+		settings.sun_intensity * brightest,
+	);
+
+	match lights.iter_mut().find(|light| light.kind == Light::DIRECTIONAL) {
+		Some(light) => *light = sun,
+		None => lights.insert(0, sun),
+	}
+}
+
+fn update_sky(settings: Res<SkySettings>, mut lights: ResMut<Lights>, mut models: Query<&mut SkyModel>) {
+	if !settings.is_changed() {
+		return;
+	}
+
+	update_sun_light(&mut lights, &settings);
+
+	for mut model in models.iter_mut() {
+		*model = SkyModel::new(&settings);
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// How fast [`Action::SunUp`] and the others move the sun, in radians per
+/// second
+const SUN_SPEED: f32 = 0.5;
+
+fn move_sun(
+	mut settings: ResMut<SkySettings>,
+	mut keyboard_events: EventReader<KeyboardInputEvent>,
+	key_bindings: Res<KeyBindings>,
+	mut held_keys: Local<HeldKeys>,
+	time: Res<Time>,
+) {
+	held_keys.update(keyboard_events.read());
+
+	let held = |action| key_bindings.is_held(action, &held_keys) as i32 as f32;
+	let step = SUN_SPEED * time.dt_u.as_secs_f32();
+	let elevation = (held(Action::SunUp) - held(Action::SunDown)) * step;
+	let azimuth = (held(Action::SunRight) - held(Action::SunLeft)) * step;
+
+	// Only touch the settings when the sun moves, they'd count as changed
+	// otherwise
+	if elevation != 0.0 || azimuth != 0.0 {
+		settings.sun_elevation = (settings.sun_elevation + elevation).clamp(-PI / 2.0, PI / 2.0);
+		settings.sun_azimuth = (settings.sun_azimuth + azimuth).rem_euclid(TAU);
+	}
+}
+
+/// Moves the sun along a simple arc: it rises in the +x, goes up to
+/// [`MAX_ELEVATION`](Self::MAX_ELEVATION) in the -z and sets in the -x, then
+/// spends the other half of the day below the horizon.
+#[derive(bevy::Resource, Copy, Clone, Debug, Default, PartialEq)]
+pub struct DayCycle {
+	/// How long a whole day takes, it doesn't move without one
+	pub seconds_per_day: Option<f32>,
+	/// From 0 (sunrise) to 1
+	pub time_of_day: f32,
+}
+
+impl DayCycle {
+	pub const MAX_ELEVATION: f32 = 1.2;
+
+	/// The elevation and azimuth of the sun at the current time of day
+	pub fn sun_position(&self) -> (f32, f32) {
+		let angle = self.time_of_day * TAU;
+		(Self::MAX_ELEVATION * angle.sin(), (PI / 2.0 + angle).rem_euclid(TAU))
+	}
+}
+
+fn advance_day_cycle(mut day_cycle: ResMut<DayCycle>, mut settings: ResMut<SkySettings>, time: Res<Time>) {
+	let Some(seconds_per_day) = day_cycle.seconds_per_day else {
+		return;
+	};
+
+	day_cycle.time_of_day = (day_cycle.time_of_day + time.dt_u.as_secs_f32() / seconds_per_day).fract();
+	(settings.sun_elevation, settings.sun_azimuth) = day_cycle.sun_position();
+}
+
+fn day_cycle(world: &mut World, args: &[String]) -> Result<String> {
+	let mut day_cycle = world.resource_mut::<DayCycle>();
+
+	match args {
+		[] => Ok(match day_cycle.seconds_per_day {
+			Some(seconds) => format!("A day takes {}s", seconds),
+			None => "The sun stands still".to_owned(),
+		}),
+		[off] if off == "off" => {
+			day_cycle.seconds_per_day = None;
+			Ok("The sun stands still".to_owned())
+		}
+		[seconds] => {
+			let seconds = seconds.parse::<f32>().context("Expected a number of seconds")?;
+			ensure!(seconds > 0.0, "A day has to take some time");
+			day_cycle.seconds_per_day = Some(seconds);
+			Ok(format!("A day now takes {}s", seconds))
+		}
+		_ => bail!("Usage: day_cycle [off | <seconds per day>]"),
+	}
+}
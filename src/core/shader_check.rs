@@ -0,0 +1,306 @@
+use std::{collections::BTreeSet, fmt};
+
+use bevy_ecs::world::World;
+use brainrot::{
+	bevy::{App, Plugin},
+	size,
+	vek::Vec3,
+};
+use log::{info, warn};
+use typed_path::{Utf8UnixPath, Utf8UnixPathBuf};
+
+use crate::{
+	core::{console, gpu::Gpu, size::Resolution},
+	fragments::{
+		ambient_occlusion::AmbientOcclusion,
+		environment::ProceduralSky,
+		animated_noise::AnimatedNoise,
+		instrumentation::GpuAsserts,
+		intersector::{AnalyticIntersector, Raymarcher},
+		light_grid::LightGrid,
+		mesh::{Mesh, MeshIntersector},
+		mpr::{DebugRenderer, MultiPurposeRenderer, PingPongDebugRenderer},
+		path_tracer::PathTracer,
+		post_processing::{
+			Bloom, ChromaticAberration, Dither, Fxaa, GammaCorrection, PostProcessingPipeline, ToneMapping, Vignette,
+		},
+		reference_grid::ReferenceGrid,
+		sampling::BlueNoise,
+		sdf::{SdfNode, SdfScene},
+		shading::{CelShading, MaterialLibrary, PbrShading, SimpleDiffuse},
+		voxel::VoxelIntersector,
+	},
+	libs::{
+		embed::Assets,
+		shader::{self, Shader, ShaderBuilder},
+		shader_fragment::ShaderFragment,
+	},
+	ShaderAssets,
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Embedded file listing the shader files that are intentionally not included
+/// by anything, one path per line. A path ending with `/` allows a whole
+/// directory, `#` starts a comment.
+const ALLOWLIST_PATH: &str = "/unreferenced.txt";
+
+/// Reports the shader files that no pipeline includes and the `#include`
+/// directives that point to files that don't exist.
+///
+/// The included files are the union of those of every renderer/effect
+/// combination from [`all_fragments`], which also has the entry points of the
+/// pipelines of the rendering plugins. That way the files only used by a
+/// renderer that isn't active in this run aren't reported.
+///
+/// The check runs at startup in debug builds, and can be rerun anytime with
+/// the `shader_check` console command.
+pub struct ShaderCheckPlugin;
+
+impl Plugin for ShaderCheckPlugin {
+	fn build(&self, app: &mut App) {
+		console::register_command(
+			app,
+			"shader_check",
+			"Report the orphaned shader files and the missing include targets",
+			|world, _args| Ok(run_check(world).to_string()),
+		);
+
+		if cfg!(debug_assertions) {
+			let report = run_check(&app.world);
+
+			if report.is_clean() {
+				info!("{}", report);
+			} else {
+				warn!("{}", report);
+			}
+		}
+	}
+}
+
+/// All the renderers and effects that the shader check should cover.
+/// Any new fragment should be added here, otherwise its files are reported as
+/// orphans.
+fn all_fragments() -> Vec<Shader> {
+	vec![
+		// The pipelines of the rendering plugins need their textures and buffers
+		ShaderBuilder::new().include_path("compute.wgsl").into(),
+		ShaderBuilder::new().include_path("composite.wgsl").into(),
+		ShaderBuilder::new().include_path("composite/default.wgsl").into(),
+		ShaderBuilder::new().include_path("composite/easu.wgsl").into(),
+		ShaderBuilder::new().include_path("composite/rcas.wgsl").into(),
+		ShaderBuilder::new().include_path("composite/histogram.wgsl").into(),
+		ShaderBuilder::new().include_path("composite/picture_in_picture.wgsl").into(),
+		ShaderBuilder::new().include_path("histogram/histogram.wgsl").into(),
+		ShaderBuilder::new().include_path("denoise/atrous.wgsl").into(),
+		GpuAsserts::disabled().shader(),
+		// The enabled asserts need the log buffer, the file is enough for the check
+		ShaderBuilder::new()
+			.include_path("instrumentation/gpu_assert.wgsl")
+			.into(),
+		// The downsample pass needs the textures of a running capture
+		ShaderBuilder::new().include_path("capture/downsample.wgsl").into(),
+		// The wavefront renderer needs the GPU to make its queues
+		ShaderBuilder::new().include_path("wavefront/wavefront.wgsl").into(),
+		// The prefiltering passes and the environment lighting need the textures
+		// of an environment
+		ShaderBuilder::new().include_path("environment/prefilter.wgsl").into(),
+		ShaderBuilder::new().include_path("environment/irradiance.wgsl").into(),
+		ShaderBuilder::new().include_path("shading/pbr_environment.wgsl").into(),
+		// The color grading needs the LUT of the plugin
+		ShaderBuilder::new().include_path("post_processing/color_grade.wgsl").into(),
+		// The HDRI needs an image
+		ShaderBuilder::new().include_path("environment/hdri.wgsl").into(),
+		ShaderBuilder::new().include_path("environment/preetham_sky.wgsl").into(),
+		// The reprojected history needs the buffers of the accumulation
+		ShaderBuilder::new().include_path("path_tracer_history.wgsl").into(),
+		DebugRenderer.shader(),
+		PingPongDebugRenderer {
+			resolution: Resolution(size!(1, 1)),
+			blend_factor: 0.0,
+		}
+		.shader(),
+		MultiPurposeRenderer {
+			intersector: Raymarcher::default(),
+			shading: SimpleDiffuse::default(),
+			environment: ProceduralSky::default(),
+			post_processing: PostProcessingPipeline::empty()
+				.with(ChromaticAberration::default())
+				.with(Bloom::default())
+				.with(ToneMapping::default())
+				.with(Vignette::default())
+				.with(Fxaa::default())
+				.with(GammaCorrection)
+				.with(Dither),
+			reference_grid: None,
+		}
+		.shader(),
+		PostProcessingPipeline::empty()
+			.with(GammaCorrection)
+			.with(Dither)
+			.legacy_chaining()
+			.shader(),
+		// With the step heatmap, for the debug output
+		MultiPurposeRenderer {
+			intersector: Raymarcher {
+				step_heatmap: true,
+				..Default::default()
+			},
+			shading: CelShading,
+			environment: ProceduralSky::default(),
+			post_processing: PostProcessingPipeline::empty(),
+			reference_grid: Some(ReferenceGrid::default()),
+		}
+		.shader(),
+		LightGrid {
+			bounds_min: Vec3::zero(),
+			bounds_max: Vec3::one(),
+			cells: Vec3::one(),
+			lights: vec![],
+		}
+		.shader(),
+		AnimatedNoise::default().shader(),
+		BlueNoise.shader(),
+		AnalyticIntersector::default().shader(),
+		AnalyticIntersector::from_sdf_scene(&SdfScene::new(SdfNode::torus(1.0, 0.25)), true).shader(),
+		// The analytic intersector has it off by default
+		AmbientOcclusion::ray_cast(1, 1.0, 1.0).shader(),
+		MeshIntersector::new(&Mesh::default()).shader(),
+		VoxelIntersector::default().shader(),
+		PbrShading::default().shader(),
+		PathTracer::new(
+			AnalyticIntersector::default(),
+			ProceduralSky::default(),
+			&MaterialLibrary::default(),
+		)
+		.shader(),
+	]
+}
+
+fn run_check(world: &World) -> ShaderCheck {
+	let gpu = world.resource::<Gpu>();
+
+	// Building the sources is enough to get the includes, no need to compile
+	// anything. Any build error is most likely a missing include, which is
+	// reported by the static scan below anyway.
+	let mut included = BTreeSet::new();
+	let mut build_errors = Vec::new();
+	for fragment in all_fragments() {
+		match ShaderBuilder::new().include(fragment).build_source(gpu, &ShaderAssets) {
+			Ok(source) => included.extend(source.included_paths),
+			Err(error) => build_errors.push(format!("{:#}", error)),
+		}
+	}
+
+	let mut report = ShaderCheck::new(&ShaderAssets, &included);
+	report.build_errors = build_errors;
+	report
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+#[derive(Debug, Default)]
+pub struct ShaderCheck {
+	/// Embedded shader files that were never included
+	pub orphans: Vec<String>,
+	/// `(file, include target)` pairs where the target doesn't exist
+	pub missing_includes: Vec<(String, String)>,
+	pub build_errors: Vec<String>,
+}
+
+impl ShaderCheck {
+	pub fn new(shader_map: &dyn Assets, included: &BTreeSet<String>) -> Self {
+		let allowlist = Self::read_allowlist(shader_map);
+		let paths = shader_map.iter_paths("wgsl");
+
+		let orphans = paths
+			.iter()
+			.filter(|path| !included.contains(*path))
+			.filter(|path| {
+				!allowlist.iter().any(|allowed| {
+					*path == allowed || (allowed.ends_with('/') && path.starts_with(allowed.as_str()))
+				})
+			})
+			.cloned()
+			.collect();
+
+		let missing_includes = paths
+			.iter()
+			.flat_map(|path| Self::find_missing_includes(shader_map, path))
+			.collect();
+
+		Self {
+			orphans,
+			missing_includes,
+			build_errors: Vec::new(),
+		}
+	}
+
+	pub fn is_clean(&self) -> bool {
+		self.orphans.is_empty() && self.missing_includes.is_empty() && self.build_errors.is_empty()
+	}
+
+	fn read_allowlist(shader_map: &dyn Assets) -> Vec<String> {
+		let Some(file) = shader_map.get(ALLOWLIST_PATH) else {
+			return Vec::new();
+		};
+
+		String::from_utf8_lossy(&file.data)
+			.lines()
+			.map(|line| line.split('#').next().unwrap().trim())
+			.filter(|line| !line.is_empty())
+			.map(|line| format!("/{}", line.trim_start_matches('/')))
+			.collect()
+	}
+
+	/// Statically scan a file for include directives whose target isn't
+	/// embedded, without needing to build any pipeline
+	fn find_missing_includes(shader_map: &dyn Assets, path: &str) -> Vec<(String, String)> {
+		let Some(file) = shader_map.get(path) else {
+			return Vec::new();
+		};
+		let source = String::from_utf8_lossy(&file.data);
+		let parent_path = Utf8UnixPath::new(path)
+			.parent()
+			.map(|x| x.to_owned())
+			.unwrap_or(Utf8UnixPathBuf::from("/"));
+
+		shader::find_include_directives(&source)
+			.into_iter()
+			.filter(|(path_str, _)| {
+				shader::resolve_include_path(&parent_path, path_str)
+					.map(|target| shader_map.get(target.as_str()).is_none())
+					.unwrap_or(true)
+			})
+			.map(|(path_str, _)| (path.to_owned(), path_str))
+			.collect()
+	}
+}
+
+impl fmt::Display for ShaderCheck {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if self.is_clean() {
+			return write!(f, "Shader check: no orphaned files or missing includes");
+		}
+
+		writeln!(f, "Shader check found issues:")?;
+		for path in &self.orphans {
+			writeln!(f, "  orphaned file: {}", path)?;
+		}
+		for (path, target) in &self.missing_includes {
+			writeln!(f, "  missing include: `{}` in {}", target, path)?;
+		}
+		for error in &self.build_errors {
+			writeln!(f, "  build error: {}", error)?;
+		}
+		write!(f, "Add intentionally unreferenced files to src/shader{}", ALLOWLIST_PATH)
+	}
+}
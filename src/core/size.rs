@@ -0,0 +1,62 @@
+use brainrot::{bevy, vek::Extent2};
+use derive_more::{Deref, From};
+
+use crate::libs::buffer::ShaderType;
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+// Both sizes wrap the same `Extent2<u32>` on purpose: they are interchangeable
+// data-wise but not semantically, so they should never silently be mixed up.
+// Convert through `Extent2<u32>` explicitly if that's really what's wanted.
+
+/// The size of the inner area of the window, in physical pixels.
+#[repr(transparent)]
+#[derive(bevy::Resource, bytemuck::Pod, bytemuck::Zeroable, Deref, From, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct WindowSize(pub Extent2<u32>);
+
+/// The size of the textures the compute renderer renders into, in pixels.
+/// Independent from the [`WindowSize`], the composite pass takes care of
+/// fitting one into the other.
+#[repr(transparent)]
+#[derive(bevy::Resource, bytemuck::Pod, bytemuck::Zeroable, Deref, From, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Resolution(pub Extent2<u32>);
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+impl From<WindowSize> for Extent2<u32> {
+	fn from(value: WindowSize) -> Self {
+		value.0
+	}
+}
+
+impl From<Resolution> for Extent2<u32> {
+	fn from(value: Resolution) -> Self {
+		value.0
+	}
+}
+
+impl ShaderType for WindowSize {
+	const WGSL_ALIGN: usize = <Extent2<u32>>::WGSL_ALIGN;
+	const WGSL_SIZE: usize = <Extent2<u32>>::WGSL_SIZE;
+
+	fn type_name() -> String {
+		<Extent2<u32>>::type_name()
+	}
+}
+
+impl ShaderType for Resolution {
+	const WGSL_ALIGN: usize = <Extent2<u32>>::WGSL_ALIGN;
+	const WGSL_SIZE: usize = <Extent2<u32>>::WGSL_SIZE;
+
+	fn type_name() -> String {
+		<Extent2<u32>>::type_name()
+	}
+}
@@ -0,0 +1,400 @@
+use std::{
+	fmt::Write as _,
+	fs,
+	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering},
+		Arc,
+	},
+	thread::{self, JoinHandle},
+	time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use bevy_ecs::system::{Res, ResMut};
+use brainrot::bevy::{self, App, Plugin};
+use log::{error, info, warn};
+
+use super::{
+	gameloop::{PreRender, Shutdown},
+	gpu::Gpu,
+	rendering::capture::HighQualityCapture,
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Reports when the render loop stops going around, e.g. because the driver
+/// stalls in a submit or a system never returns, see [`Watchdog`].
+///
+/// The watchdog only starts with the app's own runner, so the automated runs
+/// (`gameloop::run_frames`, the C API) that drive the loop at their own pace
+/// aren't watched. It also looks away during a
+/// [`HighQualityCapture`], whose frames are expected to be long, and while
+/// the window is hidden, since nothing gets drawn then.
+pub struct WatchdogPlugin {
+	pub settings: WatchdogSettings,
+}
+
+impl Default for WatchdogPlugin {
+	fn default() -> Self {
+		Self {
+			settings: WatchdogSettings {
+				timeout: Duration::from_secs(10),
+				abort_on_stall: false,
+				diagnostics_dir: Some(PathBuf::from(".")),
+			},
+		}
+	}
+}
+
+impl Plugin for WatchdogPlugin {
+	fn build(&self, app: &mut App) {
+		let gpu_info = app
+			.world
+			.get_resource::<Gpu>()
+			.map(|gpu| format!("{:?}", gpu.adapter.get_info()));
+
+		app.world
+			.insert_resource(Watchdog::new(self.settings.clone(), gpu_info));
+
+		app.add_systems(PreRender, look_away_during_captures);
+		app.add_systems(Shutdown, stop_watchdog);
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WatchdogSettings {
+	/// How long an iteration of the loop can take before it counts as a stall
+	pub timeout: Duration,
+	/// Abort the process once a stall is reported, so that whatever supervises
+	/// it can restart it
+	pub abort_on_stall: bool,
+	/// Where the diagnostics of a stall are written, as
+	/// `watchdog_<timestamp>.txt`. They are only logged without one.
+	pub diagnostics_dir: Option<PathBuf>,
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// What the loop is busy with, as published into the [`Heartbeat`]
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Zone {
+	/// Between two iterations, i.e. in winit
+	Idle,
+	EventsCore,
+	IterStep,
+	Update,
+	PreRender,
+	Render,
+	/// Submitting the frame's commands to the GPU, inside [`Render`](Zone::Render)
+	Submit,
+	/// Presenting the frame, inside [`Render`](Zone::Render)
+	Present,
+	Shutdown,
+}
+
+impl Zone {
+	const ALL: [Self; 9] = [
+		Self::Idle,
+		Self::EventsCore,
+		Self::IterStep,
+		Self::Update,
+		Self::PreRender,
+		Self::Render,
+		Self::Submit,
+		Self::Present,
+		Self::Shutdown,
+	];
+
+	fn from_u8(zone: u8) -> Self {
+		Self::ALL.get(zone as usize).copied().unwrap_or(Self::Idle)
+	}
+}
+
+/// What the loop shares with the watchdog thread. Only atomics, so that
+/// publishing is cheap enough to do around every schedule.
+pub struct Heartbeat {
+	start: Instant,
+	/// In milliseconds since `start`
+	last_ping: AtomicU64,
+	iterations: AtomicU64,
+	current_zone: AtomicU8,
+	last_completed_zone: AtomicU8,
+	/// During captures
+	expecting_long_frames: AtomicBool,
+	/// While the window is hidden, when winit doesn't ask for redraws
+	window_hidden: AtomicBool,
+	stopped: AtomicBool,
+}
+
+impl Heartbeat {
+	fn new() -> Self {
+		Self {
+			start: Instant::now(),
+			last_ping: AtomicU64::new(0),
+			iterations: AtomicU64::new(0),
+			current_zone: AtomicU8::new(Zone::Idle as u8),
+			last_completed_zone: AtomicU8::new(Zone::Idle as u8),
+			expecting_long_frames: AtomicBool::new(false),
+			window_hidden: AtomicBool::new(false),
+			stopped: AtomicBool::new(false),
+		}
+	}
+
+	/// Called at the start of every iteration of the loop
+	pub fn ping(&self) {
+		self.last_ping
+			.store(self.start.elapsed().as_millis() as u64, Ordering::Relaxed);
+		self.iterations.fetch_add(1, Ordering::Relaxed);
+	}
+
+	/// Publish that the loop is in `zone` until the guard is dropped, after
+	/// which it's back in the zone it was in before
+	pub fn zone(&self, zone: Zone) -> ZoneGuard<'_> {
+		let outer = self.current_zone.swap(zone as u8, Ordering::Relaxed);
+		ZoneGuard {
+			heartbeat: self,
+			zone,
+			outer,
+		}
+	}
+
+	pub fn current_zone(&self) -> Zone {
+		Zone::from_u8(self.current_zone.load(Ordering::Relaxed))
+	}
+
+	pub fn last_completed_zone(&self) -> Zone {
+		Zone::from_u8(self.last_completed_zone.load(Ordering::Relaxed))
+	}
+
+	pub fn iterations(&self) -> u64 {
+		self.iterations.load(Ordering::Relaxed)
+	}
+
+	pub fn set_expecting_long_frames(&self, expecting: bool) {
+		self.expecting_long_frames.store(expecting, Ordering::Relaxed);
+	}
+
+	pub fn set_window_hidden(&self, hidden: bool) {
+		// The last ping is from before it was hidden
+		if !hidden {
+			self.ping();
+		}
+		self.window_hidden.store(hidden, Ordering::Relaxed);
+	}
+
+	fn since_last_ping(&self) -> Duration {
+		self.start
+			.elapsed()
+			.saturating_sub(Duration::from_millis(self.last_ping.load(Ordering::Relaxed)))
+	}
+
+	fn is_looking_away(&self) -> bool {
+		self.expecting_long_frames.load(Ordering::Relaxed) || self.window_hidden.load(Ordering::Relaxed)
+	}
+}
+
+pub struct ZoneGuard<'a> {
+	heartbeat: &'a Heartbeat,
+	zone: Zone,
+	outer: u8,
+}
+
+impl Drop for ZoneGuard<'_> {
+	fn drop(&mut self) {
+		self.heartbeat
+			.last_completed_zone
+			.store(self.zone as u8, Ordering::Relaxed);
+		self.heartbeat.current_zone.store(self.outer, Ordering::Relaxed);
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// A thread that checks that the [`Heartbeat`] keeps being pinged. When it
+/// isn't for longer than the timeout, it logs where the loop is stuck, writes
+/// the diagnostics and aborts if it's told to. A stall is only reported once,
+/// until the loop starts going again.
+///
+/// The thread is stopped by [`stop`](Self::stop), on [`Shutdown`] or when the
+/// resource is dropped.
+#[derive(bevy::Resource)]
+pub struct Watchdog {
+	heartbeat: Arc<Heartbeat>,
+	settings: WatchdogSettings,
+	/// The adapter the app renders with, for the diagnostics
+	gpu_info: Option<String>,
+	thread: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+	pub fn new(settings: WatchdogSettings, gpu_info: Option<String>) -> Self {
+		Self {
+			heartbeat: Arc::new(Heartbeat::new()),
+			settings,
+			gpu_info,
+			thread: None,
+		}
+	}
+
+	pub fn heartbeat(&self) -> Arc<Heartbeat> {
+		self.heartbeat.clone()
+	}
+
+	pub fn is_running(&self) -> bool {
+		self.thread.is_some()
+	}
+
+	/// Start the thread, if it isn't already running
+	pub fn start(&mut self) {
+		if self.thread.is_some() {
+			return;
+		}
+
+		self.heartbeat.stopped.store(false, Ordering::Relaxed);
+		self.heartbeat.ping();
+
+		let heartbeat = self.heartbeat.clone();
+		let settings = self.settings.clone();
+		let gpu_info = self.gpu_info.clone();
+
+		let thread = thread::Builder::new()
+			.name("watchdog".to_owned())
+			.spawn(move || watch(&heartbeat, &settings, gpu_info.as_deref()));
+
+		match thread {
+			Ok(thread) => {
+				info!(
+					"Watching the render loop, stalls of more than {:.1}s get reported",
+					self.settings.timeout.as_secs_f32()
+				);
+				self.thread = Some(thread);
+			}
+			Err(e) => error!("Couldn't start the watchdog: {}", e),
+		}
+	}
+
+	/// Stop the thread and wait for it to finish
+	pub fn stop(&mut self) {
+		let Some(thread) = self.thread.take() else {
+			return;
+		};
+
+		self.heartbeat.stopped.store(true, Ordering::Relaxed);
+		thread.thread().unpark();
+		let _ = thread.join();
+	}
+}
+
+impl Drop for Watchdog {
+	fn drop(&mut self) {
+		self.stop();
+	}
+}
+
+fn watch(heartbeat: &Heartbeat, settings: &WatchdogSettings, gpu_info: Option<&str>) {
+	// Often enough to notice a stall not long after the timeout
+	let interval = (settings.timeout / 4).clamp(Duration::from_millis(10), Duration::from_secs(1));
+	let mut reported_iteration = None;
+
+	loop {
+		thread::park_timeout(interval);
+
+		if heartbeat.stopped.load(Ordering::Relaxed) {
+			break;
+		}
+
+		let stalled_for = heartbeat.since_last_ping();
+		let iteration = heartbeat.iterations();
+
+		if stalled_for < settings.timeout || heartbeat.is_looking_away() || reported_iteration == Some(iteration) {
+			continue;
+		}
+		reported_iteration = Some(iteration);
+
+		report_stall(heartbeat, settings, gpu_info, stalled_for);
+	}
+}
+
+fn report_stall(heartbeat: &Heartbeat, settings: &WatchdogSettings, gpu_info: Option<&str>, stalled_for: Duration) {
+	warn!(
+		"The render loop hasn't gone around in {:.1}s, it's stuck in {:?} (the last zone to finish was {:?})",
+		stalled_for.as_secs_f32(),
+		heartbeat.current_zone(),
+		heartbeat.last_completed_zone()
+	);
+
+	if let Some(dir) = &settings.diagnostics_dir {
+		match write_diagnostics(dir, heartbeat, settings, gpu_info, stalled_for) {
+			Ok(path) => warn!("Wrote the diagnostics of the stall to `{}`", path.display()),
+			Err(e) => error!("Couldn't write the diagnostics of the stall: {:#}", e),
+		}
+	}
+
+	if settings.abort_on_stall {
+		error!("Aborting because of the stall");
+		log::logger().flush();
+		std::process::abort();
+	}
+}
+
+/// Write what's known about the stall to `watchdog_<timestamp>.txt` in `dir`,
+/// returns the path of the file
+pub fn write_diagnostics(
+	dir: &Path,
+	heartbeat: &Heartbeat,
+	settings: &WatchdogSettings,
+	gpu_info: Option<&str>,
+	stalled_for: Duration,
+) -> Result<PathBuf> {
+	let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+	let mut report = String::new();
+	writeln!(report, "pbr_tracer {} watchdog report", env!("CARGO_PKG_VERSION"))?;
+	writeln!(report, "time: {}", timestamp)?;
+	writeln!(
+		report,
+		"stalled for: {:.1}s (timeout {:.1}s)",
+		stalled_for.as_secs_f32(),
+		settings.timeout.as_secs_f32()
+	)?;
+	writeln!(report, "stuck in: {:?}", heartbeat.current_zone())?;
+	writeln!(report, "last zone to finish: {:?}", heartbeat.last_completed_zone())?;
+	writeln!(report, "iterations: {}", heartbeat.iterations())?;
+	writeln!(report, "uptime: {:.1}s", heartbeat.start.elapsed().as_secs_f32())?;
+	writeln!(report, "gpu: {}", gpu_info.unwrap_or("unknown"))?;
+
+	fs::create_dir_all(dir).with_context(|| format!("Couldn't create `{}`", dir.display()))?;
+	let path = dir.join(format!("watchdog_{}.txt", timestamp));
+	fs::write(&path, report).with_context(|| format!("Couldn't write `{}`", path.display()))?;
+
+	Ok(path)
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+fn look_away_during_captures(watchdog: Res<Watchdog>, capture: Option<Res<HighQualityCapture>>) {
+	let capturing = capture.is_some_and(|capture| capture.is_running());
+	watchdog.heartbeat.set_expecting_long_frames(capturing);
+}
+
+fn stop_watchdog(mut watchdog: ResMut<Watchdog>) {
+	watchdog.stop();
+}
@@ -0,0 +1,221 @@
+use std::{
+	path::{Path, PathBuf},
+	time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use bevy_ecs::{
+	event::EventReader,
+	system::{Res, ResMut},
+};
+use brainrot::bevy;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use winit::{
+	dpi::{PhysicalPosition, PhysicalSize},
+	event::WindowEvent,
+	monitor::MonitorHandle,
+	window::{Fullscreen, Window},
+};
+
+use super::{display::AppWindow, events::WinitWindowEvent, persistence};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Where and how big the window was, so that it can reopen the same way (see
+/// [`DisplayPlugin::placement_path`](super::display::DisplayPlugin)).
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct WindowPlacement {
+	/// The outer position, in physical pixels
+	pub position: [i32; 2],
+	/// The inner size, in physical pixels
+	pub size: [u32; 2],
+	pub maximized: bool,
+	pub fullscreen: bool,
+	/// The name of the monitor the window was on, if the OS gives one
+	pub monitor: Option<String>,
+}
+
+impl WindowPlacement {
+	pub const FILE_NAME: &'static str = "window_placement.ron";
+
+	/// How much of the window has to be on a monitor on both axes, so that it
+	/// can still be grabbed and moved
+	const MIN_VISIBLE: i32 = 64;
+
+	pub fn default_path() -> PathBuf {
+		std::env::current_exe()
+			.map(|exe| exe.with_file_name(Self::FILE_NAME))
+			.unwrap_or_else(|_| PathBuf::from(Self::FILE_NAME))
+	}
+
+	/// A missing file is the same as no placement, a broken one falls back to
+	/// its backup (see [`persistence::load_with_backup`])
+	pub fn load(path: &Path) -> Result<Option<Self>> {
+		persistence::load_with_backup(path, |text| Ok(ron::from_str(text)?))
+	}
+
+	/// Same as the camera poses, see [`persistence::safe_write`]
+	pub fn save(&self, path: &Path) -> Result<()> {
+		let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+		persistence::safe_write(path, text.as_bytes())
+	}
+
+	pub fn of(window: &Window) -> Self {
+		let size = window.inner_size();
+
+		let mut placement = Self {
+			position: [0, 0],
+			size: [size.width, size.height],
+			maximized: false,
+			fullscreen: false,
+			monitor: None,
+		};
+		placement.update_from(window);
+		placement
+	}
+
+	/// Returns whether anything changed
+	pub fn update_from(&mut self, window: &Window) -> bool {
+		let old = self.clone();
+
+		self.maximized = window.is_maximized();
+		self.fullscreen = window.fullscreen().is_some();
+		self.monitor = window.current_monitor().and_then(|monitor| monitor.name());
+
+		// A maximized or fullscreen window covers the monitor, what should be kept
+		// is the rect it goes back to
+		if !self.maximized && !self.fullscreen {
+			// Not available on every platform (e.g. wayland), then the old one is as good
+			// as any
+			if let Ok(position) = window.outer_position() {
+				self.position = [position.x, position.y];
+			}
+
+			let size = window.inner_size();
+			self.size = [size.width, size.height];
+		}
+
+		*self != old
+	}
+
+	/// Put the window back where it was. Does nothing and returns false if the
+	/// monitor it was on is gone or the window wouldn't be visible anymore.
+	pub fn restore(&self, window: &Window) -> bool {
+		let monitors = window.available_monitors().collect::<Vec<_>>();
+
+		let monitor = match &self.monitor {
+			Some(name) => match monitors.iter().find(|monitor| monitor.name().as_ref() == Some(name)) {
+				Some(monitor) => Some(monitor.clone()),
+				None => {
+					info!("The monitor `{}` isn't connected anymore, centering the window", name);
+					return false;
+				}
+			},
+			None => None,
+		};
+
+		if !monitors.iter().any(|monitor| self.is_visible_on(monitor)) {
+			info!("The saved window placement is off screen, centering the window");
+			return false;
+		}
+
+		let _ = window.request_inner_size(PhysicalSize::new(self.size[0], self.size[1]));
+		window.set_outer_position(PhysicalPosition::new(self.position[0], self.position[1]));
+
+		if self.maximized {
+			window.set_maximized(true);
+		}
+		if self.fullscreen {
+			window.set_fullscreen(Some(Fullscreen::Borderless(monitor)));
+		}
+
+		true
+	}
+
+	fn is_visible_on(&self, monitor: &MonitorHandle) -> bool {
+		let (x, y) = (self.position[0], self.position[1]);
+		let (w, h) = (self.size[0] as i32, self.size[1] as i32);
+
+		let monitor_position = monitor.position();
+		let monitor_size = monitor.size();
+		let (mx, my) = (monitor_position.x, monitor_position.y);
+		let (mw, mh) = (monitor_size.width as i32, monitor_size.height as i32);
+
+		let overlap_x = (x + w).min(mx + mw) - x.max(mx);
+		let overlap_y = (y + h).min(my + mh) - y.max(my);
+
+		overlap_x >= Self::MIN_VISIBLE && overlap_y >= Self::MIN_VISIBLE
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// The placement that will be saved, kept up to date while the window is moved
+/// and resized. It is only written once it stopped changing for a bit, so that
+/// dragging the window around doesn't write the file on every event.
+#[derive(bevy::Resource, Clone, Debug)]
+pub struct PendingWindowPlacement {
+	path: PathBuf,
+	placement: WindowPlacement,
+	changed_at: Option<Instant>,
+}
+
+impl PendingWindowPlacement {
+	const DEBOUNCE: Duration = Duration::from_secs(1);
+
+	pub fn new(path: PathBuf, placement: WindowPlacement) -> Self {
+		Self {
+			path,
+			placement,
+			changed_at: None,
+		}
+	}
+
+	pub fn placement(&self) -> &WindowPlacement {
+		&self.placement
+	}
+
+	fn save(&mut self) {
+		self.changed_at = None;
+
+		if let Err(error) = self.placement.save(&self.path) {
+			warn!("Couldn't save the window placement: {:#}", error);
+		}
+	}
+}
+
+pub fn track_window_placement(
+	mut pending: ResMut<PendingWindowPlacement>,
+	app_window: Res<AppWindow>,
+	mut winit_events: EventReader<WinitWindowEvent>,
+) {
+	let moved = winit_events
+		.read()
+		.any(|WinitWindowEvent(event)| matches!(event, WindowEvent::Moved(..) | WindowEvent::Resized(..)));
+
+	if moved && pending.placement.update_from(&app_window.winit_window) {
+		pending.changed_at = Some(Instant::now());
+	}
+
+	if pending
+		.changed_at
+		.is_some_and(|changed_at| changed_at.elapsed() >= PendingWindowPlacement::DEBOUNCE)
+	{
+		pending.save();
+	}
+}
+
+pub fn save_window_placement(mut pending: ResMut<PendingWindowPlacement>, app_window: Res<AppWindow>) {
+	let pending = &mut *pending;
+	pending.placement.update_from(&app_window.winit_window);
+	pending.save();
+}
@@ -0,0 +1,427 @@
+//! A small C API to render from other languages, e.g. to generate datasets
+//! from a Python script. See cbindgen.toml for the header.
+//!
+//! Every call is blocking and goes through a [`PbrContext`] handle, which owns
+//! a whole app with its own GPU and world, rendering in a hidden window. winit
+//! only allows a single event loop per process, so there can only ever be one
+//! context, and it has to be used from the thread that created it.
+//!
+//! Functions return a [`PbrStatus`]. The message of the last error on the
+//! calling thread is given by [`pbr_last_error_message`].
+
+use std::{
+	cell::RefCell,
+	ffi::{c_char, CStr, CString},
+	panic::{self, AssertUnwindSafe},
+	ptr,
+	sync::atomic::{AtomicBool, Ordering},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use bevy_ecs::{query::With, world::Mut};
+use brainrot::{bevy::App, size, vek::Rgba, Direction, Frustum, Position};
+use serde::Deserialize;
+use wgpu::TextureFormat;
+
+use crate::{
+	core::{
+		camera::{ActiveCamera, CameraController, MovementSmoothing},
+		camera_poses::CameraPose,
+		display::DisplayPlugin,
+		gameloop::{self, Time},
+		gpu::Gpu,
+		rendering::{
+			compute::{self, ComputeRenderer},
+			dynamic_quality::DynamicQuality,
+			globals::{RenderSettings, RenderSettingsOverrides, SceneSettings},
+		},
+		size::Resolution,
+	},
+	fragments::intersector::{AnalyticIntersector, Primitive, ScenePrimitives},
+	libs::buffer::storage_buffer::{DirtyRanges, StorageArray},
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PbrStatus {
+	Ok = 0,
+	/// See [`pbr_last_error_message`]
+	Error = 1,
+	/// A null pointer or a size of 0
+	InvalidArgument = 2,
+	/// Something panicked, the context shouldn't be used anymore
+	Panic = 3,
+}
+
+/// The handle given out by [`pbr_create_context`]
+pub struct PbrContext {
+	app: App,
+}
+
+impl PbrContext {
+	/// How many primitives a scene can have
+	pub const SCENE_CAPACITY: usize = 256;
+}
+
+static CONTEXT_CREATED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+	static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+	// A message can't contain a nul byte, just cut it there
+	let message = CString::new(message).unwrap_or_else(|error| {
+		let nul = error.nul_position();
+		CString::new(&error.into_vec()[..nul]).unwrap()
+	});
+
+	LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+}
+
+/// Run `f`, turning its errors and panics into a status. Nothing may unwind
+/// into the caller's code.
+fn guard(f: impl FnOnce() -> Result<()>) -> PbrStatus {
+	match panic::catch_unwind(AssertUnwindSafe(f)) {
+		Ok(Ok(())) => PbrStatus::Ok,
+		Ok(Err(error)) => {
+			set_last_error(format!("{:#}", error));
+			PbrStatus::Error
+		}
+		Err(panic) => {
+			let message = panic
+				.downcast_ref::<&str>()
+				.map(|message| message.to_string())
+				.or_else(|| panic.downcast_ref::<String>().cloned())
+				.unwrap_or_else(|| "Unknown panic".to_owned());
+
+			set_last_error(format!("Panicked: {}", message));
+			PbrStatus::Panic
+		}
+	}
+}
+
+/// # Safety
+/// `ctx` has to be null or a pointer given by [`pbr_create_context`] that
+/// wasn't destroyed yet
+unsafe fn context<'a>(ctx: *mut PbrContext) -> Result<&'a mut PbrContext, PbrStatus> {
+	ctx.as_mut().ok_or_else(|| {
+		set_last_error("The context is null".to_owned());
+		PbrStatus::InvalidArgument
+	})
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Create the context, or return null if it failed (or a context was already
+/// created in this process).
+#[no_mangle]
+pub extern "C" fn pbr_create_context() -> *mut PbrContext {
+	let mut ctx = ptr::null_mut();
+
+	let status = guard(|| {
+		if CONTEXT_CREATED.swap(true, Ordering::SeqCst) {
+			bail!("There can only be one context per process");
+		}
+
+		let app = crate::build_app_with(
+			DisplayPlugin {
+				visible: false,
+				any_thread: true,
+				placement_path: None,
+			},
+			|app| AnalyticIntersector::new(vec![Primitive::none(); PbrContext::SCENE_CAPACITY]).editable(app),
+		);
+
+		ctx = Box::into_raw(Box::new(PbrContext { app }));
+		Ok(())
+	});
+
+	match status {
+		PbrStatus::Ok => ctx,
+		_ => ptr::null_mut(),
+	}
+}
+
+/// # Safety
+/// `ctx` has to be null or a pointer given by [`pbr_create_context`] that
+/// wasn't destroyed yet. It can't be used anymore afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn pbr_destroy_context(ctx: *mut PbrContext) {
+	if !ctx.is_null() {
+		guard(|| {
+			drop(Box::from_raw(ctx));
+			Ok(())
+		});
+	}
+}
+
+/// Replace the scene with the primitives of a RON list, e.g.
+/// `[Sphere(center: (0, 1, 0), radius: 1, color: (1, 0, 0)), Plane(height: 0,
+/// color: (0.5, 0.5, 0.5)), Cuboid(center: (2, 0.5, 0), size: (1, 1, 1),
+/// color: (0, 0, 1))]`
+///
+/// The scene can also override some render settings, with
+/// `(objects: [...], settings: (march_steps_scale: 2.0))`. Only the fields
+/// that are there are overridden, and they are reverted by the next scene.
+/// The `settings ron` console command prints the current settings that way.
+///
+/// # Safety
+/// `ctx` as in [`pbr_destroy_context`], and `ron` has to be a nul-terminated
+/// string
+#[no_mangle]
+pub unsafe extern "C" fn pbr_load_scene_ron(ctx: *mut PbrContext, ron: *const c_char) -> PbrStatus {
+	let ctx = match context(ctx) {
+		Ok(ctx) => ctx,
+		Err(status) => return status,
+	};
+	if ron.is_null() {
+		set_last_error("The scene is null".to_owned());
+		return PbrStatus::InvalidArgument;
+	}
+	let ron = CStr::from_ptr(ron);
+
+	guard(|| {
+		let ron = ron.to_str().context("The scene isn't valid UTF-8")?;
+		let (objects, overrides) = match ron::from_str::<SceneFile>(ron).context("Couldn't parse the scene")? {
+			SceneFile::Objects(objects) => (objects, RenderSettingsOverrides::default()),
+			SceneFile::WithSettings { objects, settings } => (objects, settings),
+		};
+
+		if objects.len() > PbrContext::SCENE_CAPACITY {
+			bail!(
+				"The scene has {} objects, at most {} are supported",
+				objects.len(),
+				PbrContext::SCENE_CAPACITY
+			);
+		}
+
+		let world = &mut ctx.app.world;
+		let (mut primitives, mut dirty_ranges) = world
+			.query_filtered::<(&mut StorageArray<Primitive>, &mut DirtyRanges), With<ScenePrimitives>>()
+			.get_single_mut(world)
+			.context("The scene primitives are missing")?;
+
+		for (i, primitive) in primitives.iter_mut().enumerate() {
+			*primitive = objects
+				.get(i)
+				.map(SceneObject::primitive)
+				.unwrap_or_else(Primitive::none);
+		}
+		dirty_ranges.mark_range(0..primitives.len() as u64);
+
+		world.resource_scope(|world, mut scene_settings: Mut<SceneSettings>| {
+			scene_settings.load(overrides, &mut world.resource_mut::<RenderSettings>());
+		});
+
+		Ok(())
+	})
+}
+
+/// Move the camera. The angles are in degrees, the yaw turns around the
+/// vertical axis and the pitch looks up and down.
+///
+/// # Safety
+/// `ctx` as in [`pbr_destroy_context`]
+#[no_mangle]
+pub unsafe extern "C" fn pbr_set_camera(
+	ctx: *mut PbrContext,
+	x: f32,
+	y: f32,
+	z: f32,
+	yaw: f32,
+	pitch: f32,
+	y_fov: f32,
+) -> PbrStatus {
+	let ctx = match context(ctx) {
+		Ok(ctx) => ctx,
+		Err(status) => return status,
+	};
+
+	guard(|| {
+		let world = &mut ctx.app.world;
+		let (mut position, mut direction, mut frustum, controller, smoothing) = world
+			.query_filtered::<(
+				&mut Position,
+				&mut Direction,
+				&mut Frustum,
+				Option<&mut CameraController>,
+				Option<&mut MovementSmoothing>,
+			), With<ActiveCamera>>()
+			.get_single_mut(world)
+			.context("There is no active camera")?;
+
+		let pose = CameraPose {
+			position: [x, y, z],
+			yaw: yaw.to_radians(),
+			pitch: pitch.to_radians(),
+			y_fov: y_fov.to_radians(),
+			z_near: frustum.z_near,
+			z_far: frustum.z_far,
+		};
+
+		*position = pose.position();
+		*direction = pose.direction();
+		*frustum = pose.frustum();
+
+		// Same as recalling a pose, nothing should carry the camera away from it
+		if let Some(mut controller) = controller {
+			controller.reset();
+		}
+		if let Some(mut smoothing) = smoothing {
+			smoothing.reset();
+		}
+
+		Ok(())
+	})
+}
+
+/// Render `samples` frames at `width`x`height` and write their average to
+/// `out`, as `width * height * 4` floats (RGBA, row by row from the top).
+///
+/// # Safety
+/// `ctx` as in [`pbr_destroy_context`], and `out` has to point to at least
+/// `width * height * 4` writable floats
+#[no_mangle]
+pub unsafe extern "C" fn pbr_render(
+	ctx: *mut PbrContext,
+	width: u32,
+	height: u32,
+	samples: u32,
+	out: *mut f32,
+) -> PbrStatus {
+	let ctx = match context(ctx) {
+		Ok(ctx) => ctx,
+		Err(status) => return status,
+	};
+	if out.is_null() || width == 0 || height == 0 || samples == 0 {
+		set_last_error("The output is null, or the size or samples are 0".to_owned());
+		return PbrStatus::InvalidArgument;
+	}
+	let out = std::slice::from_raw_parts_mut(out, width as usize * height as usize * 4);
+
+	guard(|| {
+		let image = render(&mut ctx.app, Resolution(size!(width, height)), samples)?;
+		out.copy_from_slice(&image);
+		Ok(())
+	})
+}
+
+/// The message of the last error on this thread, or null if there wasn't any.
+/// Stays valid until the next error on this thread.
+#[no_mangle]
+pub extern "C" fn pbr_last_error_message() -> *const c_char {
+	LAST_ERROR.with(|last_error| {
+		last_error
+			.borrow()
+			.as_ref()
+			.map(|message| message.as_ptr())
+			.unwrap_or(ptr::null())
+	})
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// What [`pbr_load_scene_ron`] reads
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+enum SceneFile {
+	Objects(Vec<SceneObject>),
+	WithSettings {
+		objects: Vec<SceneObject>,
+		#[serde(default)]
+		settings: RenderSettingsOverrides,
+	},
+}
+
+/// An entry of the scenes read by [`pbr_load_scene_ron`]
+#[derive(Deserialize, Copy, Clone, Debug, PartialEq)]
+enum SceneObject {
+	Sphere {
+		center: [f32; 3],
+		radius: f32,
+		color: [f32; 3],
+	},
+	Plane {
+		height: f32,
+		color: [f32; 3],
+	},
+	Cuboid {
+		center: [f32; 3],
+		size: [f32; 3],
+		color: [f32; 3],
+	},
+}
+
+impl SceneObject {
+	fn primitive(&self) -> Primitive {
+		let color = |[r, g, b]: &[f32; 3]| Rgba::new(*r, *g, *b, 1.0);
+
+		match self {
+			SceneObject::Sphere {
+				center,
+				radius,
+				color: c,
+			} => Primitive::sphere((*center).into(), *radius, color(c)),
+			SceneObject::Plane { height, color: c } => Primitive::plane(*height, color(c)),
+			SceneObject::Cuboid { center, size, color: c } => {
+				Primitive::cuboid((*center).into(), (*size).into(), color(c))
+			}
+		}
+	}
+}
+
+/// Render at full quality at the given resolution, on a resized copy of the
+/// compute renderer, and average the frames on the CPU
+fn render(app: &mut App, resolution: Resolution, samples: u32) -> Result<Vec<f32>> {
+	let world = &mut app.world;
+
+	let renderer = world
+		.resource::<ComputeRenderer>()
+		.resized(world.resource::<Gpu>(), resolution)?;
+	compute::swap_compute_renderer(world, renderer);
+	world.insert_resource(resolution);
+
+	// Full quality, unless the scene asks for something else
+	let march_steps_scale = world.resource::<SceneSettings>().overrides().march_steps_scale;
+	world.resource_mut::<RenderSettings>().march_steps_scale = march_steps_scale.unwrap_or(1.0);
+	if let Some(mut dynamic_quality) = world.get_resource_mut::<DynamicQuality>() {
+		dynamic_quality.paused = true;
+	}
+
+	let mut sum = vec![0.0_f32; resolution.w as usize * resolution.h as usize * 4];
+
+	for _ in 0..samples {
+		let frame = app.world.resource::<Time>().counter_frame;
+		gameloop::run_frames(app, frame + 1)?;
+
+		let output = app.world.resource::<ComputeRenderer>().output_textures[0].clone();
+		if output.format() != TextureFormat::Rgba32Float {
+			return Err(anyhow!(
+				"The GPU renders to {:?}, only Rgba32Float can be read back",
+				output.format()
+			));
+		}
+
+		let texels = bytemuck::pod_collect_to_vec::<u8, f32>(&output.read_bytes(app.world.resource::<Gpu>()));
+		for (sum, texel) in sum.iter_mut().zip(texels) {
+			*sum += texel;
+		}
+	}
+
+	Ok(sum.into_iter().map(|sum| sum / samples as f32).collect())
+}
@@ -0,0 +1,112 @@
+use pbr_tracer_derive::ShaderStruct;
+
+use crate::libs::{
+	buffer::ShaderType,
+	shader::{Shader, ShaderBuilder},
+	shader_fragment::ShaderFragment,
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Shader API:\
+/// `fn ambient_occlusion(p: vec3f, n: vec3f) -> f32`
+///
+/// How much of the surrounding light reaches a point of a surface, from 0 (all
+/// blocked) to 1. Every intersector includes one, and the multi-purpose
+/// renderer multiplies it into the shaded color of the hits.
+///
+/// The settings are a uniform, so changing them doesn't need any shader edit.
+/// With 0 samples, the function is just `return 1.0`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AmbientOcclusion {
+	pub method: AmbientOcclusionMethod,
+	pub samples: u32,
+	/// How far from the surface something still occludes
+	pub radius: f32,
+	/// 1 darkens a fully occluded point to black, 0 doesn't darken anything
+	pub strength: f32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AmbientOcclusionMethod {
+	/// Samples `scene_sdf()` along the normal, cheap but only for the SDF
+	/// intersectors
+	DistanceField,
+	/// Casts rays around the normal with the intersector's `occluded()`. Works
+	/// with any intersector, but is noisy unless the frames are accumulated.
+	RayCast,
+}
+
+impl Default for AmbientOcclusion {
+	/// Off
+	fn default() -> Self {
+		Self::distance_field(0, 0.0, 0.0)
+	}
+}
+
+impl AmbientOcclusion {
+	pub fn distance_field(samples: u32, radius: f32, strength: f32) -> Self {
+		Self {
+			method: AmbientOcclusionMethod::DistanceField,
+			samples,
+			radius,
+			strength,
+		}
+	}
+
+	pub fn ray_cast(samples: u32, radius: f32, strength: f32) -> Self {
+		Self {
+			method: AmbientOcclusionMethod::RayCast,
+			samples,
+			radius,
+			strength,
+		}
+	}
+
+	pub fn is_enabled(&self) -> bool {
+		self.samples > 0
+	}
+}
+
+/// The `ambient_occlusion_settings` uniform
+#[repr(C)]
+#[derive(ShaderStruct, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+struct AmbientOcclusionSettings {
+	samples: u32,
+	radius: f32,
+	strength: f32,
+	#[shader(skip)]
+	_padding: u32,
+}
+
+impl ShaderFragment for AmbientOcclusion {
+	fn shader(&self) -> Shader {
+		if !self.is_enabled() {
+			return ShaderBuilder::new()
+				.include_path("ambient_occlusion/ambient_occlusion_off.wgsl")
+				.into();
+		}
+
+		let path = match self.method {
+			AmbientOcclusionMethod::DistanceField => "ambient_occlusion/distance_field.wgsl",
+			AmbientOcclusionMethod::RayCast => "ambient_occlusion/ray_cast.wgsl",
+		};
+
+		ShaderBuilder::new()
+			.include_path(path)
+			.include_value(
+				"ambient_occlusion_settings",
+				AmbientOcclusionSettings {
+					samples: self.samples,
+					radius: self.radius,
+					strength: self.strength,
+					_padding: 0,
+				},
+			)
+			.into()
+	}
+}
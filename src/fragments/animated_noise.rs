@@ -0,0 +1,70 @@
+use brainrot::vek::{Extent2, Vec3};
+use wgpu::{StorageTextureAccess, TextureAspect, TextureFormat};
+
+use crate::libs::{
+	buffer::storage_texture_buffer::StorageTexture,
+	shader::{Shader, ShaderBuilder},
+	shader_fragment::{PrePassDesc, PrePassDispatch, ShaderFragment},
+	texture::{InitPolicy, TextureAssetDimensions},
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Shader API:\
+/// `fn animated_noise_at(uv: vec2f) -> f32`
+///
+/// A tileable value noise texture that changes over time, regenerated by a
+/// pre-pass at the start of every frame (see [`ShaderFragment::pre_passes`]).
+/// The uv wraps around, and the noise is in `0..1`.
+pub struct AnimatedNoise {
+	pub size: Extent2<u32>,
+	/// How many times the noise changes completely per frame
+	pub speed: f32,
+}
+
+impl AnimatedNoise {
+	/// Same as the `@workgroup_size` of `animate_noise()`
+	const WORKGROUP_SIZE: u32 = 8;
+}
+
+impl Default for AnimatedNoise {
+	fn default() -> Self {
+		Self {
+			size: Extent2::new(256, 256),
+			speed: 0.01,
+		}
+	}
+}
+
+impl ShaderFragment for AnimatedNoise {
+	fn shader(&self) -> Shader {
+		ShaderBuilder::new()
+			.include_path("animated_noise/animated_noise.wgsl")
+			.include_value("animated_noise_speed", self.speed)
+			.include_buffer(StorageTexture::New {
+				var_name: "animated_noise",
+				access: StorageTextureAccess::ReadWrite,
+				dimensions: TextureAssetDimensions::D2(self.size),
+				format: TextureFormat::R32Float,
+				usage: None,
+				aspect: TextureAspect::All,
+				init: InitPolicy::Zero,
+			})
+			.into()
+	}
+
+	fn pre_passes(&self) -> Vec<PrePassDesc> {
+		let groups = self.size.map(|size| size.div_ceil(Self::WORKGROUP_SIZE));
+
+		vec![PrePassDesc {
+			entry_point: "animate_noise".to_owned(),
+			dispatch: PrePassDispatch::Fixed(Vec3::new(groups.w, groups.h, 1)),
+			reads: vec!["globals".to_owned()],
+			writes: vec!["animated_noise".to_owned()],
+		}]
+	}
+}
@@ -0,0 +1,159 @@
+use brainrot::vek::Rgb;
+use image::DynamicImage;
+use pbr_tracer_derive::ShaderStruct;
+use wgpu::{Buffer, FilterMode, TextureFormat};
+
+use crate::{
+	core::rendering::sky::{Sky, SkyModel, SkyPlugin},
+	libs::{
+		buffer::{sampled_texture_buffer::SampledTexture, uniform_buffer::UniformBufferDescriptor, ShaderType},
+		shader::{Shader, ShaderBuilder},
+		shader_fragment::ShaderFragment,
+		smart_arc::Sarc,
+		texture::SamplerEdges,
+	},
+	TextureAssets,
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Shader API:\
+/// `fn sample_environment(direction: vec3f) -> vec3f`
+///
+/// The light coming from infinitely far away in a direction, i.e. what the
+/// rays that miss the scene see.
+pub trait Environment: ShaderFragment {}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// A gradient from the horizon to the zenith, a flat ground below the horizon,
+/// and the sun with a glow around it. The sun is the first directional light
+/// of the [`Lights`](crate::core::rendering::lights::Lights), so it follows
+/// the light at runtime, and there's no disk if there's no directional light.
+#[repr(C)]
+#[derive(ShaderStruct, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+pub struct ProceduralSky {
+	pub zenith_color: Rgb<f32>,
+	/// The angular radius of the disk, in radians
+	pub sun_radius: f32,
+	pub horizon_color: Rgb<f32>,
+	/// How much brighter than the light's color the disk is
+	pub sun_intensity: f32,
+	pub ground_color: Rgb<f32>,
+	#[shader(skip)]
+	_padding: u32,
+}
+
+impl Default for ProceduralSky {
+	fn default() -> Self {
+		Self {
+			zenith_color: Rgb::new(0.0, 0.35, 0.8),
+			sun_radius: 0.02,
+			horizon_color: Rgb::new(0.6, 0.8, 1.0),
+			sun_intensity: 20.0,
+			ground_color: Rgb::new(0.25, 0.22, 0.2),
+			_padding: 0,
+		}
+	}
+}
+
+impl Environment for ProceduralSky {}
+impl ShaderFragment for ProceduralSky {
+	fn shader(&self) -> Shader {
+		ShaderBuilder::new()
+			.include_path("environment/procedural_sky.wgsl")
+			.include_value("procedural_sky", *self)
+			.into()
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// The daylight of the [`SkyPlugin`], after Preetham et al.: the sky for where
+/// the sun is and how hazy the air is, the sun disc, and a ground that gives
+/// back some of the sky. The sun follows the
+/// [`SkySettings`](crate::core::rendering::sky::SkySettings) at runtime, as
+/// does the sun light, so they always agree.
+pub struct PreethamSky {
+	model: Sarc<Buffer>,
+}
+
+impl PreethamSky {
+	/// Needs the [`SkyPlugin`]
+	pub fn new(sky: &Sky) -> Self {
+		Self {
+			model: sky.model_buffer.clone(),
+		}
+	}
+}
+
+impl Environment for PreethamSky {}
+impl ShaderFragment for PreethamSky {
+	fn shader(&self) -> Shader {
+		ShaderBuilder::new()
+			.include_path("environment/preetham_sky.wgsl")
+			.include_buffer(UniformBufferDescriptor::FromBuffer::<SkyModel, _> {
+				var_name: "sky_model",
+				buffer: self.model.clone(),
+			})
+			.into()
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// An equirectangular image around the scene, +y being the top row. Uploaded
+/// as `Rgba32Float`, so HDR images keep their range.
+#[derive(Clone)]
+pub struct HdriEnvironment {
+	pub image: DynamicImage,
+	/// Multiplied with the image
+	pub intensity: f32,
+}
+
+impl HdriEnvironment {
+	pub fn new(image: DynamicImage) -> Self {
+		Self { image, intensity: 1.0 }
+	}
+
+	/// An image of the `assets` folder, e.g. an `.hdr`
+	pub fn from_asset(path: &str) -> Self {
+		Self::new(TextureAssets::get_image(path))
+	}
+}
+
+impl Environment for HdriEnvironment {}
+impl ShaderFragment for HdriEnvironment {
+	fn shader(&self) -> Shader {
+		ShaderBuilder::new()
+			.include_path("environment/hdri.wgsl")
+			.include_value("hdri_intensity", self.intensity)
+			.include_buffer(SampledTexture::FromImage {
+				texture_var_name: "hdri",
+				sampler_var_name: "hdri_sampler",
+				image: self.image.clone(),
+				format: TextureFormat::Rgba32Float,
+				usage: None,
+				filter: FilterMode::Linear,
+				edges: SamplerEdges::ClampToEdge,
+				compare: None,
+			})
+			.into()
+	}
+}
@@ -0,0 +1,65 @@
+use wgpu::Buffer;
+
+use crate::libs::{
+	buffer::atomic_counter::AtomicCounterDescriptor,
+	shader::{Shader, ShaderBuilder},
+	shader_fragment::ShaderFragment,
+	smart_arc::Sarc,
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Shader API:\
+/// `fn gpu_assert(condition: bool, code: u32, payload: vec4f)`\
+/// `var<private> gpu_assert_pixel: vec2u`, set by the entry point
+///
+/// Without a log buffer `gpu_assert` does nothing, so that the calls can stay
+/// in the shaders of release builds.
+#[derive(Clone, Default)]
+pub struct GpuAsserts {
+	log: Option<Sarc<Buffer>>,
+}
+
+impl GpuAsserts {
+	/// How many `u32` every record takes in the log, after the counter:
+	/// code, pixel x, pixel y, frame, and the 4 payload values as bits
+	pub const RECORD_WORDS: u32 = 8;
+
+	pub fn disabled() -> Self {
+		Self::default()
+	}
+
+	/// The log is an array of atomic `u32`, see
+	/// [`GpuAssertsPlugin`](crate::core::rendering::gpu_asserts::GpuAssertsPlugin)
+	pub fn with_log(log: Sarc<Buffer>) -> Self {
+		Self { log: Some(log) }
+	}
+
+	pub fn max_records(log: &Buffer) -> u32 {
+		((log.size() / 4) as u32).saturating_sub(1) / Self::RECORD_WORDS
+	}
+}
+
+impl ShaderFragment for GpuAsserts {
+	fn shader(&self) -> Shader {
+		let Some(log) = &self.log else {
+			return ShaderBuilder::new()
+				.include_path("instrumentation/gpu_assert_off.wgsl")
+				.into();
+		};
+
+		ShaderBuilder::new()
+			.include_path("instrumentation/gpu_assert.wgsl")
+			.include_buffer(AtomicCounterDescriptor::FromBuffer {
+				var_name: "gpu_assert_log",
+				buffer: log.clone(),
+			})
+			.define("GPU_ASSERT_MAX_RECORDS", format!("{}u", Self::max_records(log)))
+			.define("GPU_ASSERT_RECORD_WORDS", format!("{}u", Self::RECORD_WORDS))
+			.into()
+	}
+}
@@ -1,10 +1,31 @@
+use anyhow::{bail, Context, Result};
+use bevy_ecs::world::{Mut, World};
+use brainrot::{
+	bevy::{self, App},
+	vek::{Mat4, Rgba, Vec3},
+};
+use log::warn;
 use pbr_tracer_derive::ShaderStruct;
+use wgpu::Buffer;
 
-use super::mpr::Intersector;
-use crate::libs::{
-	buffer::ShaderType,
-	shader::{Shader, ShaderBuilder},
-	shader_fragment::ShaderFragment,
+use super::{
+	ambient_occlusion::AmbientOcclusion,
+	mpr::Intersector,
+	sdf::{SdfNode, SdfScene},
+};
+use crate::{
+	core::{console, gpu::Gpu, params},
+	libs::{
+		buffer::{
+			self,
+			storage_buffer::{self as storage, DirtyRanges, StorageArray, StorageBuffer, StorageBufferDescriptor},
+			uniform_buffer::{UniformBuffer, UniformBufferDescriptor},
+			ShaderType,
+		},
+		shader::{Shader, ShaderBuilder},
+		shader_fragment::ShaderFragment,
+		smart_arc::Sarc,
+	},
 };
 
 /*
@@ -13,32 +34,458 @@ use crate::libs::{
 --------------------------------------------------------------------------------
 */
 
-pub struct Raymarcher;
+/// Sphere traces an [`SdfScene`]. A ray that runs out of steps or goes past the
+/// max distance (or the far plane) doesn't hit anything.
+pub struct Raymarcher {
+	pub scene: SdfScene,
+	pub max_steps: u32,
+	/// How close to a surface counts as a hit
+	pub hit_epsilon: f32,
+	pub max_distance: f32,
+	/// From the distance field by default
+	pub ambient_occlusion: AmbientOcclusion,
+	/// Store how many steps the camera rays took, over the max steps, in the
+	/// renderer's `output_debug`, for the
+	/// [`DebugView::Steps`](crate::core::rendering::composite::DebugView::Steps).
+	/// Compiled out when off.
+	pub step_heatmap: bool,
 
-#[repr(C)]
-#[derive(ShaderStruct, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
-pub struct RaymarchSettings {
-	epsilon: f32,
-	min_march: f32,
-	max_march_steps: u32,
+	/// See [`tweakable`](Self::tweakable), the settings are fixed without it
+	settings_buffer: Option<Sarc<Buffer>>,
 }
 
-impl Default for RaymarchSettings {
+impl Default for Raymarcher {
 	fn default() -> Self {
 		Self {
-			epsilon: 0.00001,
-			min_march: 0.001,
-			max_march_steps: 100,
+			scene: SdfScene::default(),
+			max_steps: 100,
+			hit_epsilon: 0.00001,
+			max_distance: 1000.0,
+			ambient_occlusion: AmbientOcclusion::distance_field(5, 0.5, 0.8),
+			step_heatmap: false,
+			settings_buffer: None,
 		}
 	}
 }
 
-impl Intersector for Raymarcher {}
+impl Raymarcher {
+	pub fn new(scene: SdfScene) -> Self {
+		Self {
+			scene,
+			..Default::default()
+		}
+	}
+
+	/// Spawn the settings as an auto-updated [`RaymarchSettings`] uniform, so
+	/// that they can be changed while the app runs, e.g. with the `raymarch`
+	/// console command. Needs the GPU plugin.
+	pub fn tweakable(mut self, app: &mut App) -> Self {
+		let settings = self.settings();
+		let gpu = app.world.resource::<Gpu>();
+
+		let settings_buffer = Sarc::new(UniformBuffer::raw_buffer_from_data(gpu, &settings, None));
+		buffer::spawn_buffer(app, settings, settings_buffer.clone());
+
+		console::register_command(
+			app,
+			"raymarch",
+			"raymarch [max_steps | epsilon | max_distance <value>]: Show or change the raymarching settings",
+			raymarch,
+		);
+		params::registry(app).register_float(
+			"raymarch.epsilon",
+			"How close to a surface a ray needs to get to count as a hit",
+			1e-7..=0.1,
+			|world| Ok(raymarch_settings(world)?.hit_epsilon),
+			|world, epsilon| {
+				raymarch_settings(world)?.hit_epsilon = epsilon;
+				Ok(())
+			},
+		);
+
+		self.settings_buffer = Some(settings_buffer);
+		self
+	}
+
+	fn settings(&self) -> RaymarchSettings {
+		RaymarchSettings {
+			hit_epsilon: self.hit_epsilon,
+			min_march: RaymarchSettings::MIN_MARCH,
+			max_distance: self.max_distance,
+			max_steps: self.max_steps,
+		}
+	}
+}
+
+/// The `raymarch_settings` uniform. When the [`Raymarcher`] is
+/// [`tweakable`](Raymarcher::tweakable), changing this component changes the
+/// uniform.
+#[repr(C)]
+#[derive(ShaderStruct, bevy::Component, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+pub struct RaymarchSettings {
+	pub hit_epsilon: f32,
+	/// Where the rays start, so that they don't hit the camera's own surface
+	pub min_march: f32,
+	pub max_distance: f32,
+	pub max_steps: u32,
+}
+
+impl RaymarchSettings {
+	const MIN_MARCH: f32 = 0.001;
+}
+
+/// Codes of the `gpu_assert`s in raymarch.wgsl
+#[repr(u32)]
+#[derive(ShaderStruct, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RaymarchAssert {
+	/// Ran out of steps before getting close to a surface or past the far plane
+	StepOverflow = 0x100,
+}
+
+impl Raymarcher {
+	/// `raymarch_sdf()` with the scene and the settings, without the intersector
+	/// API or the ambient occlusion
+	fn march_shader(&self) -> Shader {
+		let mut builder = ShaderBuilder::new();
+		builder
+			.include_path("raymarch/march.wgsl")
+			.include(self.scene.shader())
+			.include(RaymarchAssert::struct_definition().unwrap())
+			.define("RAYMARCH_STEP_HEATMAP", self.step_heatmap.to_string());
+
+		match &self.settings_buffer {
+			Some(buffer) => builder.include_buffer(UniformBufferDescriptor::FromBuffer::<RaymarchSettings, _> {
+				var_name: "raymarch_settings",
+				buffer: buffer.clone(),
+			}),
+			None => builder.include_value("raymarch_settings", self.settings()),
+		};
+
+		builder.into()
+	}
+}
+
+impl Intersector for Raymarcher {
+	fn has_debug_output(&self) -> bool {
+		self.step_heatmap
+	}
+}
+
 impl ShaderFragment for Raymarcher {
 	fn shader(&self) -> Shader {
 		ShaderBuilder::new()
 			.include_path("raymarch/raymarch.wgsl")
-			.include_value("settings", RaymarchSettings::default())
+			.include(self.march_shader())
+			.include(self.ambient_occlusion.shader())
 			.into()
 	}
 }
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Intersects the rays exactly with a list of [`Primitive`]s, no marching
+/// involved. Good as a ground truth for the [`Raymarcher`], and much cheaper
+/// than it for the scenes it can handle, see
+/// [`from_sdf_scene`](Self::from_sdf_scene).
+#[derive(Default)]
+pub struct AnalyticIntersector {
+	pub primitives: Vec<Primitive>,
+	/// Marched for what the primitives can't represent, the closer of the two hits
+	/// wins. Only its scene and settings are used.
+	pub fallback: Option<Raymarcher>,
+	/// Off by default, there's no distance field to sample so only
+	/// [`AmbientOcclusion::ray_cast`] works here (unless there's a fallback, but
+	/// then only its shapes occlude)
+	pub ambient_occlusion: AmbientOcclusion,
+
+	/// See [`editable`](Self::editable), the primitives are fixed without it
+	primitives_buffer: Option<Sarc<Buffer>>,
+}
+
+impl AnalyticIntersector {
+	pub fn new(primitives: Vec<Primitive>) -> Self {
+		Self {
+			primitives,
+			fallback: None,
+			ambient_occlusion: AmbientOcclusion::default(),
+			primitives_buffer: None,
+		}
+	}
+
+	/// The spheres, boxes and planes of the scene as primitives, as long as
+	/// nothing but unions, transforms and materials is above them. The rest (the
+	/// tori, and anything blended, subtracted or intersected) has no analytic
+	/// form: with `hybrid` it's marched as the [`fallback`](Self::fallback),
+	/// otherwise it's left out.
+	///
+	/// The primitives are red like the raymarched shapes. The SDF planes are solid
+	/// below, the analytic ones are only a surface, which looks the same from
+	/// above.
+	pub fn from_sdf_scene(scene: &SdfScene, hybrid: bool) -> Self {
+		let mut primitives = Vec::new();
+		let mut rest = Vec::new();
+		flatten_sdf(&scene.root, Mat4::identity(), 1.0, None, &mut primitives, &mut rest);
+
+		let fallback = rest.into_iter().reduce(SdfNode::union);
+		if fallback.is_some() && !hybrid {
+			warn!("Parts of the scene have no analytic form, they are left out");
+		}
+
+		Self {
+			fallback: fallback
+				.filter(|_| hybrid)
+				.map(|root| Raymarcher::new(SdfScene::new(root))),
+			..Self::new(primitives)
+		}
+	}
+
+	/// Spawn the primitives as a [`ScenePrimitives`] entity, so that they can be
+	/// changed while the app runs. Needs the GPU plugin.
+	///
+	/// The buffer keeps the size it was created with, so only the existing
+	/// primitives can be changed. Mark the changed ones in the entity's
+	/// [`DirtyRanges`] and they are re-uploaded before the next render.
+	pub fn editable(mut self, app: &mut App) -> Self {
+		let primitives = self.primitive_array();
+		let gpu = app.world.resource::<Gpu>();
+
+		let primitives_buffer = Sarc::new(StorageBuffer::raw_buffer_from_data(
+			gpu,
+			&primitives,
+			Some("Scene primitives"),
+		));
+
+		storage::register_range_updates::<Primitive>(app);
+		app.world.spawn((
+			ScenePrimitives,
+			primitives,
+			primitives_buffer.clone(),
+			DirtyRanges::default(),
+		));
+
+		self.primitives_buffer = Some(primitives_buffer);
+		self
+	}
+
+	fn primitive_array(&self) -> StorageArray<Primitive> {
+		// The runtime-sized array needs at least one element
+		if self.primitives.is_empty() {
+			return StorageArray(vec![Primitive::none()]);
+		}
+
+		StorageArray(self.primitives.clone())
+	}
+}
+
+/// Marks the entity holding the `StorageArray<Primitive>` of an
+/// [`editable`](AnalyticIntersector::editable) [`AnalyticIntersector`].
+#[derive(bevy::Component)]
+pub struct ScenePrimitives;
+
+#[repr(u32)]
+#[derive(ShaderStruct, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PrimitiveKind {
+	/// Skipped, e.g. to remove a primitive without resizing the buffer
+	None = 0,
+	/// The sphere of radius 1 around the origin
+	Sphere = 1,
+	/// The plane y = 0, visible from both sides
+	Plane = 2,
+	/// The box from -1 to 1 on every axis
+	Box = 3,
+}
+
+/// A unit shape (see [`PrimitiveKind`]) placed in the scene by a transform.
+#[repr(C)]
+#[derive(ShaderStruct, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+pub struct Primitive {
+	/// From the scene to the shape's own space, the rays are intersected there
+	pub inverse_transform: Mat4<f32>,
+	pub color: Rgba<f32>,
+	/// A [`PrimitiveKind`]
+	pub kind: u32,
+	/// Passed on to the shading as the object's `material_id`
+	pub material_id: u32,
+	#[shader(skip)]
+	_padding: [u32; 2],
+}
+
+impl Primitive {
+	pub fn new(kind: PrimitiveKind, transform: Mat4<f32>, color: Rgba<f32>, material_id: u32) -> Self {
+		Self {
+			inverse_transform: transform.inverted(),
+			color,
+			kind: kind as u32,
+			material_id,
+			_padding: [0; 2],
+		}
+	}
+
+	pub fn none() -> Self {
+		Self::new(PrimitiveKind::None, Mat4::identity(), Rgba::zero(), 0)
+	}
+
+	pub fn sphere(center: Vec3<f32>, radius: f32, color: Rgba<f32>) -> Self {
+		let transform = Mat4::translation_3d(center) * Mat4::scaling_3d(radius);
+		Self::new(PrimitiveKind::Sphere, transform, color, 0)
+	}
+
+	/// A horizontal plane, use [`new`](Self::new) for any other orientation
+	pub fn plane(height: f32, color: Rgba<f32>) -> Self {
+		let transform = Mat4::translation_3d(Vec3::new(0.0, height, 0.0));
+		Self::new(PrimitiveKind::Plane, transform, color, 0)
+	}
+
+	pub fn cuboid(center: Vec3<f32>, size: Vec3<f32>, color: Rgba<f32>) -> Self {
+		let transform = Mat4::translation_3d(center) * Mat4::scaling_3d(size / 2.0);
+		Self::new(PrimitiveKind::Box, transform, color, 0)
+	}
+}
+
+impl Intersector for AnalyticIntersector {}
+impl ShaderFragment for AnalyticIntersector {
+	fn shader(&self) -> Shader {
+		let fallback = match &self.fallback {
+			Some(fallback) => ShaderBuilder::new()
+				.include_path("analytic/fallback.wgsl")
+				.include(fallback.march_shader())
+				.into(),
+			None => ShaderBuilder::new().include_path("analytic/fallback_off.wgsl").into(),
+		};
+
+		let mut builder = ShaderBuilder::new();
+		builder
+			.include_path("analytic/analytic.wgsl")
+			.include(fallback)
+			.include(self.ambient_occlusion.shader())
+			.include(PrimitiveKind::struct_definition().unwrap());
+
+		match &self.primitives_buffer {
+			Some(buffer) => builder.include_buffer(StorageBufferDescriptor::FromBuffer::<StorageArray<Primitive>, _> {
+				var_name: "primitives",
+				read_only: true,
+				buffer: buffer.clone(),
+			}),
+			None => builder.include_buffer(StorageBufferDescriptor::FromData {
+				var_name: "primitives",
+				read_only: true,
+				data: self.primitive_array(),
+			}),
+		};
+
+		builder.into()
+	}
+}
+
+/// Sort the node into primitives and what's left to march. `transform`,
+/// `scale` and `material` are what the nodes above it apply.
+fn flatten_sdf(
+	node: &SdfNode,
+	transform: Mat4<f32>,
+	scale: f32,
+	material: Option<u32>,
+	primitives: &mut Vec<Primitive>,
+	rest: &mut Vec<SdfNode>,
+) {
+	// Same as the object color of the raymarcher
+	let color = Rgba::new(1.0, 0.0, 0.0, 1.0);
+
+	let (kind, shape_transform, material_id) = match node {
+		SdfNode::Sphere { radius, material_id } => (PrimitiveKind::Sphere, Mat4::scaling_3d(*radius), material_id),
+		SdfNode::Cuboid { size, material_id } => (PrimitiveKind::Box, Mat4::scaling_3d(*size / 2.0), material_id),
+		SdfNode::Plane { material_id } => (PrimitiveKind::Plane, Mat4::identity(), material_id),
+
+		SdfNode::Transform {
+			transform: child_transform,
+			scale: child_scale,
+			child,
+		} => {
+			return flatten_sdf(
+				child,
+				transform * *child_transform,
+				scale * child_scale,
+				material,
+				primitives,
+				rest,
+			);
+		}
+		// The outermost material wins, same as in the distance field
+		SdfNode::Material { material_id, child } => {
+			return flatten_sdf(
+				child,
+				transform,
+				scale,
+				material.or(Some(*material_id)),
+				primitives,
+				rest,
+			);
+		}
+		SdfNode::Union(a, b) => {
+			flatten_sdf(a, transform, scale, material, primitives, rest);
+			return flatten_sdf(b, transform, scale, material, primitives, rest);
+		}
+
+		_ => {
+			let node = match material {
+				Some(material_id) => node.clone().material(material_id),
+				None => node.clone(),
+			};
+			rest.push(SdfNode::Transform {
+				transform,
+				scale,
+				child: Box::new(node),
+			});
+			return;
+		}
+	};
+
+	primitives.push(Primitive::new(
+		kind,
+		transform * shape_transform,
+		color,
+		material.unwrap_or(*material_id),
+	));
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+fn raymarch_settings(world: &mut World) -> Result<Mut<'_, RaymarchSettings>> {
+	world
+		.query::<&mut RaymarchSettings>()
+		.get_single_mut(world)
+		.context("The raymarcher isn't tweakable")
+}
+
+fn raymarch(world: &mut World, args: &[String]) -> Result<String> {
+	let mut settings = raymarch_settings(world)?;
+
+	match args {
+		[] => {}
+		[setting, value] => match setting.as_str() {
+			"max_steps" => {
+				let max_steps = value.parse::<u32>().context("Expected a number")?;
+				if max_steps == 0 {
+					bail!("max_steps needs to be at least 1");
+				}
+				settings.max_steps = max_steps;
+			}
+			"epsilon" => settings.hit_epsilon = value.parse::<f32>().context("Expected a number")?,
+			"max_distance" => settings.max_distance = value.parse::<f32>().context("Expected a number")?,
+			_ => bail!("Unknown setting `{}`", setting),
+		},
+		_ => bail!("Usage: raymarch [max_steps | epsilon | max_distance <value>]"),
+	}
+
+	Ok(format!(
+		"max_steps = {}, epsilon = {}, max_distance = {}",
+		settings.max_steps, settings.hit_epsilon, settings.max_distance
+	))
+}
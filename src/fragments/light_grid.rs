@@ -0,0 +1,119 @@
+use brainrot::vek::{Rgba, Vec3};
+use pbr_tracer_derive::ShaderStruct;
+
+use crate::libs::{
+	buffer::{
+		storage_buffer::{array_stride, StorageArray, StorageBufferDescriptor},
+		ShaderType,
+	},
+	shader::{Shader, ShaderBuilder},
+	shader_fragment::{PrePassDesc, PrePassDispatch, ShaderFragment},
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Shader API:\
+/// `fn light_grid_lights_at(p: vec3f) -> LightGridCell`
+///
+/// Splits a box of the scene into cells and lists the point lights that can
+/// reach each cell, so that shading only has to go through a few of them.
+/// The grid is built by a pre-pass every frame (see
+/// [`ShaderFragment::pre_passes`]), an example of setup work that runs before
+/// the main trace.
+///
+/// Points outside of the box get an empty cell.
+pub struct LightGrid {
+	pub bounds_min: Vec3<f32>,
+	pub bounds_max: Vec3<f32>,
+	/// How many cells along each axis
+	pub cells: Vec3<u32>,
+	pub lights: Vec<PointLight>,
+}
+
+#[repr(C)]
+#[derive(ShaderStruct, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+pub struct PointLight {
+	pub position: Vec3<f32>,
+	/// The light doesn't reach further than this
+	pub radius: f32,
+	/// The alpha is the intensity
+	pub color: Rgba<f32>,
+}
+
+#[repr(C)]
+#[derive(ShaderStruct, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+pub struct LightGridCell {
+	pub count: u32,
+	/// Indices into the lights, only the first `count` are valid
+	pub lights: [u32; LightGrid::MAX_LIGHTS_PER_CELL],
+}
+
+#[repr(C)]
+#[derive(ShaderStruct, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+struct LightGridParams {
+	bounds_min: Vec3<f32>,
+	light_count: u32,
+	bounds_max: Vec3<f32>,
+	#[shader(skip)]
+	_padding: u32,
+	cells: Vec3<u32>,
+	#[shader(skip)]
+	_padding2: u32,
+}
+
+impl LightGrid {
+	/// Any more lights reaching a cell are ignored
+	pub const MAX_LIGHTS_PER_CELL: usize = 15;
+
+	/// Same as the `@workgroup_size` of `build_light_grid()`
+	const WORKGROUP_SIZE: u32 = 4;
+
+	fn cell_count(&self) -> u64 {
+		self.cells.map(u64::from).product()
+	}
+}
+
+impl ShaderFragment for LightGrid {
+	fn shader(&self) -> Shader {
+		let params = LightGridParams {
+			bounds_min: self.bounds_min,
+			light_count: self.lights.len() as u32,
+			bounds_max: self.bounds_max,
+			_padding: 0,
+			cells: self.cells,
+			_padding2: 0,
+		};
+
+		ShaderBuilder::new()
+			.include_path("light_grid/light_grid.wgsl")
+			.include_value("light_grid", params)
+			.include_buffer(StorageBufferDescriptor::FromData {
+				var_name: "light_grid_lights",
+				read_only: true,
+				data: StorageArray(self.lights.clone()),
+			})
+			.include_buffer(StorageBufferDescriptor::<StorageArray<LightGridCell>, _>::New {
+				var_name: "light_grid_cells",
+				read_only: false,
+				size: self.cell_count() * array_stride::<LightGridCell>(),
+			})
+			.define(
+				"LIGHT_GRID_MAX_LIGHTS_PER_CELL",
+				format!("{}u", Self::MAX_LIGHTS_PER_CELL),
+			)
+			.into()
+	}
+
+	fn pre_passes(&self) -> Vec<PrePassDesc> {
+		vec![PrePassDesc {
+			entry_point: "build_light_grid".to_owned(),
+			dispatch: PrePassDispatch::Fixed(self.cells.map(|cells| cells.div_ceil(Self::WORKGROUP_SIZE))),
+			reads: vec!["light_grid".to_owned(), "light_grid_lights".to_owned()],
+			writes: vec!["light_grid_cells".to_owned()],
+		}]
+	}
+}
@@ -0,0 +1,583 @@
+use std::{io::BufRead, ops::Range, path::Path};
+
+use anyhow::{Context, Result};
+use brainrot::vek::{Rgba, Vec2, Vec3, Vec4};
+use image::DynamicImage;
+use pbr_tracer_derive::ShaderStruct;
+use wgpu::{Buffer, FilterMode, TextureFormat};
+
+use super::{ambient_occlusion::AmbientOcclusion, mpr::Intersector};
+use crate::{
+	core::{
+		gpu::Gpu,
+		rendering::chunked_upload::{ChunkedUploader, UploadHandle},
+	},
+	libs::{
+		buffer::{
+			sampled_texture_buffer::SampledTexture,
+			storage_buffer::{StorageArray, StorageBuffer, StorageBufferDescriptor},
+			DynBufferUploadable, ShaderType,
+		},
+		shader::{Shader, ShaderBuilder},
+		shader_fragment::ShaderFragment,
+		smart_arc::Sarc,
+		texture::SamplerEdges,
+	},
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// An indexed triangle mesh, as loaded from a file
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Mesh {
+	pub positions: Vec<Vec3<f32>>,
+	/// Either one per position, or empty for flat shading
+	pub normals: Vec<Vec3<f32>>,
+	/// Either one per position, or empty. (0, 0) is the top left of the texture.
+	pub uvs: Vec<Vec2<f32>>,
+	/// Either one per position, or empty to derive them from the uvs, see
+	/// [`MeshTriangle::t0`]
+	pub tangents: Vec<Vec4<f32>>,
+	/// Three per triangle
+	pub indices: Vec<u32>,
+	/// One per triangle
+	pub material_ids: Vec<u32>,
+}
+
+impl Mesh {
+	pub fn load_obj(path: impl AsRef<Path>) -> Result<Self> {
+		let path = path.as_ref();
+		let (models, _) = tobj::load_obj(path, &Self::obj_load_options())
+			.with_context(|| format!("Couldn't load `{}`", path.display()))?;
+
+		Ok(Self::from_obj_models(models))
+	}
+
+	/// Same as [`load_obj`](Self::load_obj), e.g. for an embedded file. The
+	/// material libraries are ignored.
+	pub fn load_obj_from(reader: &mut impl BufRead) -> Result<Self> {
+		let (models, _) = tobj::load_obj_buf(reader, &Self::obj_load_options(), |_| Ok(Default::default()))
+			.context("Couldn't load the OBJ")?;
+
+		Ok(Self::from_obj_models(models))
+	}
+
+	fn obj_load_options() -> tobj::LoadOptions {
+		// A single index is what the triangles need, a position and a normal per
+		// vertex
+		tobj::LoadOptions {
+			single_index: true,
+			triangulate: true,
+			..Default::default()
+		}
+	}
+
+	/// All the models are merged into one mesh, each keeping its material
+	fn from_obj_models(models: Vec<tobj::Model>) -> Self {
+		let mut mesh = Self::default();
+		let mut flat = false;
+		let mut untextured = false;
+
+		for model in models {
+			let offset = mesh.positions.len() as u32;
+			let material_id = model.mesh.material_id.unwrap_or(0) as u32;
+
+			if model.mesh.normals.len() == model.mesh.positions.len() {
+				mesh.normals
+					.extend(model.mesh.normals.chunks_exact(3).map(Vec3::from_slice));
+			} else {
+				flat = true;
+			}
+
+			// OBJ has (0, 0) at the bottom left
+			if model.mesh.texcoords.len() / 2 == model.mesh.positions.len() / 3 {
+				mesh.uvs.extend(
+					model
+						.mesh
+						.texcoords
+						.chunks_exact(2)
+						.map(|uv| Vec2::new(uv[0], 1.0 - uv[1])),
+				);
+			} else {
+				untextured = true;
+			}
+
+			mesh.positions
+				.extend(model.mesh.positions.chunks_exact(3).map(Vec3::from_slice));
+			mesh.indices
+				.extend(model.mesh.indices.iter().map(|index| index + offset));
+			mesh.material_ids
+				.extend(std::iter::repeat(material_id).take(model.mesh.indices.len() / 3));
+		}
+
+		// Normals are all or nothing, a single model without them makes the whole mesh
+		// flat shaded
+		if flat {
+			mesh.normals.clear();
+		}
+		if untextured {
+			mesh.uvs.clear();
+		}
+
+		mesh
+	}
+
+	pub fn triangle_count(&self) -> usize {
+		self.indices.len() / 3
+	}
+
+	pub fn triangles(&self) -> Vec<MeshTriangle> {
+		self.indices
+			.chunks_exact(3)
+			.zip(&self.material_ids)
+			.map(|(indices, material_id)| {
+				let [p0, p1, p2] = [0, 1, 2].map(|i| self.positions[indices[i] as usize]);
+
+				let [n0, n1, n2] = if self.normals.is_empty() {
+					[(p1 - p0).cross(p2 - p0).normalized(); 3]
+				} else {
+					[0, 1, 2].map(|i| self.normals[indices[i] as usize])
+				};
+
+				let [uv0, uv1, uv2] = if self.uvs.is_empty() {
+					[Vec2::zero(); 3]
+				} else {
+					[0, 1, 2].map(|i| self.uvs[indices[i] as usize])
+				};
+
+				let [t0, t1, t2] = if self.tangents.is_empty() {
+					[triangle_tangent([p0, p1, p2], [uv0, uv1, uv2]); 3]
+				} else {
+					[0, 1, 2].map(|i| self.tangents[indices[i] as usize])
+				};
+
+				MeshTriangle {
+					p0,
+					material_id: *material_id,
+					p1,
+					_padding1: 0,
+					p2,
+					_padding2: 0,
+					n0,
+					_padding3: 0,
+					n1,
+					_padding4: 0,
+					n2,
+					_padding5: 0,
+					t0,
+					t1,
+					t2,
+					uv0,
+					uv1,
+					uv2,
+					_padding6: [0; 2],
+				}
+			})
+			.collect()
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+#[repr(C)]
+#[derive(ShaderStruct, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, Default, PartialEq)]
+pub struct MeshTriangle {
+	pub p0: Vec3<f32>,
+	pub material_id: u32,
+	pub p1: Vec3<f32>,
+	#[shader(skip)]
+	_padding1: u32,
+	pub p2: Vec3<f32>,
+	#[shader(skip)]
+	_padding2: u32,
+	/// The vertex normals, interpolated over the triangle
+	pub n0: Vec3<f32>,
+	#[shader(skip)]
+	_padding3: u32,
+	pub n1: Vec3<f32>,
+	#[shader(skip)]
+	_padding4: u32,
+	pub n2: Vec3<f32>,
+	#[shader(skip)]
+	_padding5: u32,
+	/// The tangents along +u for the normal maps, with the sign of the bitangent
+	/// in w. Derived from the uvs for the meshes that don't have them, the same
+	/// for the whole triangle then. All 0 without uvs, which the shading reads
+	/// as no normal map.
+	pub t0: Vec4<f32>,
+	pub t1: Vec4<f32>,
+	pub t2: Vec4<f32>,
+	/// The texture coordinates, interpolated the same way
+	pub uv0: Vec2<f32>,
+	pub uv1: Vec2<f32>,
+	pub uv2: Vec2<f32>,
+	#[shader(skip)]
+	_padding6: [u32; 2],
+}
+
+impl MeshTriangle {
+	fn centroid(&self) -> Vec3<f32> {
+		(self.p0 + self.p1 + self.p2) / 3.0
+	}
+}
+
+/// The direction +u goes in over the triangle, 0 if the uvs don't tell (e.g.
+/// they're all the same)
+fn triangle_tangent([p0, p1, p2]: [Vec3<f32>; 3], [uv0, uv1, uv2]: [Vec2<f32>; 3]) -> Vec4<f32> {
+	let (edge1, edge2) = (p1 - p0, p2 - p0);
+	let (duv1, duv2) = (uv1 - uv0, uv2 - uv0);
+
+	let determinant = duv1.x * duv2.y - duv2.x * duv1.y;
+	if determinant.abs() < 1e-12 {
+		return Vec4::zero();
+	}
+
+	let tangent = (edge1 * duv2.y - edge2 * duv1.y) / determinant;
+	let bitangent = (edge2 * duv1.x - edge1 * duv2.x) / determinant;
+	let normal = edge1.cross(edge2);
+
+	// +Y of the normal maps is up in the image, so against +v since (0, 0) is
+	// the top left
+	let sign = if normal.cross(tangent).dot(-bitangent) < 0.0 {
+		-1.0
+	} else {
+		1.0
+	};
+
+	let tangent = tangent.normalized();
+	Vec4::new(tangent.x, tangent.y, tangent.z, sign)
+}
+
+/// The material of the triangles with the same index as `material_id`
+#[repr(C)]
+#[derive(ShaderStruct, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+pub struct MeshMaterial {
+	/// Multiplied with the texture, if there's one
+	pub base_color: Rgba<f32>,
+	pub metallic: f32,
+	pub roughness: f32,
+	/// A layer of the mesh textures, or [`NO_TEXTURE`](Self::NO_TEXTURE)
+	pub base_color_texture: u32,
+	#[shader(skip)]
+	_padding: u32,
+}
+
+impl MeshMaterial {
+	pub const NO_TEXTURE: u32 = u32::MAX;
+
+	pub fn new(base_color: Rgba<f32>, metallic: f32, roughness: f32, base_color_texture: Option<u32>) -> Self {
+		Self {
+			base_color,
+			metallic,
+			roughness,
+			base_color_texture: base_color_texture.unwrap_or(Self::NO_TEXTURE),
+			_padding: 0,
+		}
+	}
+}
+
+impl Default for MeshMaterial {
+	/// The same grey as before there were materials
+	fn default() -> Self {
+		Self::new(Rgba::new(0.8, 0.8, 0.8, 1.0), 0.0, 1.0, None)
+	}
+}
+
+/// A node of the flattened [`Bvh`]. The two children of a node are always next
+/// to each other in the array.
+#[repr(C)]
+#[derive(ShaderStruct, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, Default, PartialEq)]
+pub struct BvhNode {
+	pub bounds_min: Vec3<f32>,
+	/// The first triangle of a leaf, or the left child of an inner node
+	pub left_or_first: u32,
+	pub bounds_max: Vec3<f32>,
+	/// How many triangles a leaf has, 0 for an inner node
+	pub count: u32,
+}
+
+impl BvhNode {
+	pub fn is_leaf(&self) -> bool {
+		self.count > 0
+	}
+
+	pub fn triangles(&self) -> Range<usize> {
+		self.left_or_first as usize..(self.left_or_first + self.count) as usize
+	}
+}
+
+/// A bounding volume hierarchy over the triangles of a mesh, built by splitting
+/// the triangles at the median of their centroids along the longest axis.
+///
+/// The build only depends on the order of the triangles, so the same mesh
+/// always gives the same BVH.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bvh {
+	/// The root is the first node
+	pub nodes: Vec<BvhNode>,
+	/// Reordered so that every leaf's triangles are contiguous
+	pub triangles: Vec<MeshTriangle>,
+}
+
+impl Bvh {
+	/// Leaves are split until they have at most this many triangles
+	pub const MAX_LEAF_TRIANGLES: usize = 4;
+
+	/// An empty mesh gets a single degenerate triangle, which no ray can hit,
+	/// so that the root is always a leaf or has children
+	pub fn build(mut triangles: Vec<MeshTriangle>) -> Self {
+		if triangles.is_empty() {
+			triangles.push(MeshTriangle::default());
+		}
+
+		let mut nodes = vec![BvhNode::default()];
+		let mut stack = vec![(0, 0..triangles.len())];
+
+		while let Some((node, range)) = stack.pop() {
+			let (bounds_min, bounds_max) = bounds(triangles[range.clone()].iter().flat_map(|t| [t.p0, t.p1, t.p2]));
+			let (centroids_min, centroids_max) = bounds(triangles[range.clone()].iter().map(MeshTriangle::centroid));
+
+			let extent = centroids_max - centroids_min;
+			let axis = if extent.x >= extent.y && extent.x >= extent.z {
+				0
+			} else if extent.y >= extent.z {
+				1
+			} else {
+				2
+			};
+
+			// Triangles on top of each other can't be split, so they stay in a bigger leaf
+			if range.len() <= Self::MAX_LEAF_TRIANGLES || extent[axis] <= 0.0 {
+				nodes[node] = BvhNode {
+					bounds_min,
+					left_or_first: range.start as u32,
+					bounds_max,
+					count: range.len() as u32,
+				};
+				continue;
+			}
+
+			// A stable sort, so that equal centroids keep their order
+			triangles[range.clone()].sort_by(|a, b| a.centroid()[axis].total_cmp(&b.centroid()[axis]));
+			let middle = range.start + range.len() / 2;
+
+			let left = nodes.len();
+			nodes.extend([BvhNode::default(); 2]);
+			nodes[node] = BvhNode {
+				bounds_min,
+				left_or_first: left as u32,
+				bounds_max,
+				count: 0,
+			};
+
+			stack.push((left + 1, middle..range.end));
+			stack.push((left, range.start..middle));
+		}
+
+		Self { nodes, triangles }
+	}
+
+	/// The most nodes on the way from the root to a leaf, root and leaf included
+	pub fn depth(&self) -> usize {
+		let mut depth = 0;
+		let mut stack = vec![(0, 1)];
+
+		while let Some((node, node_depth)) = stack.pop() {
+			let node = &self.nodes[node];
+			depth = depth.max(node_depth);
+
+			if !node.is_leaf() {
+				let left = node.left_or_first as usize;
+				stack.extend([(left, node_depth + 1), (left + 1, node_depth + 1)]);
+			}
+		}
+
+		depth
+	}
+}
+
+fn bounds(points: impl Iterator<Item = Vec3<f32>>) -> (Vec3<f32>, Vec3<f32>) {
+	points.fold(
+		(Vec3::broadcast(f32::MAX), Vec3::broadcast(f32::MIN)),
+		|(min, max), point| (Vec3::partial_min(min, point), Vec3::partial_max(max, point)),
+	)
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Intersects the rays with the triangles of a [`Mesh`], going through its
+/// [`Bvh`]. The normals are interpolated from the vertex normals.
+///
+/// The color of a triangle is the base color of its material, times its
+/// texture. Material ids without a material get the default one.
+pub struct MeshIntersector {
+	pub bvh: Bvh,
+	pub materials: Vec<MeshMaterial>,
+	/// The layers of the texture array, in sRGB. They are all resized to the
+	/// size of the first one.
+	pub textures: Vec<DynamicImage>,
+
+	/// See [`streamed`](Self::streamed), the BVH is uploaded with the shader
+	/// without it
+	bvh_buffers: Option<BvhBuffers>,
+}
+
+struct BvhBuffers {
+	nodes: Sarc<Buffer>,
+	triangles: Sarc<Buffer>,
+	uploads: Vec<UploadHandle>,
+}
+
+impl MeshIntersector {
+	pub fn new(mesh: &Mesh) -> Self {
+		Self {
+			bvh: Bvh::build(mesh.triangles()),
+			materials: Vec::new(),
+			textures: Vec::new(),
+			bvh_buffers: None,
+		}
+	}
+
+	/// Stream the BVH to the GPU with the [`ChunkedUploader`], instead of all at
+	/// once when the shader is built, which stalls that frame for a big mesh.
+	/// Nothing should be rendered with the intersector before its
+	/// [`uploads`](Self::uploads) are done, see
+	/// [`swap_compute_renderer_when_uploaded`](crate::core::rendering::chunked_upload::swap_compute_renderer_when_uploaded).
+	///
+	/// The BVH is fixed from then on, changing it doesn't change the buffers.
+	pub fn streamed(mut self, gpu: &Gpu, uploader: &mut ChunkedUploader) -> Self {
+		let mut stream = |label: &str, bytes: Vec<u8>| {
+			let buffer = Sarc::new(StorageBuffer::raw_buffer_from_size(
+				gpu,
+				bytes.len() as u64,
+				Some(label),
+			));
+			let upload = uploader.upload_buffer(&buffer, 0, bytes);
+			(buffer, upload)
+		};
+
+		let (nodes, nodes_upload) = stream("Mesh BVH nodes", StorageArray(self.bvh.nodes.clone()).get_dyn_bytes());
+		let (triangles, triangles_upload) = stream(
+			"Mesh triangles",
+			StorageArray(self.bvh.triangles.clone()).get_dyn_bytes(),
+		);
+
+		self.bvh_buffers = Some(BvhBuffers {
+			nodes,
+			triangles,
+			uploads: vec![nodes_upload, triangles_upload],
+		});
+		self
+	}
+
+	/// The uploads of the BVH when it's [`streamed`](Self::streamed), none
+	/// otherwise
+	pub fn uploads(&self) -> Vec<UploadHandle> {
+		self.bvh_buffers
+			.as_ref()
+			.map(|buffers| buffers.uploads.clone())
+			.unwrap_or_default()
+	}
+
+	pub fn with_materials(mut self, materials: Vec<MeshMaterial>, textures: Vec<DynamicImage>) -> Self {
+		self.materials = materials;
+		self.textures = textures;
+		self
+	}
+
+	/// The traversal keeps the nodes left to visit in a fixed-size array, so the
+	/// BVH can't be deeper than this
+	const STACK_SIZE: usize = 64;
+}
+
+/// Codes of the `gpu_assert`s in mesh.wgsl
+#[repr(u32)]
+#[derive(ShaderStruct, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MeshAssert {
+	/// The traversal ran out of stack, the children of the node were skipped
+	StackOverflow = 0x200,
+}
+
+impl Intersector for MeshIntersector {}
+impl ShaderFragment for MeshIntersector {
+	fn shader(&self) -> Shader {
+		// The depth is at most the log2 of the triangle count, so this is only hit by
+		// absurdly big meshes
+		assert!(
+			self.bvh.depth() < Self::STACK_SIZE,
+			"The BVH is {} levels deep, the traversal only supports {}",
+			self.bvh.depth(),
+			Self::STACK_SIZE
+		);
+
+		let mut builder = ShaderBuilder::new();
+		builder
+			.include_path("mesh/mesh.wgsl")
+			.include(MeshAssert::struct_definition().unwrap())
+			// Off, but the renderer still calls it
+			.include(AmbientOcclusion::default().shader());
+
+		match &self.bvh_buffers {
+			Some(buffers) => builder
+				.include_buffer(StorageBufferDescriptor::FromBuffer::<StorageArray<BvhNode>, _> {
+					var_name: "mesh_bvh_nodes",
+					read_only: true,
+					buffer: buffers.nodes.clone(),
+				})
+				.include_buffer(StorageBufferDescriptor::FromBuffer::<StorageArray<MeshTriangle>, _> {
+					var_name: "mesh_triangles",
+					read_only: true,
+					buffer: buffers.triangles.clone(),
+				}),
+			None => builder
+				.include_buffer(StorageBufferDescriptor::FromData {
+					var_name: "mesh_bvh_nodes",
+					read_only: true,
+					data: StorageArray(self.bvh.nodes.clone()),
+				})
+				.include_buffer(StorageBufferDescriptor::FromData {
+					var_name: "mesh_triangles",
+					read_only: true,
+					data: StorageArray(self.bvh.triangles.clone()),
+				}),
+		};
+
+		builder
+			.include_buffer(StorageBufferDescriptor::FromData {
+				var_name: "mesh_materials",
+				read_only: true,
+				// Runtime arrays can't be empty, the shader checks the count anyway
+				data: StorageArray(if self.materials.is_empty() {
+					vec![MeshMaterial::default()]
+				} else {
+					self.materials.clone()
+				}),
+			})
+			.include_buffer(SampledTexture::FromImageArray {
+				texture_var_name: "mesh_textures",
+				sampler_var_name: "mesh_sampler",
+				images: self.textures.clone(),
+				// Rgba8UnormSrgb can't be a storage texture, so the shader decodes the sRGB
+				format: TextureFormat::Rgba8Unorm,
+				usage: None,
+				filter: FilterMode::Linear,
+				edges: SamplerEdges::Repeat,
+				compare: None,
+			})
+			.define("MESH_BVH_STACK_SIZE", format!("{}u", Self::STACK_SIZE))
+			.define("MESH_MATERIAL_COUNT", format!("{}u", self.materials.len()))
+			.into()
+	}
+}
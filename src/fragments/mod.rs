@@ -1,4 +1,17 @@
+pub mod ambient_occlusion;
+pub mod animated_noise;
+pub mod environment;
+pub mod instrumentation;
 pub mod intersector;
+pub mod light_grid;
+pub mod mesh;
 pub mod mpr;
+pub mod path_tracer;
 pub mod post_processing;
+pub mod reference_grid;
+pub mod sampling;
+pub mod scene_gltf;
+pub mod sdf;
 pub mod shading;
+pub mod voxel;
+pub mod wavefront;
@@ -1,11 +1,17 @@
-use brainrot::{path, vek::Extent2};
+use std::sync::Arc;
+
+use brainrot::path;
 use wgpu::{TextureAspect, TextureFormat, TextureUsages};
 
-use super::post_processing::PostProcessingPipeline;
-use crate::libs::{
-	shader::{Shader, ShaderBuilder},
-	shader_fragment::{Renderer, ShaderFragment},
-	texture::{TexDescriptor, TextureAssetDimensions},
+use super::{environment::Environment, post_processing::PostProcessingPipeline, reference_grid::ReferenceGrid};
+use crate::{
+	core::size::Resolution,
+	libs::{
+		buffer::ping_pong_texture::PingPongTexture,
+		shader::{Shader, ShaderBuilder},
+		shader_fragment::{PrePassDesc, Renderer, RendererResizer, ShaderFragment},
+		texture::{InitPolicy, TexDescriptor, TextureAssetDimensions},
+	},
 };
 
 /*
@@ -15,11 +21,28 @@ use crate::libs::{
 */
 
 /// Shader API:\
-/// `fn intersect_scene(ray_origin: vec3f, ray_dir: vec3f) -> Intersection`
-pub trait Intersector: ShaderFragment {}
+/// `fn intersect_scene(ray_origin: vec3f, ray_dir: vec3f) -> Intersection`\
+/// `fn occluded(ray_origin: vec3f, ray_dir: vec3f, max_t: f32) -> bool`
+///
+/// `occluded()` is for the shadow rays of the shading, which only need to know
+/// whether anything is closer than `max_t`.
+///
+/// An intersector also includes an
+/// [`AmbientOcclusion`](super::ambient_occlusion::AmbientOcclusion), even if
+/// it's off.
+pub trait Intersector: ShaderFragment {
+	/// Whether the intersector sets `mpr_debug_output` (see `mpr_common.wgsl`),
+	/// in which case the renderer stores it in an `output_debug` texture for the
+	/// [`DebugView`](crate::core::rendering::composite::DebugView)s
+	fn has_debug_output(&self) -> bool {
+		false
+	}
+}
 
 /// Shader API:\
 /// `fn shade(intersection: Intersection) -> vec4f`
+///
+/// Only called for the hits, the misses show the [`Environment`].
 pub trait Shading: ShaderFragment {}
 
 /*
@@ -28,25 +51,63 @@ pub trait Shading: ShaderFragment {}
 --------------------------------------------------------------------------------
 */
 
-pub struct MultiPurposeRenderer<I, S>
+pub struct MultiPurposeRenderer<I, S, E>
 where
 	I: Intersector,
 	S: Shading,
+	E: Environment,
 {
 	pub intersector: I,
 	pub shading: S,
+	pub environment: E,
 	pub post_processing: PostProcessingPipeline,
+	pub reference_grid: Option<ReferenceGrid>,
+}
+
+impl<I, S, E> MultiPurposeRenderer<I, S, E>
+where
+	I: Intersector,
+	S: Shading,
+	E: Environment,
+{
+	/// Everything but the kernel itself, so that it can be run another way (see
+	/// [`WavefrontRenderer`](super::wavefront::WavefrontRenderer))
+	pub(crate) fn fragments_shader(&self) -> Shader {
+		let reference_grid = match &self.reference_grid {
+			Some(reference_grid) => reference_grid.shader(),
+			None => ShaderBuilder::new()
+				.include_path("reference_grid/reference_grid_off.wgsl")
+				.into(),
+		};
+
+		// Not to bind a texture that nothing writes
+		let debug_output = if self.intersector.has_debug_output() {
+			"mpr_debug_output.wgsl"
+		} else {
+			"mpr_debug_output_off.wgsl"
+		};
+
+		ShaderBuilder::new()
+			.include_path(debug_output)
+			.include(self.intersector.shader())
+			.include(self.shading.shader())
+			.include(self.environment.shader())
+			.include(reference_grid)
+			.include(self.post_processing.shader())
+			.into()
+	}
 }
 
-impl<I, S> Renderer for MultiPurposeRenderer<I, S>
+impl<I, S, E> Renderer for MultiPurposeRenderer<I, S, E>
 where
 	I: Intersector,
 	S: Shading,
+	E: Environment,
 {
-	fn output_textures(&self, resolution: Extent2<u32>) -> Vec<(String, TexDescriptor)> {
+	fn output_textures(&self, resolution: Resolution) -> Vec<(String, TexDescriptor)> {
 		let depth = TexDescriptor {
 			label: "Depth output texture",
-			dimensions: TextureAssetDimensions::D2(resolution),
+			dimensions: TextureAssetDimensions::D2(resolution.into()),
 			format: TextureFormat::Rgba32Float,
 			usage: Some(TextureUsages::STORAGE_BINDING | TextureUsages::COPY_SRC),
 			aspect: TextureAspect::All,
@@ -54,33 +115,59 @@ where
 
 		let normal = TexDescriptor {
 			label: "Normal output texture",
-			dimensions: TextureAssetDimensions::D2(resolution),
+			dimensions: TextureAssetDimensions::D2(resolution.into()),
 			format: TextureFormat::Rgba32Float,
 			usage: Some(TextureUsages::STORAGE_BINDING | TextureUsages::COPY_SRC),
 			aspect: TextureAspect::All,
 		};
 
-		std::vec![
+		let mut outputs = std::vec![
 			("output_color".to_string(), self.default_color_texture(resolution)),
 			("output_normal".to_string(), normal),
 			("output_depth".to_string(), depth),
-		]
+			("output_id".to_string(), self.id_texture(resolution)),
+		];
+
+		// Only values in [0, 1], e.g. the raymarcher's step counts
+		if self.intersector.has_debug_output() {
+			outputs.push((
+				"output_debug".to_string(),
+				TexDescriptor {
+					label: "Debug output texture",
+					dimensions: TextureAssetDimensions::D2(resolution.into()),
+					format: TextureFormat::Rgba8Unorm,
+					usage: Some(TextureUsages::STORAGE_BINDING | TextureUsages::COPY_SRC),
+					aspect: TextureAspect::All,
+				},
+			));
+		}
+
+		outputs.extend(self.post_processing.output_textures(resolution));
+		outputs
 	}
 }
 
-impl<I, S> ShaderFragment for MultiPurposeRenderer<I, S>
+impl<I, S, E> ShaderFragment for MultiPurposeRenderer<I, S, E>
 where
 	I: Intersector,
 	S: Shading,
+	E: Environment,
 {
 	fn shader(&self) -> Shader {
 		ShaderBuilder::new()
 			.include_path("mpr.wgsl")
-			.include(self.intersector.shader())
-			.include(self.shading.shader())
-			.include(self.post_processing.shader())
+			.include(self.fragments_shader())
 			.into()
 	}
+
+	fn pre_passes(&self) -> Vec<PrePassDesc> {
+		let mut pre_passes = self.intersector.pre_passes();
+		pre_passes.extend(self.shading.pre_passes());
+		pre_passes.extend(self.environment.pre_passes());
+		pre_passes.extend(self.reference_grid.iter().flat_map(ShaderFragment::pre_passes));
+		pre_passes.extend(self.post_processing.pre_passes());
+		pre_passes
+	}
 }
 
 /*
@@ -97,3 +184,47 @@ impl ShaderFragment for DebugRenderer {
 		path!("/debug.wgsl").into()
 	}
 }
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Blends every frame with the previous one through a [`PingPongTexture`].
+/// Moving the camera should leave trails behind.
+pub struct PingPongDebugRenderer {
+	pub resolution: Resolution,
+	pub blend_factor: f32,
+}
+
+impl Renderer for PingPongDebugRenderer {
+	// The history is at the resolution
+	fn resizer(&self) -> Option<RendererResizer> {
+		let blend_factor = self.blend_factor;
+
+		Some(Arc::new(move |_, resolution| {
+			let renderer = PingPongDebugRenderer {
+				resolution,
+				blend_factor,
+			};
+			(renderer.shader(), renderer.pre_passes())
+		}))
+	}
+}
+
+impl ShaderFragment for PingPongDebugRenderer {
+	fn shader(&self) -> Shader {
+		ShaderBuilder::new()
+			.include_path("ping_pong_debug.wgsl")
+			.include_value("blend_factor", self.blend_factor)
+			.include_buffer(PingPongTexture {
+				var_name: "history",
+				dimensions: TextureAssetDimensions::D2(self.resolution.into()),
+				format: TextureFormat::Rgba32Float,
+				usage: None,
+				init: InitPolicy::Zero,
+			})
+			.into()
+	}
+}
@@ -0,0 +1,349 @@
+use anyhow::{Context, Result};
+use bevy_ecs::world::{Mut, World};
+use brainrot::{
+	bevy::{self, App},
+	vek::Vec2,
+};
+use image::DynamicImage;
+use pbr_tracer_derive::ShaderStruct;
+use wgpu::{Buffer, TextureAspect, TextureFormat, TextureUsages};
+
+use super::{
+	environment::Environment,
+	mpr::Intersector,
+	post_processing::PostProcessingPipeline,
+	shading::{self, Material, MaterialLibrary},
+};
+use crate::{
+	core::{
+		gpu::Gpu,
+		params,
+		rendering::{
+			accumulation::{self, Reprojection, ReprojectionBuffers},
+			camera_view::CameraView,
+		},
+		size::Resolution,
+	},
+	libs::{
+		buffer::{
+			self,
+			atomic_counter::AtomicCounterDescriptor,
+			uniform_buffer::{UniformBuffer, UniformBufferDescriptor},
+			ShaderType,
+		},
+		shader::{Shader, ShaderBuilder},
+		shader_fragment::{PrePassDesc, PrePassDispatch, Renderer, ShaderFragment},
+		smart_arc::Sarc,
+		texture::{TexDescriptor, TextureAssetDimensions},
+	},
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Traces paths for every pixel and frame, bouncing off the materials of the
+/// [`MaterialLibrary`] with the same BRDF as the
+/// [`PbrShading`](super::shading::PbrShading), and lighting every bounce with
+/// the [`Lights`](crate::core::rendering::lights::Lights). The paths that miss
+/// the scene see the [`Environment`].
+///
+/// The frames are summed into the `output_accumulation` texture for as long as
+/// the [`Accumulation`](crate::core::rendering::accumulation::Accumulation)
+/// goes on, i.e. while the camera and the lights stand still, and the average
+/// is what gets post-processed. Needs the
+/// [`AccumulationPlugin`](crate::core::rendering::accumulation::AccumulationPlugin),
+/// otherwise every frame starts over. Once [`reprojected`](Self::reprojected),
+/// the sum follows the camera instead of starting over when it moves.
+///
+/// The lights are points, so their shadows are hard, unless they have a
+/// [size](crate::core::rendering::lights::Light::with_size). Those are spheres
+/// that the paths can hit, and that
+/// [`next_event_estimation`](PathTracerSettings::next_event_estimation) samples
+/// at every bounce.
+pub struct PathTracer<I, E>
+where
+	I: Intersector,
+	E: Environment,
+{
+	pub intersector: I,
+	pub environment: E,
+	pub materials: Vec<Material>,
+	/// The textures of the materials, without the placeholders
+	pub textures: Vec<DynamicImage>,
+	pub settings: PathTracerSettings,
+	pub post_processing: PostProcessingPipeline,
+
+	/// See [`tweakable`](Self::tweakable), the settings are fixed without it
+	settings_buffer: Option<Sarc<Buffer>>,
+	/// See [`reprojected`](Self::reprojected)
+	reprojection: Option<ReprojectionBuffers>,
+}
+
+impl<I, E> PathTracer<I, E>
+where
+	I: Intersector,
+	E: Environment,
+{
+	/// Same as the `@workgroup_size` of `path_tracer_save_history`
+	const HISTORY_WORKGROUP_SIZE: Vec2<u32> = Vec2 { x: 8, y: 8 };
+
+	/// The materials and textures are copied, same as for the
+	/// [`PbrShading`](super::shading::PbrShading)
+	pub fn new(intersector: I, environment: E, library: &MaterialLibrary) -> Self {
+		Self {
+			intersector,
+			environment,
+			materials: library.materials().to_vec(),
+			textures: library.textures().to_vec(),
+			settings: PathTracerSettings::default(),
+			post_processing: PostProcessingPipeline::empty(),
+			settings_buffer: None,
+			reprojection: None,
+		}
+	}
+
+	/// Reproject the sum into the new view when the camera moves, if the
+	/// [`Accumulation`](crate::core::rendering::accumulation::Accumulation)
+	/// asks for it (see
+	/// [`ReprojectionSettings`](crate::core::rendering::accumulation::ReprojectionSettings)).
+	/// Every pixel looks for its surface in the last frame's sum, and keeps it if
+	/// the depth there matches, otherwise it starts over. Needs the GPU plugin
+	/// and the [`AccumulationPlugin`](crate::core::rendering::accumulation::AccumulationPlugin).
+	pub fn reprojected(mut self, app: &mut App) -> Self {
+		self.reprojection = Some(accumulation::reprojection_buffers(app));
+		self
+	}
+
+	/// Spawn the settings as an auto-updated [`PathTracerSettings`] uniform, so
+	/// that they can be changed while the app runs, e.g. with the params. Every
+	/// change starts the accumulation over. Needs the GPU plugin.
+	pub fn tweakable(mut self, app: &mut App) -> Self {
+		let gpu = app.world.resource::<Gpu>();
+
+		let settings_buffer = Sarc::new(UniformBuffer::raw_buffer_from_data(gpu, &self.settings, None));
+		buffer::spawn_buffer(app, self.settings, settings_buffer.clone());
+
+		params::registry(app)
+			.register_int(
+				"path_tracer.bounces",
+				"How often a path can bounce before it stops, 0 for direct lighting only",
+				0..=64,
+				|world| Ok(path_tracer_settings(world)?.max_bounces as i64),
+				|world, bounces| {
+					path_tracer_settings(world)?.max_bounces = bounces as u32;
+					Ok(())
+				},
+			)
+			.register_int(
+				"path_tracer.roulette_depth",
+				"The bounce from which the paths carrying little light can stop early",
+				0..=64,
+				|world| Ok(path_tracer_settings(world)?.roulette_depth as i64),
+				|world, depth| {
+					path_tracer_settings(world)?.roulette_depth = depth as u32;
+					Ok(())
+				},
+			)
+			.register_float(
+				"path_tracer.clamp",
+				"The brightest a path can be before it's scaled down, against fireflies, 0 for no clamp",
+				0.0..=1000.0,
+				|world| Ok(path_tracer_settings(world)?.firefly_clamp),
+				|world, clamp| {
+					path_tracer_settings(world)?.firefly_clamp = clamp;
+					Ok(())
+				},
+			)
+			.register_int(
+				"path_tracer.samples",
+				"How many paths every pixel traces per frame",
+				1..=64,
+				|world| Ok(path_tracer_settings(world)?.samples_per_frame as i64),
+				|world, samples| {
+					path_tracer_settings(world)?.samples_per_frame = samples as u32;
+					Ok(())
+				},
+			)
+			.register_bool(
+				"path_tracer.nee",
+				"Sample a light at every bounce, instead of only finding the spheres of light by hitting them",
+				|world| Ok(path_tracer_settings(world)?.next_event_estimation != 0),
+				|world, enabled| {
+					path_tracer_settings(world)?.next_event_estimation = enabled as u32;
+					Ok(())
+				},
+			);
+
+		self.settings_buffer = Some(settings_buffer);
+		self
+	}
+}
+
+/// The `path_tracer_settings` uniform. When the [`PathTracer`] is
+/// [`tweakable`](PathTracer::tweakable), changing this component changes the
+/// uniform.
+#[repr(C)]
+#[derive(ShaderStruct, bevy::Component, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+pub struct PathTracerSettings {
+	/// How often a path can bounce before it stops, 0 for direct lighting only
+	pub max_bounces: u32,
+	/// From which bounce on the paths that carry little light can stop early
+	/// (Russian roulette)
+	pub roulette_depth: u32,
+	/// The brightest a path can be, brighter ones are scaled down to it. Takes
+	/// out the fireflies, at the cost of some energy. 0 for no clamp.
+	pub firefly_clamp: f32,
+	/// How many paths every pixel traces per frame, at least 1
+	pub samples_per_frame: u32,
+	/// Whether every bounce samples one of the lights (next event estimation),
+	/// weighted against the paths that hit it by multiple importance sampling.
+	/// Otherwise the lights with a size are only found by hitting them, the
+	/// points are always sampled since nothing else finds them. 0 or 1.
+	pub next_event_estimation: u32,
+}
+
+impl Default for PathTracerSettings {
+	fn default() -> Self {
+		Self {
+			max_bounces: 4,
+			roulette_depth: 2,
+			firefly_clamp: 0.0,
+			samples_per_frame: 1,
+			next_event_estimation: 1,
+		}
+	}
+}
+
+fn path_tracer_settings(world: &mut World) -> Result<Mut<'_, PathTracerSettings>> {
+	world
+		.query::<&mut PathTracerSettings>()
+		.get_single_mut(world)
+		.context("The path tracer isn't tweakable")
+}
+
+impl<I, E> Renderer for PathTracer<I, E>
+where
+	I: Intersector,
+	E: Environment,
+{
+	fn output_textures(&self, resolution: Resolution) -> Vec<(String, TexDescriptor)> {
+		// The sum of the frames in rgb, their count in alpha
+		let accumulation = TexDescriptor {
+			label: "Accumulation output texture",
+			dimensions: TextureAssetDimensions::D2(resolution.into()),
+			format: TextureFormat::Rgba32Float,
+			usage: Some(TextureUsages::STORAGE_BINDING),
+			aspect: TextureAspect::All,
+		};
+
+		// Same as the multi-purpose renderer's, of the first path
+		let depth = TexDescriptor {
+			label: "Depth output texture",
+			dimensions: TextureAssetDimensions::D2(resolution.into()),
+			format: TextureFormat::Rgba32Float,
+			usage: Some(TextureUsages::STORAGE_BINDING | TextureUsages::COPY_SRC),
+			aspect: TextureAspect::All,
+		};
+
+		let mut outputs = std::vec![
+			("output_color".to_string(), self.default_color_texture(resolution)),
+			("output_accumulation".to_string(), accumulation),
+			("output_depth".to_string(), depth),
+			("output_id".to_string(), self.id_texture(resolution)),
+		];
+
+		// The last frame's sum and depth, which the pixels look into after the
+		// camera moved, since the sum itself is being written
+		if self.reprojection.is_some() {
+			let history = |label, format| TexDescriptor {
+				label,
+				dimensions: TextureAssetDimensions::D2(resolution.into()),
+				format,
+				usage: Some(TextureUsages::STORAGE_BINDING),
+				aspect: TextureAspect::All,
+			};
+
+			outputs.push((
+				"output_history".to_string(),
+				history("History output texture", TextureFormat::Rgba32Float),
+			));
+			outputs.push((
+				"output_history_depth".to_string(),
+				history("History depth output texture", TextureFormat::R32Float),
+			));
+		}
+
+		outputs.extend(self.post_processing.output_textures(resolution));
+		outputs
+	}
+}
+
+impl<I, E> ShaderFragment for PathTracer<I, E>
+where
+	I: Intersector,
+	E: Environment,
+{
+	fn shader(&self) -> Shader {
+		let mut builder = ShaderBuilder::new();
+		builder
+			.include_path("path_tracer.wgsl")
+			// For lights.wgsl, the soft shadows come from the spheres of light
+			.include_value("shadow_softness", 0.0f32)
+			.include(shading::materials_shader(&self.materials, &self.textures))
+			.include(self.intersector.shader())
+			.include(self.environment.shader())
+			.include(self.post_processing.shader());
+
+		match &self.settings_buffer {
+			Some(buffer) => builder.include_buffer(UniformBufferDescriptor::FromBuffer::<PathTracerSettings, _> {
+				var_name: "path_tracer_settings",
+				buffer: buffer.clone(),
+			}),
+			None => builder.include_value("path_tracer_settings", self.settings),
+		};
+
+		match &self.reprojection {
+			Some(buffers) => builder
+				.include_path("path_tracer_history.wgsl")
+				.include_buffer(UniformBufferDescriptor::FromBuffer::<Reprojection, _> {
+					var_name: "reprojection",
+					buffer: buffers.reprojection.clone(),
+				})
+				.include_buffer(UniformBufferDescriptor::FromBuffer::<CameraView, _> {
+					var_name: "previous_camera",
+					buffer: buffers.previous_camera.clone(),
+				})
+				.include_buffer(AtomicCounterDescriptor::FromBuffer {
+					var_name: "reprojection_stats",
+					buffer: buffers.stats.clone(),
+				}),
+			None => builder.include_path("path_tracer_history_off.wgsl"),
+		};
+
+		builder.into()
+	}
+
+	fn pre_passes(&self) -> Vec<PrePassDesc> {
+		let mut pre_passes = self.intersector.pre_passes();
+		pre_passes.extend(self.environment.pre_passes());
+		pre_passes.extend(self.post_processing.pre_passes());
+
+		if self.reprojection.is_some() {
+			pre_passes.push(PrePassDesc {
+				entry_point: "path_tracer_save_history".to_owned(),
+				dispatch: PrePassDispatch::Resolution(Self::HISTORY_WORKGROUP_SIZE),
+				reads: vec![
+					"reprojection".to_owned(),
+					"output_accumulation".to_owned(),
+					"output_depth".to_owned(),
+				],
+				writes: vec!["output_history".to_owned(), "output_history_depth".to_owned()],
+			});
+		}
+
+		pre_passes
+	}
+}
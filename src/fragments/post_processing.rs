@@ -1,6 +1,42 @@
-use crate::libs::{
-	shader::{Shader, ShaderBuilder},
-	shader_fragment::ShaderFragment,
+use anyhow::{bail, Context, Result};
+use bevy_ecs::{
+	event::EventReader,
+	schedule::IntoSystemConfigs,
+	system::{Query, Res, ResMut},
+	world::{Mut, World},
+};
+use brainrot::{
+	bevy::{self, App},
+	vek::{Vec2, Vec4},
+};
+use log::info;
+use pbr_tracer_derive::ShaderStruct;
+use wgpu::{Buffer, TextureAspect, TextureFormat, TextureUsages};
+
+use crate::{
+	core::{
+		console::{self, is_console_closed},
+		events::KeyboardInputEvent,
+		gameloop::Update,
+		gpu::Gpu,
+		key_bindings::{Action, KeyBindings},
+		params,
+		rendering::color_grade::{ColorGradeLut, ColorGradeSettings},
+		size::Resolution,
+	},
+	libs::{
+		buffer::{
+			self,
+			atomic_counter::AtomicCounterDescriptor,
+			sampled_texture_buffer::SampledTexture,
+			uniform_buffer::{UniformBuffer, UniformBufferDescriptor},
+			ShaderType,
+		},
+		shader::{Shader, ShaderBuilder},
+		shader_fragment::{PrePassDesc, PrePassDispatch, ShaderFragment},
+		smart_arc::Sarc,
+		texture::{Tex, TexDescriptor, TextureAssetDimensions},
+	},
 };
 
 /*
@@ -10,49 +46,902 @@ use crate::libs::{
 */
 
 /// Shader API:\
-/// `fn post_processing_effect(coord: vec2f, color: vec4f) -> vec4f`
-pub trait PostProcessingEffect: ShaderFragment {}
+/// `fn post_processing_effect(coord: vec2f, color: vec4f, ctx: PPContext) -> vec4f`
+///
+/// An effect that needs more than the pixel it's given (e.g. a blur) can have
+/// textures of its own that follow the render resolution, see
+/// [`output_textures`](Self::output_textures), and fill them with
+/// [`pre_passes`](ShaderFragment::pre_passes). The pre-passes run before the
+/// main pass, so they see what the main pass wrote the frame before.
+///
+/// An effect that only needs to look at the colors around its pixel can ask
+/// for its source instead, see [`samples_source`](Self::samples_source).
+pub trait PostProcessingEffect: ShaderFragment {
+	/// Used in the name the effect's function gets in the pipeline, the type's
+	/// name in snake_case by default
+	fn name(&self) -> String {
+		let type_name = std::any::type_name::<Self>();
+		let type_name = type_name.split('<').next().unwrap_or(type_name);
+		let type_name = type_name.rsplit("::").next().unwrap_or(type_name);
+
+		let mut name = String::new();
+		for (i, c) in type_name.chars().enumerate() {
+			if c.is_uppercase() && i > 0 {
+				name.push('_');
+			}
+			name.extend(c.to_lowercase());
+		}
+		name
+	}
+
+	/// Storage textures the renderer should make for the effect, bound read-write
+	/// under the given names like the renderer's own outputs
+	fn output_textures(&self, _resolution: Resolution) -> Vec<(String, TexDescriptor)> {
+		Vec::new()
+	}
+
+	/// Whether the effect calls `fn pp_sample_source(coord: vec2f) -> vec4f`,
+	/// which samples the colors as they enter the effect at any coordinate,
+	/// bilinearly filtered. Every pixel stores its color right before the
+	/// effect runs on it, so the other pixels are the ones of the frame before.
+	///
+	/// The pipeline keeps a texture for each effect that samples its source.
+	fn samples_source(&self) -> bool {
+		false
+	}
+
+	/// Which colors the effect expects, see [`ColorStage`]. Effects that work
+	/// on any colors don't have one.
+	fn input_stage(&self) -> Option<ColorStage> {
+		None
+	}
+
+	/// Which colors the effect gives back, the ones it takes by default
+	fn output_stage(&self) -> Option<ColorStage> {
+		self.input_stage()
+	}
+}
+
+/// What the colors going through a [`PostProcessingPipeline`] are, in the
+/// order they come in. [`PostProcessingPipeline::with`] makes sure that no
+/// effect gets colors of a later stage than it expects.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColorStage {
+	/// Linear and unbounded, as the renderer made them
+	Hdr,
+	/// Linear and in [0; 1], after the [`ToneMapping`]
+	Display,
+	/// Encoded for the target, after the [`GammaCorrection`]
+	Encoded,
+}
 
 /// Shader API:\
 /// `fn post_processing_pipeline(coord: vec2f, color: vec4f) -> vec4f`
+///
+/// Every effect's function is renamed to `pp_effect_<index>_<name>` and called
+/// from a generated `pp_dispatch()`. Which effects run and in which order is
+/// read from a [`PostProcessingChain`] uniform, so changing it doesn't need the
+/// shader to be rebuilt (see [`controlled_by`](Self::controlled_by)).
 #[derive(Default)]
-pub struct PostProcessingPipeline(Vec<Box<dyn PostProcessingEffect>>);
+pub struct PostProcessingPipeline {
+	effects: Vec<Box<dyn PostProcessingEffect>>,
+	counters: Option<Sarc<Buffer>>,
+	truncate_after: Option<usize>,
+	chain_buffer: Option<Sarc<Buffer>>,
+	legacy_chaining: bool,
+}
 
 impl PostProcessingPipeline {
 	pub fn empty() -> Self {
 		Self::default()
 	}
 
+	/// Add an effect at the end of the pipeline. The effects run in the order
+	/// they are added (unless [`controlled_by`](Self::controlled_by) a chain
+	/// that says otherwise), so the usual order is:
+	/// - the effects on the HDR colors, e.g. [`Bloom`]
+	/// - the [`ToneMapping`], which brings them into [0; 1]
+	/// - the effects on the display colors, e.g. [`ColorGrade`]
+	/// - the [`GammaCorrection`], unless the target is sRGB and encodes the
+	///   colors itself, like the window does
+	/// - the [`Dither`], right before the colors are quantized
+	///
+	/// Panics if the effect expects colors of an earlier [`ColorStage`] than
+	/// the effects before it give, e.g. a [`Bloom`] after the tone mapping.
+	/// Effects without a stage can go anywhere.
 	pub fn with(mut self, effect: impl PostProcessingEffect + 'static) -> Self {
-		self.0.push(Box::new(effect));
+		if let (Some(input), Some(stage)) = (effect.input_stage(), self.stage()) {
+			assert!(
+				input >= stage,
+				"The {} expects {:?} colors, but the effects before it already give {:?} colors",
+				effect.name(),
+				input,
+				stage
+			);
+		}
+
+		self.effects.push(Box::new(effect));
 		self
 	}
-}
 
-impl ShaderFragment for PostProcessingPipeline {
-	fn shader(&self) -> Shader {
-		// Set up the pipeline function
-		let mut builder = ShaderBuilder::new();
-		builder.include_path("post_processing/pipeline.wgsl");
+	/// The latest [`ColorStage`] the effects bring the colors to, if any of
+	/// them has one
+	pub fn stage(&self) -> Option<ColorStage> {
+		self.effects.iter().filter_map(|effect| effect.output_stage()).max()
+	}
+
+	/// Count how many times every effect runs, in an
+	/// [`AtomicCounter`](crate::libs::buffer::atomic_counter::AtomicCounter)
+	/// buffer with one counter per effect.
+	///
+	/// The buffer should be spawned with `atomic_counter::spawn_counter` so that
+	/// it is cleared every frame, and can be read with `dump_buffer`.
+	pub fn instrumented(mut self, counters: Sarc<Buffer>) -> Self {
+		self.counters = Some(counters);
+		self
+	}
+
+	/// Only run the first `count` effects. Timing every truncated variant of a
+	/// pipeline with the GPU timers gives the marginal cost of each effect.
+	pub fn truncated(mut self, count: usize) -> Self {
+		self.truncate_after = Some(count);
+		self
+	}
+
+	/// Read the chain from a uniform buffer holding a [`PostProcessingChain`],
+	/// so that effects can be toggled and reordered by uploading a new one.
+	/// Without it, the chain is fixed to [`default_chain`](Self::default_chain).
+	pub fn controlled_by(mut self, chain_buffer: Sarc<Buffer>) -> Self {
+		self.chain_buffer = Some(chain_buffer);
+		self
+	}
+
+	/// Control the chain with a [`PostProcessingEffects`] resource, so that the
+	/// effects can be turned on and off and moved around while the app runs,
+	/// with the params, the `post_effects` console command or
+	/// [`Action::TogglePostEffect`]. The chain is spawned as an auto-updated
+	/// uniform, so the shader isn't rebuilt and the output textures stay the
+	/// same. Add the effects first, the ones added after aren't in the
+	/// resource. Needs the GPU plugin.
+	pub fn tweakable(self, app: &mut App) -> Self {
+		let gpu = app.world.resource::<Gpu>();
+
+		let chain = self.default_chain();
+		let chain_buffer = Sarc::new(UniformBuffer::raw_buffer_from_data(gpu, &chain, None));
+		buffer::spawn_buffer(app, chain, chain_buffer.clone());
+
+		let effects = PostProcessingEffects::new(&self);
+		let names = effects
+			.effects()
+			.iter()
+			.map(|effect| effect.name.clone())
+			.collect::<Vec<_>>();
+		app.world.insert_resource(effects);
+
+		let mut registry = params::registry(app);
+		for (index, name) in names.into_iter().enumerate() {
+			registry.register_bool(
+				format!("post_processing.{}", name),
+				format!("Whether the {} runs", name),
+				move |world| Ok(world.resource::<PostProcessingEffects>().is_enabled(index)),
+				move |world, enabled| {
+					world
+						.resource_mut::<PostProcessingEffects>()
+						.set_enabled(index, enabled);
+					Ok(())
+				},
+			);
+		}
+
+		console::register_command(
+			app,
+			"post_effects",
+			"post_effects [list | move <name> <position>]: List the post processing effects in the order they run, or move one",
+			post_effects,
+		);
+
+		app.add_systems(
+			Update,
+			(toggle_post_effects.run_if(is_console_closed), update_chain).chain(),
+		);
+
+		self.controlled_by(chain_buffer)
+	}
+
+	/// Call the effects directly one after the other, with obfuscated names,
+	/// like the pipeline used to. Only kept to compare against the dispatch, the
+	/// chain can't be changed without rebuilding the shader.
+	pub fn legacy_chaining(mut self) -> Self {
+		self.legacy_chaining = true;
+		self
+	}
+
+	pub fn len(&self) -> usize {
+		self.effects.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.effects.is_empty()
+	}
+
+	/// The textures of all the effects, and the sources of those that sample
+	/// them, for the renderer's
+	/// [`output_textures`](crate::libs::shader_fragment::Renderer::output_textures)
+	pub fn output_textures(&self, resolution: Resolution) -> Vec<(String, TexDescriptor)> {
+		let sources = self.sampling_effects().map(|i| {
+			(
+				format!("pp_source_{}", i),
+				TexDescriptor {
+					label: "Post processing source texture",
+					dimensions: TextureAssetDimensions::D2(resolution.into()),
+					format: TextureFormat::Rgba32Float,
+					usage: Some(TextureUsages::STORAGE_BINDING),
+					aspect: TextureAspect::All,
+				},
+			)
+		});
+
+		self.effects
+			.iter()
+			.flat_map(|effect| effect.output_textures(resolution))
+			.chain(sources)
+			.collect()
+	}
+
+	/// The indices of the effects that sample their source, see
+	/// [`PostProcessingEffect::samples_source`]
+	pub fn sampling_effects(&self) -> impl Iterator<Item = usize> + '_ {
+		self.effects
+			.iter()
+			.enumerate()
+			.filter(|(_, effect)| effect.samples_source())
+			.map(|(i, _)| i)
+	}
+
+	/// All the effects in the order they were added, minus the truncated ones
+	pub fn default_chain(&self) -> PostProcessingChain {
+		let count = self
+			.truncate_after
+			.unwrap_or(self.effects.len())
+			.min(self.effects.len());
+		PostProcessingChain::new(&(0..count as u32).collect::<Vec<_>>())
+	}
+
+	/// The name of the function of the effect at `index` in the pipeline
+	pub fn effect_fn_name(&self, index: usize) -> String {
+		format!("pp_effect_{}_{}", index, self.effects[index].name())
+	}
+
+	/// The generated `pp_dispatch()`, with one case per effect
+	pub fn dispatch_source(&self) -> String {
+		let mut source = String::new();
+
+		source += "fn pp_dispatch(index: u32, ctx: PPContext, coord: vec2f, color: vec4f) -> vec4f {\n";
+		source += "\tswitch index {\n";
+
+		for i in 0..self.effects.len() {
+			source += &format!("\t\tcase {}u: {{\n", i);
+			if self.counters.is_some() {
+				source += &format!("\t\t\tatomicAdd(&pp_effect_counters[{}], 1u);\n", i);
+			}
+			if self.effects[i].samples_source() {
+				source += &format!("\t\t\tpp_store_source_{}(coord, color);\n", i);
+			}
+			source += &format!("\t\t\treturn {}(coord, color, ctx);\n", self.effect_fn_name(i));
+			source += "\t\t}\n";
+		}
+
+		source += "\t\tdefault: {}\n";
+		source += "\t}\n";
+		source += "\treturn color;\n";
+		source += "}\n";
+
+		source
+	}
+
+	/// The functions that store and sample the source of the effect at `index`,
+	/// which it calls as `pp_sample_source()`. Every effect has a texture of
+	/// its own, which can't be passed around, hence one copy per effect.
+	pub fn source_functions(&self, index: usize) -> String {
+		let texture = format!("pp_source_{}", index);
+
+		let mut source = String::new();
+
+		source += &format!("fn pp_store_source_{}(coord: vec2f, color: vec4f) {{\n", index);
+		source += &format!(
+			"\ttextureStore({0}, pp_source_pixel(coord, textureDimensions({0})), color);\n",
+			texture
+		);
+		source += "}\n\n";
+
+		source += &format!("fn pp_sample_source_{}(coord: vec2f) -> vec4f {{\n", index);
+		source += &format!(
+			"\tlet texels = pp_source_texels(coord, textureDimensions({}));\n",
+			texture
+		);
+		source += "\treturn pp_source_bilinear(\n";
+		source += "\t\ttexels,\n";
+		for corner in ["p00", "p10", "p01", "p11"] {
+			source += &format!("\t\ttextureLoad({}, texels.{}),\n", texture, corner);
+		}
+		source += "\t);\n";
+		source += "}\n";
+
+		source
+	}
+
+	/// The effect's shader, calling its source by its index if it samples it
+	fn effect_shader(&self, index: usize, builder: &mut ShaderBuilder) -> Shader {
+		let effect = &self.effects[index];
+		let mut shader = effect.shader();
+
+		if effect.samples_source() {
+			shader.rename_fn("pp_sample_source", &format!("pp_sample_source_{}", index));
+			builder.include_path("post_processing/source.wgsl");
+			builder.include(self.source_functions(index));
+		}
+
+		shader
+	}
+
+	fn legacy_shader(&self, builder: &mut ShaderBuilder) {
+		builder.include_path("post_processing/pipeline_legacy.wgsl");
 
 		let mut pipeline = String::new();
 
+		let count = self.truncate_after.unwrap_or(self.effects.len());
+
 		// Go through all the effects, obfuscate their post_processing_effect() function
 		// to a unique name and add a call to that function to the pipeline
-		for effect in &self.0 {
-			let mut shader = (*effect).shader();
+		for (i, effect) in self.effects.iter().take(count).enumerate() {
+			let mut shader = self.effect_shader(i, builder);
 
 			let func_name = shader.obfuscate_fn("post_processing_effect");
-			pipeline += &format!("color = {}(coord, color);\n", func_name);
+
+			if self.counters.is_some() {
+				pipeline += &format!("atomicAdd(&pp_effect_counters[{}], 1u);\n", i);
+			}
+			if effect.samples_source() {
+				pipeline += &format!("pp_store_source_{}(coord, color);\n", i);
+			}
+			pipeline += &format!("color = {}(coord, color, ctx);\n", func_name);
 
 			builder.include(shader);
 		}
 
 		// Add the pipeline callers
 		builder.define("CALL_EFFECTS", pipeline);
+	}
+
+	fn dispatch_shader(&self, builder: &mut ShaderBuilder) {
+		assert!(
+			self.effects.len() <= PostProcessingChain::MAX_EFFECTS,
+			"A post processing pipeline can't have more than {} effects",
+			PostProcessingChain::MAX_EFFECTS
+		);
+
+		builder.include_path("post_processing/pipeline.wgsl");
+
+		match &self.chain_buffer {
+			Some(buffer) => builder.include_buffer(UniformBufferDescriptor::FromBuffer::<PostProcessingChain, _> {
+				var_name: "pp_chain",
+				buffer: buffer.clone(),
+			}),
+			None => builder.include_value("pp_chain", self.default_chain()),
+		};
+
+		for i in 0..self.effects.len() {
+			let mut shader = self.effect_shader(i, builder);
+			shader.rename_fn("post_processing_effect", &self.effect_fn_name(i));
+			builder.include(shader);
+		}
+
+		builder.include(self.dispatch_source());
+	}
+}
+
+impl ShaderFragment for PostProcessingPipeline {
+	fn shader(&self) -> Shader {
+		let mut builder = ShaderBuilder::new();
+
+		if let Some(counters) = &self.counters {
+			builder.include_buffer(AtomicCounterDescriptor::FromBuffer {
+				var_name: "pp_effect_counters",
+				buffer: counters.clone(),
+			});
+		}
+
+		if self.legacy_chaining {
+			self.legacy_shader(&mut builder);
+		} else {
+			self.dispatch_shader(&mut builder);
+		}
 
 		builder.into()
 	}
+
+	/// The pre-passes of all the effects, whether the chain runs them or not
+	fn pre_passes(&self) -> Vec<PrePassDesc> {
+		self.effects.iter().flat_map(|effect| effect.pre_passes()).collect()
+	}
+}
+
+/// Which effects of a [`PostProcessingPipeline`] run, by index, and in which
+/// order. An effect can be disabled by leaving it out, or run several times.
+#[repr(C)]
+#[derive(ShaderStruct, bevy::Component, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+pub struct PostProcessingChain {
+	pub count: u32,
+	#[shader(skip)]
+	_padding: [u32; 3],
+	/// Packed by 4, since the elements of arrays in uniforms are 16 bytes apart
+	order: [Vec4<u32>; PostProcessingChain::MAX_EFFECTS / 4],
+}
+
+impl PostProcessingChain {
+	pub const MAX_EFFECTS: usize = 16;
+
+	pub fn new(order: &[u32]) -> Self {
+		assert!(order.len() <= Self::MAX_EFFECTS);
+
+		let mut chain = Self {
+			count: order.len() as u32,
+			_padding: [0; 3],
+			order: [Vec4::zero(); Self::MAX_EFFECTS / 4],
+		};
+
+		for (i, index) in order.iter().enumerate() {
+			chain.order[i / 4][i % 4] = *index;
+		}
+
+		chain
+	}
+
+	pub fn order(&self) -> Vec<u32> {
+		(0..self.count as usize).map(|i| self.order[i / 4][i % 4]).collect()
+	}
+}
+
+/// What a [`PostProcessingEffects`] knows of an effect of the pipeline
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EffectDescriptor {
+	/// The effect's [`name`](PostProcessingEffect::name), with its index if
+	/// another effect has the same one
+	pub name: String,
+	pub input_stage: Option<ColorStage>,
+	pub output_stage: Option<ColorStage>,
+}
+
+/// Which effects of a [`tweakable`](PostProcessingPipeline::tweakable)
+/// pipeline run and in which order, turned into the [`PostProcessingChain`]
+/// every time it changes. The effects are always referred to by their index
+/// in the pipeline, wherever they are in the order.
+#[derive(bevy::Resource, Clone, Debug, PartialEq)]
+pub struct PostProcessingEffects {
+	effects: Vec<EffectDescriptor>,
+	/// All the effects, enabled or not
+	order: Vec<usize>,
+	enabled: Vec<bool>,
+}
+
+impl PostProcessingEffects {
+	/// All the effects of the pipeline in the order they were added, with the
+	/// truncated ones disabled
+	pub fn new(pipeline: &PostProcessingPipeline) -> Self {
+		let running = pipeline.default_chain().count as usize;
+
+		let mut effects = Vec::<EffectDescriptor>::new();
+		for (index, effect) in pipeline.effects.iter().enumerate() {
+			let mut name = effect.name();
+			if effects.iter().any(|other| other.name == name) {
+				name = format!("{}_{}", name, index);
+			}
+
+			effects.push(EffectDescriptor {
+				name,
+				input_stage: effect.input_stage(),
+				output_stage: effect.output_stage(),
+			});
+		}
+
+		Self {
+			order: (0..effects.len()).collect(),
+			enabled: (0..effects.len()).map(|index| index < running).collect(),
+			effects,
+		}
+	}
+
+	/// By index in the pipeline
+	pub fn effects(&self) -> &[EffectDescriptor] {
+		&self.effects
+	}
+
+	pub fn index_of(&self, name: &str) -> Option<usize> {
+		self.effects.iter().position(|effect| effect.name == name)
+	}
+
+	/// The indices of all the effects in the order they run, enabled or not
+	pub fn order(&self) -> &[usize] {
+		&self.order
+	}
+
+	pub fn is_enabled(&self, index: usize) -> bool {
+		self.enabled.get(index).copied().unwrap_or(false)
+	}
+
+	pub fn set_enabled(&mut self, index: usize, enabled: bool) {
+		if let Some(slot) = self.enabled.get_mut(index) {
+			*slot = enabled;
+		}
+	}
+
+	/// Returns whether the effect is now enabled
+	pub fn toggle(&mut self, index: usize) -> bool {
+		self.set_enabled(index, !self.is_enabled(index));
+		self.is_enabled(index)
+	}
+
+	/// Move the effect at `index` in the pipeline to `position` in the order.
+	///
+	/// Fails if that would give an effect colors of a later [`ColorStage`]
+	/// than it expects, like [`PostProcessingPipeline::with`] would panic. The
+	/// disabled effects count too, so that enabling them can't break the chain.
+	pub fn move_to(&mut self, index: usize, position: usize) -> Result<()> {
+		let current = self
+			.order
+			.iter()
+			.position(|&other| other == index)
+			.context("There's no effect with that index")?;
+		if position >= self.order.len() {
+			bail!("There are only {} effects", self.order.len());
+		}
+
+		let mut order = self.order.clone();
+		order.remove(current);
+		order.insert(position, index);
+
+		let mut stage = None;
+		for &other in &order {
+			let effect = &self.effects[other];
+			if let (Some(input), Some(stage)) = (effect.input_stage, stage) {
+				if input < stage {
+					bail!(
+						"The {} expects {:?} colors, but the effects before it already give {:?} colors",
+						effect.name,
+						input,
+						stage
+					);
+				}
+			}
+			stage = stage.max(effect.output_stage);
+		}
+
+		self.order = order;
+		Ok(())
+	}
+
+	/// The enabled effects in order
+	pub fn chain(&self) -> PostProcessingChain {
+		let order = self
+			.order
+			.iter()
+			.filter(|&&index| self.enabled[index])
+			.map(|&index| index as u32)
+			.collect::<Vec<_>>();
+		PostProcessingChain::new(&order)
+	}
+}
+
+fn update_chain(effects: Res<PostProcessingEffects>, mut chain: Query<&mut PostProcessingChain>) {
+	if !effects.is_changed() {
+		return;
+	}
+
+	let new_chain = effects.chain();
+	for mut chain in chain.iter_mut() {
+		// Not to re-upload it every frame for nothing
+		if *chain != new_chain {
+			*chain = new_chain;
+		}
+	}
+}
+
+fn toggle_post_effects(
+	mut effects: ResMut<PostProcessingEffects>,
+	mut keyboard_events: EventReader<KeyboardInputEvent>,
+	key_bindings: Res<KeyBindings>,
+) {
+	let events = keyboard_events.read().collect::<Vec<_>>();
+
+	let pressed = |slot: &u8| key_bindings.has_pressed(Action::TogglePostEffect(*slot), events.iter().copied());
+
+	for slot in (0..Action::POST_EFFECT_SLOTS).filter(pressed) {
+		let index = slot as usize;
+		if index >= effects.effects().len() {
+			continue;
+		}
+
+		let enabled = effects.toggle(index);
+		info!(
+			"{}: {}",
+			effects.effects()[index].name,
+			if enabled { "on" } else { "off" }
+		);
+	}
+}
+
+fn post_effects(world: &mut World, args: &[String]) -> Result<String> {
+	let mut effects = world.resource_mut::<PostProcessingEffects>();
+
+	match args {
+		[] => {}
+		[list] if list == "list" => {}
+		[mv, name, position] if mv == "move" => {
+			let index = effects.index_of(name).context("There's no effect with that name")?;
+			let position = position.parse().context("Expected a number for the position")?;
+			effects.move_to(index, position)?;
+		}
+		_ => bail!("Usage: post_effects [list | move <name> <position>]"),
+	}
+
+	let lines = effects
+		.order()
+		.iter()
+		.enumerate()
+		.map(|(position, &index)| {
+			format!(
+				"{}: {} (key {}){}",
+				position,
+				effects.effects()[index].name,
+				index + 1,
+				if effects.is_enabled(index) { "" } else { " (disabled)" }
+			)
+		})
+		.collect::<Vec<_>>();
+
+	Ok(lines.join("\n"))
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Brings the colors of a physically based renderer, which go way above 1,
+/// into [0; 1] with a [`ToneMapOperator`], after scaling them by the exposure.
+///
+/// Should come after the effects that work on the HDR colors (like the
+/// [`Bloom`]) and before the ones that expect display colors (like the
+/// [`ColorGrade`]), see [`PostProcessingPipeline::with`]. The colors stay
+/// linear, the gamma is still to be done.
+#[derive(Default)]
+pub struct ToneMapping {
+	pub settings: ToneMappingSettings,
+
+	/// See [`tweakable`](Self::tweakable), the settings are fixed without it
+	settings_buffer: Option<Sarc<Buffer>>,
+}
+
+impl ToneMapping {
+	/// How many stops [`Action::ExposureUp`] and [`Action::ExposureDown`] change
+	/// the exposure by
+	pub const EXPOSURE_STEP: f32 = 0.5;
+
+	pub fn new(settings: ToneMappingSettings) -> Self {
+		Self {
+			settings,
+			settings_buffer: None,
+		}
+	}
+
+	/// Spawn the settings as an auto-updated [`ToneMappingSettings`] uniform,
+	/// so that they can be changed while the app runs, with the params or with
+	/// [`Action::ExposureUp`] and [`Action::ExposureDown`]. Needs the GPU plugin.
+	pub fn tweakable(mut self, app: &mut App) -> Self {
+		let gpu = app.world.resource::<Gpu>();
+
+		let settings_buffer = Sarc::new(UniformBuffer::raw_buffer_from_data(gpu, &self.settings, None));
+		buffer::spawn_buffer(app, self.settings, settings_buffer.clone());
+
+		params::registry(app)
+			.register_float(
+				"tone_mapping.exposure",
+				"In stops, the colors are multiplied by 2^exposure before being tone mapped",
+				-16.0..=16.0,
+				|world| Ok(tone_mapping_settings(world)?.exposure),
+				|world, exposure| {
+					tone_mapping_settings(world)?.exposure = exposure;
+					Ok(())
+				},
+			)
+			.register_int(
+				"tone_mapping.operator",
+				"0 for Reinhard, 1 for ACES, 2 for AgX, 3 for Khronos PBR Neutral",
+				0..=ToneMapOperator::ALL.len() as i64 - 1,
+				|world| Ok(tone_mapping_settings(world)?.operator as i64),
+				|world, operator| {
+					tone_mapping_settings(world)?.operator = operator as u32;
+					Ok(())
+				},
+			);
+
+		app.add_systems(Update, adjust_exposure.run_if(is_console_closed));
+
+		self.settings_buffer = Some(settings_buffer);
+		self
+	}
+}
+
+impl PostProcessingEffect for ToneMapping {
+	fn input_stage(&self) -> Option<ColorStage> {
+		Some(ColorStage::Hdr)
+	}
+
+	fn output_stage(&self) -> Option<ColorStage> {
+		Some(ColorStage::Display)
+	}
+}
+
+impl ShaderFragment for ToneMapping {
+	fn shader(&self) -> Shader {
+		let mut builder = ShaderBuilder::new();
+		builder.include_path("/post_processing/tone_mapping.wgsl");
+
+		match &self.settings_buffer {
+			Some(buffer) => builder.include_buffer(UniformBufferDescriptor::FromBuffer::<ToneMappingSettings, _> {
+				var_name: "tone_mapping",
+				buffer: buffer.clone(),
+			}),
+			None => builder.include_value("tone_mapping", self.settings),
+		};
+
+		builder.into()
+	}
+}
+
+/// How the exposed colors are brought into [0; 1], the values are the ones of
+/// the `TONE_MAP_*` constants in `tone_mapping.wgsl`
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ToneMapOperator {
+	/// `c / (1 + c)` per channel, never quite reaches white
+	Reinhard = 0,
+	/// Narkowicz's fit of the ACES filmic curve, contrasty and saturated
+	Aces = 1,
+	/// Troy Sobotka's AgX, through Benjamin Wrensch's fit. Bright colors go to
+	/// white instead of clipping to a saturated hue.
+	#[default]
+	Agx = 2,
+	/// Khronos PBR Neutral, which keeps the colors below ~0.8 as they are, for
+	/// when they should look like the base colors of the materials
+	Neutral = 3,
+}
+
+impl ToneMapOperator {
+	pub const ALL: [Self; 4] = [Self::Reinhard, Self::Aces, Self::Agx, Self::Neutral];
+
+	pub fn from_index(index: u32) -> Option<Self> {
+		Self::ALL.get(index as usize).copied()
+	}
+
+	/// Same curves as `tone_mapping.wgsl`, for a gray of this (exposed) value.
+	/// The matrices around AgX about leave the grays alone, so only its curve is
+	/// left.
+	pub fn map_gray(self, x: f32) -> f32 {
+		let x = x.max(0.0);
+
+		let mapped = match self {
+			Self::Reinhard => x / (1.0 + x),
+			Self::Aces => {
+				let x = x * 0.6;
+				(x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+			}
+			Self::Agx => {
+				let (min_ev, max_ev) = (-12.47393, 4.026069);
+				let x = (x.max(1e-10).log2().clamp(min_ev, max_ev) - min_ev) / (max_ev - min_ev);
+
+				let (x2, x4) = (x * x, x * x * x * x);
+				let x =
+					15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232;
+				x.max(0.0).powf(2.2)
+			}
+			Self::Neutral => {
+				let start_compression = 0.8 - 0.04;
+				let offset = if x < 0.08 { x - 6.25 * x * x } else { 0.04 };
+				let x = x - offset;
+				if x < start_compression {
+					x
+				} else {
+					// The desaturation doesn't change a gray
+					let d = 1.0 - start_compression;
+					1.0 - d * d / (x + d - start_compression)
+				}
+			}
+		};
+
+		mapped.clamp(0.0, 1.0)
+	}
+}
+
+/// The `tone_mapping` uniform. When the [`ToneMapping`] is
+/// [`tweakable`](ToneMapping::tweakable), changing this component changes the
+/// uniform.
+#[repr(C)]
+#[derive(ShaderStruct, bevy::Component, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+pub struct ToneMappingSettings {
+	/// In stops, the colors are multiplied by 2^exposure before being tone
+	/// mapped
+	pub exposure: f32,
+	/// A [`ToneMapOperator`], the shader leaves the colors as they are (but
+	/// clamped) if it isn't one
+	pub operator: u32,
+	#[shader(skip)]
+	_padding: [u32; 2],
+}
+
+impl ToneMappingSettings {
+	pub fn new(operator: ToneMapOperator, exposure: f32) -> Self {
+		Self {
+			exposure,
+			operator: operator as u32,
+			_padding: [0; 2],
+		}
+	}
+
+	pub fn operator(&self) -> Option<ToneMapOperator> {
+		ToneMapOperator::from_index(self.operator)
+	}
+
+	/// Where a gray of this value ends up once exposed and tone mapped, see
+	/// [`ToneMapOperator::map_gray`]
+	pub fn map_gray(&self, value: f32) -> f32 {
+		let exposed = value * self.exposure.exp2();
+		match self.operator() {
+			Some(operator) => operator.map_gray(exposed),
+			None => exposed.clamp(0.0, 1.0),
+		}
+	}
+}
+
+impl Default for ToneMappingSettings {
+	fn default() -> Self {
+		Self::new(ToneMapOperator::default(), 0.0)
+	}
+}
+
+fn tone_mapping_settings(world: &mut World) -> Result<Mut<'_, ToneMappingSettings>> {
+	world
+		.query::<&mut ToneMappingSettings>()
+		.get_single_mut(world)
+		.context("The tone mapping isn't tweakable")
+}
+
+fn adjust_exposure(
+	mut settings: Query<&mut ToneMappingSettings>,
+	mut keyboard_events: EventReader<KeyboardInputEvent>,
+	key_bindings: Res<KeyBindings>,
+) {
+	let events = keyboard_events.read().collect::<Vec<_>>();
+	let pressed = |action| key_bindings.has_pressed(action, events.iter().copied());
+
+	let mut step = 0.0;
+	if pressed(Action::ExposureUp) {
+		step += ToneMapping::EXPOSURE_STEP;
+	}
+	if pressed(Action::ExposureDown) {
+		step -= ToneMapping::EXPOSURE_STEP;
+	}
+
+	if step == 0.0 {
+		return;
+	}
+	let Ok(mut settings) = settings.get_single_mut() else {
+		return;
+	};
+
+	settings.exposure += step;
+	info!("Exposure: {:+.1} EV", settings.exposure);
 }
 
 /*
@@ -63,7 +952,16 @@ impl ShaderFragment for PostProcessingPipeline {
 
 pub struct GammaCorrection;
 
-impl PostProcessingEffect for GammaCorrection {}
+impl PostProcessingEffect for GammaCorrection {
+	fn input_stage(&self) -> Option<ColorStage> {
+		Some(ColorStage::Display)
+	}
+
+	fn output_stage(&self) -> Option<ColorStage> {
+		Some(ColorStage::Encoded)
+	}
+}
+
 impl ShaderFragment for GammaCorrection {
 	fn shader(&self) -> Shader {
 		ShaderBuilder::new()
@@ -72,3 +970,560 @@ impl ShaderFragment for GammaCorrection {
 			.into()
 	}
 }
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Triangular-PDF dithering to hide the banding of the 8-bit output.
+/// Should be the last effect of the pipeline, right before quantization.
+pub struct Dither;
+
+impl PostProcessingEffect for Dither {
+	fn input_stage(&self) -> Option<ColorStage> {
+		Some(ColorStage::Encoded)
+	}
+}
+
+impl ShaderFragment for Dither {
+	fn shader(&self) -> Shader {
+		ShaderBuilder::new()
+			.include_path("/post_processing/dither.wgsl")
+			.include_value("dither_levels", 255_f32)
+			.into()
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Darkens the image towards the edges of the screen. It only scales the
+/// colors, so it can go anywhere in the pipeline.
+#[repr(C)]
+#[derive(ShaderStruct, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+pub struct Vignette {
+	/// How much of the colors are gone in the corners, from 0 to 1
+	pub strength: f32,
+	/// Where the darkening starts, 1 being the middle of the edges of the
+	/// screen
+	pub radius: f32,
+	/// 0 follows the shape of the screen, 1 is a circle
+	pub roundness: f32,
+	#[shader(skip)]
+	_padding: u32,
+}
+
+impl Vignette {
+	pub fn new(strength: f32, radius: f32, roundness: f32) -> Self {
+		Self {
+			strength,
+			radius,
+			roundness,
+			_padding: 0,
+		}
+	}
+}
+
+impl Default for Vignette {
+	fn default() -> Self {
+		Self::new(0.5, 0.75, 0.5)
+	}
+}
+
+impl PostProcessingEffect for Vignette {}
+impl ShaderFragment for Vignette {
+	fn shader(&self) -> Shader {
+		ShaderBuilder::new()
+			.include_path("/post_processing/vignette.wgsl")
+			.include_value("vignette", *self)
+			.into()
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Splits the colors towards the edges of the screen like a cheap lens does,
+/// red outwards and blue inwards.
+///
+/// The red and blue are sampled around the pixel (see
+/// [`PostProcessingEffect::samples_source`]), from the frame before.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ChromaticAberration {
+	/// How far apart the colors are, relative to the distance from the middle
+	/// of the screen
+	pub strength: f32,
+}
+
+impl Default for ChromaticAberration {
+	fn default() -> Self {
+		Self { strength: 0.005 }
+	}
+}
+
+impl PostProcessingEffect for ChromaticAberration {
+	fn samples_source(&self) -> bool {
+		true
+	}
+}
+
+impl ShaderFragment for ChromaticAberration {
+	fn shader(&self) -> Shader {
+		ShaderBuilder::new()
+			.include_path("/post_processing/chromatic_aberration.wgsl")
+			.include_value("chromatic_aberration_strength", self.strength)
+			.into()
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// FXAA 3.11, the quality version: finds the edges by the contrast of the
+/// luma, walks along them to find where they end, and blends across them.
+/// Smooths the jagged edges of renderers with a single sample per pixel.
+///
+/// Like the original, it runs on the display colors, after the
+/// [`ToneMapping`] (which [`PostProcessingPipeline::with`] makes sure of) and
+/// before the [`GammaCorrection`]. The neighbours are sampled from the frame
+/// before, see [`PostProcessingEffect::samples_source`]. Can only be in a
+/// pipeline once.
+#[derive(Default)]
+pub struct Fxaa {
+	pub settings: FxaaSettings,
+
+	/// See [`tweakable`](Self::tweakable), the settings are fixed without it
+	settings_buffer: Option<Sarc<Buffer>>,
+}
+
+impl Fxaa {
+	pub fn new(settings: FxaaSettings) -> Self {
+		Self {
+			settings,
+			settings_buffer: None,
+		}
+	}
+
+	/// Spawn the settings as an auto-updated [`FxaaSettings`] uniform, so that
+	/// they can be changed while the app runs, with the params or with
+	/// [`Action::ToggleFxaa`]. Needs the GPU plugin.
+	pub fn tweakable(mut self, app: &mut App) -> Self {
+		let gpu = app.world.resource::<Gpu>();
+
+		let settings_buffer = Sarc::new(UniformBuffer::raw_buffer_from_data(gpu, &self.settings, None));
+		buffer::spawn_buffer(app, self.settings, settings_buffer.clone());
+
+		params::registry(app)
+			.register_bool(
+				"fxaa.enabled",
+				"Whether the edges are anti-aliased",
+				|world| Ok(fxaa_settings(world)?.enabled != 0),
+				|world, enabled| {
+					fxaa_settings(world)?.enabled = enabled as u32;
+					Ok(())
+				},
+			)
+			.register_int(
+				"fxaa.quality",
+				"0 for low, 1 for medium, 2 for high, 3 for extreme, how far along the edges it looks",
+				0..=FxaaQuality::ALL.len() as i64 - 1,
+				|world| Ok(fxaa_settings(world)?.quality as i64),
+				|world, quality| {
+					let quality = FxaaQuality::from_index(quality as u32).context("Not a quality preset")?;
+					fxaa_settings(world)?.set_quality(quality);
+					Ok(())
+				},
+			)
+			.register_float(
+				"fxaa.subpixel",
+				"How much of the aliasing inside the pixels is removed, softer the higher",
+				0.0..=1.0,
+				|world| Ok(fxaa_settings(world)?.subpixel),
+				|world, subpixel| {
+					fxaa_settings(world)?.subpixel = subpixel;
+					Ok(())
+				},
+			);
+
+		app.add_systems(Update, toggle_fxaa.run_if(is_console_closed));
+
+		self.settings_buffer = Some(settings_buffer);
+		self
+	}
+}
+
+impl PostProcessingEffect for Fxaa {
+	fn samples_source(&self) -> bool {
+		true
+	}
+
+	fn input_stage(&self) -> Option<ColorStage> {
+		Some(ColorStage::Display)
+	}
+}
+
+impl ShaderFragment for Fxaa {
+	fn shader(&self) -> Shader {
+		let mut builder = ShaderBuilder::new();
+		builder.include_path("/post_processing/fxaa.wgsl");
+
+		match &self.settings_buffer {
+			Some(buffer) => builder.include_buffer(UniformBufferDescriptor::FromBuffer::<FxaaSettings, _> {
+				var_name: "fxaa",
+				buffer: buffer.clone(),
+			}),
+			None => builder.include_value("fxaa", self.settings),
+		};
+
+		builder.into()
+	}
+}
+
+/// The presets of the original, which only differ by how far along the edges
+/// they look and in how many steps
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum FxaaQuality {
+	/// Preset 10
+	Low = 0,
+	/// Preset 12, the default of the original
+	#[default]
+	Medium = 1,
+	/// Preset 29
+	High = 2,
+	/// Preset 39
+	Extreme = 3,
+}
+
+impl FxaaQuality {
+	pub const ALL: [Self; 4] = [Self::Low, Self::Medium, Self::High, Self::Extreme];
+
+	pub fn from_index(index: u32) -> Option<Self> {
+		Self::ALL.get(index as usize).copied()
+	}
+
+	/// How far every step along the edge goes, in pixels
+	pub fn steps(self) -> &'static [f32] {
+		match self {
+			Self::Low => &[1.5, 3.0, 12.0],
+			Self::Medium => &[1.0, 1.5, 2.0, 4.0, 12.0],
+			Self::High => &[1.0, 1.5, 2.0, 2.0, 2.0, 2.0, 2.0, 2.0, 2.0, 2.0, 4.0, 8.0],
+			Self::Extreme => &[1.0, 1.0, 1.0, 1.0, 1.0, 1.5, 2.0, 2.0, 2.0, 2.0, 4.0, 8.0],
+		}
+	}
+}
+
+/// The `fxaa` uniform. When the [`Fxaa`] is [`tweakable`](Fxaa::tweakable),
+/// changing this component changes the uniform.
+#[repr(C)]
+#[derive(ShaderStruct, bevy::Component, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+pub struct FxaaSettings {
+	/// The colors are left as they are when 0
+	pub enabled: u32,
+	/// A [`FxaaQuality`], see [`set_quality`](Self::set_quality)
+	pub quality: u32,
+	step_count: u32,
+	/// How much of the aliasing inside the pixels is removed, from 0 to 1
+	pub subpixel: f32,
+	/// How much contrast an edge needs, relative to the brightest luma around
+	/// the pixel
+	pub edge_threshold: f32,
+	/// Below this contrast, there's no edge however dark the pixels are
+	pub edge_threshold_min: f32,
+	#[shader(skip)]
+	_padding: [u32; 2],
+	/// The steps of the quality, packed by 4 since the elements of arrays in
+	/// uniforms are 16 bytes apart
+	steps: [Vec4<f32>; 3],
+}
+
+impl FxaaSettings {
+	/// With the defaults of the original
+	pub fn new(quality: FxaaQuality) -> Self {
+		let mut settings = Self {
+			enabled: 1,
+			quality: 0,
+			step_count: 0,
+			subpixel: 0.75,
+			edge_threshold: 0.166,
+			edge_threshold_min: 0.0833,
+			_padding: [0; 2],
+			steps: [Vec4::zero(); 3],
+		};
+		settings.set_quality(quality);
+		settings
+	}
+
+	pub fn set_quality(&mut self, quality: FxaaQuality) {
+		let steps = quality.steps();
+
+		self.quality = quality as u32;
+		self.step_count = steps.len() as u32;
+		self.steps = [Vec4::zero(); 3];
+		for (i, step) in steps.iter().enumerate() {
+			self.steps[i / 4][i % 4] = *step;
+		}
+	}
+
+	pub fn quality(&self) -> Option<FxaaQuality> {
+		FxaaQuality::from_index(self.quality)
+	}
+}
+
+impl Default for FxaaSettings {
+	fn default() -> Self {
+		Self::new(FxaaQuality::default())
+	}
+}
+
+fn fxaa_settings(world: &mut World) -> Result<Mut<'_, FxaaSettings>> {
+	world
+		.query::<&mut FxaaSettings>()
+		.get_single_mut(world)
+		.context("The FXAA isn't tweakable")
+}
+
+fn toggle_fxaa(
+	mut settings: Query<&mut FxaaSettings>,
+	mut keyboard_events: EventReader<KeyboardInputEvent>,
+	key_bindings: Res<KeyBindings>,
+) {
+	if !key_bindings.has_pressed(Action::ToggleFxaa, keyboard_events.read()) {
+		return;
+	}
+	let Ok(mut settings) = settings.get_single_mut() else {
+		return;
+	};
+
+	settings.enabled = (settings.enabled == 0) as u32;
+	info!("FXAA: {}", if settings.enabled != 0 { "on" } else { "off" });
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Grades the colors with the LUT of the
+/// [`ColorGradePlugin`](crate::core::rendering::color_grade::ColorGradePlugin), trilinearly
+/// interpolated. Does nothing until a LUT is loaded.
+///
+/// `.cube` LUTs are made for display colors, so the lookup is done on the sRGB
+/// encoding of the colors and the result is decoded back. It goes after the
+/// tone mapping, where the colors are linear and in [0; 1].
+pub struct ColorGrade {
+	lut: Sarc<Tex>,
+	settings: Sarc<Buffer>,
+}
+
+impl ColorGrade {
+	/// Needs the
+	/// [`ColorGradePlugin`](crate::core::rendering::color_grade::ColorGradePlugin)
+	pub fn new(lut: &ColorGradeLut) -> Self {
+		Self {
+			lut: lut.texture.clone(),
+			settings: lut.settings_buffer.clone(),
+		}
+	}
+}
+
+impl PostProcessingEffect for ColorGrade {
+	fn input_stage(&self) -> Option<ColorStage> {
+		Some(ColorStage::Display)
+	}
+}
+
+impl ShaderFragment for ColorGrade {
+	fn shader(&self) -> Shader {
+		ShaderBuilder::new()
+			.include_path("/post_processing/color_grade.wgsl")
+			.include_buffer(SampledTexture::FromTex {
+				texture_var_name: "color_grade_lut",
+				sampler_var_name: "color_grade_sampler",
+				tex: self.lut.clone(),
+			})
+			.include_buffer(UniformBufferDescriptor::FromBuffer::<ColorGradeSettings, _> {
+				var_name: "color_grade",
+				buffer: self.settings.clone(),
+			})
+			.into()
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Adds a glow around the bright parts of the image: whatever is brighter than
+/// the threshold is blurred and added back on top.
+///
+/// The blur is done by two pre-passes (horizontal then vertical) over textures
+/// of its own, so the glow is a frame behind the image. Can only be in a
+/// pipeline once, the pre-passes would clash.
+#[derive(Default)]
+pub struct Bloom {
+	pub settings: BloomSettings,
+
+	/// See [`tweakable`](Self::tweakable), the settings are fixed without it
+	settings_buffer: Option<Sarc<Buffer>>,
+}
+
+impl Bloom {
+	/// Same as the `@workgroup_size` of the blur passes in `bloom.wgsl`
+	const WORKGROUP_SIZE: Vec2<u32> = Vec2 { x: 8, y: 8 };
+	/// The blur can't reach further than this, in pixels
+	pub const MAX_RADIUS: u32 = 64;
+
+	pub fn new(settings: BloomSettings) -> Self {
+		Self {
+			settings,
+			settings_buffer: None,
+		}
+	}
+
+	/// Spawn the settings as an auto-updated [`BloomSettings`] uniform, so that
+	/// they can be changed while the app runs, e.g. with the params. Needs the
+	/// GPU plugin.
+	pub fn tweakable(mut self, app: &mut App) -> Self {
+		let gpu = app.world.resource::<Gpu>();
+
+		let settings_buffer = Sarc::new(UniformBuffer::raw_buffer_from_data(gpu, &self.settings, None));
+		buffer::spawn_buffer(app, self.settings, settings_buffer.clone());
+
+		params::registry(app)
+			.register_float(
+				"bloom.threshold",
+				"How bright a pixel has to be to glow",
+				0.0..=10.0,
+				|world| Ok(bloom_settings(world)?.threshold),
+				|world, threshold| {
+					bloom_settings(world)?.threshold = threshold;
+					Ok(())
+				},
+			)
+			.register_float(
+				"bloom.intensity",
+				"How much of the glow is added back",
+				0.0..=10.0,
+				|world| Ok(bloom_settings(world)?.intensity),
+				|world, intensity| {
+					bloom_settings(world)?.intensity = intensity;
+					Ok(())
+				},
+			)
+			.register_int(
+				"bloom.radius",
+				"How far the glow reaches, in pixels",
+				0..=Self::MAX_RADIUS as i64,
+				|world| Ok(bloom_settings(world)?.radius as i64),
+				|world, radius| {
+					bloom_settings(world)?.radius = radius as u32;
+					Ok(())
+				},
+			);
+
+		self.settings_buffer = Some(settings_buffer);
+		self
+	}
+}
+
+impl PostProcessingEffect for Bloom {
+	fn input_stage(&self) -> Option<ColorStage> {
+		Some(ColorStage::Hdr)
+	}
+
+	fn output_textures(&self, resolution: Resolution) -> Vec<(String, TexDescriptor)> {
+		let texture = |label| TexDescriptor {
+			label,
+			dimensions: TextureAssetDimensions::D2(resolution.into()),
+			format: TextureFormat::Rgba32Float,
+			usage: Some(TextureUsages::STORAGE_BINDING),
+			aspect: TextureAspect::All,
+		};
+
+		vec![
+			("bloom_bright".to_owned(), texture("Bloom bright texture")),
+			("bloom_blur_temp".to_owned(), texture("Bloom horizontal blur texture")),
+			("bloom_blurred".to_owned(), texture("Bloom blurred texture")),
+		]
+	}
+}
+
+impl ShaderFragment for Bloom {
+	fn shader(&self) -> Shader {
+		let mut builder = ShaderBuilder::new();
+		builder.include_path("/post_processing/bloom.wgsl");
+
+		match &self.settings_buffer {
+			Some(buffer) => builder.include_buffer(UniformBufferDescriptor::FromBuffer::<BloomSettings, _> {
+				var_name: "bloom",
+				buffer: buffer.clone(),
+			}),
+			None => builder.include_value("bloom", self.settings),
+		};
+
+		builder.into()
+	}
+
+	fn pre_passes(&self) -> Vec<PrePassDesc> {
+		let pass = |entry_point: &str, reads: &str, writes: &str| PrePassDesc {
+			entry_point: entry_point.to_owned(),
+			dispatch: PrePassDispatch::Resolution(Self::WORKGROUP_SIZE),
+			reads: vec!["bloom".to_owned(), reads.to_owned()],
+			writes: vec![writes.to_owned()],
+		};
+
+		vec![
+			pass("bloom_blur_horizontal", "bloom_bright", "bloom_blur_temp"),
+			pass("bloom_blur_vertical", "bloom_blur_temp", "bloom_blurred"),
+		]
+	}
+}
+
+/// The `bloom` uniform. When the [`Bloom`] is
+/// [`tweakable`](Bloom::tweakable), changing this component changes the
+/// uniform.
+#[repr(C)]
+#[derive(ShaderStruct, bevy::Component, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+pub struct BloomSettings {
+	/// How bright a pixel has to be to glow, the part above it is what glows
+	pub threshold: f32,
+	/// How much of the glow is added back
+	pub intensity: f32,
+	/// How far the glow reaches, in pixels, at most [`Bloom::MAX_RADIUS`]
+	pub radius: u32,
+	#[shader(skip)]
+	_padding: u32,
+}
+
+impl Default for BloomSettings {
+	fn default() -> Self {
+		Self {
+			threshold: 1.0,
+			intensity: 0.5,
+			radius: 16,
+			_padding: 0,
+		}
+	}
+}
+
+fn bloom_settings(world: &mut World) -> Result<Mut<'_, BloomSettings>> {
+	world
+		.query::<&mut BloomSettings>()
+		.get_single_mut(world)
+		.context("The bloom isn't tweakable")
+}
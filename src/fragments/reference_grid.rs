@@ -0,0 +1,68 @@
+use brainrot::vek::Rgba;
+use pbr_tracer_derive::ShaderStruct;
+
+use crate::libs::{
+	buffer::ShaderType,
+	shader::{Shader, ShaderBuilder},
+	shader_fragment::ShaderFragment,
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Shader API:\
+/// `fn composite_reference_grid(ray_origin: vec3f, ray_dir: vec3f, pixel_size: vec2u, scene_distance: f32, color: vec4f) -> vec4f`
+///
+/// An infinite grid on a horizontal plane, drawn over the shaded color where
+/// nothing in the scene is closer. It isn't part of the scene, so the
+/// intersector never hits it and it doesn't cast shadows or show up in
+/// reflections. It fades out with distance, leaving the background as is.
+///
+/// Can be hidden at runtime with
+/// [`RenderSettings::show_reference_grid`](crate::core::rendering::globals::RenderSettings).
+#[repr(C)]
+#[derive(ShaderStruct, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+pub struct ReferenceGrid {
+	/// The alpha is the opacity of the lines
+	pub minor_color: Rgba<f32>,
+	pub major_color: Rgba<f32>,
+	/// The y of the plane
+	pub height: f32,
+	/// The world distance between two minor lines
+	pub minor_spacing: f32,
+	/// How many minor cells a major one spans
+	pub major_every: u32,
+	/// In pixels
+	pub line_width: f32,
+	/// The distance from the camera at which the grid is completely gone
+	pub fade_distance: f32,
+	#[shader(skip)]
+	_padding: [u32; 3],
+}
+
+impl Default for ReferenceGrid {
+	fn default() -> Self {
+		Self {
+			minor_color: Rgba::new(0.5, 0.5, 0.5, 0.4),
+			major_color: Rgba::new(0.8, 0.8, 0.8, 0.7),
+			height: 0.0,
+			minor_spacing: 1.0,
+			major_every: 10,
+			line_width: 1.0,
+			fade_distance: 100.0,
+			_padding: [0; 3],
+		}
+	}
+}
+
+impl ShaderFragment for ReferenceGrid {
+	fn shader(&self) -> Shader {
+		ShaderBuilder::new()
+			.include_path("reference_grid/reference_grid.wgsl")
+			.include_value("reference_grid", *self)
+			.into()
+	}
+}
@@ -0,0 +1,127 @@
+use brainrot::vek::{Extent2, Vec2, Vec3};
+use wgpu::{StorageTextureAccess, TextureFormat};
+
+use crate::{
+	libs::{
+		buffer::storage_texture_buffer::StorageTexture,
+		shader::{Shader, ShaderBuilder},
+		shader_fragment::ShaderFragment,
+	},
+	TextureAssets,
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+// CPU mirror of shader/sampling/sampling.wgsl and shader/sampling/rng.wgsl,
+// which the fragments use for their per-pixel randomness. Both have to be
+// changed together.
+
+/// How finely the image is split for the stable pixel seeds, on both axes
+pub const SEED_GRID: u32 = 16384;
+
+/// The random seed of a pixel, `pixel_seed()` in sampling.wgsl.
+///
+/// If `stable`, the pixel's center is snapped to a fixed [`SEED_GRID`] over
+/// the image, so that the same point of the image gets the same seed at any
+/// resolution and noise patterns can be compared across render scales. The
+/// downside is that at very low resolutions, neighbouring pixels land on
+/// nearby grid cells, whose hashes are slightly correlated.
+///
+/// Otherwise the seed comes from the pixel's index, which changes whenever the
+/// resolution does.
+pub fn pixel_seed(pixel: Vec2<u32>, resolution: Extent2<u32>, frame_seed: u32, stable: bool) -> Vec3<u32> {
+	let point = if stable {
+		// (pixel + 0.5) / resolution * grid, in integers like in the shader
+		let resolution = Vec2::new(resolution.w, resolution.h);
+		(pixel * 2 + 1) * SEED_GRID / (resolution * 2)
+	} else {
+		pixel
+	};
+
+	sampling_hash(Vec3::new(point.x, point.y, frame_seed))
+}
+
+/// PCG3D hash, from "Hash Functions for GPU Rendering" (Jarzynski & Olano, 2020)
+pub fn sampling_hash(v: Vec3<u32>) -> Vec3<u32> {
+	let mut v = v.map(|x| x.wrapping_mul(1664525).wrapping_add(1013904223));
+
+	v.x = v.x.wrapping_add(v.y.wrapping_mul(v.z));
+	v.y = v.y.wrapping_add(v.z.wrapping_mul(v.x));
+	v.z = v.z.wrapping_add(v.x.wrapping_mul(v.y));
+
+	v = v.map(|x| x ^ (x >> 16));
+
+	v.x = v.x.wrapping_add(v.y.wrapping_mul(v.z));
+	v.y = v.y.wrapping_add(v.z.wrapping_mul(v.x));
+	v.z = v.z.wrapping_add(v.x.wrapping_mul(v.y));
+
+	v
+}
+
+/// `RngState` of sampling/rng.wgsl, the generator that the fragments share
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RngState {
+	pub state: u32,
+}
+
+impl RngState {
+	/// `rng_init()`, the frame being `frame_info.frame`
+	pub fn new(pixel: Vec2<u32>, frame: u32) -> Self {
+		Self {
+			state: rng_hash(pixel.x.wrapping_add(rng_hash(pixel.y.wrapping_add(rng_hash(frame))))),
+		}
+	}
+
+	pub fn next_u32(&mut self) -> u32 {
+		self.state = self.state.wrapping_mul(747796405).wrapping_add(2891336453);
+		rng_permute(self.state)
+	}
+
+	/// In [0; 1)
+	pub fn next_f32(&mut self) -> f32 {
+		(self.next_u32() >> 8) as f32 / 16777216.0
+	}
+}
+
+/// PCG-RXS-M-XS, from "Hash Functions for GPU Rendering" (Jarzynski & Olano, 2020)
+pub fn rng_hash(v: u32) -> u32 {
+	rng_permute(v.wrapping_mul(747796405).wrapping_add(2891336453))
+}
+
+fn rng_permute(state: u32) -> u32 {
+	let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+	(word >> 22) ^ word
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Shader API:\
+/// `fn blue_noise(pixel: vec2u) -> vec4f`
+///
+/// Four values in [0; 1) per pixel from a tiling blue noise texture, shifted
+/// every frame. Better spread than `rng_next_f32()` for the first few samples
+/// of a pixel, but there are only four of them.
+pub struct BlueNoise;
+
+impl ShaderFragment for BlueNoise {
+	fn shader(&self) -> Shader {
+		ShaderBuilder::new()
+			.include_path("/sampling/blue_noise.wgsl")
+			.include_buffer(StorageTexture::FromImage {
+				var_name: "blue_noise_texture",
+				access: StorageTextureAccess::ReadOnly,
+				image: TextureAssets::get_image("blue_noise.png"),
+				format: TextureFormat::Rgba8Unorm,
+				usage: None,
+			})
+			.into()
+	}
+}
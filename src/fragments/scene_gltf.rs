@@ -0,0 +1,325 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{Context, Result};
+use brainrot::vek::{Mat4, Rgb, Rgba, Vec2, Vec3, Vec4};
+use gltf::{
+	image::Format,
+	khr_lights_punctual::Kind,
+	mesh::{util::ReadIndices, Mode},
+	Material as GltfMaterial, Node,
+};
+use image::{DynamicImage, ImageBuffer, RgbaImage};
+use log::warn;
+
+use super::{
+	light_grid::{LightGrid, PointLight},
+	mesh::{Mesh, MeshIntersector, MeshMaterial},
+	shading::{Material, MaterialLibrary},
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// A glTF 2.0 scene, flattened into a single [`Mesh`] in world space.
+///
+/// Only what the tracer can use is loaded: the triangles, the base color and
+/// metallic-roughness factors, the base color, metallic-roughness and normal
+/// textures and the point and directional lights (`KHR_lights_punctual`).
+/// Skins, animations and spot lights are skipped with a warning, the scene is
+/// loaded in its rest pose.
+#[derive(Clone, Debug)]
+pub struct GltfScene {
+	pub mesh: Mesh,
+	/// The material ids of the mesh index into this. The last one is the default
+	/// material, for the primitives that don't have any.
+	pub materials: Vec<MeshMaterial>,
+	/// The base color textures, referenced by the materials
+	pub textures: Vec<DynamicImage>,
+	/// The same materials for the [`PbrShading`](super::shading::PbrShading)
+	/// and the [`PathTracer`](super::path_tracer::PathTracer), with the same
+	/// ids, along with their metallic-roughness and normal maps. Their base
+	/// color is white, the intersector already gives it.
+	pub library: MaterialLibrary,
+	pub point_lights: Vec<PointLight>,
+	pub directional_lights: Vec<DirectionalLight>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DirectionalLight {
+	/// Where the light is going
+	pub direction: Vec3<f32>,
+	/// The alpha is the intensity
+	pub color: Rgba<f32>,
+}
+
+impl GltfScene {
+	/// A point light without a range stops once it's this dim
+	const MIN_LIGHT_INTENSITY: f32 = 0.01;
+
+	/// Loads a `.gltf` (with its buffers and images next to it) or a `.glb`
+	pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+		let path = path.as_ref();
+		let (document, buffers, images) =
+			gltf::import(path).with_context(|| format!("Couldn't load `{}`", path.display()))?;
+
+		if document.skins().len() > 0 {
+			warn!("`{}` has skins, they are ignored", path.display());
+		}
+		if document.animations().len() > 0 {
+			warn!("`{}` has animations, they are ignored", path.display());
+		}
+
+		let mut scene = Self {
+			mesh: Mesh::default(),
+			materials: Vec::new(),
+			textures: Vec::new(),
+			library: MaterialLibrary::default(),
+			point_lights: Vec::new(),
+			directional_lights: Vec::new(),
+		};
+
+		// Only the images used as a base color become a layer of the intersector's
+		// textures, the others go to the library
+		let mut layers = HashMap::<usize, u32>::new();
+		let mut library_layers = HashMap::<usize, u32>::new();
+		let mut library_materials = Vec::new();
+		for material in document.materials() {
+			let pbr = material.pbr_metallic_roughness();
+
+			let texture = pbr.base_color_texture().map(|info| {
+				warn_tex_coord(&material, info.tex_coord());
+
+				let image = info.texture().source().index();
+				*layers.entry(image).or_insert_with(|| {
+					scene.textures.push(to_dynamic_image(&images[image]));
+					scene.textures.len() as u32 - 1
+				})
+			});
+
+			let mut library_layer = |image: usize| {
+				*library_layers
+					.entry(image)
+					.or_insert_with(|| scene.library.register_texture(to_dynamic_image(&images[image])))
+			};
+
+			let mut library_material = Material::new(Rgb::one(), pbr.metallic_factor(), pbr.roughness_factor());
+			if let Some(info) = pbr.metallic_roughness_texture() {
+				warn_tex_coord(&material, info.tex_coord());
+				library_material =
+					library_material.with_metallic_roughness_texture(library_layer(info.texture().source().index()));
+			}
+			if let Some(info) = material.normal_texture() {
+				warn_tex_coord(&material, info.tex_coord());
+				library_material = library_material.with_normal_texture(library_layer(info.texture().source().index()));
+			}
+			library_materials.push(library_material);
+
+			scene.materials.push(MeshMaterial::new(
+				Rgba::from(pbr.base_color_factor()),
+				pbr.metallic_factor(),
+				pbr.roughness_factor(),
+				texture,
+			));
+		}
+
+		let default_material = scene.materials.len() as u32;
+		let default = MeshMaterial::default();
+		scene.materials.push(default);
+		library_materials.push(Material::new(Rgb::one(), default.metallic, default.roughness));
+
+		// The library starts with its own default material, which the first one replaces
+		for (id, material) in library_materials.into_iter().enumerate() {
+			if id == 0 {
+				scene.library.set(MaterialLibrary::DEFAULT_MATERIAL, material);
+			} else {
+				scene.library.register(material);
+			}
+		}
+
+		let root = document
+			.default_scene()
+			.or_else(|| document.scenes().next())
+			.context("The file doesn't have any scene")?;
+
+		for node in root.nodes() {
+			scene.add_node(&node, Mat4::identity(), &buffers, default_material);
+		}
+
+		Ok(scene.finish())
+	}
+
+	fn add_node(&mut self, node: &Node, parent: Mat4<f32>, buffers: &[gltf::buffer::Data], default_material: u32) {
+		let transform = parent * Mat4::from_col_arrays(node.transform().matrix());
+
+		if let Some(mesh) = node.mesh() {
+			for primitive in mesh.primitives() {
+				if primitive.mode() != Mode::Triangles {
+					warn!(
+						"A primitive of the mesh {:?} is made of {:?}, only triangles are supported",
+						mesh.name(),
+						primitive.mode()
+					);
+					continue;
+				}
+
+				let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+				let Some(positions) = reader.read_positions() else {
+					warn!("A primitive of the mesh {:?} doesn't have positions", mesh.name());
+					continue;
+				};
+
+				let offset = self.mesh.positions.len() as u32;
+				let count = positions.len();
+				self.mesh
+					.positions
+					.extend(positions.map(|p| transform.mul_point(Vec3::from(p))));
+
+				// The normals are all or nothing for the whole mesh, same as for the OBJs
+				let normal_transform = transform.inverted().transposed();
+				match reader.read_normals() {
+					Some(normals) => self
+						.mesh
+						.normals
+						.extend(normals.map(|n| normal_transform.mul_direction(Vec3::from(n)).normalized())),
+					None => self.mesh.normals.extend(std::iter::repeat(Vec3::zero()).take(count)),
+				}
+
+				match reader.read_tex_coords(0) {
+					Some(uvs) => self.mesh.uvs.extend(uvs.into_f32().map(Vec2::from)),
+					None => self.mesh.uvs.extend(std::iter::repeat(Vec2::zero()).take(count)),
+				}
+
+				// All or nothing too, the mesh derives them from the uvs otherwise
+				match reader.read_tangents() {
+					Some(tangents) => self.mesh.tangents.extend(tangents.map(|t| {
+						let tangent = transform.mul_direction(Vec3::new(t[0], t[1], t[2])).normalized();
+						Vec4::new(tangent.x, tangent.y, tangent.z, t[3])
+					})),
+					None => self.mesh.tangents.extend(std::iter::repeat(Vec4::zero()).take(count)),
+				}
+
+				// Non-indexed primitives are a plain list of triangles
+				let indices = match reader.read_indices() {
+					Some(ReadIndices::U8(indices)) => indices.map(u32::from).collect::<Vec<_>>(),
+					Some(ReadIndices::U16(indices)) => indices.map(u32::from).collect(),
+					Some(ReadIndices::U32(indices)) => indices.collect(),
+					None => (0..count as u32).collect(),
+				};
+
+				let material_id = primitive
+					.material()
+					.index()
+					.map_or(default_material, |index| index as u32);
+
+				self.mesh.indices.extend(indices.iter().map(|index| index + offset));
+				self.mesh
+					.material_ids
+					.extend(std::iter::repeat(material_id).take(indices.len() / 3));
+			}
+		}
+
+		if let Some(light) = node.light() {
+			let [r, g, b] = light.color();
+			let color = Rgba::new(r, g, b, light.intensity());
+
+			match light.kind() {
+				Kind::Point => self.point_lights.push(PointLight {
+					position: transform.mul_point(Vec3::zero()),
+					radius: light
+						.range()
+						.unwrap_or_else(|| (light.intensity() / Self::MIN_LIGHT_INTENSITY).sqrt()),
+					color,
+				}),
+				// glTF lights point down their -Z
+				Kind::Directional => self.directional_lights.push(DirectionalLight {
+					direction: transform.mul_direction(-Vec3::unit_z()).normalized(),
+					color,
+				}),
+				Kind::Spot { .. } => warn!("The spot light {:?} isn't supported, skipping it", light.name()),
+			}
+		}
+
+		for child in node.children() {
+			self.add_node(&child, transform, buffers, default_material);
+		}
+	}
+
+	/// The primitives without normals got zeroes, a valid normal never is. Then
+	/// the whole mesh is flat shaded. Same for the tangents, then they're all
+	/// derived.
+	fn finish(mut self) -> Self {
+		if self.mesh.normals.iter().any(|n| *n == Vec3::zero()) {
+			self.mesh.normals.clear();
+		}
+		if self.mesh.tangents.iter().any(|t| *t == Vec4::zero()) {
+			self.mesh.tangents.clear();
+		}
+		self
+	}
+
+	/// A [`LightGrid`] with the point lights, over the bounds of the mesh
+	pub fn light_grid(&self, cells: Vec3<u32>) -> LightGrid {
+		let (bounds_min, bounds_max) = self.mesh.positions.iter().fold(
+			(Vec3::broadcast(f32::MAX), Vec3::broadcast(f32::MIN)),
+			|(min, max), p| (Vec3::partial_min(min, *p), Vec3::partial_max(max, *p)),
+		);
+
+		LightGrid {
+			bounds_min,
+			bounds_max,
+			cells,
+			lights: self.point_lights.clone(),
+		}
+	}
+}
+
+impl MeshIntersector {
+	/// Loads the meshes, materials and textures of a glTF scene, see
+	/// [`GltfScene`]. The lights aren't part of the intersector, use
+	/// [`GltfScene::load`] for them.
+	pub fn from_gltf(path: impl AsRef<Path>) -> Result<Self> {
+		let scene = GltfScene::load(path)?;
+		Ok(Self::new(&scene.mesh).with_materials(scene.materials, scene.textures))
+	}
+}
+
+fn warn_tex_coord(material: &GltfMaterial, tex_coord: u32) {
+	if tex_coord != 0 {
+		warn!(
+			"The material {:?} uses the texture coordinates {}, only the first ones are supported",
+			material.name(),
+			tex_coord
+		);
+	}
+}
+
+/// 16-bit and float images are too precise for the 8-bit texture array, they
+/// are replaced by white
+fn to_dynamic_image(data: &gltf::image::Data) -> DynamicImage {
+	let (width, height) = (data.width, data.height);
+
+	let rgba = match data.format {
+		Format::R8G8B8A8 => data.pixels.clone(),
+		Format::R8G8B8 => data
+			.pixels
+			.chunks_exact(3)
+			.flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+			.collect(),
+		Format::R8G8 => data
+			.pixels
+			.chunks_exact(2)
+			.flat_map(|rg| [rg[0], rg[1], 0, 255])
+			.collect(),
+		Format::R8 => data.pixels.iter().flat_map(|r| [*r, *r, *r, 255]).collect(),
+		format => {
+			warn!("{:?} textures aren't supported, using white instead", format);
+			vec![255; 4 * width as usize * height as usize]
+		}
+	};
+
+	let image: RgbaImage = ImageBuffer::from_raw(width, height, rgba).expect("The image data doesn't match its size");
+	DynamicImage::ImageRgba8(image)
+}
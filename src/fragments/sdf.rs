@@ -0,0 +1,279 @@
+use std::fmt::Write;
+
+use brainrot::vek::{Mat4, Quaternion, Vec3};
+
+use crate::libs::{
+	shader::{Shader, ShaderBuilder},
+	shader_fragment::ShaderFragment,
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Shader API:\
+/// `fn scene_sdf(p: vec3f) -> SdfResult`
+///
+/// A signed distance field built from Rust, e.g. to generate scenes
+/// procedurally. The tree of [`SdfNode`]s is compiled to straight-line WGSL,
+/// one `let` per node, so there's no interpreting going on in the shader.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SdfScene {
+	pub root: SdfNode,
+}
+
+/// A node of an [`SdfScene`]. The shapes are centered on the origin, move them
+/// around with the transforms.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SdfNode {
+	Sphere {
+		radius: f32,
+		material_id: u32,
+	},
+	Cuboid {
+		size: Vec3<f32>,
+		material_id: u32,
+	},
+	/// Lying flat in the XZ plane
+	Torus {
+		radius: f32,
+		thickness: f32,
+		material_id: u32,
+	},
+	/// The plane y = 0, solid below
+	Plane {
+		material_id: u32,
+	},
+
+	/// Only rigid transforms and uniform scaling keep the distances right, so
+	/// that's all the builder methods make
+	Transform {
+		transform: Mat4<f32>,
+		/// How much the transform scales, the child's distances are scaled back by
+		/// it
+		scale: f32,
+		child: Box<SdfNode>,
+	},
+	/// Overrides the material of everything in the child
+	Material {
+		material_id: u32,
+		child: Box<SdfNode>,
+	},
+
+	Union(Box<SdfNode>, Box<SdfNode>),
+	/// `k` is how far the blend reaches
+	SmoothUnion(Box<SdfNode>, Box<SdfNode>, f32),
+	/// The first minus the second
+	Subtract(Box<SdfNode>, Box<SdfNode>),
+	Intersect(Box<SdfNode>, Box<SdfNode>),
+}
+
+impl SdfNode {
+	pub fn sphere(radius: f32) -> Self {
+		Self::Sphere { radius, material_id: 0 }
+	}
+
+	pub fn cuboid(size: Vec3<f32>) -> Self {
+		Self::Cuboid { size, material_id: 0 }
+	}
+
+	pub fn torus(radius: f32, thickness: f32) -> Self {
+		Self::Torus {
+			radius,
+			thickness,
+			material_id: 0,
+		}
+	}
+
+	pub fn plane() -> Self {
+		Self::Plane { material_id: 0 }
+	}
+
+	pub fn translated(self, offset: Vec3<f32>) -> Self {
+		self.transformed(Mat4::translation_3d(offset), 1.0)
+	}
+
+	pub fn rotated(self, rotation: Quaternion<f32>) -> Self {
+		self.transformed(Mat4::from(rotation), 1.0)
+	}
+
+	pub fn scaled(self, scale: f32) -> Self {
+		self.transformed(Mat4::scaling_3d(scale), scale)
+	}
+
+	fn transformed(self, transform: Mat4<f32>, scale: f32) -> Self {
+		Self::Transform {
+			transform,
+			scale,
+			child: Box::new(self),
+		}
+	}
+
+	pub fn material(self, material_id: u32) -> Self {
+		Self::Material {
+			material_id,
+			child: Box::new(self),
+		}
+	}
+
+	pub fn union(self, other: Self) -> Self {
+		Self::Union(Box::new(self), Box::new(other))
+	}
+
+	pub fn smooth_union(self, other: Self, k: f32) -> Self {
+		Self::SmoothUnion(Box::new(self), Box::new(other), k)
+	}
+
+	pub fn subtract(self, other: Self) -> Self {
+		Self::Subtract(Box::new(self), Box::new(other))
+	}
+
+	pub fn intersect(self, other: Self) -> Self {
+		Self::Intersect(Box::new(self), Box::new(other))
+	}
+}
+
+impl Default for SdfScene {
+	/// The two spheres that were hard-coded in raymarch.wgsl
+	fn default() -> Self {
+		Self::new(SdfNode::sphere(1.0).union(SdfNode::sphere(2.0).translated(Vec3::new(2.0, 3.0, 1.0))))
+	}
+}
+
+impl SdfScene {
+	pub fn new(root: SdfNode) -> Self {
+		Self { root }
+	}
+
+	/// The generated `scene_sdf()`, needs the definitions of sdf.wgsl
+	pub fn to_wgsl(&self) -> String {
+		let mut body = String::new();
+		let mut counter = 0;
+		let result = emit(&self.root, "p", &mut body, &mut counter);
+
+		format!(
+			"fn scene_sdf(p: vec3f) -> SdfResult {{\n{}\treturn {};\n}}\n",
+			body, result
+		)
+	}
+}
+
+impl ShaderFragment for SdfScene {
+	fn shader(&self) -> Shader {
+		ShaderBuilder::new()
+			.include_path("/sdf/sdf.wgsl")
+			.include(self.to_wgsl())
+			.into()
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Write the lines computing the node at the point `p`, and return the name of
+/// the `SdfResult` they end up in
+fn emit(node: &SdfNode, p: &str, body: &mut String, counter: &mut usize) -> String {
+	let (name, value) = match node {
+		SdfNode::Sphere { radius, material_id } => (
+			next_name("r", counter),
+			format!("SdfResult(sphere({}, {}), {}u)", p, float(*radius), material_id),
+		),
+		SdfNode::Cuboid { size, material_id } => (
+			next_name("r", counter),
+			format!("SdfResult(bbox({}, {}), {}u)", p, vec3(*size), material_id),
+		),
+		SdfNode::Torus {
+			radius,
+			thickness,
+			material_id,
+		} => (
+			next_name("r", counter),
+			format!(
+				"SdfResult(torus({}, {}, {}), {}u)",
+				p,
+				float(*radius),
+				float(*thickness),
+				material_id
+			),
+		),
+		SdfNode::Plane { material_id } => (next_name("r", counter), format!("SdfResult({}.y, {}u)", p, material_id)),
+
+		SdfNode::Transform {
+			transform,
+			scale,
+			child,
+		} => {
+			// The child is evaluated in its own space, so the point goes through the
+			// inverse
+			let local = next_name("p", counter);
+			let columns = transform.inverted().into_col_array().map(float).join(", ");
+			writeln!(
+				body,
+				"\tlet {} = (mat4x4f({}) * vec4f({}, 1.0)).xyz;",
+				local, columns, p
+			)
+			.unwrap();
+
+			let child = emit(child, &local, body, counter);
+			(
+				next_name("r", counter),
+				format!(
+					"SdfResult({}.distance * {}, {}.material_id)",
+					child,
+					float(*scale),
+					child
+				),
+			)
+		}
+		SdfNode::Material { material_id, child } => {
+			let child = emit(child, p, body, counter);
+			(
+				next_name("r", counter),
+				format!("SdfResult({}.distance, {}u)", child, material_id),
+			)
+		}
+
+		SdfNode::Union(a, b) => {
+			let (a, b) = (emit(a, p, body, counter), emit(b, p, body, counter));
+			(next_name("r", counter), format!("sdf_union({}, {})", a, b))
+		}
+		SdfNode::SmoothUnion(a, b, k) => {
+			let (a, b) = (emit(a, p, body, counter), emit(b, p, body, counter));
+			(
+				next_name("r", counter),
+				format!("sdf_smooth_union({}, {}, {})", a, b, float(*k)),
+			)
+		}
+		SdfNode::Subtract(a, b) => {
+			let (a, b) = (emit(a, p, body, counter), emit(b, p, body, counter));
+			(next_name("r", counter), format!("sdf_subtract({}, {})", a, b))
+		}
+		SdfNode::Intersect(a, b) => {
+			let (a, b) = (emit(a, p, body, counter), emit(b, p, body, counter));
+			(next_name("r", counter), format!("sdf_intersect({}, {})", a, b))
+		}
+	};
+
+	writeln!(body, "\tlet {} = {};", name, value).unwrap();
+	name
+}
+
+fn next_name(prefix: &str, counter: &mut usize) -> String {
+	*counter += 1;
+	format!("{}{}", prefix, counter)
+}
+
+/// Debug always has a decimal point or an exponent, so it stays a float in
+/// WGSL
+fn float(x: f32) -> String {
+	format!("{:?}", x)
+}
+
+fn vec3(v: Vec3<f32>) -> String {
+	format!("vec3f({}, {}, {})", float(v.x), float(v.y), float(v.z))
+}
@@ -1,12 +1,24 @@
-use brainrot::vec3;
-use wgpu::{StorageTextureAccess, TextureFormat};
+use brainrot::{
+	bevy::{self},
+	vek::Rgb,
+};
+use image::{DynamicImage, Rgba, RgbaImage};
+use pbr_tracer_derive::ShaderStruct;
+use wgpu::{FilterMode, StorageTextureAccess, TextureFormat};
 
 use super::mpr::Shading;
 use crate::{
+	core::rendering::environment::EnvironmentMaps,
 	libs::{
-		buffer::storage_texture_buffer::StorageTexture,
+		buffer::{
+			sampled_texture_buffer::SampledTexture,
+			storage_buffer::{StorageArray, StorageBufferDescriptor},
+			storage_texture_buffer::StorageTexture,
+			ShaderType,
+		},
 		shader::{Shader, ShaderBuilder},
 		shader_fragment::ShaderFragment,
+		texture::SamplerEdges,
 	},
 	TextureAssets,
 };
@@ -17,18 +29,34 @@ use crate::{
 --------------------------------------------------------------------------------
 */
 
-pub struct SimpleDiffuse;
+/// Lambert lit by the [`Lights`](crate::core::rendering::lights::Lights),
+/// with shadows
+pub struct SimpleDiffuse {
+	/// How wide the lights are for the soft shadows, see [`PbrShading`]
+	pub shadow_softness: f32,
+}
+
+impl Default for SimpleDiffuse {
+	fn default() -> Self {
+		Self {
+			shadow_softness: DEFAULT_SHADOW_SOFTNESS,
+		}
+	}
+}
 
 impl Shading for SimpleDiffuse {}
 impl ShaderFragment for SimpleDiffuse {
 	fn shader(&self) -> Shader {
 		ShaderBuilder::new()
 			.include_path("/shading/simple_diffuse.wgsl")
-			.include_value("sun_direction", vec3!(1.0, -1.0, 1.0).normalized())
+			.include_value("shadow_softness", self.shadow_softness)
 			.into()
 	}
 }
 
+// Small enough that the penumbras only show up near the contact points
+const DEFAULT_SHADOW_SOFTNESS: f32 = 0.1;
+
 /*
 --------------------------------------------------------------------------------
 ||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
@@ -50,8 +78,268 @@ impl ShaderFragment for CelShading {
 
 		ShaderBuilder::new()
 			.include_path("/shading/cel_shading.wgsl")
-			.include_value("sun_direction", vec3!(1.0, -1.0, 0.0).normalized())
 			.include_buffer(gradient)
 			.into()
 	}
 }
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Cook-Torrance with the GGX distribution, lit by the
+/// [`Lights`](crate::core::rendering::lights::Lights) plus an ambient term: the
+/// [`EnvironmentMaps`] if it has them, a constant color otherwise.
+///
+/// The material of a hit is the one of the [`MaterialLibrary`] with the
+/// intersection's material id, the unknown ids get the default material. Its
+/// textures are sampled at the intersection's uv.
+///
+/// Every light casts a shadow ray through the intersector. The shadows are hard,
+/// except while a [`HighQualityCapture`](crate::core::rendering::capture::HighQualityCapture)
+/// accumulates frames: the lights then count as discs of radius `shadow_softness`
+/// (the tangent of the angle for the directional lights), each frame aims at a
+/// random point of them and the frames average out into soft penumbras.
+pub struct PbrShading {
+	pub materials: Vec<Material>,
+	/// The textures the materials use, without the placeholders, see
+	/// [`MaterialLibrary::register_texture`]
+	pub textures: Vec<DynamicImage>,
+	pub ambient_color: Rgb<f32>,
+	/// 0 for hard shadows even when accumulating
+	pub shadow_softness: f32,
+	/// Replaces the `ambient_color`, see [`with_environment`](Self::with_environment)
+	pub environment: Option<EnvironmentMaps>,
+}
+
+impl Default for PbrShading {
+	fn default() -> Self {
+		Self::new(&MaterialLibrary::default())
+	}
+}
+
+impl PbrShading {
+	/// The materials and textures are copied, the ones registered afterwards are
+	/// only known to the renderers built afterwards
+	pub fn new(library: &MaterialLibrary) -> Self {
+		Self {
+			materials: library.materials().to_vec(),
+			textures: library.textures().to_vec(),
+			ambient_color: Rgb::broadcast(0.03),
+			shadow_softness: DEFAULT_SHADOW_SOFTNESS,
+			environment: None,
+		}
+	}
+
+	/// Light with the prefiltered environment instead of the constant ambient
+	/// color. The maps are bound as they are, so the environments loaded later on
+	/// show up without rebuilding the renderer.
+	pub fn with_environment(mut self, maps: &EnvironmentMaps) -> Self {
+		self.environment = Some(maps.clone());
+		self
+	}
+}
+
+impl Shading for PbrShading {}
+impl ShaderFragment for PbrShading {
+	fn shader(&self) -> Shader {
+		let mut builder = ShaderBuilder::new();
+		builder
+			.include_path("/shading/pbr.wgsl")
+			.include_value("ambient_color", self.ambient_color)
+			.include_value("shadow_softness", self.shadow_softness)
+			.include(materials_shader(&self.materials, &self.textures));
+
+		match &self.environment {
+			Some(maps) => builder
+				.include_path("/shading/pbr_environment.wgsl")
+				.include_buffer(SampledTexture::FromTex {
+					texture_var_name: "environment_prefiltered",
+					sampler_var_name: "environment_prefiltered_sampler",
+					tex: maps.prefiltered.clone(),
+				})
+				.include_buffer(SampledTexture::FromTex {
+					texture_var_name: "environment_irradiance",
+					sampler_var_name: "environment_irradiance_sampler",
+					tex: maps.irradiance.clone(),
+				}),
+			None => builder.include_path("/shading/pbr_environment_off.wgsl"),
+		};
+
+		builder.into()
+	}
+}
+
+/// The `materials` and the `material_textures` that pbr_brdf.wgsl needs, for
+/// the [`PbrShading`] and the
+/// [`PathTracer`](super::path_tracer::PathTracer)
+pub(crate) fn materials_shader(materials: &[Material], textures: &[DynamicImage]) -> Shader {
+	// Storage arrays can't be empty
+	let materials = if materials.is_empty() {
+		vec![Material::default()]
+	} else {
+		materials.to_vec()
+	};
+
+	ShaderBuilder::new()
+		.include_buffer(StorageBufferDescriptor::FromData {
+			var_name: "materials",
+			read_only: true,
+			data: StorageArray(materials),
+		})
+		.include_buffer(SampledTexture::FromImageArray {
+			texture_var_name: "material_textures",
+			sampler_var_name: "material_sampler",
+			images: MaterialLibrary::texture_layers(textures),
+			// Rgba8UnormSrgb would decode the normal maps too, so the shader decodes the
+			// albedo itself
+			format: TextureFormat::Rgba8Unorm,
+			usage: None,
+			filter: FilterMode::Linear,
+			edges: SamplerEdges::Repeat,
+			compare: None,
+		})
+		.into()
+}
+
+#[repr(C)]
+#[derive(ShaderStruct, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+pub struct Material {
+	/// Multiplied with the color the intersector gives, keep it white to use that
+	/// one as is
+	pub base_color: Rgb<f32>,
+	pub metallic: f32,
+	pub roughness: f32,
+	/// The textures are layers of the [`MaterialLibrary`]'s, the untextured
+	/// materials use its placeholders. In sRGB, multiplied with the base color.
+	pub albedo_texture: u32,
+	/// The roughness in green and the metallic in blue like glTF, multiplied
+	/// with the factors above
+	pub metallic_roughness_texture: u32,
+	/// In tangent space, only used where the intersector knows the tangents
+	pub normal_texture: u32,
+}
+
+impl Material {
+	pub fn new(base_color: Rgb<f32>, metallic: f32, roughness: f32) -> Self {
+		Self {
+			base_color,
+			metallic,
+			roughness,
+			albedo_texture: MaterialLibrary::WHITE_TEXTURE,
+			metallic_roughness_texture: MaterialLibrary::WHITE_TEXTURE,
+			normal_texture: MaterialLibrary::FLAT_NORMAL_TEXTURE,
+		}
+	}
+
+	pub fn with_albedo_texture(mut self, layer: u32) -> Self {
+		self.albedo_texture = layer;
+		self
+	}
+
+	pub fn with_metallic_roughness_texture(mut self, layer: u32) -> Self {
+		self.metallic_roughness_texture = layer;
+		self
+	}
+
+	pub fn with_normal_texture(mut self, layer: u32) -> Self {
+		self.normal_texture = layer;
+		self
+	}
+}
+
+impl Default for Material {
+	/// A rough white plastic
+	fn default() -> Self {
+		Self::new(Rgb::one(), 0.0, 0.5)
+	}
+}
+
+/// The materials of [`PbrShading`], their ids are the intersections'
+/// `material_id`. The id 0 is the default material, for the intersectors that
+/// don't know about materials.
+///
+/// The textures are the layers of a single texture array, so they're all
+/// resized to the biggest one. Its first layers are placeholders of a single
+/// color, a white one and a flat normal map, so that a material can have some
+/// of its textures and not the others. Without any textures, the array is
+/// just the placeholders at 1x1.
+#[derive(bevy::Resource, Clone, Debug)]
+pub struct MaterialLibrary {
+	materials: Vec<Material>,
+	textures: Vec<DynamicImage>,
+}
+
+impl Default for MaterialLibrary {
+	fn default() -> Self {
+		Self {
+			materials: vec![Material::default()],
+			textures: Vec::new(),
+		}
+	}
+}
+
+impl MaterialLibrary {
+	pub const DEFAULT_MATERIAL: u32 = 0;
+
+	/// The placeholder for the albedo and the metallic-roughness, keeping the
+	/// factors as they are
+	pub const WHITE_TEXTURE: u32 = 0;
+	pub const FLAT_NORMAL_TEXTURE: u32 = 1;
+	const PLACEHOLDER_COUNT: u32 = 2;
+
+	/// Returns the id of the new material
+	pub fn register(&mut self, material: Material) -> u32 {
+		self.materials.push(material);
+		self.materials.len() as u32 - 1
+	}
+
+	pub fn get(&self, id: u32) -> Option<&Material> {
+		self.materials.get(id as usize)
+	}
+
+	/// Change a registered material, returns `false` if there's none with that id
+	pub fn set(&mut self, id: u32, material: Material) -> bool {
+		match self.materials.get_mut(id as usize) {
+			Some(slot) => {
+				*slot = material;
+				true
+			}
+			None => false,
+		}
+	}
+
+	pub fn materials(&self) -> &[Material] {
+		&self.materials
+	}
+
+	/// Returns the layer of the new texture, for the
+	/// [`Material`]'s textures
+	pub fn register_texture(&mut self, image: DynamicImage) -> u32 {
+		self.textures.push(image);
+		self.textures.len() as u32 - 1 + Self::PLACEHOLDER_COUNT
+	}
+
+	/// Without the placeholders, so the first one is the layer 2
+	pub fn textures(&self) -> &[DynamicImage] {
+		&self.textures
+	}
+
+	/// All the layers of the texture array: the placeholders, as big as the
+	/// biggest texture, then the textures
+	pub fn texture_layers(textures: &[DynamicImage]) -> Vec<DynamicImage> {
+		let (width, height) = textures.iter().fold((1, 1), |(width, height), image| {
+			(width.max(image.width()), height.max(image.height()))
+		});
+
+		let white = RgbaImage::from_pixel(width, height, Rgba([255; 4]));
+		let flat_normal = RgbaImage::from_pixel(width, height, Rgba([128, 128, 255, 255]));
+
+		[DynamicImage::ImageRgba8(white), DynamicImage::ImageRgba8(flat_normal)]
+			.into_iter()
+			.chain(textures.iter().cloned())
+			.collect()
+	}
+}
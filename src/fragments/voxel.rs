@@ -0,0 +1,241 @@
+use brainrot::vek::{Extent3, Rgba, Vec3};
+use pbr_tracer_derive::ShaderStruct;
+use wgpu::{StorageTextureAccess, TextureFormat};
+
+use super::{ambient_occlusion::AmbientOcclusion, mpr::Intersector};
+use crate::libs::{
+	buffer::{storage_texture_buffer::StorageTexture, ShaderType},
+	shader::{Shader, ShaderBuilder},
+	shader_fragment::ShaderFragment,
+	texture::TextureAssetDimensions,
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// A dense grid of colored voxels. A voxel with an alpha of 0 is empty, any
+/// other alpha is solid.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VoxelGrid {
+	pub size: Extent3<u32>,
+	/// x first, then y, then z
+	pub voxels: Vec<Rgba<u8>>,
+}
+
+impl VoxelGrid {
+	pub const EMPTY: Rgba<u8> = Rgba { r: 0, g: 0, b: 0, a: 0 };
+
+	pub fn new(size: Extent3<u32>) -> Self {
+		Self {
+			size,
+			voxels: vec![Self::EMPTY; voxel_count(size)],
+		}
+	}
+
+	/// Every density above the threshold becomes a solid voxel of that color
+	pub fn from_density(size: Extent3<u32>, density: &[u8], threshold: u8, color: Rgba<u8>) -> Self {
+		assert_eq!(density.len(), voxel_count(size), "The density doesn't match the size");
+
+		Self {
+			size,
+			voxels: density
+				.iter()
+				.map(|&density| if density > threshold { color } else { Self::EMPTY })
+				.collect(),
+		}
+	}
+
+	fn index(&self, pos: Vec3<u32>) -> usize {
+		(pos.x + self.size.w * (pos.y + self.size.h * pos.z)) as usize
+	}
+
+	pub fn get(&self, pos: Vec3<u32>) -> Rgba<u8> {
+		self.voxels[self.index(pos)]
+	}
+
+	pub fn set(&mut self, pos: Vec3<u32>, voxel: Rgba<u8>) {
+		let index = self.index(pos);
+		self.voxels[index] = voxel;
+	}
+
+	pub fn solid_count(&self) -> usize {
+		self.voxels.iter().filter(|voxel| voxel.a > 0).count()
+	}
+
+	fn positions(size: Extent3<u32>) -> impl Iterator<Item = Vec3<u32>> {
+		(0..size.d).flat_map(move |z| (0..size.h).flat_map(move |y| (0..size.w).map(move |x| Vec3::new(x, y, z))))
+	}
+
+	/// A Menger sponge `3^level` voxels wide, colored by position
+	pub fn menger_sponge(level: u32) -> Self {
+		let width = 3_u32.pow(level);
+		let mut grid = Self::new(Extent3::new(width, width, width));
+
+		for pos in Self::positions(grid.size) {
+			// A voxel is removed if, at any scale, it's in the middle of a face or of
+			// the cube, i.e. at least two of its base 3 digits are 1
+			let mut digits = pos;
+			let removed = (0..level).any(|_| {
+				let middles = digits.map(|x| (x % 3 == 1) as u32).sum();
+				digits = digits.map(|x| x / 3);
+				middles >= 2
+			});
+
+			if !removed {
+				let color = pos.map(|x| (64 + 191 * x / (width - 1).max(1)) as u8);
+				grid.set(pos, Rgba::new(color.x, color.y, color.z, 255));
+			}
+		}
+
+		grid
+	}
+
+	/// Hills from value noise, with grass on top of dirt on top of stone. The same
+	/// seed always gives the same terrain.
+	pub fn terrain(size: Extent3<u32>, seed: u32) -> Self {
+		// How many voxels wide the hills are
+		const SCALE: f32 = 16.0;
+
+		let grass = Rgba::new(86, 155, 60, 255);
+		let dirt = Rgba::new(121, 85, 58, 255);
+		let stone = Rgba::new(128, 128, 128, 255);
+
+		let mut grid = Self::new(size);
+
+		for z in 0..size.d {
+			for x in 0..size.w {
+				// Two octaves, between 0 and 1
+				let noise = 0.7 * value_noise(x as f32 / SCALE, z as f32 / SCALE, seed)
+					+ 0.3 * value_noise(x as f32 / (SCALE / 3.0), z as f32 / (SCALE / 3.0), seed.wrapping_add(1));
+				let height = 1 + (noise * size.h.saturating_sub(1) as f32) as u32;
+
+				for y in 0..height.min(size.h) {
+					let voxel = match height - y {
+						1 => grass,
+						2..=3 => dirt,
+						_ => stone,
+					};
+					grid.set(Vec3::new(x, y, z), voxel);
+				}
+			}
+		}
+
+		grid
+	}
+
+	fn bytes(&self) -> Vec<u8> {
+		self.voxels.iter().flat_map(|voxel| voxel.into_array()).collect()
+	}
+}
+
+fn voxel_count(size: Extent3<u32>) -> usize {
+	(size.w * size.h * size.d) as usize
+}
+
+/// Smoothly interpolated random values on the integer grid, between 0 and 1
+fn value_noise(x: f32, y: f32, seed: u32) -> f32 {
+	let hash = |x: i32, y: i32| {
+		let mut h =
+			(x as u32).wrapping_mul(0x8da6b343) ^ (y as u32).wrapping_mul(0xd8163841) ^ seed.wrapping_mul(0xcb1ab31f);
+		h ^= h >> 13;
+		h = h.wrapping_mul(0x5bd1e995);
+		h ^= h >> 15;
+		h as f32 / u32::MAX as f32
+	};
+
+	let (x0, y0) = (x.floor(), y.floor());
+	let (fx, fy) = (x - x0, y - y0);
+	let (sx, sy) = (fx * fx * (3.0 - 2.0 * fx), fy * fy * (3.0 - 2.0 * fy));
+	let (x0, y0) = (x0 as i32, y0 as i32);
+
+	let top = hash(x0, y0) + (hash(x0 + 1, y0) - hash(x0, y0)) * sx;
+	let bottom = hash(x0, y0 + 1) + (hash(x0 + 1, y0 + 1) - hash(x0, y0 + 1)) * sx;
+	top + (bottom - top) * sy
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Intersects the rays with a [`VoxelGrid`], stepping through it voxel by
+/// voxel (DDA). The grid is a 3D storage texture, and its placement is the
+/// `voxel_grid` uniform.
+pub struct VoxelIntersector {
+	pub grid: VoxelGrid,
+	/// Where the corner of the first voxel is
+	pub origin: Vec3<f32>,
+	/// How wide a voxel is in the scene
+	pub voxel_size: f32,
+}
+
+impl Default for VoxelIntersector {
+	/// Something to look at without any asset
+	fn default() -> Self {
+		Self::new(VoxelGrid::menger_sponge(3))
+	}
+}
+
+impl VoxelIntersector {
+	/// Centered above the origin, 4 units wide
+	pub fn new(grid: VoxelGrid) -> Self {
+		let voxel_size = 4.0 / grid.size.w.max(grid.size.h).max(grid.size.d).max(1) as f32;
+		let extent = Vec3::new(grid.size.w, grid.size.h, grid.size.d).map(|x| x as f32) * voxel_size;
+
+		Self {
+			grid,
+			origin: Vec3::new(-extent.x / 2.0, 0.0, -extent.z / 2.0),
+			voxel_size,
+		}
+	}
+}
+
+/// The `voxel_grid` uniform
+#[repr(C)]
+#[derive(ShaderStruct, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+pub struct VoxelGridParams {
+	pub origin: Vec3<f32>,
+	pub voxel_size: f32,
+	/// In voxels
+	pub size: Vec3<u32>,
+	#[shader(skip)]
+	_padding: u32,
+}
+
+impl Intersector for VoxelIntersector {}
+impl ShaderFragment for VoxelIntersector {
+	fn shader(&self) -> Shader {
+		// Textures can't be empty
+		let grid = if self.grid.voxels.is_empty() {
+			VoxelGrid::new(Extent3::new(1, 1, 1))
+		} else {
+			self.grid.clone()
+		};
+
+		let params = VoxelGridParams {
+			origin: self.origin,
+			voxel_size: self.voxel_size,
+			size: Vec3::new(grid.size.w, grid.size.h, grid.size.d),
+			_padding: 0,
+		};
+
+		ShaderBuilder::new()
+			.include_path("voxel/voxel.wgsl")
+			// Off, but the renderer still calls it
+			.include(AmbientOcclusion::default().shader())
+			.include_value("voxel_grid", params)
+			.include_buffer(StorageTexture::FromBytes {
+				var_name: "voxels",
+				access: StorageTextureAccess::ReadOnly,
+				dimensions: TextureAssetDimensions::D3(grid.size),
+				format: TextureFormat::Rgba8Unorm,
+				usage: None,
+				bytes: grid.bytes(),
+			})
+			.into()
+	}
+}
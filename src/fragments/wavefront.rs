@@ -0,0 +1,329 @@
+use std::sync::Arc;
+
+use brainrot::vek::{Extent2, Vec2, Vec3, Vec4};
+use log::warn;
+use pbr_tracer_derive::ShaderStruct;
+use wgpu::Buffer;
+
+use super::{
+	environment::Environment,
+	mpr::{Intersector, MultiPurposeRenderer, Shading},
+};
+use crate::{
+	core::{gpu::Gpu, size::Resolution},
+	libs::{
+		buffer::{
+			atomic_counter::{AtomicCounter, AtomicCounterDescriptor},
+			storage_buffer::{array_stride, StorageArray, StorageBufferDescriptor},
+			ShaderType,
+		},
+		shader::{Shader, ShaderBuilder},
+		shader_fragment::{PrePassDesc, PrePassDispatch, Renderer, RendererResizer, ShaderFragment},
+		smart_arc::Sarc,
+		texture::TexDescriptor,
+	},
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Runs a [`MultiPurposeRenderer`] as separate kernels connected by queues,
+/// instead of one kernel doing everything per pixel (experimental).
+///
+/// Every frame, `wavefront_generate` queues one camera ray per pixel,
+/// `wavefront_extend` intersects the queued rays and queues the hits, and
+/// `wavefront_shade` shades them into a buffer of pixels. Each stage is
+/// dispatched with the indirect args written from the length of its queue by
+/// the stage before it. The main pass then only composites the reference grid
+/// and runs the post-processing, so the output is the same as the megakernel's.
+///
+/// The queues are sized for the resolution given to
+/// [`MultiPurposeRenderer::wavefront`]. If they don't fit in the adapter's
+/// limits, the renderer falls back to the megakernel.
+///
+/// The stages run once per frame: the shading of the multi-purpose renderer
+/// ends at the first hit, so there are no bounces to loop over. A wavefront
+/// [`PathTracer`](super::path_tracer::PathTracer) would need to loop the
+/// extend and shade stages on the CPU, once per bounce.
+pub struct WavefrontRenderer<I, S, E>
+where
+	I: Intersector,
+	S: Shading,
+	E: Environment,
+{
+	pub renderer: MultiPurposeRenderer<I, S, E>,
+	/// `None` when falling back to the megakernel
+	queues: Option<WavefrontQueues>,
+}
+
+struct WavefrontQueues {
+	/// How many rays or pixels the queues hold
+	capacity: u32,
+	/// The lengths of the queues and the indirect args, see `wavefront.wgsl`
+	counters: Sarc<Buffer>,
+}
+
+/// The parts of the [`MultiPurposeRenderer`] the wavefront is built from, so
+/// that it can be built again at another resolution without the renderer
+#[derive(Clone)]
+struct WavefrontParts {
+	megakernel: Shader,
+	fragments: Shader,
+	pre_passes: Vec<PrePassDesc>,
+}
+
+impl<I, S, E> MultiPurposeRenderer<I, S, E>
+where
+	I: Intersector,
+	S: Shading,
+	E: Environment,
+{
+	/// Run this renderer as a [`WavefrontRenderer`], with queues big enough for
+	/// the resolution
+	pub fn wavefront(self, gpu: &Gpu, resolution: Resolution) -> WavefrontRenderer<I, S, E> {
+		WavefrontRenderer::new(self, gpu, resolution)
+	}
+}
+
+impl<I, S, E> WavefrontRenderer<I, S, E>
+where
+	I: Intersector,
+	S: Shading,
+	E: Environment,
+{
+	pub fn new(renderer: MultiPurposeRenderer<I, S, E>, gpu: &Gpu, resolution: Resolution) -> Self {
+		Self {
+			renderer,
+			queues: WavefrontQueues::new(gpu, resolution),
+		}
+	}
+
+	/// Whether the renderer runs as a wavefront, as opposed to falling back to
+	/// the megakernel
+	pub fn is_wavefront(&self) -> bool {
+		self.queues.is_some()
+	}
+
+	fn parts(&self) -> WavefrontParts {
+		WavefrontParts {
+			megakernel: self.renderer.shader(),
+			fragments: self.renderer.fragments_shader(),
+			pre_passes: self.renderer.pre_passes(),
+		}
+	}
+}
+
+impl WavefrontQueues {
+	/// Same as the `@workgroup_size` of the queue stages
+	const WORKGROUP_SIZE: u32 = 64;
+	/// Same as the `@workgroup_size` of `wavefront_generate()`
+	const GENERATE_WORKGROUP_SIZE: Vec2<u32> = Vec2 { x: 8, y: 8 };
+
+	/// The ray count, the hit count, then the args of the extend and shade stages
+	const COUNTERS: u32 = 8;
+	const EXTEND_ARGS_OFFSET: u64 = 2 * 4;
+	const SHADE_ARGS_OFFSET: u64 = 5 * 4;
+
+	/// Queues for every pixel of the resolution, `None` if they don't fit in
+	/// the adapter's limits
+	fn new(gpu: &Gpu, resolution: Resolution) -> Option<Self> {
+		let size = Extent2::from(resolution);
+		let capacity = size.w * size.h;
+
+		let limits = gpu.device.limits();
+		let largest_queue = capacity as u64 * array_stride::<WavefrontHit>();
+		let max_size = (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size);
+
+		if largest_queue > max_size {
+			warn!(
+				"The wavefront queues need {} bytes at {}x{}, more than the limit of {}, falling back to the \
+				 megakernel",
+				largest_queue, size.w, size.h, max_size
+			);
+			return None;
+		}
+
+		Some(Self {
+			capacity,
+			counters: Sarc::new(AtomicCounter::raw_buffer_from_count(
+				gpu,
+				Self::COUNTERS,
+				Some("Wavefront queue counters"),
+			)),
+		})
+	}
+}
+
+impl WavefrontParts {
+	fn shader(&self, queues: Option<&WavefrontQueues>) -> Shader {
+		let Some(queues) = queues else {
+			return self.megakernel.clone();
+		};
+
+		let queue_size = |element_size: u64| queues.capacity as u64 * element_size;
+
+		ShaderBuilder::new()
+			.include_path("wavefront/wavefront.wgsl")
+			.include(self.fragments.clone())
+			.include_buffer(StorageBufferDescriptor::<StorageArray<WavefrontRay>, _>::New {
+				var_name: "wavefront_rays",
+				read_only: false,
+				size: queue_size(array_stride::<WavefrontRay>()),
+			})
+			.include_buffer(StorageBufferDescriptor::<StorageArray<WavefrontHit>, _>::New {
+				var_name: "wavefront_hits",
+				read_only: false,
+				size: queue_size(array_stride::<WavefrontHit>()),
+			})
+			.include_buffer(StorageBufferDescriptor::<StorageArray<WavefrontPixel>, _>::New {
+				var_name: "wavefront_pixels",
+				read_only: false,
+				size: queue_size(array_stride::<WavefrontPixel>()),
+			})
+			.include_buffer(AtomicCounterDescriptor::FromBuffer {
+				var_name: "wavefront_queues",
+				buffer: queues.counters.clone(),
+			})
+			.define("WAVEFRONT_CAPACITY", format!("{}u", queues.capacity))
+			.define(
+				"WAVEFRONT_WORKGROUP_SIZE",
+				format!("{}u", WavefrontQueues::WORKGROUP_SIZE),
+			)
+			.into()
+	}
+
+	fn pre_passes(&self, queues: Option<&WavefrontQueues>) -> Vec<PrePassDesc> {
+		let mut pre_passes = self.pre_passes.clone();
+
+		let Some(queues) = queues else {
+			return pre_passes;
+		};
+
+		// The queue lengths are read and written atomically all along, so they are
+		// only listed as written
+		let stage = |entry_point: &str, dispatch: PrePassDispatch, reads: &[&str], writes: &[&str]| PrePassDesc {
+			entry_point: entry_point.to_owned(),
+			dispatch,
+			reads: reads.iter().map(|&var_name| var_name.to_owned()).collect(),
+			writes: writes.iter().map(|&var_name| var_name.to_owned()).collect(),
+		};
+		let single = || PrePassDispatch::Fixed(Vec3::one());
+		let indirect = |offset: u64| PrePassDispatch::IndirectCopy {
+			buffer: queues.counters.clone(),
+			offset,
+		};
+
+		pre_passes.extend([
+			stage("wavefront_reset", single(), &[], &["wavefront_queues"]),
+			stage(
+				"wavefront_generate",
+				PrePassDispatch::Resolution(WavefrontQueues::GENERATE_WORKGROUP_SIZE),
+				&["camera", "dof"],
+				&["wavefront_rays", "wavefront_queues"],
+			),
+			stage("wavefront_prepare_extend", single(), &[], &["wavefront_queues"]),
+			stage(
+				"wavefront_extend",
+				indirect(WavefrontQueues::EXTEND_ARGS_OFFSET),
+				&["wavefront_rays"],
+				&["wavefront_hits", "wavefront_queues"],
+			),
+			stage("wavefront_prepare_shade", single(), &[], &["wavefront_queues"]),
+			stage(
+				"wavefront_shade",
+				indirect(WavefrontQueues::SHADE_ARGS_OFFSET),
+				&["wavefront_hits"],
+				&["wavefront_pixels", "wavefront_queues"],
+			),
+		]);
+
+		pre_passes
+	}
+}
+
+impl<I, S, E> Renderer for WavefrontRenderer<I, S, E>
+where
+	I: Intersector,
+	S: Shading,
+	E: Environment,
+{
+	fn output_textures(&self, resolution: Resolution) -> Vec<(String, TexDescriptor)> {
+		self.renderer.output_textures(resolution)
+	}
+
+	// The queues are sized for the resolution
+	fn resizer(&self) -> Option<RendererResizer> {
+		let parts = self.parts();
+
+		Some(Arc::new(move |gpu, resolution| {
+			let queues = WavefrontQueues::new(gpu, resolution);
+			(parts.shader(queues.as_ref()), parts.pre_passes(queues.as_ref()))
+		}))
+	}
+}
+
+impl<I, S, E> ShaderFragment for WavefrontRenderer<I, S, E>
+where
+	I: Intersector,
+	S: Shading,
+	E: Environment,
+{
+	fn shader(&self) -> Shader {
+		self.parts().shader(self.queues.as_ref())
+	}
+
+	fn pre_passes(&self) -> Vec<PrePassDesc> {
+		self.parts().pre_passes(self.queues.as_ref())
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+#[repr(C)]
+#[derive(ShaderStruct, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+struct WavefrontRay {
+	origin: Vec3<f32>,
+	/// The index of the pixel in the output
+	pixel: u32,
+	direction: Vec3<f32>,
+	#[shader(skip)]
+	_padding: u32,
+}
+
+/// An `Intersection`, without the bool that storage buffers can't hold
+#[repr(C)]
+#[derive(ShaderStruct, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+struct WavefrontHit {
+	position: Vec3<f32>,
+	distance: f32,
+	normal: Vec3<f32>,
+	material_id: u32,
+	color: Vec3<f32>,
+	pixel: u32,
+	outgoing: Vec3<f32>,
+	has_hit: u32,
+	tangent: Vec4<f32>,
+	uv: Vec2<f32>,
+	id: u32,
+	#[shader(skip)]
+	_padding: u32,
+}
+
+/// What the main pass needs from the stages
+#[repr(C)]
+#[derive(ShaderStruct, bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+struct WavefrontPixel {
+	color: Vec4<f32>,
+	normal: Vec3<f32>,
+	distance: f32,
+	id: u32,
+	#[shader(skip)]
+	_padding: [u32; 3],
+}
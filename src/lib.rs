@@ -1,30 +1,63 @@
 pub mod core;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 pub mod fragments;
 pub mod libs;
 
 use core::{
 	camera::CameraPlugin,
+	camera_poses::CameraPosesPlugin,
+	clip_planes::ClipPlanesPlugin,
+	console::ConsolePlugin,
 	display::DisplayPlugin,
 	event_processing::EventProcessingPlugin,
 	events::EventsPlugin,
 	gameloop::{GameloopPlugin, Render},
 	gpu::GpuPlugin,
-	render_target::WindowRenderTargetPlugin,
+	key_bindings::KeyBindingsPlugin,
+	logging::LoggingPlugin,
+	params::ParamsPlugin,
+	picking::PickingPlugin,
+	presentation::PresentationPlugin,
+	render_target::{RenderTarget, WindowRenderTargetPlugin},
 	rendering::{
+		accumulation::AccumulationPlugin,
 		camera_view::CameraViewPlugin,
+		capture::HighQualityCapturePlugin,
+		chunked_upload::ChunkedUploadPlugin,
+		color_grade::{ColorGradeLut, ColorGradePlugin},
 		composite::{CompositeRenderPass, CompositeRendererPlugin},
-		compute::{ComputeRenderPass, ComputeRendererPlugin},
+		compute::{ComputeRenderPass, ComputeRendererPlugin, DispatchMode},
+		denoise::{DenoisePass, DenoisePlugin},
+		depth_of_field::DepthOfFieldPlugin,
+		dynamic_quality::DynamicQualityPlugin,
+		environment::EnvironmentPlugin,
+		globals::GlobalsPlugin,
+		gpu_asserts::GpuAssertsPlugin,
+		gpu_timers::GpuTimersPlugin,
+		histogram::HistogramPlugin,
+		interactive::InteractiveRenderingPlugin,
+		lights::LightsPlugin,
+		picture_in_picture::PictureInPicturePlugin,
 		render::{InnerRenderPass, PostRenderPass, PreRenderPass, RenderPass, RenderPlugin},
+		render_scale::{self, RenderScalePlugin},
+		sky::{Sky, SkyPlugin},
 	},
+	shader_check::ShaderCheckPlugin,
+	watchdog::WatchdogPlugin,
 };
 
 use bevy_ecs::schedule::IntoSystemSetConfigs;
 use bevy_tasks::{AsyncComputeTaskPool, TaskPool};
-use brainrot::{
-	bevy::{self, App},
-	size, vec2,
+use brainrot::{bevy::App, vec2};
+use fragments::{
+	environment::*,
+	intersector::*,
+	mpr::{Intersector, MultiPurposeRenderer},
+	post_processing::{ColorGrade, Fxaa, GammaCorrection, PostProcessingPipeline, ToneMapping},
+	reference_grid::ReferenceGrid,
+	shading::*,
 };
-use fragments::{intersector::*, mpr::MultiPurposeRenderer, post_processing::PostProcessingPipeline, shading::*};
 use image::DynamicImage;
 use rust_embed::Embed;
 use wgpu::FilterMode;
@@ -51,7 +84,7 @@ impl TextureAssets {
 	}
 }
 
-pub trait EntityLabel: bevy::Component {}
+pub use core::entity_label::EntityLabel;
 
 /// The default `EventLoop` type to avoid having to add the extra unit type
 type EventLoop = winit::event_loop::EventLoop<()>;
@@ -63,45 +96,124 @@ type EventLoop = winit::event_loop::EventLoop<()>;
 */
 
 pub fn run() {
-	AsyncComputeTaskPool::get_or_init(TaskPool::new);
+	run_with(|_| {});
+}
 
-	let renderer = MultiPurposeRenderer {
-		intersector: Raymarcher,
-		shading: CelShading,
-		post_processing: PostProcessingPipeline::empty(),
-	};
+/// Same as [`run`], but lets the embedding application change the app before
+/// it starts, e.g. to replace the [`KeyBindings`](core::key_bindings::KeyBindings)
+pub fn run_with(configure: impl FnOnce(&mut App)) {
+	let mut app = build_app(DisplayPlugin::default());
+	configure(&mut app);
+	app.run();
+}
+
+/// Build the full app without running it, so that automated runs can drive
+/// it themselves (see `gameloop::run_frames`)
+pub fn build_app(display_plugin: DisplayPlugin) -> App {
+	build_app_with(display_plugin, |app| Raymarcher::default().tweakable(app))
+}
+
+/// Same as [`build_app`], with another intersector. It is created once the GPU
+/// is there, e.g. so that it can spawn its own buffers.
+pub fn build_app_with<I>(display_plugin: DisplayPlugin, intersector: impl FnOnce(&mut App) -> I) -> App
+where
+	I: Intersector + Send + Sync + 'static,
+{
+	AsyncComputeTaskPool::get_or_init(TaskPool::new);
 
-	App::new()
+	let mut app = App::new();
+	app
 		// Core plugins
 		.add_plugin(GpuPlugin)
+		.add_plugin(KeyBindingsPlugin)
 		.add_plugin(CameraPlugin)
+		.add_plugin(CameraPosesPlugin::default())
+		.add_plugin(ClipPlanesPlugin)
+		.add_plugin(PresentationPlugin::default())
 		.add_plugin(CameraViewPlugin)
 		.add_plugin(EventProcessingPlugin)
 		.add_plugin(EventsPlugin)
 		.add_plugin(GameloopPlugin)
-		.add_plugin(DisplayPlugin)
+		.add_plugin(WatchdogPlugin::default())
+		.add_plugin(display_plugin)
+		.add_plugin(LoggingPlugin::default())
+		.add_plugin(ConsolePlugin)
+		.add_plugin(ParamsPlugin)
 		.add_plugin(WindowRenderTargetPlugin)
+		// Before the renderer, which starts at the scaled size of the window
+		.add_plugin(RenderScalePlugin::default())
+		// Before the renderer, whose post processing samples its LUT
+		.add_plugin(ColorGradePlugin::default())
+		// Before the renderer too, whose environment is its sky
+		.add_plugin(SkyPlugin::default());
+
+	let mut post_processing = PostProcessingPipeline::empty()
+		.with(ToneMapping::default().tweakable(&mut app))
+		.with(ColorGrade::new(app.world.resource::<ColorGradeLut>()))
+		.with(Fxaa::default().tweakable(&mut app));
+	// The window's surface is sRGB and does the gamma itself, after everything else
+	if !app.world.resource::<RenderTarget>().config.format.is_srgb() {
+		post_processing = post_processing.with(GammaCorrection);
+	}
+	let post_processing = post_processing.tweakable(&mut app);
+
+	let resolution = render_scale::target_resolution(&app.world);
+
+	let renderer = MultiPurposeRenderer {
+		intersector: intersector(&mut app),
+		shading: CelShading,
+		environment: PreethamSky::new(app.world.resource::<Sky>()),
+		// environment: ProceduralSky::default(),
+		// environment: HdriEnvironment::from_asset("sky.hdr"),
+		post_processing,
+		reference_grid: Some(ReferenceGrid::default()),
+	};
+
+	app
 		// Compute renderer
+		.add_plugin(GlobalsPlugin)
+		.add_plugin(DepthOfFieldPlugin::default())
+		.add_plugin(AccumulationPlugin)
+		.add_plugin(InteractiveRenderingPlugin::default())
+		.add_plugin(LightsPlugin)
+		.add_plugin(ChunkedUploadPlugin::default())
+		.add_plugin(EnvironmentPlugin::default())
+		.add_plugin(GpuAssertsPlugin::default())
 		.add_plugin(ComputeRendererPlugin {
 			workgroup_size: vec2!(16, 16),
-			resolution: size!(2000, 1000),
+			resolution,
 			filter_mode: FilterMode::Linear,
+			dispatch_mode: DispatchMode::Fixed,
+			early_submit: false,
 			renderer,
 			// renderer: DebugRenderer,
 		})
 		// Rendering plugins
 		.add_plugin(RenderPlugin)
-		.add_plugin(CompositeRendererPlugin)
+		.add_plugin(CompositeRendererPlugin::default())
+		// After the composite, which it points at its own output
+		.add_plugin(DenoisePlugin::default())
+		.add_plugin(GpuTimersPlugin::default())
+		.add_plugin(DynamicQualityPlugin::default())
+		.add_plugin(HighQualityCapturePlugin)
+		.add_plugin(PickingPlugin)
+		.add_plugin(PictureInPicturePlugin::default())
+		.add_plugin(HistogramPlugin::default())
+		// Needs to come after all the plugins that build shaders
+		.add_plugin(ShaderCheckPlugin)
 		// Configure Renderpass order
 		.configure_sets(
 			Render,
 			((
 				PreRenderPass,
-				(ComputeRenderPass, CompositeRenderPass).chain().in_set(InnerRenderPass),
+				(ComputeRenderPass, DenoisePass, CompositeRenderPass)
+					.chain()
+					.in_set(InnerRenderPass),
 				PostRenderPass,
 			)
 				.chain()
 				.in_set(RenderPass),),
-		)
-		.run();
+		);
+
+	app
 }
@@ -0,0 +1,283 @@
+use brainrot::{
+	vec2,
+	vek::{Extent2, Rect},
+};
+use wgpu::{TextureAspect, TextureFormat, TextureUsages};
+
+use super::{
+	smart_arc::Sarc,
+	texture::{Tex, TexDescriptor, TexSamplerDescriptor, TextureAssetDimensions},
+};
+use crate::core::gpu::Gpu;
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Packs rectangles into rows ("shelves") stacked on top of each other.
+///
+/// Each shelf is as tall as the first rectangle put in it, and rectangles are
+/// placed side by side in the shelf that wastes the least height. Good enough
+/// for glyphs and icons, which mostly have similar heights.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShelfPacker {
+	size: Extent2<u32>,
+	/// Empty texels left around every rectangle, so that filtering doesn't bleed
+	/// into the neighbours
+	padding: u32,
+	shelves: Vec<Shelf>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Shelf {
+	y: u32,
+	height: u32,
+	/// Where the next rectangle goes
+	cursor_x: u32,
+}
+
+impl ShelfPacker {
+	/// Shelves taller than this times the rectangle's height are only used when
+	/// there is no room for a new shelf, otherwise small glyphs would fill up
+	/// the tall shelves and waste most of their height
+	const MAX_SHELF_WASTE: f32 = 1.5;
+
+	pub fn new(size: Extent2<u32>, padding: u32) -> Self {
+		Self {
+			size,
+			padding,
+			shelves: Vec::new(),
+		}
+	}
+
+	pub fn size(&self) -> Extent2<u32> {
+		self.size
+	}
+
+	/// Find a place for a rectangle, `None` if there's no room left
+	pub fn insert(&mut self, size: Extent2<u32>) -> Option<Rect<u32, u32>> {
+		let padded = Extent2::new(size.w + self.padding * 2, size.h + self.padding * 2);
+		let fits = |shelf: &Shelf| shelf.height >= padded.h && self.size.w - shelf.cursor_x >= padded.w;
+
+		// The shelf that wastes the least height
+		let best_shelf = self
+			.shelves
+			.iter()
+			.enumerate()
+			.filter(|(_, shelf)| fits(shelf))
+			.min_by_key(|(_, shelf)| shelf.height - padded.h)
+			.map(|(i, shelf)| (i, shelf.height));
+
+		let next_y = self.shelves.last().map(|shelf| shelf.y + shelf.height).unwrap_or(0);
+		let room_for_shelf = padded.w <= self.size.w && self.size.h - next_y >= padded.h;
+
+		let index = match best_shelf {
+			Some((i, height)) if height as f32 <= padded.h as f32 * Self::MAX_SHELF_WASTE || !room_for_shelf => i,
+			_ if room_for_shelf => {
+				self.shelves.push(Shelf {
+					y: next_y,
+					height: padded.h,
+					cursor_x: 0,
+				});
+				self.shelves.len() - 1
+			}
+			_ => return None,
+		};
+
+		let shelf = &mut self.shelves[index];
+		let rect = Rect::new(shelf.cursor_x + self.padding, shelf.y + self.padding, size.w, size.h);
+		shelf.cursor_x += padded.w;
+
+		Some(rect)
+	}
+
+	/// Make the packing area bigger, keeping everything where it is
+	pub fn grow(&mut self, size: Extent2<u32>) {
+		assert!(size.w >= self.size.w && size.h >= self.size.h);
+		self.size = size;
+	}
+
+	/// The fraction of the area that is covered by shelves, used or not
+	pub fn occupancy(&self) -> f32 {
+		let used = self.shelves.iter().map(|shelf| shelf.height).sum::<u32>();
+		used as f32 / self.size.h as f32
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// A 2D texture that images and glyphs get packed into, so that everything
+/// drawn from it can share one texture and one bind group.
+///
+/// The texture is reallocated twice as big when it is full. Anything bound to
+/// the old one has to be rebuilt, which [`generation`](Self::generation) tells.
+/// The UVs of the existing entries change too, so they should be kept as texel
+/// rects and converted with [`uv_rect`](Self::uv_rect) when drawing.
+pub struct TextureAtlas {
+	label: String,
+	format: TextureFormat,
+	sampler: Option<TexSamplerDescriptor>,
+	bytes_per_texel: u32,
+
+	tex: Sarc<Tex>,
+	packer: ShelfPacker,
+	/// A copy of the whole texture, to re-upload it after growing
+	texels: Vec<u8>,
+	generation: u64,
+}
+
+impl TextureAtlas {
+	pub fn new(
+		gpu: &Gpu,
+		label: &str,
+		size: Extent2<u32>,
+		format: TextureFormat,
+		padding: u32,
+		sampler: Option<TexSamplerDescriptor>,
+	) -> Self {
+		let bytes_per_texel = format
+			.block_copy_size(None)
+			.expect("Atlases can only use uncompressed color formats");
+
+		Self {
+			label: label.to_owned(),
+			format,
+			sampler,
+			bytes_per_texel,
+			tex: Self::create_tex(gpu, label, size, format, sampler).tracked(gpu),
+			packer: ShelfPacker::new(size, padding),
+			texels: vec![0; (size.w * size.h * bytes_per_texel) as usize],
+			generation: 0,
+		}
+	}
+
+	fn create_tex(
+		gpu: &Gpu,
+		label: &str,
+		size: Extent2<u32>,
+		format: TextureFormat,
+		sampler: Option<TexSamplerDescriptor>,
+	) -> Tex {
+		Tex::create(
+			gpu,
+			TexDescriptor {
+				label,
+				dimensions: TextureAssetDimensions::D2(size),
+				format,
+				usage: Some(TextureUsages::TEXTURE_BINDING),
+				aspect: TextureAspect::All,
+			},
+			sampler,
+		)
+	}
+
+	pub fn tex(&self) -> &Sarc<Tex> {
+		&self.tex
+	}
+
+	pub fn size(&self) -> Extent2<u32> {
+		self.packer.size()
+	}
+
+	/// Goes up every time the texture is replaced
+	pub fn generation(&self) -> u64 {
+		self.generation
+	}
+
+	/// Pack tightly packed texels into the atlas, growing it if needed. Only
+	/// the new region is uploaded, unless the atlas had to grow.
+	///
+	/// Returns `None` if the atlas can't grow enough to fit it.
+	pub fn insert(&mut self, gpu: &Gpu, size: Extent2<u32>, bytes: &[u8]) -> Option<Rect<u32, u32>> {
+		assert!(bytes.len() == (size.w * size.h * self.bytes_per_texel) as usize);
+
+		let rect = loop {
+			if let Some(rect) = self.packer.insert(size) {
+				break rect;
+			}
+
+			if !self.grow(gpu) {
+				return None;
+			}
+		};
+
+		self.write_texels(rect, bytes);
+		self.tex.upload_region(gpu, vec2!(rect.x, rect.y), size, bytes);
+
+		Some(rect)
+	}
+
+	/// Only for `Rgba8` atlases
+	pub fn insert_image(&mut self, gpu: &Gpu, img: &image::DynamicImage) -> Option<Rect<u32, u32>> {
+		assert!(matches!(
+			self.format,
+			TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb
+		));
+
+		let rgba = img.to_rgba8();
+		self.insert(gpu, Extent2::new(rgba.width(), rgba.height()), &rgba)
+	}
+
+	/// The rect in UV space, `[0, 1]` over the whole current texture
+	pub fn uv_rect(&self, rect: Rect<u32, u32>) -> Rect<f32, f32> {
+		let size = self.size();
+
+		Rect::new(
+			rect.x as f32 / size.w as f32,
+			rect.y as f32 / size.h as f32,
+			rect.w as f32 / size.w as f32,
+			rect.h as f32 / size.h as f32,
+		)
+	}
+
+	fn write_texels(&mut self, rect: Rect<u32, u32>, bytes: &[u8]) {
+		let row_len = (rect.w * self.bytes_per_texel) as usize;
+		let atlas_row_len = (self.size().w * self.bytes_per_texel) as usize;
+
+		// Empty glyphs like spaces still get a rect, but there's nothing to copy
+		if row_len == 0 {
+			return;
+		}
+
+		for (row, src) in bytes.chunks(row_len).enumerate() {
+			let start = (rect.y as usize + row) * atlas_row_len + (rect.x * self.bytes_per_texel) as usize;
+			self.texels[start..start + row_len].copy_from_slice(src);
+		}
+	}
+
+	/// Double the smaller side of the texture and upload everything again.
+	/// Returns false if it is already as big as the GPU allows.
+	fn grow(&mut self, gpu: &Gpu) -> bool {
+		let old_size = self.size();
+		let new_size = if old_size.w <= old_size.h {
+			Extent2::new(old_size.w * 2, old_size.h)
+		} else {
+			Extent2::new(old_size.w, old_size.h * 2)
+		};
+
+		let max_size = gpu.device.limits().max_texture_dimension_2d;
+		if new_size.w > max_size || new_size.h > max_size {
+			return false;
+		}
+
+		// The rows get longer, so they need to be moved
+		let old_texels = std::mem::replace(
+			&mut self.texels,
+			vec![0; (new_size.w * new_size.h * self.bytes_per_texel) as usize],
+		);
+		self.packer.grow(new_size);
+		self.write_texels(Rect::new(0, 0, old_size.w, old_size.h), &old_texels);
+
+		self.tex = Self::create_tex(gpu, &self.label, new_size, self.format, self.sampler).tracked(gpu);
+		self.tex.upload_region(gpu, vec2!(0, 0), new_size, &self.texels);
+		self.generation += 1;
+
+		true
+	}
+}
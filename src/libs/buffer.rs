@@ -1,3 +1,6 @@
+pub mod atomic_counter;
+pub mod indirect_dispatch;
+pub mod ping_pong_texture;
 pub mod sampled_texture_buffer;
 pub mod storage_buffer;
 pub mod storage_texture_buffer;
@@ -15,6 +18,7 @@ use wgpu::{
 	RenderPass, ShaderStages,
 };
 
+use self::ping_pong_texture::PingPongParity;
 use super::smart_arc::Sarc;
 use crate::core::{gameloop::PreRender, gpu::Gpu};
 
@@ -24,30 +28,72 @@ use crate::core::{gameloop::PreRender, gpu::Gpu};
 --------------------------------------------------------------------------------
 */
 
+/// A type that has a WGSL equivalent.
+///
+/// `WGSL_ALIGN` and `WGSL_SIZE` follow the WGSL memory layout rules for
+/// host-shareable types, they're what `ShaderStruct` checks the Rust layout
+/// against.
 pub trait ShaderType {
+	const WGSL_ALIGN: usize;
+	const WGSL_SIZE: usize;
+	/// Whether the uniform address space can hold the type, which needs the
+	/// stride of its arrays to be a multiple of 16
+	const UNIFORM_COMPATIBLE: bool = true;
+
 	fn type_name() -> String;
 	fn struct_definition() -> Option<String> {
 		None
 	}
+
+	/// The bytes of a value in the WGSL layout, from its bytes in the Rust one.
+	/// They only differ for the types that WGSL pads inside, i.e. the columns of
+	/// a `mat3x3` and the elements of arrays like `array<vec3<f32>, N>`.
+	fn to_wgsl_layout(bytes: &[u8]) -> Vec<u8> {
+		bytes.to_vec()
+	}
 }
 
-#[rustfmt::skip] impl                ShaderType for bool            {fn type_name() -> String {"bool".to_string()}}
-#[rustfmt::skip] impl                ShaderType for i32             {fn type_name() -> String {"i32".to_string()}}
-#[rustfmt::skip] impl                ShaderType for u32             {fn type_name() -> String {"u32".to_string()}}
-#[rustfmt::skip] impl                ShaderType for f32             {fn type_name() -> String {"f32".to_string()}}
-#[rustfmt::skip] impl<T: ShaderType> ShaderType for vek::Vec2<T>    {fn type_name() -> String {format!("vec2<{}>", T::type_name())}}
-#[rustfmt::skip] impl<T: ShaderType> ShaderType for vek::Vec3<T>    {fn type_name() -> String {format!("vec3<{}>", T::type_name())}}
-#[rustfmt::skip] impl<T: ShaderType> ShaderType for vek::Vec4<T>    {fn type_name() -> String {format!("vec4<{}>", T::type_name())}}
-#[rustfmt::skip] impl<T: ShaderType> ShaderType for vek::Extent2<T> {fn type_name() -> String {format!("vec2<{}>", T::type_name())}}
-#[rustfmt::skip] impl<T: ShaderType> ShaderType for vek::Extent3<T> {fn type_name() -> String {format!("vec3<{}>", T::type_name())}}
-#[rustfmt::skip] impl<T: ShaderType> ShaderType for vek::Rgb<T>     {fn type_name() -> String {format!("vec3<{}>", T::type_name())}}
-#[rustfmt::skip] impl<T: ShaderType> ShaderType for vek::Rgba<T>    {fn type_name() -> String {format!("vec4<{}>", T::type_name())}}
-#[rustfmt::skip] impl<T: ShaderType> ShaderType for vek::Mat2<T>    {fn type_name() -> String {format!("mat2x2<{}>", T::type_name())}}
-#[rustfmt::skip] impl<T: ShaderType> ShaderType for vek::Mat3<T>    {fn type_name() -> String {format!("mat3x3<{}>", T::type_name())}}
-#[rustfmt::skip] impl<T: ShaderType> ShaderType for vek::Mat4<T>    {fn type_name() -> String {format!("mat4x4<{}>", T::type_name())}}
-
-#[rustfmt::skip] impl<E: ShaderType>                 ShaderType for [E]    {fn type_name() -> String {format!("array<{}>", E::type_name())}}
-#[rustfmt::skip] impl<E: ShaderType, const N: usize> ShaderType for [E; N] {fn type_name() -> String {format!("array<{},{}>", E::type_name(), N)}}
+/// Round `value` up to the next multiple of `align`
+pub const fn round_up(align: usize, value: usize) -> usize {
+	value.div_ceil(align) * align
+}
+
+/// Split `bytes` into `count` elements of the same size, and pad (or cut)
+/// every converted element to `stride`
+fn relayout_elements(bytes: &[u8], count: usize, stride: usize, convert: impl Fn(&[u8]) -> Vec<u8>) -> Vec<u8> {
+	if count == 0 {
+		return Vec::new();
+	}
+
+	bytes
+		.chunks_exact(bytes.len() / count)
+		.flat_map(|element| {
+			let mut element = convert(element);
+			element.resize(stride, 0);
+			element
+		})
+		.collect()
+}
+
+#[rustfmt::skip] impl                ShaderType for bool            {const WGSL_ALIGN: usize = 4;                const WGSL_SIZE: usize = 4;                                                     fn type_name() -> String {"bool".to_string()}}
+#[rustfmt::skip] impl                ShaderType for i32             {const WGSL_ALIGN: usize = 4;                const WGSL_SIZE: usize = 4;                                                     fn type_name() -> String {"i32".to_string()}}
+#[rustfmt::skip] impl                ShaderType for u32             {const WGSL_ALIGN: usize = 4;                const WGSL_SIZE: usize = 4;                                                     fn type_name() -> String {"u32".to_string()}}
+#[rustfmt::skip] impl                ShaderType for f32             {const WGSL_ALIGN: usize = 4;                const WGSL_SIZE: usize = 4;                                                     fn type_name() -> String {"f32".to_string()}}
+#[rustfmt::skip] impl<T: ShaderType> ShaderType for vek::Vec2<T>    {const WGSL_ALIGN: usize = 2 * T::WGSL_SIZE; const WGSL_SIZE: usize = 2 * T::WGSL_SIZE;                                      fn type_name() -> String {format!("vec2<{}>", T::type_name())}}
+#[rustfmt::skip] impl<T: ShaderType> ShaderType for vek::Vec3<T>    {const WGSL_ALIGN: usize = 4 * T::WGSL_SIZE; const WGSL_SIZE: usize = 3 * T::WGSL_SIZE;                                      fn type_name() -> String {format!("vec3<{}>", T::type_name())}}
+#[rustfmt::skip] impl<T: ShaderType> ShaderType for vek::Vec4<T>    {const WGSL_ALIGN: usize = 4 * T::WGSL_SIZE; const WGSL_SIZE: usize = 4 * T::WGSL_SIZE;                                      fn type_name() -> String {format!("vec4<{}>", T::type_name())}}
+#[rustfmt::skip] impl<T: ShaderType> ShaderType for vek::Extent2<T> {const WGSL_ALIGN: usize = 2 * T::WGSL_SIZE; const WGSL_SIZE: usize = 2 * T::WGSL_SIZE;                                      fn type_name() -> String {format!("vec2<{}>", T::type_name())}}
+#[rustfmt::skip] impl<T: ShaderType> ShaderType for vek::Extent3<T> {const WGSL_ALIGN: usize = 4 * T::WGSL_SIZE; const WGSL_SIZE: usize = 3 * T::WGSL_SIZE;                                      fn type_name() -> String {format!("vec3<{}>", T::type_name())}}
+#[rustfmt::skip] impl<T: ShaderType> ShaderType for vek::Rgb<T>     {const WGSL_ALIGN: usize = 4 * T::WGSL_SIZE; const WGSL_SIZE: usize = 3 * T::WGSL_SIZE;                                      fn type_name() -> String {format!("vec3<{}>", T::type_name())}}
+#[rustfmt::skip] impl<T: ShaderType> ShaderType for vek::Rgba<T>    {const WGSL_ALIGN: usize = 4 * T::WGSL_SIZE; const WGSL_SIZE: usize = 4 * T::WGSL_SIZE;                                      fn type_name() -> String {format!("vec4<{}>", T::type_name())}}
+// Matrices are laid out as an array of column vectors
+#[rustfmt::skip] impl<T: ShaderType> ShaderType for vek::Mat2<T>    {const WGSL_ALIGN: usize = 2 * T::WGSL_SIZE; const WGSL_SIZE: usize = 2 * round_up(2 * T::WGSL_SIZE, 2 * T::WGSL_SIZE);  fn type_name() -> String {format!("mat2x2<{}>", T::type_name())}}
+#[rustfmt::skip] impl<T: ShaderType> ShaderType for vek::Mat3<T>    {const WGSL_ALIGN: usize = 4 * T::WGSL_SIZE; const WGSL_SIZE: usize = 3 * round_up(4 * T::WGSL_SIZE, 3 * T::WGSL_SIZE);  fn type_name() -> String {format!("mat3x3<{}>", T::type_name())} fn to_wgsl_layout(bytes: &[u8]) -> Vec<u8> {relayout_elements(bytes, 3, round_up(4 * T::WGSL_SIZE, 3 * T::WGSL_SIZE), <[u8]>::to_vec)}}
+#[rustfmt::skip] impl<T: ShaderType> ShaderType for vek::Mat4<T>    {const WGSL_ALIGN: usize = 4 * T::WGSL_SIZE; const WGSL_SIZE: usize = 4 * round_up(4 * T::WGSL_SIZE, 4 * T::WGSL_SIZE);  fn type_name() -> String {format!("mat4x4<{}>", T::type_name())}}
+
+// A runtime-sized array has no static size, it can only be the last field of a storage struct
+#[rustfmt::skip] impl<E: ShaderType>                 ShaderType for [E]    {const WGSL_ALIGN: usize = E::WGSL_ALIGN; const WGSL_SIZE: usize = 0;                                           fn type_name() -> String {format!("array<{}>", E::type_name())} fn struct_definition() -> Option<String> {E::struct_definition()}}
+#[rustfmt::skip] impl<E: ShaderType, const N: usize> ShaderType for [E; N] {const WGSL_ALIGN: usize = E::WGSL_ALIGN; const WGSL_SIZE: usize = N * round_up(E::WGSL_ALIGN, E::WGSL_SIZE); const UNIFORM_COMPATIBLE: bool = E::UNIFORM_COMPATIBLE && round_up(E::WGSL_ALIGN, E::WGSL_SIZE) % 16 == 0; fn type_name() -> String {format!("array<{},{}>", E::type_name(), N)} fn struct_definition() -> Option<String> {E::struct_definition()} fn to_wgsl_layout(bytes: &[u8]) -> Vec<u8> {relayout_elements(bytes, N, round_up(E::WGSL_ALIGN, E::WGSL_SIZE), E::to_wgsl_layout)}}
 
 // Incompatible:
 // impl WgslType for f16 {fn name() -> String {format!("f16")}}
@@ -68,6 +114,31 @@ impl<T: ShaderType + bytemuck::Pod + Sized + std::fmt::Debug> BufferUploadable f
 	}
 }
 
+/// Like [`BufferUploadable`], but for data whose size is only known at runtime,
+/// like a [`StorageArray`](storage_buffer::StorageArray). Anything that is
+/// `BufferUploadable` is also `DynBufferUploadable`.
+pub trait DynBufferUploadable: std::fmt::Debug + ShaderType {
+	/// The smallest size that any value of the type can have, used as the
+	/// `min_binding_size` of the buffers holding it
+	fn get_min_size() -> u64;
+	fn get_dyn_size(&self) -> u64;
+	fn get_dyn_bytes(&self) -> Vec<u8>;
+}
+
+impl<T: BufferUploadable> DynBufferUploadable for T {
+	fn get_min_size() -> u64 {
+		T::get_size()
+	}
+
+	fn get_dyn_size(&self) -> u64 {
+		T::get_size()
+	}
+
+	fn get_dyn_bytes(&self) -> Vec<u8> {
+		self.get_bytes()
+	}
+}
+
 /*
 --------------------------------------------------------------------------------
 ||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
@@ -96,9 +167,18 @@ pub trait ShaderBufferDescriptor {
 
 pub trait ShaderBufferResource {
 	fn binding_source_code(&self, group: u32, binding: u32) -> Vec<String>;
+	/// `struct` and `const` declarations needed by the bindings, merged by name
+	/// with those of the other resources
 	fn other_source_code(&self) -> Option<&str>;
 	fn layouts(&self, features: Features) -> Vec<PartialLayoutEntry>;
 	fn binding_resources(&self) -> Vec<BindingResource>;
+
+	/// The binding resources to use on the frames where the ping-pong textures
+	/// are swapped, see [`ping_pong_texture`]. Only resources that need to change
+	/// their bindings every other frame should override this.
+	fn swapped_binding_resources(&self) -> Option<Vec<BindingResource>> {
+		None
+	}
 }
 
 /*
@@ -111,6 +191,18 @@ pub struct ShaderBufferBindGroup {
 	pub index: u32,
 	pub bind_group_layout: BindGroupLayout,
 	pub bind_group: BindGroup,
+	/// Only present if one of the resources has swapped bindings
+	pub swapped_bind_group: Option<BindGroup>,
+}
+
+impl ShaderBufferBindGroup {
+	/// The bind group variant to use for the frame of `parity`
+	pub fn current(&self, parity: PingPongParity) -> &BindGroup {
+		match &self.swapped_bind_group {
+			Some(swapped_bind_group) if parity.swapped => swapped_bind_group,
+			_ => &self.bind_group,
+		}
+	}
 }
 
 impl Debug for ShaderBufferBindGroup {
@@ -121,19 +213,21 @@ impl Debug for ShaderBufferBindGroup {
 	}
 }
 
+/// Binds the variant of the bind group for the frame of the [`PingPongParity`]
+/// resource
 pub trait BufferMappingApplicable<'a> {
-	fn apply_buffer_mapping(&mut self, buffer_mapping: &'a ShaderBufferBindGroup);
+	fn apply_buffer_mapping(&mut self, buffer_mapping: &'a ShaderBufferBindGroup, parity: PingPongParity);
 }
 
 impl<'a> BufferMappingApplicable<'a> for ComputePass<'a> {
-	fn apply_buffer_mapping(&mut self, buffer_mapping: &'a ShaderBufferBindGroup) {
-		self.set_bind_group(buffer_mapping.index, &buffer_mapping.bind_group, &[]);
+	fn apply_buffer_mapping(&mut self, buffer_mapping: &'a ShaderBufferBindGroup, parity: PingPongParity) {
+		self.set_bind_group(buffer_mapping.index, buffer_mapping.current(parity), &[]);
 	}
 }
 
 impl<'a> BufferMappingApplicable<'a> for RenderPass<'a> {
-	fn apply_buffer_mapping(&mut self, buffer_mapping: &'a ShaderBufferBindGroup) {
-		self.set_bind_group(buffer_mapping.index, &buffer_mapping.bind_group, &[]);
+	fn apply_buffer_mapping(&mut self, buffer_mapping: &'a ShaderBufferBindGroup, parity: PingPongParity) {
+		self.set_bind_group(buffer_mapping.index, buffer_mapping.current(parity), &[]);
 	}
 }
 
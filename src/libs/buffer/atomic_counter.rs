@@ -0,0 +1,192 @@
+use std::{mem, sync::Arc};
+
+use bevy_ecs::{
+	query::With,
+	system::{Query, Res},
+};
+use brainrot::bevy::{self, App};
+use wgpu::{
+	BindingResource, BindingType, Buffer, BufferBindingType, BufferDescriptor, BufferUsages, CommandEncoderDescriptor,
+	Features, Maintain, MapMode,
+};
+
+use super::{PartialLayoutEntry, ShaderBufferDescriptor, ShaderBufferResource};
+use crate::{
+	core::{gameloop::PreRender, gpu::Gpu},
+	libs::smart_arc::Sarc,
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// An array of `count` atomic `u32` counters, bound as
+/// `var<storage, read_write> var_name: array<atomic<u32>, count>;`
+pub enum AtomicCounterDescriptor<S: Into<String> + Clone> {
+	New { var_name: S, count: u32 },
+	FromBuffer { var_name: S, buffer: Sarc<Buffer> },
+}
+
+impl<S: Into<String> + Clone> ShaderBufferDescriptor for AtomicCounterDescriptor<S> {
+	fn as_resource(&self, gpu: &Gpu) -> Sarc<dyn ShaderBufferResource> {
+		let resource = match self {
+			AtomicCounterDescriptor::New { var_name, count } => {
+				AtomicCounter::new_from_count(gpu, *count, var_name.to_owned().into())
+			}
+			AtomicCounterDescriptor::FromBuffer { var_name, buffer } => {
+				AtomicCounter::new(buffer.clone(), var_name.to_owned().into())
+			}
+		};
+
+		Sarc(Arc::new(resource) as Arc<dyn ShaderBufferResource>)
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+#[derive(bevy::Component)]
+pub struct AtomicCounter {
+	pub buffer: Sarc<Buffer>,
+	pub var_name: String,
+	pub count: u32,
+}
+
+impl AtomicCounter {
+	const COUNTER_SIZE: u64 = mem::size_of::<u32>() as u64;
+
+	pub fn new_from_count(gpu: &Gpu, count: u32, var_name: String) -> Self {
+		Self::new(
+			Sarc::new(Self::raw_buffer_from_count(
+				gpu,
+				count,
+				Some(&format!("AtomicCounter<{}> '{}'", count, var_name)),
+			)),
+			var_name,
+		)
+	}
+
+	pub fn new(buffer: Sarc<Buffer>, var_name: String) -> Self {
+		let count = (buffer.size() / Self::COUNTER_SIZE) as u32;
+
+		Self {
+			buffer,
+			var_name,
+			count,
+		}
+	}
+
+	pub fn raw_buffer_from_count(gpu: &Gpu, count: u32, label: Option<&str>) -> Buffer {
+		gpu.device.create_buffer(&BufferDescriptor {
+			label: label.or(Some(&format!("AtomicCounter<{}>", count))),
+			size: count as u64 * Self::COUNTER_SIZE,
+			// COPY_SRC for the readback
+			usage: BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+			mapped_at_creation: false,
+		})
+	}
+
+	/// Copy the counters back from the GPU. Blocks until the copy is done.
+	///
+	/// The counters are only written by the render passes, so this returns the
+	/// values of the *last rendered frame*: when called from `Update` or
+	/// `PreRender` there is always a one-frame latency. If the counter is
+	/// cleared every frame (see [`ClearEachFrame`]), make sure to read it before
+	/// the clear in `PreRender`, otherwise this will return all zeros.
+	pub fn readback(gpu: &Gpu, buffer: &Buffer) -> Vec<u32> {
+		let staging_buffer = gpu.device.create_buffer(&BufferDescriptor {
+			label: Some("AtomicCounter Readback Buffer"),
+			size: buffer.size(),
+			usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+
+		let mut encoder = gpu.device.create_command_encoder(&CommandEncoderDescriptor {
+			label: Some("AtomicCounter Readback Command Encoder"),
+		});
+		encoder.copy_buffer_to_buffer(buffer, 0, &staging_buffer, 0, buffer.size());
+		gpu.queue.submit([encoder.finish()]);
+
+		let slice = staging_buffer.slice(..);
+		slice.map_async(MapMode::Read, |result| result.expect("Couldn't map the readback buffer"));
+		gpu.device.poll(Maintain::Wait);
+
+		let counters = bytemuck::cast_slice::<u8, u32>(&slice.get_mapped_range()).to_vec();
+		staging_buffer.unmap();
+
+		counters
+	}
+}
+
+impl ShaderBufferResource for AtomicCounter {
+	fn binding_source_code(&self, group: u32, binding: u32) -> Vec<String> {
+		vec![format!(
+			"@group({}) @binding({}) var<storage, read_write> {}: array<atomic<u32>, {}>;",
+			group, binding, self.var_name, self.count
+		)]
+	}
+
+	fn other_source_code(&self) -> Option<&str> {
+		None
+	}
+
+	fn layouts(&self, _features: Features) -> Vec<PartialLayoutEntry> {
+		vec![PartialLayoutEntry {
+			ty: BindingType::Buffer {
+				ty: BufferBindingType::Storage { read_only: false },
+				has_dynamic_offset: false,
+				min_binding_size: None,
+			},
+			count: None,
+		}]
+	}
+
+	fn binding_resources(&self) -> Vec<BindingResource> {
+		vec![self.buffer.as_entire_binding()]
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Marker for buffers that should be zeroed at the start of every frame.
+/// Meant for counters that count something per frame, as opposed to counters
+/// that accumulate over time.
+#[derive(bevy::Component, Copy, Clone, Debug, Default)]
+pub struct ClearEachFrame;
+
+/// Spawn a counter buffer so it can be found by the ECS, optionally clearing
+/// it every frame.
+pub fn spawn_counter(app: &mut App, buffer: Sarc<Buffer>, clear_each_frame: bool) {
+	if clear_each_frame {
+		register_auto_clear(app);
+		app.world.spawn((buffer, ClearEachFrame));
+	} else {
+		app.world.spawn(buffer);
+	}
+}
+
+#[derive(bevy::Resource)]
+struct AutoClearRegistered;
+
+pub fn register_auto_clear(app: &mut App) {
+	// The system clears all the marked buffers at once, so only add it once
+	if !app.world.contains_resource::<AutoClearRegistered>() {
+		app.world.insert_resource(AutoClearRegistered);
+		app.add_systems(PreRender, clear_buffers_system);
+	}
+}
+
+fn clear_buffers_system(gpu: Res<Gpu>, q: Query<&Sarc<Buffer>, With<ClearEachFrame>>) {
+	for buffer in q.iter() {
+		buffer.upload_bytes(&gpu, &vec![0; buffer.size() as usize], 0);
+	}
+}
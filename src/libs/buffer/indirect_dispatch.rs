@@ -0,0 +1,193 @@
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use brainrot::bevy::{self};
+use pbr_tracer_derive::ShaderStruct;
+use wgpu::{
+	util::{BufferInitDescriptor, DeviceExt},
+	BindingResource, BindingType, Buffer, BufferBindingType, BufferDescriptor, BufferUsages, Features,
+	COPY_BUFFER_ALIGNMENT,
+};
+
+use super::{BufferUploadable, PartialLayoutEntry, ShaderBufferDescriptor, ShaderBufferResource, ShaderType};
+use crate::{core::gpu::Gpu, libs::smart_arc::Sarc};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// The arguments of `dispatch_workgroups_indirect`, laid out the way wgpu
+/// expects them: the number of workgroups in x, y and z.
+#[repr(C)]
+#[derive(ShaderStruct, bytemuck::Pod, bytemuck::Zeroable, bevy::Component, Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DispatchIndirectArgs {
+	pub x: u32,
+	pub y: u32,
+	pub z: u32,
+}
+
+impl Default for DispatchIndirectArgs {
+	fn default() -> Self {
+		Self { x: 0, y: 0, z: 1 }
+	}
+}
+
+/// The buffer holding the [`DispatchIndirectArgs`] that a compute pass writes
+/// for a later pass to be dispatched with, bound as
+/// `var<storage, read_write> var_name: DispatchIndirectArgs;`
+pub enum IndirectDispatchDescriptor<S: Into<String> + Clone> {
+	New { var_name: S, args: DispatchIndirectArgs },
+	FromBuffer { var_name: S, buffer: Sarc<Buffer> },
+}
+
+impl<S: Into<String> + Clone> ShaderBufferDescriptor for IndirectDispatchDescriptor<S> {
+	fn as_resource(&self, gpu: &Gpu) -> Sarc<dyn ShaderBufferResource> {
+		let resource = match self {
+			IndirectDispatchDescriptor::New { var_name, args } => {
+				IndirectDispatchBuffer::new_from_args(gpu, args, var_name.to_owned().into())
+			}
+			IndirectDispatchDescriptor::FromBuffer { var_name, buffer } => {
+				IndirectDispatchBuffer::new(buffer.clone(), var_name.to_owned().into())
+			}
+		};
+
+		Sarc(Arc::new(resource) as Arc<dyn ShaderBufferResource>)
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+#[derive(bevy::Component)]
+pub struct IndirectDispatchBuffer {
+	pub buffer: Sarc<Buffer>,
+	pub var_name: String,
+	struct_definition: Option<String>,
+}
+
+impl IndirectDispatchBuffer {
+	pub fn new_from_args(gpu: &Gpu, args: &DispatchIndirectArgs, var_name: String) -> Self {
+		Self::new(
+			Sarc::new(Self::raw_buffer_from_args(
+				gpu,
+				args,
+				Some(&format!("IndirectDispatchBuffer '{}'", var_name)),
+			)),
+			var_name,
+		)
+	}
+
+	pub fn new(buffer: Sarc<Buffer>, var_name: String) -> Self {
+		Self {
+			buffer,
+			var_name,
+			struct_definition: DispatchIndirectArgs::struct_definition(),
+		}
+	}
+
+	pub fn raw_buffer_from_args(gpu: &Gpu, args: &DispatchIndirectArgs, label: Option<&str>) -> Buffer {
+		gpu.device.create_buffer_init(&BufferInitDescriptor {
+			label: label.or(Some("IndirectDispatchBuffer")),
+			contents: &args.get_bytes(),
+			// STORAGE for the pass that writes the args, INDIRECT for the pass that is
+			// dispatched with them
+			usage: BufferUsages::STORAGE | BufferUsages::INDIRECT | BufferUsages::COPY_DST,
+		})
+	}
+
+	/// Check that the buffer can be used with `dispatch_workgroups_indirect`
+	pub fn validate(buffer: &Buffer) -> Result<()> {
+		if buffer.size() < DispatchIndirectArgs::get_size() {
+			bail!(
+				"Indirect dispatch buffer is too small: {} bytes, needs at least {}",
+				buffer.size(),
+				DispatchIndirectArgs::get_size()
+			);
+		}
+
+		if !buffer.usage().contains(BufferUsages::INDIRECT) {
+			bail!(
+				"Indirect dispatch buffer is missing the INDIRECT usage: {:?}",
+				buffer.usage()
+			);
+		}
+
+		Ok(())
+	}
+
+	/// Check that the args at `offset` can be copied to a buffer made by
+	/// [`raw_copy_target`](Self::raw_copy_target)
+	pub fn validate_copy_source(buffer: &Buffer, offset: u64) -> Result<()> {
+		if offset % COPY_BUFFER_ALIGNMENT != 0 {
+			bail!(
+				"Indirect dispatch args offset {} isn't a multiple of {}",
+				offset,
+				COPY_BUFFER_ALIGNMENT
+			);
+		}
+
+		if buffer.size() < offset + DispatchIndirectArgs::get_size() {
+			bail!(
+				"Indirect dispatch buffer is too small: {} bytes, needs at least {} for the args at {}",
+				buffer.size(),
+				offset + DispatchIndirectArgs::get_size(),
+				offset
+			);
+		}
+
+		if !buffer.usage().contains(BufferUsages::COPY_SRC) {
+			bail!(
+				"Indirect dispatch buffer is missing the COPY_SRC usage: {:?}",
+				buffer.usage()
+			);
+		}
+
+		Ok(())
+	}
+
+	/// A buffer only used for the dispatch, that the args are copied into
+	pub fn raw_copy_target(gpu: &Gpu, label: Option<&str>) -> Buffer {
+		gpu.device.create_buffer(&BufferDescriptor {
+			label: label.or(Some("IndirectDispatchBuffer copy target")),
+			size: DispatchIndirectArgs::get_size(),
+			usage: BufferUsages::INDIRECT | BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		})
+	}
+}
+
+impl ShaderBufferResource for IndirectDispatchBuffer {
+	fn binding_source_code(&self, group: u32, binding: u32) -> Vec<String> {
+		vec![format!(
+			"@group({}) @binding({}) var<storage, read_write> {}: {};",
+			group,
+			binding,
+			self.var_name,
+			DispatchIndirectArgs::type_name()
+		)]
+	}
+
+	fn other_source_code(&self) -> Option<&str> {
+		self.struct_definition.as_deref()
+	}
+
+	fn layouts(&self, _features: Features) -> Vec<PartialLayoutEntry> {
+		vec![PartialLayoutEntry {
+			ty: BindingType::Buffer {
+				ty: BufferBindingType::Storage { read_only: false },
+				has_dynamic_offset: false,
+				min_binding_size: None,
+			},
+			count: None,
+		}]
+	}
+
+	fn binding_resources(&self) -> Vec<BindingResource> {
+		vec![self.buffer.as_entire_binding()]
+	}
+}
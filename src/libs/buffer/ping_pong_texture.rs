@@ -0,0 +1,181 @@
+use std::sync::Arc;
+
+use brainrot::bevy::{self};
+use wgpu::{
+	BindingResource, BindingType, Features, StorageTextureAccess, TextureAspect, TextureDimension, TextureFormat,
+	TextureUsages, TextureViewDimension,
+};
+
+use super::{ShaderBufferDescriptor, ShaderBufferResource};
+use crate::{
+	core::gpu::Gpu,
+	libs::{
+		buffer::PartialLayoutEntry,
+		smart_arc::Sarc,
+		texture::{self, InitPolicy, Tex, TexDescriptor, TextureAssetDimensions},
+	},
+};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Which of the two textures of every ping-pong texture is read from on the
+/// current frame. They all swap in lockstep once per frame, so a single parity
+/// is enough to know which of the two bind group variants to use.
+#[derive(bevy::Resource, Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub struct PingPongParity {
+	pub swapped: bool,
+}
+
+impl PingPongParity {
+	/// Swap the read and write textures of every ping-pong texture.
+	/// Called once per frame by the `PostRenderPass`.
+	pub fn swap(&mut self) {
+		self.swapped = !self.swapped;
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// A double-buffered storage texture, for passes that need to read the result
+/// of the previous frame while writing the current one.
+///
+/// The shader gets two bindings: `{var_name}_read` (read-only, last frame) and
+/// `{var_name}_write` (write-only, this frame). The two underlying textures
+/// swap roles every frame.
+pub struct PingPongTexture<S: Into<String> + Clone> {
+	pub var_name: S,
+	pub dimensions: TextureAssetDimensions,
+	pub format: TextureFormat,
+	pub usage: Option<TextureUsages>,
+	/// Applied to both textures, the first frame reads one of them before
+	/// anything was written to it
+	pub init: InitPolicy,
+}
+
+impl<S: Into<String> + Clone> ShaderBufferDescriptor for PingPongTexture<S> {
+	fn as_resource(&self, gpu: &Gpu) -> Sarc<dyn ShaderBufferResource> {
+		let var_name: String = self.var_name.to_owned().into();
+
+		let create_tex = |index: usize| {
+			let tex = Tex::create(
+				gpu,
+				TexDescriptor {
+					label: &format!("PingPongTexture '{}' #{}", var_name, index),
+					dimensions: self.dimensions,
+					format: self.format,
+					usage: self.usage,
+					aspect: TextureAspect::All,
+				},
+				None,
+			);
+			tex.initialize(gpu, self.init);
+
+			tex.tracked(gpu)
+		};
+
+		let resource = PingPongTextureResource {
+			textures: [create_tex(0), create_tex(1)],
+			var_name,
+			dimension: self.dimensions.get_dimension().compatible_texture_dimension(),
+			view_dimension: self.dimensions.get_dimension(),
+			format: self.format,
+		};
+
+		Sarc(Arc::new(resource) as Arc<dyn ShaderBufferResource>)
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+pub struct PingPongTextureResource {
+	pub textures: [Sarc<Tex>; 2],
+	pub var_name: String,
+	pub dimension: TextureDimension,
+	pub view_dimension: TextureViewDimension,
+	pub format: TextureFormat,
+}
+
+impl PingPongTextureResource {
+	/// The texture that is read from during the frame of `parity`
+	pub fn current_read(&self, parity: PingPongParity) -> &Sarc<Tex> {
+		&self.textures[parity.swapped as usize]
+	}
+
+	/// The texture that is written to during the frame of `parity`
+	pub fn current_write(&self, parity: PingPongParity) -> &Sarc<Tex> {
+		&self.textures[!parity.swapped as usize]
+	}
+}
+
+impl ShaderBufferResource for PingPongTextureResource {
+	fn binding_source_code(&self, group: u32, binding: u32) -> Vec<String> {
+		let dimension = texture::dimension_to_string(self.dimension);
+		let format = texture::format_to_string(self.format);
+
+		vec![
+			format!(
+				"@group({}) @binding({}) var {}_read: texture_storage_{}<{}, read>;",
+				group, binding, self.var_name, dimension, format
+			),
+			format!(
+				"@group({}) @binding({}) var {}_write: texture_storage_{}<{}, write>;",
+				group,
+				binding + 1,
+				self.var_name,
+				dimension,
+				format
+			),
+		]
+	}
+
+	fn other_source_code(&self) -> Option<&str> {
+		None
+	}
+
+	fn layouts(&self, _features: Features) -> Vec<PartialLayoutEntry> {
+		vec![
+			PartialLayoutEntry {
+				ty: BindingType::StorageTexture {
+					access: StorageTextureAccess::ReadOnly,
+					format: self.format,
+					view_dimension: self.view_dimension,
+				},
+				count: None,
+			},
+			PartialLayoutEntry {
+				ty: BindingType::StorageTexture {
+					access: StorageTextureAccess::WriteOnly,
+					format: self.format,
+					view_dimension: self.view_dimension,
+				},
+				count: None,
+			},
+		]
+	}
+
+	fn binding_resources(&self) -> Vec<BindingResource> {
+		vec![
+			BindingResource::TextureView(&self.textures[0].view),
+			BindingResource::TextureView(&self.textures[1].view),
+		]
+	}
+
+	fn swapped_binding_resources(&self) -> Option<Vec<BindingResource>> {
+		Some(vec![
+			BindingResource::TextureView(&self.textures[1].view),
+			BindingResource::TextureView(&self.textures[0].view),
+		])
+	}
+}
@@ -1,6 +1,7 @@
 use std::sync::Arc;
 
-use image::DynamicImage;
+use brainrot::vek::Extent2;
+use image::{imageops::FilterType, DynamicImage, Rgba, RgbaImage};
 use wgpu::{
 	BindingResource, BindingType, CompareFunction, Features, FilterMode, SamplerBindingType, TextureAspect,
 	TextureDimension, TextureFormat, TextureUsages, TextureViewDimension,
@@ -44,6 +45,19 @@ pub enum SampledTexture<S: Into<String> + Clone> {
 		edges: SamplerEdges,
 		compare: Option<CompareFunction>,
 	},
+	/// A `texture_2d_array`, with one layer per image. Every image is resized to
+	/// the size of the first one, and no images at all gives a single white
+	/// layer.
+	FromImageArray {
+		texture_var_name: S,
+		sampler_var_name: S,
+		images: Vec<DynamicImage>,
+		format: TextureFormat,
+		usage: Option<TextureUsages>,
+		filter: FilterMode,
+		edges: SamplerEdges,
+		compare: Option<CompareFunction>,
+	},
 	FromTex {
 		texture_var_name: S,
 		sampler_var_name: S,
@@ -68,7 +82,7 @@ impl<S: Into<String> + Clone> ShaderBufferDescriptor for SampledTexture<S> {
 				let texture_var_name = texture_var_name.to_owned().into();
 				let sampler_var_name = sampler_var_name.to_owned().into();
 
-				let tex = Sarc::new(Tex::create(
+				let tex = Tex::create(
 					gpu,
 					TexDescriptor {
 						label: &format!("SampledTexture '{}/{}'", texture_var_name, sampler_var_name),
@@ -82,7 +96,8 @@ impl<S: Into<String> + Clone> ShaderBufferDescriptor for SampledTexture<S> {
 						edges: *edges,
 						compare: *compare,
 					}),
-				));
+				)
+				.tracked(gpu);
 
 				SampledTextureResource {
 					tex,
@@ -107,7 +122,7 @@ impl<S: Into<String> + Clone> ShaderBufferDescriptor for SampledTexture<S> {
 				let texture_var_name = texture_var_name.to_owned().into();
 				let sampler_var_name = sampler_var_name.to_owned().into();
 
-				let tex = Sarc::new(Tex::from_image(
+				let tex = Tex::from_image(
 					gpu,
 					&format!("SampledTexture '{}/{}'", texture_var_name, sampler_var_name),
 					image,
@@ -118,7 +133,8 @@ impl<S: Into<String> + Clone> ShaderBufferDescriptor for SampledTexture<S> {
 						edges: *edges,
 						compare: *compare,
 					}),
-				));
+				)
+				.tracked(gpu);
 
 				SampledTextureResource {
 					tex,
@@ -130,6 +146,64 @@ impl<S: Into<String> + Clone> ShaderBufferDescriptor for SampledTexture<S> {
 				}
 			}
 
+			SampledTexture::FromImageArray {
+				texture_var_name,
+				sampler_var_name,
+				images,
+				format,
+				usage,
+				filter,
+				edges,
+				compare,
+			} => {
+				let texture_var_name = texture_var_name.to_owned().into();
+				let sampler_var_name = sampler_var_name.to_owned().into();
+
+				let white = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba([255; 4])));
+				let first = images.first().unwrap_or(&white);
+				let size = Extent2::new(first.width(), first.height());
+				let layers = images.len().max(1) as u32;
+
+				let tex = Tex::create(
+					gpu,
+					TexDescriptor {
+						label: &format!("SampledTexture '{}/{}'", texture_var_name, sampler_var_name),
+						dimensions: TextureAssetDimensions::D2Array(size, layers),
+						format: *format,
+						usage: *usage,
+						aspect: TextureAspect::All,
+					},
+					Some(TexSamplerDescriptor {
+						filter: *filter,
+						edges: *edges,
+						compare: *compare,
+					}),
+				)
+				.tracked(gpu);
+
+				if images.is_empty() {
+					tex.upload_image_layer(gpu, &white, 0);
+				}
+
+				for (layer, image) in images.iter().enumerate() {
+					if image.width() == size.w && image.height() == size.h {
+						tex.upload_image_layer(gpu, image, layer as u32);
+					} else {
+						let resized = image.resize_exact(size.w, size.h, FilterType::Triangle);
+						tex.upload_image_layer(gpu, &resized, layer as u32);
+					}
+				}
+
+				SampledTextureResource {
+					tex,
+					texture_var_name,
+					sampler_var_name,
+					dimension: TextureDimension::D2,
+					view_dimension: TextureViewDimension::D2Array,
+					format: *format,
+				}
+			}
+
 			SampledTexture::FromTex {
 				texture_var_name,
 				sampler_var_name,
@@ -165,13 +239,14 @@ pub struct SampledTextureResource {
 
 impl ShaderBufferResource for SampledTextureResource {
 	fn binding_source_code(&self, group: u32, binding: u32) -> Vec<String> {
-		let dimension = texture::dimension_to_string(self.dimension);
+		// The view decides the WGSL type, a 2D texture can be bound as an array
+		let texture_type = texture::view_dimension_to_string(self.view_dimension);
 		let sample_type = texture::format_to_type_string(self.format);
 
 		vec![
 			format!(
-				"@group({}) @binding({}) var {}: texture_{}<{}>;",
-				group, binding, self.texture_var_name, dimension, sample_type
+				"@group({}) @binding({}) var {}: {}<{}>;",
+				group, binding, self.texture_var_name, texture_type, sample_type
 			),
 			format!(
 				"@group({}) @binding({}) var {}: sampler;",
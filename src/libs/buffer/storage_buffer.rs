@@ -1,13 +1,21 @@
-use std::sync::Arc;
+use std::{num::NonZero, ops::Range, sync::Arc};
 
-use brainrot::bevy;
+use bevy_ecs::system::{Query, Res};
+use brainrot::bevy::{self, App};
+use derive_more::{Deref, DerefMut};
 use wgpu::{
 	util::{BufferInitDescriptor, DeviceExt},
 	BindingResource, BindingType, Buffer, BufferBindingType, BufferDescriptor, BufferUsages, Features,
 };
 
-use super::{BufferUploadable, PartialLayoutEntry, ShaderBufferDescriptor, ShaderBufferResource};
-use crate::{core::gpu::Gpu, libs::smart_arc::Sarc};
+use super::{
+	relayout_elements, round_up, BufferUploadable, DynBufferUploadable, PartialLayoutEntry, ShaderBufferDescriptor,
+	ShaderBufferResource, ShaderType,
+};
+use crate::{
+	core::{gameloop::PreRender, gpu::Gpu},
+	libs::smart_arc::Sarc,
+};
 
 /*
 --------------------------------------------------------------------------------
@@ -17,7 +25,7 @@ use crate::{core::gpu::Gpu, libs::smart_arc::Sarc};
 
 pub enum StorageBufferDescriptor<T, S>
 where
-	T: BufferUploadable,
+	T: DynBufferUploadable,
 	S: Into<String> + Clone,
 {
 	New {
@@ -39,7 +47,7 @@ where
 
 impl<T, S> ShaderBufferDescriptor for StorageBufferDescriptor<T, S>
 where
-	T: BufferUploadable,
+	T: DynBufferUploadable,
 	S: Into<String> + Clone,
 {
 	fn as_resource(&self, gpu: &Gpu) -> Sarc<dyn ShaderBufferResource> {
@@ -78,10 +86,11 @@ pub struct StorageBuffer {
 	pub read_only: bool,
 	type_name: String,
 	struct_definition: Option<String>,
+	min_binding_size: Option<NonZero<u64>>,
 }
 
 impl StorageBuffer {
-	pub fn new_from_size<T: BufferUploadable>(gpu: &Gpu, size: u64, var_name: String, read_only: bool) -> Self {
+	pub fn new_from_size<T: DynBufferUploadable>(gpu: &Gpu, size: u64, var_name: String, read_only: bool) -> Self {
 		Self::new::<T>(
 			Sarc::new(Self::raw_buffer_from_size(
 				gpu,
@@ -93,7 +102,7 @@ impl StorageBuffer {
 		)
 	}
 
-	pub fn new_from_data<T: BufferUploadable>(gpu: &Gpu, data: &T, var_name: String, read_only: bool) -> Self {
+	pub fn new_from_data<T: DynBufferUploadable>(gpu: &Gpu, data: &T, var_name: String, read_only: bool) -> Self {
 		Self::new::<T>(
 			Sarc::new(Self::raw_buffer_from_data::<T>(
 				gpu,
@@ -105,13 +114,14 @@ impl StorageBuffer {
 		)
 	}
 
-	pub fn new<T: BufferUploadable>(buffer: Sarc<Buffer>, var_name: String, read_only: bool) -> Self {
+	pub fn new<T: DynBufferUploadable>(buffer: Sarc<Buffer>, var_name: String, read_only: bool) -> Self {
 		StorageBuffer {
 			buffer,
 			var_name,
 			read_only,
 			type_name: T::type_name(),
 			struct_definition: T::struct_definition(),
+			min_binding_size: NonZero::new(T::get_min_size()),
 		}
 	}
 
@@ -123,18 +133,66 @@ impl StorageBuffer {
 		gpu.device.create_buffer(&BufferDescriptor {
 			label: label.or(Some(&format!("StorageBuffer<size: {}>", size))),
 			size,
-			usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+			// COPY_SRC for the readback
+			usage: BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
 			mapped_at_creation: false,
 		})
 	}
 
-	pub fn raw_buffer_from_data<T: BufferUploadable>(gpu: &Gpu, data: &T, label: Option<&str>) -> Buffer {
+	pub fn raw_buffer_from_data<T: DynBufferUploadable>(gpu: &Gpu, data: &T, label: Option<&str>) -> Buffer {
 		gpu.device.create_buffer_init(&BufferInitDescriptor {
 			label: label.or(Some(&format!("StorageBuffer<{}>", T::type_name()))),
-			contents: &data.get_bytes(),
-			usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+			contents: &data.get_dyn_bytes(),
+			// COPY_SRC for the readback
+			usage: BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
 		})
 	}
+
+	/// Upload `items` into the buffer, starting at element `index` (so at byte
+	/// offset `index * array_stride::<T>()`), without touching the rest of the
+	/// buffer.
+	pub fn upload_range<T: BufferUploadable>(&self, gpu: &Gpu, index: u64, items: &[T]) {
+		upload_range(&self.buffer, gpu, index, items);
+	}
+
+	/// Upload only the elements of `items` that are marked in `dirty_ranges`,
+	/// with one write per coalesced range. `items` is the whole CPU-side array,
+	/// not just the changed elements.
+	pub fn upload_dirty_ranges<T: BufferUploadable>(&self, gpu: &Gpu, dirty_ranges: &DirtyRanges, items: &[T]) {
+		upload_dirty_ranges(&self.buffer, gpu, dirty_ranges, items);
+	}
+}
+
+fn upload_range<T: BufferUploadable>(buffer: &Buffer, gpu: &Gpu, index: u64, items: &[T]) {
+	let offset = index * array_stride::<T>();
+	let bytes = array_bytes(items);
+
+	// Panic to avoid dumb errors, wgpu would only report it as a validation error
+	assert!(
+		offset + bytes.len() as u64 <= buffer.size(),
+		"Range upload of {} elements at index {} overflows the buffer (size: {})",
+		items.len(),
+		index,
+		buffer.size()
+	);
+
+	gpu.queue.write_buffer(buffer, offset, &bytes);
+}
+
+fn upload_dirty_ranges<T: BufferUploadable>(buffer: &Buffer, gpu: &Gpu, dirty_ranges: &DirtyRanges, items: &[T]) {
+	for range in dirty_ranges.coalesced() {
+		// Same as above, a plain slice panic wouldn't say where the range came from
+		assert!(
+			range.end <= items.len() as u64,
+			"Dirty range {}..{} is past the end of the {} elements, were they marked before removing some?",
+			range.start,
+			range.end,
+			items.len()
+		);
+
+		let slice = &items[range.start as usize..range.end as usize];
+		upload_range(buffer, gpu, range.start, slice);
+	}
 }
 
 impl ShaderBufferResource for StorageBuffer {
@@ -160,7 +218,7 @@ impl ShaderBufferResource for StorageBuffer {
 					read_only: self.read_only,
 				},
 				has_dynamic_offset: false,
-				min_binding_size: None,
+				min_binding_size: self.min_binding_size,
 			},
 			count: None,
 		}]
@@ -170,3 +228,131 @@ impl ShaderBufferResource for StorageBuffer {
 		vec![self.buffer.as_entire_binding()]
 	}
 }
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// CPU-side copy of the elements of an array storage buffer.
+///
+/// Meant to be spawned alongside the buffer's [`Sarc<Buffer>`] and a
+/// [`DirtyRanges`], see [`register_range_updates`].
+#[derive(bevy::Component, Deref, DerefMut, Clone, Debug, Default)]
+pub struct StorageArray<E>(pub Vec<E>);
+
+impl<E: ShaderType> ShaderType for StorageArray<E> {
+	const WGSL_ALIGN: usize = <[E]>::WGSL_ALIGN;
+	const WGSL_SIZE: usize = <[E]>::WGSL_SIZE;
+
+	fn type_name() -> String {
+		<[E]>::type_name()
+	}
+
+	// The array itself needs no definition, but its elements might
+	fn struct_definition() -> Option<String> {
+		E::struct_definition()
+	}
+}
+
+impl<E: BufferUploadable> DynBufferUploadable for StorageArray<E> {
+	// WGSL runtime-sized arrays need at least one element
+	fn get_min_size() -> u64 {
+		array_stride::<E>()
+	}
+
+	fn get_dyn_size(&self) -> u64 {
+		self.len() as u64 * array_stride::<E>()
+	}
+
+	fn get_dyn_bytes(&self) -> Vec<u8> {
+		array_bytes(&self.0)
+	}
+}
+
+/// The distance in bytes between two elements of a WGSL `array<E>`, which is
+/// bigger than `E` itself for e.g. `vec3`s (16 bytes instead of 12)
+pub const fn array_stride<E: ShaderType>() -> u64 {
+	round_up(E::WGSL_ALIGN, E::WGSL_SIZE) as u64
+}
+
+/// The bytes of `items` laid out like a WGSL `array<E>`, each element padded
+/// to the array stride
+fn array_bytes<E: BufferUploadable>(items: &[E]) -> Vec<u8> {
+	let bytes = items.iter().flat_map(|item| item.get_bytes()).collect::<Vec<_>>();
+	relayout_elements(&bytes, items.len(), array_stride::<E>() as usize, E::to_wgsl_layout)
+}
+
+impl<E> From<Vec<E>> for StorageArray<E> {
+	fn from(value: Vec<E>) -> Self {
+		Self(value)
+	}
+}
+
+/// The element ranges of a [`StorageArray`] that changed since the last upload.
+#[derive(bevy::Component, Clone, Debug, Default)]
+pub struct DirtyRanges {
+	ranges: Vec<Range<u64>>,
+}
+
+impl DirtyRanges {
+	pub fn mark(&mut self, index: u64) {
+		self.mark_range(index..index + 1);
+	}
+
+	pub fn mark_range(&mut self, range: Range<u64>) {
+		if !range.is_empty() {
+			self.ranges.push(range);
+		}
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.ranges.is_empty()
+	}
+
+	pub fn clear(&mut self) {
+		self.ranges.clear();
+	}
+
+	/// The marked ranges, sorted and with overlapping or adjacent ranges merged
+	/// together
+	pub fn coalesced(&self) -> Vec<Range<u64>> {
+		let mut ranges = self.ranges.clone();
+		ranges.sort_by_key(|range| range.start);
+
+		let mut coalesced = Vec::<Range<u64>>::with_capacity(ranges.len());
+		for range in ranges {
+			match coalesced.last_mut() {
+				Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+				_ => coalesced.push(range),
+			}
+		}
+
+		coalesced
+	}
+}
+
+/// Upload the dirty ranges of every `(StorageArray<E>, Sarc<Buffer>,
+/// DirtyRanges)` entity right before rendering.
+pub fn register_range_updates<E>(app: &mut App)
+where
+	E: BufferUploadable + Send + Sync + 'static,
+{
+	app.add_systems(PreRender, upload_dirty_ranges_system::<E>);
+}
+
+fn upload_dirty_ranges_system<E>(gpu: Res<Gpu>, mut q: Query<(&StorageArray<E>, &Sarc<Buffer>, &mut DirtyRanges)>)
+where
+	E: BufferUploadable + Send + Sync + 'static,
+{
+	for (items, buffer, mut dirty_ranges) in q.iter_mut() {
+		// Don't trigger change detection when there is nothing to flush
+		if dirty_ranges.is_empty() {
+			continue;
+		}
+
+		upload_dirty_ranges(buffer, &gpu, &dirty_ranges, items);
+		dirty_ranges.clear();
+	}
+}
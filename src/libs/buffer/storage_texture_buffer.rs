@@ -3,7 +3,7 @@ use std::sync::Arc;
 use image::DynamicImage;
 use wgpu::{
 	BindingResource, BindingType, Features, StorageTextureAccess, TextureAspect, TextureDimension, TextureFormat,
-	TextureUsages, TextureViewDimension,
+	TextureUsages, TextureView, TextureViewDimension,
 };
 
 use super::{ShaderBufferDescriptor, ShaderBufferResource};
@@ -12,7 +12,7 @@ use crate::{
 	libs::{
 		buffer::PartialLayoutEntry,
 		smart_arc::Sarc,
-		texture::{self, Tex, TexDescriptor, TextureAssetDimensions},
+		texture::{self, InitPolicy, Tex, TexDescriptor, TextureAssetDimensions},
 	},
 };
 
@@ -30,6 +30,7 @@ pub enum StorageTexture<S: Into<String> + Clone> {
 		format: TextureFormat,
 		usage: Option<TextureUsages>,
 		aspect: TextureAspect,
+		init: InitPolicy,
 	},
 	FromImage {
 		var_name: S,
@@ -38,11 +39,27 @@ pub enum StorageTexture<S: Into<String> + Clone> {
 		format: TextureFormat,
 		usage: Option<TextureUsages>,
 	},
+	/// Tightly packed texels, see [`Tex::upload_texels`]
+	FromBytes {
+		var_name: S,
+		access: StorageTextureAccess,
+		dimensions: TextureAssetDimensions,
+		format: TextureFormat,
+		usage: Option<TextureUsages>,
+		bytes: Vec<u8>,
+	},
 	FromTex {
 		var_name: S,
 		access: StorageTextureAccess,
 		tex: Sarc<Tex>,
 	},
+	/// A single mip level of the texture, see [`Tex::mip_view`]
+	FromTexMip {
+		var_name: S,
+		access: StorageTextureAccess,
+		tex: Sarc<Tex>,
+		mip_level: u32,
+	},
 }
 
 impl<S: Into<String> + Clone> ShaderBufferDescriptor for StorageTexture<S> {
@@ -55,10 +72,11 @@ impl<S: Into<String> + Clone> ShaderBufferDescriptor for StorageTexture<S> {
 				format,
 				usage,
 				aspect,
+				init,
 			} => {
 				let var_name = var_name.to_owned().into();
 
-				let tex = Sarc::new(Tex::create(
+				let tex = Tex::create(
 					gpu,
 					TexDescriptor {
 						label: &format!("StorageTexture '{}'", var_name),
@@ -68,7 +86,9 @@ impl<S: Into<String> + Clone> ShaderBufferDescriptor for StorageTexture<S> {
 						aspect: *aspect,
 					},
 					None,
-				));
+				)
+				.tracked(gpu);
+				tex.initialize(gpu, *init);
 
 				StorageTextureResource {
 					tex,
@@ -77,6 +97,7 @@ impl<S: Into<String> + Clone> ShaderBufferDescriptor for StorageTexture<S> {
 					dimension: dimensions.get_dimension().compatible_texture_dimension(),
 					view_dimension: dimensions.get_dimension(),
 					format: *format,
+					mip_view: None,
 				}
 			}
 
@@ -88,14 +109,15 @@ impl<S: Into<String> + Clone> ShaderBufferDescriptor for StorageTexture<S> {
 				usage,
 			} => {
 				let var_name = var_name.to_owned().into();
-				let tex = Sarc::new(Tex::from_image(
+				let tex = Tex::from_image(
 					gpu,
 					&format!("StorageTexture '{}'", var_name),
 					image,
 					*format,
 					*usage,
 					None,
-				));
+				)
+				.tracked(gpu);
 
 				StorageTextureResource {
 					tex,
@@ -104,6 +126,42 @@ impl<S: Into<String> + Clone> ShaderBufferDescriptor for StorageTexture<S> {
 					dimension: TextureDimension::D2,
 					view_dimension: TextureViewDimension::D2,
 					format: *format,
+					mip_view: None,
+				}
+			}
+
+			StorageTexture::FromBytes {
+				var_name,
+				access,
+				dimensions,
+				format,
+				usage,
+				bytes,
+			} => {
+				let var_name = var_name.to_owned().into();
+
+				let tex = Tex::create(
+					gpu,
+					TexDescriptor {
+						label: &format!("StorageTexture '{}'", var_name),
+						dimensions: *dimensions,
+						format: *format,
+						usage: *usage,
+						aspect: TextureAspect::All,
+					},
+					None,
+				)
+				.tracked(gpu);
+				tex.upload_texels(gpu, bytes);
+
+				StorageTextureResource {
+					tex,
+					var_name,
+					access: *access,
+					dimension: dimensions.get_dimension().compatible_texture_dimension(),
+					view_dimension: dimensions.get_dimension(),
+					format: *format,
+					mip_view: None,
 				}
 			}
 
@@ -114,6 +172,22 @@ impl<S: Into<String> + Clone> ShaderBufferDescriptor for StorageTexture<S> {
 				dimension: tex.dimension(),
 				view_dimension: tex.view_dimension(),
 				format: tex.format(),
+				mip_view: None,
+			},
+
+			StorageTexture::FromTexMip {
+				var_name,
+				access,
+				tex,
+				mip_level,
+			} => StorageTextureResource {
+				tex: tex.clone(),
+				var_name: var_name.to_owned().into(),
+				access: *access,
+				dimension: tex.dimension(),
+				view_dimension: tex.view_dimension(),
+				format: tex.format(),
+				mip_view: Some(tex.mip_view(*mip_level)),
 			},
 		};
 
@@ -134,6 +208,8 @@ pub struct StorageTextureResource {
 	pub dimension: TextureDimension,
 	pub view_dimension: TextureViewDimension,
 	pub format: TextureFormat,
+	/// Bound instead of the view of the whole texture
+	pub mip_view: Option<TextureView>,
 }
 
 impl ShaderBufferResource for StorageTextureResource {
@@ -164,6 +240,8 @@ impl ShaderBufferResource for StorageTextureResource {
 	}
 
 	fn binding_resources(&self) -> Vec<BindingResource> {
-		vec![BindingResource::TextureView(&self.tex.view)]
+		vec![BindingResource::TextureView(
+			self.mip_view.as_ref().unwrap_or(&self.tex.view),
+		)]
 	}
 }
@@ -1,12 +1,13 @@
-use std::sync::Arc;
+use std::{num::NonZero, sync::Arc};
 
 use brainrot::bevy::{self};
+use log::{debug, warn};
 use wgpu::{
 	util::{BufferInitDescriptor, DeviceExt},
 	BindingResource, BindingType, Buffer, BufferBindingType, BufferDescriptor, BufferUsages, Features,
 };
 
-use super::{BufferUploadable, PartialLayoutEntry, ShaderBufferDescriptor, ShaderBufferResource};
+use super::{round_up, BufferUploadable, PartialLayoutEntry, ShaderBufferDescriptor, ShaderBufferResource};
 use crate::{core::gpu::Gpu, libs::smart_arc::Sarc};
 
 /*
@@ -53,20 +54,32 @@ where
 --------------------------------------------------------------------------------
 */
 
+/// A value bound as `var<uniform>`.
+///
+/// The buffers made here are laid out the way WGSL wants them (see
+/// [`ShaderType::to_wgsl_layout`](super::ShaderType::to_wgsl_layout)) and
+/// padded to a multiple of 16 bytes, so that e.g. a bare `vec3<f32>` or a
+/// `mat3x3<f32>` can be included as is. The types that the uniform address
+/// space can't hold at all (arrays with a stride that isn't a multiple of 16,
+/// like `array<f32, 4>`) are bound as a read-only storage buffer instead, with
+/// a warning. The shaders read them the same way.
 #[derive(bevy::Component)]
 pub struct UniformBuffer {
 	pub buffer: Sarc<Buffer>,
 	pub var_name: String,
 	type_name: String,
 	struct_definition: Option<String>,
+	min_binding_size: Option<NonZero<u64>>,
+	as_storage: bool,
 }
 
 impl UniformBuffer {
 	pub fn new_from_size<T: BufferUploadable>(gpu: &Gpu, size: u64, var_name: String) -> Self {
 		Self::new::<T>(
-			Sarc::new(Self::raw_buffer_from_size(
+			Sarc::new(Self::raw_buffer_with_usage(
 				gpu,
-				size,
+				round_up(16, size as usize) as u64,
+				Self::usage::<T>(),
 				Some(&format!("UniformBuffer<{}> '{}'", T::type_name(), var_name)),
 			)),
 			var_name,
@@ -85,41 +98,90 @@ impl UniformBuffer {
 	}
 
 	pub fn new<T: BufferUploadable>(buffer: Sarc<Buffer>, var_name: String) -> Self {
+		let as_storage = !T::UNIFORM_COMPATIBLE;
+
+		if as_storage {
+			warn!(
+				"Uniform '{}': `{}` can't be in the uniform address space, its array stride isn't a multiple of 16. \
+				 Binding it as a read-only storage buffer instead",
+				var_name,
+				T::type_name()
+			);
+		} else if T::WGSL_SIZE % 16 != 0 || T::WGSL_SIZE as u64 != T::get_size() {
+			debug!(
+				"Uniform '{}': `{}` is laid out for WGSL and padded to {} bytes",
+				var_name,
+				T::type_name(),
+				Self::size_of::<T>()
+			);
+		}
+
 		UniformBuffer {
 			buffer,
 			var_name,
 			type_name: T::type_name(),
 			struct_definition: T::struct_definition(),
+			min_binding_size: NonZero::new(T::WGSL_SIZE as u64),
+			as_storage,
 		}
 	}
 
+	/// How big the buffer of a `T` is: its WGSL size (or its Rust size if that's
+	/// bigger, e.g. with trailing padding fields), rounded up to 16 bytes
+	pub fn size_of<T: BufferUploadable>() -> u64 {
+		round_up(16, T::WGSL_SIZE.max(T::get_size() as usize).max(1)) as u64
+	}
+
+	/// The bytes of `data` as they go into its buffer, see
+	/// [`size_of`](Self::size_of)
+	pub fn bytes_of<T: BufferUploadable>(data: &T) -> Vec<u8> {
+		let mut bytes = T::to_wgsl_layout(&data.get_bytes());
+		bytes.resize(Self::size_of::<T>() as usize, 0);
+		bytes
+	}
+
 	pub fn raw_buffer_from_type<T: BufferUploadable>(gpu: &Gpu, label: Option<&str>) -> Buffer {
-		Self::raw_buffer_from_size(gpu, T::get_size(), label)
+		Self::raw_buffer_with_usage(gpu, Self::size_of::<T>(), Self::usage::<T>(), label)
 	}
 
 	pub fn raw_buffer_from_size(gpu: &Gpu, size: u64, label: Option<&str>) -> Buffer {
+		Self::raw_buffer_with_usage(gpu, size, BufferUsages::UNIFORM | BufferUsages::COPY_DST, label)
+	}
+
+	pub fn raw_buffer_from_data<T: BufferUploadable>(gpu: &Gpu, data: &T, label: Option<&str>) -> Buffer {
+		gpu.device.create_buffer_init(&BufferInitDescriptor {
+			label: label.or(Some(&format!("UniformBuffer<{}>", T::type_name()))),
+			contents: &Self::bytes_of(data),
+			usage: Self::usage::<T>(),
+		})
+	}
+
+	fn raw_buffer_with_usage(gpu: &Gpu, size: u64, usage: BufferUsages, label: Option<&str>) -> Buffer {
 		gpu.device.create_buffer(&BufferDescriptor {
 			label: label.or(Some(&format!("UniformBuffer<size: {}>", size))),
 			size,
-			usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+			usage,
 			mapped_at_creation: false,
 		})
 	}
 
-	pub fn raw_buffer_from_data<T: BufferUploadable>(gpu: &Gpu, data: &T, label: Option<&str>) -> Buffer {
-		gpu.device.create_buffer_init(&BufferInitDescriptor {
-			label: label.or(Some(&format!("UniformBuffer<{}>", T::type_name()))),
-			contents: &data.get_bytes(),
-			usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
-		})
+	/// The types that fall back to a storage buffer need it in the usage too
+	fn usage<T: BufferUploadable>() -> BufferUsages {
+		if T::UNIFORM_COMPATIBLE {
+			BufferUsages::UNIFORM | BufferUsages::COPY_DST
+		} else {
+			BufferUsages::UNIFORM | BufferUsages::STORAGE | BufferUsages::COPY_DST
+		}
 	}
 }
 
 impl ShaderBufferResource for UniformBuffer {
 	fn binding_source_code(&self, group: u32, binding: u32) -> Vec<String> {
+		let address_space = if self.as_storage { "storage, read" } else { "uniform" };
+
 		vec![format!(
-			"@group({}) @binding({}) var<uniform> {}: {};",
-			group, binding, self.var_name, self.type_name
+			"@group({}) @binding({}) var<{}> {}: {};",
+			group, binding, address_space, self.var_name, self.type_name
 		)]
 	}
 
@@ -130,9 +192,13 @@ impl ShaderBufferResource for UniformBuffer {
 	fn layouts(&self, _features: Features) -> Vec<PartialLayoutEntry> {
 		vec![PartialLayoutEntry {
 			ty: BindingType::Buffer {
-				ty: BufferBindingType::Uniform,
+				ty: if self.as_storage {
+					BufferBindingType::Storage { read_only: true }
+				} else {
+					BufferBindingType::Uniform
+				},
 				has_dynamic_offset: false,
-				min_binding_size: None,
+				min_binding_size: self.min_binding_size,
 			},
 			count: None,
 		}]
@@ -2,6 +2,14 @@
 pub trait Assets {
 	fn get(&self, file_path: &str) -> Option<rust_embed::EmbeddedFile>;
 	fn iter(&self) -> rust_embed::Filenames;
+
+	/// All the embedded paths with the given extension (without the dot)
+	fn iter_paths(&self, extension: &str) -> Vec<String> {
+		self.iter()
+			.filter(|path| path.rsplit_once('.').is_some_and(|(_, ext)| ext == extension))
+			.map(|path| path.into_owned())
+			.collect()
+	}
 }
 
 impl<T: rust_embed::Embed> Assets for T {
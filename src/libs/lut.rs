@@ -0,0 +1,204 @@
+use std::{fs, path::Path};
+
+use anyhow::{bail, ensure, Context, Result};
+use brainrot::vek::{Rgb, Vec3};
+use image::{DynamicImage, GenericImageView};
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LutDimensions {
+	/// One curve per channel, `size` entries
+	D1,
+	/// A `size`³ cube, red changing fastest then green then blue
+	D3,
+}
+
+/// A color lookup table from an Adobe `.cube` file.
+///
+/// The values are kept as they are in the file, they can go outside of [0; 1]
+/// (e.g. for HDR LUTs). The inputs are mapped from the domain to [0; 1] before
+/// the lookup.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CubeLut {
+	pub title: Option<String>,
+	pub dimensions: LutDimensions,
+	pub size: u32,
+	pub domain_min: Vec3<f32>,
+	pub domain_max: Vec3<f32>,
+	/// `size` entries for a 1D LUT, `size`³ for a 3D one
+	pub table: Vec<Rgb<f32>>,
+}
+
+impl CubeLut {
+	/// The sizes the spec allows
+	pub const MAX_1D_SIZE: u32 = 65536;
+	pub const MAX_3D_SIZE: u32 = 256;
+
+	pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+		let path = path.as_ref();
+		let source = fs::read_to_string(path).with_context(|| format!("Couldn't read {}", path.display()))?;
+		Self::parse(&source).with_context(|| format!("Couldn't parse {}", path.display()))
+	}
+
+	/// Parse the contents of a `.cube` file. The keywords have to come before
+	/// the table, and the ones that aren't in the spec (like the ones some
+	/// editors add) are skipped.
+	pub fn parse(source: &str) -> Result<Self> {
+		let mut title = None;
+		let mut size_1d = None;
+		let mut size_3d = None;
+		let mut domain_min = Vec3::zero();
+		let mut domain_max = Vec3::one();
+		let mut table = Vec::new();
+
+		for (i, line) in source.lines().enumerate() {
+			let line_number = i + 1;
+			let line = line.trim();
+
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+
+			let is_data = line.starts_with(|c: char| c.is_ascii_digit() || matches!(c, '-' | '+' | '.'));
+			if is_data {
+				table.push(parse_triplet(line.split_whitespace()).with_context(|| format!("Line {}", line_number))?);
+				continue;
+			}
+
+			ensure!(
+				table.is_empty(),
+				"Line {}: keywords have to come before the table",
+				line_number
+			);
+
+			let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+			let rest = rest.trim();
+
+			match keyword {
+				"TITLE" => {
+					title = Some(rest.trim_matches('"').to_owned());
+				}
+				"LUT_1D_SIZE" => {
+					size_1d =
+						Some(parse_size(rest, Self::MAX_1D_SIZE).with_context(|| format!("Line {}", line_number))?);
+				}
+				"LUT_3D_SIZE" => {
+					size_3d =
+						Some(parse_size(rest, Self::MAX_3D_SIZE).with_context(|| format!("Line {}", line_number))?);
+				}
+				"DOMAIN_MIN" => {
+					domain_min =
+						parse_triplet(rest.split_whitespace()).with_context(|| format!("Line {}", line_number))?;
+				}
+				"DOMAIN_MAX" => {
+					domain_max =
+						parse_triplet(rest.split_whitespace()).with_context(|| format!("Line {}", line_number))?;
+				}
+				_ => {}
+			}
+		}
+
+		let (dimensions, size, expected) = match (size_1d, size_3d) {
+			(Some(_), Some(_)) => bail!("A LUT can't have both a LUT_1D_SIZE and a LUT_3D_SIZE"),
+			(None, None) => bail!("Missing the LUT_1D_SIZE or LUT_3D_SIZE"),
+			(Some(size), None) => (LutDimensions::D1, size, size as usize),
+			(None, Some(size)) => (LutDimensions::D3, size, (size as usize).pow(3)),
+		};
+
+		ensure!(
+			table.len() == expected,
+			"Expected {} entries in the table, found {}",
+			expected,
+			table.len()
+		);
+
+		for axis in 0..3 {
+			ensure!(
+				domain_min[axis] < domain_max[axis],
+				"The DOMAIN_MIN has to be below the DOMAIN_MAX, found {:?} and {:?}",
+				domain_min,
+				domain_max
+			);
+		}
+
+		Ok(Self {
+			title,
+			dimensions,
+			size,
+			domain_min,
+			domain_max,
+			table: table.into_iter().map(|x| Rgb::new(x.x, x.y, x.z)).collect(),
+		})
+	}
+
+	/// The entry of a 3D LUT at the given grid point
+	pub fn at(&self, r: u32, g: u32, b: u32) -> Rgb<f32> {
+		debug_assert!(self.dimensions == LutDimensions::D3);
+		self.table[(r + self.size * (g + self.size * b)) as usize]
+	}
+}
+
+fn parse_size(value: &str, max: u32) -> Result<u32> {
+	let size = value
+		.parse::<u32>()
+		.with_context(|| format!("Invalid size {:?}", value))?;
+
+	ensure!(
+		(2..=max).contains(&size),
+		"The size has to be between 2 and {}, found {}",
+		max,
+		size
+	);
+
+	Ok(size)
+}
+
+fn parse_triplet<'a>(mut values: impl Iterator<Item = &'a str>) -> Result<Vec3<f32>> {
+	let mut triplet = Vec3::zero();
+
+	for axis in 0..3 {
+		let value = values.next().context("Expected 3 values")?;
+		triplet[axis] = value
+			.parse::<f32>()
+			.ok()
+			.filter(|x| x.is_finite())
+			.with_context(|| format!("Invalid value {:?}", value))?;
+	}
+
+	ensure!(values.next().is_none(), "Expected 3 values, found more");
+
+	Ok(triplet)
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Split the image of a 3D LUT laid out as a strip into its depth layers, one
+/// per blue entry.
+///
+/// The strip is `size` tiles of `size`x`size` side by side (e.g. 1024x32 for a
+/// 32³ LUT), blue going up from one tile to the next. Within a tile, red goes
+/// up to the right and green downwards, like the strips of Unreal. The colors
+/// are taken as they are, so they're the sRGB-encoded outputs of the LUT.
+pub fn strip_layers(image: &DynamicImage) -> Result<Vec<DynamicImage>> {
+	let (width, height) = image.dimensions();
+
+	ensure!(
+		height >= 2 && width as u64 == (height as u64).pow(2),
+		"A LUT strip has to be as wide as its height squared (e.g. 1024x32), this one is {}x{}",
+		width,
+		height
+	);
+
+	Ok((0..height)
+		.map(|b| image.crop_imm(b * height, 0, height, height))
+		.collect())
+}
@@ -0,0 +1,167 @@
+use std::time::Duration;
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// The statistics of the last `N` samples of something, e.g. frame times.
+///
+/// The samples are kept in a ring buffer next to a sorted copy of them, so a
+/// new sample costs a shift of at most `N` floats and the percentiles are just
+/// a lookup. No allocations, it's `Copy` so that it can live in [`Time`](crate::core::gameloop::Time).
+///
+/// The percentiles are nearest-rank: the p-th percentile is the smallest sample
+/// that at least p% of the samples are less than or equal to.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RollingStats<const N: usize> {
+	ring: [f32; N],
+	sorted: [f32; N],
+	len: usize,
+	/// Where the next sample goes, which is the oldest one once the ring is full
+	next: usize,
+	// In f64 so that the additions and removals don't drift too much
+	sum: f64,
+}
+
+impl<const N: usize> Default for RollingStats<N> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<const N: usize> RollingStats<N> {
+	pub fn new() -> Self {
+		assert!(N > 0, "A RollingStats needs room for at least one sample");
+
+		Self {
+			ring: [0.0; N],
+			sorted: [0.0; N],
+			len: 0,
+			next: 0,
+			sum: 0.0,
+		}
+	}
+
+	/// Add a sample, dropping the oldest one if the window is full. The samples
+	/// that aren't finite (like a rate over a zero duration) are ignored.
+	pub fn push(&mut self, sample: f32) {
+		if !sample.is_finite() {
+			return;
+		}
+
+		if self.len == N {
+			let oldest = self.ring[self.next];
+			let index = self.sorted[..self.len]
+				.binary_search_by(|s| s.total_cmp(&oldest))
+				.expect("The oldest sample should be in the sorted samples");
+
+			self.sorted.copy_within(index + 1..self.len, index);
+			self.len -= 1;
+			self.sum -= oldest as f64;
+		}
+
+		self.ring[self.next] = sample;
+		self.next = (self.next + 1) % N;
+
+		let index = self.sorted[..self.len].partition_point(|s| s.total_cmp(&sample).is_lt());
+		self.sorted.copy_within(index..self.len, index + 1);
+		self.sorted[index] = sample;
+		self.len += 1;
+		self.sum += sample as f64;
+	}
+
+	pub fn clear(&mut self) {
+		self.len = 0;
+		self.next = 0;
+		self.sum = 0.0;
+	}
+
+	/// How many samples are kept at most
+	pub fn window(&self) -> usize {
+		N
+	}
+
+	pub fn len(&self) -> usize {
+		self.len
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len == 0
+	}
+
+	/// The samples in the window, from the oldest to the newest
+	pub fn samples(&self) -> impl Iterator<Item = f32> + '_ {
+		let (older, newer) = if self.len == N {
+			(&self.ring[self.next..], &self.ring[..self.next])
+		} else {
+			(&self.ring[..self.len], &self.ring[..0])
+		};
+
+		older.iter().chain(newer).copied()
+	}
+
+	pub fn latest(&self) -> Option<f32> {
+		(!self.is_empty()).then(|| self.ring[(self.next + N - 1) % N])
+	}
+
+	pub fn mean(&self) -> Option<f32> {
+		(!self.is_empty()).then(|| (self.sum / self.len as f64) as f32)
+	}
+
+	pub fn min(&self) -> Option<f32> {
+		self.sorted[..self.len].first().copied()
+	}
+
+	pub fn max(&self) -> Option<f32> {
+		self.sorted[..self.len].last().copied()
+	}
+
+	/// `percent` goes from 0 (the min) to 100 (the max)
+	pub fn percentile(&self, percent: f32) -> Option<f32> {
+		if self.is_empty() {
+			return None;
+		}
+
+		let rank = (percent.clamp(0.0, 100.0) / 100.0 * self.len as f32).ceil() as usize;
+		Some(self.sorted[rank.clamp(1, self.len) - 1])
+	}
+
+	pub fn p50(&self) -> Option<f32> {
+		self.percentile(50.0)
+	}
+
+	pub fn p95(&self) -> Option<f32> {
+		self.percentile(95.0)
+	}
+
+	pub fn p99(&self) -> Option<f32> {
+		self.percentile(99.0)
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// A duration in milliseconds for the logs and overlays, e.g. `0.125ms`,
+/// `4.17ms`, `16.7ms` or `250ms`
+pub fn format_duration_ms(duration: Duration) -> String {
+	format_ms(duration.as_secs_f32() * 1000.0)
+}
+
+/// Like [`format_duration_ms`], for a number of milliseconds. Keeps about three
+/// significant digits, which is all the precision the timings have anyway.
+pub fn format_ms(ms: f32) -> String {
+	let decimals = match ms.abs() {
+		ms if ms < 1.0 => 3,
+		ms if ms < 10.0 => 2,
+		ms if ms < 100.0 => 1,
+		_ => 0,
+	};
+
+	format!("{:.*}ms", decimals, ms)
+}
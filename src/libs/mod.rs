@@ -1,5 +1,8 @@
+pub mod atlas;
 pub mod buffer;
 pub mod embed;
+pub mod lut;
+pub mod metrics;
 pub mod shader;
 pub mod shader_fragment;
 pub mod smart_arc;
@@ -149,6 +149,9 @@ pub struct Extras<'w> {
 
 impl<'w> Extras<'w> {
 	pub fn get<D: QueryData, F: QueryFilter>(&'w mut self) -> ROQueryItem<'w, D> {
-		self.world.query_filtered::<D, F>().single(self.world)
+		self.world
+			.query_filtered::<D, F>()
+			.get_single(self.world)
+			.unwrap_or_else(|error| panic!("Couldn't get the extra `{}`: {}", std::any::type_name::<D>(), error))
 	}
 }
@@ -1,8 +1,18 @@
-use std::{borrow::Cow, collections::HashSet, hash::Hash, mem, ops::Range, sync::Arc};
+use std::{
+	borrow::Cow,
+	collections::{BTreeSet, HashMap, HashSet},
+	error::Error,
+	fmt,
+	hash::{DefaultHasher, Hash, Hasher},
+	mem,
+	ops::{Deref, Range},
+	sync::{Arc, Mutex, OnceLock, Weak},
+};
 
-use anyhow::{anyhow, Ok, Result};
+use anyhow::{anyhow, bail, Ok, Result};
 use brainrot::{path, root, rooted_path};
 use hashlink::{LinkedHashMap, LinkedHashSet};
+use log::debug;
 use rand::seq::IteratorRandom;
 use regex::Regex;
 use replace_with::replace_with_or_abort;
@@ -12,8 +22,8 @@ use typed_path::{
 };
 use velcro::iter;
 use wgpu::{
-	BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, ShaderModule,
-	ShaderModuleDescriptor, ShaderStages,
+	BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindingResource,
+	ErrorFilter, ShaderModule, ShaderModuleDescriptor, ShaderStages,
 };
 
 use super::{
@@ -31,10 +41,14 @@ use crate::core::gpu::Gpu;
 ||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
 --------------------------------------------------------------------------------
 */
-#[derive(Clone, Debug, Default, Hash, Eq, PartialEq)]
+#[derive(Clone, Debug, Default)]
 pub struct ShaderBuilder {
 	include_directives: LinkedHashSet<Shader>,
 	define_directives: LinkedHashMap<String, String>,
+	limits: Option<ShaderBuildLimits>,
+	/// Hashing a builder hashes everything it includes, so it's only done once
+	/// and forgotten whenever the builder changes
+	content_hash: OnceLock<u64>,
 }
 
 impl ShaderBuilder {
@@ -43,6 +57,7 @@ impl ShaderBuilder {
 	}
 
 	pub fn include(&mut self, shader: impl Into<Shader>) -> &mut Self {
+		self.content_hash.take();
 		self.include_directives.insert(shader.into());
 		self
 	}
@@ -76,10 +91,29 @@ impl ShaderBuilder {
 		K: Into<String>,
 		V: Into<String>,
 	{
+		self.content_hash.take();
 		self.define_directives.insert(key.into(), value.into());
 		self
 	}
 
+	/// Only the limits of the builder that is built count, not those of the
+	/// builders it includes
+	pub fn with_limits(&mut self, limits: ShaderBuildLimits) -> &mut Self {
+		self.content_hash.take();
+		self.limits = Some(limits);
+		self
+	}
+
+	fn content_hash(&self) -> u64 {
+		*self.content_hash.get_or_init(|| {
+			let mut hasher = DefaultHasher::new();
+			self.include_directives.hash(&mut hasher);
+			self.define_directives.hash(&mut hasher);
+			self.limits.hash(&mut hasher);
+			hasher.finish()
+		})
+	}
+
 	pub fn build<T: Assets>(
 		&mut self,
 		gpu: &Gpu,
@@ -98,7 +132,8 @@ impl ShaderBuilder {
 	}
 
 	pub fn build_source<T: Assets>(&mut self, gpu: &Gpu, shader_map: &T) -> Result<ShaderSource> {
-		let mut state = ShaderBuilderState::new(gpu, shader_map);
+		let limits = self.limits.unwrap_or_default();
+		let mut state = ShaderBuilderState::new(gpu, shader_map, limits);
 		self.build_source_from_state(&mut state)
 	}
 
@@ -108,14 +143,16 @@ impl ShaderBuilder {
 		let mut shader_source = ShaderSource::empty();
 
 		for shader in builder.include_directives.drain() {
+			let name = shader.name();
 			let included_source = shader.build_recursively(state)?;
 			shader_source.extend(included_source);
+			state.check_size(&shader_source, &name)?;
 		}
 
 		builder
 			.define_directives
 			.extend(Self::process_define_directives(&mut shader_source));
-		shader_source = builder.apply_define_directives(shader_source);
+		shader_source = builder.apply_define_directives(shader_source, state)?;
 
 		Ok(shader_source)
 	}
@@ -151,18 +188,68 @@ impl ShaderBuilder {
 		define_directives
 	}
 
-	fn apply_define_directives(&mut self, mut shader_source: ShaderSource) -> ShaderSource {
+	/// Values can contain other keys, so the directives are applied again until
+	/// nothing changes anymore
+	fn apply_define_directives(
+		&mut self,
+		mut shader_source: ShaderSource,
+		state: &ShaderBuilderState,
+	) -> Result<ShaderSource> {
 		let mut directives = self.define_directives.iter().collect::<Vec<_>>();
 		// Sort by reverse size, so from biggest key to smallest key
 		directives.sort_by(|(key1, _), (key2, _)| key2.cmp(key1));
 
-		for (key, value) in directives {
-			shader_source.source = shader_source.source.replace(key, value);
+		for _ in 0..state.limits.max_define_passes {
+			let mut expanded = false;
+
+			for (key, value) in directives.iter() {
+				if shader_source.source.contains(key.as_str()) {
+					shader_source.source = shader_source.source.replace(key.as_str(), value);
+					expanded = true;
+
+					state.check_size(&shader_source, &format!("#define {}", key))?;
+				}
+			}
+
+			if !expanded {
+				return Ok(shader_source);
+			}
 		}
-		shader_source
+
+		// Whatever is still there after the last pass keeps on expanding
+		let keys = directives
+			.iter()
+			.filter(|(key, _)| shader_source.source.contains(key.as_str()))
+			.map(|(key, _)| key.to_string())
+			.collect();
+
+		Err(ShaderBuildError::DefineExpansion {
+			keys,
+			passes: state.limits.max_define_passes,
+		}
+		.into())
+	}
+}
+
+impl Hash for ShaderBuilder {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		state.write_u64(self.content_hash());
 	}
 }
 
+impl PartialEq for ShaderBuilder {
+	fn eq(&self, other: &Self) -> bool {
+		// Different hashes are different builders, the same hash still needs the
+		// full comparison in case of a collision
+		self.content_hash() == other.content_hash()
+			&& self.include_directives == other.include_directives
+			&& self.define_directives == other.define_directives
+			&& self.limits == other.limits
+	}
+}
+
+impl Eq for ShaderBuilder {}
+
 /*
 --------------------------------------------------------------------------------
 ||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
@@ -172,18 +259,107 @@ struct ShaderBuilderState<'a> {
 	pub gpu: &'a Gpu,
 	pub shader_map: &'a dyn Assets,
 	pub blacklist: HashSet<Shader>,
+	pub limits: ShaderBuildLimits,
+	/// The names of the shaders currently being included, outermost first
+	pub include_stack: Vec<String>,
 }
 
 impl<'a> ShaderBuilderState<'a> {
-	pub fn new<T: Assets>(gpu: &'a Gpu, shader_map: &'a T) -> Self {
+	pub fn new<T: Assets>(gpu: &'a Gpu, shader_map: &'a T, limits: ShaderBuildLimits) -> Self {
 		Self {
 			gpu,
 			shader_map: shader_map as &'a dyn Assets,
 			blacklist: HashSet::new(),
+			limits,
+			include_stack: Vec::new(),
+		}
+	}
+
+	fn check_size(&self, shader_source: &ShaderSource, name: &str) -> Result<()> {
+		if shader_source.source.len() > self.limits.max_source_size {
+			return Err(ShaderBuildError::SourceTooLarge {
+				shader: name.to_owned(),
+				size: shader_source.source.len(),
+				max: self.limits.max_source_size,
+			}
+			.into());
+		}
+
+		Ok(())
+	}
+}
+
+/// Keeps a broken include or define from growing the source out of proportion
+/// before naga even gets to see it. Going over any of them is a
+/// [`ShaderBuildError`].
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub struct ShaderBuildLimits {
+	/// In bytes, checked after every include and define expansion
+	pub max_source_size: usize,
+	/// How deep includes (files including files, builders including builders)
+	/// can nest
+	pub max_include_depth: usize,
+	/// How many times the defines are applied to the source before giving up,
+	/// which is only reached if they keep expanding into each other
+	pub max_define_passes: usize,
+}
+
+impl Default for ShaderBuildLimits {
+	fn default() -> Self {
+		Self {
+			max_source_size: 8 * 1024 * 1024,
+			max_include_depth: 32,
+			max_define_passes: 8,
 		}
 	}
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ShaderBuildError {
+	SourceTooLarge {
+		/// The shader or define that made the source go over the limit
+		shader: String,
+		size: usize,
+		max: usize,
+	},
+	IncludeTooDeep {
+		/// From the outermost shader to the one that went over the limit
+		chain: Vec<String>,
+		max: usize,
+	},
+	DefineExpansion {
+		/// The keys that were still in the source after the last pass
+		keys: Vec<String>,
+		passes: usize,
+	},
+}
+
+impl fmt::Display for ShaderBuildError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ShaderBuildError::SourceTooLarge { shader, size, max } => write!(
+				f,
+				"The shader source grew to {} bytes while adding {}, the limit is {} bytes",
+				size, shader, max
+			),
+			ShaderBuildError::IncludeTooDeep { chain, max } => write!(
+				f,
+				"Includes are nested deeper than {} levels: {}",
+				max,
+				chain.join(" -> ")
+			),
+			ShaderBuildError::DefineExpansion { keys, passes } => write!(
+				f,
+				"The defines {} still expand after {} passes, they probably expand into each other",
+				keys.join(", "),
+				passes
+			),
+		}
+	}
+}
+
+impl Error for ShaderBuildError {}
+
 /*
 --------------------------------------------------------------------------------
 ||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
@@ -191,7 +367,7 @@ impl<'a> ShaderBuilderState<'a> {
 */
 #[derive(Hash, Debug, Clone, PartialEq, Eq)]
 pub enum Shader {
-	Source(String),
+	Source(SourceText),
 	Path(Utf8UnixPathBuf),
 	Builder(ShaderBuilder),
 	Buffer(Sarc<dyn ShaderBufferDescriptor>),
@@ -216,8 +392,16 @@ impl Shader {
 			.into_iter()
 			.collect::<String>();
 
+		self.rename_fn(func_name, &obfuscated);
+
+		obfuscated
+	}
+
+	/// Rename every call and definition of a function, e.g. to give a fragment's
+	/// API function a name that doesn't collide with the other fragments
+	pub fn rename_fn(&mut self, func_name: &str, new_name: &str) {
 		let from = format!("{}(", func_name);
-		let to = format!("{}(", obfuscated);
+		let to = format!("{}(", new_name);
 
 		replace_with_or_abort(self, |self_| match self_ {
 			// Replace the source string directly
@@ -230,27 +414,39 @@ impl Shader {
 			Shader::Buffer(_) => self_,
 			Shader::BufferResource(_) => self_,
 		});
+	}
 
-		obfuscated
+	/// What to call the shader in errors
+	pub fn name(&self) -> String {
+		match self {
+			Shader::Source(_) => "<inline source>".to_owned(),
+			Shader::Path(path) => path.as_str().to_owned(),
+			Shader::Builder(builder) => match builder.include_directives.front() {
+				Some(first) => format!("<builder of {}>", first.name()),
+				None => "<empty builder>".to_owned(),
+			},
+			Shader::Buffer(_) | Shader::BufferResource(_) => "<buffer>".to_owned(),
+		}
 	}
 
 	fn get_raw_source(self, state: &mut ShaderBuilderState) -> Result<ShaderSource> {
 		match self {
-			Shader::Source(source) => Ok(ShaderSource::from_source(source)),
+			Shader::Source(source) => Ok(ShaderSource::from_source(normalize_source(&source).into_owned())),
 
 			Shader::Path(path) => {
 				let path = rooted_path!(path);
 
 				// Get the source from the shader map
-				let source_data = state
+				let file = state
 					.shader_map
 					.get(path.as_str())
-					.ok_or(anyhow!("File not found: {}", path.as_str()))?
-					.data;
-				let source =
-					String::from_utf8(source_data.to_vec()).or(Err(anyhow!("Invalid UTF8 file: {}", path.as_str())))?;
+					.ok_or(anyhow!("File not found: {}", path.as_str()))?;
 
-				Ok(ShaderSource::from_source(source))
+				let source = interned_source(path.as_str(), &file)?;
+
+				let mut shader_source = ShaderSource::from_source(source.to_string());
+				shader_source.included_paths.insert(path.as_str().to_owned());
+				Ok(shader_source)
 			}
 
 			Shader::Builder(mut builder) => builder.build_source_from_state(state),
@@ -274,6 +470,24 @@ impl Shader {
 		// Blacklist the shader from including it anymore
 		state.blacklist.insert(self.clone());
 
+		state.include_stack.push(self.name());
+		if state.include_stack.len() > state.limits.max_include_depth {
+			return Err(ShaderBuildError::IncludeTooDeep {
+				chain: state.include_stack.clone(),
+				max: state.limits.max_include_depth,
+			}
+			.into());
+		}
+
+		let shader_source = self.build_includes(state)?;
+
+		state.include_stack.pop();
+		Ok(shader_source)
+	}
+
+	fn build_includes(self, state: &mut ShaderBuilderState) -> Result<ShaderSource> {
+		let name = self.name();
+
 		// The path of the current shader file
 		let parent_path = self.get_parent();
 
@@ -281,18 +495,7 @@ impl Shader {
 		let mut shader_source = self.get_raw_source(state)?;
 
 		let mut byte_offset: isize = 0;
-		let mut includes = Vec::<(String, Range<usize>)>::new();
-
-		// Find all `#include "path/to/shader.wgsl"` in the source
-		let re = Regex::new(r#"(?m)^#include "(.+?)""#).unwrap();
-
-		for caps in re.captures_iter(&shader_source.source) {
-			// The bytes that the `#include "path/to/shader.wgsl"` statement occupies
-			let range = caps.get(0).unwrap().range();
-			// The `path/to/shader.wgsl` part
-			let path_str = caps.get(1).unwrap().as_str().to_owned();
-			includes.push((path_str, range));
-		}
+		let includes = find_include_directives(&shader_source.source);
 
 		// Replace the include statements in the source with the actual source of each
 		// file
@@ -300,11 +503,7 @@ impl Shader {
 			// Offset the range by byte_offset
 			let range = (range.start as isize + byte_offset) as usize..(range.end as isize + byte_offset) as usize;
 
-			// Fix up the path
-			let path_relative: Utf8UnixPathBuf = path!(&path_str)
-				.try_into()
-				.or(Err(anyhow!("Invalid file `{}`", path_str)))?;
-			let path_absolute = rooted_path!(parent_path.join(path_relative));
+			let path_absolute = resolve_include_path(&parent_path, &path_str)?;
 
 			// Recursively build the source of the included file
 			let source_to_include = path_absolute.into_shader().build_recursively(state)?;
@@ -315,12 +514,219 @@ impl Shader {
 
 			// Replace the whole range with the included file source
 			shader_source.extend_range(source_to_include, range);
+			state.check_size(&shader_source, &name)?;
 		}
 
 		Ok(shader_source)
 	}
 }
 
+/// The source of a [`Shader::Source`], shared between its clones.
+///
+/// Generated sources can be megabytes long, and the builders hash and compare
+/// their includes a lot, so the hash is computed the first time it's needed
+/// and kept. Big sources are interned, so that the identical ones the builds
+/// of a hot-reload storm generate share one allocation.
+#[derive(Clone)]
+pub struct SourceText {
+	text: Arc<str>,
+	hash: OnceLock<u64>,
+}
+
+impl SourceText {
+	/// Sources at least this long are interned, the hash of the shorter ones is
+	/// cheaper than the lookup
+	pub const INTERN_MIN_LEN: usize = 64 * 1024;
+
+	pub fn new(text: impl Into<String>) -> Self {
+		let text = text.into();
+		if text.len() < Self::INTERN_MIN_LEN {
+			return Self {
+				text: text.into(),
+				hash: OnceLock::new(),
+			};
+		}
+
+		let hash = hash_text(&text);
+		Self {
+			text: interned_text(hash, text),
+			hash: OnceLock::from(hash),
+		}
+	}
+
+	pub fn as_str(&self) -> &str {
+		&self.text
+	}
+
+	/// Whether both are the same allocation, e.g. clones or interned copies
+	pub fn shares_text_with(&self, other: &Self) -> bool {
+		Arc::ptr_eq(&self.text, &other.text)
+	}
+
+	fn content_hash(&self) -> u64 {
+		*self.hash.get_or_init(|| hash_text(&self.text))
+	}
+}
+
+impl Deref for SourceText {
+	type Target = str;
+
+	fn deref(&self) -> &str {
+		&self.text
+	}
+}
+
+impl fmt::Debug for SourceText {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		fmt::Debug::fmt(&*self.text, f)
+	}
+}
+
+impl Hash for SourceText {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		state.write_u64(self.content_hash());
+	}
+}
+
+impl PartialEq for SourceText {
+	fn eq(&self, other: &Self) -> bool {
+		self.shares_text_with(other) || (self.content_hash() == other.content_hash() && self.text == other.text)
+	}
+}
+
+impl Eq for SourceText {}
+
+impl From<String> for SourceText {
+	fn from(text: String) -> Self {
+		Self::new(text)
+	}
+}
+
+impl From<&str> for SourceText {
+	fn from(text: &str) -> Self {
+		Self::new(text)
+	}
+}
+
+fn hash_text(text: &str) -> u64 {
+	let mut hasher = DefaultHasher::new();
+	text.hash(&mut hasher);
+	hasher.finish()
+}
+
+// The big sources by hash, weakly so that they go away with the last shader
+// that uses them
+static INTERNED_TEXTS: Mutex<Option<HashMap<u64, Weak<str>>>> = Mutex::new(None);
+
+fn interned_text(hash: u64, text: String) -> Arc<str> {
+	let mut interned = INTERNED_TEXTS.lock().unwrap();
+	let interned = interned.get_or_insert_with(HashMap::new);
+
+	if let Some(existing) = interned.get(&hash).and_then(Weak::upgrade) {
+		// A collision keeps its own copy
+		if *existing == *text {
+			return existing;
+		}
+	}
+
+	interned.retain(|_, weak| weak.strong_count() > 0);
+
+	let text = Arc::<str>::from(text);
+	interned.entry(hash).or_insert_with(|| Arc::downgrade(&text));
+	text
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+// The decoded source of every file, by path and content hash, so that the
+// builds of a hot-reload storm don't all decode their own copy of the same
+// files. A file that changed gets a new hash and replaces its old entry.
+static INTERNED_SOURCES: Mutex<Option<HashMap<String, ([u8; 32], Arc<str>)>>> = Mutex::new(None);
+
+fn interned_source(path: &str, file: &rust_embed::EmbeddedFile) -> Result<Arc<str>> {
+	let hash = file.metadata.sha256_hash();
+
+	let mut interned = INTERNED_SOURCES.lock().unwrap();
+	let interned = interned.get_or_insert_with(HashMap::new);
+
+	if let Some((interned_hash, source)) = interned.get(path) {
+		if *interned_hash == hash {
+			return Ok(source.clone());
+		}
+	}
+
+	let source = std::str::from_utf8(&file.data).or(Err(anyhow!("Invalid UTF8 file: {}", path)))?;
+
+	// Only decoded once per version of the file, so that's once per file too
+	let normalized = normalize_source(source);
+	if normalized.len() != source.len() {
+		debug!("`{}` has a BOM or CRLF line endings, normalized them", path);
+	}
+
+	let source = Arc::<str>::from(normalized);
+	interned.insert(path.to_owned(), (hash, source.clone()));
+
+	Ok(source)
+}
+
+/// Strip the UTF-8 BOM and turn the CRLF line endings into LF, which files
+/// edited on Windows tend to have. The directives are matched by line, a BOM
+/// hides the first one and the CRs end up in the `#define` values.
+pub fn normalize_source(source: &str) -> Cow<str> {
+	let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+
+	if source.contains("\r\n") {
+		Cow::Owned(source.replace("\r\n", "\n"))
+	} else {
+		Cow::Borrowed(source)
+	}
+}
+
+/// Find all `#include "path/to/shader.wgsl"` in the source, returning the
+/// included path and the bytes that the whole statement occupies
+pub fn find_include_directives(source: &str) -> Vec<(String, Range<usize>)> {
+	let re = Regex::new(r#"(?m)^#include "(.+?)""#).unwrap();
+
+	re.captures_iter(source)
+		.map(|caps| (caps.get(1).unwrap().as_str().to_owned(), caps.get(0).unwrap().range()))
+		.collect()
+}
+
+/// Resolve the path of an include directive relative to the directory of the
+/// file that contains it
+pub fn resolve_include_path(parent_path: &Utf8UnixPath, path_str: &str) -> Result<Utf8UnixPathBuf> {
+	let path_relative: Utf8UnixPathBuf = path!(path_str)
+		.try_into()
+		.or(Err(anyhow!("Invalid file `{}`", path_str)))?;
+
+	Ok(rooted_path!(parent_path.join(path_relative)))
+}
+
+/// Merge blocks of WGSL `struct` and `const` declarations, keeping only the
+/// first declaration of every name. Anything else in the blocks is dropped.
+///
+/// Used to combine the definitions of nested shader structs, and those of
+/// several resources using the same struct.
+pub fn merge_definitions<'a>(definitions: impl IntoIterator<Item = &'a str>) -> String {
+	let re = Regex::new(r#"(?s)struct\s+(\w+)\s*\{.*?\}\s*;?|const\s+(\w+)[^;]*;"#).unwrap();
+
+	let mut merged = LinkedHashMap::new();
+	for definition in definitions {
+		for caps in re.captures_iter(definition) {
+			let name = caps.get(1).or(caps.get(2)).unwrap().as_str();
+			merged
+				.entry(name.to_owned())
+				.or_insert_with(|| caps.get(0).unwrap().as_str().to_owned());
+		}
+	}
+
+	merged.into_values().collect::<Vec<_>>().join("\n")
+}
+
 /*
 --------------------------------------------------------------------------------
 ||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
@@ -346,13 +752,13 @@ trait IntoShader {
 
 impl IntoShader for String {
 	fn into_shader(self) -> Shader {
-		Shader::Source(self)
+		Shader::Source(self.into())
 	}
 }
 
 impl IntoShader for &str {
 	fn into_shader(self) -> Shader {
-		Shader::Source(self.to_owned())
+		Shader::Source(self.into())
 	}
 }
 
@@ -398,6 +804,8 @@ where
 pub struct ShaderSource {
 	pub source: String,
 	pub resources: Vec<Sarc<dyn ShaderBufferResource>>,
+	/// The absolute paths of the shader files that went into the source
+	pub included_paths: BTreeSet<String>,
 }
 
 impl ShaderSource {
@@ -423,6 +831,7 @@ impl ShaderSource {
 	pub fn extend_range(&mut self, other: ShaderSource, range: Range<usize>) -> &mut Self {
 		self.source.replace_range(range, &other.source);
 		self.resources.extend(other.resources);
+		self.included_paths.extend(other.included_paths);
 		self
 	}
 
@@ -430,6 +839,7 @@ impl ShaderSource {
 	pub fn extend(&mut self, other: ShaderSource) -> &mut Self {
 		self.source.push_str(&other.source);
 		self.resources.extend(other.resources);
+		self.included_paths.extend(other.included_paths);
 		self
 	}
 
@@ -438,6 +848,10 @@ impl ShaderSource {
 		let mut source = self.source;
 		let mut layouts = Vec::new();
 		let mut bindings = Vec::new();
+		let mut swapped_bindings = Vec::new();
+		let mut has_swapped_bindings = false;
+		let mut binding_declarations = Vec::new();
+		let mut definitions = Vec::new();
 
 		let mut binding_index = 0;
 
@@ -447,20 +861,36 @@ impl ShaderSource {
 			let local_sources = resource.binding_source_code(bind_group_index, binding_index);
 			let local_layouts = resource.layouts(gpu.device.features());
 			let local_bindings = resource.binding_resources();
+			let local_swapped_bindings = resource.swapped_binding_resources();
 
 			// If all the lengths are not consistent, then there was a programming mistake and might as well panic to avoid bugs down the line
 			let offset = local_layouts.len();
 			assert_eq!(offset, local_sources.len());
 			assert_eq!(offset, local_bindings.len());
 
+			// Resources without swapped bindings just use the same bindings in both variants
+			match local_swapped_bindings {
+				Some(local_swapped_bindings) => {
+					assert_eq!(offset, local_swapped_bindings.len());
+					has_swapped_bindings = true;
+					swapped_bindings.extend(local_swapped_bindings);
+				}
+				None => swapped_bindings.extend(resource.binding_resources()),
+			}
+
 			source.push_str(&local_sources.join("\n"));
-			source.push_str(resource.other_source_code().unwrap_or_default());
+			binding_declarations.extend(local_sources);
+			definitions.extend(resource.other_source_code());
 			layouts.extend(local_layouts);
 			bindings.extend(local_bindings);
 
 			binding_index += offset as u32;
 		}
 
+		// Several resources can use the same struct, which can only be declared once
+		source.push('\n');
+		source.push_str(&merge_definitions(definitions));
+
 		// Converte the partial layout entry to a proper bind group layout entry
 		let layouts = layouts
 			.into_iter()
@@ -475,17 +905,23 @@ impl ShaderSource {
 		});
 
 		// The bind group for the entire shader
-		let bind_group = gpu.device.create_bind_group(&BindGroupDescriptor {
-			label: Some(&format!("{} Bind Group", label)),
-			layout: &bind_group_layout,
-			entries: &bindings
-				.into_iter()
-				.zip(0..)
-				.map(|(b, i)| BindGroupEntry {
-					binding: i,
-					resource: b,
-				})
-				.collect::<Vec<_>>(),
+		let bind_group = create_bind_group(
+			gpu,
+			format!("{} Bind Group", label),
+			&bind_group_layout,
+			bindings,
+			&binding_declarations,
+		);
+
+		// The variant for the frames where the ping-pong textures are swapped, if needed
+		let swapped_bind_group = has_swapped_bindings.then(|| {
+			create_bind_group(
+				gpu,
+				format!("{} Swapped Bind Group", label),
+				&bind_group_layout,
+				swapped_bindings,
+				&binding_declarations,
+			)
 		});
 
 		let shader_module = gpu.device.create_shader_module(ShaderModuleDescriptor {
@@ -494,19 +930,126 @@ impl ShaderSource {
 		});
 
 		CompiledShader {
+			label,
 			shader_module,
+			binding_declarations,
 			binding: ShaderBufferBindGroup {
 				index: bind_group_index,
 				bind_group_layout,
 				bind_group,
+				swapped_bind_group,
 			},
 		}
 	}
+
+	/// Bind the resources again in place of the bind groups of `compiled`,
+	/// which was built from the same source, e.g. after some of its textures
+	/// were created again at another size.
+	///
+	/// Doesn't compile anything, the layout stays the same so the pipelines
+	/// created from `compiled` can keep being used. Fails if the bindings
+	/// changed in a way that needs another layout (like another texture
+	/// format), since those would need to be compiled again.
+	pub fn rebind(self, gpu: &Gpu, compiled: &mut CompiledShader) -> Result<()> {
+		let mut bindings = Vec::new();
+		let mut swapped_bindings = Vec::new();
+		let mut has_swapped_bindings = false;
+		let mut binding_declarations = Vec::new();
+
+		let mut binding_index = 0;
+
+		// Same as in `build`, without the source code and layouts
+		for resource in self.resources.iter() {
+			let local_sources = resource.binding_source_code(compiled.binding.index, binding_index);
+			let local_bindings = resource.binding_resources();
+
+			match resource.swapped_binding_resources() {
+				Some(local_swapped_bindings) => {
+					has_swapped_bindings = true;
+					swapped_bindings.extend(local_swapped_bindings);
+				}
+				None => swapped_bindings.extend(resource.binding_resources()),
+			}
+
+			binding_index += local_sources.len() as u32;
+			binding_declarations.extend(local_sources);
+			bindings.extend(local_bindings);
+		}
+
+		if binding_declarations != compiled.binding_declarations {
+			bail!(
+				"The bindings of '{}' changed, it needs to be compiled again",
+				compiled.label
+			);
+		}
+
+		let layout = &compiled.binding.bind_group_layout;
+		let bind_group = create_bind_group(
+			gpu,
+			format!("{} Bind Group", compiled.label),
+			layout,
+			bindings,
+			&binding_declarations,
+		);
+		let swapped_bind_group = has_swapped_bindings.then(|| {
+			create_bind_group(
+				gpu,
+				format!("{} Swapped Bind Group", compiled.label),
+				layout,
+				swapped_bindings,
+				&binding_declarations,
+			)
+		});
+
+		compiled.binding.bind_group = bind_group;
+		compiled.binding.swapped_bind_group = swapped_bind_group;
+
+		Ok(())
+	}
+}
+
+/// Like [`CompiledShader::create_pipeline`], panics with the binding
+/// declarations if a binding doesn't validate, e.g. a buffer smaller than the
+/// `min_binding_size` of its layout
+fn create_bind_group(
+	gpu: &Gpu,
+	label: String,
+	layout: &BindGroupLayout,
+	bindings: Vec<BindingResource>,
+	binding_declarations: &[String],
+) -> BindGroup {
+	gpu.device.push_error_scope(ErrorFilter::Validation);
+	let bind_group = gpu.device.create_bind_group(&BindGroupDescriptor {
+		label: Some(&label),
+		layout,
+		entries: &bindings
+			.into_iter()
+			.zip(0..)
+			.map(|(b, i)| BindGroupEntry {
+				binding: i,
+				resource: b,
+			})
+			.collect::<Vec<_>>(),
+	});
+
+	if let Some(error) = pollster::block_on(gpu.device.pop_error_scope()) {
+		panic!(
+			"Couldn't create '{}': {}\nThe bindings of the shader are:\n{}",
+			label,
+			error,
+			binding_declarations.join("\n")
+		);
+	}
+
+	bind_group
 }
 
 #[derive(Debug)]
 pub struct CompiledShader {
+	pub label: String,
 	pub shader_module: ShaderModule,
+	/// The generated WGSL declaration of every binding, in binding order
+	pub binding_declarations: Vec<String>,
 	pub binding: ShaderBufferBindGroup,
 }
 
@@ -514,4 +1057,26 @@ impl CompiledShader {
 	pub fn layouts(&self) -> Vec<&BindGroupLayout> {
 		vec![&self.binding.bind_group_layout]
 	}
+
+	/// Create a pipeline from this shader, panicking with the binding
+	/// declarations of the shader if the pipeline doesn't validate.
+	///
+	/// wgpu only reports the group and binding indices of the offending binding
+	/// (e.g. when a buffer is smaller than what the shader expects), which are
+	/// meaningless without knowing which variable got which index.
+	pub fn create_pipeline<P>(&self, gpu: &Gpu, create: impl FnOnce() -> P) -> P {
+		gpu.device.push_error_scope(ErrorFilter::Validation);
+		let pipeline = create();
+
+		if let Some(error) = pollster::block_on(gpu.device.pop_error_scope()) {
+			panic!(
+				"Couldn't create the pipeline of '{}': {}\nThe bindings of the shader are:\n{}",
+				self.label,
+				error,
+				self.binding_declarations.join("\n")
+			);
+		}
+
+		pipeline
+	}
 }
@@ -1,8 +1,16 @@
-use brainrot::vek::Extent2;
-use wgpu::{TextureAspect, TextureFormat, TextureUsages};
+use std::sync::Arc;
 
-use super::texture::{TexDescriptor, TextureAssetDimensions};
-use crate::libs::shader::Shader;
+use brainrot::vek::{Vec2, Vec3};
+use wgpu::{Buffer, TextureAspect, TextureFormat, TextureUsages};
+
+use super::{
+	smart_arc::Sarc,
+	texture::{TexDescriptor, TextureAssetDimensions},
+};
+use crate::{
+	core::{gpu::Gpu, size::Resolution},
+	libs::shader::Shader,
+};
 
 /*
 --------------------------------------------------------------------------------
@@ -12,6 +20,13 @@ use crate::libs::shader::Shader;
 
 pub trait ShaderFragment {
 	fn shader(&self) -> Shader;
+
+	/// The compute passes this fragment needs to run every frame before the
+	/// renderer's main dispatch. Fragments made of other fragments should pass
+	/// theirs along.
+	fn pre_passes(&self) -> Vec<PrePassDesc> {
+		Vec::new()
+	}
 }
 
 impl<T> ShaderFragment for T
@@ -26,17 +41,82 @@ where
 /// Shader API:\
 /// `fn render_pixel(pixel_coord: vec2u, pixel_size: vec2u)`
 pub trait Renderer: ShaderFragment {
-	fn default_color_texture(&self, resolution: Extent2<u32>) -> TexDescriptor<'static> {
+	fn default_color_texture(&self, resolution: Resolution) -> TexDescriptor<'static> {
 		TexDescriptor {
 			label: "Renderer default output texture",
-			dimensions: TextureAssetDimensions::D2(resolution),
+			dimensions: TextureAssetDimensions::D2(resolution.into()),
 			format: TextureFormat::Rgba32Float,
 			usage: Some(TextureUsages::STORAGE_BINDING | TextureUsages::COPY_SRC),
 			aspect: TextureAspect::All,
 		}
 	}
 
-	fn output_textures(&self, resolution: Extent2<u32>) -> Vec<(String, TexDescriptor)> {
+	/// For the `output_id`, what the intersection hit in every pixel (see
+	/// [`PickingPlugin`](crate::core::picking::PickingPlugin))
+	fn id_texture(&self, resolution: Resolution) -> TexDescriptor<'static> {
+		TexDescriptor {
+			label: "Id output texture",
+			dimensions: TextureAssetDimensions::D2(resolution.into()),
+			format: TextureFormat::R32Uint,
+			usage: Some(TextureUsages::STORAGE_BINDING | TextureUsages::COPY_SRC),
+			aspect: TextureAspect::All,
+		}
+	}
+
+	fn output_textures(&self, resolution: Resolution) -> Vec<(String, TexDescriptor)> {
 		vec![("output_color".to_string(), self.default_color_texture(resolution))]
 	}
+
+	/// For renderers that size buffers or textures of their own from the
+	/// resolution, how to build their shader and pre-passes again at another
+	/// one. The output textures are resized on their own.
+	fn resizer(&self) -> Option<RendererResizer> {
+		None
+	}
+}
+
+/// Builds a renderer's shader and pre-passes for a resolution, see
+/// [`Renderer::resizer`]
+pub type RendererResizer = Arc<dyn Fn(&Gpu, Resolution) -> (Shader, Vec<PrePassDesc>) + Send + Sync>;
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// A compute pass that a fragment runs before the main trace of every frame,
+/// e.g. to build a light grid or animate a texture.
+///
+/// The entry point is a `@compute` function in the fragment's own WGSL. It is
+/// compiled into the renderer's shader, so it shares the bindings of the main
+/// pass, including the ones the fragment declares itself.
+#[derive(Clone, Debug)]
+pub struct PrePassDesc {
+	pub entry_point: String,
+	pub dispatch: PrePassDispatch,
+	/// The shader variables the pass reads and writes. They are checked against
+	/// the bindings and the order of the passes when the renderer is built.
+	pub reads: Vec<String>,
+	pub writes: Vec<String>,
+}
+
+/// How many workgroups a [`PrePassDesc`] is dispatched with
+#[derive(Clone, Debug)]
+pub enum PrePassDispatch {
+	Fixed(Vec3<u32>),
+	/// Enough workgroups of this size to cover the render resolution, should be
+	/// the same as the entry point's `@workgroup_size`
+	Resolution(Vec2<u32>),
+	/// Read the workgroup counts from a buffer of
+	/// [`DispatchIndirectArgs`](crate::libs::buffer::indirect_dispatch::DispatchIndirectArgs)
+	Indirect(Sarc<Buffer>),
+	/// Same as `Indirect`, for args that the shader itself writes, at this byte
+	/// offset. A buffer can't be bound as writable and used for the dispatch at
+	/// the same time, so the args are copied to a buffer of the renderer first,
+	/// which splits the compute pass. The buffer needs the `COPY_SRC` usage.
+	IndirectCopy {
+		buffer: Sarc<Buffer>,
+		offset: u64,
+	},
 }
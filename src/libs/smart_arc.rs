@@ -2,7 +2,7 @@ use std::{
 	fmt::{self, Debug},
 	hash::{Hash, Hasher},
 	ops::Deref,
-	sync::Arc,
+	sync::{Arc, Weak},
 };
 
 use brainrot::bevy;
@@ -26,6 +26,16 @@ impl<T: Sized> Sarc<T> {
 	}
 }
 
+impl<T: ?Sized> Sarc<T> {
+	pub fn downgrade(&self) -> WeakSarc<T> {
+		WeakSarc(Arc::downgrade(&self.0))
+	}
+
+	pub fn strong_count(&self) -> usize {
+		Arc::strong_count(&self.0)
+	}
+}
+
 impl<T: ?Sized> Clone for Sarc<T> {
 	fn clone(&self) -> Self {
 		Self(self.0.clone())
@@ -70,6 +80,38 @@ impl<T: ?Sized> AsRef<T> for Sarc<T> {
 	}
 }
 
+/// A [`Sarc`] that doesn't keep its data alive, e.g. to keep track of
+/// something without being the reason it's never dropped
+pub struct WeakSarc<T: ?Sized>(pub Weak<T>);
+
+impl<T: ?Sized> WeakSarc<T> {
+	pub fn upgrade(&self) -> Option<Sarc<T>> {
+		self.0.upgrade().map(Sarc)
+	}
+
+	/// 0 once the data was dropped
+	pub fn strong_count(&self) -> usize {
+		self.0.strong_count()
+	}
+
+	/// Same as the [`Sarc`] equality, whether both point to the same data
+	pub fn ptr_eq(&self, other: &Self) -> bool {
+		Weak::ptr_eq(&self.0, &other.0)
+	}
+}
+
+impl<T: ?Sized> Clone for WeakSarc<T> {
+	fn clone(&self) -> Self {
+		Self(self.0.clone())
+	}
+}
+
+impl<T: ?Sized> Debug for WeakSarc<T> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_tuple(std::any::type_name::<Self>()).finish()
+	}
+}
+
 impl Sarc<Buffer> {
 	pub fn upload_bytes(&self, gpu: &Gpu, bytes: &[u8], offset: BufferAddress) {
 		gpu.queue.write_buffer(self, offset, bytes)
@@ -1,15 +1,25 @@
 #![allow(dead_code)]
 
-use brainrot::vek::{Extent2, Extent3};
+use std::sync::{
+	atomic::{AtomicBool, Ordering},
+	Arc, Mutex,
+};
+
+use brainrot::vek::{Extent2, Extent3, Vec2, Vec4};
 use image::GenericImageView;
+use log::warn;
 use wgpu::{
-	AddressMode, AstcBlock, AstcChannel, CompareFunction, Extent3d, FilterMode, ImageCopyTexture, ImageDataLayout,
+	AddressMode, AstcBlock, AstcChannel, Buffer, BufferDescriptor, BufferUsages, CommandEncoderDescriptor,
+	CompareFunction, Extent3d, FilterMode, ImageCopyBuffer, ImageCopyTexture, ImageDataLayout, Maintain, MapMode,
 	Origin3d, Sampler, SamplerBorderColor, SamplerDescriptor, StorageTextureAccess, Texture, TextureAspect,
 	TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages, TextureView,
-	TextureViewDescriptor, TextureViewDimension,
+	TextureViewDescriptor, TextureViewDimension, COPY_BYTES_PER_ROW_ALIGNMENT,
 };
 
-use crate::core::gpu::Gpu;
+use crate::{
+	core::gpu::Gpu,
+	libs::smart_arc::{Sarc, WeakSarc},
+};
 
 /*
 --------------------------------------------------------------------------------
@@ -116,8 +126,98 @@ impl SamplerEdges {
 	}
 }
 
+/// What a texture contains right after it is created.
+///
+/// wgpu zeroes textures lazily on first use, which isn't always well-defined
+/// when a shader only partially writes to a texture it also reads from. The
+/// textures that persist across frames should be initialized explicitly.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum InitPolicy {
+	/// Leave it to wgpu. For textures that are entirely written before ever
+	/// being read, or that are cleared by a render pass.
+	#[default]
+	None,
+	Zero,
+	/// Every texel set to this value, converted to the texture format
+	Value(Vec4<f32>),
+}
+
+impl InitPolicy {
+	/// Fill with NaNs, to find out which texels are read before being written
+	pub fn nan() -> Self {
+		Self::Value(Vec4::broadcast(f32::NAN))
+	}
+
+	/// The bytes of a single texel, `None` if there's nothing to initialize
+	fn texel_bytes(&self, format: TextureFormat) -> Option<Vec<u8>> {
+		let texel_size = format.block_copy_size(None)? as usize;
+
+		let value = match self {
+			InitPolicy::None => return None,
+			InitPolicy::Zero => return Some(vec![0; texel_size]),
+			InitPolicy::Value(value) => *value,
+		};
+
+		let bytes = match format {
+			TextureFormat::R32Float | TextureFormat::Rg32Float | TextureFormat::Rgba32Float => {
+				bytemuck::cast_slice(&value.into_array()).to_vec()
+			}
+			TextureFormat::R16Float | TextureFormat::Rg16Float | TextureFormat::Rgba16Float => {
+				bytemuck::cast_slice(&value.map(f32_to_f16_bits).into_array()).to_vec()
+			}
+			TextureFormat::R32Uint | TextureFormat::Rg32Uint | TextureFormat::Rgba32Uint => {
+				bytemuck::cast_slice(&value.map(|x| x as u32).into_array()).to_vec()
+			}
+			// The value is taken as already encoded for the srgb formats
+			TextureFormat::R8Unorm
+			| TextureFormat::Rg8Unorm
+			| TextureFormat::Rgba8Unorm
+			| TextureFormat::Rgba8UnormSrgb => value.map(to_unorm8).into_array().to_vec(),
+			TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => {
+				let [r, g, b, a] = value.map(to_unorm8).into_array();
+				vec![b, g, r, a]
+			}
+			_ => {
+				warn!(
+					"Can't initialize a {:?} texture with a value, zeroing it instead",
+					format
+				);
+				return Some(vec![0; texel_size]);
+			}
+		};
+
+		// Formats with less than 4 channels only take the first few components
+		Some(bytes[..texel_size].to_vec())
+	}
+}
+
+fn to_unorm8(value: f32) -> u8 {
+	(value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// Only handles what textures need: NaNs, infinities and normal numbers, the
+/// rest is flushed to zero
+pub fn f32_to_f16_bits(value: f32) -> u16 {
+	let bits = value.to_bits();
+	let sign = ((bits >> 16) & 0x8000) as u16;
+
+	if value.is_nan() {
+		return sign | 0x7e00;
+	}
+
+	let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+	let mantissa = ((bits >> 13) & 0x3ff) as u16;
+
+	match exponent {
+		e if e >= 0x1f => sign | 0x7c00,
+		e if e <= 0 => sign,
+		e => sign | ((e as u16) << 10) | mantissa,
+	}
+}
+
 #[derive(Debug)]
 pub struct Tex {
+	label: String,
 	view_dimension: TextureViewDimension,
 	aspect: TextureAspect,
 	pub texture: Texture,
@@ -164,23 +264,39 @@ impl Tex {
 		texture
 	}
 
-	// pub fn create_depth_texture(gpu: &Gpu, size: Extent2<u32>, label: &str) -> Self {
-	// 	Self::create_with_sampler(
-	// 		gpu,
-	// 		TexSamplerDescriptor {
-	// 			label,
-	// 			dimensions: TextureAssetDimensions::D2(size),
-	// 			format: Self::DEFAULT_DEPTH_FORMAT,
-	// 			usage: Some(TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING),
-	// 			aspect: TextureAspect::DepthOnly,
-	// 			edges: SamplerEdges::ClampToEdge,
-	// 			filter: FilterMode::Linear,
-	// 			compare: Some(CompareFunction::LessEqual),
-	// 		},
-	// 	)
-	// }
-
-	pub fn create(gpu: &Gpu, mut desc: TexDescriptor, sampler_desc: Option<TexSamplerDescriptor>) -> Self {
+	/// A 2D depth texture that can be rendered to and sampled with a comparison
+	/// sampler
+	pub fn create_depth_texture(gpu: &Gpu, size: Extent2<u32>, label: &str) -> Self {
+		Self::create(
+			gpu,
+			TexDescriptor {
+				label,
+				dimensions: TextureAssetDimensions::D2(size),
+				format: Self::DEFAULT_DEPTH_FORMAT,
+				usage: Some(TextureUsages::RENDER_ATTACHMENT),
+				aspect: TextureAspect::DepthOnly,
+			},
+			Some(TexSamplerDescriptor {
+				filter: FilterMode::Linear,
+				edges: SamplerEdges::ClampToEdge,
+				compare: Some(CompareFunction::LessEqual),
+			}),
+		)
+	}
+
+	pub fn create(gpu: &Gpu, desc: TexDescriptor, sampler_desc: Option<TexSamplerDescriptor>) -> Self {
+		Self::create_with_mips(gpu, desc, 1, sampler_desc)
+	}
+
+	/// Same as [`create`](Self::create), with room for a chain of mip levels
+	/// (see [`mip_levels_for`]). They are left for the caller to fill, and the
+	/// [`view`](Self::view) covers all of them.
+	pub fn create_with_mips(
+		gpu: &Gpu,
+		mut desc: TexDescriptor,
+		mip_level_count: u32,
+		sampler_desc: Option<TexSamplerDescriptor>,
+	) -> Self {
 		let view_dimension = desc.dimensions.get_dimension();
 		let aspect = desc.aspect;
 		let mut sampler = None::<Sampler>;
@@ -204,16 +320,23 @@ impl Tex {
 			}));
 		}
 
+		// Depth formats can't be used as storage textures
+		let storage_usage = if desc.format.is_depth_stencil_format() {
+			TextureUsages::empty()
+		} else {
+			TextureUsages::STORAGE_BINDING
+		};
+
 		let texture = gpu.device.create_texture(&TextureDescriptor {
 			label: Some(&format!("{} Texture", desc.label)),
 			size: desc.dimensions.get_size(),
-			mip_level_count: 1,
+			mip_level_count,
 			sample_count: 1,
 			dimension: view_dimension.compatible_texture_dimension(),
 			format: desc.format,
 			usage: desc.usage.unwrap_or(TextureUsages::empty())
 				| TextureUsages::COPY_DST
-				| TextureUsages::STORAGE_BINDING
+				| storage_usage
 				| TextureUsages::TEXTURE_BINDING,
 			// TODO: Clean up usages
 			view_formats: &[],
@@ -228,6 +351,7 @@ impl Tex {
 		});
 
 		Self {
+			label: desc.label.to_owned(),
 			view_dimension,
 			aspect,
 			texture,
@@ -236,6 +360,38 @@ impl Tex {
 		}
 	}
 
+	/// Fill the whole texture according to the policy. The write happens before
+	/// the next submission, so before any pass of the current frame.
+	pub fn initialize(&self, gpu: &Gpu, policy: InitPolicy) {
+		// Depth textures can't be written to, render passes clear them instead
+		if self.format().is_depth_stencil_format() {
+			return;
+		}
+
+		let Some(texel) = policy.texel_bytes(self.format()) else {
+			return;
+		};
+
+		let size = self.size();
+		let bytes = texel.repeat((size.width * size.height * size.depth_or_array_layers) as usize);
+
+		gpu.queue.write_texture(
+			ImageCopyTexture {
+				aspect: self.aspect,
+				texture: &self.texture,
+				mip_level: 0,
+				origin: Origin3d::ZERO,
+			},
+			&bytes,
+			ImageDataLayout {
+				offset: 0,
+				bytes_per_row: Some(texel.len() as u32 * size.width),
+				rows_per_image: Some(size.height),
+			},
+			size,
+		);
+	}
+
 	pub fn upload_bytes(&self, gpu: &Gpu, bytes: &[u8]) {
 		self.upload_bytes_layer(gpu, bytes, 0)
 	}
@@ -249,30 +405,307 @@ impl Tex {
 		self.upload_image_layer(gpu, img, 0)
 	}
 
+	/// The image is converted to the format of the texture, which needs to be one
+	/// of the 8-bit RGBA formats or `Rgba32Float`/`Rgba16Float` (e.g. for HDR
+	/// images).
+	///
+	/// The layer is one of an array or one depth slice of a 3D texture. The image
+	/// can be smaller than the layer, it then goes in its corner and the rest of
+	/// the layer is left as it is.
 	pub fn upload_image_layer(&self, gpu: &Gpu, img: &image::DynamicImage, layer: u32) {
-		let rgba = img.to_rgba8();
+		let (rgba, bytes_per_pixel) = match self.format() {
+			TextureFormat::Rgba32Float => (bytemuck::cast_slice(img.to_rgba32f().as_raw()).to_vec(), 16),
+			TextureFormat::Rgba16Float => (
+				img.to_rgba32f()
+					.as_raw()
+					.iter()
+					.flat_map(|value| f32_to_f16_bits(*value).to_le_bytes())
+					.collect(),
+				8,
+			),
+			_ => (img.to_rgba8().into_raw(), 4),
+		};
 		let dimensions = img.dimensions();
 
 		// Panic to avoid dumb errors in the long run
 		assert!(layer < self.size().depth_or_array_layers);
-		assert!(dimensions.0 == self.size().width);
-		assert!(dimensions.1 == self.size().height);
+		assert!(dimensions.0 <= self.size().width);
+		assert!(dimensions.1 <= self.size().height);
 
 		gpu.queue.write_texture(
 			ImageCopyTexture {
 				aspect: self.aspect,
 				texture: &self.texture,
 				mip_level: 0,
-				origin: Origin3d::ZERO,
+				origin: Origin3d { x: 0, y: 0, z: layer },
 			},
 			&rgba,
 			ImageDataLayout {
 				offset: 0,
-				bytes_per_row: Some(4 * dimensions.0),
+				bytes_per_row: Some(bytes_per_pixel * dimensions.0),
 				rows_per_image: Some(dimensions.1),
 			},
-			self.size(),
+			// Only the one layer, as far as the image goes
+			Extent3d {
+				width: dimensions.0,
+				height: dimensions.1,
+				depth_or_array_layers: 1,
+			},
+		);
+	}
+
+	/// Write tightly packed texels to the whole texture, row by row and then
+	/// layer by layer (or slice by slice for a 3D texture)
+	pub fn upload_texels(&self, gpu: &Gpu, bytes: &[u8]) {
+		self.upload_texels_mip(gpu, bytes, 0)
+	}
+
+	/// Same as [`upload_texels`](Self::upload_texels), for the whole of one mip
+	/// level
+	pub fn upload_texels_mip(&self, gpu: &Gpu, bytes: &[u8], mip_level: u32) {
+		let bytes_per_texel = self
+			.format()
+			.block_copy_size(Some(self.aspect))
+			.expect("Can't upload to a texture with this format");
+		let size = self.mip_size(mip_level);
+
+		// Panic to avoid dumb errors in the long run
+		assert!(bytes.len() == (size.width * size.height * size.depth_or_array_layers * bytes_per_texel) as usize);
+
+		gpu.queue.write_texture(
+			ImageCopyTexture {
+				aspect: self.aspect,
+				texture: &self.texture,
+				mip_level,
+				origin: Origin3d::ZERO,
+			},
+			bytes,
+			ImageDataLayout {
+				offset: 0,
+				bytes_per_row: Some(bytes_per_texel * size.width),
+				rows_per_image: Some(size.height),
+			},
+			size,
+		);
+	}
+
+	/// Write tightly packed texels to a region of the first layer, leaving the
+	/// rest of the texture as it is
+	pub fn upload_region(&self, gpu: &Gpu, origin: Vec2<u32>, size: Extent2<u32>, bytes: &[u8]) {
+		let bytes_per_texel = self
+			.format()
+			.block_copy_size(Some(self.aspect))
+			.expect("Can't upload to a texture with this format");
+
+		// Panic to avoid dumb errors in the long run
+		assert!(origin.x + size.w <= self.size().width);
+		assert!(origin.y + size.h <= self.size().height);
+		assert!(bytes.len() == (size.w * size.h * bytes_per_texel) as usize);
+
+		gpu.queue.write_texture(
+			ImageCopyTexture {
+				aspect: self.aspect,
+				texture: &self.texture,
+				mip_level: 0,
+				origin: Origin3d {
+					x: origin.x,
+					y: origin.y,
+					z: 0,
+				},
+			},
+			bytes,
+			ImageDataLayout {
+				offset: 0,
+				bytes_per_row: Some(bytes_per_texel * size.w),
+				rows_per_image: Some(size.h),
+			},
+			Extent3d {
+				width: size.w,
+				height: size.h,
+				depth_or_array_layers: 1,
+			},
+		);
+	}
+
+	/// Write whole rows of tightly packed texels to one layer (or slice) of a mip
+	/// level, starting at `row`, e.g. to upload a big texture a bit at a time
+	pub fn upload_rows(&self, gpu: &Gpu, mip_level: u32, layer: u32, row: u32, bytes: &[u8]) {
+		let size = self.mip_size(mip_level);
+		let bytes_per_row = self.bytes_per_texel() * size.width;
+		let rows = bytes.len() as u32 / bytes_per_row;
+
+		// Panic to avoid dumb errors in the long run
+		assert!(bytes.len() as u32 == rows * bytes_per_row);
+		assert!(row + rows <= size.height);
+		assert!(layer < size.depth_or_array_layers);
+
+		gpu.queue.write_texture(
+			ImageCopyTexture {
+				aspect: self.aspect,
+				texture: &self.texture,
+				mip_level,
+				origin: Origin3d { x: 0, y: row, z: layer },
+			},
+			bytes,
+			ImageDataLayout {
+				offset: 0,
+				bytes_per_row: Some(bytes_per_row),
+				rows_per_image: Some(rows),
+			},
+			Extent3d {
+				width: size.width,
+				height: rows,
+				depth_or_array_layers: 1,
+			},
+		);
+	}
+
+	/// Copy the first layer of the texture back from the GPU, tightly packed
+	/// row by row. Blocks until the copy is done.
+	///
+	/// The texture needs to have been created with [`TextureUsages::COPY_SRC`].
+	pub fn read_bytes(&self, gpu: &Gpu) -> Vec<u8> {
+		self.read_bytes_mip(gpu, 0)
+	}
+
+	/// Same as [`read_bytes`](Self::read_bytes), for one mip level
+	pub fn read_bytes_mip(&self, gpu: &Gpu, mip_level: u32) -> Vec<u8> {
+		let size = self.mip_size(mip_level);
+		let bytes_per_pixel = self
+			.format()
+			.block_copy_size(Some(self.aspect))
+			.expect("Can't read back a texture with this format");
+
+		// Rows in the buffer need to be aligned, so they are padded and the padding is
+		// stripped afterwards
+		let unpadded_bytes_per_row = size.width * bytes_per_pixel;
+		let padded_bytes_per_row =
+			unpadded_bytes_per_row.div_ceil(COPY_BYTES_PER_ROW_ALIGNMENT) * COPY_BYTES_PER_ROW_ALIGNMENT;
+
+		let staging_buffer = gpu.device.create_buffer(&BufferDescriptor {
+			label: Some("Tex Readback Buffer"),
+			size: (padded_bytes_per_row * size.height) as u64,
+			usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+
+		let mut encoder = gpu.device.create_command_encoder(&CommandEncoderDescriptor {
+			label: Some("Tex Readback Command Encoder"),
+		});
+		encoder.copy_texture_to_buffer(
+			ImageCopyTexture {
+				aspect: self.aspect,
+				texture: &self.texture,
+				mip_level,
+				origin: Origin3d::ZERO,
+			},
+			ImageCopyBuffer {
+				buffer: &staging_buffer,
+				layout: ImageDataLayout {
+					offset: 0,
+					bytes_per_row: Some(padded_bytes_per_row),
+					rows_per_image: Some(size.height),
+				},
+			},
+			Extent3d {
+				depth_or_array_layers: 1,
+				..size
+			},
 		);
+		gpu.queue.submit([encoder.finish()]);
+
+		let slice = staging_buffer.slice(..);
+		slice.map_async(MapMode::Read, |result| {
+			result.expect("Couldn't map the readback buffer")
+		});
+		gpu.device.poll(Maintain::Wait);
+
+		let bytes = slice
+			.get_mapped_range()
+			.chunks(padded_bytes_per_row as usize)
+			.flat_map(|row| &row[..unpadded_bytes_per_row as usize])
+			.copied()
+			.collect();
+		staging_buffer.unmap();
+
+		bytes
+	}
+
+	/// Copy a single texel of the first layer back from the GPU, e.g. to look
+	/// at one pixel of an output without reading all of it. Blocks until the
+	/// copy is done.
+	///
+	/// The texture needs to have been created with [`TextureUsages::COPY_SRC`].
+	pub fn read_texel(&self, gpu: &Gpu, texel: Vec2<u32>) -> Vec<u8> {
+		self.request_texel(gpu, texel).wait(gpu)
+	}
+
+	/// Same as [`read_texel`](Self::read_texel), without waiting for the copy:
+	/// the texel can be taken from the [`TexelReadback`] a few frames later.
+	/// The copy is submitted right away, so it sees what was rendered before.
+	pub fn request_texel(&self, gpu: &Gpu, texel: Vec2<u32>) -> TexelReadback {
+		let bytes_per_pixel = self
+			.format()
+			.block_copy_size(Some(self.aspect))
+			.expect("Can't read back a texture with this format");
+
+		// Panic to avoid dumb errors in the long run
+		assert!(texel.x < self.size().width && texel.y < self.size().height);
+
+		let staging_buffer = gpu.device.create_buffer(&BufferDescriptor {
+			label: Some("Tex Texel Readback Buffer"),
+			size: bytes_per_pixel as u64,
+			usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+
+		let mut encoder = gpu.device.create_command_encoder(&CommandEncoderDescriptor {
+			label: Some("Tex Texel Readback Command Encoder"),
+		});
+		encoder.copy_texture_to_buffer(
+			ImageCopyTexture {
+				aspect: self.aspect,
+				texture: &self.texture,
+				mip_level: 0,
+				origin: Origin3d {
+					x: texel.x,
+					y: texel.y,
+					z: 0,
+				},
+			},
+			ImageCopyBuffer {
+				buffer: &staging_buffer,
+				// A single row doesn't need to be aligned
+				layout: ImageDataLayout {
+					offset: 0,
+					bytes_per_row: None,
+					rows_per_image: None,
+				},
+			},
+			Extent3d {
+				width: 1,
+				height: 1,
+				depth_or_array_layers: 1,
+			},
+		);
+		gpu.queue.submit([encoder.finish()]);
+
+		let readback = TexelReadback {
+			buffer: staging_buffer,
+			mapped: Arc::new(AtomicBool::new(false)),
+		};
+
+		let mapped = readback.mapped.clone();
+		readback.buffer.slice(..).map_async(MapMode::Read, move |result| {
+			result.expect("Couldn't map the readback buffer");
+			mapped.store(true, Ordering::Release);
+		});
+
+		readback
+	}
+
+	pub fn label(&self) -> &str {
+		&self.label
 	}
 
 	pub fn view_dimension(&self) -> TextureViewDimension {
@@ -287,9 +720,116 @@ impl Tex {
 		self.texture.size()
 	}
 
+	/// The size of a mip level, the layers of an array texture aren't halved
+	pub fn mip_size(&self, mip_level: u32) -> Extent3d {
+		self.size().mip_level_size(mip_level, self.dimension())
+	}
+
+	pub fn mip_level_count(&self) -> u32 {
+		self.texture.mip_level_count()
+	}
+
+	/// A view of a single mip level, e.g. to write to it as a storage texture
+	pub fn mip_view(&self, mip_level: u32) -> TextureView {
+		assert!(mip_level < self.mip_level_count());
+
+		self.texture.create_view(&TextureViewDescriptor {
+			label: Some(&format!("{} Texture View (mip {})", self.label, mip_level)),
+			format: Some(self.format()),
+			dimension: Some(self.view_dimension),
+			aspect: self.aspect,
+			base_mip_level: mip_level,
+			mip_level_count: Some(1),
+			..Default::default()
+		})
+	}
+
 	pub fn format(&self) -> TextureFormat {
 		self.texture.format()
 	}
+
+	pub fn bytes_per_texel(&self) -> u32 {
+		self.format()
+			.block_copy_size(Some(self.aspect))
+			.expect("Not a format that can be copied by texel")
+	}
+}
+
+/// How many mip levels a full chain down to 1x1 has for this size
+pub fn mip_levels_for(size: Extent2<u32>) -> u32 {
+	u32::BITS - size.w.max(size.h).max(1).leading_zeros()
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// A texel on its way back from the GPU, see [`Tex::request_texel`]
+pub struct TexelReadback {
+	buffer: Buffer,
+	mapped: Arc<AtomicBool>,
+}
+
+impl TexelReadback {
+	/// The bytes of the texel once the copy is done, `None` until then (and after
+	/// they were taken). Only polls the GPU, doesn't wait for it.
+	pub fn try_take(&self, gpu: &Gpu) -> Option<Vec<u8>> {
+		gpu.device.poll(Maintain::Poll);
+		if !self.mapped.swap(false, Ordering::Acquire) {
+			return None;
+		}
+
+		let bytes = self.buffer.slice(..).get_mapped_range().to_vec();
+		self.buffer.unmap();
+		Some(bytes)
+	}
+
+	/// Block until the copy is done
+	pub fn wait(self, gpu: &Gpu) -> Vec<u8> {
+		gpu.device.poll(Maintain::Wait);
+		self.try_take(gpu)
+			.expect("The readback should be mapped after waiting for the GPU")
+	}
+}
+
+/*
+--------------------------------------------------------------------------------
+||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||||
+--------------------------------------------------------------------------------
+*/
+
+/// Every texture created through [`Tex::tracked`], kept in the [`Gpu`]
+/// resource that created them
+#[derive(Default)]
+pub struct TrackedTextures(Mutex<Vec<WeakSarc<Tex>>>);
+
+impl TrackedTextures {
+	/// The tracked textures that are still alive, oldest first. They are weak so
+	/// that looking at them doesn't change their strong count.
+	pub fn alive(&self) -> Vec<WeakSarc<Tex>> {
+		let mut tracked = self.0.lock().unwrap();
+		tracked.retain(|tex| tex.strong_count() > 0);
+		tracked.clone()
+	}
+
+	fn push(&self, tex: WeakSarc<Tex>) {
+		// Drop the dead ones here too, resizing would pile them up otherwise
+		let mut tracked = self.0.lock().unwrap();
+		tracked.retain(|tex| tex.strong_count() > 0);
+		tracked.push(tex);
+	}
+}
+
+impl Tex {
+	/// Same as [`Sarc::new`], but the texture shows up in
+	/// [`Gpu::tracked_textures`] for as long as it's alive
+	pub fn tracked(self, gpu: &Gpu) -> Sarc<Tex> {
+		let tex = Sarc::new(self);
+		gpu.tracked_textures.push(tex.downgrade());
+		tex
+	}
 }
 
 /*
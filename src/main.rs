@@ -1,10 +1,40 @@
-use log::LevelFilter;
+use log::{error, LevelFilter};
+use pbr_tracer::core::{
+	logging,
+	presentation::PresentationMode,
+	rendering::capture::{CaptureSettings, HighQualityCapture},
+};
 
 fn main() {
-	env_logger::Builder::new()
-		.filter_level(LevelFilter::Error)
-		.filter_module("pbr_tracer", LevelFilter::Debug)
-		.init();
+	logging::init(LevelFilter::Error, &[("pbr_tracer", LevelFilter::Debug)]);
 
-	pbr_tracer::run();
+	let args = std::env::args().skip(1).collect::<Vec<_>>();
+
+	// `--capture <path>` renders a single high quality still and exits
+	let capture_path = args
+		.iter()
+		.position(|arg| arg == "--capture")
+		.and_then(|i| args.get(i + 1))
+		.cloned();
+
+	// `--presentation` starts with the camera on the turntable
+	let presentation = args.iter().any(|arg| arg == "--presentation");
+
+	pbr_tracer::run_with(|app| {
+		app.world.resource_mut::<PresentationMode>().enabled = presentation;
+
+		let Some(path) = capture_path else {
+			return;
+		};
+
+		let settings = CaptureSettings {
+			path,
+			exit_when_done: true,
+			..Default::default()
+		};
+
+		if let Err(e) = app.world.resource_mut::<HighQualityCapture>().request(settings) {
+			error!("Couldn't start the capture: {:#}", e);
+		}
+	});
 }
@@ -0,0 +1,5 @@
+// No ambient occlusion, see ambient_occlusion.rs
+
+fn ambient_occlusion(p: vec3f, n: vec3f) -> f32 {
+	return 1.0;
+}
@@ -0,0 +1,24 @@
+// Samples the distance field at a few points along the normal. With nothing
+// around, the distance at each point is how far it is from the surface, and
+// anything closer means something is in the way.
+// (after https://iquilezles.org/articles/nvscene2008/rwwtt.pdf)
+
+fn ambient_occlusion(p: vec3f, n: vec3f) -> f32 {
+	let settings = ambient_occlusion_settings;
+	
+	var occlusion = 0.0;
+	var total_weight = 0.0;
+	// The closer samples matter more
+	var weight = 1.0;
+	
+	for (var i = 0u; i < settings.samples; i++) {
+		let h = settings.radius * f32(i + 1u) / f32(settings.samples);
+		let distance = scene_sdf(p + n * h).distance;
+		
+		occlusion += weight * saturate((h - distance) / h);
+		total_weight += weight;
+		weight *= 0.5;
+	}
+	
+	return 1.0 - settings.strength * occlusion / max(total_weight, 1e-6);
+}
@@ -0,0 +1,37 @@
+#include "/sampling/sampling.wgsl"
+
+// Casts shadow rays around the normal, works with any intersector that has
+// occluded()
+
+// How far off the surface the rays start, so that they don't hit it
+const AMBIENT_OCCLUSION_BIAS: f32 = 0.001;
+const AMBIENT_OCCLUSION_TAU: f32 = 6.28318530718;
+
+
+fn ambient_occlusion(p: vec3f, n: vec3f) -> f32 {
+	let settings = ambient_occlusion_settings;
+	
+	// The same noise every frame, unless the frames are averaged
+	let frame_seed = select(0u, globals.seed, globals.accumulating != 0u);
+	let seed = pixel_seed(shading_pixel, globals.resolution, frame_seed);
+	
+	let up = select(vec3f(1.0, 0.0, 0.0), vec3f(0.0, 1.0, 0.0), abs(n.x) > 0.9);
+	let tangent = normalize(cross(n, up));
+	let bitangent = cross(n, tangent);
+	
+	var hits = 0u;
+	for (var i = 0u; i < settings.samples; i++) {
+		let random = seed_to_unit(sampling_hash(seed + vec3u(i)));
+		
+		// Cosine-weighted around the normal
+		let r = sqrt(random.x);
+		let angle = AMBIENT_OCCLUSION_TAU * random.y;
+		let dir = tangent * r * cos(angle) + bitangent * r * sin(angle) + n * sqrt(1.0 - random.x);
+		
+		if occluded(p + n * AMBIENT_OCCLUSION_BIAS, dir, settings.radius) {
+			hits++;
+		}
+	}
+	
+	return 1.0 - settings.strength * f32(hits) / f32(max(settings.samples, 1u));
+}
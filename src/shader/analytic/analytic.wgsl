@@ -0,0 +1,109 @@
+
+// Closer than this is the surface the ray starts from
+const ANALYTIC_MIN_DISTANCE: f32 = 0.0001;
+
+
+fn intersect_scene(ray_origin: vec3f, ray_dir: vec3f) -> Intersection {
+	let object = Object(vec3f(1, 0, 0), 0u);
+	var intersection = Intersection(false, object, camera.z_far, vec3f(0), vec3f(0), -ray_dir, vec2f(0), vec4f(0), 0u);
+	
+	for (var i = 0u; i < arrayLength(&primitives); i++) {
+		let primitive = primitives[i];
+		
+		// The ray direction isn't normalized in the shape's space, so the distances
+		// are still the ones in the scene
+		let origin = (primitive.inverse_transform * vec4f(ray_origin, 1.0)).xyz;
+		let dir = (primitive.inverse_transform * vec4f(ray_dir, 0.0)).xyz;
+		
+		// The distance and the normal in the shape's space, a negative distance is a miss
+		var hit = vec4f(-1.0);
+		switch (primitive.kind) {
+			case PRIMITIVE_KIND_SPHERE: { hit = intersect_unit_sphere(origin, dir); }
+			case PRIMITIVE_KIND_PLANE: { hit = intersect_unit_plane(origin, dir); }
+			case PRIMITIVE_KIND_BOX: { hit = intersect_unit_box(origin, dir); }
+			default: {}
+		}
+		
+		if (hit.x < ANALYTIC_MIN_DISTANCE || hit.x >= intersection.distance) {
+			continue;
+		}
+		
+		intersection.has_hit = true;
+		intersection.object = Object(primitive.color.rgb, primitive.material_id);
+		intersection.distance = hit.x;
+		intersection.position = ray_origin + ray_dir * hit.x;
+		// Normals are transformed by the inverse transpose
+		intersection.normal = normalize((transpose(primitive.inverse_transform) * vec4f(hit.yzw, 0.0)).xyz);
+		intersection.id = i + 1u;
+	}
+	
+	return analytic_fallback(ray_origin, ray_dir, intersection);
+}
+
+// Whether anything is between the origin and max_t, for the shadow rays
+fn occluded(ray_origin: vec3f, ray_dir: vec3f, max_t: f32) -> bool {
+	let intersection = intersect_scene(ray_origin, ray_dir);
+	return intersection.has_hit && intersection.distance < max_t;
+}
+
+fn intersect_unit_sphere(origin: vec3f, dir: vec3f) -> vec4f {
+	let a = dot(dir, dir);
+	let b = dot(origin, dir);
+	let c = dot(origin, origin) - 1.0;
+	
+	let discriminant = b * b - a * c;
+	if (discriminant < 0.0) {
+		return vec4f(-1.0);
+	}
+	
+	// The far side when the ray starts inside
+	var t = (-b - sqrt(discriminant)) / a;
+	if (t < ANALYTIC_MIN_DISTANCE) {
+		t = (-b + sqrt(discriminant)) / a;
+	}
+	
+	return vec4f(t, origin + dir * t);
+}
+
+fn intersect_unit_plane(origin: vec3f, dir: vec3f) -> vec4f {
+	if (abs(dir.y) < 1e-8) {
+		return vec4f(-1.0);
+	}
+	
+	// Facing the ray, whichever side it comes from
+	let normal = vec3f(0.0, -sign(dir.y), 0.0);
+	return vec4f(-origin.y / dir.y, normal);
+}
+
+fn intersect_unit_box(origin: vec3f, dir: vec3f) -> vec4f {
+	let inv_dir = 1.0 / dir;
+	let t1 = (vec3f(-1.0) - origin) * inv_dir;
+	let t2 = (vec3f(1.0) - origin) * inv_dir;
+	
+	let t_min = min(t1, t2);
+	let t_max = max(t1, t2);
+	let t_near = max(t_min.x, max(t_min.y, t_min.z));
+	let t_far = min(t_max.x, min(t_max.y, t_max.z));
+	
+	if (t_near > t_far || t_far < ANALYTIC_MIN_DISTANCE) {
+		return vec4f(-1.0);
+	}
+	
+	// The far side when the ray starts inside
+	var t = t_near;
+	if (t < ANALYTIC_MIN_DISTANCE) {
+		t = t_far;
+	}
+	
+	// The face is on the axis where the hit is furthest from the center
+	let p = origin + dir * t;
+	let d = abs(p);
+	var normal = vec3f(0.0, 0.0, sign(p.z));
+	if (d.x >= d.y && d.x >= d.z) {
+		normal = vec3f(sign(p.x), 0.0, 0.0);
+	} else if (d.y >= d.z) {
+		normal = vec3f(0.0, sign(p.y), 0.0);
+	}
+	
+	return vec4f(t, normal);
+}
@@ -0,0 +1,14 @@
+// The parts of the scene without an analytic form are marched, but only up to
+// the closest primitive
+
+fn analytic_fallback(ray_origin: vec3f, ray_dir: vec3f, intersection: Intersection) -> Intersection {
+	let max_distance = min(intersection.distance, min(raymarch_settings.max_distance, camera.z_far));
+	var marched = raymarch_sdf(ray_origin, ray_dir, max_distance);
+	
+	if marched.has_hit && marched.distance < intersection.distance {
+		// The marched parts come after the primitives
+		marched.id += arrayLength(&primitives);
+		return marched;
+	}
+	return intersection;
+}
@@ -0,0 +1,5 @@
+// Everything in the scene is a primitive
+
+fn analytic_fallback(ray_origin: vec3f, ray_dir: vec3f, intersection: Intersection) -> Intersection {
+	return intersection;
+}
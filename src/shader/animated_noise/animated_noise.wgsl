@@ -0,0 +1,51 @@
+// How many lattice cells the texture spans, on both axes
+const ANIMATED_NOISE_CELLS: u32 = 16u;
+
+@compute
+@workgroup_size(8, 8, 1)
+fn animate_noise(@builtin(global_invocation_id) gid: vec3u) {
+	let size = textureDimensions(animated_noise);
+	if any(gid.xy >= size) {
+		return;
+	}
+	
+	let time = f32(globals.frame) * animated_noise_speed;
+	let p = (vec2f(gid.xy) + 0.5) / vec2f(size) * f32(ANIMATED_NOISE_CELLS);
+	
+	// Blend between two noise slices so that it changes smoothly over time
+	let slice = u32(floor(time));
+	let t = smoothstep(0.0, 1.0, fract(time));
+	let value = mix(animated_noise_value(p, slice), animated_noise_value(p, slice + 1u), t);
+	
+	textureStore(animated_noise, gid.xy, vec4f(value, 0.0, 0.0, 1.0));
+}
+
+fn animated_noise_at(uv: vec2f) -> f32 {
+	let size = textureDimensions(animated_noise);
+	let texel = vec2u(fract(uv) * vec2f(size)) % size;
+	return textureLoad(animated_noise, texel).r;
+}
+
+fn animated_noise_value(p: vec2f, slice: u32) -> f32 {
+	let cell = vec2u(floor(p));
+	let f = smoothstep(vec2f(0.0), vec2f(1.0), fract(p));
+	
+	let a = animated_noise_hash(cell, slice);
+	let b = animated_noise_hash(cell + vec2u(1u, 0u), slice);
+	let c = animated_noise_hash(cell + vec2u(0u, 1u), slice);
+	let d = animated_noise_hash(cell + vec2u(1u, 1u), slice);
+	
+	return mix(mix(a, b, f.x), mix(c, d, f.x), f.y);
+}
+
+// Wraps the lattice so that the texture tiles
+fn animated_noise_hash(cell: vec2u, slice: u32) -> f32 {
+	let wrapped = cell % ANIMATED_NOISE_CELLS;
+	
+	var h = (wrapped.x * 73856093u) ^ (wrapped.y * 19349663u) ^ (slice * 83492791u);
+	h = (h ^ (h >> 16u)) * 0x45d9f3bu;
+	h = (h ^ (h >> 16u)) * 0x45d9f3bu;
+	h = h ^ (h >> 16u);
+	
+	return f32(h) / 4294967295.0;
+}
\ No newline at end of file
@@ -0,0 +1,24 @@
+// Box filters the supersampled render down to the capture size, and adds it to
+// the average of all the capture frames
+
+@compute
+@workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE, 1)
+fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
+	let size = textureDimensions(capture_accumulation);
+	
+	if gid.x >= size.x || gid.y >= size.y {
+		return;
+	}
+	
+	var sum = vec4f(0.0);
+	for (var y = 0u; y < capture_factor; y++) {
+		for (var x = 0u; x < capture_factor; x++) {
+			sum += textureLoad(capture_source, gid.xy * capture_factor + vec2u(x, y));
+		}
+	}
+	
+	let color = sum / f32(capture_factor * capture_factor);
+	let accumulated = textureLoad(capture_accumulation, gid.xy);
+	
+	textureStore(capture_accumulation, gid.xy, accumulated + color * capture_weight);
+}
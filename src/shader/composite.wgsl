@@ -1,3 +1,10 @@
+#include "composite/viewport.wgsl"
+#include "composite/debug_view.wgsl"
+
+const PI: f32 = 3.14159265358979;
+
+// The window pixel being drawn, for the composites that need it
+var<private> composite_pixel: vec2u;
 
 @vertex
 fn vs_main(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4f {
@@ -16,22 +23,72 @@ fn fs_main(@builtin(position) frag_coord: vec4f) -> @location(0) vec4f {
 	
 	// Invert the y coordinate since texture.y is from top to bottom.
 	tex_coord.y = 1.0 - tex_coord.y;
+	
+	composite_pixel = vec2u(frag_coord.xy);
+	
+	// See DebugView, the mode is uniform so the composite still has uniform
+	// control flow
+	if debug_view.mode != 0u {
+		return debug_view_color(tex_coord);
+	}
+	
+	// See CompositeFragment
+	return composite(tex_coord);
+}
+
+// The output at uv, scaled to the window by the current upscaler
+fn composite_upscaled(uv: vec2f) -> vec4f {
+	// Outside of the switch, textureSample needs uniform control flow
+	let bilinear = textureSample(out_texture, out_sampler, uv);
 
-	return textureSample(out_texture, out_sampler, tex_coord);
+	switch upscaler.mode {
+		// Lanczos3
+		case 1u: {
+			return sample_lanczos3(uv);
+		}
+		// FSR, already upscaled to the window by the compute passes
+		case 2u: {
+			return textureLoad(upscaled_texture, composite_pixel, 0);
+		}
+		default: {
+			return bilinear;
+		}
+	}
 }
 
-fn get_texture_coordinates(frag_coord: vec2f, texture_size: vec2f, screen_size: vec2f) -> vec2f {
-	if texture_size.x / texture_size.y < screen_size.x / screen_size.y {
-		// texture is TALLER than the screen
-		let x = frag_coord.x / screen_size.x;
-		let size_y = screen_size.y / screen_size.x / texture_size.y * texture_size.x;
-		let y = frag_coord.y / screen_size.y * size_y + (1.0 - size_y) * 0.5;
-		return vec2f(x, y);
-	} else {
-		// texture is WIDER than the screen
-		let y = frag_coord.y / screen_size.y;
-		let size_x = screen_size.x / screen_size.y / texture_size.x * texture_size.y;
-		let x = frag_coord.x / screen_size.x * size_x + (1.0 - size_x) * 0.5;
-		return vec2f(x, y);
+fn lanczos3(x: f32) -> f32 {
+	if abs(x) < 1e-5 {
+		return 1.0;
+	}
+	if abs(x) >= 3.0 {
+		return 0.0;
 	}
+	
+	let px = PI * x;
+	return 3.0 * sin(px) * sin(px / 3.0) / (px * px);
 }
+
+// 6x6 taps around the texel, normalized since the weights don't quite sum to 1
+fn sample_lanczos3(tex_coord: vec2f) -> vec4f {
+	let size = vec2i(textureDimensions(out_texture));
+	let p = tex_coord * vec2f(size) - 0.5;
+	let base = vec2i(floor(p));
+	let f = p - floor(p);
+	
+	var sum = vec4f(0.0);
+	var weight_sum = 0.0;
+	
+	for (var y = -2; y <= 3; y++) {
+		let weight_y = lanczos3(f32(y) - f.y);
+		
+		for (var x = -2; x <= 3; x++) {
+			let weight = lanczos3(f32(x) - f.x) * weight_y;
+			let texel = clamp(base + vec2i(x, y), vec2i(0), size - 1);
+			
+			sum += textureLoad(out_texture, texel, 0) * weight;
+			weight_sum += weight;
+		}
+	}
+	
+	return sum / weight_sum;
+}
\ No newline at end of file
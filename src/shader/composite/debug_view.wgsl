@@ -0,0 +1,50 @@
+// The outputs other than the color, see DebugView. Sampled at the nearest
+// level with the implicit filtering, like the bilinear upscaler would.
+fn debug_view_color(uv: vec2f) -> vec4f {
+	switch debug_view.mode {
+		// Normal, stored as 0.5 * n + 0.5
+		case 1u: {
+			let normal = textureSampleLevel(debug_normal_texture, debug_normal_sampler, uv, 0.0).rgb;
+			return vec4f(debug_view_stored(normal), 1.0);
+		}
+		// Depth, stored as the distance over the far plane
+		case 2u: {
+			let distance = textureSampleLevel(debug_depth_texture, debug_depth_sampler, uv, 0.0).r * debug_view.z_far;
+			let near = max(debug_view.z_near, 1e-4);
+			
+			// Logarithmic, or everything close is about black
+			let depth = log(max(distance, near) / near) / log(max(debug_view.z_far / near, 1.0 + 1e-4));
+			return vec4f(debug_view_stored(vec3f(clamp(depth, 0.0, 1.0))), 1.0);
+		}
+		// Steps, or whatever else the renderer's debug output holds in [0, 1]
+		case 4u: {
+			let value = textureSampleLevel(debug_output_texture, debug_output_sampler, uv, 0.0).r;
+			return vec4f(debug_view_heatmap(clamp(value, 0.0, 1.0)), 1.0);
+		}
+		// Heatmap
+		default: {
+			let color = textureSampleLevel(out_texture, out_sampler, uv, 0.0).rgb;
+			let luminance = dot(color, vec3f(0.2126, 0.7152, 0.0722));
+			
+			// From -6 to +6 stops
+			let t = clamp(log2(max(luminance, 1e-6)) / 12.0 + 0.5, 0.0, 1.0);
+			return vec4f(debug_view_heatmap(t), 1.0);
+		}
+	}
+}
+
+// The window's sRGB encodes the color, so the stored values are decoded first
+// to show up as they are
+fn debug_view_stored(value: vec3f) -> vec3f {
+	return pow(value, vec3f(2.2));
+}
+
+// Blue, cyan, green, yellow, red
+fn debug_view_heatmap(t: f32) -> vec3f {
+	let x = t * 4.0;
+	return vec3f(
+		clamp(x - 2.0, 0.0, 1.0),
+		clamp(2.0 - abs(x - 2.0), 0.0, 1.0),
+		clamp(2.0 - x, 0.0, 1.0),
+	);
+}
@@ -0,0 +1,4 @@
+// The render as it is, see DefaultComposite
+fn composite(uv: vec2f) -> vec4f {
+	return composite_upscaled(uv);
+}
@@ -0,0 +1,88 @@
+#include "viewport.wgsl"
+
+// Edge adaptive upscaling in the spirit of FSR1's EASU: a lanczos2-like kernel
+// over the 4x4 texels around the sample, stretched along the local edge so
+// that edges stay sharp without staircases, then clamped to the nearest 2x2 to
+// avoid ringing.
+
+@compute
+@workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE, 1)
+fn main(@builtin(global_invocation_id) gid: vec3u) {
+	let output_size = textureDimensions(upscale_intermediate);
+	if any(gid.xy >= output_size) {
+		return;
+	}
+	
+	let texture_size = vec2f(textureDimensions(out_texture));
+	var tex_coord = get_texture_coordinates(vec2f(gid.xy) + 0.5, texture_size, vec2f(output_size));
+	tex_coord.y = 1.0 - tex_coord.y;
+	
+	let p = tex_coord * texture_size - 0.5;
+	let base = vec2i(floor(p));
+	let f = p - floor(p);
+	
+	// The direction of the gradient, from the 2x2 texels around the sample
+	let a = easu_luma(easu_load(base));
+	let b = easu_luma(easu_load(base + vec2i(1, 0)));
+	let c = easu_luma(easu_load(base + vec2i(0, 1)));
+	let d = easu_luma(easu_load(base + vec2i(1, 1)));
+	
+	var dir = vec2f(mix(b - a, d - c, f.y), mix(c - a, d - b, f.x));
+	let len = length(dir);
+	
+	// Flat areas get a round kernel
+	var stretch = 0.0;
+	if len > 1e-5 {
+		dir /= len;
+		stretch = saturate(len * 4.0);
+	} else {
+		dir = vec2f(1.0, 0.0);
+	}
+	
+	// Sharper across the edge, softer along it
+	let scale = vec2f(1.0 + stretch, 1.0 - 0.5 * stretch);
+	
+	var sum = vec4f(0.0);
+	var weight_sum = 0.0;
+	
+	for (var y = -1; y <= 2; y++) {
+		for (var x = -1; x <= 2; x++) {
+			let offset = vec2f(f32(x), f32(y)) - f;
+			let v = vec2f(dot(offset, dir), dot(offset, vec2f(-dir.y, dir.x))) * scale;
+			let weight = easu_lanczos2(dot(v, v));
+			
+			sum += easu_load(base + vec2i(x, y)) * weight;
+			weight_sum += weight;
+		}
+	}
+	
+	let color = sum / max(weight_sum, 1e-5);
+	
+	// Deringing
+	let color_a = easu_load(base);
+	let color_b = easu_load(base + vec2i(1, 0));
+	let color_c = easu_load(base + vec2i(0, 1));
+	let color_d = easu_load(base + vec2i(1, 1));
+	let lo = min(min(color_a, color_b), min(color_c, color_d));
+	let hi = max(max(color_a, color_b), max(color_c, color_d));
+	
+	textureStore(upscale_intermediate, gid.xy, clamp(color, lo, hi));
+}
+
+fn easu_load(texel: vec2i) -> vec4f {
+	let size = vec2i(textureDimensions(out_texture));
+	return textureLoad(out_texture, clamp(texel, vec2i(0), size - 1), 0);
+}
+
+fn easu_luma(color: vec4f) -> f32 {
+	return dot(color.rgb, vec3f(0.299, 0.587, 0.114));
+}
+
+// The polynomial approximation of lanczos2 that FSR uses, on the squared
+// distance and cut off at 2
+fn easu_lanczos2(distance2: f32) -> f32 {
+	let x2 = min(distance2, 4.0);
+	let window = 0.25 * x2 - 1.0;
+	let base = 0.4 * x2 - 1.0;
+	return (25.0 / 16.0 * base * base - (25.0 / 16.0 - 1.0)) * window * window;
+}
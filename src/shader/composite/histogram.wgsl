@@ -0,0 +1,41 @@
+struct VertexOutput {
+	@builtin(position) position: vec4f,
+	@location(0) uv: vec2f,
+}
+
+// The same 4 vertices as in composite.wgsl, the viewport puts them in the panel
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+	let corner = vec2f(f32(vertex_index & 1), f32((vertex_index >> 1) & 1));
+
+	var out: VertexOutput;
+	out.position = vec4(corner * 2.0 - 1.0, 0.0, 1.0);
+	// From the bottom left, the bars grow upwards
+	out.uv = corner;
+	return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+	// About a pixel wide, before anything diverges
+	let line_width = fwidth(in.uv.x);
+	
+	let bin = min(u32(in.uv.x * f32(HISTOGRAM_BINS)), HISTOGRAM_BINS - 1u);
+	
+	// Where middle gray ends up, see HistogramMarker
+	let middle_gray = histogram_marker.middle_gray;
+	if middle_gray >= 0.0 && abs(in.uv.x - middle_gray) < line_width {
+		return vec4f(1.0, 0.8, 0.1, 1.0);
+	}
+	
+	if in.uv.y < histogram_bars[bin] {
+		// The clipped shadows and highlights
+		if bin == 0u || bin == HISTOGRAM_BINS - 1u {
+			return vec4f(0.9, 0.1, 0.1, 1.0);
+		}
+		return vec4f(0.8, 0.8, 0.8, 0.9);
+	}
+	
+	// See-through background
+	return vec4f(0.0, 0.0, 0.0, 0.5);
+}
@@ -0,0 +1,21 @@
+struct VertexOutput {
+	@builtin(position) position: vec4f,
+	@location(0) tex_coord: vec2f,
+}
+
+// The same 4 vertices as in composite.wgsl, the viewport puts them in the panel
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+	let corner = vec2f(f32(vertex_index & 1), f32((vertex_index >> 1) & 1));
+
+	var out: VertexOutput;
+	out.position = vec4(corner * 2.0 - 1.0, 0.0, 1.0);
+	// Bottom up, the same way the composite pass draws the main output
+	out.tex_coord = corner;
+	return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+	return textureSample(pip_texture, pip_sampler, in.tex_coord);
+}
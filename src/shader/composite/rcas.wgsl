@@ -0,0 +1,37 @@
+// Contrast adaptive sharpening in the spirit of FSR1's RCAS: sharpens with the
+// 4 direct neighbours, as much as possible without making any channel go past
+// the neighbourhood's min or max.
+
+@compute
+@workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE, 1)
+fn main(@builtin(global_invocation_id) gid: vec3u) {
+	let size = textureDimensions(upscale_output);
+	if any(gid.xy >= size) {
+		return;
+	}
+	
+	let texel = vec2i(gid.xy);
+	let e = rcas_load(texel);
+	let b = rcas_load(texel + vec2i(0, -1));
+	let d = rcas_load(texel + vec2i(-1, 0));
+	let f = rcas_load(texel + vec2i(1, 0));
+	let h = rcas_load(texel + vec2i(0, 1));
+	
+	let lo = min(min(b, d), min(f, h)).rgb;
+	let hi = max(max(b, d), max(f, h)).rgb;
+	
+	// The negative lobe that keeps every channel in range
+	let hit_lo = lo / (4.0 * hi + 1e-5);
+	let hit_hi = (1.0 - hi) / (4.0 * lo - 4.0 - 1e-5);
+	let lobe_rgb = max(-hit_lo, hit_hi);
+	let lobe = max(-0.1875, min(max(max(lobe_rgb.r, lobe_rgb.g), lobe_rgb.b), 0.0)) * exp2(-upscaler.sharpness);
+	
+	let color = (lobe * (b + d + f + h) + e) / (4.0 * lobe + 1.0);
+	
+	textureStore(upscale_output, gid.xy, vec4f(color.rgb, 1.0));
+}
+
+fn rcas_load(texel: vec2i) -> vec4f {
+	let size = vec2i(textureDimensions(upscale_intermediate));
+	return textureLoad(upscale_intermediate, clamp(texel, vec2i(0), size - 1), 0);
+}
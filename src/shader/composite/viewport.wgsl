@@ -0,0 +1,17 @@
+// Where a point of the window ends up in the texture, in uv (y from the bottom).
+// The texture covers the window and the overflow is cropped.
+fn get_texture_coordinates(frag_coord: vec2f, texture_size: vec2f, screen_size: vec2f) -> vec2f {
+	if texture_size.x / texture_size.y < screen_size.x / screen_size.y {
+		// texture is TALLER than the screen
+		let x = frag_coord.x / screen_size.x;
+		let size_y = screen_size.y / screen_size.x / texture_size.y * texture_size.x;
+		let y = frag_coord.y / screen_size.y * size_y + (1.0 - size_y) * 0.5;
+		return vec2f(x, y);
+	} else {
+		// texture is WIDER than the screen
+		let y = frag_coord.y / screen_size.y;
+		let size_x = screen_size.x / screen_size.y / texture_size.x * texture_size.y;
+		let x = frag_coord.x / screen_size.x * size_x + (1.0 - size_x) * 0.5;
+		return vec2f(x, y);
+	}
+}
\ No newline at end of file
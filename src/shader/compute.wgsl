@@ -1,3 +1,4 @@
+#include "sampling/rng.wgsl"
 
 
 @compute
@@ -9,5 +10,7 @@ fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
 		return;
 	}
 	
+	gpu_assert_pixel = gid.xy;
+	
 	render_pixel(gid.xy, resolution);
 }
\ No newline at end of file
@@ -0,0 +1,63 @@
+// One iteration of the edge-avoiding À-Trous wavelet filter (Dammertz et al.
+// 2010): a 5x5 B3 spline kernel whose taps are 2^iteration texels apart, so
+// that a few iterations cover a wide area. The taps across an edge of the
+// color, the normals or the depth count less.
+
+@compute
+@workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE, 1)
+fn main(@builtin(global_invocation_id) gid: vec3u) {
+	let size = textureDimensions(denoise_target);
+	if any(gid.xy >= size) {
+		return;
+	}
+	
+	var kernel = array<f32, 3>(0.375, 0.25, 0.0625);
+	let step = i32(1u << denoise_iteration);
+	
+	let texel = vec2i(gid.xy);
+	let color = textureLoad(denoise_source, texel);
+	let normal = denoise_normal_at(texel);
+	let depth = textureLoad(denoise_depth, texel).r;
+	
+	// The noise goes down with the square root of the accumulated samples, so
+	// the filter fades out as the render converges. The later iterations only
+	// smooth what's left, which is smaller.
+	let samples = max(f32(denoise.accumulated_samples), 1.0);
+	let sigma_color = denoise.sigma_color * exp2(-f32(denoise_iteration)) / sqrt(samples);
+	
+	// The center always counts fully, even where the normals are 0 (the sky)
+	var sum = color * kernel[0] * kernel[0];
+	var weight_sum = kernel[0] * kernel[0];
+	
+	for (var y = -2; y <= 2; y++) {
+		for (var x = -2; x <= 2; x++) {
+			if x == 0 && y == 0 {
+				continue;
+			}
+			
+			let tap = clamp(texel + vec2i(x, y) * step, vec2i(0), vec2i(size) - 1);
+			let tap_color = textureLoad(denoise_source, tap);
+			
+			let color_diff = tap_color.rgb - color.rgb;
+			let color_weight = exp(-dot(color_diff, color_diff) / max(sigma_color * sigma_color, 1e-8));
+			
+			let normal_weight = pow(max(dot(normal, denoise_normal_at(tap)), 0.0), denoise.sigma_normal);
+			
+			// Relative to the distance, the depth of a slanted surface changes
+			// faster far away
+			let depth_diff = abs(textureLoad(denoise_depth, tap).r - depth);
+			let depth_weight = exp(-depth_diff / (denoise.sigma_depth * f32(step) * depth + 1e-6));
+			
+			let weight = kernel[abs(x)] * kernel[abs(y)] * color_weight * normal_weight * depth_weight;
+			sum += tap_color * weight;
+			weight_sum += weight;
+		}
+	}
+	
+	textureStore(denoise_target, gid.xy, sum / weight_sum);
+}
+
+// The normals are stored in [0, 1]
+fn denoise_normal_at(texel: vec2i) -> vec3f {
+	return textureLoad(denoise_normal, texel).xyz * 2.0 - 1.0;
+}
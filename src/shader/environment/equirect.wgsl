@@ -0,0 +1,17 @@
+// The mapping of the environment maps: u goes once around the y axis starting
+// behind -z, v goes from straight up (+y) at the top to straight down
+
+const EQUIRECT_PI: f32 = 3.14159265359;
+
+fn equirect_direction(uv: vec2f) -> vec3f {
+	let phi = (uv.x - 0.5) * 2.0 * EQUIRECT_PI;
+	let theta = uv.y * EQUIRECT_PI;
+	return vec3f(sin(theta) * sin(phi), cos(theta), -sin(theta) * cos(phi));
+}
+
+fn equirect_uv(direction: vec3f) -> vec2f {
+	let d = normalize(direction);
+	let phi = atan2(d.x, -d.z);
+	let theta = acos(clamp(d.y, -1.0, 1.0));
+	return vec2f(phi / (2.0 * EQUIRECT_PI) + 0.5, theta / EQUIRECT_PI);
+}
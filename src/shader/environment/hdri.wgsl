@@ -0,0 +1,7 @@
+#include "equirect.wgsl"
+
+// An equirectangular image around the scene
+
+fn sample_environment(direction: vec3f) -> vec3f {
+	return textureSampleLevel(hdri, hdri_sampler, equirect_uv(direction), 0.0).rgb * hdri_intensity;
+}
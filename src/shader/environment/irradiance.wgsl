@@ -0,0 +1,21 @@
+#include "prefilter_common.wgsl"
+
+// The cosine-weighted average of the environment around each direction, so
+// the light that a diffuse surface facing it receives, divided by pi
+
+fn prefilter_texel(n: vec3f) -> vec3f {
+	var sum = vec3f(0.0);
+	for (var i = 0u; i < prefilter_chunk.samples; i++) {
+		let xi = prefilter_hammersley(i, prefilter_chunk.samples);
+		
+		// Distributed like the cosine already, so every sample counts the same
+		let phi = 2.0 * EQUIRECT_PI * xi.x;
+		let sin_theta = sqrt(xi.y);
+		let cos_theta = sqrt(1.0 - xi.y);
+		let l = prefilter_to_world(vec3f(sin_theta * cos(phi), sin_theta * sin(phi), cos_theta), n);
+		
+		sum += prefilter_source(l, prefilter_source_lod(cos_theta / EQUIRECT_PI));
+	}
+	
+	return sum / f32(max(prefilter_chunk.samples, 1u));
+}
@@ -0,0 +1,51 @@
+// "A Practical Analytic Model for Daylight" (Preetham et al. 1999). Everything
+// that only depends on the sun is in sky_model, see SkyModel
+
+// Below the horizon, how much of the sky the ground gives back
+const PREETHAM_GROUND_ALBEDO: f32 = 0.3;
+
+fn sample_environment(direction: vec3f) -> vec3f {
+	let d = normalize(direction);
+	
+	// The ground is lit by the sky above it, seen in a mirror for simplicity
+	let sky_direction = vec3f(d.x, abs(d.y), d.z);
+	var color = preetham_sky(sky_direction);
+	color *= mix(1.0, PREETHAM_GROUND_ALBEDO, smoothstep(0.0, 0.05, -d.y));
+	
+	// The disk with a slightly soft edge, hidden by the ground
+	let cos_angle = dot(d, sky_model.sun_direction);
+	let cos_radius = sky_model.cos_sun_radius;
+	let disk = smoothstep(cos_radius - 0.00005, cos_radius, cos_angle) * step(0.0, d.y);
+	color += sky_model.sun_radiance * disk;
+	
+	return color;
+}
+
+fn preetham_sky(d: vec3f) -> vec3f {
+	// The model blows up at the horizon
+	let cos_theta = max(d.y, 0.01);
+	let cos_gamma = clamp(dot(d, sky_model.sun_direction), -1.0, 1.0);
+	let gamma = acos(cos_gamma);
+	
+	// Y, x and y
+	let Yxy = sky_model.zenith * preetham_perez(cos_theta, gamma, cos_gamma);
+	let Y = Yxy.x * sky_model.brightness;
+	
+	let XYZ = vec3f(Yxy.y / Yxy.z * Y, Y, (1.0 - Yxy.y - Yxy.z) / Yxy.z * Y);
+	let xyz_to_rgb = mat3x3f(
+		3.2406, -0.9689, 0.0557,
+		-1.5372, 1.8758, -0.2040,
+		-0.4986, 0.0415, 1.0570,
+	);
+	return max(xyz_to_rgb * XYZ, vec3f(0.0));
+}
+
+// The Perez function, for Y, x and y at once
+fn preetham_perez(cos_theta: f32, gamma: f32, cos_gamma: f32) -> vec3f {
+	let a = sky_model.perez[0].xyz;
+	let b = sky_model.perez[1].xyz;
+	let c = sky_model.perez[2].xyz;
+	let d = sky_model.perez[3].xyz;
+	let e = sky_model.perez[4].xyz;
+	return (1.0 + a * exp(b / cos_theta)) * (1.0 + c * exp(d * gamma) + e * cos_gamma * cos_gamma);
+}
@@ -0,0 +1,44 @@
+#include "prefilter_common.wgsl"
+
+// The environment convolved with the GGX lobe of `prefilter_chunk.roughness`,
+// for one mip of the prefiltered chain. The view and the normal are taken to
+// be the reflected direction, like the split sum approximation does
+// ("Real Shading in Unreal Engine 4", Karis 2013).
+
+fn prefilter_texel(r: vec3f) -> vec3f {
+	// A mirror reflects the environment as it is
+	if prefilter_chunk.roughness == 0.0 {
+		return prefilter_source(r, 0.0);
+	}
+	
+	// Same remapping as pbr_distribution_ggx()
+	let a = prefilter_chunk.roughness * prefilter_chunk.roughness;
+	let a2 = a * a;
+	
+	var sum = vec3f(0.0);
+	var weight = 0.0;
+	for (var i = 0u; i < prefilter_chunk.samples; i++) {
+		let xi = prefilter_hammersley(i, prefilter_chunk.samples);
+		
+		// A half vector around r, distributed like GGX
+		let phi = 2.0 * EQUIRECT_PI * xi.x;
+		let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a2 - 1.0) * xi.y));
+		let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+		let h = prefilter_to_world(vec3f(sin_theta * cos(phi), sin_theta * sin(phi), cos_theta), r);
+		
+		let l = 2.0 * dot(r, h) * h - r;
+		let n_dot_l = dot(r, l);
+		if n_dot_l <= 0.0 {
+			continue;
+		}
+		
+		// With n = v, the pdf of l is D(h) / 4
+		let denom = cos_theta * cos_theta * (a2 - 1.0) + 1.0;
+		let pdf = a2 / (EQUIRECT_PI * denom * denom) / 4.0;
+		
+		sum += prefilter_source(l, prefilter_source_lod(pdf)) * n_dot_l;
+		weight += n_dot_l;
+	}
+	
+	return sum / max(weight, 0.0001);
+}
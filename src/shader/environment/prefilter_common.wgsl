@@ -0,0 +1,51 @@
+#include "equirect.wgsl"
+
+// The entry point of the prefiltering passes, for the rows of one chunk. The
+// pass includes this and defines `prefilter_texel()`, the value of the texel
+// in a direction.
+
+@compute
+@workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE, 1)
+fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
+	let size = textureDimensions(prefilter_output);
+	let texel = vec2u(gid.x, prefilter_chunk.row_offset + gid.y);
+	
+	if gid.y >= prefilter_chunk.rows || texel.x >= size.x || texel.y >= size.y {
+		return;
+	}
+	
+	let uv = (vec2f(texel) + 0.5) / vec2f(size);
+	let color = prefilter_texel(equirect_direction(uv));
+	
+	textureStore(prefilter_output, texel, vec4f(color, 1.0));
+}
+
+// Low-discrepancy points in [0; 1[², the same ones for every texel so that
+// there is no noise, only some banding at worst
+fn prefilter_hammersley(i: u32, count: u32) -> vec2f {
+	return vec2f(f32(i) / f32(count), f32(reverseBits(i)) * 2.3283064365386963e-10);
+}
+
+// From the frame around n (with n as z) to world space
+fn prefilter_to_world(v: vec3f, n: vec3f) -> vec3f {
+	let up = select(vec3f(1.0, 0.0, 0.0), vec3f(0.0, 0.0, 1.0), abs(n.z) < 0.999);
+	let tangent = normalize(cross(up, n));
+	let bitangent = cross(n, tangent);
+	return tangent * v.x + bitangent * v.y + n * v.z;
+}
+
+// The mip of the source to read a sample from, so that the unlikely samples
+// average out the area they stand for instead of aliasing ("GPU-Based
+// Importance Sampling", GPU Gems 3, chapter 20)
+fn prefilter_source_lod(pdf: f32) -> f32 {
+	let size = vec2f(textureDimensions(environment_source));
+	let texel_solid_angle = 4.0 * EQUIRECT_PI / (size.x * size.y);
+	let sample_solid_angle = 1.0 / (f32(prefilter_chunk.samples) * pdf + 0.0001);
+	
+	let max_lod = f32(textureNumLevels(environment_source) - 1u);
+	return clamp(0.5 * log2(sample_solid_angle / texel_solid_angle), 0.0, max_lod);
+}
+
+fn prefilter_source(direction: vec3f, lod: f32) -> vec3f {
+	return textureSampleLevel(environment_source, environment_source_sampler, equirect_uv(direction), lod).rgb;
+}
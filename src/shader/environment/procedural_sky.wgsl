@@ -0,0 +1,38 @@
+// A gradient sky with the sun, loosely after "A Practical Analytic Model for
+// Daylight" (Preetham et al. 1999): brighter towards the horizon and around the
+// sun, without the actual scattering terms
+
+// Same as Light::DIRECTIONAL
+const SKY_LIGHT_DIRECTIONAL: u32 = 1u;
+
+fn sample_environment(direction: vec3f) -> vec3f {
+	let d = normalize(direction);
+	
+	if d.y < 0.0 {
+		// A bit of the horizon haze bleeds into the ground
+		return mix(procedural_sky.horizon_color, procedural_sky.ground_color, smoothstep(0.0, 0.05, -d.y));
+	}
+	
+	var color = mix(procedural_sky.horizon_color, procedural_sky.zenith_color, pow(d.y, 0.5));
+	
+	for (var i = 0u; i < arrayLength(&lights); i++) {
+		let light = lights[i];
+		if light.kind != SKY_LIGHT_DIRECTIONAL {
+			continue;
+		}
+		
+		let sun_color = light.color * light.intensity;
+		let cos_angle = dot(d, -normalize(light.direction));
+		
+		// The glow, then the disk with a slightly soft edge
+		color += sun_color * 0.5 * pow(max(cos_angle, 0.0), 64.0);
+		let cos_radius = cos(procedural_sky.sun_radius);
+		let disk = smoothstep(cos_radius - 0.00005, cos_radius, cos_angle);
+		color = mix(color, sun_color * procedural_sky.sun_intensity, disk);
+		
+		// Only the first one is the sun
+		break;
+	}
+	
+	return color;
+}
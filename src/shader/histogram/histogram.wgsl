@@ -0,0 +1,34 @@
+// Counts the luminance of the render in HISTOGRAM_BINS bins over [0, 1], the
+// first and last ones also get what's below and above. Every workgroup counts
+// in its own bins first, so that the pixels don't all fight over the same few
+// atomics.
+
+var<workgroup> histogram_local: array<atomic<u32>, HISTOGRAM_BINS>;
+
+@compute
+@workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE, 1)
+fn main(@builtin(global_invocation_id) gid: vec3u, @builtin(local_invocation_index) local_index: u32) {
+	let invocations = u32(WORKGROUP_SIZE * WORKGROUP_SIZE);
+	
+	for (var i = local_index; i < HISTOGRAM_BINS; i += invocations) {
+		atomicStore(&histogram_local[i], 0u);
+	}
+	workgroupBarrier();
+	
+	// No early return, every invocation has to get to the barriers
+	let size = textureDimensions(histogram_source);
+	if all(gid.xy < size) {
+		let color = textureLoad(histogram_source, gid.xy).rgb;
+		let luminance = clamp(dot(color, vec3f(0.2126, 0.7152, 0.0722)), 0.0, 1.0);
+		let bin = min(u32(luminance * f32(HISTOGRAM_BINS)), HISTOGRAM_BINS - 1u);
+		atomicAdd(&histogram_local[bin], 1u);
+	}
+	workgroupBarrier();
+	
+	for (var i = local_index; i < HISTOGRAM_BINS; i += invocations) {
+		let count = atomicLoad(&histogram_local[i]);
+		if count > 0u {
+			atomicAdd(&histogram_bins[i], count);
+		}
+	}
+}
@@ -0,0 +1,26 @@
+
+// Set by the entry point, so that the records know which pixel failed
+var<private> gpu_assert_pixel: vec2u;
+
+fn gpu_assert(condition: bool, code: u32, payload: vec4f) {
+	if (condition) {
+		return;
+	}
+	
+	// The counter keeps counting past the capacity, so that the CPU knows how
+	// many records were dropped
+	let slot = atomicAdd(&gpu_assert_log[0], 1u);
+	if (slot >= GPU_ASSERT_MAX_RECORDS) {
+		return;
+	}
+	
+	let base = 1u + slot * GPU_ASSERT_RECORD_WORDS;
+	atomicStore(&gpu_assert_log[base], code);
+	atomicStore(&gpu_assert_log[base + 1u], gpu_assert_pixel.x);
+	atomicStore(&gpu_assert_log[base + 2u], gpu_assert_pixel.y);
+	atomicStore(&gpu_assert_log[base + 3u], globals.frame);
+	atomicStore(&gpu_assert_log[base + 4u], bitcast<u32>(payload.x));
+	atomicStore(&gpu_assert_log[base + 5u], bitcast<u32>(payload.y));
+	atomicStore(&gpu_assert_log[base + 6u], bitcast<u32>(payload.z));
+	atomicStore(&gpu_assert_log[base + 7u], bitcast<u32>(payload.w));
+}
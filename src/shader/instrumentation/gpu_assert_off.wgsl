@@ -0,0 +1,6 @@
+
+// The GPU asserts are disabled, see gpu_assert.wgsl
+
+var<private> gpu_assert_pixel: vec2u;
+
+fn gpu_assert(condition: bool, code: u32, payload: vec4f) {}
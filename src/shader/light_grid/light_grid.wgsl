@@ -0,0 +1,42 @@
+@compute
+@workgroup_size(4, 4, 4)
+fn build_light_grid(@builtin(global_invocation_id) gid: vec3u) {
+	if any(gid >= light_grid.cells) {
+		return;
+	}
+	
+	let cell_size = (light_grid.bounds_max - light_grid.bounds_min) / vec3f(light_grid.cells);
+	let cell_min = light_grid.bounds_min + vec3f(gid) * cell_size;
+	let cell_max = cell_min + cell_size;
+	
+	var cell = LightGridCell();
+	
+	for (var i = 0u; i < light_grid.light_count && cell.count < LIGHT_GRID_MAX_LIGHTS_PER_CELL; i++) {
+		let light = light_grid_lights[i];
+		
+		// The closest point of the cell to the light
+		let closest = clamp(light.position, cell_min, cell_max);
+		
+		if distance(closest, light.position) <= light.radius {
+			cell.lights[cell.count] = i;
+			cell.count++;
+		}
+	}
+	
+	light_grid_cells[light_grid_cell_index(gid)] = cell;
+}
+
+fn light_grid_cell_index(cell: vec3u) -> u32 {
+	return (cell.z * light_grid.cells.y + cell.y) * light_grid.cells.x + cell.x;
+}
+
+fn light_grid_lights_at(p: vec3f) -> LightGridCell {
+	let uvw = (p - light_grid.bounds_min) / (light_grid.bounds_max - light_grid.bounds_min);
+	
+	if any(uvw < vec3f(0.0)) || any(uvw >= vec3f(1.0)) {
+		return LightGridCell();
+	}
+	
+	let cell = vec3u(uvw * vec3f(light_grid.cells));
+	return light_grid_cells[light_grid_cell_index(cell)];
+}
\ No newline at end of file
@@ -0,0 +1,174 @@
+
+// Closer than this is the surface the ray starts from
+const MESH_MIN_DISTANCE: f32 = 0.0001;
+
+
+fn intersect_scene(ray_origin: vec3f, ray_dir: vec3f) -> Intersection {
+	let object = Object(vec3f(0.8), 0u);
+	var intersection = Intersection(false, object, camera.z_far, vec3f(0), vec3f(0), -ray_dir, vec2f(0), vec4f(0), 0u);
+	
+	let inv_dir = 1.0 / ray_dir;
+	
+	// The nodes left to visit, starting with the root
+	var stack: array<u32, MESH_BVH_STACK_SIZE>;
+	stack[0] = 0u;
+	var stack_size = 1u;
+	
+	while (stack_size > 0u) {
+		stack_size--;
+		let node = mesh_bvh_nodes[stack[stack_size]];
+		
+		// Nothing in there can be closer than what was already hit
+		if (!mesh_hits_bounds(ray_origin, inv_dir, node, intersection.distance)) {
+			continue;
+		}
+		
+		if (node.count == 0u) {
+			// Can't happen with the depth checked on the CPU, but skipping the children
+			// beats writing past the end of the stack
+			let room = stack_size + 2u <= MESH_BVH_STACK_SIZE;
+			gpu_assert(room, MESH_ASSERT_STACK_OVERFLOW, vec4f(ray_dir, f32(stack_size)));
+			if (!room) {
+				continue;
+			}
+			
+			// The children are next to each other, see BvhNode
+			stack[stack_size] = node.left_or_first + 1u;
+			stack[stack_size + 1u] = node.left_or_first;
+			stack_size += 2u;
+			continue;
+		}
+		
+		for (var i = node.left_or_first; i < node.left_or_first + node.count; i++) {
+			let triangle = mesh_triangles[i];
+			let hit = intersect_mesh_triangle(ray_origin, ray_dir, triangle);
+			
+			if (hit.x < MESH_MIN_DISTANCE || hit.x >= intersection.distance) {
+				continue;
+			}
+			
+			// Barycentric interpolation of the vertex normals, uvs and tangents
+			let weights = vec3f(1.0 - hit.y - hit.z, hit.y, hit.z);
+			let normal = triangle.n0 * weights.x + triangle.n1 * weights.y + triangle.n2 * weights.z;
+			let uv = triangle.uv0 * weights.x + triangle.uv1 * weights.y + triangle.uv2 * weights.z;
+			let tangent = triangle.t0.xyz * weights.x + triangle.t1.xyz * weights.y + triangle.t2.xyz * weights.z;
+			
+			intersection.has_hit = true;
+			intersection.object = Object(mesh_material_color(triangle.material_id, uv), triangle.material_id);
+			intersection.distance = hit.x;
+			intersection.position = ray_origin + ray_dir * hit.x;
+			intersection.normal = normalize(normal);
+			intersection.uv = uv;
+			// The sign is the same for the whole triangle, 0 without uvs
+			intersection.tangent = vec4f(tangent, sign(triangle.t0.w));
+			intersection.id = i + 1u;
+		}
+	}
+	
+	return intersection;
+}
+
+// Whether anything is between the origin and max_t, for the shadow rays. Same
+// traversal, but any hit will do so it stops at the first one.
+fn occluded(ray_origin: vec3f, ray_dir: vec3f, max_t: f32) -> bool {
+	let inv_dir = 1.0 / ray_dir;
+	
+	var stack: array<u32, MESH_BVH_STACK_SIZE>;
+	stack[0] = 0u;
+	var stack_size = 1u;
+	
+	while (stack_size > 0u) {
+		stack_size--;
+		let node = mesh_bvh_nodes[stack[stack_size]];
+		
+		if (!mesh_hits_bounds(ray_origin, inv_dir, node, max_t)) {
+			continue;
+		}
+		
+		if (node.count == 0u) {
+			let room = stack_size + 2u <= MESH_BVH_STACK_SIZE;
+			gpu_assert(room, MESH_ASSERT_STACK_OVERFLOW, vec4f(ray_dir, f32(stack_size)));
+			if (!room) {
+				continue;
+			}
+			
+			stack[stack_size] = node.left_or_first + 1u;
+			stack[stack_size + 1u] = node.left_or_first;
+			stack_size += 2u;
+			continue;
+		}
+		
+		for (var i = node.left_or_first; i < node.left_or_first + node.count; i++) {
+			let hit = intersect_mesh_triangle(ray_origin, ray_dir, mesh_triangles[i]);
+			
+			if (hit.x >= MESH_MIN_DISTANCE && hit.x < max_t) {
+				return true;
+			}
+		}
+	}
+	
+	return false;
+}
+
+fn mesh_material_color(material_id: u32, uv: vec2f) -> vec3f {
+	if (material_id >= MESH_MATERIAL_COUNT) {
+		return vec3f(0.8);
+	}
+	
+	let material = mesh_materials[material_id];
+	if (material.base_color_texture == 0xffffffffu) {
+		return material.base_color.rgb;
+	}
+	
+	// The textures are stored as plain unorm, see MeshIntersector::shader()
+	let texel = textureSampleLevel(mesh_textures, mesh_sampler, uv, material.base_color_texture, 0.0);
+	return material.base_color.rgb * mesh_srgb_to_linear(texel.rgb);
+}
+
+fn mesh_srgb_to_linear(srgb: vec3f) -> vec3f {
+	let low = srgb / 12.92;
+	let high = pow((srgb + 0.055) / 1.055, vec3f(2.4));
+	return select(high, low, srgb <= vec3f(0.04045));
+}
+
+fn mesh_hits_bounds(ray_origin: vec3f, inv_dir: vec3f, node: BvhNode, max_distance: f32) -> bool {
+	let t1 = (node.bounds_min - ray_origin) * inv_dir;
+	let t2 = (node.bounds_max - ray_origin) * inv_dir;
+	
+	let t_min = min(t1, t2);
+	let t_max = max(t1, t2);
+	let t_near = max(t_min.x, max(t_min.y, t_min.z));
+	let t_far = min(t_max.x, min(t_max.y, t_max.z));
+	
+	return t_near <= t_far && t_far >= 0.0 && t_near < max_distance;
+}
+
+// Möller-Trumbore, returns the distance and the barycentric coordinates of p1
+// and p2. A negative distance is a miss.
+fn intersect_mesh_triangle(ray_origin: vec3f, ray_dir: vec3f, triangle: MeshTriangle) -> vec3f {
+	let edge1 = triangle.p1 - triangle.p0;
+	let edge2 = triangle.p2 - triangle.p0;
+	
+	let p = cross(ray_dir, edge2);
+	let det = dot(edge1, p);
+	
+	// Parallel to the triangle, or a degenerate triangle
+	if (abs(det) < 1e-12) {
+		return vec3f(-1.0);
+	}
+	
+	let inv_det = 1.0 / det;
+	let s = ray_origin - triangle.p0;
+	let u = dot(s, p) * inv_det;
+	if (u < 0.0 || u > 1.0) {
+		return vec3f(-1.0);
+	}
+	
+	let q = cross(s, edge1);
+	let v = dot(ray_dir, q) * inv_det;
+	if (v < 0.0 || u + v > 1.0) {
+		return vec3f(-1.0);
+	}
+	
+	return vec3f(dot(edge2, q) * inv_det, u, v);
+}
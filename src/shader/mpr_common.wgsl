@@ -0,0 +1,112 @@
+#include "/sampling/sampling.wgsl"
+
+// The pixel being shaded, for the fragments' per-pixel randomness
+var<private> shading_pixel: vec2u;
+
+// Set by the intersectors that have a debug output (see
+// Intersector::has_debug_output), stored after the camera ray's intersection,
+// before the shading casts its own rays
+var<private> mpr_debug_output: vec4f;
+
+struct Intersection {
+	has_hit: bool,
+	object: Object,
+	distance: f32,
+	position: vec3f,
+	normal: vec3f,
+	outgoing: vec3f,
+	// The texture coordinates, 0 if the intersector doesn't have any
+	uv: vec2f,
+	// Along +u in xyz, and in w the sign of the bitangent for the normal maps
+	// (see pbr_normal()). All 0 if the intersector doesn't know.
+	tangent: vec4f,
+	// What was hit, for the picking (see output_id). What that is depends on the
+	// intersector, e.g. the primitive of the analytic one. 0 if nothing was hit.
+	id: u32,
+}
+
+struct Object {
+	color: vec3f,
+	// Which material the shading should use, 0 if the intersector doesn't know
+	material_id: u32,
+}
+
+struct CameraRay {
+	origin: vec3f,
+	direction: vec3f,
+	// In [-1; 1], centered
+	coord: vec2f,
+}
+
+// With depth of field, only the frames that are accumulated go through a random
+// point of the lens, a single frame would only be noise. The others go through
+// its center, which is the same ray as without it.
+fn camera_ray(pixel_coord: vec2u, pixel_size: vec2u) -> CameraRay {
+	var lens = vec2f(0.0);
+	
+	if globals.accumulating != 0u {
+		let seed = pixel_seed(pixel_coord, globals.resolution, globals.seed);
+		lens = camera_lens_point(seed_to_unit(seed).xy);
+	}
+	
+	return camera_ray_through_lens(pixel_coord, pixel_size, lens);
+}
+
+// The lens point is on the unit disc, scaled by the aperture (see
+// camera_lens_point()). Without depth of field, every lens point gives the
+// same ray.
+fn camera_ray_through_lens(pixel_coord: vec2u, pixel_size: vec2u, lens: vec2f) -> CameraRay {
+	let coord = camera_coord(pixel_coord, pixel_size);
+	
+	if (camera.equirectangular != 0u) {
+		// All around the camera, no lens
+		let ray_dir_raw = camera_equirectangular_direction(pixel_coord, pixel_size);
+		let ray_dir = (camera.inverse_view_mat * vec4f(ray_dir_raw, 0.0)).xyz;
+		let ray_origin = (camera.inverse_view_mat * vec4f(0.0, 0.0, 0.0, 1.0)).xyz;
+		return CameraRay(ray_origin, ray_dir, coord);
+	}
+	let focal_length = camera.focal_length / f32(pixel_size.y);
+	
+	var ray_dir_raw = normalize(vec3f(coord, focal_length));
+	var ray_origin_raw = vec3f(0.0);
+	
+	if (camera.orthographic != 0u) {
+		// Parallel rays, offset by the pixel position on the view plane
+		ray_dir_raw = vec3f(0.0, 0.0, 1.0);
+		ray_origin_raw = vec3f(coord * camera.ortho_height, 0.0);
+	}
+	
+	if (dof.enabled != 0u && dof.aperture > 0.0) {
+		// Every ray through the lens meets the pinhole ray on the focal plane, so
+		// that's where things are sharp
+		let focus_point = ray_origin_raw + ray_dir_raw * (dof.focus_distance / ray_dir_raw.z);
+		ray_origin_raw += vec3f(lens * dof.aperture, 0.0);
+		ray_dir_raw = normalize(focus_point - ray_origin_raw);
+	}
+	
+	let ray_dir = (camera.inverse_view_mat * vec4f(ray_dir_raw, 0.0)).xyz;
+	let ray_origin = (camera.inverse_view_mat * vec4f(ray_origin_raw, 1.0)).xyz;
+	
+	return CameraRay(ray_origin, ray_dir, coord);
+}
+
+// Coord is in [-1; 1], centered
+fn camera_coord(pixel_coord: vec2u, pixel_size: vec2u) -> vec2f {
+	return (vec2f(pixel_coord) - vec2f(pixel_size) / 2.0) / f32(pixel_size.y);
+}
+
+// The longitude goes along x, with the forward (+z) in the middle of the image
+// and the back on both edges, and the latitude along y
+fn camera_equirectangular_direction(pixel_coord: vec2u, pixel_size: vec2u) -> vec3f {
+	let uv = vec2f(pixel_coord) / vec2f(pixel_size) - 0.5;
+	let longitude = uv.x * 6.28318530718;
+	let latitude = uv.y * 3.14159265359;
+	return vec3f(cos(latitude) * sin(longitude), sin(latitude), cos(latitude) * cos(longitude));
+}
+
+// Uniform on the unit disc, from two numbers in [0; 1]
+fn camera_lens_point(random: vec2f) -> vec2f {
+	let radius = sqrt(random.x);
+	let angle = 6.28318530718 * random.y;
+	return radius * vec2f(cos(angle), sin(angle));
+}
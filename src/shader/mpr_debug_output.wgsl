@@ -0,0 +1,5 @@
+// Stores what the intersector set for the camera ray, see
+// Intersector::has_debug_output
+fn mpr_store_debug_output(pixel_coord: vec2u) {
+	textureStore(output_debug, pixel_coord, mpr_debug_output);
+}
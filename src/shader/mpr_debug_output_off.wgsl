@@ -0,0 +1,2 @@
+// The intersector has no debug output, there's no texture to store it in
+fn mpr_store_debug_output(pixel_coord: vec2u) {}
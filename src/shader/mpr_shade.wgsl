@@ -0,0 +1,13 @@
+#include "mpr_common.wgsl"
+
+// The shading of a hit, darkened by the intersector's ambient occlusion, or
+// the environment for a miss
+fn shade_occluded(intersection: Intersection) -> vec4f {
+	if !intersection.has_hit {
+		return vec4f(sample_environment(-intersection.outgoing), 1.0);
+	}
+	
+	let color = shade(intersection);
+	let occlusion = ambient_occlusion(intersection.position, intersection.normal);
+	return vec4f(color.rgb * occlusion, color.a);
+}
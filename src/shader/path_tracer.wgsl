@@ -0,0 +1,336 @@
+#include "mpr_common.wgsl"
+#include "/shading/lights.wgsl"
+#include "/shading/pbr_brdf.wgsl"
+#include "/sampling/rng.wgsl"
+
+// Progressive path tracing: every frame adds path_tracer_settings.samples_per_frame
+// paths per pixel to output_accumulation, whose alpha counts the paths, and the
+// average is what gets post-processed. The environment is only found by the
+// paths that escape. The average isn't shown while globals.hold_output is set.
+// The sum is followed from the last frame's by path_tracer_history(), which
+// reprojects it when the camera moved (see path_tracer_history.wgsl).
+//
+// The point and directional lights can only be found by sampling them at every
+// bounce. The lights with a size are spheres, which the paths can also hit: with
+// next event estimation one light is sampled per bounce, and the two ways of
+// finding a sphere are weighted against each other (multiple importance
+// sampling). Without it, the spheres are only found by the paths that hit them.
+
+// What a bounce needs to know about the surface
+struct PathTracerSurface {
+	albedo: vec3f,
+	metallic: f32,
+	roughness: f32,
+	f0: vec3f,
+	// How likely the next direction is to come from the specular lobe
+	specular_chance: f32,
+}
+
+var<private> path_tracer_rng: RngState;
+// Where the first path that hit the scene hit it first, with the distance along
+// its ray in w, which is -1 if none did
+var<private> path_tracer_primary: vec4f;
+// What it hit there, see Intersection::id
+var<private> path_tracer_primary_id: u32;
+
+fn render_pixel(pixel_coord: vec2u, pixel_size: vec2u) {
+	shading_pixel = pixel_coord;
+	path_tracer_rng = rng_init(pixel_coord);
+	path_tracer_primary = vec4f(0.0, 0.0, 0.0, -1.0);
+	path_tracer_primary_id = 0u;
+
+	var sum = vec4f(0.0);
+	for (var i = 0u; i < max(path_tracer_settings.samples_per_frame, 1u); i++) {
+		// Every path goes through its own point of the lens, the accumulation
+		// averages them into the depth of field
+		let lens = camera_lens_point(rng_next_vec2f(&path_tracer_rng));
+		let ray = camera_ray_through_lens(pixel_coord, pixel_size, lens);
+		sum += vec4f(path_tracer_sample(trace_path(ray.origin, ray.direction)), 1.0);
+	}
+
+	if globals.accumulated_samples > 0u {
+		sum += path_tracer_history(pixel_coord, pixel_size);
+	}
+	textureStore(output_accumulation, pixel_coord, sum);
+	textureStore(output_depth, pixel_coord, path_tracer_depth());
+	textureStore(output_id, pixel_coord, vec4u(path_tracer_primary_id));
+
+	// Right after the camera stops, the last interactive frame stays up until
+	// the sum of the full quality ones is less noisy than it
+	if globals.hold_output != 0u {
+		return;
+	}
+
+	let color = post_processing_pipeline(camera_coord(pixel_coord, pixel_size), vec4f(sum.rgb / sum.a, 1.0));
+	textureStore(output_color, pixel_coord, color);
+}
+
+// Same as the multi-purpose renderer's, the sky is on the far plane
+fn path_tracer_depth() -> vec4f {
+	if path_tracer_primary.w < 0.0 {
+		return vec4f(1.0);
+	}
+	return vec4f(vec3f(path_tracer_primary.w / camera.z_far), 1.0);
+}
+
+fn path_tracer_sample(radiance: vec3f) -> vec3f {
+	// A NaN or infinite path would poison the pixel until the next reset
+	if any(radiance != radiance) || any(abs(radiance) > vec3f(1e30)) {
+		return vec3f(0.0);
+	}
+
+	// Against the fireflies, keeping the hue
+	let limit = path_tracer_settings.firefly_clamp;
+	let brightest = max(radiance.r, max(radiance.g, radiance.b));
+	if limit > 0.0 && brightest > limit {
+		return radiance * (limit / brightest);
+	}
+
+	return radiance;
+}
+
+fn trace_path(ray_origin: vec3f, ray_dir: vec3f) -> vec3f {
+	var origin = ray_origin;
+	var dir = normalize(ray_dir);
+	var throughput = vec3f(1.0);
+	var radiance = vec3f(0.0);
+	// Of the direction the path went in at the last bounce, 0 for the camera ray
+	var last_pdf = 0.0;
+
+	for (var bounce = 0u; bounce <= path_tracer_settings.max_bounces; bounce++) {
+		let intersection = intersect_scene(origin, dir);
+
+		// The spheres of light aren't part of the scene
+		let max_t = select(1e30, intersection.distance, intersection.has_hit);
+		let emitter = path_tracer_hit_light(origin, dir, max_t);
+		if emitter < light_count() {
+			radiance += throughput * light_emission(emitter, origin) * path_tracer_hit_weight(emitter, origin, last_pdf);
+			break;
+		}
+
+		if !intersection.has_hit {
+			radiance += throughput * sample_environment(dir);
+			break;
+		}
+
+		if bounce == 0u && path_tracer_primary.w < 0.0 {
+			path_tracer_primary = vec4f(intersection.position, intersection.distance);
+			path_tracer_primary_id = intersection.id;
+		}
+
+		// Same clamps as the PBR shading, a perfect mirror would need its own path
+		let pbr = pbr_surface(intersection);
+		var surface: PathTracerSurface;
+		surface.albedo = pbr.albedo;
+		surface.metallic = pbr.metallic;
+		surface.roughness = pbr.roughness;
+		surface.f0 = mix(vec3f(0.04), surface.albedo, surface.metallic);
+
+		let v = -dir;
+		// The side the path arrives from, e.g. for the planes and the insides
+		let n = faceForward(pbr.normal, dir, normalize(intersection.normal));
+		let p = intersection.position;
+
+		// Picking the lobe is part of the pdf, so the estimate stays unbiased
+		// whichever is picked
+		let fresnel = pbr_fresnel_schlick(max(dot(n, v), 0.0001), surface.f0);
+		surface.specular_chance = clamp(max(fresnel.r, max(fresnel.g, fresnel.b)) + surface.metallic, 0.1, 0.9);
+
+		radiance += throughput * path_tracer_direct_light(p, n, v, surface);
+
+		if bounce == path_tracer_settings.max_bounces {
+			break;
+		}
+
+		// The next direction, from the diffuse or the specular lobe
+		let random = path_tracer_random();
+		var l: vec3f;
+		if random.z < surface.specular_chance {
+			let h = path_tracer_to_world(path_tracer_sample_ggx(random.xy, surface.roughness), n);
+			l = reflect(-v, h);
+		} else {
+			l = path_tracer_to_world(path_tracer_sample_cosine(random.xy), n);
+		}
+
+		let n_dot_l = dot(n, l);
+		if n_dot_l <= 0.0 {
+			break;
+		}
+
+		let pdf = path_tracer_pdf(n, v, l, surface);
+		if pdf <= 1e-6 {
+			break;
+		}
+
+		throughput *= path_tracer_brdf(n, v, l, surface) * n_dot_l / pdf;
+		last_pdf = pdf;
+
+		// Russian roulette, the survivors carry the light of the stopped paths
+		if bounce >= path_tracer_settings.roulette_depth {
+			let survival = clamp(max(throughput.r, max(throughput.g, throughput.b)), 0.05, 0.95);
+			if path_tracer_random().x > survival {
+				break;
+			}
+			throughput /= survival;
+		}
+
+		origin = p + n * SHADOW_BIAS;
+		dir = l;
+	}
+
+	return radiance;
+}
+
+// The light that reaches p straight from the lights, without the spheres that
+// the next bounce finds by itself when next event estimation is off
+fn path_tracer_direct_light(p: vec3f, n: vec3f, v: vec3f, surface: PathTracerSurface) -> vec3f {
+	if path_tracer_settings.next_event_estimation == 0u {
+		var sum = vec3f(0.0);
+		for (var i = 0u; i < light_count(); i++) {
+			if !light_is_sphere(i) {
+				sum += path_tracer_point_light(i, p, n, v, surface);
+			}
+		}
+		return sum;
+	}
+
+	// A single light, the brighter ones more often
+	let random = path_tracer_random();
+	let i = light_pick(random.x);
+	let pick_pdf = light_pick_pdf(i);
+	if pick_pdf <= 0.0 {
+		return vec3f(0.0);
+	}
+
+	if !light_is_sphere(i) {
+		return path_tracer_point_light(i, p, n, v, surface) / pick_pdf;
+	}
+
+	let light = light_sample_sphere(i, p, random.yz);
+	let n_dot_l = dot(n, light.direction);
+	if n_dot_l <= 0.0 || all(light.radiance == vec3f(0.0)) {
+		return vec3f(0.0);
+	}
+	if occluded(p + n * SHADOW_BIAS, light.direction, light.distance - SHADOW_BIAS) {
+		return vec3f(0.0);
+	}
+
+	let light_pdf = pick_pdf * light_sphere_pdf(i, p);
+	let weight = path_tracer_power_heuristic(light_pdf, path_tracer_pdf(n, v, light.direction, surface));
+	return path_tracer_brdf(n, v, light.direction, surface) * light.radiance * n_dot_l * weight / light_pdf;
+}
+
+fn path_tracer_point_light(i: u32, p: vec3f, n: vec3f, v: vec3f, surface: PathTracerSurface) -> vec3f {
+	let light = sample_light(i, p);
+	let visibility = light_visibility(i, light, p, n);
+	if visibility == 0.0 {
+		return vec3f(0.0);
+	}
+
+	let n_dot_l = max(dot(n, light.direction), 0.0);
+	return path_tracer_brdf(n, v, light.direction, surface) * light.radiance * n_dot_l * visibility;
+}
+
+// The closest sphere of light along the ray before max_t, light_count() if
+// there's none
+fn path_tracer_hit_light(origin: vec3f, dir: vec3f, max_t: f32) -> u32 {
+	var closest = light_count();
+	var closest_t = max_t;
+
+	for (var i = 0u; i < light_count(); i++) {
+		if !light_is_sphere(i) {
+			continue;
+		}
+
+		let t = light_sphere_hit(i, origin, dir);
+		if t > 0.0 && t < closest_t {
+			closest = i;
+			closest_t = t;
+		}
+	}
+
+	return closest;
+}
+
+// The weight of a path that hit a sphere of light, against the chance of the
+// next event estimation having found it from the same point
+fn path_tracer_hit_weight(i: u32, origin: vec3f, bsdf_pdf: f32) -> f32 {
+	if path_tracer_settings.next_event_estimation == 0u || bsdf_pdf <= 0.0 {
+		return 1.0;
+	}
+	return path_tracer_power_heuristic(bsdf_pdf, light_pick_pdf(i) * light_sphere_pdf(i, origin));
+}
+
+// Veach's power heuristic, the weight of the strategy with `pdf`
+fn path_tracer_power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+	let a = pdf * pdf;
+	let b = other_pdf * other_pdf;
+	if a + b <= 0.0 {
+		return 0.0;
+	}
+	return a / (a + b);
+}
+
+// Cook-Torrance, same as the PBR shading
+fn path_tracer_brdf(n: vec3f, v: vec3f, l: vec3f, surface: PathTracerSurface) -> vec3f {
+	let h = normalize(v + l);
+	let n_dot_v = max(dot(n, v), 0.0001);
+	let n_dot_l = max(dot(n, l), 0.0001);
+	let n_dot_h = max(dot(n, h), 0.0);
+	let v_dot_h = max(dot(v, h), 0.0);
+
+	let fresnel = pbr_fresnel_schlick(v_dot_h, surface.f0);
+	let distribution = pbr_distribution_ggx(n_dot_h, surface.roughness);
+	let geometry = pbr_geometry_smith(n_dot_v, n_dot_l, surface.roughness);
+
+	let specular = distribution * geometry * fresnel / (4.0 * n_dot_v * n_dot_l);
+	let diffuse = (vec3f(1.0) - fresnel) * (1.0 - surface.metallic) * surface.albedo / PBR_PI;
+	return diffuse + specular;
+}
+
+// Of the bounce picking l, the two lobes mixed by the specular chance
+fn path_tracer_pdf(n: vec3f, v: vec3f, l: vec3f, surface: PathTracerSurface) -> f32 {
+	let n_dot_l = dot(n, l);
+	if n_dot_l <= 0.0 {
+		return 0.0;
+	}
+
+	let h = normalize(v + l);
+	let n_dot_h = max(dot(n, h), 0.0);
+	let v_dot_h = max(dot(v, h), 0.0001);
+	let specular_pdf = pbr_distribution_ggx(n_dot_h, surface.roughness) * n_dot_h / (4.0 * v_dot_h);
+	let diffuse_pdf = n_dot_l / PBR_PI;
+	return mix(diffuse_pdf, specular_pdf, surface.specular_chance);
+}
+
+fn path_tracer_random() -> vec3f {
+	// The generator wants a function pointer
+	var rng = path_tracer_rng;
+	let random = vec3f(rng_next_f32(&rng), rng_next_f32(&rng), rng_next_f32(&rng));
+	path_tracer_rng = rng;
+	return random;
+}
+
+// Around +z, distributed like the cosine
+fn path_tracer_sample_cosine(xi: vec2f) -> vec3f {
+	let r = sqrt(xi.x);
+	let phi = 2.0 * PBR_PI * xi.y;
+	return vec3f(r * cos(phi), r * sin(phi), sqrt(max(1.0 - xi.x, 0.0)));
+}
+
+// A half vector around +z, distributed like the GGX lobe (a = roughness²)
+fn path_tracer_sample_ggx(xi: vec2f, roughness: f32) -> vec3f {
+	let a = roughness * roughness;
+	let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+	let sin_theta = sqrt(max(1.0 - cos_theta * cos_theta, 0.0));
+	let phi = 2.0 * PBR_PI * xi.x;
+	return vec3f(sin_theta * cos(phi), sin_theta * sin(phi), cos_theta);
+}
+
+// From the frame around n (+z) to the world
+fn path_tracer_to_world(v: vec3f, n: vec3f) -> vec3f {
+	let up = select(vec3f(1.0, 0.0, 0.0), vec3f(0.0, 1.0, 0.0), abs(n.x) > 0.9);
+	let tangent = normalize(cross(up, n));
+	let bitangent = cross(n, tangent);
+	return tangent * v.x + bitangent * v.y + n * v.z;
+}
@@ -0,0 +1,56 @@
+#include "reprojection.wgsl"
+
+// On the frames where the camera moved (reprojection.active), the last frame's
+// sum is copied to output_history before the pixels write theirs. Every pixel
+// then looks for its surface where the previous_camera saw it, and keeps that
+// sum if the depth there matches, discounted by reprojection.history_weight.
+// The ones whose surface was hidden or out of view start over, like the sky,
+// whose paths don't have a surface to follow.
+
+@compute
+@workgroup_size(8, 8, 1)
+fn path_tracer_save_history(@builtin(global_invocation_id) gid: vec3u) {
+	if reprojection.active == 0u || any(gid.xy >= textureDimensions(output_accumulation)) {
+		return;
+	}
+	
+	textureStore(output_history, gid.xy, textureLoad(output_accumulation, gid.xy));
+	textureStore(output_history_depth, gid.xy, textureLoad(output_depth, gid.xy));
+}
+
+// The sum of the earlier frames for the pixel, with their count in alpha
+fn path_tracer_history(pixel_coord: vec2u, pixel_size: vec2u) -> vec4f {
+	if reprojection.active == 0u {
+		return textureLoad(output_accumulation, pixel_coord);
+	}
+	
+	let history = path_tracer_reprojected_history(pixel_size);
+	
+	// For the `accumulation` command, the share of the history that was kept in
+	// 1/256ths
+	atomicAdd(&reprojection_stats[0], 1u);
+	atomicAdd(&reprojection_stats[1], u32(round(select(0.0, reprojection.history_weight, history.a > 0.0) * 256.0)));
+	
+	return history;
+}
+
+fn path_tracer_reprojected_history(pixel_size: vec2u) -> vec4f {
+	if path_tracer_primary.w < 0.0 {
+		return vec4f(0.0);
+	}
+	
+	let previous = camera_reproject(previous_camera, path_tracer_primary.xyz, pixel_size);
+	let texel = vec2i(round(previous.xy));
+	if previous.w == 0.0 || any(texel < vec2i(0)) || any(texel >= vec2i(pixel_size)) {
+		return vec4f(0.0);
+	}
+	
+	// Something else was in front of the surface
+	let history_depth = textureLoad(output_history_depth, texel).r;
+	if abs(history_depth - previous.z) > reprojection.depth_tolerance * previous.z {
+		return vec4f(0.0);
+	}
+	
+	// The sum and its count alike, the average stays the same
+	return textureLoad(output_history, texel) * reprojection.history_weight;
+}
@@ -0,0 +1,4 @@
+// The path tracer isn't reprojected, the sum starts over when the camera moves
+fn path_tracer_history(pixel_coord: vec2u, pixel_size: vec2u) -> vec4f {
+	return textureLoad(output_accumulation, pixel_coord);
+}
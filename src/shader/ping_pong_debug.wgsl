@@ -0,0 +1,14 @@
+
+fn render_pixel(pixel_coord: vec2u, pixel_size: vec2u) {
+	// Scroll a gradient with the camera position, so that moving around leaves
+	// visible trails if the previous frame is correctly blended in
+	let offset = camera.inverse_view_mat[3].xy * 0.1;
+	let uv = fract(vec2f(pixel_coord) / vec2f(pixel_size) + offset);
+	let current = vec4f(uv, 0.5, 1.0);
+
+	let previous = textureLoad(history_read, pixel_coord);
+	let color = mix(previous, current, blend_factor);
+
+	textureStore(history_write, pixel_coord, color);
+	textureStore(output_color, pixel_coord, color);
+}
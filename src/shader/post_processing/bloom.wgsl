@@ -0,0 +1,71 @@
+// The bloom is a frame behind: the main pass stores the bright parts of the
+// image in bloom_bright, the pre-passes of the next frame blur them
+// horizontally into bloom_blur_temp and then vertically into bloom_blurred,
+// which the main pass adds back on top.
+
+fn post_processing_effect(coord: vec2f, color: vec4f, ctx: PPContext) -> vec4f {
+	// Back to pixel coordinates, see render_pixel() in mpr.wgsl
+	let pixel = vec2u(coord * f32(ctx.resolution.y) + vec2f(ctx.resolution) / 2.0);
+	
+	// Only the part above the threshold glows, keeping the hue
+	let brightness = max(color.r, max(color.g, color.b));
+	let bright = color.rgb * (max(brightness - bloom.threshold, 0.0) / max(brightness, 1e-4));
+	textureStore(bloom_bright, pixel, vec4f(bright, 1.0));
+	
+	let glow = textureLoad(bloom_blurred, pixel).rgb;
+	return vec4f(color.rgb + glow * bloom.intensity, color.a);
+}
+
+// A gaussian that is about gone at the radius
+fn bloom_weight(offset: i32) -> f32 {
+	let sigma = max(f32(bloom_radius()) / 3.0, 0.5);
+	let x = f32(offset) / sigma;
+	return exp(-0.5 * x * x);
+}
+
+// Same as Bloom::MAX_RADIUS
+fn bloom_radius() -> i32 {
+	return i32(min(bloom.radius, 64u));
+}
+
+@compute
+@workgroup_size(8, 8, 1)
+fn bloom_blur_horizontal(@builtin(global_invocation_id) gid: vec3u) {
+	let size = vec2i(textureDimensions(bloom_bright));
+	let pixel = vec2i(gid.xy);
+	if any(pixel >= size) {
+		return;
+	}
+	
+	var sum = vec3f(0.0);
+	var total = 0.0;
+	for (var i = -bloom_radius(); i <= bloom_radius(); i++) {
+		let weight = bloom_weight(i);
+		let x = clamp(pixel.x + i, 0, size.x - 1);
+		sum += textureLoad(bloom_bright, vec2i(x, pixel.y)).rgb * weight;
+		total += weight;
+	}
+	
+	textureStore(bloom_blur_temp, pixel, vec4f(sum / total, 1.0));
+}
+
+@compute
+@workgroup_size(8, 8, 1)
+fn bloom_blur_vertical(@builtin(global_invocation_id) gid: vec3u) {
+	let size = vec2i(textureDimensions(bloom_blur_temp));
+	let pixel = vec2i(gid.xy);
+	if any(pixel >= size) {
+		return;
+	}
+	
+	var sum = vec3f(0.0);
+	var total = 0.0;
+	for (var i = -bloom_radius(); i <= bloom_radius(); i++) {
+		let weight = bloom_weight(i);
+		let y = clamp(pixel.y + i, 0, size.y - 1);
+		sum += textureLoad(bloom_blur_temp, vec2i(pixel.x, y)).rgb * weight;
+		total += weight;
+	}
+	
+	textureStore(bloom_blurred, pixel, vec4f(sum / total, 1.0));
+}
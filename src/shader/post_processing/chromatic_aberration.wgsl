@@ -0,0 +1,8 @@
+fn post_processing_effect(coord: vec2f, color: vec4f, ctx: PPContext) -> vec4f {
+	// Red bends less than blue, so it lands further out. The offsets grow away
+	// from the middle of the screen.
+	let red = pp_sample_source(coord * (1.0 + chromatic_aberration_strength)).r;
+	let blue = pp_sample_source(coord * (1.0 - chromatic_aberration_strength)).b;
+	
+	return vec4f(red, color.g, blue, color.a);
+}
@@ -0,0 +1,42 @@
+fn post_processing_effect(coord: vec2f, color: vec4f, ctx: PPContext) -> vec4f {
+	if color_grade.size == 0u {
+		return color;
+	}
+	
+	// The LUT is made for display colors, the surface does the encoding after us
+	let encoded = color_grade_linear_to_srgb(max(color.rgb, vec3f(0.0)));
+	let input = (encoded - color_grade.domain_min) / (color_grade.domain_max - color_grade.domain_min);
+	
+	// Only the corner of the texture is used, and its texel centers are the
+	// grid points of the LUT
+	let texture_size = f32(textureDimensions(color_grade_lut).x);
+	let uvw = (clamp(input, vec3f(0.0), vec3f(1.0)) * f32(color_grade.size - 1u) + 0.5) / texture_size;
+	
+	var graded: vec3f;
+	if color_grade.is_1d != 0u {
+		// One curve per channel, along x
+		let row = 0.5 / texture_size;
+		graded = vec3f(
+			textureSampleLevel(color_grade_lut, color_grade_sampler, vec3f(uvw.r, row, row), 0.0).r,
+			textureSampleLevel(color_grade_lut, color_grade_sampler, vec3f(uvw.g, row, row), 0.0).g,
+			textureSampleLevel(color_grade_lut, color_grade_sampler, vec3f(uvw.b, row, row), 0.0).b,
+		);
+	} else {
+		graded = textureSampleLevel(color_grade_lut, color_grade_sampler, uvw, 0.0).rgb;
+	}
+	
+	let decoded = color_grade_srgb_to_linear(max(graded, vec3f(0.0)));
+	return vec4f(mix(color.rgb, decoded, color_grade.intensity), color.a);
+}
+
+fn color_grade_linear_to_srgb(color: vec3f) -> vec3f {
+	let low = color * 12.92;
+	let high = 1.055 * pow(color, vec3f(1.0 / 2.4)) - 0.055;
+	return select(high, low, color <= vec3f(0.0031308));
+}
+
+fn color_grade_srgb_to_linear(color: vec3f) -> vec3f {
+	let low = color / 12.92;
+	let high = pow((color + 0.055) / 1.055, vec3f(2.4));
+	return select(high, low, color <= vec3f(0.04045));
+}
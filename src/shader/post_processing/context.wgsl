@@ -0,0 +1,5 @@
+struct PPContext {
+	frame: u32,
+	resolution: vec2u,
+	seed: u32,
+}
\ No newline at end of file
@@ -0,0 +1,13 @@
+#include "/sampling/sampling.wgsl"
+
+fn post_processing_effect(coord: vec2f, color: vec4f, ctx: PPContext) -> vec4f {
+	// Back to pixel coordinates, see render_pixel() in mpr.wgsl
+	let pixel = vec2u(coord * f32(ctx.resolution.y) + vec2f(ctx.resolution) / 2.0);
+	
+	// The difference of two uniform noises has a triangular PDF in [-1; 1]
+	let noise_a = seed_to_unit(pixel_seed(pixel, ctx.resolution, ctx.frame));
+	let noise_b = seed_to_unit(pixel_seed(pixel, ctx.resolution, ctx.seed));
+	let noise = noise_a - noise_b;
+	
+	return vec4f(color.rgb + noise / dither_levels, color.a);
+}
\ No newline at end of file
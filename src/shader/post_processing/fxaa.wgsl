@@ -0,0 +1,133 @@
+// FXAA 3.11 by Timothy Lottes, the quality version, with the same names as the
+// original where it makes sense. The original works on the texture coordinates
+// with y going down, here it's the pipeline's coordinates where one pixel is
+// 1 / resolution.y on both axes, and the neighbours come from
+// pp_sample_source().
+
+fn post_processing_effect(coord: vec2f, color: vec4f, ctx: PPContext) -> vec4f {
+	if fxaa.enabled == 0u {
+		return color;
+	}
+	
+	let texel = 1.0 / f32(ctx.resolution.y);
+	
+	var lumaM = fxaa_luma(color.rgb);
+	var lumaS = fxaa_luma_at(coord + vec2f(0.0, 1.0) * texel);
+	let lumaE = fxaa_luma_at(coord + vec2f(1.0, 0.0) * texel);
+	var lumaN = fxaa_luma_at(coord + vec2f(0.0, -1.0) * texel);
+	let lumaW = fxaa_luma_at(coord + vec2f(-1.0, 0.0) * texel);
+	
+	// Leave the pixels that aren't on an edge as they are
+	let rangeMax = max(max(lumaN, lumaW), max(lumaE, max(lumaS, lumaM)));
+	let rangeMin = min(min(lumaN, lumaW), min(lumaE, min(lumaS, lumaM)));
+	let lumaRange = rangeMax - rangeMin;
+	if lumaRange < max(fxaa.edge_threshold_min, rangeMax * fxaa.edge_threshold) {
+		return color;
+	}
+	
+	let lumaNW = fxaa_luma_at(coord + vec2f(-1.0, -1.0) * texel);
+	let lumaSE = fxaa_luma_at(coord + vec2f(1.0, 1.0) * texel);
+	let lumaNE = fxaa_luma_at(coord + vec2f(1.0, -1.0) * texel);
+	let lumaSW = fxaa_luma_at(coord + vec2f(-1.0, 1.0) * texel);
+	
+	// Whether the edge is horizontal or vertical
+	let lumaNS = lumaN + lumaS;
+	let lumaWE = lumaW + lumaE;
+	let lumaNESE = lumaNE + lumaSE;
+	let lumaNWNE = lumaNW + lumaNE;
+	let lumaNWSW = lumaNW + lumaSW;
+	let lumaSWSE = lumaSW + lumaSE;
+	let edgeHorz = abs(-2.0 * lumaW + lumaNWSW) + abs(-2.0 * lumaM + lumaNS) * 2.0 + abs(-2.0 * lumaE + lumaNESE);
+	let edgeVert = abs(-2.0 * lumaS + lumaSWSE) + abs(-2.0 * lumaM + lumaWE) * 2.0 + abs(-2.0 * lumaN + lumaNWNE);
+	let horzSpan = edgeHorz >= edgeVert;
+	
+	// How much the pixel stands out from its neighbours, for the sub-pixel
+	// aliasing
+	let subpixA = (lumaNS + lumaWE) * 2.0 + lumaNWSW + lumaNESE;
+	let subpixB = subpixA / 12.0 - lumaM;
+	let subpixC = clamp(abs(subpixB) / lumaRange, 0.0, 1.0);
+	let subpixF = (-2.0 * subpixC + 3.0) * subpixC * subpixC;
+	let subpixH = subpixF * subpixF * fxaa.subpixel;
+	
+	// Which side of the pixel the edge is on
+	if !horzSpan {
+		lumaN = lumaW;
+		lumaS = lumaE;
+	}
+	let gradientN = lumaN - lumaM;
+	let gradientS = lumaS - lumaM;
+	let pairN = abs(gradientN) >= abs(gradientS);
+	let gradient = max(abs(gradientN), abs(gradientS));
+	var lengthSign = texel;
+	if pairN {
+		lengthSign = -lengthSign;
+	}
+	let lumaNN = select(lumaS + lumaM, lumaN + lumaM, pairN);
+	
+	// Walk along the edge in both directions until its ends, on the boundary
+	// between the pixel and the neighbour across the edge
+	var posB = coord;
+	var offNP = vec2f(0.0);
+	if horzSpan {
+		posB.y += lengthSign * 0.5;
+		offNP.x = texel;
+	} else {
+		posB.x += lengthSign * 0.5;
+		offNP.y = texel;
+	}
+	
+	let gradientScaled = gradient / 4.0;
+	var posN = posB;
+	var posP = posB;
+	var lumaEndN = 0.0;
+	var lumaEndP = 0.0;
+	var doneN = false;
+	var doneP = false;
+	for (var i = 0u; i < fxaa.step_count && !(doneN && doneP); i++) {
+		let stride = fxaa.steps[i / 4u][i % 4u];
+		if !doneN {
+			posN -= offNP * stride;
+			lumaEndN = fxaa_luma_at(posN) - lumaNN * 0.5;
+			doneN = abs(lumaEndN) >= gradientScaled;
+		}
+		if !doneP {
+			posP += offNP * stride;
+			lumaEndP = fxaa_luma_at(posP) - lumaNN * 0.5;
+			doneP = abs(lumaEndP) >= gradientScaled;
+		}
+	}
+	
+	// Blend across the edge, more the closer the pixel is to the end of the
+	// edge that the pixel belongs to
+	var dstN = coord.x - posN.x;
+	var dstP = posP.x - coord.x;
+	if !horzSpan {
+		dstN = coord.y - posN.y;
+		dstP = posP.y - coord.y;
+	}
+	let lumaMLTZero = lumaM - lumaNN * 0.5 < 0.0;
+	let goodSpanN = (lumaEndN < 0.0) != lumaMLTZero;
+	let goodSpanP = (lumaEndP < 0.0) != lumaMLTZero;
+	let goodSpan = select(goodSpanP, goodSpanN, dstN < dstP);
+	let pixelOffset = 0.5 - min(dstN, dstP) / (dstP + dstN);
+	let pixelOffsetSubpix = max(select(0.0, pixelOffset, goodSpan), subpixH);
+	
+	var posM = coord;
+	if horzSpan {
+		posM.y += pixelOffsetSubpix * lengthSign;
+	} else {
+		posM.x += pixelOffsetSubpix * lengthSign;
+	}
+	
+	return vec4f(pp_sample_source(posM).rgb, color.a);
+}
+
+// The original expects colors that are already gamma encoded, the square root
+// of the luma of the linear colors is close enough
+fn fxaa_luma(color: vec3f) -> f32 {
+	return sqrt(dot(max(color, vec3f(0.0)), vec3f(0.299, 0.587, 0.114)));
+}
+
+fn fxaa_luma_at(coord: vec2f) -> f32 {
+	return fxaa_luma(pp_sample_source(coord).rgb);
+}
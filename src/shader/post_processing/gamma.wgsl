@@ -1,3 +1,3 @@
-fn post_processing_effect(coord: vec2f, color: vec4f) -> vec4f {
+fn post_processing_effect(coord: vec2f, color: vec4f, ctx: PPContext) -> vec4f {
 	return pow(color, vec4f(1.0 / gamma));
 }
\ No newline at end of file
@@ -1,7 +1,16 @@
+#include "context.wgsl"
+
 fn post_processing_pipeline(coord: vec2f, color_in: vec4f) -> vec4f {
 	var color = color_in;
 	
-	CALL_EFFECTS
+	let ctx = PPContext(globals.frame, globals.resolution, globals.seed);
+	
+	// pp_dispatch() is generated with one case per effect, the chain only says
+	// which ones run and in what order
+	for (var i = 0u; i < pp_chain.count; i++) {
+		let index = pp_chain.order[i / 4u][i % 4u];
+		color = pp_dispatch(index, ctx, coord, color);
+	}
 	
 	return color;
 }
\ No newline at end of file
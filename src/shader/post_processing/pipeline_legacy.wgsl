@@ -0,0 +1,11 @@
+#include "context.wgsl"
+
+fn post_processing_pipeline(coord: vec2f, color_in: vec4f) -> vec4f {
+	var color = color_in;
+	
+	let ctx = PPContext(globals.frame, globals.resolution, globals.seed);
+	
+	CALL_EFFECTS
+	
+	return color;
+}
\ No newline at end of file
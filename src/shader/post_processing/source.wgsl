@@ -0,0 +1,43 @@
+// What the pp_store_source_<index>() and pp_sample_source_<index>() generated
+// for the effects that sample their source have in common, see
+// PostProcessingPipeline::source_functions()
+
+// The four pixels around a coordinate and how much of each to take
+struct PPSourceTexels {
+	p00: vec2u,
+	p10: vec2u,
+	p01: vec2u,
+	p11: vec2u,
+	t: vec2f,
+}
+
+// Back to pixel coordinates, see camera_coord() in mpr_common.wgsl, where the
+// pixels are at the whole positions
+fn pp_source_position(coord: vec2f, size: vec2u) -> vec2f {
+	return coord * f32(size.y) + vec2f(size) / 2.0;
+}
+
+fn pp_source_pixel(coord: vec2f, size: vec2u) -> vec2u {
+	return vec2u(round(pp_source_position(coord, size)));
+}
+
+// Clamped to the edges
+fn pp_source_texels(coord: vec2f, size: vec2u) -> PPSourceTexels {
+	let position = pp_source_position(coord, size);
+	let base = vec2i(floor(position));
+	return PPSourceTexels(
+		pp_source_clamp(base, size),
+		pp_source_clamp(base + vec2i(1, 0), size),
+		pp_source_clamp(base + vec2i(0, 1), size),
+		pp_source_clamp(base + vec2i(1, 1), size),
+		fract(position),
+	);
+}
+
+fn pp_source_clamp(pixel: vec2i, size: vec2u) -> vec2u {
+	return vec2u(clamp(pixel, vec2i(0), vec2i(size) - 1));
+}
+
+fn pp_source_bilinear(texels: PPSourceTexels, c00: vec4f, c10: vec4f, c01: vec4f, c11: vec4f) -> vec4f {
+	return mix(mix(c00, c10, texels.t.x), mix(c01, c11, texels.t.x), texels.t.y);
+}
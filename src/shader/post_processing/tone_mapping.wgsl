@@ -0,0 +1,86 @@
+// Same values as ToneMapOperator
+const TONE_MAP_REINHARD = 0u;
+const TONE_MAP_ACES = 1u;
+const TONE_MAP_AGX = 2u;
+const TONE_MAP_NEUTRAL = 3u;
+
+fn post_processing_effect(coord: vec2f, color: vec4f, ctx: PPContext) -> vec4f {
+	let exposed = max(color.rgb, vec3f(0.0)) * exp2(tone_mapping.exposure);
+
+	var mapped: vec3f;
+	if tone_mapping.operator == TONE_MAP_REINHARD {
+		mapped = tone_map_reinhard(exposed);
+	} else if tone_mapping.operator == TONE_MAP_ACES {
+		mapped = tone_map_aces(exposed);
+	} else if tone_mapping.operator == TONE_MAP_AGX {
+		mapped = tone_map_agx(exposed);
+	} else if tone_mapping.operator == TONE_MAP_NEUTRAL {
+		mapped = tone_map_neutral(exposed);
+	} else {
+		mapped = exposed;
+	}
+
+	return vec4f(clamp(mapped, vec3f(0.0), vec3f(1.0)), color.a);
+}
+
+fn tone_map_reinhard(color: vec3f) -> vec3f {
+	return color / (1.0 + color);
+}
+
+// Krzysztof Narkowicz's fit, which expects the colors to be scaled by 0.6 so
+// that 1 stays about where it is
+fn tone_map_aces(color: vec3f) -> vec3f {
+	let x = color * 0.6;
+	return (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14);
+}
+
+// Benjamin Wrensch's fit of AgX, with the default look. The result is decoded
+// back to linear, the gamma correction comes later.
+fn tone_map_agx(color: vec3f) -> vec3f {
+	let inset = mat3x3f(
+		0.842479062253094, 0.0423282422610123, 0.0423756549057051,
+		0.0784335999999992, 0.878468636469772, 0.0784336,
+		0.0792237451477643, 0.0791661274605434, 0.879142973793104,
+	);
+	let outset = mat3x3f(
+		1.19687900512017, -0.0528968517574562, -0.0529716355144438,
+		-0.0980208811401368, 1.15190312990417, -0.0980434501171241,
+		-0.0990297440797205, -0.0989611768448433, 1.15107367264116,
+	);
+	let min_ev = -12.47393;
+	let max_ev = 4.026069;
+
+	// Into a log encoding that spans the stops from min_ev to max_ev
+	var x = inset * color;
+	x = clamp(log2(max(x, vec3f(1e-10))), vec3f(min_ev), vec3f(max_ev));
+	x = (x - min_ev) / (max_ev - min_ev);
+
+	// The sigmoid, as a polynomial
+	let x2 = x * x;
+	let x4 = x2 * x2;
+	x = 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232;
+
+	x = outset * x;
+	return pow(max(x, vec3f(0.0)), vec3f(2.2));
+}
+
+// Khronos PBR Neutral, see
+// https://github.com/KhronosGroup/ToneMapping/tree/main/PBR_Neutral
+fn tone_map_neutral(color: vec3f) -> vec3f {
+	let start_compression = 0.8 - 0.04;
+	let desaturation = 0.15;
+
+	let lowest = min(color.r, min(color.g, color.b));
+	let offset = select(0.04, lowest - 6.25 * lowest * lowest, lowest < 0.08);
+	let c = color - offset;
+
+	let peak = max(c.r, max(c.g, c.b));
+	if peak < start_compression {
+		return c;
+	}
+
+	let d = 1.0 - start_compression;
+	let new_peak = 1.0 - d * d / (peak + d - start_compression);
+	let g = 1.0 - 1.0 / (desaturation * (peak - new_peak) + 1.0);
+	return mix(c * (new_peak / peak), vec3f(new_peak), g);
+}
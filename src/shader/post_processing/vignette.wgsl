@@ -0,0 +1,14 @@
+// How far past the radius the darkening is complete
+const VIGNETTE_SOFTNESS: f32 = 0.5;
+
+fn post_processing_effect(coord: vec2f, color: vec4f, ctx: PPContext) -> vec4f {
+	// 1 at the middle of the edges of the screen, see camera_coord()
+	let aspect = f32(ctx.resolution.x) / f32(ctx.resolution.y);
+	let stretched = coord * 2.0 / vec2f(aspect, 1.0);
+	let circular = coord * 2.0;
+	
+	let from_middle = length(mix(stretched, circular, vignette.roundness));
+	let darkening = smoothstep(vignette.radius, vignette.radius + VIGNETTE_SOFTNESS, from_middle);
+	
+	return vec4f(color.rgb * (1.0 - vignette.strength * darkening), color.a);
+}
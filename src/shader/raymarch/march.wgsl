@@ -0,0 +1,71 @@
+// Sphere traces scene_sdf() with the raymarch_settings, up to max_distance.
+// Shared by the raymarcher and the hybrid analytic intersector.
+fn raymarch_sdf(ray_origin: vec3f, ray_dir: vec3f, max_distance: f32) -> Intersection {
+	// struct Intersection {
+	// 	has_hit: bool,
+	// 	object: Object,
+	// 	distance: f32,
+	// 	position: vec3f,
+	// 	normal: vec3f,
+	// 	outgoing: vec3f,
+	// 	uv: vec2f,
+	// 	tangent: vec4f,
+	// 	id: u32,
+	// }
+	let object = Object(vec3f(1, 0, 0), 0u);
+	var intersection = Intersection(false, object, 0.0, vec3f(0), vec3f(0), -ray_dir, vec2f(0), vec4f(0), 0u);
+	
+	var iters: u32;
+	var t = raymarch_settings.min_march;
+	var p = ray_origin;
+	var has_hit = false;
+	
+	// Lowered by the dynamic quality when the GPU can't keep up
+	let max_steps = max(u32(f32(raymarch_settings.max_steps) * globals.march_steps_scale), 1u);
+	
+	for (iters = 0u; iters < max_steps && t < max_distance; iters++) {
+		p = ray_origin + ray_dir * t;
+		
+		let result = scene_sdf(p);
+		
+		if (result.distance < raymarch_settings.hit_epsilon) {
+			has_hit = true;
+			intersection.object.material_id = result.material_id;
+			break;
+		}
+		
+		t += result.distance;
+	}
+	
+	gpu_assert(iters < max_steps, RAYMARCH_ASSERT_STEP_OVERFLOW, vec4f(ray_dir, t));
+	
+	// See Raymarcher::step_heatmap, a constant so it's compiled out when off.
+	// 1 is as many steps as allowed, which includes the rays that ran out.
+	if RAYMARCH_STEP_HEATMAP {
+		mpr_debug_output = vec4f(vec3f(f32(iters) / f32(max_steps)), 1.0);
+	}
+	
+	if (!has_hit) {
+		// Marched too far away or too often, we didn't hit anything
+		intersection.distance = camera.z_far;
+		return intersection;
+	}
+	
+	intersection.has_hit = true;
+	intersection.distance = t;
+	intersection.position = p;
+	intersection.normal = calc_normal(p);
+	// The scene only knows its materials
+	intersection.id = intersection.object.material_id + 1u;
+	
+	return intersection;
+}
+
+fn calc_normal(p: vec3f) -> vec3f {
+	let h = 0.0001; // replace by an appropriate value
+	let k = vec2f(1, -1);
+	return normalize(k.xyy * scene_sdf(p + k.xyy * h).distance + 
+						  k.yyx * scene_sdf(p + k.yyx * h).distance + 
+						  k.yxy * scene_sdf(p + k.yxy * h).distance + 
+						  k.xxx * scene_sdf(p + k.xxx * h).distance);
+}
\ No newline at end of file
@@ -0,0 +1,53 @@
+// An infinite grid on the plane y = reference_grid.height, drawn over the scene
+
+fn composite_reference_grid(ray_origin: vec3f, ray_dir: vec3f, pixel_size: vec2u, scene_distance: f32, color: vec4f) -> vec4f {
+	if globals.show_reference_grid == 0u || abs(ray_dir.y) < 1e-6 {
+		return color;
+	}
+	
+	let t = (reference_grid.height - ray_origin.y) / ray_dir.y;
+	
+	// Behind the camera, hidden by the scene, or completely faded out
+	if t <= 0.0 || t >= scene_distance || t >= reference_grid.fade_distance {
+		return color;
+	}
+	
+	let p = (ray_origin + ray_dir * t).xz;
+	
+	// The world size of a pixel where the ray hits the plane, stretched at grazing
+	// angles. There are no derivatives in compute shaders, so it comes from the
+	// camera instead.
+	var footprint = t / camera.focal_length;
+	if (camera.orthographic != 0u) {
+		footprint = camera.ortho_height / f32(pixel_size.y);
+	} else if (camera.equirectangular != 0u) {
+		// A pixel is the same angle everywhere
+		footprint = t * 3.14159265359 / f32(pixel_size.y);
+	}
+	footprint /= max(abs(ray_dir.y), 0.05);
+	
+	let major_spacing = reference_grid.minor_spacing * f32(reference_grid.major_every);
+	let minor = grid_lines(p, reference_grid.minor_spacing, footprint);
+	let major = grid_lines(p, major_spacing, footprint);
+	
+	let fade = 1.0 - smoothstep(reference_grid.fade_distance * 0.5, reference_grid.fade_distance, t);
+	
+	// The major lines go over the minor ones
+	var result = mix(color.rgb, reference_grid.minor_color.rgb, reference_grid.minor_color.a * minor * fade);
+	result = mix(result, reference_grid.major_color.rgb, reference_grid.major_color.a * major * fade);
+	
+	return vec4f(result, color.a);
+}
+
+// The coverage of the lines of one grid at p, in [0, 1]
+fn grid_lines(p: vec2f, spacing: f32, footprint: f32) -> f32 {
+	// Distance to the closest line on both axes, in pixels
+	let to_line = abs(fract(p / spacing - 0.5) - 0.5) * spacing / footprint;
+	let half_width = reference_grid.line_width * 0.5;
+	let lines = 1.0 - smoothstep(vec2f(half_width - 0.5), vec2f(half_width + 0.5), to_line);
+	
+	// Lines closer than a few pixels would only make moiré, so they fade out
+	let density = smoothstep(2.0, 4.0, spacing / footprint);
+	
+	return max(lines.x, lines.y) * density;
+}
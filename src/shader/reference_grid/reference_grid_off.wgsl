@@ -0,0 +1,5 @@
+// The renderer has no reference grid, see reference_grid.wgsl
+
+fn composite_reference_grid(ray_origin: vec3f, ray_dir: vec3f, pixel_size: vec2u, scene_distance: f32, color: vec4f) -> vec4f {
+	return color;
+}
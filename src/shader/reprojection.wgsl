@@ -0,0 +1,31 @@
+// Where a world position is seen from a camera view, the reverse of
+// camera_ray(): the pixel in xy (same coordinates as the pixel_coord, in the
+// render resolution), and in z the distance along its ray relative to the far
+// plane, like the renderers' output_depth. w is 0 if the view can't see it,
+// i.e. it's behind the camera or the view is a panorama.
+fn camera_reproject(view: CameraView, position: vec3f, pixel_size: vec2u) -> vec4f {
+	if view.equirectangular != 0u {
+		return vec4f(0.0);
+	}
+	
+	let p = (view.view_mat * vec4f(position, 1.0)).xyz;
+	if p.z <= 0.0 {
+		return vec4f(0.0);
+	}
+	
+	let height = f32(pixel_size.y);
+	
+	var coord: vec2f;
+	var distance: f32;
+	if view.orthographic != 0u {
+		// The rays start on the view plane
+		coord = p.xy / view.ortho_height;
+		distance = p.z;
+	} else {
+		coord = p.xy / p.z * (view.focal_length / height);
+		distance = length(p);
+	}
+	
+	let pixel = coord * height + vec2f(pixel_size) / 2.0;
+	return vec4f(pixel, distance / view.z_far, 1.0);
+}
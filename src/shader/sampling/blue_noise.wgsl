@@ -0,0 +1,14 @@
+// A tiling 64x64 blue noise, with four independent channels in
+// blue_noise_texture. Neighbouring pixels get values far apart, so a few
+// samples per pixel look like fine grain instead of clumps.
+
+// The golden ratio, shifting the values every frame so that the frames
+// average out
+const BLUE_NOISE_FRAME_SHIFT: f32 = 0.61803398875;
+
+// Four values in [0; 1) for the pixel, different every frame
+fn blue_noise(pixel: vec2u) -> vec4f {
+	let size = textureDimensions(blue_noise_texture);
+	let noise = textureLoad(blue_noise_texture, pixel % size);
+	return fract(noise + f32(frame_info.frame % 1024u) * BLUE_NOISE_FRAME_SHIFT);
+}
@@ -0,0 +1,41 @@
+// A random number generator per invocation, for the fragments that need more
+// than a few numbers per pixel (soft shadows, depth of field, path tracing).
+// Mirrored on the CPU by RngState in fragments/sampling.rs.
+//
+// var rng = rng_init(pixel);
+// let x = rng_next_f32(&rng);
+
+struct RngState {
+	state: u32,
+}
+
+// Different for every pixel and every frame
+fn rng_init(pixel: vec2u) -> RngState {
+	return RngState(rng_hash(pixel.x + rng_hash(pixel.y + rng_hash(frame_info.frame))));
+}
+
+fn rng_next_u32(rng: ptr<function, RngState>) -> u32 {
+	(*rng).state = (*rng).state * 747796405u + 2891336453u;
+	return rng_permute((*rng).state);
+}
+
+// In [0; 1)
+fn rng_next_f32(rng: ptr<function, RngState>) -> f32 {
+	// The top 24 bits, which a f32 holds exactly
+	return f32(rng_next_u32(rng) >> 8u) / 16777216.0;
+}
+
+fn rng_next_vec2f(rng: ptr<function, RngState>) -> vec2f {
+	let x = rng_next_f32(rng);
+	return vec2f(x, rng_next_f32(rng));
+}
+
+// PCG-RXS-M-XS, from "Hash Functions for GPU Rendering" (Jarzynski & Olano, 2020)
+fn rng_hash(v: u32) -> u32 {
+	return rng_permute(v * 747796405u + 2891336453u);
+}
+
+fn rng_permute(state: u32) -> u32 {
+	let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+	return (word >> 22u) ^ word;
+}
@@ -0,0 +1,41 @@
+// The per-pixel randomness, mirrored on the CPU by fragments/sampling.rs
+
+// Same as SEED_GRID in sampling.rs
+const SAMPLING_SEED_GRID: u32 = 16384u;
+
+// Three random u32s for the pixel, different for every frame seed.
+// With globals.stable_pixel_seeds, the pixel's center is snapped to a fixed
+// grid over the image first, so the same point of the image gets the same
+// seed at any resolution.
+fn pixel_seed(pixel: vec2u, resolution: vec2u, frame_seed: u32) -> vec3u {
+	var point = pixel;
+	
+	if (globals.stable_pixel_seeds != 0u) {
+		// (pixel + 0.5) / resolution * grid, in integers so that it is exact
+		point = (2u * pixel + 1u) * SAMPLING_SEED_GRID / (2u * resolution);
+	}
+	
+	return sampling_hash(vec3u(point, frame_seed));
+}
+
+// PCG3D hash, from "Hash Functions for GPU Rendering" (Jarzynski & Olano, 2020)
+fn sampling_hash(v_in: vec3u) -> vec3u {
+	var v = v_in * 1664525u + 1013904223u;
+	
+	v.x += v.y * v.z;
+	v.y += v.z * v.x;
+	v.z += v.x * v.y;
+	
+	v ^= v >> vec3u(16u);
+	
+	v.x += v.y * v.z;
+	v.y += v.z * v.x;
+	v.z += v.x * v.y;
+	
+	return v;
+}
+
+// In [0; 1]
+fn seed_to_unit(seed: vec3u) -> vec3f {
+	return vec3f(seed) / f32(0xffffffffu);
+}
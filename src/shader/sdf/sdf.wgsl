@@ -0,0 +1,35 @@
+
+#include "/raymarch/primitives.wgsl"
+
+
+// The distance to the closest surface, and that surface's material
+struct SdfResult {
+	distance: f32,
+	material_id: u32,
+}
+
+fn sdf_union(a: SdfResult, b: SdfResult) -> SdfResult {
+	if (a.distance < b.distance) {
+		return a;
+	}
+	return b;
+}
+
+// Polynomial smooth min, the material is the one of the closer shape
+fn sdf_smooth_union(a: SdfResult, b: SdfResult, k: f32) -> SdfResult {
+	let h = clamp(0.5 + 0.5 * (b.distance - a.distance) / k, 0.0, 1.0);
+	let distance = mix(b.distance, a.distance, h) - k * h * (1.0 - h);
+	return SdfResult(distance, select(b.material_id, a.material_id, h >= 0.5));
+}
+
+// What's cut out keeps the material of a
+fn sdf_subtract(a: SdfResult, b: SdfResult) -> SdfResult {
+	return SdfResult(max(a.distance, -b.distance), a.material_id);
+}
+
+fn sdf_intersect(a: SdfResult, b: SdfResult) -> SdfResult {
+	if (a.distance > b.distance) {
+		return a;
+	}
+	return b;
+}
@@ -1,11 +1,10 @@
-fn shade(intersection: Intersection) -> vec4f {
-	if !intersection.has_hit {
-		return vec4f(0.0, 0.6, 1.0, 1.0);
-	}
+// Same as Light::DIRECTIONAL
+const CEL_LIGHT_DIRECTIONAL: u32 = 1u;
 
+fn shade(intersection: Intersection) -> vec4f {
 	let object = intersection.object;
 
-	let full_diffuse = dot(intersection.normal, -sun_direction) * 0.5 + 0.5;
+	let full_diffuse = dot(intersection.normal, -cel_sun_direction()) * 0.5 + 0.5;
 	let cel_diffuse = get_gradient_value(full_diffuse);
 	
 	let color = object.color * cel_diffuse;
@@ -17,4 +16,14 @@ fn get_gradient_value(diffuse: f32) -> vec3f {
 	let coords = vec2f(diffuse, 0.5);
 	let fitted_coords = coords * vec2f(textureDimensions(cel_gradient));
 	return textureLoad(cel_gradient, vec2u(fitted_coords)).rgb;
-}
\ No newline at end of file
+}
+
+// Where the first directional light is going, or straight down if there is none
+fn cel_sun_direction() -> vec3f {
+	for (var i = 0u; i < arrayLength(&lights); i++) {
+		if lights[i].kind == CEL_LIGHT_DIRECTIONAL {
+			return normalize(lights[i].direction);
+		}
+	}
+	return vec3f(0.0, -1.0, 0.0);
+}
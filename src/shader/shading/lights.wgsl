@@ -0,0 +1,200 @@
+#include "/sampling/sampling.wgsl"
+
+// Same as the constants of `Light`
+const LIGHT_POINT: u32 = 0u;
+const LIGHT_DIRECTIONAL: u32 = 1u;
+const LIGHT_SPOT: u32 = 2u;
+
+// What a light gives to a point
+struct LightSample {
+	// Towards the light
+	direction: vec3f,
+	distance: f32,
+	// Already attenuated, zero if the light doesn't reach the point
+	radiance: vec3f,
+}
+
+fn light_count() -> u32 {
+	return arrayLength(&lights);
+}
+
+fn sample_light(i: u32, p: vec3f) -> LightSample {
+	let light = lights[i];
+	let radiance = light.color * light.intensity;
+
+	if light.kind == LIGHT_DIRECTIONAL {
+		return LightSample(-light.direction, 1e30, radiance);
+	}
+	
+	let to_light = light.position - p;
+	let distance = max(length(to_light), 0.0001);
+	let direction = to_light / distance;
+	
+	// Inverse square, windowed so that it reaches exactly zero at the radius
+	let window = saturate(1.0 - pow(distance / light.radius, 4.0));
+	var attenuation = window * window / (distance * distance);
+	
+	if light.kind == LIGHT_SPOT {
+		let cos_angle = dot(-direction, light.direction);
+		attenuation *= smoothstep(light.cos_outer_cone, light.cos_inner_cone, cos_angle);
+	}
+	
+	return LightSample(direction, distance, radiance * attenuation);
+}
+
+// How far off the surface the shadow rays start, so that they don't hit it
+const SHADOW_BIAS: f32 = 0.001;
+const SHADOW_TAU: f32 = 6.28318530718;
+
+// 0 if something is between the point and the light, 1 otherwise.
+// While the frames are accumulated, the lights are discs facing the point,
+// with shadow_softness as the radius (in scene units, or the tangent of the
+// angle for the directional lights), and every frame aims the ray at a random
+// point of the disc. The frames average out into soft penumbras. Otherwise the shadows are
+// hard, since a single frame would only be noise.
+fn light_visibility(i: u32, light: LightSample, p: vec3f, n: vec3f) -> f32 {
+	// Nothing to hide
+	if dot(n, light.direction) <= 0.0 || all(light.radiance == vec3f(0.0)) {
+		return 0.0;
+	}
+	
+	var direction = light.direction;
+	var max_t = light.distance;
+	
+	if shadow_softness > 0.0 && globals.accumulating != 0u {
+		// Another sample for every light
+		let seed = pixel_seed(shading_pixel, globals.resolution, globals.seed);
+		let random = seed_to_unit(sampling_hash(seed + vec3u(i)));
+		
+		// Uniform on the disc
+		let radius = shadow_softness * sqrt(random.x);
+		let angle = SHADOW_TAU * random.y;
+		
+		let up = select(vec3f(1.0, 0.0, 0.0), vec3f(0.0, 1.0, 0.0), abs(direction.x) > 0.9);
+		let tangent = normalize(cross(direction, up));
+		let bitangent = cross(direction, tangent);
+		let offset = (tangent * cos(angle) + bitangent * sin(angle)) * radius;
+		
+		if lights[i].kind == LIGHT_DIRECTIONAL {
+			direction = normalize(direction + offset);
+		} else {
+			let to_light = direction * light.distance + offset;
+			max_t = length(to_light);
+			direction = to_light / max_t;
+		}
+	}
+	
+	if occluded(p + n * SHADOW_BIAS, direction, max_t - SHADOW_BIAS) {
+		return 0.0;
+	}
+	return 1.0;
+}
+
+// How likely light_pick() is to pick light i, in proportion to its brightness
+fn light_pick_weight(i: u32) -> f32 {
+	let light = lights[i];
+	return max(dot(light.color, vec3f(0.2126, 0.7152, 0.0722)) * light.intensity, 0.0);
+}
+
+fn light_pick_total() -> f32 {
+	var total = 0.0;
+	for (var i = 0u; i < light_count(); i++) {
+		total += light_pick_weight(i);
+	}
+	return total;
+}
+
+// 0 if there's no light to pick
+fn light_pick_pdf(i: u32) -> f32 {
+	let total = light_pick_total();
+	if total <= 0.0 {
+		return 0.0;
+	}
+	return light_pick_weight(i) / total;
+}
+
+// One of the lights, in proportion to light_pick_weight(). xi is in [0, 1).
+fn light_pick(xi: f32) -> u32 {
+	var remaining = xi * light_pick_total();
+	for (var i = 0u; i < light_count(); i++) {
+		remaining -= light_pick_weight(i);
+		if remaining < 0.0 {
+			return i;
+		}
+	}
+	return light_count() - 1u;
+}
+
+// The point and spot lights with a size are spheres, the others can only be
+// reached by sampling them
+fn light_is_sphere(i: u32) -> bool {
+	return lights[i].kind != LIGHT_DIRECTIONAL && lights[i].size > 0.0;
+}
+
+// What the sphere of light i gives off towards p. As bright in total as the
+// point it replaces, since the sphere covers about pi size² / distance² of what p
+// sees.
+fn light_emission(i: u32, p: vec3f) -> vec3f {
+	let light = sample_light(i, p);
+	let size = lights[i].size;
+	return light.radiance * light.distance * light.distance / (0.5 * SHADOW_TAU * size * size);
+}
+
+// The cosine of the angle that the sphere of light i covers around its center,
+// as seen from p. -1 from inside the sphere.
+fn light_sphere_cos_max(i: u32, p: vec3f) -> f32 {
+	let to_light = lights[i].position - p;
+	let size = lights[i].size;
+	let distance_sq = dot(to_light, to_light);
+	if distance_sq <= size * size {
+		return -1.0;
+	}
+	return sqrt(1.0 - size * size / distance_sq);
+}
+
+// The pdf (over the solid angle) of light_sample_sphere() giving a direction
+// towards the sphere of light i, 0 from inside it
+fn light_sphere_pdf(i: u32, p: vec3f) -> f32 {
+	let cos_max = light_sphere_cos_max(i, p);
+	if cos_max < 0.0 {
+		return 0.0;
+	}
+	return 1.0 / (SHADOW_TAU * (1.0 - cos_max));
+}
+
+// Where the ray hits the sphere of light i, or a negative distance if it doesn't
+fn light_sphere_hit(i: u32, origin: vec3f, dir: vec3f) -> f32 {
+	let size = lights[i].size;
+	let oc = origin - lights[i].position;
+	let b = dot(oc, dir);
+	let c = dot(oc, oc) - size * size;
+	let discriminant = b * b - c;
+	if discriminant < 0.0 {
+		return -1.0;
+	}
+	return -b - sqrt(discriminant);
+}
+
+// A direction from p towards the sphere of light i, uniform over the cone the
+// sphere covers. The distance is to the sphere's surface, the radiance is
+// light_emission(), both zero from inside the sphere.
+fn light_sample_sphere(i: u32, p: vec3f, xi: vec2f) -> LightSample {
+	let cos_max = light_sphere_cos_max(i, p);
+	if cos_max < 0.0 {
+		return LightSample(vec3f(0.0, 1.0, 0.0), 0.0, vec3f(0.0));
+	}
+
+	let cos_theta = 1.0 - xi.x * (1.0 - cos_max);
+	let sin_theta = sqrt(max(1.0 - cos_theta * cos_theta, 0.0));
+	let phi = SHADOW_TAU * xi.y;
+
+	let axis = normalize(lights[i].position - p);
+	let up = select(vec3f(1.0, 0.0, 0.0), vec3f(0.0, 1.0, 0.0), abs(axis.x) > 0.9);
+	let tangent = normalize(cross(up, axis));
+	let bitangent = cross(axis, tangent);
+	let direction = normalize(tangent * sin_theta * cos(phi) + bitangent * sin_theta * sin(phi) + axis * cos_theta);
+
+	// Grazing the edge can miss by a rounding error
+	let distance = max(light_sphere_hit(i, p, direction), 0.0);
+	return LightSample(direction, distance, light_emission(i, p));
+}
@@ -0,0 +1,47 @@
+#include "lights.wgsl"
+#include "pbr_brdf.wgsl"
+
+
+fn shade(intersection: Intersection) -> vec4f {
+	let surface = pbr_surface(intersection);
+	let albedo = surface.albedo;
+	let metallic = surface.metallic;
+	let roughness = surface.roughness;
+	
+	let n = surface.normal;
+	let v = normalize(intersection.outgoing);
+	let n_dot_v = max(dot(n, v), 0.0001);
+	
+	// Dielectrics reflect about 4% head-on, metals reflect with their color
+	let f0 = mix(vec3f(0.04), albedo, metallic);
+	
+	var color = pbr_ambient(n, v, albedo, metallic, roughness, f0);
+	for (var i = 0u; i < light_count(); i++) {
+		let light = sample_light(i, intersection.position);
+		
+		let visibility = light_visibility(i, light, intersection.position, n);
+		if visibility == 0.0 {
+			continue;
+		}
+		
+		let l = light.direction;
+		let h = normalize(v + l);
+		
+		let n_dot_l = max(dot(n, l), 0.0);
+		let n_dot_h = max(dot(n, h), 0.0);
+		let v_dot_h = max(dot(v, h), 0.0);
+		
+		let fresnel = pbr_fresnel_schlick(v_dot_h, f0);
+		let distribution = pbr_distribution_ggx(n_dot_h, roughness);
+		let geometry = pbr_geometry_smith(n_dot_v, n_dot_l, roughness);
+		
+		let specular = distribution * geometry * fresnel / (4.0 * n_dot_v * max(n_dot_l, 0.0001));
+		
+		// What isn't reflected is diffused, except by metals
+		let diffuse = (vec3f(1.0) - fresnel) * (1.0 - metallic) * albedo / PBR_PI;
+		
+		color += (diffuse + specular) * light.radiance * n_dot_l * visibility;
+	}
+	
+	return vec4f(color, 1.0);
+}
@@ -0,0 +1,84 @@
+// The Cook-Torrance terms and the materials, shared by the PBR shading and the
+// path tracer. Needs the `materials` array and the `material_textures`.
+
+const PBR_PI: f32 = 3.14159265359;
+
+// A material at a hit, with its textures
+struct PbrSurface {
+	albedo: vec3f,
+	metallic: f32,
+	roughness: f32,
+	// With the normal map
+	normal: vec3f,
+}
+
+fn pbr_material(material_id: u32) -> Material {
+	if material_id >= arrayLength(&materials) {
+		return materials[0];
+	}
+	return materials[material_id];
+}
+
+fn pbr_surface(intersection: Intersection) -> PbrSurface {
+	let material = pbr_material(intersection.object.material_id);
+	let uv = intersection.uv;
+	
+	// The untextured materials sample the placeholders, see MaterialLibrary
+	let albedo = pbr_srgb_to_linear(pbr_sample_texture(material.albedo_texture, uv).rgb);
+	let metallic_roughness = pbr_sample_texture(material.metallic_roughness_texture, uv);
+	let normal = pbr_sample_texture(material.normal_texture, uv).xyz;
+	
+	var surface: PbrSurface;
+	surface.albedo = material.base_color * intersection.object.color * albedo;
+	surface.metallic = clamp(material.metallic * metallic_roughness.b, 0.0, 1.0);
+	// A perfectly smooth surface would only light up in a single direction
+	surface.roughness = clamp(material.roughness * metallic_roughness.g, 0.04, 1.0);
+	surface.normal = pbr_normal(intersection, normal);
+	return surface;
+}
+
+fn pbr_sample_texture(layer: u32, uv: vec2f) -> vec4f {
+	// The compute shaders have no derivatives to pick a mip with
+	return textureSampleLevel(material_textures, material_sampler, uv, layer, 0.0);
+}
+
+// The normal map is in tangent space, the tangent going along +u and the
+// bitangent against +v (+Y is up in the image). The intersectors that don't
+// know the tangents keep their normal.
+fn pbr_normal(intersection: Intersection, texel: vec3f) -> vec3f {
+	let n = normalize(intersection.normal);
+	if intersection.tangent.w == 0.0 {
+		return n;
+	}
+	
+	// Gram-Schmidt, the interpolated tangent isn't quite perpendicular anymore
+	let t = normalize(intersection.tangent.xyz - n * dot(n, intersection.tangent.xyz));
+	let b = cross(n, t) * intersection.tangent.w;
+	let local = texel * 2.0 - 1.0;
+	return normalize(t * local.x + b * local.y + n * local.z);
+}
+
+// The textures are stored as plain unorm, see materials_shader()
+fn pbr_srgb_to_linear(srgb: vec3f) -> vec3f {
+	let low = srgb / 12.92;
+	let high = pow((srgb + 0.055) / 1.055, vec3f(2.4));
+	return select(high, low, srgb <= vec3f(0.04045));
+}
+
+fn pbr_fresnel_schlick(cos_theta: f32, f0: vec3f) -> vec3f {
+	return f0 + (vec3f(1.0) - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+fn pbr_distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+	let a2 = pow(roughness, 4.0);
+	let denom = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+	return a2 / (PBR_PI * denom * denom);
+}
+
+// Smith's method with Schlick-GGX, k remapped for direct lighting
+fn pbr_geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+	let k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+	let g_v = n_dot_v / (n_dot_v * (1.0 - k) + k);
+	let g_l = n_dot_l / (n_dot_l * (1.0 - k) + k);
+	return g_v * g_l;
+}
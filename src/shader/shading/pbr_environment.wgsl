@@ -0,0 +1,42 @@
+#include "/environment/equirect.wgsl"
+
+// The ambient light of the PBR shading, from the prefiltered environment maps
+// with the split sum approximation
+
+fn pbr_ambient(n: vec3f, v: vec3f, albedo: vec3f, metallic: f32, roughness: f32, f0: vec3f) -> vec3f {
+	let n_dot_v = max(dot(n, v), 0.0001);
+	let r = reflect(-v, n);
+	
+	// One mip per roughness, from 0 to 1
+	let max_lod = f32(textureNumLevels(environment_prefiltered) - 1u);
+	let prefiltered = textureSampleLevel(
+		environment_prefiltered,
+		environment_prefiltered_sampler,
+		equirect_uv(r),
+		roughness * max_lod
+	).rgb;
+	let irradiance = textureSampleLevel(
+		environment_irradiance,
+		environment_irradiance_sampler,
+		equirect_uv(n),
+		0.0
+	).rgb;
+	
+	let specular = pbr_environment_brdf(f0, roughness, n_dot_v);
+	let diffuse = (vec3f(1.0) - specular) * (1.0 - metallic) * albedo;
+	
+	return diffuse * irradiance + specular * prefiltered;
+}
+
+// An analytical fit of the second half of the split sum, instead of a lookup
+// texture ("Physically Based Shading on Mobile", Karis 2014)
+fn pbr_environment_brdf(f0: vec3f, roughness: f32, n_dot_v: f32) -> vec3f {
+	let c0 = vec4f(-1.0, -0.0275, -0.572, 0.022);
+	let c1 = vec4f(1.0, 0.0425, 1.04, -0.04);
+	
+	let r = roughness * c0 + c1;
+	let a004 = min(r.x * r.x, exp2(-9.28 * n_dot_v)) * r.x + r.y;
+	let ab = vec2f(-1.04, 1.04) * a004 + r.zw;
+	
+	return f0 * ab.x + ab.y;
+}
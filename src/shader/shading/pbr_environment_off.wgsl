@@ -0,0 +1,6 @@
+// Without an environment map, a constant ambient color that only the diffuse
+// part reflects
+
+fn pbr_ambient(n: vec3f, v: vec3f, albedo: vec3f, metallic: f32, roughness: f32, f0: vec3f) -> vec3f {
+	return ambient_color * albedo;
+}
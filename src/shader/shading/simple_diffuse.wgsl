@@ -1,14 +1,16 @@
-fn shade(intersection: Intersection) -> vec4f {
-	if !intersection.has_hit {
-		return vec4f(0.0, 0.6, 1.0, 1.0);
-	}
+#include "lights.wgsl"
 
+fn shade(intersection: Intersection) -> vec4f {
 	let object = intersection.object;
 
-	var diffuse = dot(intersection.normal, -sun_direction);
-	diffuse = max(diffuse, 0.0);
-	
-	let color = object.color * diffuse;
+	var color = vec3f(0.0);
+	for (var i = 0u; i < light_count(); i++) {
+		let light = sample_light(i, intersection.position);
+		
+		let diffuse = max(dot(intersection.normal, light.direction), 0.0);
+		let visibility = light_visibility(i, light, intersection.position, intersection.normal);
+		color += object.color * diffuse * light.radiance * visibility;
+	}
 	
 	return vec4f(color, 1.0);
-}
\ No newline at end of file
+}
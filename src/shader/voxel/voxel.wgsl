@@ -0,0 +1,104 @@
+
+// Nudges the entry point inside the first voxel
+const VOXEL_EPSILON: f32 = 0.0001;
+
+
+fn intersect_scene(ray_origin: vec3f, ray_dir: vec3f) -> Intersection {
+	let object = Object(vec3f(0), 0u);
+	var intersection = Intersection(false, object, camera.z_far, vec3f(0), vec3f(0), -ray_dir, vec2f(0), vec4f(0), 0u);
+	
+	// Everything below is in voxels, with the grid going from 0 to its size
+	let origin = (ray_origin - voxel_grid.origin) / voxel_grid.voxel_size;
+	let size = vec3f(voxel_grid.size);
+	let inv_dir = 1.0 / ray_dir;
+	let dir_sign = sign(ray_dir);
+	
+	let t1 = -origin * inv_dir;
+	let t2 = (size - origin) * inv_dir;
+	let t_min = min(t1, t2);
+	let t_max = max(t1, t2);
+	let t_near = max(t_min.x, max(t_min.y, t_min.z));
+	let t_far = min(t_max.x, min(t_max.y, t_max.z));
+	
+	if (t_near > t_far || t_far < 0.0) {
+		return intersection;
+	}
+	
+	// The ray enters the grid through the face of the furthest slab, unless it
+	// starts inside
+	var t = max(t_near, 0.0);
+	var normal = -ray_dir;
+	if (t_near > 0.0) {
+		if (t_near == t_min.x) {
+			normal = vec3f(-dir_sign.x, 0.0, 0.0);
+		} else if (t_near == t_min.y) {
+			normal = vec3f(0.0, -dir_sign.y, 0.0);
+		} else {
+			normal = vec3f(0.0, 0.0, -dir_sign.z);
+		}
+	}
+	
+	let entry = origin + ray_dir * (t + VOXEL_EPSILON);
+	var cell = clamp(vec3i(floor(entry)), vec3i(0), vec3i(voxel_grid.size) - 1);
+	
+	// Amanatides & Woo: how far along the ray the next boundary of each axis is,
+	// and how far apart the boundaries are
+	let step = vec3i(dir_sign);
+	let delta = abs(inv_dir);
+	var side_dist = (dir_sign * (vec3f(cell) - origin) + dir_sign * 0.5 + 0.5) * delta;
+	
+	// A ray can't cross more voxels than this
+	let max_steps = voxel_grid.size.x + voxel_grid.size.y + voxel_grid.size.z;
+	
+	for (var i = 0u; i < max_steps; i++) {
+		let voxel = textureLoad(voxels, cell);
+		
+		if (voxel.a > 0.0) {
+			let distance = t * voxel_grid.voxel_size;
+			if (distance >= camera.z_far) {
+				break;
+			}
+			
+			intersection.has_hit = true;
+			intersection.object = Object(voxel.rgb, 0u);
+			intersection.distance = distance;
+			intersection.position = ray_origin + ray_dir * distance;
+			intersection.normal = normal;
+			// The voxel, x first
+			let grid = vec3u(voxel_grid.size);
+			let index = vec3u(cell);
+			intersection.id = (index.z * grid.y + index.y) * grid.x + index.x + 1u;
+			break;
+		}
+		
+		// Step into the neighbour across the closest boundary
+		if (side_dist.x < side_dist.y && side_dist.x < side_dist.z) {
+			t = side_dist.x;
+			side_dist.x += delta.x;
+			cell.x += step.x;
+			normal = vec3f(-dir_sign.x, 0.0, 0.0);
+		} else if (side_dist.y < side_dist.z) {
+			t = side_dist.y;
+			side_dist.y += delta.y;
+			cell.y += step.y;
+			normal = vec3f(0.0, -dir_sign.y, 0.0);
+		} else {
+			t = side_dist.z;
+			side_dist.z += delta.z;
+			cell.z += step.z;
+			normal = vec3f(0.0, 0.0, -dir_sign.z);
+		}
+		
+		if (any(cell < vec3i(0)) || any(cell >= vec3i(voxel_grid.size))) {
+			break;
+		}
+	}
+	
+	return intersection;
+}
+
+// Whether anything is between the origin and max_t, for the shadow rays
+fn occluded(ray_origin: vec3f, ray_dir: vec3f, max_t: f32) -> bool {
+	let intersection = intersect_scene(ray_origin, ray_dir);
+	return intersection.has_hit && intersection.distance < max_t;
+}
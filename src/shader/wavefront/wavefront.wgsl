@@ -0,0 +1,140 @@
+#include "/mpr_shade.wgsl"
+
+
+// What's in wavefront_queues: the length of the ray and hit queues, then the
+// indirect args of the extend and shade stages
+const WAVEFRONT_RAY_COUNT: u32 = 0u;
+const WAVEFRONT_HIT_COUNT: u32 = 1u;
+const WAVEFRONT_EXTEND_ARGS: u32 = 2u;
+const WAVEFRONT_SHADE_ARGS: u32 = 5u;
+
+
+@compute
+@workgroup_size(1, 1, 1)
+fn wavefront_reset() {
+	atomicStore(&wavefront_queues[WAVEFRONT_RAY_COUNT], 0u);
+	atomicStore(&wavefront_queues[WAVEFRONT_HIT_COUNT], 0u);
+}
+
+@compute
+@workgroup_size(8, 8, 1)
+fn wavefront_generate(@builtin(global_invocation_id) gid: vec3u) {
+	let resolution = textureDimensions(output_color);
+	
+	if gid.x >= resolution.x || gid.y >= resolution.y {
+		return;
+	}
+	
+	// The queues only fit the resolution the renderer was made for
+	let pixel = gid.y * resolution.x + gid.x;
+	if pixel >= WAVEFRONT_CAPACITY {
+		return;
+	}
+	
+	let ray = camera_ray(gid.xy, resolution);
+	let index = atomicAdd(&wavefront_queues[WAVEFRONT_RAY_COUNT], 1u);
+	wavefront_rays[index] = WavefrontRay(ray.origin, pixel, ray.direction);
+}
+
+@compute
+@workgroup_size(1, 1, 1)
+fn wavefront_prepare_extend() {
+	wavefront_write_args(WAVEFRONT_EXTEND_ARGS, atomicLoad(&wavefront_queues[WAVEFRONT_RAY_COUNT]));
+}
+
+@compute
+@workgroup_size(WAVEFRONT_WORKGROUP_SIZE, 1, 1)
+fn wavefront_extend(@builtin(global_invocation_id) gid: vec3u) {
+	if gid.x >= atomicLoad(&wavefront_queues[WAVEFRONT_RAY_COUNT]) {
+		return;
+	}
+	
+	let ray = wavefront_rays[gid.x];
+	gpu_assert_pixel = wavefront_pixel_coord(ray.pixel);
+	
+	let intersection = intersect_scene(ray.origin, ray.direction);
+	mpr_store_debug_output(gpu_assert_pixel);
+	
+	// The misses are queued too, they get the environment in the shade stage
+	let index = atomicAdd(&wavefront_queues[WAVEFRONT_HIT_COUNT], 1u);
+	wavefront_hits[index] = WavefrontHit(
+		intersection.position,
+		intersection.distance,
+		intersection.normal,
+		intersection.object.material_id,
+		intersection.object.color,
+		ray.pixel,
+		intersection.outgoing,
+		u32(intersection.has_hit),
+		intersection.tangent,
+		intersection.uv,
+		intersection.id,
+	);
+}
+
+@compute
+@workgroup_size(1, 1, 1)
+fn wavefront_prepare_shade() {
+	wavefront_write_args(WAVEFRONT_SHADE_ARGS, atomicLoad(&wavefront_queues[WAVEFRONT_HIT_COUNT]));
+}
+
+@compute
+@workgroup_size(WAVEFRONT_WORKGROUP_SIZE, 1, 1)
+fn wavefront_shade(@builtin(global_invocation_id) gid: vec3u) {
+	if gid.x >= atomicLoad(&wavefront_queues[WAVEFRONT_HIT_COUNT]) {
+		return;
+	}
+	
+	let hit = wavefront_hits[gid.x];
+	gpu_assert_pixel = wavefront_pixel_coord(hit.pixel);
+	shading_pixel = gpu_assert_pixel;
+	
+	let object = Object(hit.color, hit.material_id);
+	let intersection = Intersection(
+		hit.has_hit != 0u,
+		object,
+		hit.distance,
+		hit.position,
+		hit.normal,
+		hit.outgoing,
+		hit.uv,
+		hit.tangent,
+		hit.id,
+	);
+	
+	wavefront_pixels[hit.pixel] = WavefrontPixel(shade_occluded(intersection), hit.normal, hit.distance, hit.id);
+}
+
+// Enough workgroups for every element of a queue
+fn wavefront_write_args(args: u32, count: u32) {
+	atomicStore(&wavefront_queues[args], (count + WAVEFRONT_WORKGROUP_SIZE - 1u) / WAVEFRONT_WORKGROUP_SIZE);
+	atomicStore(&wavefront_queues[args + 1u], 1u);
+	atomicStore(&wavefront_queues[args + 2u], 1u);
+}
+
+fn wavefront_pixel_coord(pixel: u32) -> vec2u {
+	let width = textureDimensions(output_color).x;
+	return vec2u(pixel % width, pixel / width);
+}
+
+// The main pass, after all the stages
+fn render_pixel(pixel_coord: vec2u, pixel_size: vec2u) {
+	let pixel_index = pixel_coord.y * pixel_size.x + pixel_coord.x;
+	if pixel_index >= WAVEFRONT_CAPACITY {
+		return;
+	}
+	
+	let ray = camera_ray(pixel_coord, pixel_size);
+	let pixel = wavefront_pixels[pixel_index];
+	
+	var color = composite_reference_grid(ray.origin, ray.direction, pixel_size, pixel.distance, pixel.color);
+	color = post_processing_pipeline(ray.coord, color);
+	
+	let depth = vec4f(vec3f(pixel.distance / camera.z_far), 1.0);
+	let normal = vec4f(pixel.normal, 1.0) * 0.5 + vec4f(0.5);
+	
+	textureStore(output_color, pixel_coord, color);
+	textureStore(output_depth, pixel_coord, depth);
+	textureStore(output_normal, pixel_coord, normal);
+	textureStore(output_id, pixel_coord, vec4u(pixel.id));
+}
@@ -0,0 +1,92 @@
+use brainrot::{
+	bevy::App,
+	vek::{Mat4, Rgb, Vec3},
+};
+use pbr_tracer::core::{
+	camera::ActiveCamera,
+	gameloop::PreRender,
+	rendering::{
+		accumulation::{Accumulation, AccumulationPlugin},
+		camera_view::CameraView,
+		depth_of_field::DofSettings,
+		lights::{Light, Lights},
+	},
+};
+
+fn frames(app: &mut App, count: u32) -> u32 {
+	for _ in 0..count {
+		app.world.run_schedule(PreRender);
+	}
+	app.world.resource::<Accumulation>().samples()
+}
+
+#[test]
+fn counts_frames_until_something_changes() {
+	let mut app = App::new();
+	app.add_plugin(AccumulationPlugin);
+	app.world.insert_resource(Lights(vec![]));
+
+	assert_eq!(frames(&mut app, 3), 3);
+
+	app.world
+		.resource_mut::<Lights>()
+		.push(Light::directional(-Vec3::unit_y(), Rgb::one(), 1.0));
+	assert_eq!(frames(&mut app, 1), 1);
+	assert_eq!(frames(&mut app, 2), 3);
+
+	app.world.resource_mut::<Accumulation>().reset();
+	assert_eq!(frames(&mut app, 1), 1);
+}
+
+#[test]
+fn starts_over_when_the_depth_of_field_changes() {
+	let mut app = App::new();
+	app.add_plugin(AccumulationPlugin);
+	let dof = app.world.spawn(DofSettings::default()).id();
+
+	assert_eq!(frames(&mut app, 3), 3);
+
+	app.world.get_mut::<DofSettings>(dof).unwrap().focus_distance = 2.0;
+	assert_eq!(frames(&mut app, 1), 1);
+	assert_eq!(frames(&mut app, 2), 3);
+}
+
+#[test]
+fn reprojects_when_only_the_camera_moves() {
+	let mut app = App::new();
+	app.add_plugin(AccumulationPlugin);
+	app.world.insert_resource(Lights(vec![]));
+	let camera = app.world.spawn((ActiveCamera, CameraView::default())).id();
+	let move_camera = |app: &mut App, x: f32| {
+		app.world.get_mut::<CameraView>(camera).unwrap().view_mat = Mat4::translation_3d(Vec3::new(x, 0.0, 0.0));
+	};
+
+	// Off by default
+	assert_eq!(frames(&mut app, 3), 3);
+	move_camera(&mut app, 1.0);
+	assert_eq!(frames(&mut app, 1), 1);
+	assert!(app.world.resource::<Accumulation>().reprojected_from().is_none());
+
+	app.world.resource_mut::<Accumulation>().reprojection.enabled = true;
+	assert_eq!(frames(&mut app, 2), 3);
+	move_camera(&mut app, 2.0);
+	assert_eq!(frames(&mut app, 1), 4);
+
+	let accumulation = app.world.resource::<Accumulation>();
+	let from = accumulation
+		.reprojected_from()
+		.expect("The history should follow the camera");
+	assert_eq!(from.view_mat, Mat4::translation_3d(Vec3::new(1.0, 0.0, 0.0)));
+
+	// Only for the frame after the move
+	assert_eq!(frames(&mut app, 1), 5);
+	assert!(app.world.resource::<Accumulation>().reprojected_from().is_none());
+
+	// The light changes everywhere, the history can't be kept
+	move_camera(&mut app, 3.0);
+	app.world
+		.resource_mut::<Lights>()
+		.push(Light::directional(-Vec3::unit_y(), Rgb::one(), 1.0));
+	assert_eq!(frames(&mut app, 1), 1);
+	assert!(app.world.resource::<Accumulation>().reprojected_from().is_none());
+}
@@ -0,0 +1,69 @@
+use brainrot::vek::{Vec3, Vec4};
+use pbr_tracer::fragments::{
+	intersector::{AnalyticIntersector, PrimitiveKind},
+	sdf::{SdfNode, SdfScene},
+};
+
+fn scene() -> SdfScene {
+	SdfScene::new(
+		SdfNode::sphere(1.0)
+			.translated(Vec3::new(2.0, 0.0, 0.0))
+			.material(3)
+			.union(SdfNode::cuboid(Vec3::broadcast(2.0)))
+			.union(SdfNode::torus(1.0, 0.25).scaled(2.0).material(4)),
+	)
+}
+
+#[test]
+fn unions_of_shapes_become_primitives() {
+	let intersector = AnalyticIntersector::from_sdf_scene(&scene(), true);
+	let [sphere, cuboid] = intersector.primitives.as_slice() else {
+		panic!("Expected 2 primitives, got {}", intersector.primitives.len());
+	};
+
+	assert_eq!(sphere.kind, PrimitiveKind::Sphere as u32);
+	assert_eq!(sphere.material_id, 3);
+	// The edge of the sphere is on the edge of the unit sphere
+	assert_eq!(
+		sphere.inverse_transform * Vec4::new(3.0, 0.0, 0.0, 1.0),
+		Vec4::new(1.0, 0.0, 0.0, 1.0)
+	);
+
+	assert_eq!(cuboid.kind, PrimitiveKind::Box as u32);
+	assert_eq!(cuboid.material_id, 0);
+	assert_eq!(
+		cuboid.inverse_transform * Vec4::new(1.0, 1.0, 1.0, 1.0),
+		Vec4::new(1.0, 1.0, 1.0, 1.0)
+	);
+}
+
+#[test]
+fn the_rest_is_marched_in_hybrid_mode() {
+	let fallback = AnalyticIntersector::from_sdf_scene(&scene(), true)
+		.fallback
+		.expect("The torus has no analytic form");
+	let wgsl = fallback.scene.to_wgsl();
+
+	assert!(wgsl.contains("torus("));
+	assert!(!wgsl.contains("sphere("));
+	assert!(!wgsl.contains("bbox("));
+	// Still scaled and with its material
+	assert!(wgsl.contains("* 2.0"));
+	assert!(wgsl.contains("4u"));
+}
+
+#[test]
+fn the_rest_is_left_out_otherwise() {
+	let intersector = AnalyticIntersector::from_sdf_scene(&scene(), false);
+
+	assert!(intersector.fallback.is_none());
+	assert_eq!(intersector.primitives.len(), 2);
+}
+
+#[test]
+fn scenes_of_shapes_need_no_fallback() {
+	let intersector = AnalyticIntersector::from_sdf_scene(&SdfScene::default(), true);
+
+	assert!(intersector.fallback.is_none());
+	assert_eq!(intersector.primitives.len(), 2);
+}
@@ -0,0 +1,138 @@
+use brainrot::vek::{Extent2, Rect};
+use pbr_tracer::libs::atlas::ShelfPacker;
+
+fn overlaps(a: Rect<u32, u32>, b: Rect<u32, u32>) -> bool {
+	a.x < b.x + b.w && b.x < a.x + a.w && a.y < b.y + b.h && b.y < a.y + a.h
+}
+
+/// No two rects overlap once grown by the padding, and all of them fit
+fn assert_packed(packer: &ShelfPacker, rects: &[Rect<u32, u32>], padding: u32) {
+	let size = packer.size();
+	let padded = |rect: Rect<u32, u32>| {
+		Rect::new(
+			rect.x - padding,
+			rect.y - padding,
+			rect.w + padding * 2,
+			rect.h + padding * 2,
+		)
+	};
+
+	for (i, &a) in rects.iter().enumerate() {
+		assert!(a.x >= padding && a.y >= padding, "{:?} is in the padding", a);
+		assert!(
+			a.x + a.w + padding <= size.w && a.y + a.h + padding <= size.h,
+			"{:?} is out of bounds",
+			a
+		);
+
+		for &b in &rects[i + 1..] {
+			assert!(!overlaps(padded(a), padded(b)), "{:?} and {:?} overlap", a, b);
+		}
+	}
+}
+
+#[test]
+fn fills_up_without_overlapping() {
+	let mut packer = ShelfPacker::new(Extent2::new(64, 64), 1);
+
+	let mut rects = Vec::new();
+	while let Some(rect) = packer.insert(Extent2::new(14, 14)) {
+		rects.push(rect);
+		assert!(rects.len() <= 16, "More rects than fit");
+	}
+
+	// 16 texels per padded rect, 4 per row and 4 rows
+	assert_eq!(rects.len(), 16);
+	assert_packed(&packer, &rects, 1);
+	assert_eq!(packer.occupancy(), 1.0);
+
+	// Nothing is too small to fit
+	assert_eq!(packer.insert(Extent2::new(1, 1)), None);
+}
+
+#[test]
+fn too_big_never_fits() {
+	let mut packer = ShelfPacker::new(Extent2::new(32, 32), 0);
+	assert_eq!(packer.insert(Extent2::new(33, 1)), None);
+	assert_eq!(packer.insert(Extent2::new(1, 33)), None);
+	assert_eq!(packer.occupancy(), 0.0);
+
+	// The padding counts
+	let mut packer = ShelfPacker::new(Extent2::new(32, 32), 1);
+	assert_eq!(packer.insert(Extent2::new(31, 8)), None);
+}
+
+#[test]
+fn growing_keeps_everything_in_place() {
+	let mut packer = ShelfPacker::new(Extent2::new(32, 32), 0);
+
+	let mut rects = (0..4)
+		.map(|_| packer.insert(Extent2::new(16, 16)).unwrap())
+		.collect::<Vec<_>>();
+	assert_eq!(packer.insert(Extent2::new(16, 16)), None);
+
+	let before = packer.clone();
+	packer.grow(Extent2::new(64, 32));
+	assert_eq!(packer.size(), Extent2::new(64, 32));
+	assert_eq!(packer.occupancy(), before.occupancy());
+
+	// Wider, so the existing shelves have room again
+	let rect = packer.insert(Extent2::new(16, 16)).unwrap();
+	assert_eq!(rect, Rect::new(32, 0, 16, 16));
+	rects.push(rect);
+
+	packer.grow(Extent2::new(64, 64));
+	assert_eq!(packer.occupancy(), 0.5);
+	while let Some(rect) = packer.insert(Extent2::new(16, 16)) {
+		rects.push(rect);
+	}
+
+	assert_eq!(rects.len(), 16);
+	assert_packed(&packer, &rects, 0);
+}
+
+#[test]
+#[should_panic]
+fn cant_shrink() {
+	let mut packer = ShelfPacker::new(Extent2::new(32, 32), 0);
+	packer.grow(Extent2::new(64, 16));
+}
+
+#[test]
+fn small_rects_dont_fragment_tall_shelves() {
+	let mut packer = ShelfPacker::new(Extent2::new(64, 64), 0);
+
+	let tall = packer.insert(Extent2::new(8, 32)).unwrap();
+	assert_eq!(tall.y, 0);
+
+	// Would waste most of the tall shelf, so it gets its own while there's room
+	let small = packer.insert(Extent2::new(8, 8)).unwrap();
+	assert_eq!(small.y, 32);
+
+	// Close enough in height to share it
+	let medium = packer.insert(Extent2::new(8, 24)).unwrap();
+	assert_eq!((medium.x, medium.y), (8, 0));
+
+	// Picks the shelf that wastes the least height
+	let small = packer.insert(Extent2::new(8, 8)).unwrap();
+	assert_eq!((small.x, small.y), (8, 32));
+
+	assert_eq!(packer.occupancy(), 40.0 / 64.0);
+}
+
+#[test]
+fn tall_shelves_are_reused_once_full() {
+	let mut packer = ShelfPacker::new(Extent2::new(32, 32), 0);
+
+	let mut rects = vec![packer.insert(Extent2::new(8, 24)).unwrap()];
+	// A shelf of 8 fills the rest of the height
+	rects.extend((0..4).map(|_| packer.insert(Extent2::new(8, 8)).unwrap()));
+	assert!(rects[1..].iter().all(|rect| rect.y == 24));
+
+	// No room for a new shelf, so the small ones go into the tall one
+	rects.extend((0..3).map(|_| packer.insert(Extent2::new(8, 8)).unwrap()));
+	assert!(rects[5..].iter().all(|rect| rect.y == 0));
+
+	assert_eq!(packer.insert(Extent2::new(8, 8)), None);
+	assert_packed(&packer, &rects, 0);
+}
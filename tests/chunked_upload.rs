@@ -0,0 +1,158 @@
+#![cfg(feature = "gpu-tests")]
+
+use brainrot::{bevy::App, vek::Extent2};
+use pbr_tracer::{
+	core::{
+		gpu::{Gpu, GpuPlugin},
+		rendering::chunked_upload::{ChunkedUploader, UploadHandle},
+	},
+	libs::{
+		smart_arc::Sarc,
+		texture::{Tex, TexDescriptor, TextureAssetDimensions},
+	},
+};
+use wgpu::{
+	Buffer, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Maintain, MapMode, TextureAspect, TextureFormat,
+	TextureUsages,
+};
+
+const BUDGET: u64 = 1024;
+
+fn pattern(length: usize) -> Vec<u8> {
+	(0..length).map(|i| (i * 7 % 251) as u8).collect()
+}
+
+fn read_buffer(gpu: &Gpu, buffer: &Buffer) -> Vec<u8> {
+	let staging_buffer = gpu.device.create_buffer(&BufferDescriptor {
+		label: Some("Test readback buffer"),
+		size: buffer.size(),
+		usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+		mapped_at_creation: false,
+	});
+
+	let mut encoder = gpu
+		.device
+		.create_command_encoder(&CommandEncoderDescriptor { label: None });
+	encoder.copy_buffer_to_buffer(buffer, 0, &staging_buffer, 0, buffer.size());
+	gpu.queue.submit([encoder.finish()]);
+
+	let slice = staging_buffer.slice(..);
+	slice.map_async(MapMode::Read, |result| {
+		result.expect("Couldn't map the readback buffer")
+	});
+	gpu.device.poll(Maintain::Wait);
+
+	let bytes = slice.get_mapped_range().to_vec();
+	staging_buffer.unmap();
+	bytes
+}
+
+/// Advance until everything is written, returns how many frames it took
+fn run_to_completion(gpu: &Gpu, uploader: &mut ChunkedUploader) -> u32 {
+	let mut frames = 0;
+	let mut last_progress = 0.0;
+
+	while let Some(progress) = uploader.progress() {
+		assert!(progress >= last_progress, "The progress went back");
+		last_progress = progress;
+
+		let written = uploader.advance(gpu);
+		// A texture chunk is always at least one row, 256 bytes here
+		assert!(written > 0 && written <= BUDGET, "{} bytes in one frame", written);
+
+		frames += 1;
+		assert!(frames < 1000, "The uploads never finished");
+	}
+
+	frames
+}
+
+#[test]
+fn big_uploads_take_several_frames_and_arrive_whole() {
+	let mut app = App::new();
+	app.add_plugin(GpuPlugin);
+	let gpu = app.world.resource::<Gpu>();
+
+	let mut uploader = ChunkedUploader::new(BUDGET, 256);
+
+	let buffer = Sarc::new(gpu.device.create_buffer(&BufferDescriptor {
+		label: Some("Test buffer"),
+		size: 10 * 1024,
+		usage: BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+		mapped_at_creation: false,
+	}));
+	let buffer_bytes = pattern(10 * 1024);
+	let buffer_upload = uploader.upload_buffer(&buffer, 0, buffer_bytes.clone());
+
+	// 64 texels of 4 bytes per row
+	let tex = Sarc::new(Tex::create(
+		gpu,
+		TexDescriptor {
+			label: "Test texture",
+			dimensions: TextureAssetDimensions::D2(Extent2::new(64, 48)),
+			format: TextureFormat::Rgba8Unorm,
+			usage: Some(TextureUsages::COPY_SRC),
+			aspect: TextureAspect::All,
+		},
+		None,
+	));
+	let tex_bytes = pattern(64 * 48 * 4);
+	let tex_upload = uploader.upload_texture(&tex, 0, tex_bytes.clone());
+
+	let uploads = [buffer_upload.clone(), tex_upload.clone()];
+	assert_eq!(UploadHandle::progress_of(&uploads), 0.0);
+
+	let frames = run_to_completion(gpu, &mut uploader);
+
+	let total = (buffer_bytes.len() + tex_bytes.len()) as u32;
+	assert_eq!(frames, total.div_ceil(BUDGET as u32));
+	assert!(UploadHandle::all_done(&uploads));
+	assert_eq!(UploadHandle::progress_of(&uploads), 1.0);
+
+	assert!(read_buffer(gpu, &buffer) == buffer_bytes, "The buffer doesn't match");
+	assert!(tex.read_bytes(gpu) == tex_bytes, "The texture doesn't match");
+}
+
+#[test]
+fn one_frame_is_one_budget() {
+	let mut app = App::new();
+	app.add_plugin(GpuPlugin);
+	let gpu = app.world.resource::<Gpu>();
+
+	let mut uploader = ChunkedUploader::new(BUDGET, 256);
+	let buffer = Sarc::new(gpu.device.create_buffer(&BufferDescriptor {
+		label: Some("Test buffer"),
+		size: 4 * BUDGET,
+		usage: BufferUsages::COPY_DST,
+		mapped_at_creation: false,
+	}));
+	let upload = uploader.upload_buffer(&buffer, 0, pattern(4 * BUDGET as usize));
+
+	assert_eq!(uploader.advance(gpu), BUDGET);
+	assert_eq!(upload.written(), BUDGET);
+	assert!(!upload.is_done());
+	assert_eq!(uploader.progress(), Some(0.25));
+}
+
+#[test]
+fn dropping_the_target_cancels_the_upload() {
+	let mut app = App::new();
+	app.add_plugin(GpuPlugin);
+	let gpu = app.world.resource::<Gpu>();
+
+	let mut uploader = ChunkedUploader::new(BUDGET, 256);
+	let buffer = Sarc::new(gpu.device.create_buffer(&BufferDescriptor {
+		label: Some("Test buffer"),
+		size: 4 * BUDGET,
+		usage: BufferUsages::COPY_DST,
+		mapped_at_creation: false,
+	}));
+	let upload = uploader.upload_buffer(&buffer, 0, pattern(4 * BUDGET as usize));
+
+	uploader.advance(gpu);
+	drop(buffer);
+
+	assert_eq!(uploader.advance(gpu), 0);
+	assert!(upload.is_done());
+	assert!(uploader.is_idle());
+}
@@ -0,0 +1,95 @@
+//! Helpers shared by the test binaries that need them
+
+use pbr_tracer::{
+	core::{
+		gpu::Gpu,
+		rendering::composite::{CompositeRenderer, Upscaler},
+	},
+	libs::buffer::ping_pong_texture::PingPongParity,
+};
+use wgpu::{
+	BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Extent3d, ImageCopyBuffer, ImageCopyTexture,
+	ImageDataLayout, Maintain, MapMode, Origin3d, Texture, TextureAspect, TextureDescriptor, TextureDimension,
+	TextureUsages, TextureViewDescriptor, COPY_BYTES_PER_ROW_ALIGNMENT,
+};
+
+/// Composite the current output into a texture the size of the window, and
+/// read it back. 4 bytes per pixel, in the window's format.
+pub fn composite_to_texture(gpu: &Gpu, composite_renderer: &CompositeRenderer, size: Extent3d) -> Vec<u8> {
+	let target = gpu.device.create_texture(&TextureDescriptor {
+		label: Some("Composite test target"),
+		size,
+		mip_level_count: 1,
+		sample_count: 1,
+		dimension: TextureDimension::D2,
+		format: composite_renderer.format(),
+		usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+		view_formats: &[],
+	});
+	let view = target.create_view(&TextureViewDescriptor::default());
+
+	let mut encoder = gpu
+		.device
+		.create_command_encoder(&CommandEncoderDescriptor { label: None });
+	// Nothing in the composite is double-buffered
+	composite_renderer.encode(&mut encoder, &view, Upscaler::Bilinear, PingPongParity::default(), None);
+	gpu.queue.submit([encoder.finish()]);
+
+	read_texture(gpu, &target)
+}
+
+pub fn read_texture(gpu: &Gpu, texture: &Texture) -> Vec<u8> {
+	let size = texture.size();
+	let bytes_per_row = size.width * 4;
+	let padded_bytes_per_row = bytes_per_row.div_ceil(COPY_BYTES_PER_ROW_ALIGNMENT) * COPY_BYTES_PER_ROW_ALIGNMENT;
+
+	let staging_buffer = gpu.device.create_buffer(&BufferDescriptor {
+		label: Some("Test readback buffer"),
+		size: (padded_bytes_per_row * size.height) as u64,
+		usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+		mapped_at_creation: false,
+	});
+
+	let mut encoder = gpu
+		.device
+		.create_command_encoder(&CommandEncoderDescriptor { label: None });
+	encoder.copy_texture_to_buffer(
+		ImageCopyTexture {
+			texture,
+			mip_level: 0,
+			origin: Origin3d::ZERO,
+			aspect: TextureAspect::All,
+		},
+		ImageCopyBuffer {
+			buffer: &staging_buffer,
+			layout: ImageDataLayout {
+				offset: 0,
+				bytes_per_row: Some(padded_bytes_per_row),
+				rows_per_image: Some(size.height),
+			},
+		},
+		size,
+	);
+	gpu.queue.submit([encoder.finish()]);
+
+	let slice = staging_buffer.slice(..);
+	slice.map_async(MapMode::Read, |result| {
+		result.expect("Couldn't map the readback buffer")
+	});
+	gpu.device.poll(Maintain::Wait);
+
+	let bytes = slice
+		.get_mapped_range()
+		.chunks_exact(padded_bytes_per_row as usize)
+		.flat_map(|row| row[..bytes_per_row as usize].to_vec())
+		.collect();
+	staging_buffer.unmap();
+	bytes
+}
+
+/// Whether red, green and blue are about the same, whichever order the window
+/// has them in
+pub fn is_gray(pixel: &[u8]) -> bool {
+	let (min, max) = (pixel[..3].iter().min().unwrap(), pixel[..3].iter().max().unwrap());
+	max - min <= 1
+}
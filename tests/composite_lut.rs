@@ -0,0 +1,175 @@
+#![cfg(feature = "gpu-tests")]
+
+mod common;
+
+use brainrot::{
+	bevy::App,
+	vek::{Extent3, Vec3},
+};
+use common::{composite_to_texture, is_gray};
+use pbr_tracer::{
+	core::{
+		display::DisplayPlugin,
+		gameloop,
+		gpu::Gpu,
+		render_target::RenderTarget,
+		rendering::composite::{CompositeFragment, CompositeRenderer, DebugView, DefaultComposite},
+	},
+	libs::{
+		buffer::sampled_texture_buffer::SampledTexture,
+		shader::{Shader, ShaderBuilder},
+		smart_arc::Sarc,
+		texture::{SamplerEdges, Tex, TexDescriptor, TexSamplerDescriptor, TextureAssetDimensions},
+	},
+};
+use wgpu::{Extent3d, FilterMode, TextureAspect, TextureFormat};
+
+const LUT_SIZE: u32 = 32;
+
+// The texel centers are the grid points of the LUT
+const LUT_COMPOSITE: &str = "
+fn composite(uv: vec2f) -> vec4f {
+	let color = clamp(composite_upscaled(uv).rgb, vec3f(0.0), vec3f(1.0));
+	let size = f32(textureDimensions(lut).x);
+	let coord = color * (size - 1.0) / size + 0.5 / size;
+	return vec4f(textureSampleLevel(lut, lut_sampler, coord, 0.0).rgb, 1.0);
+}
+";
+
+/// Color grading with a 3D LUT, bound as an extra sampled texture
+struct LutComposite {
+	lut: Sarc<Tex>,
+}
+
+impl CompositeFragment for LutComposite {
+	fn shader(&self) -> Shader {
+		ShaderBuilder::new()
+			.include(Shader::Source(LUT_COMPOSITE.into()))
+			.include_buffer(SampledTexture::FromTex {
+				texture_var_name: "lut",
+				sampler_var_name: "lut_sampler",
+				tex: self.lut.clone(),
+			})
+			.into()
+	}
+}
+
+/// Every color to its luminance
+fn grayscale_lut(gpu: &Gpu) -> Sarc<Tex> {
+	let lut = Tex::create(
+		gpu,
+		TexDescriptor {
+			label: "Grayscale LUT",
+			dimensions: TextureAssetDimensions::D3(Extent3::broadcast(LUT_SIZE)),
+			format: TextureFormat::Rgba8Unorm,
+			usage: None,
+			aspect: TextureAspect::All,
+		},
+		Some(TexSamplerDescriptor {
+			filter: FilterMode::Linear,
+			edges: SamplerEdges::ClampToEdge,
+			compare: None,
+		}),
+	);
+
+	// Red along x, green along y, blue along z
+	let texels = (0..LUT_SIZE.pow(3))
+		.flat_map(|i| {
+			let rgb = Vec3::new(i % LUT_SIZE, i / LUT_SIZE % LUT_SIZE, i / LUT_SIZE / LUT_SIZE)
+				.map(|x| x as f32 / (LUT_SIZE - 1) as f32);
+			let luminance = (rgb.dot(Vec3::new(0.2126, 0.7152, 0.0722)) * 255.0).round() as u8;
+			[luminance, luminance, luminance, 255]
+		})
+		.collect::<Vec<_>>();
+	lut.upload_texels(gpu, &texels);
+
+	Sarc::new(lut)
+}
+
+// winit only allows creating one event loop per process, so everything that
+// needs the default app has to happen in this one test
+#[test]
+fn a_custom_composite_applies_its_lut() {
+	let mut app = pbr_tracer::build_app(DisplayPlugin {
+		visible: false,
+		any_thread: true,
+		placement_path: None,
+	});
+	gameloop::run_frames(&mut app, 2).expect("The app should render frames without exiting");
+
+	let gpu = app.world.resource::<Gpu>();
+	let window_size = app.world.resource::<RenderTarget>().size;
+	let size = Extent3d {
+		width: window_size.w.max(1),
+		height: window_size.h.max(1),
+		depth_or_array_layers: 1,
+	};
+
+	let composite_renderer = app.world.resource::<CompositeRenderer>();
+
+	// The default scene has some color in it, so that the LUT has something to do
+	let default = composite_renderer.with_composite(gpu, window_size, &DefaultComposite);
+	let pixels = composite_to_texture(gpu, &default, size);
+	assert!(
+		!pixels.chunks_exact(4).all(is_gray),
+		"The default composite should show the colors of the scene"
+	);
+
+	let graded = composite_renderer.with_composite(
+		gpu,
+		window_size,
+		&LutComposite {
+			lut: grayscale_lut(gpu),
+		},
+	);
+	let pixels = composite_to_texture(gpu, &graded, size);
+	assert!(
+		pixels.chunks_exact(4).all(is_gray),
+		"Every pixel should have gone through the grayscale LUT"
+	);
+	assert!(
+		pixels.chunks_exact(4).any(|pixel| pixel[0] > 0),
+		"The LUT shouldn't make everything black"
+	);
+
+	debug_views_draw_the_other_outputs(&mut app, size);
+}
+
+fn debug_views_draw_the_other_outputs(app: &mut App, size: Extent3d) {
+	let composite_renderer = app.world.resource::<CompositeRenderer>();
+	assert!(DebugView::ALL
+		.into_iter()
+		.filter(|view| *view != DebugView::Steps)
+		.all(|view| composite_renderer.supports(view)));
+	assert!(
+		!composite_renderer.supports(DebugView::Steps),
+		"The default raymarcher has no step heatmap"
+	);
+
+	// The params are synced and uploaded with the next frame
+	let mut draw = |view| {
+		app.world.insert_resource(view);
+		gameloop::run_frames(app, 1).expect("The app should render the debug view");
+		composite_to_texture(
+			app.world.resource::<Gpu>(),
+			app.world.resource::<CompositeRenderer>(),
+			size,
+		)
+	};
+
+	let depth = draw(DebugView::Depth);
+	assert!(depth.chunks_exact(4).all(is_gray), "The depth should be grayscale");
+	assert!(
+		depth.chunks_exact(4).any(|pixel| pixel[0] > 0),
+		"The depth shouldn't be all black"
+	);
+
+	let normal = draw(DebugView::Normal);
+	assert!(
+		!normal.chunks_exact(4).all(is_gray),
+		"The normals of the scene should point in different directions"
+	);
+
+	let heatmap = draw(DebugView::Heatmap);
+	assert_ne!(heatmap, normal);
+}
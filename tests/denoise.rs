@@ -0,0 +1,86 @@
+#![cfg(feature = "gpu-tests")]
+
+use bevy_ecs::world::World;
+use brainrot::bevy::App;
+use pbr_tracer::{
+	core::{
+		display::DisplayPlugin,
+		gameloop,
+		gpu::Gpu,
+		params,
+		rendering::{
+			composite::CompositeRenderer,
+			compute::{self, ComputeRenderer},
+			denoise::{DenoiseSettings, Denoiser},
+		},
+		size::Resolution,
+	},
+	libs::{smart_arc::Sarc, texture::Tex},
+};
+
+fn denoiser_output(world: &mut World) -> Sarc<Tex> {
+	let settings = *world.query::<&DenoiseSettings>().single(world);
+	world
+		.resource::<Denoiser>()
+		.output(&settings)
+		.expect("The denoiser should have an output")
+		.clone()
+}
+
+// winit only allows creating one event loop per process, so everything that
+// needs the app has to happen in this one test
+#[test]
+fn composite_draws_the_denoised_output_once_enabled() {
+	let mut app = pbr_tracer::build_app(DisplayPlugin {
+		visible: false,
+		any_thread: true,
+		placement_path: None,
+	});
+	gameloop::run_frames(&mut app, 2).expect("The app should render frames without exiting");
+
+	assert!(
+		app.world.resource::<Denoiser>().is_available(),
+		"The default renderer outputs its normals and depth"
+	);
+
+	// Off by default, the composite draws the output as it is
+	let raw = app.world.resource::<ComputeRenderer>().output_textures[0].clone();
+	assert_eq!(app.world.resource::<CompositeRenderer>().output_texture(), &raw);
+
+	params::set_param(&mut app.world, "denoise.enabled", "true").unwrap();
+	let denoised = denoiser_output(&mut app.world);
+	assert_ne!(denoised, raw);
+	assert_eq!(app.world.resource::<CompositeRenderer>().output_texture(), &denoised);
+
+	// The last iteration writes to the other texture
+	params::set_param(&mut app.world, "denoise.iterations", "3").unwrap();
+	let fewer = denoiser_output(&mut app.world);
+	assert_ne!(fewer, denoised);
+	assert_eq!(app.world.resource::<CompositeRenderer>().output_texture(), &fewer);
+
+	gameloop::run_frames(&mut app, 2).expect("The app should render denoised frames");
+
+	let bytes = fewer.read_bytes(app.world.resource::<Gpu>());
+	let texels = bytemuck::cast_slice::<u8, f32>(&bytes);
+	assert!(texels.iter().all(|x| x.is_finite()), "The filter shouldn't divide by 0");
+	assert!(
+		texels.iter().any(|x| *x > 0.0),
+		"The filter shouldn't make everything black"
+	);
+
+	a_new_renderer_stays_denoised(&mut app, fewer);
+}
+
+fn a_new_renderer_stays_denoised(app: &mut App, before: Sarc<Tex>) {
+	let renderer = app.world.resource::<ComputeRenderer>();
+	let resolution = Resolution(renderer.resolution().map(|x| x / 2));
+	let renderer = renderer.resized(app.world.resource::<Gpu>(), resolution).unwrap();
+	compute::swap_compute_renderer(&mut app.world, renderer);
+
+	let after = denoiser_output(&mut app.world);
+	assert_ne!(after, before, "The denoiser should follow the new output textures");
+	assert_eq!(app.world.resource::<CompositeRenderer>().output_texture(), &after);
+	assert_eq!(after.size().width, resolution.w);
+
+	gameloop::run_frames(app, 2).expect("The app should render after the swap");
+}
@@ -0,0 +1,64 @@
+use bevy_ecs::{
+	component::Component,
+	entity::Entity,
+	system::{Query, RunSystemOnce},
+	world::World,
+};
+use pbr_tracer::{
+	core::entity_label::{self, LabelError, Labeled},
+	EntityLabel,
+};
+
+/// A label defined outside of the crate, like an app would for its own
+/// special entity
+#[derive(Component)]
+struct Sun;
+impl EntityLabel for Sun {}
+
+#[derive(Component, Copy, Clone, Debug, PartialEq)]
+struct Brightness(f32);
+
+/// How an app's system would reach the components of its labeled entity
+fn sun_brightness(sun: Labeled<Sun>, brightnesses: Query<&Brightness>) -> Result<f32, LabelError> {
+	let sun = sun.entity()?;
+	Ok(brightnesses.get(sun).map_or(0.0, |brightness| brightness.0))
+}
+
+fn sun_entity(sun: Labeled<Sun>) -> Result<Entity, LabelError> {
+	sun.entity()
+}
+
+#[test]
+fn finds_the_only_labeled_entity() {
+	let mut world = World::new();
+	world.spawn(Brightness(0.5));
+	let sun = world.spawn((Sun, Brightness(2.0))).id();
+
+	assert_eq!(entity_label::single_entity::<Sun>(&mut world), Ok(sun));
+	assert_eq!(world.run_system_once(sun_entity), Ok(sun));
+	assert_eq!(world.run_system_once(sun_brightness), Ok(2.0));
+}
+
+#[test]
+fn names_the_label_when_there_is_none() {
+	let mut world = World::new();
+	world.spawn(Brightness(0.5));
+
+	let error = entity_label::single_entity::<Sun>(&mut world).unwrap_err();
+	assert_eq!(error, LabelError::NotFound { label: "Sun" });
+	assert_eq!(error.to_string(), "Expected exactly one `Sun` but found none");
+
+	assert_eq!(world.run_system_once(sun_entity), Err(error));
+}
+
+#[test]
+fn counts_the_labeled_entities_when_there_are_several() {
+	let mut world = World::new();
+	world.spawn_batch([Sun, Sun, Sun]);
+
+	let error = entity_label::single_entity::<Sun>(&mut world).unwrap_err();
+	assert_eq!(error, LabelError::Multiple { label: "Sun", count: 3 });
+	assert_eq!(error.to_string(), "Expected exactly one `Sun` but found 3");
+
+	assert_eq!(world.run_system_once(sun_entity), Err(error));
+}
@@ -0,0 +1,176 @@
+#![cfg(feature = "gpu-tests")]
+
+use std::f32::consts::PI;
+
+use brainrot::bevy::App;
+use image::{Rgba, Rgba32FImage};
+use pbr_tracer::{
+	core::{
+		gpu::{Gpu, GpuPlugin},
+		rendering::{
+			chunked_upload::ChunkedUploader,
+			environment::{EnvironmentMaps, EnvironmentPrefilter},
+		},
+	},
+	libs::buffer::ping_pong_texture::PingPongParity,
+};
+
+/// Brighter towards +y: 1 + cos(theta), with the rows going from +y to -y
+fn gradient_environment() -> Rgba32FImage {
+	Rgba32FImage::from_fn(64, 32, |_, y| {
+		let theta = (y as f32 + 0.5) / 32.0 * PI;
+		let value = 1.0 + theta.cos();
+		Rgba([value, value, value, 1.0])
+	})
+}
+
+/// The red channel of every texel of an Rgba16Float readback
+fn red_channel(bytes: &[u8]) -> Vec<f32> {
+	bytes
+		.chunks_exact(8)
+		.map(|texel| f16_to_f32(u16::from_le_bytes([texel[0], texel[1]])))
+		.collect()
+}
+
+fn f16_to_f32(bits: u16) -> f32 {
+	let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+	let exponent = ((bits >> 10) & 0x1f) as i32;
+	let mantissa = (bits & 0x3ff) as f32;
+
+	match exponent {
+		0 => sign * mantissa * 2f32.powi(-24),
+		0x1f => f32::NAN,
+		_ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+	}
+}
+
+/// The upload is done in a single frame
+fn uploader() -> ChunkedUploader {
+	ChunkedUploader::new(u64::MAX, u64::MAX)
+}
+
+fn run_to_completion(
+	gpu: &Gpu,
+	uploader: &mut ChunkedUploader,
+	prefilter: &mut EnvironmentPrefilter,
+	maps: &EnvironmentMaps,
+) {
+	let mut frames = 0;
+	while prefilter.is_running() {
+		uploader.advance(gpu);
+		let commands = prefilter
+			.advance(gpu, maps, PingPongParity::default())
+			.expect("A running job should encode something once uploaded");
+		gpu.queue.submit([commands]);
+
+		frames += 1;
+		assert!(frames < 1000, "The prefiltering never finished");
+	}
+}
+
+#[test]
+fn irradiance_matches_the_cosine_convolution() {
+	let mut app = App::new();
+	app.add_plugin(GpuPlugin);
+	let gpu = app.world.resource::<Gpu>();
+
+	let maps = EnvironmentMaps::new(gpu, "Test environment");
+	let mut uploader = uploader();
+	let mut prefilter = EnvironmentPrefilter::new(512, 8);
+	prefilter
+		.load(gpu, &mut uploader, "gradient", gradient_environment())
+		.unwrap();
+
+	// Nothing to prefilter before the upload
+	assert!(prefilter.is_uploading());
+	assert!(prefilter.advance(gpu, &maps, PingPongParity::default()).is_none());
+	uploader.advance(gpu);
+	assert!(!prefilter.is_uploading());
+
+	// Only the first chunk, the maps shouldn't see any of it yet
+	gpu.queue
+		.submit([prefilter.advance(gpu, &maps, PingPongParity::default()).unwrap()]);
+	assert!(red_channel(&maps.irradiance.read_bytes(gpu))
+		.iter()
+		.all(|value| *value == 0.0));
+
+	run_to_completion(gpu, &mut uploader, &mut prefilter, &maps);
+	assert_eq!(prefilter.progress(), None);
+
+	// Convolving 1 + d.y with the clamped cosine around n gives 1 + 2/3 n.y
+	let size = EnvironmentMaps::IRRADIANCE_SIZE;
+	let irradiance = red_channel(&maps.irradiance.read_bytes(gpu));
+	for (i, value) in irradiance.iter().enumerate() {
+		let row = i as u32 / size.w;
+		let theta = (row as f32 + 0.5) / size.h as f32 * PI;
+		let expected = 1.0 + 2.0 / 3.0 * theta.cos();
+
+		assert!(
+			(value - expected).abs() < 0.05 * expected,
+			"Row {}: got {}, expected {}",
+			row,
+			value,
+			expected
+		);
+	}
+
+	// The mirror mip is the environment itself
+	let size = EnvironmentMaps::PREFILTERED_SIZE;
+	let mirror = red_channel(&maps.prefiltered.read_bytes_mip(gpu, 0));
+	for (i, value) in mirror.iter().enumerate() {
+		let row = i as u32 / size.w;
+		let theta = (row as f32 + 0.5) / size.h as f32 * PI;
+		let expected = 1.0 + theta.cos();
+
+		assert!(
+			(value - expected).abs() < 0.05 + 0.05 * expected,
+			"Row {}: got {}, expected {}",
+			row,
+			value,
+			expected
+		);
+	}
+
+	// The roughest mip is blurred, but still brighter on top
+	let roughest = red_channel(
+		&maps
+			.prefiltered
+			.read_bytes_mip(gpu, EnvironmentMaps::PREFILTERED_MIP_LEVELS - 1),
+	);
+	let (top, bottom) = (roughest[0], roughest[roughest.len() - 1]);
+	assert!(
+		top > bottom && top < 2.0 && bottom > 0.0,
+		"top {}, bottom {}",
+		top,
+		bottom
+	);
+}
+
+#[test]
+fn loading_again_restarts_and_cancelling_keeps_the_maps() {
+	let mut app = App::new();
+	app.add_plugin(GpuPlugin);
+	let gpu = app.world.resource::<Gpu>();
+
+	let maps = EnvironmentMaps::new(gpu, "Test environment");
+	let mut uploader = uploader();
+	let mut prefilter = EnvironmentPrefilter::new(16, 8);
+
+	prefilter
+		.load(gpu, &mut uploader, "first", gradient_environment())
+		.unwrap();
+	uploader.advance(gpu);
+	gpu.queue
+		.submit([prefilter.advance(gpu, &maps, PingPongParity::default()).unwrap()]);
+	assert!(prefilter.progress().unwrap() > 0.0);
+
+	prefilter
+		.load(gpu, &mut uploader, "second", gradient_environment())
+		.unwrap();
+	assert_eq!(prefilter.progress(), Some(0.0));
+
+	assert!(prefilter.cancel());
+	assert!(!prefilter.is_running());
+	assert!(prefilter.advance(gpu, &maps, PingPongParity::default()).is_none());
+	assert!(!prefilter.cancel());
+}
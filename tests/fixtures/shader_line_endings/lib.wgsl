@@ -0,0 +1,3 @@
+fn lib_value() -> f32 {
+	return 1.0;
+}
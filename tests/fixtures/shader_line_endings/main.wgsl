@@ -0,0 +1,6 @@
+﻿#include "lib.wgsl"
+#define SCALE 2.0
+
+fn main_value() -> f32 {
+	return lib_value() * SCALE;
+}
@@ -0,0 +1,35 @@
+#![cfg(feature = "gpu-tests")]
+
+use brainrot::bevy::App;
+use image::{DynamicImage, Rgba, Rgba32FImage};
+use pbr_tracer::{
+	core::gpu::{Gpu, GpuPlugin},
+	libs::texture::Tex,
+};
+use wgpu::{TextureFormat, TextureUsages};
+
+// HDR values are above 1, they would be clamped if the image went through Rgba8
+#[test]
+fn hdr_images_keep_their_range() {
+	let mut app = App::new();
+	app.add_plugin(GpuPlugin);
+	let gpu = app.world.resource::<Gpu>();
+
+	let image = Rgba32FImage::from_fn(4, 2, |x, y| Rgba([x as f32 * 10.0, y as f32, 0.25, 1.0]));
+	let tex = Tex::from_image(
+		gpu,
+		"HDR image",
+		&DynamicImage::ImageRgba32F(image.clone()),
+		TextureFormat::Rgba32Float,
+		Some(TextureUsages::COPY_SRC),
+		None,
+	);
+
+	let texels = tex
+		.read_bytes(gpu)
+		.chunks_exact(4)
+		.map(|value| f32::from_le_bytes(value.try_into().unwrap()))
+		.collect::<Vec<_>>();
+
+	assert_eq!(texels, image.into_raw());
+}
@@ -0,0 +1,32 @@
+#![cfg(feature = "gpu-tests")]
+
+use pbr_tracer::core::{
+	display::DisplayPlugin,
+	gameloop,
+	rendering::{composite::CompositeRenderer, histogram::Histogram},
+};
+
+#[test]
+fn histogram_counts_every_pixel_of_the_composite() {
+	let mut app = pbr_tracer::build_app(DisplayPlugin {
+		visible: false,
+		any_thread: true,
+		placement_path: None,
+	});
+	gameloop::run_frames(&mut app, 2).expect("The app should render frames without exiting");
+
+	// Hidden by default, nothing gets counted
+	assert!(app.world.resource::<Histogram>().stats().is_none());
+
+	app.world.resource_mut::<Histogram>().request();
+	gameloop::run_frames(&mut app, 3).expect("The app should render with the histogram pass");
+
+	let size = app.world.resource::<CompositeRenderer>().output_texture().size();
+	let histogram = app.world.resource::<Histogram>();
+	let stats = histogram.stats().expect("The requested count should have been read back");
+
+	assert_eq!(stats.bins.len(), Histogram::BINS as usize);
+	assert_eq!(stats.total(), size.width as u64 * size.height as u64);
+	assert!((0.0..=1.0).contains(&stats.shadows_clipped()));
+	assert!((0.0..=1.0).contains(&stats.highlights_clipped()));
+}
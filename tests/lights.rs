@@ -0,0 +1,41 @@
+use brainrot::vek::{Rgb, Vec3};
+use pbr_tracer::core::rendering::lights::{Light, Lights};
+
+#[test]
+fn no_lights_is_a_single_black_one() {
+	let data = Lights(vec![]).gpu_data();
+
+	assert_eq!(data.len(), 1);
+	assert_eq!(data[0].intensity, 0.0);
+}
+
+#[test]
+fn lights_are_uploaded_in_order() {
+	let lights = Lights(vec![
+		Light::point(Vec3::new(0.0, 2.0, 0.0), Rgb::one(), 5.0, 10.0),
+		Light::directional(Vec3::new(0.0, -2.0, 0.0), Rgb::one(), 1.0),
+	]);
+	let data = lights.gpu_data();
+
+	assert_eq!(data.len(), 2);
+	assert_eq!(data[0].kind, Light::POINT);
+	assert_eq!(data[1].kind, Light::DIRECTIONAL);
+	assert_eq!(data[1].direction, -Vec3::unit_y());
+}
+
+#[test]
+fn spot_cones_are_cosines() {
+	let light = Light::spot(
+		Vec3::zero(),
+		-Vec3::unit_y(),
+		Rgb::one(),
+		1.0,
+		10.0,
+		0.0,
+		std::f32::consts::FRAC_PI_2,
+	);
+
+	assert_eq!(light.kind, Light::SPOT);
+	assert_eq!(light.cos_inner_cone, 1.0);
+	assert!(light.cos_outer_cone.abs() < 1e-6);
+}
@@ -0,0 +1,151 @@
+use brainrot::vek::{Rgb, Vec3};
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+use pbr_tracer::libs::lut::{self, CubeLut, LutDimensions};
+
+/// The identity cube of the given size, red changing fastest
+fn identity_3d(size: u32) -> String {
+	let mut source = format!("TITLE \"Identity\"\nLUT_3D_SIZE {}\n", size);
+	for b in 0..size {
+		for g in 0..size {
+			for r in 0..size {
+				let scale = (size - 1) as f32;
+				source += &format!("{} {} {}\n", r as f32 / scale, g as f32 / scale, b as f32 / scale);
+			}
+		}
+	}
+	source
+}
+
+fn error_of(source: &str) -> String {
+	format!(
+		"{:#}",
+		CubeLut::parse(source).expect_err("The LUT should have been rejected")
+	)
+}
+
+#[test]
+fn reads_a_3d_table_with_red_fastest() {
+	let lut = CubeLut::parse(&identity_3d(3)).unwrap();
+
+	assert_eq!(lut.title.as_deref(), Some("Identity"));
+	assert_eq!(lut.dimensions, LutDimensions::D3);
+	assert_eq!(lut.size, 3);
+	assert_eq!(lut.table.len(), 27);
+	assert_eq!(lut.domain_min, Vec3::zero());
+	assert_eq!(lut.domain_max, Vec3::one());
+
+	assert_eq!(lut.at(1, 0, 0), Rgb::new(0.5, 0.0, 0.0));
+	assert_eq!(lut.at(0, 2, 1), Rgb::new(0.0, 1.0, 0.5));
+	assert_eq!(lut.at(2, 2, 2), Rgb::broadcast(1.0));
+}
+
+#[test]
+fn reads_a_1d_table_with_its_domain_and_comments() {
+	let source = "\
+# Made by hand
+TITLE \"Curves\"
+LUT_1D_SIZE 3
+DOMAIN_MIN -1 0 0
+DOMAIN_MAX 1 2 4
+
+# The table
+0 0 0
+0.25 1 2
+1 2 4
+";
+	let lut = CubeLut::parse(source).unwrap();
+
+	assert_eq!(lut.dimensions, LutDimensions::D1);
+	assert_eq!(lut.size, 3);
+	assert_eq!(lut.domain_min, Vec3::new(-1.0, 0.0, 0.0));
+	assert_eq!(lut.domain_max, Vec3::new(1.0, 2.0, 4.0));
+	assert_eq!(
+		lut.table,
+		vec![Rgb::zero(), Rgb::new(0.25, 1.0, 2.0), Rgb::new(1.0, 2.0, 4.0)]
+	);
+}
+
+#[test]
+fn keeps_values_outside_of_the_unit_range() {
+	let source = "LUT_1D_SIZE 2\n-0.5 0 1e-3\n1.5 16 +2\n";
+	let lut = CubeLut::parse(source).unwrap();
+
+	assert_eq!(lut.table, vec![Rgb::new(-0.5, 0.0, 0.001), Rgb::new(1.5, 16.0, 2.0)]);
+}
+
+#[test]
+fn skips_unknown_keywords() {
+	let source = "LUT_3D_INPUT_RANGE 0 1\n".to_owned() + &identity_3d(2);
+	assert_eq!(CubeLut::parse(&source).unwrap().size, 2);
+}
+
+#[test]
+fn rejects_malformed_files() {
+	assert!(error_of("").contains("Missing the LUT_1D_SIZE or LUT_3D_SIZE"));
+	assert!(error_of("0 0 0\n1 1 1\n").contains("Missing"));
+	assert!(error_of("LUT_1D_SIZE 2\nLUT_3D_SIZE 2\n").contains("both"));
+
+	assert!(error_of("LUT_1D_SIZE two\n0 0 0\n1 1 1\n").contains("Invalid size"));
+	assert!(error_of("LUT_1D_SIZE 1\n0 0 0\n").contains("between 2 and"));
+	assert!(error_of("LUT_3D_SIZE 257\n").contains("between 2 and 256"));
+
+	assert!(error_of("LUT_1D_SIZE 3\n0 0 0\n1 1 1\n").contains("Expected 3 entries in the table, found 2"));
+	assert!(error_of(&(identity_3d(2) + "1 1 1\n")).contains("Expected 8 entries in the table, found 9"));
+
+	let error = error_of("LUT_1D_SIZE 2\n0 0 0\n1 x 1\n");
+	assert!(
+		error.contains("Line 3") && error.contains("Invalid value \"x\""),
+		"{}",
+		error
+	);
+	assert!(error_of("LUT_1D_SIZE 2\n0 0\n1 1 1\n").contains("Expected 3 values"));
+	assert!(error_of("LUT_1D_SIZE 2\n0 0 0 0\n1 1 1\n").contains("found more"));
+	assert!(error_of("LUT_1D_SIZE 2\n0 0 inf\n1 1 1\n").contains("Invalid value"));
+
+	assert!(error_of("LUT_1D_SIZE 2\n0 0 0\nDOMAIN_MIN 0 0 0\n1 1 1\n").contains("before the table"));
+	assert!(
+		error_of("LUT_1D_SIZE 2\nDOMAIN_MIN 0 1 0\nDOMAIN_MAX 1 1 1\n0 0 0\n1 1 1\n").contains("below the DOMAIN_MAX")
+	);
+}
+
+#[test]
+fn splits_a_strip_into_blue_layers() {
+	// 2 entries per axis, red to the right and green downwards in every tile
+	let mut strip = RgbaImage::new(4, 2);
+	for (x, y, pixel) in strip.enumerate_pixels_mut() {
+		let (r, g, b) = (x % 2, y, x / 2);
+		*pixel = Rgba([(r * 255) as u8, (g * 255) as u8, (b * 255) as u8, 255]);
+	}
+
+	let layers = lut::strip_layers(&DynamicImage::ImageRgba8(strip)).unwrap();
+
+	assert_eq!(layers.len(), 2);
+	for (b, layer) in layers.iter().enumerate() {
+		assert_eq!(layer.dimensions(), (2, 2));
+		assert_eq!(layer.get_pixel(1, 0), Rgba([255, 0, b as u8 * 255, 255]));
+		assert_eq!(layer.get_pixel(0, 1), Rgba([0, 255, b as u8 * 255, 255]));
+	}
+}
+
+#[test]
+fn rejects_images_that_arent_strips() {
+	for (width, height) in [(32, 32), (1024, 31), (1, 1)] {
+		let image = DynamicImage::new_rgba8(width, height);
+		assert!(lut::strip_layers(&image).is_err(), "{}x{}", width, height);
+	}
+}
+
+#[test]
+fn shipped_luts_are_32_cubed_and_the_neutral_one_is_the_identity() {
+	for name in ["neutral", "teal_orange"] {
+		let image = image::open(format!("assets/luts/{}.png", name)).unwrap();
+		assert_eq!(lut::strip_layers(&image).unwrap().len(), 32, "{}", name);
+	}
+
+	let neutral = image::open("assets/luts/neutral.png").unwrap();
+	let layers = lut::strip_layers(&neutral).unwrap();
+	for (r, g, b) in [(0, 0, 0), (31, 0, 0), (0, 31, 0), (0, 0, 31), (10, 20, 30)] {
+		let expected = [r, g, b].map(|x| (x as f32 / 31.0 * 255.0).round() as u8);
+		assert_eq!(layers[b as usize].get_pixel(r, g).0[..3], expected, "{} {} {}", r, g, b);
+	}
+}
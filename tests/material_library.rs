@@ -0,0 +1,62 @@
+use brainrot::vek::Rgb;
+use image::{DynamicImage, RgbaImage};
+use pbr_tracer::fragments::shading::{Material, MaterialLibrary, PbrShading};
+
+#[test]
+fn ids_follow_the_default_material() {
+	let mut library = MaterialLibrary::default();
+	assert_eq!(
+		library.get(MaterialLibrary::DEFAULT_MATERIAL),
+		Some(&Material::default())
+	);
+
+	let gold = Material::new(Rgb::new(1.0, 0.78, 0.34), 1.0, 0.3);
+	let rubber = Material::new(Rgb::new(0.1, 0.1, 0.1), 0.0, 0.9);
+	assert_eq!(library.register(gold), 1);
+	assert_eq!(library.register(rubber), 2);
+	assert_eq!(library.get(1), Some(&gold));
+	assert_eq!(library.get(3), None);
+
+	assert!(library.set(2, gold));
+	assert!(!library.set(3, gold));
+	assert_eq!(library.get(2), Some(&gold));
+}
+
+#[test]
+fn shading_copies_the_library() {
+	let mut library = MaterialLibrary::default();
+	library.register(Material::new(Rgb::new(1.0, 0.0, 0.0), 0.0, 0.2));
+
+	let shading = PbrShading::new(&library);
+	library.register(Material::default());
+
+	assert_eq!(shading.materials.len(), 2);
+	assert_eq!(library.materials().len(), 3);
+}
+
+#[test]
+fn textures_come_after_the_placeholders() {
+	let mut library = MaterialLibrary::default();
+	assert_eq!(library.get(0).unwrap().albedo_texture, MaterialLibrary::WHITE_TEXTURE);
+	assert_eq!(
+		library.get(0).unwrap().normal_texture,
+		MaterialLibrary::FLAT_NORMAL_TEXTURE
+	);
+
+	let small = DynamicImage::ImageRgba8(RgbaImage::new(4, 8));
+	let big = DynamicImage::ImageRgba8(RgbaImage::new(16, 2));
+	assert_eq!(library.register_texture(small), 2);
+	assert_eq!(library.register_texture(big), 3);
+	assert_eq!(library.textures().len(), 2);
+
+	// The placeholders are as big as the biggest texture in both directions
+	let layers = MaterialLibrary::texture_layers(library.textures());
+	assert_eq!(layers.len(), 4);
+	assert_eq!((layers[0].width(), layers[0].height()), (16, 8));
+	assert_eq!(layers[1].to_rgba8().get_pixel(3, 3).0, [128, 128, 255, 255]);
+
+	// Just the placeholders without any
+	let layers = MaterialLibrary::texture_layers(&[]);
+	assert_eq!(layers.len(), 2);
+	assert_eq!((layers[0].width(), layers[0].height()), (1, 1));
+}
@@ -0,0 +1,97 @@
+use brainrot::vek::{Vec2, Vec3, Vec4};
+use pbr_tracer::fragments::mesh::{Bvh, Mesh};
+
+fn cube() -> Mesh {
+	Mesh::load_obj(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/cube.obj")).unwrap()
+}
+
+#[test]
+fn loads_the_cube() {
+	let mesh = cube();
+
+	assert_eq!(mesh.triangle_count(), 12);
+	assert_eq!(mesh.material_ids.len(), 12);
+	assert_eq!(mesh.normals.len(), mesh.positions.len());
+}
+
+#[test]
+fn builds_the_same_bvh_every_time() {
+	let mesh = cube();
+
+	assert_eq!(Bvh::build(mesh.triangles()), Bvh::build(mesh.triangles()));
+}
+
+#[test]
+fn every_triangle_is_in_exactly_one_leaf() {
+	let bvh = Bvh::build(cube().triangles());
+
+	let mut seen = vec![0; bvh.triangles.len()];
+	for leaf in bvh.nodes.iter().filter(|node| node.is_leaf()) {
+		assert!(leaf.count as usize <= Bvh::MAX_LEAF_TRIANGLES);
+
+		for i in leaf.triangles() {
+			seen[i] += 1;
+
+			// The leaf's bounds contain its triangles
+			let triangle = &bvh.triangles[i];
+			for p in [triangle.p0, triangle.p1, triangle.p2] {
+				assert!(p.partial_cmpge(&leaf.bounds_min).reduce_and());
+				assert!(p.partial_cmple(&leaf.bounds_max).reduce_and());
+			}
+		}
+	}
+
+	assert!(seen.iter().all(|&count| count == 1), "{:?}", seen);
+}
+
+#[test]
+fn an_empty_mesh_still_has_a_root() {
+	let bvh = Bvh::build(Mesh::default().triangles());
+
+	assert_eq!(bvh.nodes.len(), 1);
+	assert!(bvh.nodes[0].is_leaf());
+	assert_eq!(bvh.depth(), 1);
+}
+
+#[test]
+fn tangents_follow_the_uvs() {
+	// A quad facing +Z, with the texture upright: u goes along +X and v down
+	// along -Y
+	let mut mesh = Mesh {
+		positions: vec![
+			Vec3::new(0.0, 0.0, 0.0),
+			Vec3::new(1.0, 0.0, 0.0),
+			Vec3::new(1.0, 1.0, 0.0),
+			Vec3::new(0.0, 1.0, 0.0),
+		],
+		uvs: vec![
+			Vec2::new(0.0, 1.0),
+			Vec2::new(1.0, 1.0),
+			Vec2::new(1.0, 0.0),
+			Vec2::new(0.0, 0.0),
+		],
+		indices: vec![0, 1, 2, 0, 2, 3],
+		material_ids: vec![0, 0],
+		..Default::default()
+	};
+
+	for triangle in mesh.triangles() {
+		for tangent in [triangle.t0, triangle.t1, triangle.t2] {
+			assert!(
+				(tangent - Vec4::new(1.0, 0.0, 0.0, 1.0)).magnitude() < 1e-5,
+				"{:?}",
+				tangent
+			);
+		}
+	}
+
+	// Mirrored along v, the bitangent flips
+	for uv in &mut mesh.uvs {
+		uv.y = 1.0 - uv.y;
+	}
+	assert!(mesh.triangles().iter().all(|triangle| triangle.t0.w == -1.0));
+
+	// No uvs, no tangents
+	mesh.uvs.clear();
+	assert!(mesh.triangles().iter().all(|triangle| triangle.t0 == Vec4::zero()));
+}
@@ -0,0 +1,93 @@
+use std::time::Duration;
+
+use pbr_tracer::libs::metrics::{format_duration_ms, format_ms, RollingStats};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// The nearest-rank percentile of the samples, the slow way
+fn reference_percentile(samples: &[f32], percent: f32) -> f32 {
+	let mut sorted = samples.to_vec();
+	sorted.sort_by(f32::total_cmp);
+
+	let rank = (percent / 100.0 * sorted.len() as f32).ceil() as usize;
+	sorted[rank.max(1) - 1]
+}
+
+#[test]
+fn percentiles_match_a_sorted_reference() {
+	let mut rng = StdRng::seed_from_u64(0);
+
+	for length in [1, 7, 64, 100, 250] {
+		let mut stats = RollingStats::<100>::new();
+		let mut samples = Vec::new();
+
+		for _ in 0..length {
+			// Few distinct values, so that there are plenty of duplicates
+			let sample = rng.gen_range(0..40) as f32 * 0.5;
+			stats.push(sample);
+			samples.push(sample);
+		}
+
+		let window = &samples[samples.len().saturating_sub(100)..];
+
+		for percent in [0.0, 1.0, 25.0, 50.0, 95.0, 99.0, 100.0] {
+			assert_eq!(
+				stats.percentile(percent),
+				Some(reference_percentile(window, percent)),
+				"p{} of {} samples",
+				percent,
+				length
+			);
+		}
+
+		let mean = window.iter().sum::<f32>() / window.len() as f32;
+		assert!((stats.mean().unwrap() - mean).abs() < 1e-3);
+		assert_eq!(stats.min(), window.iter().copied().reduce(f32::min));
+		assert_eq!(stats.max(), window.iter().copied().reduce(f32::max));
+	}
+}
+
+#[test]
+fn ring_buffer_wraps_around() {
+	let mut stats = RollingStats::<4>::new();
+	assert!(stats.is_empty());
+	assert_eq!(stats.p50(), None);
+
+	for sample in 1..=10 {
+		stats.push(sample as f32);
+	}
+
+	assert_eq!(stats.len(), 4);
+	assert_eq!(stats.samples().collect::<Vec<_>>(), [7.0, 8.0, 9.0, 10.0]);
+	assert_eq!(stats.latest(), Some(10.0));
+	assert_eq!(stats.min(), Some(7.0));
+	assert_eq!(stats.max(), Some(10.0));
+	assert_eq!(stats.mean(), Some(8.5));
+
+	// The oldest sample is the biggest one, so dropping it reorders the rest
+	stats.clear();
+	for sample in [9.0, 1.0, 2.0, 3.0, 4.0] {
+		stats.push(sample);
+	}
+	assert_eq!(stats.max(), Some(4.0));
+	assert_eq!(stats.p99(), Some(4.0));
+}
+
+#[test]
+fn skips_samples_that_arent_finite() {
+	let mut stats = RollingStats::<8>::new();
+	stats.push(1.0);
+	stats.push(f32::INFINITY);
+	stats.push(f32::NAN);
+
+	assert_eq!(stats.len(), 1);
+	assert_eq!(stats.mean(), Some(1.0));
+}
+
+#[test]
+fn durations_keep_about_three_digits() {
+	assert_eq!(format_duration_ms(Duration::from_micros(125)), "0.125ms");
+	assert_eq!(format_duration_ms(Duration::from_secs(1) / 240), "4.17ms");
+	assert_eq!(format_duration_ms(Duration::from_secs(1) / 60), "16.7ms");
+	assert_eq!(format_duration_ms(Duration::from_millis(250)), "250ms");
+	assert_eq!(format_ms(0.0), "0.000ms");
+}
@@ -0,0 +1,25 @@
+use brainrot::{bevy::App, size, vec2};
+use pbr_tracer::{
+	core::{
+		rendering::compute::{ComputeRendererPlugin, DispatchMode},
+		size::Resolution,
+	},
+	fragments::mpr::DebugRenderer,
+};
+use wgpu::FilterMode;
+
+// Fails before it needs the GPU, so it doesn't need the gpu-tests feature
+#[test]
+#[should_panic(expected = "add the CameraViewPlugin before the compute renderer")]
+fn compute_renderer_names_the_missing_plugin() {
+	let mut app = App::new();
+
+	app.add_plugin(ComputeRendererPlugin {
+		workgroup_size: vec2!(16, 16),
+		resolution: Resolution(size!(64, 64)),
+		filter_mode: FilterMode::Linear,
+		dispatch_mode: DispatchMode::Fixed,
+		early_submit: false,
+		renderer: DebugRenderer,
+	});
+}
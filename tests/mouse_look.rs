@@ -0,0 +1,58 @@
+use brainrot::{deg, vek::Vec2, Direction};
+use pbr_tracer::core::camera::{CameraController, InputSettings, Sensitivity};
+
+/// A second of mouse motion, delivered in small events like a real mouse would
+const EVENTS: usize = 1000;
+const COUNTS_PER_EVENT: f32 = 1.0;
+
+/// Feed the same mouse motion to a camera updated `ups` times per second, and
+/// return the final yaw in radians
+fn final_yaw(ups: usize) -> f32 {
+	let sensitivity = Sensitivity(deg!(0.1));
+	let input_settings = InputSettings::default();
+
+	let mut controller = CameraController::default();
+	let mut direction = Direction::default();
+
+	for tick in 0..ups {
+		// The events that arrived since the previous tick
+		let events = (tick * EVENTS / ups)..((tick + 1) * EVENTS / ups);
+
+		for _ in events {
+			controller.add_mouse_motion(Vec2::new(COUNTS_PER_EVENT, 0.0), &input_settings);
+		}
+
+		let (yaw_counts, _) = controller.take_mouse_motion();
+		direction.yaw += sensitivity.turn(yaw_counts, &input_settings);
+	}
+
+	direction.yaw.to_radians()
+}
+
+#[test]
+fn yaw_does_not_depend_on_update_rate() {
+	let expected = (EVENTS as f32 * COUNTS_PER_EVENT * 0.1).to_radians();
+
+	for ups in [30, 60, 144, 240] {
+		let yaw = final_yaw(ups);
+		assert!(
+			(yaw - expected).abs() < 1e-4,
+			"At {} updates per second the yaw is {} instead of {}",
+			ups,
+			yaw,
+			expected
+		);
+	}
+}
+
+#[test]
+fn motion_is_drained_once() {
+	let input_settings = InputSettings::default();
+	let mut controller = CameraController::default();
+
+	controller.add_mouse_motion(Vec2::new(5.0, -2.0), &input_settings);
+	controller.add_mouse_motion(Vec2::new(1.0, 1.0), &input_settings);
+
+	assert_eq!(controller.take_mouse_motion(), (6.0, -1.0));
+	assert_eq!(controller.take_mouse_motion(), (0.0, 0.0));
+}
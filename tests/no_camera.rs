@@ -0,0 +1,27 @@
+#![cfg(feature = "gpu-tests")]
+
+use bevy_ecs::{entity::Entity, query::With};
+use pbr_tracer::core::{camera::Camera, display::DisplayPlugin, gameloop};
+
+// Same as building the app without the CameraPlugin, everything that needs the
+// active camera should skip instead of panicking
+#[test]
+fn renders_frames_without_camera() {
+	let mut app = pbr_tracer::build_app(DisplayPlugin {
+		visible: false,
+		any_thread: true,
+		placement_path: None,
+	});
+
+	let cameras = app
+		.world
+		.query_filtered::<Entity, With<Camera>>()
+		.iter(&app.world)
+		.collect::<Vec<_>>();
+
+	for camera in cameras {
+		app.world.despawn(camera);
+	}
+
+	gameloop::run_frames(&mut app, 10).expect("The app should keep rendering without a camera");
+}
@@ -0,0 +1,59 @@
+use bevy_ecs::{system::Resource, world::World};
+use pbr_tracer::core::params::{self, ParamKind, ParamRegistry, ParamValue};
+
+#[derive(Resource)]
+struct Exposure(f32);
+
+fn world() -> World {
+	let mut registry = ParamRegistry::default();
+	registry.register_float(
+		"exposure",
+		"Test",
+		0.0..=4.0,
+		|world| Ok(world.resource::<Exposure>().0),
+		|world, value| {
+			world.resource_mut::<Exposure>().0 = value;
+			Ok(())
+		},
+	);
+
+	let mut world = World::new();
+	world.insert_resource(registry);
+	world.insert_resource(Exposure(1.0));
+	world
+}
+
+#[test]
+fn sets_and_gets_through_the_closures() {
+	let mut world = world();
+
+	assert_eq!(
+		params::set_param(&mut world, "exposure", "2.5").unwrap(),
+		ParamValue::Float(2.5)
+	);
+	assert_eq!(world.resource::<Exposure>().0, 2.5);
+	assert_eq!(
+		params::get_param(&mut world, "exposure").unwrap(),
+		ParamValue::Float(2.5)
+	);
+}
+
+#[test]
+fn bad_values_leave_the_value_alone() {
+	let mut world = world();
+
+	assert!(params::set_param(&mut world, "exposure", "5").is_err());
+	assert!(params::set_param(&mut world, "exposure", "-0.1").is_err());
+	assert!(params::set_param(&mut world, "exposure", "bright").is_err());
+	assert!(params::set_param(&mut world, "brightness", "1").is_err());
+
+	assert_eq!(world.resource::<Exposure>().0, 1.0);
+}
+
+#[test]
+fn kinds_parse_their_own_values() {
+	assert_eq!(ParamKind::Int(1..=8).parse("8").unwrap(), ParamValue::Int(8));
+	assert!(ParamKind::Int(1..=8).parse("1.5").is_err());
+	assert_eq!(ParamKind::Bool.parse("true").unwrap(), ParamValue::Bool(true));
+	assert!(ParamKind::Bool.parse("1").is_err());
+}
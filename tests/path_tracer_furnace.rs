@@ -0,0 +1,84 @@
+#![cfg(feature = "gpu-tests")]
+
+use brainrot::vek::{Rgb, Rgba, Vec3};
+use pbr_tracer::{
+	core::{
+		display::DisplayPlugin,
+		gameloop,
+		gpu::Gpu,
+		rendering::{
+			compute::{self, ComputeRenderer},
+			lights::Lights,
+		},
+	},
+	fragments::{
+		environment::ProceduralSky,
+		intersector::{AnalyticIntersector, Primitive},
+		path_tracer::{PathTracer, PathTracerSettings},
+		shading::{Material, MaterialLibrary},
+	},
+};
+
+// The furnace test: a white diffuse sphere under an environment that is 1 in
+// every direction, and no lights. Whatever the paths do, all they can find is
+// the environment, so everything should converge to 1 (a bit less, the
+// single-scattering GGX lobe loses some energy at roughness 1).
+#[test]
+fn white_sphere_in_a_furnace_converges_to_one() {
+	const FRAMES: u64 = 32;
+	const TOLERANCE: f32 = 0.05;
+
+	let mut app = pbr_tracer::build_app(DisplayPlugin {
+		visible: false,
+		any_thread: true,
+		placement_path: None,
+	});
+
+	app.world.insert_resource(Lights(vec![]));
+
+	let mut environment = ProceduralSky::default();
+	environment.zenith_color = Rgb::one();
+	environment.horizon_color = Rgb::one();
+	environment.ground_color = Rgb::one();
+
+	let mut materials = MaterialLibrary::default();
+	materials.set(MaterialLibrary::DEFAULT_MATERIAL, Material::new(Rgb::one(), 0.0, 1.0));
+
+	// Big enough to fill the view of the default camera, 5 units away
+	let intersector = AnalyticIntersector::new(vec![Primitive::sphere(Vec3::zero(), 4.5, Rgba::one())]);
+
+	let mut path_tracer = PathTracer::new(intersector, environment, &materials);
+	path_tracer.settings = PathTracerSettings {
+		samples_per_frame: 4,
+		..Default::default()
+	};
+
+	let renderer = app
+		.world
+		.resource::<ComputeRenderer>()
+		.with_renderer(app.world.resource::<Gpu>(), &path_tracer);
+	compute::swap_compute_renderer(&mut app.world, renderer);
+
+	gameloop::run_frames(&mut app, FRAMES).expect("The app should render frames without exiting");
+
+	let color = app.world.resource::<ComputeRenderer>().output_textures[0].clone();
+	let bytes = color.read_bytes(app.world.resource::<Gpu>());
+
+	// Rgba32Float
+	let texels = bytes
+		.chunks_exact(16)
+		.map(|texel| {
+			let channel = |i: usize| f32::from_le_bytes(texel[i * 4..i * 4 + 4].try_into().unwrap());
+			(channel(0) + channel(1) + channel(2)) / 3.0
+		})
+		.collect::<Vec<_>>();
+	let mean = texels.iter().sum::<f32>() / texels.len() as f32;
+
+	assert!(
+		(mean - 1.0).abs() < TOLERANCE,
+		"The mean pixel value is {} after {} frames, expected 1 ± {}",
+		mean,
+		FRAMES,
+		TOLERANCE
+	);
+}
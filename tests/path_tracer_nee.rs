@@ -0,0 +1,118 @@
+#![cfg(feature = "gpu-tests")]
+
+use brainrot::{
+	bevy::App,
+	size,
+	vek::{Rgb, Rgba, Vec3},
+};
+use image::Rgb32FImage;
+use pbr_tracer::{
+	core::{
+		display::DisplayPlugin,
+		gameloop,
+		gpu::Gpu,
+		rendering::{
+			compute::{self, ComputeRenderer},
+			lights::{Light, Lights},
+		},
+		size::Resolution,
+	},
+	fragments::{
+		environment::ProceduralSky,
+		intersector::{AnalyticIntersector, Primitive},
+		path_tracer::{PathTracer, PathTracerSettings},
+		shading::{Material, MaterialLibrary},
+	},
+};
+
+const FRAMES: u64 = 16;
+const REFERENCE_FRAMES: u64 = 512;
+
+fn resolution() -> Resolution {
+	Resolution(size!(256, 128))
+}
+
+/// A sphere on a floor, lit only by a small sphere of light
+fn render(app: &mut App, next_event_estimation: bool, frames: u64) -> Rgb32FImage {
+	let mut environment = ProceduralSky::default();
+	environment.zenith_color = Rgb::zero();
+	environment.horizon_color = Rgb::zero();
+	environment.ground_color = Rgb::zero();
+
+	let intersector = AnalyticIntersector::new(vec![
+		Primitive::sphere(Vec3::zero(), 1.0, Rgba::one()),
+		Primitive::plane(-1.0, Rgba::new(0.8, 0.8, 0.8, 1.0)),
+	]);
+
+	let mut materials = MaterialLibrary::default();
+	materials.set(MaterialLibrary::DEFAULT_MATERIAL, Material::new(Rgb::one(), 0.0, 0.6));
+
+	let mut path_tracer = PathTracer::new(intersector, environment, &materials);
+	path_tracer.settings = PathTracerSettings {
+		next_event_estimation: next_event_estimation as u32,
+		..Default::default()
+	};
+
+	let resolution = resolution();
+	let gpu = app.world.resource::<Gpu>();
+	let renderer = app
+		.world
+		.resource::<ComputeRenderer>()
+		.with_renderer(gpu, &path_tracer)
+		.resized(gpu, resolution)
+		.unwrap();
+	compute::swap_compute_renderer(&mut app.world, renderer);
+	app.world.insert_resource(resolution);
+
+	gameloop::run_frames(app, frames).expect("The app should render frames without exiting");
+
+	// Rgba32Float
+	let bytes = app.world.resource::<ComputeRenderer>().output_textures[0].read_bytes(app.world.resource::<Gpu>());
+	let texels = bytes
+		.chunks_exact(16)
+		.flat_map(|texel| (0..3).map(|i| f32::from_le_bytes(texel[i * 4..i * 4 + 4].try_into().unwrap())))
+		.collect();
+
+	Rgb32FImage::from_raw(resolution.w, resolution.h, texels).unwrap()
+}
+
+fn rmse(image: &Rgb32FImage, reference: &Rgb32FImage) -> f32 {
+	let sum = image
+		.as_raw()
+		.iter()
+		.zip(reference.as_raw())
+		.map(|(a, b)| (a.min(4.0) - b.min(4.0)).powi(2))
+		.sum::<f32>();
+	(sum / image.as_raw().len() as f32).sqrt()
+}
+
+#[test]
+fn next_event_estimation_is_less_noisy() {
+	let mut app = pbr_tracer::build_app(DisplayPlugin {
+		visible: false,
+		any_thread: true,
+		placement_path: None,
+	});
+
+	app.world.insert_resource(Lights(vec![Light::point(
+		Vec3::new(1.5, 2.5, -1.5),
+		Rgb::one(),
+		20.0,
+		50.0,
+	)
+	.with_size(0.15)]));
+
+	let with_nee = render(&mut app, true, FRAMES);
+	let without_nee = render(&mut app, false, FRAMES);
+	let reference = render(&mut app, true, REFERENCE_FRAMES);
+
+	// Both converge to the same picture, only the noise differs
+	let (error_with, error_without) = (rmse(&with_nee, &reference), rmse(&without_nee, &reference));
+	assert!(
+		error_with < 0.5 * error_without,
+		"RMSE after {} frames: {} with NEE, {} without",
+		FRAMES,
+		error_with,
+		error_without
+	);
+}
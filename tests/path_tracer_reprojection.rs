@@ -0,0 +1,97 @@
+#![cfg(feature = "gpu-tests")]
+
+use bevy_ecs::query::With;
+use brainrot::{
+	bevy::App,
+	size,
+	vek::{Rgb, Rgba, Vec3},
+	Position,
+};
+use pbr_tracer::{
+	core::{
+		camera::ActiveCamera,
+		display::DisplayPlugin,
+		gameloop,
+		gpu::Gpu,
+		params,
+		rendering::{
+			accumulation::ReprojectionBuffers,
+			compute::{self, ComputeRenderer},
+		},
+		size::Resolution,
+	},
+	fragments::{
+		environment::ProceduralSky,
+		intersector::{AnalyticIntersector, Primitive},
+		path_tracer::PathTracer,
+		shading::{Material, MaterialLibrary},
+	},
+};
+
+/// The count of every pixel's sum
+fn sample_counts(app: &App) -> Vec<f32> {
+	let bytes = app
+		.world
+		.resource::<ComputeRenderer>()
+		.output_texture("output_accumulation")
+		.expect("The path tracer sums its frames")
+		.read_bytes(app.world.resource::<Gpu>());
+	bytemuck::cast_slice::<u8, f32>(&bytes)
+		.chunks_exact(4)
+		.map(|texel| texel[3])
+		.collect()
+}
+
+#[test]
+fn a_small_move_keeps_most_of_the_history() {
+	let mut app = pbr_tracer::build_app(DisplayPlugin {
+		visible: false,
+		any_thread: true,
+		placement_path: None,
+	});
+
+	// A floor and a sphere, whatever the camera looks at
+	let intersector = AnalyticIntersector::new(vec![
+		Primitive::sphere(Vec3::zero(), 1.0, Rgba::one()),
+		Primitive::plane(-1.0, Rgba::new(0.8, 0.8, 0.8, 1.0)),
+	]);
+	let mut materials = MaterialLibrary::default();
+	materials.set(MaterialLibrary::DEFAULT_MATERIAL, Material::new(Rgb::one(), 0.0, 0.6));
+	let path_tracer = PathTracer::new(intersector, ProceduralSky::default(), &materials).reprojected(&mut app);
+
+	let resolution = Resolution(size!(128, 64));
+	let gpu = app.world.resource::<Gpu>();
+	let renderer = app
+		.world
+		.resource::<ComputeRenderer>()
+		.with_renderer(gpu, &path_tracer)
+		.resized(gpu, resolution)
+		.unwrap();
+	compute::swap_compute_renderer(&mut app.world, renderer);
+	app.world.insert_resource(resolution);
+
+	params::set_param(&mut app.world, "accumulation.reproject", "true").unwrap();
+	params::set_param(&mut app.world, "accumulation.history_weight", "1.0").unwrap();
+	gameloop::run_frames(&mut app, 8).expect("The app should render frames without exiting");
+
+	app.world
+		.query_filtered::<&mut Position, With<ActiveCamera>>()
+		.single_mut(&mut app.world)
+		.0 += Vec3::new(0.01, 0.0, 0.0);
+	gameloop::run_frames(&mut app, 1).expect("The app should render after the move");
+
+	let retained = app
+		.world
+		.resource::<ReprojectionBuffers>()
+		.retained_history(app.world.resource::<Gpu>())
+		.expect("The move should have been reprojected");
+	assert!(retained > 0.0 && retained <= 1.0);
+
+	// Without the reprojection, every pixel would be back to a single frame
+	let counts = sample_counts(&app);
+	assert!(counts.iter().all(|count| *count >= 1.0));
+	assert!(
+		counts.iter().any(|count| *count > 1.0),
+		"The surfaces should have kept their sums"
+	);
+}
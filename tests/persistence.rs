@@ -0,0 +1,78 @@
+use std::{
+	fs, io,
+	io::Write,
+	path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use pbr_tracer::core::persistence::{backup_path, load_with_backup, safe_write, safe_write_with, tmp_path};
+
+/// An empty directory of its own for every test
+fn test_dir(name: &str) -> PathBuf {
+	let dir = std::env::temp_dir().join(format!("pbr_tracer_persistence_{}_{}", name, std::process::id()));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	dir
+}
+
+fn parse_number(text: &str) -> Result<u32> {
+	Ok(text.trim().parse()?)
+}
+
+fn load(path: &Path) -> Result<Option<u32>> {
+	load_with_backup(path, parse_number)
+}
+
+#[test]
+fn a_failed_write_leaves_the_file_alone() {
+	let path = test_dir("failed_write").join("value.txt");
+	safe_write(&path, b"1").unwrap();
+
+	let result = safe_write_with(&path, |file| {
+		file.write_all(b"2")?;
+		Err(io::Error::other("Disk full"))
+	});
+
+	assert!(result.is_err());
+	assert_eq!(fs::read_to_string(&path).unwrap(), "1");
+	assert!(!tmp_path(&path).exists());
+}
+
+#[test]
+fn a_missing_file_is_nothing() {
+	let path = test_dir("missing").join("value.txt");
+
+	assert_eq!(load(&path).unwrap(), None);
+	assert!(!backup_path(&path).exists());
+}
+
+#[test]
+fn a_broken_file_falls_back_to_the_backup() {
+	let path = test_dir("fallback").join("value.txt");
+
+	safe_write(&path, b"1").unwrap();
+	assert_eq!(load(&path).unwrap(), Some(1));
+	assert!(backup_path(&path).exists());
+
+	// Like a crash in the middle of an in-place write
+	fs::write(&path, b"").unwrap();
+	assert_eq!(load(&path).unwrap(), Some(1));
+
+	// The next good file becomes the backup
+	safe_write(&path, b"2").unwrap();
+	assert_eq!(load(&path).unwrap(), Some(2));
+	fs::write(&path, b"broken").unwrap();
+	assert_eq!(load(&path).unwrap(), Some(2));
+}
+
+#[test]
+fn without_a_good_backup_the_error_is_returned() {
+	let path = test_dir("no_backup").join("value.txt");
+
+	fs::write(&path, b"broken").unwrap();
+	assert!(load(&path).is_err());
+
+	fs::write(backup_path(&path), b"also broken").unwrap();
+	let error = load(&path).unwrap_err();
+	assert!(format!("{:#}", error).contains("value.txt"), "{:#}", error);
+}
@@ -0,0 +1,77 @@
+#![cfg(feature = "gpu-tests")]
+
+use bevy_ecs::query::With;
+use brainrot::{
+	vek::{Rgba, Vec2, Vec3},
+	Direction, Position,
+};
+use pbr_tracer::{
+	core::{camera::ActiveCamera, display::DisplayPlugin, gameloop, gpu::Gpu, rendering::compute::ComputeRenderer},
+	fragments::intersector::{AnalyticIntersector, Primitive},
+};
+
+fn id(bytes: Vec<u8>) -> u32 {
+	u32::from_le_bytes(bytes[..4].try_into().unwrap())
+}
+
+#[test]
+fn the_id_output_holds_the_hit_primitive() {
+	let mut app = pbr_tracer::build_app_with(
+		DisplayPlugin {
+			visible: false,
+			any_thread: true,
+			placement_path: None,
+		},
+		|_| {
+			AnalyticIntersector::new(vec![
+				Primitive::sphere(Vec3::zero(), 1.0, Rgba::one()),
+				Primitive::plane(-2.0, Rgba::one()),
+			])
+		},
+	);
+
+	// In front of the sphere, looking at it
+	let (mut position, mut direction) = app
+		.world
+		.query_filtered::<(&mut Position, &mut Direction), With<ActiveCamera>>()
+		.single_mut(&mut app.world);
+	*position = Vec3::new(0.0, 0.0, -5.0).into();
+	*direction = Direction::default();
+
+	gameloop::run_frames(&mut app, 2).expect("The app should render frames without exiting");
+
+	let ids = app
+		.world
+		.resource::<ComputeRenderer>()
+		.output_texture("output_id")
+		.expect("The multi-purpose renderer has an id output")
+		.clone();
+	let gpu = app.world.resource::<Gpu>();
+	let (width, height) = (ids.size().width, ids.size().height);
+
+	// The primitives count from 1, the sky is 0
+	let center = Vec2::new(width / 2, height / 2);
+	assert_eq!(id(ids.read_texel(gpu, center)), 1, "The sphere is in the middle");
+	assert_eq!(
+		id(ids.read_texel(gpu, Vec2::new(width / 2, 0))),
+		2,
+		"The floor is below"
+	);
+	assert_eq!(
+		id(ids.read_texel(gpu, Vec2::new(width / 2, height - 1))),
+		0,
+		"The sky is above"
+	);
+
+	// Same texel without blocking, the copy is done a few frames later at most
+	let readback = ids.request_texel(gpu, center);
+	let mut picked = None;
+	for _ in 0..60 {
+		if let Some(bytes) = readback.try_take(app.world.resource::<Gpu>()) {
+			picked = Some(id(bytes));
+			break;
+		}
+		gameloop::run_frames(&mut app, 1).expect("The app should keep rendering");
+	}
+	assert_eq!(picked, Some(1));
+}
@@ -0,0 +1,106 @@
+use brainrot::{
+	deg,
+	vek::{Extent2, Vec2, Vec3},
+	Direction, Frustum, Position,
+};
+use pbr_tracer::core::{camera::ProjectionMode, rendering::camera_view::CameraView, size::Resolution};
+
+const RESOLUTION: Extent2<u32> = Extent2 { w: 2000, h: 1000 };
+
+fn view(direction: Direction, projection_mode: ProjectionMode) -> CameraView {
+	let position = Position::from(Vec3::new(1.0, 2.0, 3.0));
+	let frustum = Frustum {
+		y_fov: 70.0_f32.to_radians(),
+		z_near: 0.1,
+		z_far: 1000.0,
+	};
+
+	CameraView::new(position, direction, frustum, projection_mode, Resolution(RESOLUTION))
+}
+
+fn directions() -> Vec<Direction> {
+	[(0.0, 0.0), (90.0, 0.0), (-135.0, 30.0), (10.0, -80.0)]
+		.into_iter()
+		.map(|(yaw, pitch)| {
+			let mut direction = Direction::default();
+			direction.yaw = deg!(yaw);
+			direction.pitch = deg!(pitch);
+			direction
+		})
+		.collect()
+}
+
+fn center() -> Vec2<f32> {
+	Vec2::new(RESOLUTION.w as f32, RESOLUTION.h as f32) / 2.0
+}
+
+#[test]
+fn center_ray_is_camera_forward() {
+	for direction in directions() {
+		let view = view(direction, ProjectionMode::Perspective);
+		let forward = view.inverse_view_mat.mul_direction(Vec3::unit_z());
+
+		let (origin, dir) = view.pixel_ray(center(), RESOLUTION);
+
+		assert!((dir - forward).magnitude() < 1e-5, "{:?} != {:?}", dir, forward);
+		assert!((origin - Vec3::new(1.0, 2.0, 3.0)).magnitude() < 1e-4);
+	}
+}
+
+#[test]
+fn orthographic_center_ray_starts_at_camera() {
+	for direction in directions() {
+		let view = view(direction, ProjectionMode::Orthographic { height: 10.0 });
+		let forward = view.inverse_view_mat.mul_direction(Vec3::unit_z());
+
+		let (origin, dir) = view.pixel_ray(center(), RESOLUTION);
+
+		assert!((dir - forward).magnitude() < 1e-5, "{:?} != {:?}", dir, forward);
+		assert!((origin - Vec3::new(1.0, 2.0, 3.0)).magnitude() < 1e-4);
+	}
+}
+
+#[test]
+fn top_edge_ray_is_half_fov_up() {
+	let view = view(Direction::default(), ProjectionMode::Perspective);
+	let forward = view.inverse_view_mat.mul_direction(Vec3::unit_z());
+
+	let (_, dir) = view.pixel_ray(Vec2::new(center().x, RESOLUTION.h as f32), RESOLUTION);
+
+	let angle = dir.normalized().dot(forward).acos();
+	assert!((angle - 35.0_f32.to_radians()).abs() < 1e-4);
+}
+
+#[test]
+fn equirectangular_rays_go_all_around() {
+	for direction in directions() {
+		let view = view(direction, ProjectionMode::Equirectangular);
+		let forward = view.inverse_view_mat.mul_direction(Vec3::unit_z());
+		let up = view.inverse_view_mat.mul_direction(Vec3::unit_y());
+		let right = view.inverse_view_mat.mul_direction(Vec3::unit_x());
+		let (w, h) = (RESOLUTION.w as f32, RESOLUTION.h as f32);
+
+		let expected = [
+			(center(), forward),
+			(Vec2::new(0.0, h / 2.0), -forward),
+			(Vec2::new(w, h / 2.0), -forward),
+			(Vec2::new(w * 0.75, h / 2.0), right),
+			(Vec2::new(w * 0.25, h / 2.0), -right),
+			(Vec2::new(w / 2.0, h), up),
+			(Vec2::new(w / 2.0, 0.0), -up),
+		];
+
+		for (pixel, expected) in expected {
+			let (origin, dir) = view.pixel_ray(pixel, RESOLUTION);
+
+			assert!(
+				(dir - expected).magnitude() < 1e-4,
+				"{:?}: {:?} != {:?}",
+				pixel,
+				dir,
+				expected
+			);
+			assert!((origin - Vec3::new(1.0, 2.0, 3.0)).magnitude() < 1e-4);
+		}
+	}
+}
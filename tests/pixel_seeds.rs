@@ -0,0 +1,43 @@
+use brainrot::vek::{Extent2, Vec2};
+use pbr_tracer::fragments::sampling::pixel_seed;
+
+const LOW: Extent2<u32> = Extent2 { w: 160, h: 90 };
+// 3 times the low resolution, so that the center of pixel `3p + 1` is exactly
+// the center of pixel `p` of the low resolution
+const HIGH: Extent2<u32> = Extent2 { w: 480, h: 270 };
+
+#[test]
+fn stable_seeds_match_across_resolutions() {
+	for y in 0..LOW.h {
+		for x in 0..LOW.w {
+			let low = Vec2::new(x, y);
+			let high = low * 3 + 1;
+
+			assert_eq!(
+				pixel_seed(low, LOW, 42, true),
+				pixel_seed(high, HIGH, 42, true),
+				"Different seeds for pixel {} and {}",
+				low,
+				high
+			);
+		}
+	}
+}
+
+#[test]
+fn raw_seeds_follow_the_pixel_index() {
+	let pixel = Vec2::new(10, 20);
+
+	assert_eq!(pixel_seed(pixel, LOW, 42, false), pixel_seed(pixel, HIGH, 42, false));
+	assert_ne!(
+		pixel_seed(pixel, LOW, 42, false),
+		pixel_seed(pixel * 3 + 1, HIGH, 42, false)
+	);
+}
+
+#[test]
+fn stable_seeds_change_with_the_frame_seed() {
+	let pixel = Vec2::new(10, 20);
+
+	assert_ne!(pixel_seed(pixel, LOW, 1, true), pixel_seed(pixel, LOW, 2, true));
+}
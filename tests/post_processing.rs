@@ -0,0 +1,245 @@
+use brainrot::size;
+use pbr_tracer::{
+	core::size::Resolution,
+	fragments::post_processing::{
+		Bloom, ChromaticAberration, ColorStage, Dither, Fxaa, FxaaQuality, FxaaSettings, GammaCorrection,
+		PostProcessingChain, PostProcessingEffect, PostProcessingEffects, PostProcessingPipeline, ToneMapOperator,
+		ToneMapping, ToneMappingSettings,
+	},
+	libs::{
+		shader::{Shader, ShaderBuilder},
+		shader_fragment::ShaderFragment,
+	},
+};
+
+struct Invert;
+
+impl PostProcessingEffect for Invert {}
+impl ShaderFragment for Invert {
+	fn shader(&self) -> Shader {
+		let source = "fn post_processing_effect(coord: vec2f, color: vec4f, ctx: PPContext) -> vec4f {
+	return vec4f(1.0 - color.rgb, color.a);
+}";
+
+		ShaderBuilder::new().include(source.to_owned()).into()
+	}
+}
+
+fn pipeline() -> PostProcessingPipeline {
+	PostProcessingPipeline::empty()
+		.with(GammaCorrection)
+		.with(Invert)
+		.with(Dither)
+}
+
+#[test]
+fn dispatch_source_is_pinned() {
+	let expected = "\
+fn pp_dispatch(index: u32, ctx: PPContext, coord: vec2f, color: vec4f) -> vec4f {
+	switch index {
+		case 0u: {
+			return pp_effect_0_gamma_correction(coord, color, ctx);
+		}
+		case 1u: {
+			return pp_effect_1_invert(coord, color, ctx);
+		}
+		case 2u: {
+			return pp_effect_2_dither(coord, color, ctx);
+		}
+		default: {}
+	}
+	return color;
+}
+";
+
+	assert_eq!(pipeline().dispatch_source(), expected);
+}
+
+#[test]
+fn default_chain_runs_everything_in_order() {
+	assert_eq!(pipeline().default_chain().order(), vec![0, 1, 2]);
+	assert_eq!(pipeline().truncated(2).default_chain().order(), vec![0, 1]);
+}
+
+#[test]
+fn chain_keeps_the_order() {
+	let chain = PostProcessingChain::new(&[2, 0, 2, 1, 0]);
+	assert_eq!(chain.count, 5);
+	assert_eq!(chain.order(), vec![2, 0, 2, 1, 0]);
+}
+
+#[test]
+fn effects_bring_their_pre_passes_and_textures() {
+	assert!(pipeline().pre_passes().is_empty());
+	assert!(pipeline().output_textures(Resolution(size!(64, 32))).is_empty());
+
+	let pipeline = PostProcessingPipeline::empty()
+		.with(Bloom::default())
+		.with(GammaCorrection)
+		.with(Dither);
+
+	let entry_points = pipeline
+		.pre_passes()
+		.into_iter()
+		.map(|pass| pass.entry_point)
+		.collect::<Vec<_>>();
+	assert_eq!(entry_points, vec!["bloom_blur_horizontal", "bloom_blur_vertical"]);
+
+	let textures = pipeline.output_textures(Resolution(size!(64, 32)));
+	let names = textures.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>();
+	assert_eq!(names, vec!["bloom_bright", "bloom_blur_temp", "bloom_blurred"]);
+	assert!(textures
+		.iter()
+		.all(|(_, desc)| desc.dimensions.get_size().width == 64 && desc.dimensions.get_size().height == 32));
+}
+
+#[test]
+fn sources_are_only_kept_for_the_effects_that_sample_them() {
+	assert_eq!(pipeline().sampling_effects().count(), 0);
+
+	let pipeline = PostProcessingPipeline::empty()
+		.with(ChromaticAberration::default())
+		.with(Bloom::default())
+		.with(ToneMapping::default())
+		.with(Fxaa::default())
+		.with(Dither);
+	assert_eq!(pipeline.sampling_effects().collect::<Vec<_>>(), vec![0, 3]);
+
+	let textures = pipeline.output_textures(Resolution(size!(64, 32)));
+	let names = textures.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>();
+	assert_eq!(
+		names,
+		vec![
+			"bloom_bright",
+			"bloom_blur_temp",
+			"bloom_blurred",
+			"pp_source_0",
+			"pp_source_3"
+		]
+	);
+
+	// Stored right before the effect runs, so that it samples its own input
+	let dispatch = pipeline.dispatch_source();
+	assert!(
+		dispatch.contains("\t\t\tpp_store_source_3(coord, color);\n\t\t\treturn pp_effect_3_fxaa(coord, color, ctx);")
+	);
+	assert!(!dispatch.contains("pp_store_source_1("));
+	assert!(pipeline
+		.source_functions(3)
+		.contains("fn pp_sample_source_3(coord: vec2f) -> vec4f {"));
+}
+
+#[test]
+fn effects_go_in_the_order_of_their_color_stages() {
+	let pipeline = PostProcessingPipeline::empty().with(Invert);
+	assert_eq!(pipeline.stage(), None);
+
+	let pipeline = pipeline.with(Bloom::default()).with(ToneMapping::default());
+	assert_eq!(pipeline.stage(), Some(ColorStage::Display));
+
+	let pipeline = pipeline.with(Fxaa::default()).with(Invert).with(GammaCorrection);
+	assert_eq!(pipeline.stage(), Some(ColorStage::Encoded));
+}
+
+#[test]
+#[should_panic(expected = "The bloom expects Hdr colors")]
+fn hdr_effects_cant_go_after_the_tone_mapping() {
+	let _ = PostProcessingPipeline::empty()
+		.with(ToneMapping::default())
+		.with(Bloom::default());
+}
+
+#[test]
+#[should_panic(expected = "The fxaa expects Display colors")]
+fn fxaa_cant_go_after_the_gamma_correction() {
+	let _ = PostProcessingPipeline::empty()
+		.with(ToneMapping::default())
+		.with(GammaCorrection)
+		.with(Fxaa::default());
+}
+
+#[test]
+fn effects_can_be_toggled_and_moved() {
+	let pipeline = PostProcessingPipeline::empty()
+		.with(Bloom::default())
+		.with(ToneMapping::default())
+		.with(Invert)
+		.with(Fxaa::default())
+		.with(Invert)
+		.with(GammaCorrection)
+		.truncated(5);
+	let mut effects = PostProcessingEffects::new(&pipeline);
+
+	let names = effects
+		.effects()
+		.iter()
+		.map(|effect| effect.name.as_str())
+		.collect::<Vec<_>>();
+	assert_eq!(
+		names,
+		vec![
+			"bloom",
+			"tone_mapping",
+			"invert",
+			"fxaa",
+			"invert_4",
+			"gamma_correction"
+		]
+	);
+	assert_eq!(effects.chain(), pipeline.default_chain());
+
+	assert!(!effects.toggle(0));
+	assert!(effects.toggle(5));
+	assert_eq!(effects.chain().order(), vec![1, 2, 3, 4, 5]);
+
+	// Effects without a stage go anywhere
+	effects.move_to(effects.index_of("invert").unwrap(), 0).unwrap();
+	assert_eq!(effects.order(), &[2, 0, 1, 3, 4, 5]);
+	assert_eq!(effects.chain().order(), vec![2, 1, 3, 4, 5]);
+
+	// The disabled bloom still can't go after the tone mapping
+	let error = effects.move_to(0, 3).unwrap_err();
+	assert!(error.to_string().starts_with("The bloom expects Hdr colors"));
+	assert!(effects.move_to(3, 5).is_err());
+	assert!(effects.move_to(3, 6).is_err());
+	assert_eq!(effects.order(), &[2, 0, 1, 3, 4, 5]);
+}
+
+#[test]
+fn fxaa_quality_sets_its_steps() {
+	for quality in FxaaQuality::ALL {
+		let settings = FxaaSettings::new(quality);
+		assert_eq!(settings.quality(), Some(quality));
+	}
+
+	let mut settings = FxaaSettings::new(FxaaQuality::Extreme);
+	settings.set_quality(FxaaQuality::Low);
+	assert_eq!(settings.quality(), Some(FxaaQuality::Low));
+	assert_eq!(FxaaQuality::Low.steps(), &[1.5, 3.0, 12.0]);
+	assert_eq!(FxaaQuality::from_index(FxaaQuality::ALL.len() as u32), None);
+}
+
+#[test]
+fn tone_map_operators_match_the_shader() {
+	let source = include_str!("../src/shader/post_processing/tone_mapping.wgsl");
+
+	for (operator, constant) in [
+		(ToneMapOperator::Reinhard, "TONE_MAP_REINHARD"),
+		(ToneMapOperator::Aces, "TONE_MAP_ACES"),
+		(ToneMapOperator::Agx, "TONE_MAP_AGX"),
+		(ToneMapOperator::Neutral, "TONE_MAP_NEUTRAL"),
+	] {
+		let declaration = format!("const {} = {}u;", constant, operator as u32);
+		assert!(
+			source.contains(&declaration),
+			"Expected `{}` in the shader",
+			declaration
+		);
+
+		let settings = ToneMappingSettings::new(operator, 0.0);
+		assert_eq!(settings.operator(), Some(operator));
+	}
+
+	assert_eq!(ToneMapOperator::from_index(ToneMapOperator::ALL.len() as u32), None);
+	assert_eq!(ToneMapping::default().name(), "tone_mapping");
+}
@@ -0,0 +1,68 @@
+use brainrot::{
+	calc_view_matrix, rad,
+	vek::{Vec2, Vec3},
+	Direction, Position,
+};
+use pbr_tracer::core::{
+	clip_planes::SceneBounds,
+	presentation::{interpolate_pose, PresentationSettings, Turntable},
+};
+
+fn forward(direction: Direction) -> Vec3<f32> {
+	calc_view_matrix(Vec3::zero().into(), direction)
+		.inverted()
+		.mul_direction(Vec3::unit_z())
+}
+
+fn looking(yaw: f32, pitch: f32) -> Direction {
+	let mut direction = Direction::default();
+	direction.yaw = rad!(yaw);
+	direction.pitch = rad!(pitch);
+	direction
+}
+
+#[test]
+fn turntable_looks_at_the_center_from_outside() {
+	let bounds = SceneBounds::default();
+	let settings = PresentationSettings::default();
+
+	for start in [
+		Vec3::new(0.0, 0.0, -5.0),
+		Vec3::new(10.0, 1.0, 3.0),
+		Vec3::new(-4.0, 30.0, 8.0),
+	] {
+		let turntable = Turntable::around(&bounds, start.into(), Direction::default(), &settings);
+		let (position, direction) = turntable.pose();
+
+		let offset = position.0 - bounds.center();
+		assert!((offset.magnitude() - turntable.radius).abs() < 1e-3);
+		assert!(turntable.radius > (bounds.max - bounds.min).magnitude() / 2.0);
+		assert!(forward(direction).dot(-offset.normalized()) > 0.999);
+
+		// Stays on the side it came from
+		let side = |v: Vec3<f32>| Vec2::new(v.x, v.z).normalized();
+		assert!(side(offset).dot(side(start - bounds.center())) > 0.999);
+
+		let elevation = (offset.y / offset.magnitude()).asin().to_degrees();
+		assert!(elevation >= settings.min_elevation - 1e-3 && elevation <= settings.max_elevation + 1e-3);
+	}
+}
+
+#[test]
+fn transitions_go_the_short_way() {
+	let from: (Position, Direction) = (Vec3::new(0.0, 0.0, 0.0).into(), looking(3.0, 0.0));
+	let to: (Position, Direction) = (Vec3::new(2.0, 4.0, 0.0).into(), looking(-3.0, -0.5));
+
+	let (position, direction) = interpolate_pose(from, to, 0.0);
+	assert_eq!(position.0, from.0 .0);
+	assert!((direction.yaw.to_radians() - 3.0).abs() < 1e-6);
+
+	let (position, direction) = interpolate_pose(from, to, 1.0);
+	assert!((position.0 - to.0 .0).magnitude() < 1e-5);
+	assert!((direction.pitch.to_radians() + 0.5).abs() < 1e-5);
+
+	// From 3 to -3 radians is shorter through pi than through 0
+	let (position, direction) = interpolate_pose(from, to, 0.5);
+	assert_eq!(position.0, Vec3::new(1.0, 2.0, 0.0));
+	assert!((direction.yaw.to_radians().rem_euclid(std::f32::consts::TAU) - std::f32::consts::PI).abs() < 1e-4);
+}
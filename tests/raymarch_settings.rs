@@ -0,0 +1,41 @@
+#![cfg(feature = "gpu-tests")]
+
+use pbr_tracer::{
+	core::{display::DisplayPlugin, gameloop, gpu::Gpu, rendering::compute::ComputeRenderer},
+	fragments::intersector::RaymarchSettings,
+};
+
+// With a single step no ray gets close enough to a surface, so the depth output
+// should be at the far plane nearly everywhere. Only works if the settings
+// reach the shader through the uniform.
+#[test]
+fn single_step_renders_background() {
+	let mut app = pbr_tracer::build_app(DisplayPlugin {
+		visible: false,
+		any_thread: true,
+		placement_path: None,
+	});
+
+	for mut settings in app.world.query::<&mut RaymarchSettings>().iter_mut(&mut app.world) {
+		settings.max_steps = 1;
+	}
+
+	gameloop::run_frames(&mut app, 5).expect("The app should render frames without exiting");
+
+	let depth = app.world.resource::<ComputeRenderer>().output_textures[2].clone();
+	let bytes = depth.read_bytes(app.world.resource::<Gpu>());
+
+	// Rgba32Float, the depth is in every channel
+	let texels = bytes
+		.chunks_exact(16)
+		.map(|texel| f32::from_le_bytes(texel[0..4].try_into().unwrap()))
+		.collect::<Vec<_>>();
+	let background = texels.iter().filter(|depth| **depth >= 0.999).count();
+
+	assert!(
+		background as f32 / texels.len() as f32 > 0.9,
+		"Only {} of {} pixels are background",
+		background,
+		texels.len()
+	);
+}
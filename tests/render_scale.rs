@@ -0,0 +1,141 @@
+use brainrot::size;
+use pbr_tracer::core::{
+	rendering::render_scale::RenderScale,
+	size::{Resolution, WindowSize},
+};
+
+#[test]
+fn scales_the_window_size() {
+	let window = WindowSize(size!(1920, 1080));
+
+	assert_eq!(RenderScale(1.0).resolution(window), Resolution(size!(1920, 1080)));
+	assert_eq!(RenderScale(0.5).resolution(window), Resolution(size!(960, 540)));
+	assert_eq!(RenderScale(0.3).resolution(window), Resolution(size!(576, 324)));
+	assert_eq!(
+		RenderScale(0.25).resolution(WindowSize(size!(2, 1))),
+		Resolution(size!(1, 1)),
+		"Never 0"
+	);
+}
+
+#[test]
+fn steps_from_anywhere() {
+	assert_eq!(RenderScale(1.0).stepped_up(), RenderScale(1.25));
+	assert_eq!(RenderScale(1.0).stepped_down(), RenderScale(0.75));
+
+	// In between two steps
+	assert_eq!(RenderScale(0.6).stepped_up(), RenderScale(0.75));
+	assert_eq!(RenderScale(0.6).stepped_down(), RenderScale(0.5));
+
+	// Stays at the ends
+	assert_eq!(
+		RenderScale(RenderScale::MAX).stepped_up(),
+		RenderScale(RenderScale::MAX)
+	);
+	assert_eq!(
+		RenderScale(RenderScale::MIN).stepped_down(),
+		RenderScale(RenderScale::MIN)
+	);
+}
+
+#[cfg(feature = "gpu-tests")]
+mod app {
+	use bevy_ecs::world::World;
+	use brainrot::bevy::App;
+	use pbr_tracer::{
+		core::{
+			display::DisplayPlugin,
+			entity_label, gameloop,
+			gpu::Gpu,
+			params,
+			render_target::{RenderTarget, WindowRenderTarget},
+			rendering::{
+				accumulation::Accumulation,
+				composite::CompositeRenderer,
+				compute::{self, ComputeRenderer},
+				render_scale::RenderScale,
+			},
+			size::{Resolution, WindowSize},
+		},
+		fragments::mpr::PingPongDebugRenderer,
+	};
+
+	fn window_size(world: &mut World) -> WindowSize {
+		let entity = entity_label::single_entity::<WindowRenderTarget>(world).unwrap();
+		world.get::<RenderTarget>(entity).unwrap().size
+	}
+
+	fn assert_resolution(world: &World, resolution: Resolution) {
+		assert_eq!(*world.resource::<Resolution>(), resolution);
+		assert_eq!(world.resource::<ComputeRenderer>().resolution(), resolution);
+
+		let drawn = world.resource::<CompositeRenderer>().output_texture().size();
+		assert_eq!((drawn.width, drawn.height), (resolution.w, resolution.h));
+	}
+
+	// winit only allows creating one event loop per process, so everything that
+	// needs the app has to happen in this one test
+	#[test]
+	fn follows_the_scale() {
+		let mut app = pbr_tracer::build_app(DisplayPlugin {
+			visible: false,
+			any_thread: true,
+			placement_path: None,
+		});
+		gameloop::run_frames(&mut app, 3).expect("The app should render frames without exiting");
+
+		let window = window_size(&mut app.world);
+		assert_resolution(&app.world, RenderScale(1.0).resolution(window));
+
+		// Applied right away, and the accumulation starts over
+		params::set_param(&mut app.world, "render_scale", "0.5").unwrap();
+		assert_resolution(&app.world, RenderScale(0.5).resolution(window));
+		gameloop::run_frames(&mut app, 1).expect("The app should render at the new resolution");
+		assert_eq!(app.world.resource::<Accumulation>().samples(), 1);
+
+		// The keys step the resource, which is applied before the next frame
+		app.world.insert_resource(RenderScale(0.25));
+		gameloop::run_frames(&mut app, 1).expect("The app should render at the new resolution");
+		assert_resolution(&app.world, RenderScale(0.25).resolution(window));
+
+		resizes_in_place(&mut app);
+		resizes_renderer_resources(&mut app);
+	}
+
+	fn resizes_in_place(app: &mut App) {
+		let before = app.world.resource::<ComputeRenderer>().output_textures.clone();
+		let resolution = Resolution(app.world.resource::<Resolution>().map(|x| x / 3));
+		compute::resize_compute_renderer(&mut app.world, resolution).unwrap();
+		assert_resolution(&app.world, resolution);
+
+		// Every output that followed the resolution got a new texture
+		let after = &app.world.resource::<ComputeRenderer>().output_textures;
+		assert_eq!(after.len(), before.len());
+		for (before, after) in before.iter().zip(after) {
+			assert_ne!(before, after);
+		}
+
+		gameloop::run_frames(app, 2).expect("The app should render at the new resolution");
+	}
+
+	// The ping pong debug renderer's history is at the resolution, and has to
+	// follow it or the trails would only cover part of the screen
+	fn resizes_renderer_resources(app: &mut App) {
+		let resolution = *app.world.resource::<Resolution>();
+		let renderer = PingPongDebugRenderer {
+			resolution,
+			blend_factor: 0.5,
+		};
+		let swapped = app
+			.world
+			.resource::<ComputeRenderer>()
+			.with_renderer(app.world.resource::<Gpu>(), &renderer);
+		app.world.insert_resource(swapped);
+		gameloop::run_frames(app, 1).expect("The app should render with the ping pong debug renderer");
+
+		let resolution = Resolution(resolution.map(|x| x * 2));
+		compute::resize_compute_renderer(&mut app.world, resolution).unwrap();
+		assert_resolution(&app.world, resolution);
+		gameloop::run_frames(app, 2).expect("The app should render at the new resolution");
+	}
+}
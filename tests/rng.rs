@@ -0,0 +1,24 @@
+use brainrot::vek::Vec2;
+use pbr_tracer::fragments::sampling::RngState;
+
+#[test]
+fn floats_are_in_the_unit_range() {
+	let mut rng = RngState::new(Vec2::new(3, 7), 0);
+	let values = (0..10_000).map(|_| rng.next_f32()).collect::<Vec<_>>();
+
+	assert!(values.iter().all(|value| (0.0..1.0).contains(value)));
+
+	let mean = values.iter().sum::<f32>() / values.len() as f32;
+	assert!((mean - 0.5).abs() < 0.02, "Mean {}", mean);
+}
+
+#[test]
+fn pixels_and_frames_get_their_own_sequence() {
+	let first = |pixel: Vec2<u32>, frame: u32| RngState::new(pixel, frame).next_u32();
+
+	assert_ne!(first(Vec2::new(0, 0), 0), first(Vec2::new(1, 0), 0));
+	assert_ne!(first(Vec2::new(0, 0), 0), first(Vec2::new(0, 1), 0));
+	assert_ne!(first(Vec2::new(0, 0), 0), first(Vec2::new(0, 0), 1));
+	// Swapping the coordinates shouldn't give the same sequence either
+	assert_ne!(first(Vec2::new(2, 5), 0), first(Vec2::new(5, 2), 0));
+}
@@ -0,0 +1,74 @@
+use pbr_tracer::core::rendering::globals::{RenderSettings, RenderSettingsOverrides, SceneSettings};
+
+#[test]
+fn only_the_fields_in_the_file_override() {
+	let overrides = ron::from_str::<RenderSettingsOverrides>("(march_steps_scale: 2.0)").unwrap();
+
+	let mut settings = RenderSettings {
+		show_reference_grid: false,
+		..Default::default()
+	};
+	overrides.apply(&mut settings);
+
+	assert_eq!(settings.march_steps_scale, 2.0);
+	assert!(!settings.show_reference_grid);
+	assert_eq!(overrides.fields(), vec!["march_steps_scale"]);
+}
+
+#[test]
+fn the_next_scene_reverts_the_overrides() {
+	let mut settings = RenderSettings::default();
+	let mut scene_settings = SceneSettings::default();
+
+	let first = RenderSettingsOverrides {
+		march_steps_scale: Some(4.0),
+		stable_pixel_seeds: Some(false),
+		..Default::default()
+	};
+	scene_settings.load(first, &mut settings);
+	assert!(scene_settings.is_overridden("march_steps_scale"));
+
+	// Not overridden, so it stays what the user set
+	settings.show_reference_grid = false;
+
+	let second = RenderSettingsOverrides {
+		stable_pixel_seeds: Some(false),
+		..Default::default()
+	};
+	scene_settings.load(second, &mut settings);
+
+	assert_eq!(settings.march_steps_scale, 1.0);
+	assert!(!settings.stable_pixel_seeds);
+	assert!(!settings.show_reference_grid);
+	assert!(!scene_settings.is_overridden("march_steps_scale"));
+
+	scene_settings.load(RenderSettingsOverrides::default(), &mut settings);
+	assert_eq!(
+		settings,
+		RenderSettings {
+			show_reference_grid: false,
+			..Default::default()
+		}
+	);
+}
+
+#[test]
+fn all_the_settings_round_trip_through_ron() {
+	let settings = RenderSettings {
+		march_steps_scale: 0.5,
+		..Default::default()
+	};
+	let overrides = RenderSettingsOverrides::all(&settings);
+
+	let text = ron::to_string(&overrides).unwrap();
+	assert_eq!(ron::from_str::<RenderSettingsOverrides>(&text).unwrap(), overrides);
+
+	let mut applied = RenderSettings::default();
+	overrides.apply(&mut applied);
+	assert_eq!(applied, settings);
+}
+
+#[test]
+fn unknown_fields_are_errors() {
+	assert!(ron::from_str::<RenderSettingsOverrides>("(bounces: 8)").is_err());
+}
@@ -0,0 +1,39 @@
+use brainrot::vek::Vec3;
+use pbr_tracer::fragments::sdf::{SdfNode, SdfScene};
+
+#[test]
+fn compiles_to_one_let_per_node() {
+	let scene = SdfScene::new(
+		SdfNode::cuboid(Vec3::one())
+			.subtract(SdfNode::sphere(0.6))
+			.material(2)
+			.smooth_union(SdfNode::torus(1.0, 0.25).translated(Vec3::new(0.0, 1.0, 0.0)), 0.1)
+			.union(SdfNode::plane().material(1)),
+	);
+	let wgsl = scene.to_wgsl();
+
+	assert!(wgsl.starts_with("fn scene_sdf(p: vec3f) -> SdfResult {"));
+	assert!(wgsl.contains("let r1 = SdfResult(bbox(p, vec3f(1.0, 1.0, 1.0)), 0u);"));
+	assert!(wgsl.contains("let r2 = SdfResult(sphere(p, 0.6), 0u);"));
+	assert!(wgsl.contains("let r3 = sdf_subtract(r1, r2);"));
+	assert!(wgsl.contains("let r4 = SdfResult(r3.distance, 2u);"));
+	// The torus is evaluated in its own space
+	assert!(wgsl.contains("let p5 = (mat4x4f("));
+	assert!(wgsl.contains("* vec4f(p, 1.0)).xyz;"));
+	assert!(wgsl.contains("let r6 = SdfResult(torus(p5, 1.0, 0.25), 0u);"));
+	assert!(wgsl.contains("let r8 = sdf_smooth_union(r4, r7, 0.1);"));
+	assert!(wgsl.trim_end().ends_with("return r11;\n}"));
+}
+
+#[test]
+fn generates_the_same_source_every_time() {
+	assert_eq!(SdfScene::default().to_wgsl(), SdfScene::default().to_wgsl());
+}
+
+#[test]
+fn scaling_scales_the_distance_back() {
+	let wgsl = SdfScene::new(SdfNode::sphere(1.0).scaled(2.0)).to_wgsl();
+
+	assert!(wgsl.contains("let r2 = SdfResult(sphere(p1, 1.0), 0u);"));
+	assert!(wgsl.contains("let r3 = SdfResult(r2.distance * 2.0, r2.material_id);"));
+}
@@ -0,0 +1,110 @@
+use std::{
+	collections::HashSet,
+	hash::{BuildHasher, RandomState},
+	time::Instant,
+};
+
+use pbr_tracer::libs::shader::{Shader, ShaderBuilder, SourceText};
+
+/// About 5 MB of generated functions, like a pipeline full of generated code
+fn generated_source(seed: u32) -> String {
+	(0..100_000)
+		.map(|i| format!("fn generated_{}_{}() -> u32 {{ return {}u; }}\n", seed, i, i))
+		.collect()
+}
+
+#[test]
+fn big_sources_are_interned() {
+	let first = SourceText::new(generated_source(0));
+	let second = SourceText::new(generated_source(0));
+	assert!(first.len() > SourceText::INTERN_MIN_LEN);
+	assert!(first.shares_text_with(&second));
+	assert_eq!(first, second);
+
+	let other = SourceText::new(generated_source(1));
+	assert!(!first.shares_text_with(&other));
+	assert_ne!(first, other);
+
+	// Not worth it for the small ones, which are still equal
+	let small = SourceText::new("fn main() {}");
+	assert!(!small.shares_text_with(&SourceText::new("fn main() {}")));
+	assert_eq!(small, SourceText::new("fn main() {}"));
+}
+
+#[test]
+fn equal_shaders_hash_the_same() {
+	let hasher = RandomState::new();
+
+	let a = Shader::from("fn a() {}");
+	let b = Shader::from("fn a() {}".to_owned());
+	assert_eq!(a, b);
+	assert_eq!(hasher.hash_one(&a), hasher.hash_one(&b));
+
+	let builder = |define: &str| -> Shader {
+		ShaderBuilder::new()
+			.include("fn a() {}")
+			.include(ShaderBuilder::new().include("fn b() {}"))
+			.define("KEY", define)
+			.into()
+	};
+	assert_eq!(builder("1"), builder("1"));
+	assert_eq!(hasher.hash_one(builder("1")), hasher.hash_one(builder("1")));
+	assert_ne!(builder("1"), builder("2"));
+}
+
+#[test]
+fn builder_forgets_its_hash_when_it_changes() {
+	let hasher = RandomState::new();
+
+	let mut builder = ShaderBuilder::new();
+	builder.include("fn a() {}");
+	let before = hasher.hash_one(&builder);
+
+	builder.define("KEY", "1");
+	assert_ne!(hasher.hash_one(&builder), before);
+
+	let mut same = ShaderBuilder::new();
+	same.include("fn a() {}").define("KEY", "1");
+	assert_eq!(builder, same);
+}
+
+#[test]
+fn identical_includes_are_only_included_once() {
+	let source = generated_source(2);
+
+	let mut builder = ShaderBuilder::new();
+	builder.include(source.clone()).include(source).include("fn main() {}");
+	let mut other = ShaderBuilder::new();
+	other.include(generated_source(2)).include("fn main() {}");
+
+	assert_eq!(builder, other);
+}
+
+// Not a real benchmark, but enough to compare with hashing the whole string
+// every time: cargo test --release --test shader_hashing -- --ignored --nocapture
+#[test]
+#[ignore]
+fn inserting_a_huge_source() {
+	const INSERTIONS: u32 = 200;
+
+	let source = generated_source(3);
+	println!("Source: {:.1} MB", source.len() as f32 / 1_000_000.0);
+
+	let start = Instant::now();
+	let mut strings = HashSet::new();
+	for _ in 0..INSERTIONS {
+		strings.insert(source.clone());
+	}
+	println!("String: {:?} per insertion", start.elapsed() / INSERTIONS);
+
+	let shader = Shader::from(source);
+	let start = Instant::now();
+	let mut shaders = HashSet::new();
+	for _ in 0..INSERTIONS {
+		shaders.insert(shader.clone());
+	}
+	println!("Shader: {:?} per insertion", start.elapsed() / INSERTIONS);
+
+	assert_eq!(strings.len(), 1);
+	assert_eq!(shaders.len(), 1);
+}
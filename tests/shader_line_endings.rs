@@ -0,0 +1,62 @@
+use pbr_tracer::libs::shader::{find_include_directives, normalize_source};
+
+#[test]
+fn strips_the_bom_and_crlf() {
+	let source = "\u{feff}#include \"lib.wgsl\"\r\n#define SCALE 2.0\r\n";
+
+	assert_eq!(normalize_source(source), "#include \"lib.wgsl\"\n#define SCALE 2.0\n");
+}
+
+#[test]
+fn leaves_lf_sources_alone() {
+	let source = "#include \"lib.wgsl\"\nfn main() {}\n";
+
+	assert!(matches!(normalize_source(source), std::borrow::Cow::Borrowed(_)));
+}
+
+#[test]
+fn finds_the_include_behind_a_bom() {
+	let source = normalize_source("\u{feff}#include \"lib.wgsl\"\r\n").into_owned();
+
+	let includes = find_include_directives(&source);
+	assert_eq!(includes.len(), 1);
+	assert_eq!(includes[0].0, "lib.wgsl");
+}
+
+// Goes through the whole preprocessor, with fixtures saved with a BOM and CRLF
+// line endings (see the .gitattributes next to them)
+#[cfg(feature = "gpu-tests")]
+mod preprocessor {
+	use brainrot::bevy::App;
+	use pbr_tracer::{
+		core::gpu::{Gpu, GpuPlugin},
+		libs::shader::ShaderBuilder,
+	};
+	use rust_embed::Embed;
+
+	#[derive(Embed)]
+	#[folder = "tests/fixtures/shader_line_endings/"]
+	#[prefix = "/"]
+	#[include = "*.wgsl"]
+	struct Fixtures;
+
+	#[test]
+	fn resolves_includes_and_defines() {
+		let mut app = App::new();
+		app.add_plugin(GpuPlugin);
+		let gpu = app.world.resource::<Gpu>();
+
+		let source = ShaderBuilder::new()
+			.include_path("main.wgsl")
+			.build_source(gpu, &Fixtures)
+			.expect("The fixtures should build")
+			.source;
+
+		assert!(!source.contains('\r'));
+		assert!(!source.contains('\u{feff}'));
+		assert!(!source.contains("#include"));
+		assert!(!source.contains("#define"));
+		assert!(source.contains("fn lib_value() -> f32 {"));
+		assert!(source.contains("return lib_value() * 2.0;"));
+	}
+}
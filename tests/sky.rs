@@ -0,0 +1,72 @@
+use brainrot::vek::Vec3;
+use pbr_tracer::core::rendering::{
+	lights::Lights,
+	sky::{DayCycle, SkyModel, SkySettings},
+};
+
+fn assert_close(a: Vec3<f32>, b: Vec3<f32>) {
+	assert!((a - b).magnitude() < 1e-4, "{} != {}", a, b);
+}
+
+fn sky_at(sun_elevation: f32) -> SkySettings {
+	SkySettings {
+		sun_elevation,
+		..Default::default()
+	}
+}
+
+#[test]
+fn default_sun_is_the_default_light() {
+	let light = Lights::default()[0];
+
+	assert_close(-SkySettings::default().sun_direction(), light.direction);
+}
+
+#[test]
+fn sun_reddens_towards_the_horizon() {
+	let noon = sky_at(1.2).sun_transmittance();
+	let evening = sky_at(0.05).sun_transmittance();
+
+	assert!(evening.r < noon.r && evening.b < noon.b);
+	assert!(evening.b / evening.r < noon.b / noon.r, "{} vs {}", evening, noon);
+	assert!(noon.r < 1.0 && noon.b > 0.0);
+}
+
+#[test]
+fn sky_fades_out_at_night() {
+	let day = SkyModel::new(&sky_at(0.5));
+	let night = SkyModel::new(&sky_at(-0.5));
+
+	assert!(day.brightness > 0.0);
+	assert!(day.zenith.x > 0.0 && day.zenith.x.is_finite());
+	assert_eq!(night.brightness, 0.0);
+	assert_eq!(night.sun_radiance.r, 0.0);
+	assert_eq!(sky_at(-0.5).sun_transmittance().g, 0.0);
+}
+
+#[test]
+fn day_cycle_rises_in_the_x_and_peaks_in_the_minus_z() {
+	let sunrise = DayCycle::default();
+	let (elevation, azimuth) = sunrise.sun_position();
+	let sunrise_direction = SkySettings {
+		sun_elevation: elevation,
+		sun_azimuth: azimuth,
+		..Default::default()
+	}
+	.sun_direction();
+	assert_close(sunrise_direction, Vec3::unit_x());
+
+	let noon = DayCycle {
+		time_of_day: 0.25,
+		..Default::default()
+	};
+	let (elevation, azimuth) = noon.sun_position();
+	assert!((elevation - DayCycle::MAX_ELEVATION).abs() < 1e-5);
+	let noon_direction = SkySettings {
+		sun_elevation: elevation,
+		sun_azimuth: azimuth,
+		..Default::default()
+	}
+	.sun_direction();
+	assert!(noon_direction.z < -0.3 && noon_direction.x.abs() < 1e-4);
+}
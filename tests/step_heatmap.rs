@@ -0,0 +1,72 @@
+#![cfg(feature = "gpu-tests")]
+
+mod common;
+
+use common::{composite_to_texture, is_gray};
+use pbr_tracer::{
+	core::{
+		display::DisplayPlugin,
+		gameloop,
+		gpu::Gpu,
+		render_target::RenderTarget,
+		rendering::{
+			composite::{CompositeRenderer, DebugView},
+			compute::ComputeRenderer,
+		},
+	},
+	fragments::intersector::Raymarcher,
+};
+use wgpu::{Extent3d, TextureFormat};
+
+#[test]
+fn step_heatmap_draws_the_raymarcher_steps() {
+	let mut app = pbr_tracer::build_app_with(
+		DisplayPlugin {
+			visible: false,
+			any_thread: true,
+			placement_path: None,
+		},
+		|app| {
+			Raymarcher {
+				step_heatmap: true,
+				..Default::default()
+			}
+			.tweakable(app)
+		},
+	);
+	gameloop::run_frames(&mut app, 2).expect("The app should render frames without exiting");
+
+	let steps = app
+		.world
+		.resource::<ComputeRenderer>()
+		.output_texture("output_debug")
+		.expect("The raymarcher should have a debug output")
+		.clone();
+	assert_eq!(steps.format(), TextureFormat::Rgba8Unorm);
+
+	// Every ray takes at least a step, and the spheres take more around the
+	// edges than in the middle
+	let bytes = steps.read_bytes(app.world.resource::<Gpu>());
+	assert!(bytes.chunks_exact(4).all(|texel| texel[0] > 0));
+	assert!(bytes.chunks_exact(4).any(|texel| texel[0] != bytes[0]));
+
+	assert!(app.world.resource::<CompositeRenderer>().supports(DebugView::Steps));
+	app.world.insert_resource(DebugView::Steps);
+	gameloop::run_frames(&mut app, 1).expect("The app should render the debug view");
+
+	let window_size = app.world.resource::<RenderTarget>().size;
+	let size = Extent3d {
+		width: window_size.w.max(1),
+		height: window_size.h.max(1),
+		depth_or_array_layers: 1,
+	};
+	let pixels = composite_to_texture(
+		app.world.resource::<Gpu>(),
+		app.world.resource::<CompositeRenderer>(),
+		size,
+	);
+	assert!(
+		!pixels.chunks_exact(4).all(is_gray),
+		"The steps should be drawn in false colors"
+	);
+}
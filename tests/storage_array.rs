@@ -0,0 +1,136 @@
+use brainrot::vek::Vec3;
+use pbr_tracer::libs::buffer::{
+	storage_buffer::{array_stride, StorageArray},
+	DynBufferUploadable, ShaderType,
+};
+
+#[repr(C)]
+#[derive(bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+struct Sample {
+	position: Vec3<f32>,
+	weight: f32,
+}
+
+// What the derive would generate, which only works inside the crate
+impl ShaderType for Sample {
+	const WGSL_ALIGN: usize = 16;
+	const WGSL_SIZE: usize = 16;
+
+	fn type_name() -> String {
+		"Sample".to_string()
+	}
+}
+
+fn samples() -> Vec<Sample> {
+	(0..5)
+		.map(|i| Sample {
+			position: Vec3::new(i as f32, i as f32 * 2.0, i as f32 * 3.0),
+			weight: 1.0 / (i + 1) as f32,
+		})
+		.collect()
+}
+
+fn points() -> Vec<Vec3<f32>> {
+	(0..5).map(|i| Vec3::new(i as f32, -(i as f32), 0.5)).collect()
+}
+
+/// Undo the padding of an `array<vec3<f32>>`
+fn unpad_vec3s(bytes: &[u8]) -> Vec<Vec3<f32>> {
+	bytemuck::pod_collect_to_vec::<_, f32>(bytes)
+		.chunks_exact(4)
+		.map(|element| {
+			assert_eq!(element[3], 0.0, "The padding isn't zeroed");
+			Vec3::new(element[0], element[1], element[2])
+		})
+		.collect()
+}
+
+#[test]
+fn vec3_elements_are_padded_to_the_array_stride() {
+	assert_eq!(array_stride::<Vec3<f32>>(), 16);
+	assert_eq!(StorageArray::<Vec3<f32>>::get_min_size(), 16);
+
+	let array = StorageArray(points());
+	let bytes = array.get_dyn_bytes();
+	assert_eq!(bytes.len() as u64, array.get_dyn_size());
+	assert_eq!(bytes.len(), 5 * 16);
+	assert_eq!(unpad_vec3s(&bytes), points());
+}
+
+#[test]
+fn structs_are_already_at_the_array_stride() {
+	assert_eq!(array_stride::<Sample>(), 16);
+
+	let array = StorageArray(samples());
+	assert_eq!(array.get_dyn_bytes(), bytemuck::cast_slice::<_, u8>(&samples()));
+}
+
+#[cfg(feature = "gpu-tests")]
+mod gpu {
+	use brainrot::bevy::App;
+	use pbr_tracer::{
+		core::gpu::{Gpu, GpuPlugin},
+		libs::buffer::storage_buffer::{DirtyRanges, StorageArray, StorageBuffer},
+	};
+	use wgpu::{Buffer, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Maintain, MapMode};
+
+	use super::{points, samples, unpad_vec3s, Sample};
+
+	fn read_buffer(gpu: &Gpu, buffer: &Buffer) -> Vec<u8> {
+		let staging_buffer = gpu.device.create_buffer(&BufferDescriptor {
+			label: Some("Test readback buffer"),
+			size: buffer.size(),
+			usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+
+		let mut encoder = gpu
+			.device
+			.create_command_encoder(&CommandEncoderDescriptor { label: None });
+		encoder.copy_buffer_to_buffer(buffer, 0, &staging_buffer, 0, buffer.size());
+		gpu.queue.submit([encoder.finish()]);
+
+		let slice = staging_buffer.slice(..);
+		slice.map_async(MapMode::Read, |result| {
+			result.expect("Couldn't map the readback buffer")
+		});
+		gpu.device.poll(Maintain::Wait);
+
+		let bytes = slice.get_mapped_range().to_vec();
+		staging_buffer.unmap();
+		bytes
+	}
+
+	#[test]
+	fn arrays_round_trip_through_the_gpu() {
+		let mut app = App::new();
+		app.add_plugin(GpuPlugin);
+		let gpu = app.world.resource::<Gpu>();
+
+		let buffer = StorageBuffer::raw_buffer_from_data(gpu, &StorageArray(samples()), None);
+		let bytes = read_buffer(gpu, &buffer);
+		assert_eq!(bytemuck::pod_collect_to_vec::<_, Sample>(&bytes), samples());
+
+		let buffer = StorageBuffer::raw_buffer_from_data(gpu, &StorageArray(points()), None);
+		assert_eq!(buffer.size(), 5 * 16);
+		assert_eq!(unpad_vec3s(&read_buffer(gpu, &buffer)), points());
+	}
+
+	#[test]
+	#[should_panic(expected = "Dirty range 3..5 is past the end of the 3 elements")]
+	fn stale_dirty_ranges_are_caught() {
+		let mut app = App::new();
+		app.add_plugin(GpuPlugin);
+		let gpu = app.world.resource::<Gpu>();
+
+		let mut items = samples();
+		let buffer = StorageBuffer::new_from_data(gpu, &StorageArray(items.clone()), "samples".to_string(), true);
+
+		// Marked, then removed before the upload
+		let mut dirty_ranges = DirtyRanges::default();
+		dirty_ranges.mark_range(3..5);
+		items.truncate(3);
+
+		buffer.upload_dirty_ranges(gpu, &dirty_ranges, &items);
+	}
+}
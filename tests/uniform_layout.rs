@@ -0,0 +1,126 @@
+use brainrot::vek::{Mat3, Vec3, Vec4};
+use naga::TypeInner;
+use pbr_tracer::libs::buffer::{uniform_buffer::UniformBuffer, ShaderType};
+
+/// The size of `T` and the stride of the array (if it is one), as naga lays it
+/// out in a struct
+fn naga_layout<T: ShaderType>() -> (u32, Option<u32>) {
+	let source = format!("struct Wrapper {{ value: {}, after: u32 }}", T::type_name());
+	let module = naga::front::wgsl::parse_str(&source).expect("Invalid WGSL");
+
+	let (_, wrapper) = module
+		.types
+		.iter()
+		.find(|(_, ty)| ty.name.as_deref() == Some("Wrapper"))
+		.unwrap();
+	let TypeInner::Struct { members, .. } = &wrapper.inner else {
+		panic!("Wrapper isn't a struct");
+	};
+
+	// Everything here is a multiple of 4 bytes, so the next field starts right after
+	let size = members[1].offset;
+	let stride = match module.types[members[0].ty].inner {
+		TypeInner::Array { stride, .. } => Some(stride),
+		_ => None,
+	};
+
+	(size, stride)
+}
+
+fn floats(bytes: &[u8]) -> Vec<f32> {
+	bytemuck::pod_collect_to_vec(bytes)
+}
+
+#[test]
+fn vec3_is_padded_to_16_bytes() {
+	assert_eq!(naga_layout::<Vec3<f32>>(), (Vec3::<f32>::WGSL_SIZE as u32, None));
+
+	let bytes = UniformBuffer::bytes_of(&Vec3::new(1.0f32, 2.0, 3.0));
+	assert_eq!(floats(&bytes), [1.0, 2.0, 3.0, 0.0]);
+	assert_eq!(UniformBuffer::size_of::<f32>(), 16);
+}
+
+#[test]
+fn mat3_columns_have_a_stride_of_16() {
+	let (size, _) = naga_layout::<Mat3<f32>>();
+	assert_eq!(size, 48);
+	assert_eq!(Mat3::<f32>::WGSL_SIZE as u32, size);
+
+	let matrix = Mat3::<f32>::new(1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0);
+	let rust = floats(bytemuck::bytes_of(&matrix));
+	let wgsl = floats(&UniformBuffer::bytes_of(&matrix));
+
+	assert_eq!(wgsl.len() * 4, size as usize);
+	for column in 0..3 {
+		assert_eq!(wgsl[column * 4..column * 4 + 3], rust[column * 3..column * 3 + 3]);
+		assert_eq!(wgsl[column * 4 + 3], 0.0);
+	}
+}
+
+#[test]
+fn vec3_arrays_have_a_stride_of_16() {
+	let (size, stride) = naga_layout::<[Vec3<f32>; 3]>();
+	assert_eq!(stride, Some(16));
+	assert_eq!(<[Vec3<f32>; 3]>::WGSL_SIZE as u32, size);
+	assert!(<[Vec3<f32>; 3]>::UNIFORM_COMPATIBLE);
+
+	let array = [
+		Vec3::new(1.0f32, 2.0, 3.0),
+		Vec3::new(4.0, 5.0, 6.0),
+		Vec3::new(7.0, 8.0, 9.0),
+	];
+	assert_eq!(
+		floats(&UniformBuffer::bytes_of(&array)),
+		[1.0, 2.0, 3.0, 0.0, 4.0, 5.0, 6.0, 0.0, 7.0, 8.0, 9.0, 0.0]
+	);
+}
+
+#[test]
+fn tightly_packed_arrays_fall_back_to_storage() {
+	let (size, stride) = naga_layout::<[f32; 4]>();
+	assert_eq!(stride, Some(4));
+	assert_eq!(<[f32; 4]>::WGSL_SIZE as u32, size);
+	assert!(!<[f32; 4]>::UNIFORM_COMPATIBLE);
+
+	// A vec4 has the stride the uniforms want
+	assert!(<[Vec4<f32>; 4]>::UNIFORM_COMPATIBLE);
+}
+
+#[cfg(feature = "gpu-tests")]
+mod gpu {
+	use brainrot::{bevy::App, vek::Mat4};
+	use pbr_tracer::{
+		core::gpu::{Gpu, GpuPlugin},
+		libs::{
+			buffer::{
+				storage_buffer::{StorageBuffer, StorageBufferDescriptor},
+				ShaderBufferDescriptor,
+			},
+			shader::ShaderSource,
+			smart_arc::Sarc,
+		},
+	};
+	use wgpu::ShaderStages;
+
+	#[test]
+	#[should_panic(expected = "var<storage, read> too_small: mat4x4<f32>;")]
+	fn undersized_buffers_name_their_binding() {
+		let mut app = App::new();
+		app.add_plugin(GpuPlugin);
+		let gpu = app.world.resource::<Gpu>();
+
+		// A mat4x4 needs 64 bytes
+		let buffer = Sarc::new(StorageBuffer::raw_buffer_from_size(gpu, 16, None));
+		let resource = StorageBufferDescriptor::<Mat4<f32>, _>::FromBuffer {
+			var_name: "too_small",
+			read_only: true,
+			buffer,
+		}
+		.as_resource(gpu);
+
+		let mut source =
+			ShaderSource::from_source("@compute @workgroup_size(1) fn main() { _ = too_small; }".to_owned());
+		source.extend(ShaderSource::from_resource(resource));
+		source.build(gpu, "Undersized".to_owned(), 0, ShaderStages::COMPUTE);
+	}
+}
@@ -0,0 +1,40 @@
+use brainrot::vek::{Extent3, Rgba, Vec3};
+use pbr_tracer::fragments::voxel::VoxelGrid;
+
+#[test]
+fn menger_sponge_keeps_twenty_of_twenty_seven() {
+	assert_eq!(VoxelGrid::menger_sponge(0).solid_count(), 1);
+	assert_eq!(VoxelGrid::menger_sponge(1).solid_count(), 20);
+	assert_eq!(VoxelGrid::menger_sponge(2).solid_count(), 400);
+
+	let sponge = VoxelGrid::menger_sponge(1);
+	assert_eq!(sponge.get(Vec3::new(1, 1, 1)), VoxelGrid::EMPTY);
+	assert_eq!(sponge.get(Vec3::new(1, 1, 0)), VoxelGrid::EMPTY);
+	assert_ne!(sponge.get(Vec3::new(1, 0, 0)), VoxelGrid::EMPTY);
+}
+
+#[test]
+fn terrain_is_deterministic() {
+	let size = Extent3::new(32, 16, 32);
+	let terrain = VoxelGrid::terrain(size, 7);
+
+	assert_eq!(terrain, VoxelGrid::terrain(size, 7));
+	assert_ne!(terrain, VoxelGrid::terrain(size, 8));
+
+	// Every column has at least its ground voxel
+	for z in 0..size.d {
+		for x in 0..size.w {
+			assert_ne!(terrain.get(Vec3::new(x, 0, z)), VoxelGrid::EMPTY);
+		}
+	}
+}
+
+#[test]
+fn density_above_threshold_is_solid() {
+	let color = Rgba::new(255, 0, 0, 255);
+	let grid = VoxelGrid::from_density(Extent3::new(2, 2, 1), &[0, 100, 101, 255], 100, color);
+
+	assert_eq!(grid.solid_count(), 2);
+	assert_eq!(grid.get(Vec3::new(1, 0, 0)), VoxelGrid::EMPTY);
+	assert_eq!(grid.get(Vec3::new(0, 1, 0)), color);
+}
@@ -0,0 +1,98 @@
+use std::{fs, path::PathBuf, thread, time::Duration};
+
+use pbr_tracer::core::watchdog::{Watchdog, WatchdogSettings, Zone};
+
+/// An empty directory of its own for every test
+fn test_dir(name: &str) -> PathBuf {
+	let dir = std::env::temp_dir().join(format!("pbr_tracer_watchdog_{}_{}", name, std::process::id()));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	dir
+}
+
+fn watchdog(dir: &PathBuf) -> Watchdog {
+	Watchdog::new(
+		WatchdogSettings {
+			timeout: Duration::from_millis(50),
+			abort_on_stall: false,
+			diagnostics_dir: Some(dir.clone()),
+		},
+		Some("Test adapter".to_owned()),
+	)
+}
+
+fn reports(dir: &PathBuf) -> Vec<String> {
+	fs::read_dir(dir)
+		.unwrap()
+		.map(|entry| fs::read_to_string(entry.unwrap().path()).unwrap())
+		.collect()
+}
+
+#[test]
+fn zones_nest() {
+	let watchdog = watchdog(&test_dir("zones"));
+	let heartbeat = watchdog.heartbeat();
+
+	{
+		let _render = heartbeat.zone(Zone::Render);
+		{
+			let _submit = heartbeat.zone(Zone::Submit);
+			assert_eq!(heartbeat.current_zone(), Zone::Submit);
+		}
+		assert_eq!(heartbeat.current_zone(), Zone::Render);
+		assert_eq!(heartbeat.last_completed_zone(), Zone::Submit);
+	}
+
+	assert_eq!(heartbeat.current_zone(), Zone::Idle);
+	assert_eq!(heartbeat.last_completed_zone(), Zone::Render);
+}
+
+#[test]
+fn reports_a_stall_once_with_where_it_is_stuck() {
+	let dir = test_dir("stall");
+	let mut watchdog = watchdog(&dir);
+	let heartbeat = watchdog.heartbeat();
+
+	watchdog.start();
+	{
+		let _update = heartbeat.zone(Zone::Update);
+		thread::sleep(Duration::from_millis(300));
+	}
+	watchdog.stop();
+	assert!(!watchdog.is_running());
+
+	let reports = reports(&dir);
+	assert_eq!(reports.len(), 1, "The stall should only be reported once");
+	assert!(reports[0].contains("stuck in: Update"), "{}", reports[0]);
+	assert!(reports[0].contains("gpu: Test adapter"), "{}", reports[0]);
+}
+
+#[test]
+fn looks_away_from_long_frames() {
+	let dir = test_dir("long_frames");
+	let mut watchdog = watchdog(&dir);
+	let heartbeat = watchdog.heartbeat();
+
+	heartbeat.set_expecting_long_frames(true);
+	watchdog.start();
+	thread::sleep(Duration::from_millis(300));
+	watchdog.stop();
+
+	assert!(reports(&dir).is_empty());
+}
+
+#[test]
+fn keeps_quiet_while_pinged() {
+	let dir = test_dir("pinged");
+	let mut watchdog = watchdog(&dir);
+	let heartbeat = watchdog.heartbeat();
+
+	watchdog.start();
+	for _ in 0..30 {
+		heartbeat.ping();
+		thread::sleep(Duration::from_millis(10));
+	}
+	drop(watchdog);
+
+	assert!(reports(&dir).is_empty());
+}
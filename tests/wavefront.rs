@@ -0,0 +1,104 @@
+#![cfg(feature = "gpu-tests")]
+
+use brainrot::{
+	bevy::App,
+	vek::{Rgb, Rgba, Vec3},
+};
+use pbr_tracer::{
+	core::{
+		display::DisplayPlugin,
+		gameloop,
+		gpu::Gpu,
+		rendering::{
+			compute::{self, ComputeRenderer},
+			lights::{Light, Lights},
+		},
+		size::Resolution,
+	},
+	fragments::{
+		environment::ProceduralSky,
+		intersector::{AnalyticIntersector, Primitive},
+		mpr::MultiPurposeRenderer,
+		post_processing::PostProcessingPipeline,
+		shading::SimpleDiffuse,
+	},
+	libs::shader_fragment::Renderer,
+};
+
+const FRAMES: u64 = 2;
+const TOLERANCE: f32 = 1e-3;
+
+/// A sphere on a floor under a sky, lit by a point light. Hard shadows, so
+/// that nothing is random and both renders are the same down to the rounding.
+fn renderer() -> MultiPurposeRenderer<AnalyticIntersector, SimpleDiffuse, ProceduralSky> {
+	MultiPurposeRenderer {
+		intersector: AnalyticIntersector::new(vec![
+			Primitive::sphere(Vec3::zero(), 1.0, Rgba::new(0.9, 0.4, 0.2, 1.0)),
+			Primitive::plane(-1.0, Rgba::new(0.8, 0.8, 0.8, 1.0)),
+		]),
+		shading: SimpleDiffuse { shadow_softness: 0.0 },
+		environment: ProceduralSky::default(),
+		post_processing: PostProcessingPipeline::empty(),
+		reference_grid: None,
+	}
+}
+
+fn render(app: &mut App, renderer: &dyn Renderer) -> Vec<f32> {
+	let swapped = app
+		.world
+		.resource::<ComputeRenderer>()
+		.with_renderer(app.world.resource::<Gpu>(), renderer);
+	compute::swap_compute_renderer(&mut app.world, swapped);
+
+	gameloop::run_frames(app, FRAMES).expect("The app should render frames without exiting");
+
+	// Rgba32Float
+	let bytes = app.world.resource::<ComputeRenderer>().output_textures[0].read_bytes(app.world.resource::<Gpu>());
+	bytes
+		.chunks_exact(4)
+		.map(|channel| f32::from_le_bytes(channel.try_into().unwrap()))
+		.collect()
+}
+
+#[test]
+fn matches_the_megakernel() {
+	let mut app = pbr_tracer::build_app(DisplayPlugin {
+		visible: false,
+		any_thread: true,
+		placement_path: None,
+	});
+
+	app.world.insert_resource(Lights(vec![Light::point(
+		Vec3::new(1.5, 2.5, -1.5),
+		Rgb::one(),
+		20.0,
+		50.0,
+	)]));
+
+	let megakernel = render(&mut app, &renderer());
+
+	let resolution = *app.world.resource::<Resolution>();
+	let wavefront = renderer().wavefront(app.world.resource::<Gpu>(), resolution);
+	assert!(
+		wavefront.is_wavefront(),
+		"The queues should fit at {}x{}",
+		resolution.w,
+		resolution.h
+	);
+	let wavefront = render(&mut app, &wavefront);
+
+	let mean = megakernel.iter().sum::<f32>() / megakernel.len() as f32;
+	assert!(mean > 0.01, "The megakernel rendered a black image");
+
+	let difference = megakernel
+		.iter()
+		.zip(&wavefront)
+		.map(|(a, b)| (a - b).abs())
+		.sum::<f32>()
+		/ megakernel.len() as f32;
+	assert!(
+		difference < TOLERANCE,
+		"The wavefront is {} off the megakernel on average",
+		difference
+	);
+}
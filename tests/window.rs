@@ -0,0 +1,16 @@
+#![cfg(feature = "gpu-tests")]
+
+use pbr_tracer::core::{display::DisplayPlugin, gameloop};
+
+// winit only allows creating one event loop per process, so everything that
+// needs the window has to happen in this one test
+#[test]
+fn renders_frames_in_hidden_window() {
+	let mut app = pbr_tracer::build_app(DisplayPlugin {
+		visible: false,
+		any_thread: true,
+		placement_path: None,
+	});
+
+	gameloop::run_frames(&mut app, 10).expect("The app should render frames without exiting");
+}